@@ -0,0 +1,269 @@
+//! What every example needs: a device, a graphics queue and a command list to record into, and a
+//! way to check the pixels that came out. Only d3d12_utils and the windows crate are used, so the
+//! examples show what the library is enough for on its own. None of them need a window, they
+//! build with `--no-default-features` too.
+
+// Every example builds its own copy of this module and uses only part of it
+#![allow(dead_code)]
+
+use anyhow::{ensure, Context, Result};
+use d3d12_utils::*;
+use windows::Win32::Graphics::{
+    Direct3D::D3D_FEATURE_LEVEL_12_0, Direct3D12::*, Dxgi::Common::DXGI_FORMAT,
+};
+
+/// Renders to textures of this size and format, and reads them back to check them
+pub const EXTENT: (u32, u32) = (64, 64);
+
+/// Of the compute shaders run over textures, along x and y
+pub const THREAD_GROUP_SIZE: u32 = 8;
+
+// Has to match the constants of the compute shaders
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct TextureConstants {
+    texture_index: u32,
+    texture_size: [u32; 2],
+}
+
+pub struct Example {
+    pub device: ID3D12Device4,
+    pub graphics_queue: CommandQueue,
+    pub texture_manager: TextureManager,
+    pub descriptor_manager: DescriptorManager,
+    pub barriers: BarrierBatcher,
+    pub command_list: ID3D12GraphicsCommandList,
+    command_allocator: ID3D12CommandAllocator,
+    pipelines: Vec<(ID3D12RootSignature, ID3D12PipelineState)>,
+}
+
+impl Example {
+    /// On WARP so the examples give the same pixels on any machine, or on the adapter given as the
+    /// first argument
+    pub fn new() -> Result<Self> {
+        Self::on_adapter(adapter_argument(1)?)
+    }
+
+    pub fn on_adapter(selection: AdapterSelection) -> Result<Self> {
+        let (_, device) = create_headless_device(selection, D3D_FEATURE_LEVEL_12_0)?;
+
+        let graphics_queue =
+            CommandQueue::new(&device, D3D12_COMMAND_LIST_TYPE_DIRECT, "Example Queue")?;
+        let texture_manager = TextureManager::new(&device, None)?;
+        let descriptor_manager = DescriptorManager::new(&device)?;
+
+        let command_allocator: ID3D12CommandAllocator =
+            unsafe { device.CreateCommandAllocator(D3D12_COMMAND_LIST_TYPE_DIRECT) }?;
+        let command_list: ID3D12GraphicsCommandList = unsafe {
+            device.CreateCommandList1(
+                0,
+                D3D12_COMMAND_LIST_TYPE_DIRECT,
+                D3D12_COMMAND_LIST_FLAG_NONE,
+            )
+        }?;
+        unsafe {
+            command_list.Reset(&command_allocator, None)?;
+        }
+
+        Ok(Self {
+            device,
+            graphics_queue,
+            texture_manager,
+            descriptor_manager,
+            barriers: BarrierBatcher::new(),
+            command_list,
+            command_allocator,
+            pipelines: Vec::new(),
+        })
+    }
+
+    /// Runs what was recorded, waits for it and opens the command list again
+    pub fn submit_and_wait(&mut self) -> Result<()> {
+        self.submit()?;
+        self.wait_and_reset()
+    }
+
+    /// Runs what was recorded without waiting, nothing can be recorded until `wait_and_reset`
+    pub fn submit(&mut self) -> Result<()> {
+        self.barriers.flush(&self.command_list);
+        unsafe {
+            self.command_list.Close()?;
+        }
+        self.graphics_queue
+            .execute_command_list(&self.command_list.clone().into())?;
+
+        Ok(())
+    }
+
+    pub fn wait_and_reset(&mut self) -> Result<()> {
+        self.graphics_queue.wait_for_idle()?;
+        self.pipelines.clear();
+        unsafe {
+            self.command_allocator.Reset()?;
+            self.command_list.Reset(&self.command_allocator, None)?;
+        }
+
+        Ok(())
+    }
+
+    /// A 2D texture of `EXTENT` compute shaders can write, resting in the unordered access state
+    pub fn create_uav_texture(&mut self, format: DXGI_FORMAT) -> Result<TextureHandle> {
+        let (width, height) = EXTENT;
+        self.texture_manager.create_empty_texture(
+            &self.device,
+            TextureInfo {
+                dimension: TextureDimension::Two(width as usize, height),
+                format,
+                is_unordered_access: true,
+                ..Default::default()
+            },
+            None,
+            D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+            &mut self.descriptor_manager,
+            true,
+        )
+    }
+
+    /// Records `CSMain` of a compute shader next to the examples over every texel of `texture`,
+    /// which is in the unordered access state. The shader gets the texture's UAV index and size
+    /// as constants.
+    pub fn dispatch_over_texture(&mut self, shader: &str, texture: &TextureHandle) -> Result<()> {
+        let (width, height) = EXTENT;
+        let constants = self.texture_constants(texture)?;
+        self.record_compute(shader, &constants, |command_list| {
+            unsafe {
+                command_list.Dispatch(
+                    width.div_ceil(THREAD_GROUP_SIZE),
+                    height.div_ceil(THREAD_GROUP_SIZE),
+                    1,
+                );
+            }
+            Ok(())
+        })
+    }
+
+    /// `dispatch_over_texture` with the thread groups dispatch `index` of `arguments` says
+    pub fn dispatch_indirect_over_texture(
+        &mut self,
+        shader: &str,
+        texture: &TextureHandle,
+        arguments: &IndirectDispatch,
+        index: usize,
+    ) -> Result<()> {
+        let constants = self.texture_constants(texture)?;
+        self.record_compute(shader, &constants, |command_list| {
+            arguments.dispatch(command_list, index)
+        })
+    }
+
+    /// Sets up `CSMain` of a compute shader next to the examples with `constants` as its root
+    /// constants, then lets `record` record the dispatch
+    pub fn record_compute<T>(
+        &mut self,
+        shader: &str,
+        constants: &T,
+        record: impl FnOnce(&ID3D12GraphicsCommandList) -> Result<()>,
+    ) -> Result<()> {
+        let num_constants = (std::mem::size_of::<T>() / 4) as u32;
+        let root_signature = create_constants_root_signature(&self.device, num_constants)?;
+        let pso = create_compute_pipeline_state(
+            &self.device,
+            &root_signature,
+            &compile_compute_shader(&shader_path(shader), "CSMain")?,
+        )?;
+
+        // The pipeline and root signature are only released once the GPU is done with them
+        self.barriers.flush(&self.command_list);
+        unsafe {
+            self.command_list.SetPipelineState(&pso);
+            self.command_list.SetDescriptorHeaps(&[Some(
+                self.descriptor_manager.get_heap(DescriptorType::Resource)?,
+            )]);
+            self.command_list.SetComputeRootSignature(&root_signature);
+            self.command_list.SetComputeRoot32BitConstants(
+                0,
+                num_constants,
+                constants as *const T as _,
+                0,
+            );
+        }
+        record(&self.command_list)?;
+        self.pipelines.push((root_signature, pso));
+
+        Ok(())
+    }
+
+    fn texture_constants(&self, texture: &TextureHandle) -> Result<TextureConstants> {
+        let (width, height) = EXTENT;
+        Ok(TextureConstants {
+            texture_index: self.texture_manager.get_uav(texture)?.index as u32,
+            texture_size: [width, height],
+        })
+    }
+
+    /// Copies a 2D `texture` that is in `state` back to the CPU, submitting everything recorded
+    /// so far along with it
+    pub fn read_back(
+        &mut self,
+        texture: &TextureHandle,
+        state: D3D12_RESOURCE_STATES,
+    ) -> Result<CapturedFrame> {
+        let resource = self
+            .texture_manager
+            .get_texture(texture)?
+            .get_resource()?
+            .device_resource
+            .clone();
+        let mut readback = FrameReadback::new(&self.device, &unsafe { resource.GetDesc() }, 1)?;
+
+        self.barriers.flush(&self.command_list);
+        readback.copy(&self.command_list, &resource, state, 0)?;
+        self.submit_and_wait()?;
+
+        readback.take(0).context("Nothing was read back")
+    }
+}
+
+/// The adapter named by an argument, "warp" or an index, WARP without one
+pub fn adapter_argument(position: usize) -> Result<AdapterSelection> {
+    match std::env::args().nth(position) {
+        Some(argument) => argument.parse(),
+        None => Ok(AdapterSelection::Warp),
+    }
+}
+
+/// Full path of a shader next to the examples, so they run from any directory
+pub fn shader_path(name: &str) -> String {
+    format!("{}/examples/shaders/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+/// Fails unless the RGBA8 pixel at `x`, `y` is within `tolerance` of `expected` in every channel
+pub fn expect_pixel(
+    frame: &CapturedFrame,
+    (x, y): (u32, u32),
+    expected: [u8; 4],
+    tolerance: u8,
+) -> Result<()> {
+    ensure!(
+        x < frame.width && y < frame.height,
+        "({}, {}) is outside the frame",
+        x,
+        y
+    );
+
+    let offset = y as usize * frame.row_pitch + x as usize * 4;
+    let pixel = &frame.data[offset..offset + 4];
+    ensure!(
+        pixel
+            .iter()
+            .zip(expected)
+            .all(|(actual, expected)| actual.abs_diff(expected) <= tolerance),
+        "Pixel ({}, {}) is {:?}, expected {:?}",
+        x,
+        y,
+        pixel,
+        expected
+    );
+
+    Ok(())
+}
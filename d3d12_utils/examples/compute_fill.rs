@@ -0,0 +1,30 @@
+//! A compute shader filling a texture through its UAV with a gradient.
+//!
+//! `cargo run -p d3d12_utils --example compute_fill [adapter]`, fails when the pixels are off.
+
+mod common;
+
+use anyhow::Result;
+use common::{expect_pixel, Example, EXTENT};
+use windows::Win32::Graphics::{Direct3D12::*, Dxgi::Common::*};
+
+fn main() -> Result<()> {
+    let mut example = Example::new()?;
+    let (width, height) = EXTENT;
+
+    let texture = example.create_uav_texture(DXGI_FORMAT_R8G8B8A8_UNORM)?;
+    example.dispatch_over_texture("compute_fill.hlsl", &texture)?;
+
+    let frame = example.read_back(&texture, D3D12_RESOURCE_STATE_UNORDERED_ACCESS)?;
+
+    expect_pixel(&frame, (0, 0), [0, 0, 255, 255], 0)?;
+    expect_pixel(&frame, (width - 1, 0), [255, 0, 255, 255], 0)?;
+    expect_pixel(&frame, (0, height - 1), [0, 255, 255, 255], 0)?;
+    expect_pixel(&frame, (width - 1, height - 1), [255, 255, 255, 255], 0)?;
+    // 21 of 63 steps along x is a third of the way
+    expect_pixel(&frame, (21, 42), [85, 170, 255, 255], 1)?;
+
+    println!("Compute fill wrote the expected gradient");
+
+    Ok(())
+}
@@ -0,0 +1,72 @@
+//! Explicit multi-adapter: a gradient is drawn on one adapter, handed to a second one through a
+//! cross adapter heap, and post processed there, like offloading post processing to a second GPU.
+//!
+//! `cargo run -p d3d12_utils --example cross_adapter [source adapter] [destination adapter]`, e.g.
+//! `0 warp` to draw on the first GPU and post process in software. Both default to WARP, which
+//! still goes through the shared heap and fence. Fails when the pixels are off.
+
+mod common;
+
+use anyhow::Result;
+use common::{adapter_argument, expect_pixel, Example, EXTENT};
+use d3d12_utils::*;
+use windows::Win32::Graphics::{Direct3D12::*, Dxgi::Common::*};
+
+fn main() -> Result<()> {
+    let mut source = Example::on_adapter(adapter_argument(1)?)?;
+    let mut destination = Example::on_adapter(adapter_argument(2)?)?;
+    let (width, height) = EXTENT;
+    let format = DXGI_FORMAT_R8G8B8A8_UNORM;
+
+    let source_texture = source.create_uav_texture(format)?;
+    let destination_texture = destination.create_uav_texture(format)?;
+    let source_resource = source
+        .texture_manager
+        .get_texture(&source_texture)?
+        .get_resource()?
+        .device_resource
+        .clone();
+    let destination_resource = destination
+        .texture_manager
+        .get_texture(&destination_texture)?
+        .get_resource()?
+        .device_resource
+        .clone();
+
+    let mut transfer = CrossAdapterTexture::new(&source.device, &destination.device, &unsafe {
+        source_resource.GetDesc()
+    })?;
+
+    source.dispatch_over_texture("compute_fill.hlsl", &source_texture)?;
+    source.barriers.uav(Some(&source_resource));
+    source.barriers.flush(&source.command_list);
+    transfer.copy_from(
+        &source.command_list,
+        &source_resource,
+        D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+    );
+    source.submit()?;
+    transfer.hand_over(&source.graphics_queue, &destination.graphics_queue)?;
+
+    transfer.copy_to(
+        &destination.command_list,
+        &destination_resource,
+        D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+    );
+    destination.dispatch_over_texture("invert.hlsl", &destination_texture)?;
+    destination.barriers.uav(Some(&destination_resource));
+    let frame =
+        destination.read_back(&destination_texture, D3D12_RESOURCE_STATE_UNORDERED_ACCESS)?;
+    transfer.hand_back(&source.graphics_queue, &destination.graphics_queue)?;
+    source.wait_and_reset()?;
+
+    // The gradient of the compute_fill example, inverted
+    expect_pixel(&frame, (0, 0), [255, 255, 0, 255], 0)?;
+    expect_pixel(&frame, (width - 1, 0), [0, 255, 0, 255], 0)?;
+    expect_pixel(&frame, (0, height - 1), [255, 0, 0, 255], 0)?;
+    expect_pixel(&frame, (21, 42), [170, 85, 0, 255], 1)?;
+
+    println!("Gradient went through the second adapter as expected");
+
+    Ok(())
+}
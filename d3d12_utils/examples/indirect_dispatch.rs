@@ -0,0 +1,62 @@
+//! A compute shader writing the thread group counts of a dispatch, which then fills the top half
+//! of a texture through `ExecuteIndirect` without the CPU seeing the counts.
+//!
+//! `cargo run -p d3d12_utils --example indirect_dispatch [adapter]`, fails when the pixels are off.
+
+mod common;
+
+use anyhow::Result;
+use common::{expect_pixel, Example, EXTENT, THREAD_GROUP_SIZE};
+use d3d12_utils::IndirectDispatch;
+use windows::Win32::Graphics::{Direct3D12::*, Dxgi::Common::*};
+
+// Has to match the constants of dispatch_arguments.hlsl
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct ArgumentConstants {
+    arguments_index: u32,
+    extent: [u32; 2],
+    thread_group_size: u32,
+}
+
+fn main() -> Result<()> {
+    let mut example = Example::new()?;
+    let (width, height) = EXTENT;
+    let filled_rows = height / 2;
+
+    let texture = example.create_uav_texture(DXGI_FORMAT_R8G8B8A8_UNORM)?;
+    let mut arguments = IndirectDispatch::new(&example.device, &mut example.descriptor_manager, 1)?;
+
+    arguments.begin_writes(&mut example.barriers);
+    let constants = ArgumentConstants {
+        arguments_index: arguments.uav().index as u32,
+        extent: [width, filled_rows],
+        thread_group_size: THREAD_GROUP_SIZE,
+    };
+    example.record_compute("dispatch_arguments.hlsl", &constants, |command_list| {
+        unsafe {
+            command_list.Dispatch(1, 1, 1);
+        }
+        Ok(())
+    })?;
+    arguments.finish_writes(&mut example.barriers);
+    example.dispatch_indirect_over_texture("compute_fill.hlsl", &texture, &arguments, 0)?;
+
+    let frame = example.read_back(&texture, D3D12_RESOURCE_STATE_UNORDERED_ACCESS)?;
+    arguments.submitted();
+
+    // The gradient still spans the whole texture, only its top half was dispatched
+    expect_pixel(&frame, (0, 0), [0, 0, 255, 255], 0)?;
+    expect_pixel(
+        &frame,
+        (width - 1, filled_rows - 1),
+        [255, 125, 255, 255],
+        1,
+    )?;
+    expect_pixel(&frame, (0, filled_rows), [0, 0, 0, 0], 0)?;
+    expect_pixel(&frame, (width - 1, height - 1), [0, 0, 0, 0], 0)?;
+
+    println!("Indirect dispatch filled the rows the GPU asked for");
+
+    Ok(())
+}
@@ -0,0 +1,17 @@
+cbuffer Constants : register(b0) {
+    uint texture_index;
+    uint2 texture_size;
+}
+
+// A gradient from black to red along x and to green along y, blue everywhere
+[numthreads(8, 8, 1)]
+void CSMain(uint3 id : SV_DispatchThreadID)
+{
+    if (any(id.xy >= texture_size))
+    {
+        return;
+    }
+
+    RWTexture2D<unorm float4> texture = ResourceDescriptorHeap[texture_index];
+    texture[id.xy] = float4(float2(id.xy) / float2(texture_size - 1), 1.0, 1.0);
+}
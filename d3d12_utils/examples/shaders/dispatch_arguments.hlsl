@@ -0,0 +1,13 @@
+cbuffer Constants : register(b0) {
+    uint arguments_index;
+    uint2 extent;
+    uint thread_group_size;
+}
+
+// Thread groups of a dispatch covering `extent`, as if the GPU had just counted it
+[numthreads(1, 1, 1)]
+void CSMain()
+{
+    RWStructuredBuffer<uint3> arguments = ResourceDescriptorHeap[arguments_index];
+    arguments[0] = uint3((extent + thread_group_size - 1) / thread_group_size, 1);
+}
@@ -0,0 +1,18 @@
+cbuffer Constants : register(b0) {
+    uint texture_index;
+    uint2 texture_size;
+}
+
+// Inverts the colour of a texture in place, alpha stays
+[numthreads(8, 8, 1)]
+void CSMain(uint3 id : SV_DispatchThreadID)
+{
+    if (any(id.xy >= texture_size))
+    {
+        return;
+    }
+
+    RWTexture2D<unorm float4> texture = ResourceDescriptorHeap[texture_index];
+    float4 colour = texture[id.xy];
+    texture[id.xy] = float4(1.0 - colour.rgb, colour.a);
+}
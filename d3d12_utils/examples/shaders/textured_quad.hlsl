@@ -0,0 +1,34 @@
+cbuffer Constants : register(b0) {
+    uint texture_index;
+}
+
+SamplerState linear_clamp : register(s0);
+
+struct PSInput
+{
+    float4 position : SV_POSITION;
+    float2 uv : TEXCOORD;
+};
+
+// Two triangles covering the middle half of the target
+PSInput VSMain(uint vertex_id : SV_VertexID)
+{
+    static const float2 corners[6] = {
+        float2(0.0, 0.0), float2(1.0, 0.0), float2(0.0, 1.0),
+        float2(0.0, 1.0), float2(1.0, 0.0), float2(1.0, 1.0),
+    };
+    float2 uv = corners[vertex_id];
+
+    PSInput result;
+    result.position = float4(uv * float2(1.0, -1.0) + float2(-0.5, 0.5), 0.0, 1.0);
+    result.uv = uv;
+
+    return result;
+}
+
+float4 PSMain(PSInput input) : SV_TARGET
+{
+    Texture2D<float4> texture = ResourceDescriptorHeap[texture_index];
+
+    return texture.SampleLevel(linear_clamp, input.uv, 0.0);
+}
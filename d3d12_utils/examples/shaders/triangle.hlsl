@@ -0,0 +1,25 @@
+struct VSInput
+{
+    float2 position : POSITION;
+    float3 colour : COLOR;
+};
+
+struct PSInput
+{
+    float4 position : SV_POSITION;
+    float3 colour : COLOR;
+};
+
+PSInput VSMain(VSInput input)
+{
+    PSInput result;
+    result.position = float4(input.position, 0.5, 1.0);
+    result.colour = input.colour;
+
+    return result;
+}
+
+float4 PSMain(PSInput input) : SV_TARGET
+{
+    return float4(input.colour, 1.0);
+}
@@ -0,0 +1,106 @@
+//! A 2x2 texture uploaded through an upload ring buffer and drawn on a quad, looked up in the
+//! descriptor heap by index.
+//!
+//! `cargo run -p d3d12_utils --example textured_quad [adapter]`, fails when the pixels are off.
+
+mod common;
+
+use anyhow::Result;
+use common::{expect_pixel, shader_path, Example, EXTENT};
+use d3d12_utils::*;
+use windows::Win32::Graphics::{
+    Direct3D::D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST, Direct3D12::*, Dxgi::Common::*,
+};
+
+const TEXELS: [[u8; 4]; 4] = [
+    [255, 0, 0, 255],
+    [0, 255, 0, 255],
+    [0, 0, 255, 255],
+    [255, 255, 255, 255],
+];
+
+fn main() -> Result<()> {
+    let mut example = Example::new()?;
+    let device = example.device.clone();
+    let format = DXGI_FORMAT_R8G8B8A8_UNORM;
+
+    let mut uploader = UploadRingBuffer::new(&device, None, None)?;
+    let texture = example.texture_manager.create_texture(
+        &device,
+        &mut uploader,
+        Some(&example.graphics_queue),
+        &mut example.descriptor_manager,
+        TextureInfo {
+            dimension: TextureDimension::Two(2, 2),
+            format,
+            ..Default::default()
+        },
+        TEXELS.as_flattened(),
+    )?;
+
+    let target = RenderTarget::new(
+        &device,
+        &mut example.texture_manager,
+        &mut example.descriptor_manager,
+        EXTENT,
+        format,
+        [0.0, 0.0, 0.0, 1.0],
+        DepthRange::Standard,
+    )?;
+
+    let root_signature = create_constants_root_signature(&device, 1)?;
+    let shader = shader_path("textured_quad.hlsl");
+    let pso = create_fullscreen_pipeline_state(
+        &device,
+        &root_signature,
+        &compile_vertex_shader(&shader, "VSMain")?,
+        &compile_pixel_shader(&shader, "PSMain")?,
+        format,
+    )?;
+    let texture_index = example.texture_manager.get_srv(&texture)?.index as u32;
+
+    let command_list = example.command_list.clone();
+    example
+        .texture_manager
+        .transition_uploads(&mut example.barriers);
+    target.begin(
+        &command_list,
+        &mut example.barriers,
+        &example.texture_manager,
+        &example.descriptor_manager,
+    )?;
+    let rtv = example
+        .descriptor_manager
+        .get_cpu_handle(&example.texture_manager.get_rtv(&target.color)?)?;
+    unsafe {
+        command_list.SetPipelineState(&pso);
+        command_list.SetDescriptorHeaps(&[Some(
+            example
+                .descriptor_manager
+                .get_heap(DescriptorType::Resource)?,
+        )]);
+        command_list.SetGraphicsRootSignature(&root_signature);
+        command_list.SetGraphicsRoot32BitConstant(0, texture_index, 0);
+        command_list.RSSetViewports(&[target.viewport]);
+        command_list.RSSetScissorRects(&[target.scissor_rect]);
+        command_list.OMSetRenderTargets(1, &rtv, false, std::ptr::null());
+        command_list.IASetPrimitiveTopology(D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST);
+        command_list.DrawInstanced(6, 1, 0, 0);
+    }
+    target.end(&mut example.barriers, &example.texture_manager)?;
+
+    let frame = example.read_back(&target.color, D3D12_RESOURCE_STATE_ALL_SHADER_RESOURCE)?;
+
+    // The quad covers pixels 16 to 47. The outer quarter of each texel's half of it is clamped to
+    // that texel, so filtering leaves it alone.
+    expect_pixel(&frame, (20, 20), TEXELS[0], 1)?;
+    expect_pixel(&frame, (44, 20), TEXELS[1], 1)?;
+    expect_pixel(&frame, (20, 44), TEXELS[2], 1)?;
+    expect_pixel(&frame, (44, 44), TEXELS[3], 1)?;
+    expect_pixel(&frame, (4, 4), [0, 0, 0, 255], 0)?;
+    expect_pixel(&frame, (60, 32), [0, 0, 0, 255], 0)?;
+
+    println!("Textured quad rendered as expected");
+
+    Ok(())
+}
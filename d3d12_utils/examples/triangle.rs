@@ -0,0 +1,157 @@
+//! A vertex coloured triangle drawn into an offscreen render target, with a vertex buffer in an
+//! upload heap and a pipeline checked against the vertex shader's inputs.
+//!
+//! `cargo run -p d3d12_utils --example triangle [adapter]`, fails when the pixels are off.
+
+mod common;
+
+use anyhow::Result;
+use common::{expect_pixel, shader_path, Example, EXTENT};
+use d3d12_utils::*;
+use windows::{
+    core::PCSTR,
+    Win32::Graphics::{
+        Direct3D::D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST, Direct3D12::*, Dxgi::Common::*,
+    },
+};
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct Vertex {
+    position: [f32; 2],
+    colour: [f32; 3],
+}
+
+// Clockwise, so it faces the camera
+const VERTICES: [Vertex; 3] = [
+    Vertex {
+        position: [0.0, 0.75],
+        colour: [1.0, 0.0, 0.0],
+    },
+    Vertex {
+        position: [0.75, -0.75],
+        colour: [0.0, 1.0, 0.0],
+    },
+    Vertex {
+        position: [-0.75, -0.75],
+        colour: [0.0, 0.0, 1.0],
+    },
+];
+
+fn main() -> Result<()> {
+    let mut example = Example::new()?;
+    let device = example.device.clone();
+    let format = DXGI_FORMAT_R8G8B8A8_UNORM;
+
+    let target = RenderTarget::new(
+        &device,
+        &mut example.texture_manager,
+        &mut example.descriptor_manager,
+        EXTENT,
+        format,
+        [0.0, 0.0, 0.0, 1.0],
+        DepthRange::Standard,
+    )?;
+
+    let vertex_buffer = Resource::create_committed(
+        &device,
+        &D3D12_HEAP_PROPERTIES {
+            Type: D3D12_HEAP_TYPE_UPLOAD,
+            ..Default::default()
+        },
+        &D3D12_RESOURCE_DESC {
+            Dimension: D3D12_RESOURCE_DIMENSION_BUFFER,
+            Width: std::mem::size_of_val(&VERTICES) as u64,
+            Height: 1,
+            DepthOrArraySize: 1,
+            MipLevels: 1,
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            Layout: D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
+            ..Default::default()
+        },
+        D3D12_RESOURCE_STATE_GENERIC_READ,
+        None,
+        true,
+    )?;
+    vertex_buffer.copy_from(&VERTICES)?;
+    let vbv = D3D12_VERTEX_BUFFER_VIEW {
+        BufferLocation: vertex_buffer.gpu_address(),
+        SizeInBytes: std::mem::size_of_val(&VERTICES) as u32,
+        StrideInBytes: std::mem::size_of::<Vertex>() as u32,
+    };
+
+    let input_element_descs = [
+        D3D12_INPUT_ELEMENT_DESC {
+            SemanticName: PCSTR(c"POSITION".as_ptr().cast()),
+            SemanticIndex: 0,
+            Format: DXGI_FORMAT_R32G32_FLOAT,
+            InputSlot: 0,
+            AlignedByteOffset: 0,
+            InputSlotClass: D3D12_INPUT_CLASSIFICATION_PER_VERTEX_DATA,
+            InstanceDataStepRate: 0,
+        },
+        D3D12_INPUT_ELEMENT_DESC {
+            SemanticName: PCSTR(c"COLOR".as_ptr().cast()),
+            SemanticIndex: 0,
+            Format: DXGI_FORMAT_R32G32B32_FLOAT,
+            InputSlot: 0,
+            AlignedByteOffset: 8,
+            InputSlotClass: D3D12_INPUT_CLASSIFICATION_PER_VERTEX_DATA,
+            InstanceDataStepRate: 0,
+        },
+    ];
+    // Nothing in it is used, it is the one root signature that allows an input layout
+    let root_signature = create_root_signature(&device)?;
+    let shader = shader_path("triangle.hlsl");
+    let pso = create_pipeline_state(
+        &device,
+        &root_signature,
+        &input_element_descs,
+        &compile_vertex_shader(&shader, "VSMain")?,
+        &compile_pixel_shader(&shader, "PSMain")?,
+        &target.formats(&example.texture_manager)?,
+        D3D12_PRIMITIVE_TOPOLOGY_TYPE_TRIANGLE,
+        &DepthStencilState::new(DepthRange::Standard),
+    )?;
+
+    let command_list = example.command_list.clone();
+    target.begin(
+        &command_list,
+        &mut example.barriers,
+        &example.texture_manager,
+        &example.descriptor_manager,
+    )?;
+    let rtv = example
+        .descriptor_manager
+        .get_cpu_handle(&example.texture_manager.get_rtv(&target.color)?)?;
+    let dsv = example
+        .descriptor_manager
+        .get_cpu_handle(&example.texture_manager.get_dsv(&target.depth)?)?;
+    unsafe {
+        command_list.SetPipelineState(&pso);
+        command_list.SetGraphicsRootSignature(&root_signature);
+        command_list.RSSetViewports(&[target.viewport]);
+        command_list.RSSetScissorRects(&[target.scissor_rect]);
+        command_list.OMSetRenderTargets(1, &rtv, false, &dsv);
+        command_list.IASetPrimitiveTopology(D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST);
+        command_list.IASetVertexBuffers(0, &[vbv]);
+        command_list.DrawInstanced(3, 1, 0, 0);
+    }
+    target.end(&mut example.barriers, &example.texture_manager)?;
+
+    let frame = example.read_back(&target.color, D3D12_RESOURCE_STATE_ALL_SHADER_RESOURCE)?;
+
+    // Close to each corner the colour of that vertex, outside the clear colour
+    expect_pixel(&frame, (32, 9), [247, 7, 1, 255], 4)?;
+    expect_pixel(&frame, (55, 55), [3, 251, 1, 255], 4)?;
+    expect_pixel(&frame, (8, 55), [3, 1, 251, 255], 4)?;
+    expect_pixel(&frame, (2, 2), [0, 0, 0, 255], 0)?;
+    expect_pixel(&frame, (61, 2), [0, 0, 0, 255], 0)?;
+
+    println!("Triangle rendered as expected");
+
+    Ok(())
+}
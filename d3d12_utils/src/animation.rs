@@ -0,0 +1,465 @@
+use std::fmt;
+
+use anyhow::{ensure, Result};
+use glam::{Quat, Vec3};
+
+/// Where one joint of a skeleton is relative to its parent
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JointPose {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl JointPose {
+    pub const IDENTITY: Self = Self {
+        translation: Vec3::ZERO,
+        rotation: Quat::IDENTITY,
+        scale: Vec3::ONE,
+    };
+
+    /// From `self` at 0 to `other` at 1
+    pub fn lerp(&self, other: &JointPose, t: f32) -> Self {
+        Self {
+            translation: self.translation.lerp(other.translation, t),
+            rotation: self.rotation.slerp(other.rotation, t),
+            scale: self.scale.lerp(other.scale, t),
+        }
+    }
+
+    /// What takes `reference` to `self`, to be added onto other poses with `add`
+    pub fn difference(&self, reference: &JointPose) -> Self {
+        Self {
+            translation: self.translation - reference.translation,
+            rotation: (self.rotation * reference.rotation.inverse()).normalize(),
+            scale: self.scale / reference.scale,
+        }
+    }
+
+    /// Applies `weight` of an additive pose made by `difference`
+    pub fn add(&self, additive: &JointPose, weight: f32) -> Self {
+        let additive = JointPose::IDENTITY.lerp(additive, weight);
+        Self {
+            translation: self.translation + additive.translation,
+            rotation: (additive.rotation * self.rotation).normalize(),
+            scale: self.scale * additive.scale,
+        }
+    }
+}
+
+impl Default for JointPose {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+/// How a pose is combined with the one below it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Replaces the pose below, fully at a weight of 1
+    Linear,
+    /// Adds onto the pose below, for poses made by `JointPose::difference`
+    Additive,
+}
+
+/// Combines `layer` onto `base`, joint by joint. Joints missing from `layer` are left as they are.
+pub fn blend_poses(
+    base: &[JointPose],
+    layer: &[JointPose],
+    weight: f32,
+    mode: BlendMode,
+) -> Vec<JointPose> {
+    base.iter()
+        .enumerate()
+        .map(|(index, pose)| match (layer.get(index), mode) {
+            (Some(layer), BlendMode::Linear) => pose.lerp(layer, weight),
+            (Some(layer), BlendMode::Additive) => pose.add(layer, weight),
+            (None, _) => *pose,
+        })
+        .collect()
+}
+
+/// Keyframes of one joint, in seconds from the start of the clip
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JointTrack {
+    pub times: Vec<f32>,
+    pub poses: Vec<JointPose>,
+}
+
+impl JointTrack {
+    pub fn new(times: Vec<f32>, poses: Vec<JointPose>) -> Result<Self> {
+        ensure!(
+            times.len() == poses.len(),
+            "Track has {} keyframe times but {} poses",
+            times.len(),
+            poses.len()
+        );
+        ensure!(
+            times.windows(2).all(|pair| pair[0] <= pair[1]),
+            "Keyframe times have to go up"
+        );
+
+        Ok(Self { times, poses })
+    }
+
+    /// Interpolated between the keyframes around `time`, held before the first and after the last
+    pub fn sample(&self, time: f32) -> JointPose {
+        let next = self.times.partition_point(|&key_time| key_time <= time);
+        match (next.checked_sub(1), self.poses.get(next)) {
+            (None, Some(first)) => *first,
+            (Some(previous), Some(next_pose)) => {
+                let start = self.times[previous];
+                let length = self.times[next] - start;
+                let t = if length > 0.0 {
+                    (time - start) / length
+                } else {
+                    1.0
+                };
+                self.poses[previous].lerp(next_pose, t)
+            }
+            (Some(last), None) => self.poses[last],
+            (None, None) => JointPose::IDENTITY,
+        }
+    }
+}
+
+/// An animation of a whole skeleton, a track for each joint
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AnimationClip {
+    pub name: String,
+    /// Seconds
+    pub duration: f32,
+    /// Wraps around at the end instead of holding the last pose
+    pub looping: bool,
+    pub tracks: Vec<JointTrack>,
+}
+
+impl AnimationClip {
+    /// Where in the clip `time` seconds since it started lands
+    pub fn local_time(&self, time: f32) -> f32 {
+        if self.duration <= 0.0 {
+            0.0
+        } else if self.looping {
+            time.rem_euclid(self.duration)
+        } else {
+            time.clamp(0.0, self.duration)
+        }
+    }
+
+    pub fn sample(&self, time: f32) -> Vec<JointPose> {
+        let time = self.local_time(time);
+        self.tracks.iter().map(|track| track.sample(time)).collect()
+    }
+}
+
+/// A clip the state machine can be in
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationState {
+    pub name: String,
+    /// Index into the clips the machine is sampled with
+    pub clip: usize,
+    /// Playback rate, 1 for the clip's own speed
+    pub speed: f32,
+}
+
+/// Moves the state machine on to `to` when `event` is triggered in `from`, fading between the two
+/// over `crossfade` seconds
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationTransition {
+    /// None to leave from any state
+    pub from: Option<usize>,
+    pub to: usize,
+    pub event: String,
+    pub crossfade: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Crossfade {
+    to: usize,
+    to_time: f32,
+    elapsed: f32,
+    duration: f32,
+}
+
+/// Plays one state's clip at a time and crossfades to the next when an event triggers one of
+/// the transitions. Time can be paused and scrubbed for inspecting a pose.
+///
+/// Pausing, `scrub` and the `Display` text are only the hooks for a debug UI. The renderer doesn't
+/// play skeletal animation yet, so nothing binds them to keys or draws the text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationStateMachine {
+    states: Vec<AnimationState>,
+    transitions: Vec<AnimationTransition>,
+    current: usize,
+    // Seconds since the current state was entered, scaled by its speed
+    time: f32,
+    crossfade: Option<Crossfade>,
+    /// `update` leaves the time alone, `scrub` still moves it
+    pub paused: bool,
+}
+
+impl AnimationStateMachine {
+    /// Starts out in the first state
+    pub fn new(states: Vec<AnimationState>, transitions: Vec<AnimationTransition>) -> Result<Self> {
+        ensure!(!states.is_empty(), "State machine has no states");
+        for transition in &transitions {
+            ensure!(
+                transition.to < states.len()
+                    && transition.from.is_none_or(|from| from < states.len()),
+                "Transition on \"{}\" goes between states that don't exist",
+                transition.event
+            );
+        }
+
+        Ok(Self {
+            states,
+            transitions,
+            current: 0,
+            time: 0.0,
+            crossfade: None,
+            paused: false,
+        })
+    }
+
+    pub fn current_state(&self) -> &AnimationState {
+        &self.states[self.current]
+    }
+
+    /// Seconds into the current state's clip, before it's wrapped or clamped
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    /// The state being faded to and how far along, from 0 to 1
+    pub fn fading_to(&self) -> Option<(&AnimationState, f32)> {
+        self.crossfade
+            .map(|crossfade| (&self.states[crossfade.to], crossfade.progress()))
+    }
+
+    /// Starts the transition `event` leads to from the current state, preferring ones from it over
+    /// ones from any state. Returns whether there was one. A transition during a crossfade jumps
+    /// to the state being faded to first.
+    pub fn trigger(&mut self, event: &str) -> bool {
+        if let Some(crossfade) = self.crossfade.take() {
+            self.finish(crossfade);
+        }
+        let Some(transition) = self
+            .transitions
+            .iter()
+            .filter(|transition| {
+                transition.event == event && transition.from.is_none_or(|from| from == self.current)
+            })
+            .max_by_key(|transition| transition.from.is_some())
+        else {
+            return false;
+        };
+
+        let crossfade = Crossfade {
+            to: transition.to,
+            to_time: 0.0,
+            elapsed: 0.0,
+            duration: transition.crossfade,
+        };
+        if crossfade.duration > 0.0 {
+            self.crossfade = Some(crossfade);
+        } else {
+            self.finish(crossfade);
+        }
+
+        true
+    }
+
+    /// Moves both states of a crossfade on by `delta_time` seconds, unless paused
+    pub fn update(&mut self, delta_time: f32) {
+        if !self.paused {
+            self.advance(delta_time);
+        }
+    }
+
+    /// Moves time by `delta_time` seconds even while paused, backwards for negative ones.
+    /// Crossfades don't go back past their start.
+    pub fn scrub(&mut self, delta_time: f32) {
+        self.advance(delta_time);
+    }
+
+    /// Joint poses of the current state, blended with the state being faded to
+    pub fn sample(&self, clips: &[AnimationClip]) -> Result<Vec<JointPose>> {
+        let clip = |state: &AnimationState| {
+            clips.get(state.clip).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "State \"{}\" plays clip {}, there are {}",
+                    state.name,
+                    state.clip,
+                    clips.len()
+                )
+            })
+        };
+
+        let pose = clip(self.current_state())?.sample(self.time);
+        Ok(match self.crossfade {
+            Some(crossfade) => {
+                let to = clip(&self.states[crossfade.to])?.sample(crossfade.to_time);
+                blend_poses(&pose, &to, crossfade.progress(), BlendMode::Linear)
+            }
+            None => pose,
+        })
+    }
+
+    fn advance(&mut self, delta_time: f32) {
+        self.time += delta_time * self.states[self.current].speed;
+        if let Some(crossfade) = &mut self.crossfade {
+            crossfade.to_time += delta_time * self.states[crossfade.to].speed;
+            crossfade.elapsed = (crossfade.elapsed + delta_time).max(0.0);
+            if crossfade.elapsed >= crossfade.duration {
+                let crossfade = *crossfade;
+                self.crossfade = None;
+                self.finish(crossfade);
+            }
+        }
+    }
+
+    fn finish(&mut self, crossfade: Crossfade) {
+        self.current = crossfade.to;
+        self.time = crossfade.to_time;
+    }
+}
+
+impl Crossfade {
+    fn progress(&self) -> f32 {
+        (self.elapsed / self.duration).clamp(0.0, 1.0)
+    }
+}
+
+impl fmt::Display for AnimationStateMachine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {:.2}s", self.current_state().name, self.time)?;
+        if let Some((state, progress)) = self.fading_to() {
+            write!(f, ", fading to {} {:.0}%", state.name, progress * 100.0)?;
+        }
+        if self.paused {
+            write!(f, ", paused")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(x: f32) -> JointPose {
+        JointPose {
+            translation: Vec3::new(x, 0.0, 0.0),
+            ..JointPose::IDENTITY
+        }
+    }
+
+    fn clip(name: &str, from: f32, to: f32) -> AnimationClip {
+        AnimationClip {
+            name: name.to_string(),
+            duration: 1.0,
+            looping: true,
+            tracks: vec![JointTrack::new(vec![0.0, 1.0], vec![at(from), at(to)]).unwrap()],
+        }
+    }
+
+    fn state(name: &str, clip: usize) -> AnimationState {
+        AnimationState {
+            name: name.to_string(),
+            clip,
+            speed: 1.0,
+        }
+    }
+
+    fn machine() -> AnimationStateMachine {
+        AnimationStateMachine::new(
+            vec![state("idle", 0), state("walk", 1)],
+            vec![
+                AnimationTransition {
+                    from: Some(0),
+                    to: 1,
+                    event: "move".to_string(),
+                    crossfade: 0.5,
+                },
+                AnimationTransition {
+                    from: None,
+                    to: 0,
+                    event: "stop".to_string(),
+                    crossfade: 0.0,
+                },
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn tracks_interpolate_and_hold_their_ends() {
+        let track = JointTrack::new(vec![1.0, 3.0], vec![at(0.0), at(4.0)]).unwrap();
+        assert_eq!(track.sample(0.0), at(0.0));
+        assert_eq!(track.sample(2.0), at(2.0));
+        assert_eq!(track.sample(5.0), at(4.0));
+        assert!(JointTrack::new(vec![1.0, 0.0], vec![at(0.0), at(1.0)]).is_err());
+    }
+
+    #[test]
+    fn clips_wrap_or_clamp() {
+        let mut clip = clip("walk", 0.0, 1.0);
+        assert_eq!(clip.local_time(1.25), 0.25);
+        assert_eq!(clip.local_time(-0.25), 0.75);
+
+        clip.looping = false;
+        assert_eq!(clip.local_time(1.25), 1.0);
+    }
+
+    #[test]
+    fn additive_poses_add_their_difference() {
+        let lean = JointPose {
+            rotation: Quat::from_rotation_z(0.5),
+            ..at(1.0)
+        };
+        let additive = lean.difference(&JointPose::IDENTITY);
+
+        let base = [at(2.0)];
+        let blended = blend_poses(&base, &[additive], 1.0, BlendMode::Additive);
+        assert_eq!(blended[0].translation, Vec3::new(3.0, 0.0, 0.0));
+        assert!(blended[0].rotation.abs_diff_eq(lean.rotation, 1e-6));
+
+        let halfway = blend_poses(&base, &[at(4.0)], 0.5, BlendMode::Linear);
+        assert_eq!(halfway[0], at(3.0));
+    }
+
+    #[test]
+    fn crossfades_between_states() {
+        let clips = [clip("idle", 0.0, 0.0), clip("walk", 2.0, 2.0)];
+        let mut machine = machine();
+
+        assert!(!machine.trigger("jump"));
+        assert!(machine.trigger("move"));
+        machine.update(0.25);
+        assert_eq!(machine.sample(&clips).unwrap()[0], at(1.0));
+        assert_eq!(machine.to_string(), "idle at 0.25s, fading to walk 50%");
+
+        machine.update(0.25);
+        assert_eq!(machine.current_state().name, "walk");
+        assert_eq!(machine.time(), 0.5);
+        assert_eq!(machine.sample(&clips).unwrap()[0], at(2.0));
+    }
+
+    #[test]
+    fn any_state_transitions_and_scrubbing() {
+        let mut machine = machine();
+        machine.trigger("move");
+        assert!(machine.trigger("stop"));
+        assert_eq!(machine.current_state().name, "idle");
+        assert!(machine.fading_to().is_none());
+
+        machine.paused = true;
+        machine.update(1.0);
+        assert_eq!(machine.time(), 0.0);
+        machine.scrub(0.5);
+        machine.scrub(-0.25);
+        assert_eq!(machine.time(), 0.25);
+        assert!(machine.sample(&[]).is_err());
+    }
+}
@@ -0,0 +1,236 @@
+use anyhow::{ensure, Result};
+use windows::Win32::Graphics::{
+    Direct3D12::*,
+    Dxgi::Common::{DXGI_FORMAT_UNKNOWN, DXGI_SAMPLE_DESC},
+};
+
+use crate::{
+    write_buffer_immediate, BarrierBatcher, DescriptorHandle, DescriptorManager, DescriptorType,
+    Resource,
+};
+
+const COUNTER_SIZE: usize = std::mem::size_of::<u32>();
+
+/// A structured buffer shaders append to and consume from through a UAV with a hidden counter,
+/// e.g. the particles still alive or the objects that passed culling. The counter lives in its
+/// own resource so it can be reset and copied, e.g. into the arguments of an indirect dispatch,
+/// without touching the elements.
+#[derive(Debug)]
+pub struct AppendBuffer {
+    buffer: Resource,
+    counter: Resource,
+    uav: DescriptorHandle,
+    srv: DescriptorHandle,
+    capacity: usize,
+    // Both decay to common after every submission, see `submitted`
+    buffer_state: D3D12_RESOURCE_STATES,
+    counter_state: D3D12_RESOURCE_STATES,
+}
+
+impl AppendBuffer {
+    /// Room for `capacity` elements of `stride` bytes, which is the size of the HLSL struct
+    pub fn new(
+        device: &ID3D12Device4,
+        descriptor_manager: &mut DescriptorManager,
+        capacity: usize,
+        stride: usize,
+    ) -> Result<Self> {
+        ensure!(
+            capacity > 0 && stride > 0,
+            "Append buffers need room for an element"
+        );
+
+        let buffer = create_uav_buffer(device, capacity * stride)?;
+        let counter = create_uav_buffer(device, COUNTER_SIZE)?;
+
+        let uav = descriptor_manager.allocate(DescriptorType::Resource)?;
+        let srv = descriptor_manager.allocate(DescriptorType::Resource)?;
+        unsafe {
+            device.CreateUnorderedAccessView(
+                &buffer.device_resource,
+                &counter.device_resource,
+                &D3D12_UNORDERED_ACCESS_VIEW_DESC {
+                    Format: DXGI_FORMAT_UNKNOWN,
+                    ViewDimension: D3D12_UAV_DIMENSION_BUFFER,
+                    Anonymous: D3D12_UNORDERED_ACCESS_VIEW_DESC_0 {
+                        Buffer: D3D12_BUFFER_UAV {
+                            FirstElement: 0,
+                            NumElements: capacity as u32,
+                            StructureByteStride: stride as u32,
+                            CounterOffsetInBytes: 0,
+                            Flags: D3D12_BUFFER_UAV_FLAG_NONE,
+                        },
+                    },
+                },
+                descriptor_manager.get_cpu_handle(&uav)?,
+            );
+            device.CreateShaderResourceView(
+                &buffer.device_resource,
+                &D3D12_SHADER_RESOURCE_VIEW_DESC {
+                    Format: DXGI_FORMAT_UNKNOWN,
+                    ViewDimension: D3D12_SRV_DIMENSION_BUFFER,
+                    Shader4ComponentMapping: D3D12_DEFAULT_SHADER_4_COMPONENT_MAPPING,
+                    Anonymous: D3D12_SHADER_RESOURCE_VIEW_DESC_0 {
+                        Buffer: D3D12_BUFFER_SRV {
+                            FirstElement: 0,
+                            NumElements: capacity as u32,
+                            StructureByteStride: stride as u32,
+                            Flags: D3D12_BUFFER_SRV_FLAG_NONE,
+                        },
+                    },
+                },
+                descriptor_manager.get_cpu_handle(&srv)?,
+            );
+        }
+
+        Ok(Self {
+            buffer,
+            counter,
+            uav,
+            srv,
+            capacity,
+            buffer_state: D3D12_RESOURCE_STATE_COMMON,
+            counter_state: D3D12_RESOURCE_STATE_COMMON,
+        })
+    }
+
+    /// For `AppendStructuredBuffer` and `ConsumeStructuredBuffer`, which use the counter
+    pub fn uav(&self) -> &DescriptorHandle {
+        &self.uav
+    }
+
+    /// For reading the elements as a `StructuredBuffer`, only the first count of them are valid
+    pub fn srv(&self) -> &DescriptorHandle {
+        &self.srv
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn counter(&self) -> &Resource {
+        &self.counter
+    }
+
+    /// Empties the buffer, usually at the start of a frame. Appending past the capacity is
+    /// dropped by the GPU, but still counted.
+    pub fn reset_counter(
+        &mut self,
+        command_list: &ID3D12GraphicsCommandList,
+        barriers: &mut BarrierBatcher,
+    ) -> Result<()> {
+        self.set_count(command_list, barriers, 0)
+    }
+
+    /// Sets how many elements the buffer holds, e.g. for consuming elements written without the
+    /// counter
+    pub fn set_count(
+        &mut self,
+        command_list: &ID3D12GraphicsCommandList,
+        barriers: &mut BarrierBatcher,
+        count: u32,
+    ) -> Result<()> {
+        self.transition_counter(barriers, D3D12_RESOURCE_STATE_COPY_DEST);
+        barriers.flush(command_list);
+
+        write_buffer_immediate(
+            command_list,
+            &[(self.counter.gpu_address(), count)],
+            D3D12_WRITEBUFFERIMMEDIATE_MODE_DEFAULT,
+        )
+    }
+
+    /// Makes the elements and the counter writable by the shaders recorded after the barriers are
+    /// flushed
+    pub fn begin_writes(&mut self, barriers: &mut BarrierBatcher) {
+        self.transition_buffer(barriers, D3D12_RESOURCE_STATE_UNORDERED_ACCESS);
+        self.transition_counter(barriers, D3D12_RESOURCE_STATE_UNORDERED_ACCESS);
+    }
+
+    /// Makes the elements readable through `srv` by any shader
+    pub fn begin_reads(&mut self, barriers: &mut BarrierBatcher) {
+        self.transition_buffer(
+            barriers,
+            D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE
+                | D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE,
+        );
+    }
+
+    /// Copies the count into `destination`, which has to be in the copy destination state, e.g.
+    /// the vertex count of draw arguments
+    pub fn copy_count(
+        &mut self,
+        command_list: &ID3D12GraphicsCommandList,
+        barriers: &mut BarrierBatcher,
+        destination: &Resource,
+        destination_offset: usize,
+    ) -> Result<()> {
+        ensure!(
+            destination_offset + COUNTER_SIZE <= destination.size,
+            "The count doesn't fit {} bytes at offset {}",
+            destination.size,
+            destination_offset
+        );
+
+        self.transition_counter(barriers, D3D12_RESOURCE_STATE_COPY_SOURCE);
+        barriers.flush(command_list);
+        unsafe {
+            command_list.CopyBufferRegion(
+                &destination.device_resource,
+                destination_offset as u64,
+                &self.counter.device_resource,
+                0,
+                COUNTER_SIZE as u64,
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Call once the command list is submitted, both resources are back in the common state then
+    pub fn submitted(&mut self) {
+        self.buffer_state = D3D12_RESOURCE_STATE_COMMON;
+        self.counter_state = D3D12_RESOURCE_STATE_COMMON;
+    }
+
+    fn transition_buffer(&mut self, barriers: &mut BarrierBatcher, state: D3D12_RESOURCE_STATES) {
+        if self.buffer_state != state {
+            barriers.transition(&self.buffer.device_resource, self.buffer_state, state);
+            self.buffer_state = state;
+        }
+    }
+
+    fn transition_counter(&mut self, barriers: &mut BarrierBatcher, state: D3D12_RESOURCE_STATES) {
+        if self.counter_state != state {
+            barriers.transition(&self.counter.device_resource, self.counter_state, state);
+            self.counter_state = state;
+        }
+    }
+}
+
+fn create_uav_buffer(device: &ID3D12Device4, size: usize) -> Result<Resource> {
+    Resource::create_committed(
+        device,
+        &D3D12_HEAP_PROPERTIES {
+            Type: D3D12_HEAP_TYPE_DEFAULT,
+            ..Default::default()
+        },
+        &D3D12_RESOURCE_DESC {
+            Dimension: D3D12_RESOURCE_DIMENSION_BUFFER,
+            Width: size as u64,
+            Height: 1,
+            DepthOrArraySize: 1,
+            MipLevels: 1,
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            Layout: D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
+            Flags: D3D12_RESOURCE_FLAG_ALLOW_UNORDERED_ACCESS,
+            ..Default::default()
+        },
+        D3D12_RESOURCE_STATE_COMMON,
+        None,
+        false,
+    )
+}
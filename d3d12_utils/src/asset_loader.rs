@@ -0,0 +1,170 @@
+use std::path::PathBuf;
+
+use anyhow::{ensure, Result};
+use windows::Win32::{Foundation::HANDLE, Graphics::Direct3D12::*};
+
+#[cfg(feature = "direct_storage")]
+use crate::DirectStorage;
+use crate::{map_file_range, upload_buffer, CommandQueue, SubResource, UploadRingBuffer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlobCompression {
+    None,
+    /// Only DirectStorage can decompress these
+    GDeflate {
+        uncompressed_size: usize,
+    },
+}
+
+/// A range of a file, e.g. the payload of a DDS or a mesh blob, to be loaded into a buffer in an
+/// upload or default heap
+#[derive(Debug)]
+pub struct AssetBlob<'a> {
+    pub path: PathBuf,
+    pub file_offset: u64,
+    pub size: usize,
+    pub compression: BlobCompression,
+    pub destination: SubResource<'a>,
+}
+
+impl AssetBlob<'_> {
+    pub fn uncompressed_size(&self) -> usize {
+        match self.compression {
+            BlobCompression::None => self.size,
+            BlobCompression::GDeflate { uncompressed_size } => uncompressed_size,
+        }
+    }
+}
+
+/// A `load` in flight, its blobs have arrived once the loader's fence reaches its value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AssetRequest {
+    fence_value: u64,
+}
+
+impl AssetRequest {
+    pub fn is_complete_at(&self, completed_fence_value: u64) -> bool {
+        completed_fence_value >= self.fence_value
+    }
+}
+
+// Every load signals the next value of the loader's fence
+#[derive(Debug, Default)]
+struct LoadFenceValues {
+    last_requested: u64,
+}
+
+impl LoadFenceValues {
+    fn request(&mut self) -> AssetRequest {
+        self.last_requested += 1;
+        AssetRequest {
+            fence_value: self.last_requested,
+        }
+    }
+}
+
+/// Loads asset blobs into GPU buffers. Built with the `direct_storage` feature and with the
+/// DirectStorage runtime installed the reads go through DirectStorage, otherwise the files are
+/// memory mapped and copied through the upload ring buffer.
+#[derive(Debug)]
+pub struct AssetLoader {
+    #[cfg(feature = "direct_storage")]
+    direct_storage: Option<DirectStorage>,
+    // Signalled once the blobs of each load have arrived
+    fence: ID3D12Fence,
+    fence_values: LoadFenceValues,
+}
+
+impl AssetLoader {
+    pub fn new(device: &ID3D12Device4) -> Result<Self> {
+        let fence = unsafe { device.CreateFence(0, D3D12_FENCE_FLAG_NONE) }?;
+
+        Ok(Self {
+            #[cfg(feature = "direct_storage")]
+            direct_storage: DirectStorage::new(device).ok(),
+            fence,
+            fence_values: LoadFenceValues::default(),
+        })
+    }
+
+    pub fn uses_direct_storage(&self) -> bool {
+        #[cfg(feature = "direct_storage")]
+        return self.direct_storage.is_some();
+
+        #[cfg(not(feature = "direct_storage"))]
+        false
+    }
+
+    /// `dependent_queue` won't run anything that comes after until all blobs have arrived. The
+    /// CPU can check on them with the returned request.
+    pub fn load(
+        &mut self,
+        blobs: &[AssetBlob],
+        uploader: &mut UploadRingBuffer,
+        dependent_queue: Option<&CommandQueue>,
+    ) -> Result<AssetRequest> {
+        let request = self.fence_values.request();
+
+        #[cfg(feature = "direct_storage")]
+        if let Some(direct_storage) = &mut self.direct_storage {
+            direct_storage.load(blobs, &self.fence, request.fence_value, dependent_queue)?;
+            return Ok(request);
+        }
+
+        for blob in blobs {
+            ensure!(
+                blob.compression == BlobCompression::None,
+                "{} is compressed, which needs DirectStorage",
+                blob.path.display()
+            );
+
+            // Asset files are only ever read while the renderer runs, and the map is gone once
+            // the upload has copied out of it
+            let data = unsafe { map_file_range(&blob.path, blob.file_offset, blob.size) }?;
+            upload_buffer(uploader, dependent_queue, &data, &blob.destination)?;
+        }
+        // After the copies, blobs written straight into upload heaps are there already
+        unsafe {
+            uploader
+                .queue()
+                .queue
+                .Signal(&self.fence, request.fence_value)?;
+        }
+
+        Ok(request)
+    }
+
+    pub fn is_complete(&self, request: AssetRequest) -> bool {
+        request.is_complete_at(unsafe { self.fence.GetCompletedValue() })
+    }
+
+    /// Blocks until the blobs of `request` have arrived
+    pub fn wait(&self, request: AssetRequest) -> Result<()> {
+        if !self.is_complete(request) {
+            // Without an event the call only returns once the fence is there
+            unsafe {
+                self.fence
+                    .SetEventOnCompletion(request.fence_value, HANDLE::default())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_complete_in_order() {
+        let mut fence_values = LoadFenceValues::default();
+        let first = fence_values.request();
+        let second = fence_values.request();
+
+        assert!(!first.is_complete_at(0));
+        assert!(first.is_complete_at(1));
+        assert!(!second.is_complete_at(1));
+        assert!(first.is_complete_at(2) && second.is_complete_at(2));
+    }
+}
@@ -0,0 +1,656 @@
+use std::mem::ManuallyDrop;
+
+use anyhow::{ensure, Context, Result};
+use windows::Win32::Graphics::Direct3D12::*;
+
+use crate::{aliasing_barrier, release_barrier, uav_barrier};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Transition<R> {
+    resource: R,
+    subresource: u32,
+    before: D3D12_RESOURCE_STATES,
+    after: D3D12_RESOURCE_STATES,
+    flags: D3D12_RESOURCE_BARRIER_FLAGS,
+}
+
+impl<R: PartialEq> Transition<R> {
+    fn same_subresource(&self, other: &Self) -> bool {
+        self.resource == other.resource && self.subresource == other.subresource
+    }
+
+    /// Whether both change at least one subresource, all of them overlap every single one
+    fn overlaps(&self, other: &Self) -> bool {
+        self.resource == other.resource
+            && (self.subresource == other.subresource
+                || self.subresource == D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES
+                || other.subresource == D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Barrier<R> {
+    Transition(Transition<R>),
+    /// None waits for every UAV access
+    Uav(Option<R>),
+    /// None stands for any placed resource in the heap
+    Aliasing {
+        before: Option<R>,
+        after: Option<R>,
+    },
+}
+
+impl<R: PartialEq> Barrier<R> {
+    /// Whether the barrier has to stay ordered with others on `transition`'s subresources
+    fn touches(&self, transition: &Transition<R>) -> bool {
+        let touches = |resource: &Option<R>| {
+            resource
+                .as_ref()
+                .is_none_or(|resource| *resource == transition.resource)
+        };
+
+        match self {
+            Barrier::Transition(other) => other.overlaps(transition),
+            Barrier::Uav(resource) => touches(resource),
+            Barrier::Aliasing { before, after } => touches(before) || touches(after),
+        }
+    }
+
+    fn plain_transition(&mut self) -> Option<&mut Transition<R>> {
+        match self {
+            Barrier::Transition(transition)
+                if transition.flags == D3D12_RESOURCE_BARRIER_FLAG_NONE =>
+            {
+                Some(transition)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Collects transitions between draws and dispatches and records them with a single
+/// `ResourceBarrier` call. Transitions that change nothing are dropped, and one that continues a
+/// pending transition of the same resource is folded into it, so RENDER_TARGET -> PRESENT ->
+/// COPY_SOURCE becomes RENDER_TARGET -> COPY_SOURCE and a round trip disappears completely.
+///
+/// Nothing reaches the GPU until `flush`, which has to happen before any work that uses a
+/// pushed resource and before the command list is closed.
+///
+/// Transitions the GPU can take its time with, like a target that is sampled a few passes after
+/// it was drawn, can be split with `begin_split` and `end_split` so the work in between overlaps
+/// with them.
+///
+/// UAV and aliasing barriers go through here as well so they stay in order with the transitions,
+/// a UAV barrier right after an identical one is dropped.
+#[derive(Debug, Default)]
+pub struct BarrierBatcher {
+    pending: Vec<Barrier<ID3D12Resource>>,
+    /// Split transitions whose begin was pushed but not their end
+    in_flight: Vec<Transition<ID3D12Resource>>,
+    num_pushed: usize,
+    num_recorded: usize,
+}
+
+impl BarrierBatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn transition(
+        &mut self,
+        resource: &ID3D12Resource,
+        state_before: D3D12_RESOURCE_STATES,
+        state_after: D3D12_RESOURCE_STATES,
+    ) {
+        self.transition_subresource(
+            resource,
+            D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
+            state_before,
+            state_after,
+        );
+    }
+
+    pub fn transition_subresource(
+        &mut self,
+        resource: &ID3D12Resource,
+        subresource: u32,
+        state_before: D3D12_RESOURCE_STATES,
+        state_after: D3D12_RESOURCE_STATES,
+    ) {
+        self.num_pushed += 1;
+        push_transition(
+            &mut self.pending,
+            Transition {
+                resource: resource.clone(),
+                subresource,
+                before: state_before,
+                after: state_after,
+                flags: D3D12_RESOURCE_BARRIER_FLAG_NONE,
+            },
+        );
+    }
+
+    /// Starts a transition of all subresources that `end_split` finishes later. The resource
+    /// can't be used in between.
+    pub fn begin_split(
+        &mut self,
+        resource: &ID3D12Resource,
+        state_before: D3D12_RESOURCE_STATES,
+        state_after: D3D12_RESOURCE_STATES,
+    ) -> Result<()> {
+        self.num_pushed += 1;
+        begin_split(
+            &mut self.pending,
+            &mut self.in_flight,
+            Transition {
+                resource: resource.clone(),
+                subresource: D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
+                before: state_before,
+                after: state_after,
+                flags: D3D12_RESOURCE_BARRIER_FLAG_NONE,
+            },
+        )
+    }
+
+    /// Finishes a transition started with the same states by `begin_split`. If the begin was never
+    /// flushed the two become a single plain transition.
+    pub fn end_split(
+        &mut self,
+        resource: &ID3D12Resource,
+        state_before: D3D12_RESOURCE_STATES,
+        state_after: D3D12_RESOURCE_STATES,
+    ) -> Result<()> {
+        end_split(
+            &mut self.pending,
+            &mut self.in_flight,
+            Transition {
+                resource: resource.clone(),
+                subresource: D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
+                before: state_before,
+                after: state_after,
+                flags: D3D12_RESOURCE_BARRIER_FLAG_NONE,
+            },
+        )
+    }
+
+    /// Makes work after the next flush wait for earlier unordered access to `resource`, or to
+    /// every resource with None, e.g. between two dispatches writing and reading the same buffer
+    pub fn uav(&mut self, resource: Option<&ID3D12Resource>) {
+        self.num_pushed += 1;
+        push_uav(&mut self.pending, resource.cloned());
+    }
+
+    /// Switches the memory of overlapping placed resources from `before` to `after`
+    pub fn aliasing(&mut self, before: Option<&ID3D12Resource>, after: Option<&ID3D12Resource>) {
+        self.num_pushed += 1;
+        self.pending.push(Barrier::Aliasing {
+            before: before.cloned(),
+            after: after.cloned(),
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Split transitions that were begun and not ended, has to be 0 when the command list is
+    /// closed
+    pub fn num_splits_in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    /// Records everything pushed since the last flush, if anything is left of it
+    pub fn flush(&mut self, command_list: &ID3D12GraphicsCommandList) {
+        if self.pending.is_empty() {
+            return;
+        }
+
+        let barriers: Vec<D3D12_RESOURCE_BARRIER> = self
+            .pending
+            .drain(..)
+            .map(|barrier| match barrier {
+                Barrier::Transition(transition) => D3D12_RESOURCE_BARRIER {
+                    Type: D3D12_RESOURCE_BARRIER_TYPE_TRANSITION,
+                    Flags: transition.flags,
+                    Anonymous: D3D12_RESOURCE_BARRIER_0 {
+                        Transition: ManuallyDrop::new(D3D12_RESOURCE_TRANSITION_BARRIER {
+                            pResource: Some(transition.resource),
+                            StateBefore: transition.before,
+                            StateAfter: transition.after,
+                            Subresource: transition.subresource,
+                        }),
+                    },
+                },
+                Barrier::Uav(resource) => uav_barrier(resource.as_ref()),
+                Barrier::Aliasing { before, after } => {
+                    aliasing_barrier(before.as_ref(), after.as_ref())
+                }
+            })
+            .collect();
+        self.num_recorded += barriers.len();
+
+        unsafe {
+            command_list.ResourceBarrier(&barriers);
+        }
+
+        for barrier in barriers {
+            release_barrier(barrier);
+        }
+    }
+
+    /// Transitions pushed and barriers actually recorded so far, the difference is what batching
+    /// saved
+    pub fn counts(&self) -> (usize, usize) {
+        (self.num_pushed, self.num_recorded)
+    }
+}
+
+fn push_transition<R: PartialEq>(pending: &mut Vec<Barrier<R>>, transition: Transition<R>) {
+    if transition.before == transition.after {
+        return;
+    }
+
+    // Only the latest barrier on the subresource can be continued, anything earlier already has
+    // a later one depending on it. Halves of split transitions stay as they are, and so do
+    // transitions of all subresources followed by one of a single one or the other way around,
+    // which are recorded one after the other like they would be by flushing in between.
+    let previous = pending
+        .iter()
+        .rposition(|previous| previous.touches(&transition));
+    if let Some(index) = previous {
+        if let Some(previous) = pending[index].plain_transition().filter(|previous| {
+            previous.same_subresource(&transition) && previous.after == transition.before
+        }) {
+            previous.after = transition.after;
+            if previous.before == previous.after {
+                pending.remove(index);
+            }
+            return;
+        }
+    }
+
+    pending.push(Barrier::Transition(transition));
+}
+
+fn push_uav<R: PartialEq>(pending: &mut Vec<Barrier<R>>, resource: Option<R>) {
+    // Nothing has touched the resource since an identical or a global UAV barrier
+    let latest = match &resource {
+        Some(resource) => pending.iter().rev().find(|barrier| match barrier {
+            Barrier::Transition(transition) => transition.resource == *resource,
+            Barrier::Uav(other) => other.as_ref().is_none_or(|other| other == resource),
+            Barrier::Aliasing { .. } => true,
+        }),
+        None => pending.last(),
+    };
+    if matches!(latest, Some(Barrier::Uav(other)) if other.is_none() || *other == resource) {
+        return;
+    }
+
+    pending.push(Barrier::Uav(resource));
+}
+
+fn begin_split<R: PartialEq + Clone>(
+    pending: &mut Vec<Barrier<R>>,
+    in_flight: &mut Vec<Transition<R>>,
+    transition: Transition<R>,
+) -> Result<()> {
+    if transition.before == transition.after {
+        return Ok(());
+    }
+
+    ensure!(
+        !in_flight.iter().any(|split| split.overlaps(&transition)),
+        "A split transition of the resource is already in flight"
+    );
+
+    in_flight.push(transition.clone());
+    pending.push(Barrier::Transition(Transition {
+        flags: D3D12_RESOURCE_BARRIER_FLAG_BEGIN_ONLY,
+        ..transition
+    }));
+
+    Ok(())
+}
+
+fn end_split<R: PartialEq>(
+    pending: &mut Vec<Barrier<R>>,
+    in_flight: &mut Vec<Transition<R>>,
+    transition: Transition<R>,
+) -> Result<()> {
+    if transition.before == transition.after {
+        return Ok(());
+    }
+
+    let index = in_flight
+        .iter()
+        .position(|split| *split == transition)
+        .with_context(|| {
+            format!(
+                "No split transition from {:?} to {:?} was begun for the resource",
+                transition.before, transition.after
+            )
+        })?;
+    in_flight.remove(index);
+
+    // Nothing ran in between, so there is nothing to overlap
+    let begin = pending.iter_mut().find_map(|barrier| match barrier {
+        Barrier::Transition(begin)
+            if begin.same_subresource(&transition)
+                && begin.flags == D3D12_RESOURCE_BARRIER_FLAG_BEGIN_ONLY =>
+        {
+            Some(begin)
+        }
+        _ => None,
+    });
+    if let Some(begin) = begin {
+        begin.flags = D3D12_RESOURCE_BARRIER_FLAG_NONE;
+        return Ok(());
+    }
+
+    pending.push(Barrier::Transition(Transition {
+        flags: D3D12_RESOURCE_BARRIER_FLAG_END_ONLY,
+        ..transition
+    }));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transition(
+        resource: u32,
+        before: D3D12_RESOURCE_STATES,
+        after: D3D12_RESOURCE_STATES,
+    ) -> Transition<u32> {
+        Transition {
+            resource,
+            subresource: D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
+            before,
+            after,
+            flags: D3D12_RESOURCE_BARRIER_FLAG_NONE,
+        }
+    }
+
+    fn flags(barrier: &Barrier<u32>) -> D3D12_RESOURCE_BARRIER_FLAGS {
+        match barrier {
+            Barrier::Transition(transition) => transition.flags,
+            _ => D3D12_RESOURCE_BARRIER_FLAG_NONE,
+        }
+    }
+
+    #[test]
+    fn drops_redundant_transitions() {
+        let mut pending = Vec::new();
+        push_transition(
+            &mut pending,
+            transition(
+                0,
+                D3D12_RESOURCE_STATE_RENDER_TARGET,
+                D3D12_RESOURCE_STATE_RENDER_TARGET,
+            ),
+        );
+        assert!(pending.is_empty());
+
+        // A round trip cancels out
+        push_transition(
+            &mut pending,
+            transition(
+                0,
+                D3D12_RESOURCE_STATE_PRESENT,
+                D3D12_RESOURCE_STATE_RENDER_TARGET,
+            ),
+        );
+        push_transition(
+            &mut pending,
+            transition(
+                0,
+                D3D12_RESOURCE_STATE_RENDER_TARGET,
+                D3D12_RESOURCE_STATE_PRESENT,
+            ),
+        );
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn folds_chained_transitions_per_resource() {
+        let mut pending = Vec::new();
+        push_transition(
+            &mut pending,
+            transition(
+                0,
+                D3D12_RESOURCE_STATE_RENDER_TARGET,
+                D3D12_RESOURCE_STATE_PRESENT,
+            ),
+        );
+        push_transition(
+            &mut pending,
+            transition(
+                1,
+                D3D12_RESOURCE_STATE_DEPTH_WRITE,
+                D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE,
+            ),
+        );
+        push_transition(
+            &mut pending,
+            transition(
+                0,
+                D3D12_RESOURCE_STATE_PRESENT,
+                D3D12_RESOURCE_STATE_COPY_SOURCE,
+            ),
+        );
+
+        assert_eq!(
+            pending,
+            vec![
+                Barrier::Transition(transition(
+                    0,
+                    D3D12_RESOURCE_STATE_RENDER_TARGET,
+                    D3D12_RESOURCE_STATE_COPY_SOURCE
+                )),
+                Barrier::Transition(transition(
+                    1,
+                    D3D12_RESOURCE_STATE_DEPTH_WRITE,
+                    D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE
+                )),
+            ]
+        );
+
+        // Other subresources and mismatched states are left as they are
+        let mip = Transition {
+            subresource: 1,
+            ..transition(
+                0,
+                D3D12_RESOURCE_STATE_COPY_SOURCE,
+                D3D12_RESOURCE_STATE_COPY_DEST,
+            )
+        };
+        push_transition(&mut pending, mip.clone());
+        push_transition(
+            &mut pending,
+            transition(
+                1,
+                D3D12_RESOURCE_STATE_DEPTH_WRITE,
+                D3D12_RESOURCE_STATE_DEPTH_READ,
+            ),
+        );
+        assert_eq!(pending.len(), 4);
+        assert_eq!(pending[2], Barrier::Transition(mip));
+    }
+
+    #[test]
+    fn whole_resource_transitions_order_single_subresources() {
+        let mip = |before, after| Transition {
+            subresource: 1,
+            ..transition(0, before, after)
+        };
+
+        // A mip can't be folded into the whole resource, nor the whole resource into a mip
+        let mut pending = Vec::new();
+        push_transition(
+            &mut pending,
+            transition(
+                0,
+                D3D12_RESOURCE_STATE_COPY_DEST,
+                D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE,
+            ),
+        );
+        push_transition(
+            &mut pending,
+            mip(
+                D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE,
+                D3D12_RESOURCE_STATE_COPY_DEST,
+            ),
+        );
+        push_transition(
+            &mut pending,
+            transition(
+                0,
+                D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE,
+                D3D12_RESOURCE_STATE_COPY_SOURCE,
+            ),
+        );
+        assert_eq!(pending.len(), 3);
+
+        // A mip's earlier transition isn't continued past one of the whole resource
+        let mut pending = Vec::new();
+        push_transition(
+            &mut pending,
+            mip(
+                D3D12_RESOURCE_STATE_COPY_DEST,
+                D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE,
+            ),
+        );
+        push_transition(
+            &mut pending,
+            transition(
+                0,
+                D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE,
+                D3D12_RESOURCE_STATE_COPY_SOURCE,
+            ),
+        );
+        push_transition(
+            &mut pending,
+            mip(
+                D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE,
+                D3D12_RESOURCE_STATE_RENDER_TARGET,
+            ),
+        );
+        assert_eq!(pending.len(), 3);
+        assert_eq!(
+            pending[0],
+            Barrier::Transition(mip(
+                D3D12_RESOURCE_STATE_COPY_DEST,
+                D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE
+            ))
+        );
+
+        // Splits of the whole resource block splits of any of its subresources
+        let mut in_flight = Vec::new();
+        begin_split(
+            &mut pending,
+            &mut in_flight,
+            transition(
+                0,
+                D3D12_RESOURCE_STATE_RENDER_TARGET,
+                D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE,
+            ),
+        )
+        .unwrap();
+        assert!(begin_split(
+            &mut pending,
+            &mut in_flight,
+            mip(
+                D3D12_RESOURCE_STATE_RENDER_TARGET,
+                D3D12_RESOURCE_STATE_COPY_SOURCE
+            ),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn splits_transitions_around_other_work() {
+        let mut pending = Vec::new();
+        let mut in_flight = Vec::new();
+        let to_shader_resource = transition(
+            0,
+            D3D12_RESOURCE_STATE_RENDER_TARGET,
+            D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE,
+        );
+
+        begin_split(&mut pending, &mut in_flight, to_shader_resource.clone()).unwrap();
+        assert!(begin_split(&mut pending, &mut in_flight, to_shader_resource.clone()).is_err());
+        assert_eq!(flags(&pending[0]), D3D12_RESOURCE_BARRIER_FLAG_BEGIN_ONLY);
+        // Halves are never folded into
+        push_transition(
+            &mut pending,
+            transition(
+                0,
+                D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE,
+                D3D12_RESOURCE_STATE_COPY_SOURCE,
+            ),
+        );
+        assert_eq!(pending.len(), 2);
+
+        // Flushed
+        pending.clear();
+        end_split(&mut pending, &mut in_flight, to_shader_resource.clone()).unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(flags(&pending[0]), D3D12_RESOURCE_BARRIER_FLAG_END_ONLY);
+        assert!(in_flight.is_empty());
+        assert!(end_split(&mut pending, &mut in_flight, to_shader_resource).is_err());
+    }
+
+    #[test]
+    fn unflushed_split_becomes_plain_transition() {
+        let mut pending = Vec::new();
+        let mut in_flight = Vec::new();
+        let to_shader_resource = transition(
+            0,
+            D3D12_RESOURCE_STATE_RENDER_TARGET,
+            D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE,
+        );
+
+        begin_split(&mut pending, &mut in_flight, to_shader_resource.clone()).unwrap();
+        end_split(&mut pending, &mut in_flight, to_shader_resource.clone()).unwrap();
+
+        assert_eq!(pending, vec![Barrier::Transition(to_shader_resource)]);
+        assert!(in_flight.is_empty());
+    }
+
+    #[test]
+    fn keeps_uav_barriers_in_order_with_transitions() {
+        let mut pending = Vec::new();
+        push_uav(&mut pending, Some(0));
+        push_uav(&mut pending, Some(0));
+        push_uav(&mut pending, Some(1));
+        assert_eq!(pending, vec![Barrier::Uav(Some(0)), Barrier::Uav(Some(1))]);
+
+        // A global barrier covers the ones after it until something else touches the resource
+        push_uav(&mut pending, None);
+        push_uav(&mut pending, Some(0));
+        assert_eq!(pending.len(), 3);
+
+        // Transitions don't fold across a UAV barrier on the same resource
+        push_transition(
+            &mut pending,
+            transition(
+                0,
+                D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+                D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE,
+            ),
+        );
+        push_uav(&mut pending, Some(0));
+        push_transition(
+            &mut pending,
+            transition(
+                0,
+                D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE,
+                D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+            ),
+        );
+        assert_eq!(pending.len(), 6);
+        assert_eq!(pending[4], Barrier::Uav(Some(0)));
+    }
+}
@@ -0,0 +1,278 @@
+use std::ffi::c_void;
+
+use anyhow::{bail, ensure, Context, Result};
+use windows::{core::GUID, Win32::Graphics::Direct3D12::*};
+
+use crate::{DescriptorManager, DescriptorType};
+
+// Private data `serialize_root_signature` stores the layout of every root signature under
+const ROOT_SIGNATURE_LAYOUT_GUID: GUID = GUID::from_u128(0x6c3f_5a1e_2b7d_4e90_9a41_d8c2_7f3e_1b55);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RootParameterKind {
+    DescriptorTable,
+    Constants,
+    Descriptor,
+}
+
+/// What a root signature expects to be bound before a draw or dispatch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootSignatureLayout {
+    pub parameters: Vec<RootParameterKind>,
+    /// Shaders index `ResourceDescriptorHeap`, so the heap has to be bound even without tables
+    pub indexes_resource_heap: bool,
+}
+
+impl RootSignatureLayout {
+    pub fn from_desc(desc: &D3D12_ROOT_SIGNATURE_DESC1) -> Self {
+        let parameters = if desc.NumParameters == 0 {
+            &[]
+        } else {
+            unsafe { std::slice::from_raw_parts(desc.pParameters, desc.NumParameters as usize) }
+        };
+
+        Self {
+            parameters: parameters
+                .iter()
+                .map(|parameter| match parameter.ParameterType {
+                    D3D12_ROOT_PARAMETER_TYPE_DESCRIPTOR_TABLE => {
+                        RootParameterKind::DescriptorTable
+                    }
+                    D3D12_ROOT_PARAMETER_TYPE_32BIT_CONSTANTS => RootParameterKind::Constants,
+                    _ => RootParameterKind::Descriptor,
+                })
+                .collect(),
+            indexes_resource_heap: (desc.Flags
+                & D3D12_ROOT_SIGNATURE_FLAG_CBV_SRV_UAV_HEAP_DIRECTLY_INDEXED)
+                .0
+                != 0,
+        }
+    }
+
+    /// The layout `serialize_root_signature` stored on `root_signature`, None for root
+    /// signatures created some other way
+    pub fn of(root_signature: &ID3D12RootSignature) -> Option<Self> {
+        let mut size = 0;
+        unsafe {
+            root_signature.GetPrivateData(
+                &ROOT_SIGNATURE_LAYOUT_GUID,
+                &mut size,
+                std::ptr::null_mut(),
+            )
+        }
+        .ok()?;
+
+        let mut bytes = vec![0u8; size as usize];
+        unsafe {
+            root_signature.GetPrivateData(
+                &ROOT_SIGNATURE_LAYOUT_GUID,
+                &mut size,
+                bytes.as_mut_ptr() as *mut c_void,
+            )
+        }
+        .ok()?;
+
+        Self::from_bytes(&bytes)
+    }
+
+    pub(crate) fn attach(&self, root_signature: &ID3D12RootSignature) -> Result<()> {
+        let bytes = self.to_bytes();
+        unsafe {
+            root_signature.SetPrivateData(
+                &ROOT_SIGNATURE_LAYOUT_GUID,
+                bytes.len() as u32,
+                bytes.as_ptr() as *const c_void,
+            )
+        }?;
+
+        Ok(())
+    }
+
+    /// Whether draws need `DescriptorManager`'s resource heap bound
+    pub fn uses_resource_heap(&self) -> bool {
+        self.indexes_resource_heap
+            || self
+                .parameters
+                .contains(&RootParameterKind::DescriptorTable)
+    }
+
+    // One byte with the heap flag, then one per parameter
+    fn to_bytes(&self) -> Vec<u8> {
+        std::iter::once(self.indexes_resource_heap as u8)
+            .chain(self.parameters.iter().map(|kind| *kind as u8))
+            .collect()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let (&indexes_resource_heap, parameters) = bytes.split_first()?;
+
+        Some(Self {
+            parameters: parameters
+                .iter()
+                .map(|kind| match kind {
+                    0 => Some(RootParameterKind::DescriptorTable),
+                    1 => Some(RootParameterKind::Constants),
+                    2 => Some(RootParameterKind::Descriptor),
+                    _ => None,
+                })
+                .collect::<Option<_>>()?,
+            indexes_resource_heap: indexes_resource_heap != 0,
+        })
+    }
+}
+
+/// Follows the root signature, root arguments and descriptor heaps set on a command list so a
+/// draw missing one of them fails with an error instead of faulting on the GPU. Call the
+/// setters next to the matching command list calls and `validate` before every draw. Only
+/// checks in debug builds, in release builds it does nothing.
+#[derive(Debug)]
+pub struct BindingValidator {
+    enabled: bool,
+    root_signature_set: bool,
+    // None when the root signature wasn't created by `serialize_root_signature`
+    layout: Option<RootSignatureLayout>,
+    arguments_set: Vec<bool>,
+    resource_heap: Option<ID3D12DescriptorHeap>,
+}
+
+impl Default for BindingValidator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BindingValidator {
+    pub fn new() -> Self {
+        Self {
+            enabled: cfg!(debug_assertions),
+            root_signature_set: false,
+            layout: None,
+            arguments_set: Vec::new(),
+            resource_heap: None,
+        }
+    }
+
+    pub fn set_descriptor_heaps(&mut self, heaps: &[Option<ID3D12DescriptorHeap>]) {
+        if !self.enabled {
+            return;
+        }
+
+        self.resource_heap = heaps
+            .iter()
+            .flatten()
+            .find(|heap| unsafe { heap.GetDesc() }.Type == D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV)
+            .cloned();
+    }
+
+    /// Setting a root signature unsets all root arguments
+    pub fn set_root_signature(&mut self, root_signature: &ID3D12RootSignature) {
+        if self.enabled {
+            self.bind_layout(RootSignatureLayout::of(root_signature));
+        }
+    }
+
+    pub fn set_root_argument(&mut self, index: u32) {
+        if let Some(set) = self.arguments_set.get_mut(index as usize) {
+            *set = true;
+        }
+    }
+
+    pub fn validate(&self, descriptor_manager: &DescriptorManager) -> Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+
+        self.validate_arguments()?;
+        let uses_resource_heap = self
+            .layout
+            .as_ref()
+            .is_some_and(RootSignatureLayout::uses_resource_heap);
+        if uses_resource_heap {
+            let bound = self
+                .resource_heap
+                .as_ref()
+                .context("Drawing without a CBV/SRV/UAV descriptor heap bound")?;
+            ensure!(
+                *bound == descriptor_manager.get_heap(DescriptorType::Resource)?,
+                "The bound descriptor heap isn't DescriptorManager's, its descriptors are read \
+                from the wrong heap"
+            );
+        }
+
+        Ok(())
+    }
+
+    fn bind_layout(&mut self, layout: Option<RootSignatureLayout>) {
+        self.root_signature_set = true;
+        self.arguments_set = layout
+            .as_ref()
+            .map_or_else(Vec::new, |layout| vec![false; layout.parameters.len()]);
+        self.layout = layout;
+    }
+
+    fn validate_arguments(&self) -> Result<()> {
+        ensure!(self.root_signature_set, "Drawing without a root signature");
+        let Some(layout) = &self.layout else {
+            // Nothing is known about what it expects
+            return Ok(());
+        };
+
+        let missing: Vec<String> = layout
+            .parameters
+            .iter()
+            .zip(&self.arguments_set)
+            .enumerate()
+            .filter(|(_, (_, set))| !**set)
+            .map(|(index, (kind, _))| format!("{} ({:?})", index, kind))
+            .collect();
+        if !missing.is_empty() {
+            bail!(
+                "Root parameters {} weren't set since the root signature was",
+                missing.join(", ")
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_arguments_unset_since_the_root_signature() {
+        let layout = RootSignatureLayout {
+            parameters: vec![
+                RootParameterKind::DescriptorTable,
+                RootParameterKind::Descriptor,
+                RootParameterKind::DescriptorTable,
+            ],
+            indexes_resource_heap: true,
+        };
+        assert_eq!(
+            RootSignatureLayout::from_bytes(&layout.to_bytes()).as_ref(),
+            Some(&layout)
+        );
+        assert_eq!(RootSignatureLayout::from_bytes(&[1, 3]), None);
+
+        let mut validator = BindingValidator::new();
+        assert!(validator.validate_arguments().is_err());
+
+        validator.bind_layout(Some(layout.clone()));
+        validator.set_root_argument(0);
+        validator.set_root_argument(1);
+        let error = validator.validate_arguments().unwrap_err().to_string();
+        assert!(error.contains("2 (DescriptorTable)"), "{}", error);
+
+        validator.set_root_argument(2);
+        assert!(validator.validate_arguments().is_ok());
+
+        // A new root signature unsets everything
+        validator.bind_layout(Some(layout));
+        assert!(validator.validate_arguments().is_err());
+
+        validator.bind_layout(None);
+        assert!(validator.validate_arguments().is_ok());
+    }
+}
@@ -0,0 +1,235 @@
+use std::fmt::Write;
+
+use anyhow::{ensure, Result};
+use windows::Win32::Graphics::{Direct3D12::*, Dxgi::Common::DXGI_SAMPLE_DESC};
+
+use crate::{write_buffer_immediate, Resource};
+
+// Per frame in flight: the tag of the frame that last started, how many of its markers began
+// and how many ended
+const SLOTS_PER_FRAME: usize = 3;
+
+/// How far the GPU got through the markers of a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MarkerProgress<'a> {
+    pub finished: &'a [&'static str],
+    /// Started but not finished, there can be several when passes overlap
+    pub in_flight: &'a [&'static str],
+    pub not_started: &'a [&'static str],
+}
+
+impl<'a> MarkerProgress<'a> {
+    /// `begun` and `ended` count the markers whose start and end the GPU wrote
+    fn new(markers: &'a [&'static str], begun: u32, ended: u32) -> Self {
+        let begun = (begun as usize).min(markers.len());
+        let ended = (ended as usize).min(begun);
+
+        Self {
+            finished: &markers[..ended],
+            in_flight: &markers[ended..begun],
+            not_started: &markers[begun..],
+        }
+    }
+}
+
+/// Progress markers written by the GPU itself around each pass, so that after a device removal
+/// the CPU can tell which passes were running when the GPU hung or faulted. The markers land in
+/// a readback buffer, which outlives the device's queues.
+#[derive(Debug)]
+pub struct Breadcrumbs {
+    buffer: Resource,
+    // Names of the markers recorded for each frame in flight, in order
+    markers: Vec<Vec<&'static str>>,
+    frame_numbers: Vec<u64>,
+    frame_index: usize,
+    open: bool,
+}
+
+impl Breadcrumbs {
+    pub fn new(device: &ID3D12Device4, num_frames: usize) -> Result<Self> {
+        let buffer = Resource::create_committed(
+            device,
+            &D3D12_HEAP_PROPERTIES {
+                Type: D3D12_HEAP_TYPE_READBACK,
+                ..Default::default()
+            },
+            &D3D12_RESOURCE_DESC {
+                Dimension: D3D12_RESOURCE_DIMENSION_BUFFER,
+                Width: (num_frames * SLOTS_PER_FRAME * std::mem::size_of::<u32>()) as u64,
+                Height: 1,
+                DepthOrArraySize: 1,
+                MipLevels: 1,
+                SampleDesc: DXGI_SAMPLE_DESC {
+                    Count: 1,
+                    Quality: 0,
+                },
+                Layout: D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
+                ..Default::default()
+            },
+            D3D12_RESOURCE_STATE_COPY_DEST,
+            None,
+            true,
+        )?;
+
+        Ok(Self {
+            buffer,
+            markers: vec![vec![]; num_frames],
+            frame_numbers: vec![0; num_frames],
+            frame_index: 0,
+            open: false,
+        })
+    }
+
+    /// Call at the start of every frame's command list, before any marker
+    pub fn begin_frame(
+        &mut self,
+        command_list: &ID3D12GraphicsCommandList,
+        frame_index: usize,
+        frame_number: u64,
+    ) -> Result<()> {
+        ensure!(
+            frame_index < self.markers.len(),
+            "Frame index {} is out of range",
+            frame_index
+        );
+
+        self.frame_index = frame_index;
+        self.markers[frame_index].clear();
+        self.open = false;
+        self.frame_numbers[frame_index] = frame_number;
+
+        let first_slot = frame_index * SLOTS_PER_FRAME;
+        self.write(
+            command_list,
+            &[
+                (first_slot, frame_tag(frame_number)),
+                (first_slot + 1, 0),
+                (first_slot + 2, 0),
+            ],
+            D3D12_WRITEBUFFERIMMEDIATE_MODE_MARKER_IN,
+        )
+    }
+
+    /// Marks the start of the work recorded until `end`. Markers don't nest.
+    pub fn begin(
+        &mut self,
+        command_list: &ID3D12GraphicsCommandList,
+        name: &'static str,
+    ) -> Result<()> {
+        ensure!(!self.open, "Breadcrumb {} starts inside another", name);
+
+        let markers = &mut self.markers[self.frame_index];
+        markers.push(name);
+        self.open = true;
+
+        // Written as soon as the GPU reaches the marker
+        let count = markers.len() as u32;
+        self.write(
+            command_list,
+            &[(self.frame_index * SLOTS_PER_FRAME + 1, count)],
+            D3D12_WRITEBUFFERIMMEDIATE_MODE_MARKER_IN,
+        )
+    }
+
+    pub fn end(&mut self, command_list: &ID3D12GraphicsCommandList) -> Result<()> {
+        ensure!(self.open, "Breadcrumb ended without being started");
+        self.open = false;
+
+        // Written once everything before it has finished
+        let count = self.markers[self.frame_index].len() as u32;
+        self.write(
+            command_list,
+            &[(self.frame_index * SLOTS_PER_FRAME + 2, count)],
+            D3D12_WRITEBUFFERIMMEDIATE_MODE_MARKER_OUT,
+        )
+    }
+
+    /// The progress of every frame in flight, oldest first. Only meaningful once the GPU has
+    /// stopped, e.g. after the device was removed.
+    pub fn report(&self) -> String {
+        let slots = unsafe {
+            std::slice::from_raw_parts(
+                self.buffer.mapped_data as *const u32,
+                self.markers.len() * SLOTS_PER_FRAME,
+            )
+        };
+
+        let mut frames: Vec<usize> = (0..self.markers.len())
+            .filter(|&frame_index| !self.markers[frame_index].is_empty())
+            .collect();
+        frames.sort_by_key(|&frame_index| self.frame_numbers[frame_index]);
+
+        let mut report = String::new();
+        for frame_index in frames {
+            let slot = &slots[frame_index * SLOTS_PER_FRAME..][..SLOTS_PER_FRAME];
+            let frame_number = self.frame_numbers[frame_index];
+            if slot[0] != frame_tag(frame_number) {
+                let _ = writeln!(report, "Frame {}: never started", frame_number);
+                continue;
+            }
+
+            let progress = MarkerProgress::new(&self.markers[frame_index], slot[1], slot[2]);
+            let _ = writeln!(
+                report,
+                "Frame {}: finished {:?}, in flight {:?}, not started {:?}",
+                frame_number, progress.finished, progress.in_flight, progress.not_started
+            );
+        }
+
+        report
+    }
+
+    fn write(
+        &self,
+        command_list: &ID3D12GraphicsCommandList,
+        values: &[(usize, u32)],
+        mode: D3D12_WRITEBUFFERIMMEDIATE_MODE,
+    ) -> Result<()> {
+        let values: Vec<_> = values
+            .iter()
+            .map(|&(slot, value)| {
+                (
+                    self.buffer.gpu_address() + (slot * std::mem::size_of::<u32>()) as u64,
+                    value,
+                )
+            })
+            .collect();
+
+        write_buffer_immediate(command_list, &values, mode)
+    }
+}
+
+// The buffer starts out zeroed, so no frame is tagged 0
+fn frame_tag(frame_number: u64) -> u32 {
+    (frame_number as u32).wrapping_add(1).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MARKERS: [&str; 4] = ["Shadows", "Scene", "Post", "Overlay"];
+
+    #[test]
+    fn splits_markers_by_progress() {
+        let progress = MarkerProgress::new(&MARKERS, 3, 1);
+        assert_eq!(progress.finished, ["Shadows"]);
+        assert_eq!(progress.in_flight, ["Scene", "Post"]);
+        assert_eq!(progress.not_started, ["Overlay"]);
+
+        let done = MarkerProgress::new(&MARKERS, 4, 4);
+        assert_eq!(done.finished, MARKERS);
+        assert!(done.in_flight.is_empty() && done.not_started.is_empty());
+    }
+
+    #[test]
+    fn ignores_counts_past_the_recorded_markers() {
+        // Left over from a frame with more markers, or ending before the next marker began
+        let progress = MarkerProgress::new(&MARKERS, 9, 7);
+        assert_eq!(progress.finished, MARKERS);
+
+        let progress = MarkerProgress::new(&MARKERS, 1, 2);
+        assert_eq!(progress.finished, ["Shadows"]);
+        assert!(progress.in_flight.is_empty());
+    }
+}
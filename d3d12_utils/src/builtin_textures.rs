@@ -0,0 +1,329 @@
+use std::f32::consts::PI;
+
+use anyhow::Result;
+use glam::{Vec2, Vec3};
+use windows::Win32::Graphics::{Direct3D12::ID3D12Device4, Dxgi::Common::*};
+
+use crate::{
+    f32_to_f16, CommandQueue, DescriptorManager, TextureDimension, TextureHandle, TextureInfo,
+    TextureManager, UploadRingBuffer,
+};
+
+const BLUE_NOISE_SIZE: usize = 64;
+// Spread of the energy each point of the pattern adds, in texels
+const BLUE_NOISE_SIGMA: f32 = 1.5;
+// Points the initial pattern starts with
+const BLUE_NOISE_INITIAL_FRACTION: f32 = 0.1;
+const BRDF_LUT_SIZE: usize = 32;
+const BRDF_LUT_SAMPLES: u32 = 256;
+const COLOR_LUT_SIZE: usize = 16;
+
+/// Small textures that several passes need, generated at start up so no asset files are needed.
+/// They never change and live as long as the texture manager.
+#[derive(Debug, Clone)]
+pub struct BuiltinTextures {
+    /// R8, 64x64 and tiling, every value appears equally often with no low frequencies. For
+    /// dithering and for offsetting per pixel samples.
+    pub blue_noise: TextureHandle,
+    /// R16G16 float, the split sum scale and bias to F0 of GGX specular. Indexed by N.V along x
+    /// and roughness along y.
+    pub brdf_lut: TextureHandle,
+    /// RGBA8 3D, 16 texels per side, maps every colour to itself. The starting point of colour
+    /// grading, sample it at `colour * 15 / 16 + 0.5 / 16`.
+    pub identity_color_lut: TextureHandle,
+}
+
+impl BuiltinTextures {
+    pub fn new(
+        device: &ID3D12Device4,
+        texture_manager: &mut TextureManager,
+        descriptor_manager: &mut DescriptorManager,
+        uploader: &mut UploadRingBuffer,
+        dependent_queue: Option<&CommandQueue>,
+    ) -> Result<Self> {
+        let mut create = |dimension, format, data: &[u8]| {
+            texture_manager.create_texture(
+                device,
+                uploader,
+                dependent_queue,
+                descriptor_manager,
+                TextureInfo {
+                    dimension,
+                    format,
+                    ..Default::default()
+                },
+                data,
+            )
+        };
+
+        let blue_noise = create(
+            TextureDimension::Two(BLUE_NOISE_SIZE, BLUE_NOISE_SIZE as u32),
+            DXGI_FORMAT_R8_UNORM,
+            &blue_noise(BLUE_NOISE_SIZE),
+        )?;
+        let brdf_lut: Vec<u8> = brdf_lut(BRDF_LUT_SIZE, BRDF_LUT_SAMPLES)
+            .into_iter()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        let brdf_lut = create(
+            TextureDimension::Two(BRDF_LUT_SIZE, BRDF_LUT_SIZE as u32),
+            DXGI_FORMAT_R16G16_FLOAT,
+            &brdf_lut,
+        )?;
+        let identity_color_lut = create(
+            TextureDimension::Three(COLOR_LUT_SIZE, COLOR_LUT_SIZE as u32, COLOR_LUT_SIZE as u16),
+            DXGI_FORMAT_R8G8B8A8_UNORM,
+            &identity_color_lut(COLOR_LUT_SIZE),
+        )?;
+
+        Ok(Self {
+            blue_noise,
+            brdf_lut,
+            identity_color_lut,
+        })
+    }
+}
+
+// Binary pattern on a torus, with the gaussian weighted number of set points around every texel
+#[derive(Clone)]
+struct Pattern<'a> {
+    size: usize,
+    kernel: &'a [f32],
+    set: Vec<bool>,
+    energy: Vec<f32>,
+}
+
+impl<'a> Pattern<'a> {
+    fn new(size: usize, kernel: &'a [f32]) -> Self {
+        Self {
+            size,
+            kernel,
+            set: vec![false; size * size],
+            energy: vec![0.0; size * size],
+        }
+    }
+
+    fn toggle(&mut self, index: usize) {
+        self.set[index] = !self.set[index];
+        let sign = if self.set[index] { 1.0 } else { -1.0 };
+
+        let (x, y) = (index % self.size, index / self.size);
+        for (other, energy) in self.energy.iter_mut().enumerate() {
+            let dx = (other % self.size + self.size - x) % self.size;
+            let dy = (other / self.size + self.size - y) % self.size;
+            *energy += sign * self.kernel[dy * self.size + dx];
+        }
+    }
+
+    fn tightest_cluster(&self) -> usize {
+        self.extreme(true, |energy, best| energy > best)
+    }
+
+    fn largest_void(&self) -> usize {
+        self.extreme(false, |energy, best| energy < best)
+    }
+
+    fn extreme(&self, set: bool, better: impl Fn(f32, f32) -> bool) -> usize {
+        let mut best = None;
+        for (index, &energy) in self.energy.iter().enumerate() {
+            if self.set[index] == set && best.is_none_or(|(_, best)| better(energy, best)) {
+                best = Some((index, energy));
+            }
+        }
+
+        best.map_or(0, |(index, _)| index)
+    }
+}
+
+/// Ulichney's void and cluster method: every texel gets a rank by the order it is added to a
+/// pattern that is kept as evenly spread as possible
+fn blue_noise(size: usize) -> Vec<u8> {
+    let count = size * size;
+    let kernel: Vec<f32> = (0..count)
+        .map(|index| {
+            let wrap = |delta: usize| delta.min(size - delta) as f32;
+            let (dx, dy) = (wrap(index % size), wrap(index / size));
+            (-(dx * dx + dy * dy) / (2.0 * BLUE_NOISE_SIGMA * BLUE_NOISE_SIGMA)).exp()
+        })
+        .collect();
+
+    // A fixed seed keeps the texture the same on every run
+    let mut state = 0x9E37_79B9_u32;
+    let mut random = move || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state as usize
+    };
+    let initial_count = ((count as f32 * BLUE_NOISE_INITIAL_FRACTION) as usize).max(1);
+    let mut initial = Pattern::new(size, &kernel);
+    let mut num_set = 0;
+    while num_set < initial_count {
+        let index = random() % count;
+        if !initial.set[index] {
+            initial.toggle(index);
+            num_set += 1;
+        }
+    }
+
+    // Move points from the tightest cluster into the largest void until that changes nothing
+    loop {
+        let cluster = initial.tightest_cluster();
+        initial.toggle(cluster);
+        let void = initial.largest_void();
+        initial.toggle(void);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut ranks = vec![0; count];
+    let mut removing = initial.clone();
+    for rank in (0..initial_count).rev() {
+        let cluster = removing.tightest_cluster();
+        removing.toggle(cluster);
+        ranks[cluster] = rank;
+    }
+
+    let mut adding = initial;
+    for rank in initial_count..count {
+        let void = adding.largest_void();
+        adding.toggle(void);
+        ranks[void] = rank;
+    }
+
+    ranks
+        .into_iter()
+        .map(|rank| (rank * 256 / count) as u8)
+        .collect()
+}
+
+fn hammersley(index: u32, count: u32) -> Vec2 {
+    Vec2::new(
+        index as f32 / count as f32,
+        index.reverse_bits() as f32 / 2f32.powi(32),
+    )
+}
+
+/// Karis' split sum: scale and bias to F0 of the GGX specular integrated over the hemisphere
+fn integrate_brdf(n_dot_v: f32, roughness: f32, samples: u32) -> Vec2 {
+    let view = Vec3::new((1.0 - n_dot_v * n_dot_v).sqrt(), 0.0, n_dot_v);
+    let alpha = roughness * roughness;
+    // Schlick-GGX for image based lighting
+    let k = alpha / 2.0;
+    let geometry = |n_dot_x: f32| n_dot_x / (n_dot_x * (1.0 - k) + k);
+
+    let mut sum = Vec2::ZERO;
+    for index in 0..samples {
+        let xi = hammersley(index, samples);
+        let phi = 2.0 * PI * xi.x;
+        let cos_theta = ((1.0 - xi.y) / (1.0 + (alpha * alpha - 1.0) * xi.y)).sqrt();
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let half = Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+        let light = 2.0 * view.dot(half) * half - view;
+
+        let n_dot_l = light.z;
+        if n_dot_l > 0.0 {
+            let n_dot_h = half.z.max(0.0);
+            let v_dot_h = view.dot(half).max(0.0);
+            let visibility = geometry(n_dot_v) * geometry(n_dot_l) * v_dot_h / (n_dot_h * n_dot_v);
+            let fresnel = (1.0 - v_dot_h).powi(5);
+            sum += Vec2::new((1.0 - fresnel) * visibility, fresnel * visibility);
+        }
+    }
+
+    sum / samples as f32
+}
+
+// Half floats, scale then bias per texel
+fn brdf_lut(size: usize, samples: u32) -> Vec<u16> {
+    let mut data = Vec::with_capacity(size * size * 2);
+    for y in 0..size {
+        for x in 0..size {
+            let n_dot_v = (x as f32 + 0.5) / size as f32;
+            let roughness = (y as f32 + 0.5) / size as f32;
+            let scale_bias = integrate_brdf(n_dot_v, roughness, samples);
+            data.extend([f32_to_f16(scale_bias.x), f32_to_f16(scale_bias.y)]);
+        }
+    }
+
+    data
+}
+
+// Red along x, green along y and blue along z
+fn identity_color_lut(size: usize) -> Vec<u8> {
+    let level = |index: usize| (index * 255 / (size - 1)) as u8;
+
+    let mut data = Vec::with_capacity(size * size * size * 4);
+    for z in 0..size {
+        for y in 0..size {
+            for x in 0..size {
+                data.extend([level(x), level(y), level(z), 255]);
+            }
+        }
+    }
+
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::f16_to_f32;
+
+    use super::*;
+
+    #[test]
+    fn blue_noise_uses_every_value_equally() {
+        let noise = blue_noise(16);
+
+        let mut histogram = [0; 256];
+        for value in noise {
+            histogram[value as usize] += 1;
+        }
+        assert!(histogram.iter().all(|&count| count == 1));
+    }
+
+    #[test]
+    fn blue_noise_has_no_flat_areas() {
+        let size = 32;
+        let noise = blue_noise(size);
+
+        // Neighbours of a blue noise texel are far apart in value more often than white noise
+        let mut total_difference = 0;
+        for y in 0..size {
+            for x in 0..size {
+                let right = noise[y * size + (x + 1) % size] as i32;
+                total_difference += (noise[y * size + x] as i32 - right).abs();
+            }
+        }
+        let mean_difference = total_difference / (size * size) as i32;
+        assert!(mean_difference > 85, "{}", mean_difference);
+    }
+
+    #[test]
+    fn smooth_surfaces_reflect_everything_head_on() {
+        let scale_bias = integrate_brdf(1.0, 0.0, 16);
+        assert!((scale_bias.x - 1.0).abs() < 1e-3);
+        assert!(scale_bias.y.abs() < 1e-3);
+
+        let lut = brdf_lut(4, 64);
+        for texel in lut.chunks(2) {
+            let sum = f16_to_f32(texel[0]) + f16_to_f32(texel[1]);
+            assert!(sum > 0.0 && sum <= 1.0 + 1e-3, "{}", sum);
+        }
+    }
+
+    #[test]
+    fn identity_lut_maps_colours_to_themselves() {
+        let lut = identity_color_lut(COLOR_LUT_SIZE);
+        let texel = |x: usize, y: usize, z: usize| {
+            let index = ((z * COLOR_LUT_SIZE + y) * COLOR_LUT_SIZE + x) * 4;
+            &lut[index..index + 4]
+        };
+
+        assert_eq!(texel(0, 0, 0), [0, 0, 0, 255]);
+        assert_eq!(texel(15, 0, 0), [255, 0, 0, 255]);
+        assert_eq!(texel(0, 15, 15), [0, 255, 255, 255]);
+        assert_eq!(texel(5, 10, 15), [85, 170, 255, 255]);
+    }
+}
@@ -0,0 +1,84 @@
+use anyhow::Result;
+use windows::{core::PCWSTR, Win32::Graphics::Direct3D12::*};
+
+/// Commands recorded once into a bundle and replayed every frame until what they were recorded
+/// from changes, which a key identifies. Bundles inherit the caller's root signature, root
+/// arguments, render targets and viewports, but start without a pipeline or topology and have to
+/// set the same descriptor heaps as the caller.
+#[derive(Debug)]
+pub struct Bundle {
+    command_allocator: ID3D12CommandAllocator,
+    command_list: ID3D12GraphicsCommandList,
+    // Of the finished recording, None while there is none
+    key: Option<u64>,
+}
+
+impl Bundle {
+    pub fn new(device: &ID3D12Device4, name: &str) -> Result<Self> {
+        let command_allocator: ID3D12CommandAllocator =
+            unsafe { device.CreateCommandAllocator(D3D12_COMMAND_LIST_TYPE_BUNDLE) }?;
+        let command_list: ID3D12GraphicsCommandList = unsafe {
+            device.CreateCommandList1(
+                0,
+                D3D12_COMMAND_LIST_TYPE_BUNDLE,
+                D3D12_COMMAND_LIST_FLAG_NONE,
+            )
+        }?;
+
+        unsafe {
+            command_list.SetName(PCWSTR::from(&name.to_string().into()))?;
+        }
+
+        Ok(Self {
+            command_allocator,
+            command_list,
+            key: None,
+        })
+    }
+
+    /// The bundle to record into when the recording isn't of `key`, None when it can be replayed
+    /// as it is. Recording again throws the old one away, so the GPU must be done with it. Call
+    /// `finish` once everything is recorded.
+    pub fn record(
+        &mut self,
+        key: u64,
+        initial_state: Option<&ID3D12PipelineState>,
+    ) -> Result<Option<ID3D12GraphicsCommandList>> {
+        if self.key == Some(key) {
+            return Ok(None);
+        }
+
+        unsafe {
+            self.command_allocator.Reset()?;
+            self.command_list
+                .Reset(&self.command_allocator, initial_state)?;
+        }
+        self.key = None;
+
+        Ok(Some(self.command_list.clone()))
+    }
+
+    pub fn finish(&mut self, key: u64) -> Result<()> {
+        unsafe {
+            self.command_list.Close()?;
+        }
+        self.key = Some(key);
+
+        Ok(())
+    }
+
+    /// Records replaying the bundle into `command_list`. Does nothing while there is no finished
+    /// recording.
+    pub fn execute(&self, command_list: &ID3D12GraphicsCommandList) {
+        if self.key.is_some() {
+            unsafe {
+                command_list.ExecuteBundle(&self.command_list);
+            }
+        }
+    }
+
+    /// Makes the next `record` record again, e.g. after a resource the bundle uses was replaced
+    pub fn invalidate(&mut self) {
+        self.key = None;
+    }
+}
@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{ensure, Result};
 use windows::{
     core::PCWSTR,
     Win32::{
@@ -26,10 +26,26 @@ impl CommandQueue {
         device: &ID3D12Device4,
         command_type: D3D12_COMMAND_LIST_TYPE,
         name: &str,
+    ) -> Result<CommandQueue> {
+        Self::with_priority(
+            device,
+            command_type,
+            D3D12_COMMAND_QUEUE_PRIORITY_NORMAL,
+            name,
+        )
+    }
+
+    /// Work on high priority queues gets scheduled ahead of normal priority queues of the same type
+    pub fn with_priority(
+        device: &ID3D12Device4,
+        command_type: D3D12_COMMAND_LIST_TYPE,
+        priority: D3D12_COMMAND_QUEUE_PRIORITY,
+        name: &str,
     ) -> Result<CommandQueue> {
         let queue: ID3D12CommandQueue = unsafe {
             device.CreateCommandQueue(&D3D12_COMMAND_QUEUE_DESC {
                 Type: command_type,
+                Priority: priority.0,
                 ..Default::default()
             })
         }?;
@@ -76,6 +92,15 @@ impl CommandQueue {
         fence_value <= self.last_fence_value
     }
 
+    /// What the next submission signals
+    pub fn next_fence_value(&self) -> u64 {
+        self.next_fence_value
+    }
+
+    pub fn fence(&self) -> &ID3D12Fence {
+        &self.fence
+    }
+
     pub fn insert_wait(&self, fence_value: u64) -> Result<()> {
         unsafe {
             self.queue.Wait(&self.fence, fence_value)?;
@@ -111,10 +136,32 @@ impl CommandQueue {
     }
 
     pub fn execute_command_list(&mut self, command_list: &ID3D12CommandList) -> Result<u64> {
+        self.execute_command_lists(std::slice::from_ref(command_list), None)
+    }
+
+    /// Submits all command lists in a single ExecuteCommandLists call and signals the fence once.
+    /// The label, if given, wraps the submission in a PIX event on the queue.
+    pub fn execute_command_lists(
+        &mut self,
+        command_lists: &[ID3D12CommandList],
+        label: Option<&str>,
+    ) -> Result<u64> {
+        ensure!(!command_lists.is_empty(), "No command lists to execute");
+
+        let command_lists: Vec<Option<ID3D12CommandList>> =
+            command_lists.iter().cloned().map(Some).collect();
+
         let value_to_signal = self.next_fence_value;
         unsafe {
-            self.queue
-                .ExecuteCommandLists(&[Some(command_list.clone())]);
+            if let Some(label) = label {
+                self.begin_event(label);
+            }
+
+            self.queue.ExecuteCommandLists(&command_lists);
+
+            if label.is_some() {
+                self.queue.EndEvent();
+            }
 
             self.queue.Signal(&self.fence, value_to_signal)?;
         }
@@ -124,6 +171,18 @@ impl CommandQueue {
         Ok(value_to_signal)
     }
 
+    fn begin_event(&self, label: &str) {
+        // Metadata 0 tells PIX the payload is a null terminated wide string
+        let wide_label: Vec<u16> = label.encode_utf16().chain(std::iter::once(0)).collect();
+        unsafe {
+            self.queue.BeginEvent(
+                0,
+                wide_label.as_ptr() as _,
+                std::mem::size_of_val(wide_label.as_slice()) as u32,
+            );
+        }
+    }
+
     pub fn wait_for_idle(&mut self) -> Result<()> {
         unsafe {
             self.queue.Signal(&self.fence, self.next_fence_value)?;
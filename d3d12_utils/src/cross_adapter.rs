@@ -0,0 +1,263 @@
+use anyhow::{ensure, Context, Result};
+use windows::Win32::Graphics::{Direct3D12::*, Dxgi::Common::DXGI_SAMPLE_DESC};
+
+use crate::{align_data, export_shared_handle, transition_barrier, CommandQueue, Heap, Resource};
+
+/// Hands a 2D texture from a device on one adapter to a device on another, e.g. to do the post
+/// processing of a frame on a second GPU. The texels go through a row major buffer in a heap both
+/// devices have placed a buffer in, and a fence both devices share keeps the two sides in step.
+///
+/// Every transfer takes four steps: `copy_from` records the copy into the shared buffer on the
+/// source device, `hand_over` lets the destination queue wait for it, `copy_to` records the copy
+/// out of the buffer on the destination device and `hand_back` lets the source queue wait until
+/// the buffer can be written again.
+#[derive(Debug)]
+pub struct CrossAdapterTexture {
+    footprint: D3D12_PLACED_SUBRESOURCE_FOOTPRINT,
+    // Kept alive for the buffers placed in them
+    _source_heap: Heap,
+    _destination_heap: Heap,
+    source_buffer: Resource,
+    destination_buffer: Resource,
+    source_fence: ID3D12Fence,
+    destination_fence: ID3D12Fence,
+    fence_value: u64,
+}
+
+impl CrossAdapterTexture {
+    /// `texture_desc` describes the texture on either side, both have to have its size and format
+    pub fn new(
+        source_device: &ID3D12Device4,
+        destination_device: &ID3D12Device4,
+        texture_desc: &D3D12_RESOURCE_DESC,
+    ) -> Result<Self> {
+        ensure!(
+            texture_desc.Dimension == D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+            "Only 2D textures can be handed to another adapter"
+        );
+
+        let mut footprint = D3D12_PLACED_SUBRESOURCE_FOOTPRINT::default();
+        let mut total_bytes = 0;
+        unsafe {
+            source_device.GetCopyableFootprints(
+                texture_desc,
+                0,
+                1,
+                0,
+                &mut footprint,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                &mut total_bytes,
+            );
+        }
+
+        let buffer_desc = D3D12_RESOURCE_DESC {
+            Dimension: D3D12_RESOURCE_DIMENSION_BUFFER,
+            Width: total_bytes,
+            Height: 1,
+            DepthOrArraySize: 1,
+            MipLevels: 1,
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            Layout: D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
+            Flags: D3D12_RESOURCE_FLAG_ALLOW_CROSS_ADAPTER,
+            ..Default::default()
+        };
+        // The heap hands out strictly less than its size, so it gets an alignment to spare
+        let heap_size = align_data(total_bytes as usize, Heap::default_alignment() as usize)
+            + Heap::default_alignment() as usize;
+
+        let mut source_heap = Heap::new(
+            source_device,
+            heap_size,
+            D3D12_HEAP_PROPERTIES {
+                Type: D3D12_HEAP_TYPE_DEFAULT,
+                ..Default::default()
+            },
+            Heap::default_alignment(),
+            D3D12_HEAP_FLAG_SHARED | D3D12_HEAP_FLAG_SHARED_CROSS_ADAPTER,
+            "Cross Adapter Heap".to_string(),
+        )?;
+        let heap_handle =
+            export_shared_handle(source_device, &source_heap.device_heap().into(), None)?;
+        let mut destination_heap = Heap::open_shared(
+            destination_device,
+            heap_handle.0,
+            "Cross Adapter Heap".to_string(),
+        )?;
+
+        // Both buffers are first in their heap, so they are the same memory
+        let source_buffer = source_heap.create_resource(
+            source_device,
+            &buffer_desc,
+            D3D12_RESOURCE_STATE_COPY_DEST,
+            None,
+            false,
+        )?;
+        let destination_buffer = destination_heap.create_resource(
+            destination_device,
+            &buffer_desc,
+            D3D12_RESOURCE_STATE_COPY_SOURCE,
+            None,
+            false,
+        )?;
+
+        let source_fence: ID3D12Fence = unsafe {
+            source_device.CreateFence(
+                0,
+                D3D12_FENCE_FLAG_SHARED | D3D12_FENCE_FLAG_SHARED_CROSS_ADAPTER,
+            )
+        }?;
+        let fence_handle = export_shared_handle(source_device, &(&source_fence).into(), None)?;
+        let mut destination_fence: Option<ID3D12Fence> = None;
+        unsafe { destination_device.OpenSharedHandle(fence_handle.0, &mut destination_fence) }?;
+
+        Ok(Self {
+            footprint,
+            _source_heap: source_heap,
+            _destination_heap: destination_heap,
+            source_buffer,
+            destination_buffer,
+            source_fence,
+            destination_fence: destination_fence.context("Shared handle is not a fence")?,
+            fence_value: 0,
+        })
+    }
+
+    /// Records copying `texture` of the source device into the shared buffer, leaving it in
+    /// `texture_state`. The source queue has to have waited on `hand_back` of the last transfer.
+    pub fn copy_from(
+        &self,
+        command_list: &ID3D12GraphicsCommandList,
+        texture: &ID3D12Resource,
+        texture_state: D3D12_RESOURCE_STATES,
+    ) {
+        let buffer = self.buffer_location(&self.source_buffer);
+        copy_texture_region(
+            command_list,
+            texture,
+            texture_state,
+            D3D12_RESOURCE_STATE_COPY_SOURCE,
+            |texture_location| unsafe {
+                command_list.CopyTextureRegion(
+                    &buffer,
+                    0,
+                    0,
+                    0,
+                    texture_location,
+                    std::ptr::null(),
+                );
+            },
+        );
+    }
+
+    /// Call once the command list with `copy_from` was executed on `source_queue`. Whatever
+    /// `destination_queue` runs after this waits for the copy.
+    pub fn hand_over(
+        &mut self,
+        source_queue: &CommandQueue,
+        destination_queue: &CommandQueue,
+    ) -> Result<()> {
+        self.fence_value += 1;
+        unsafe {
+            source_queue
+                .queue
+                .Signal(&self.source_fence, self.fence_value)?;
+            destination_queue
+                .queue
+                .Wait(&self.destination_fence, self.fence_value)?;
+        }
+
+        Ok(())
+    }
+
+    /// Records copying the shared buffer into `texture` of the destination device, leaving it in
+    /// `texture_state`
+    pub fn copy_to(
+        &self,
+        command_list: &ID3D12GraphicsCommandList,
+        texture: &ID3D12Resource,
+        texture_state: D3D12_RESOURCE_STATES,
+    ) {
+        let buffer = self.buffer_location(&self.destination_buffer);
+        copy_texture_region(
+            command_list,
+            texture,
+            texture_state,
+            D3D12_RESOURCE_STATE_COPY_DEST,
+            |texture_location| unsafe {
+                command_list.CopyTextureRegion(
+                    texture_location,
+                    0,
+                    0,
+                    0,
+                    &buffer,
+                    std::ptr::null(),
+                );
+            },
+        );
+    }
+
+    /// Call once the command list with `copy_to` was executed on `destination_queue`. Whatever
+    /// `source_queue` runs after this waits until the buffer was read, so the next `copy_from`
+    /// can't overwrite it early.
+    pub fn hand_back(
+        &mut self,
+        source_queue: &CommandQueue,
+        destination_queue: &CommandQueue,
+    ) -> Result<()> {
+        self.fence_value += 1;
+        unsafe {
+            destination_queue
+                .queue
+                .Signal(&self.destination_fence, self.fence_value)?;
+            source_queue
+                .queue
+                .Wait(&self.source_fence, self.fence_value)?;
+        }
+
+        Ok(())
+    }
+
+    fn buffer_location(&self, buffer: &Resource) -> D3D12_TEXTURE_COPY_LOCATION {
+        D3D12_TEXTURE_COPY_LOCATION {
+            pResource: Some(buffer.device_resource.clone()),
+            Type: D3D12_TEXTURE_COPY_TYPE_PLACED_FOOTPRINT,
+            Anonymous: D3D12_TEXTURE_COPY_LOCATION_0 {
+                PlacedFootprint: self.footprint,
+            },
+        }
+    }
+}
+
+// Moves `texture` from `texture_state` to `copy_state` and back around `copy`
+fn copy_texture_region(
+    command_list: &ID3D12GraphicsCommandList,
+    texture: &ID3D12Resource,
+    texture_state: D3D12_RESOURCE_STATES,
+    copy_state: D3D12_RESOURCE_STATES,
+    copy: impl FnOnce(&D3D12_TEXTURE_COPY_LOCATION),
+) {
+    let texture_location = D3D12_TEXTURE_COPY_LOCATION {
+        pResource: Some(texture.clone()),
+        Type: D3D12_TEXTURE_COPY_TYPE_SUBRESOURCE_INDEX,
+        Anonymous: D3D12_TEXTURE_COPY_LOCATION_0 {
+            SubresourceIndex: 0,
+        },
+    };
+    let to_copy = transition_barrier(texture, texture_state, copy_state);
+    let from_copy = transition_barrier(texture, copy_state, texture_state);
+
+    unsafe {
+        command_list.ResourceBarrier(std::slice::from_ref(&to_copy));
+        copy(&texture_location);
+        command_list.ResourceBarrier(std::slice::from_ref(&from_copy));
+
+        let _: D3D12_RESOURCE_TRANSITION_BARRIER =
+            std::mem::ManuallyDrop::into_inner(to_copy.Anonymous.Transition);
+        let _: D3D12_RESOURCE_TRANSITION_BARRIER =
+            std::mem::ManuallyDrop::into_inner(from_copy.Anonymous.Transition);
+    }
+}
@@ -0,0 +1,190 @@
+use anyhow::{bail, ensure, Context, Result};
+use glam::Vec3;
+use windows::Win32::Graphics::Dxgi::Common::DXGI_FORMAT_R16G16B16A16_FLOAT;
+
+use crate::{f32_to_f16, TextureDimension, TextureInfo};
+
+// Largest LUT_3D_SIZE the format allows
+const MAX_CUBE_SIZE: usize = 256;
+
+/// A 3D colour lookup table from an Adobe/Resolve .cube file
+#[derive(Debug, Clone, PartialEq)]
+pub struct CubeLut {
+    pub title: Option<String>,
+    /// Entries per side
+    pub size: usize,
+    /// Input colours mapped to the first and last entry along each axis
+    pub domain_min: Vec3,
+    pub domain_max: Vec3,
+    /// Red changes fastest, then green, then blue
+    pub data: Vec<Vec3>,
+}
+
+impl CubeLut {
+    pub fn texture_info(&self) -> TextureInfo {
+        TextureInfo {
+            dimension: TextureDimension::Three(self.size, self.size as u32, self.size as u16),
+            format: DXGI_FORMAT_R16G16B16A16_FLOAT,
+            ..Default::default()
+        }
+    }
+
+    /// Texel data for `texture_info`, output colours outside 0 to 1 are kept
+    pub fn texture_data(&self) -> Vec<u8> {
+        self.data
+            .iter()
+            .flat_map(|colour| colour.extend(1.0).to_array())
+            .flat_map(|value| f32_to_f16(value).to_le_bytes())
+            .collect()
+    }
+}
+
+pub fn parse_cube<'a, I>(lines: I) -> Result<CubeLut>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let mut title = None;
+    let mut size = None;
+    let mut domain_min = Vec3::ZERO;
+    let mut domain_max = Vec3::ONE;
+    let mut data = vec![];
+
+    for (line_index, line) in lines.into_iter().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut parse_line = || -> Result<()> {
+            let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let rest = rest.trim();
+
+            match keyword {
+                "TITLE" => title = Some(rest.trim_matches('"').to_string()),
+                "LUT_3D_SIZE" => {
+                    let value: usize = rest.parse().context("Invalid LUT_3D_SIZE")?;
+                    ensure!(
+                        (2..=MAX_CUBE_SIZE).contains(&value),
+                        "LUT_3D_SIZE has to be between 2 and {}, got {}",
+                        MAX_CUBE_SIZE,
+                        value
+                    );
+                    size = Some(value);
+                }
+                "LUT_1D_SIZE" => bail!("1D LUTs are not supported"),
+                "DOMAIN_MIN" => domain_min = parse_vec3(rest)?,
+                "DOMAIN_MAX" => domain_max = parse_vec3(rest)?,
+                _ if keyword.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+                    bail!("Unknown keyword {}", keyword)
+                }
+                _ => {
+                    ensure!(size.is_some(), "Data before LUT_3D_SIZE");
+                    data.push(parse_vec3(line)?);
+                }
+            }
+
+            Ok(())
+        };
+        parse_line().with_context(|| format!("Line {}", line_index + 1))?;
+    }
+
+    let size = size.context("No LUT_3D_SIZE")?;
+    ensure!(
+        data.len() == size * size * size,
+        "Expected {} entries, got {}",
+        size * size * size,
+        data.len()
+    );
+    ensure!(
+        domain_min.cmplt(domain_max).all(),
+        "DOMAIN_MIN {} has to be below DOMAIN_MAX {}",
+        domain_min,
+        domain_max
+    );
+
+    Ok(CubeLut {
+        title,
+        size,
+        domain_min,
+        domain_max,
+        data,
+    })
+}
+
+fn parse_vec3(text: &str) -> Result<Vec3> {
+    let values = text
+        .split_whitespace()
+        .map(|value| value.parse::<f32>().context("Invalid number"))
+        .collect::<Result<Vec<_>>>()?;
+    ensure!(
+        values.len() == 3,
+        "Expected 3 numbers, got {}",
+        values.len()
+    );
+
+    Ok(Vec3::from_slice(&values))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::f16_to_f32;
+
+    use super::*;
+
+    const INVERT: &str = r#"
+# Swaps every colour for its complement
+TITLE "Invert"
+LUT_3D_SIZE 2
+
+1.0 1.0 1.0
+0.0 1.0 1.0
+1.0 0.0 1.0
+0.0 0.0 1.0
+1.0 1.0 0.0
+0.0 1.0 0.0
+1.0 0.0 0.0
+0.0 0.0 0.0
+"#;
+
+    #[test]
+    fn parses_a_lut() {
+        let lut = parse_cube(INVERT.lines()).unwrap();
+
+        assert_eq!(lut.title.as_deref(), Some("Invert"));
+        assert_eq!(lut.size, 2);
+        assert_eq!(lut.domain_min, Vec3::ZERO);
+        assert_eq!(lut.domain_max, Vec3::ONE);
+        // Red is the fastest changing axis
+        assert_eq!(lut.data[1], Vec3::new(0.0, 1.0, 1.0));
+        assert_eq!(lut.data[6], Vec3::new(1.0, 0.0, 0.0));
+
+        let texels: Vec<f32> = lut
+            .texture_data()
+            .chunks(2)
+            .map(|half| f16_to_f32(u16::from_le_bytes([half[0], half[1]])))
+            .collect();
+        assert_eq!(texels.len(), 8 * 4);
+        assert_eq!(&texels[4..8], [0.0, 1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn reads_the_domain() {
+        let text = INVERT.replace(
+            "LUT_3D_SIZE 2",
+            "LUT_3D_SIZE 2\nDOMAIN_MIN 0 0 -0.5\nDOMAIN_MAX 1 2 1",
+        );
+        let lut = parse_cube(text.lines()).unwrap();
+
+        assert_eq!(lut.domain_min, Vec3::new(0.0, 0.0, -0.5));
+        assert_eq!(lut.domain_max, Vec3::new(1.0, 2.0, 1.0));
+    }
+
+    #[test]
+    fn rejects_broken_files() {
+        assert!(parse_cube(INVERT.replace("0.0 0.0 0.0\n", "").lines()).is_err());
+        assert!(parse_cube(INVERT.replace("LUT_3D_SIZE 2", "").lines()).is_err());
+        assert!(parse_cube(INVERT.replace("LUT_3D_SIZE", "LUT_1D_SIZE").lines()).is_err());
+        assert!(parse_cube(INVERT.replace("1.0 0.0 0.0", "1.0 0.0").lines()).is_err());
+        assert!(parse_cube(INVERT.replace("TITLE", "DOMAIN_MAX 0 0 0\nTITLE").lines()).is_err());
+    }
+}
@@ -0,0 +1,72 @@
+use glam::{Mat4, Vec2, Vec3};
+
+pub const CUBE_FACE_COUNT: usize = 6;
+
+// Forward and up of each face in D3D order: +X, -X, +Y, -Y, +Z, -Z
+const CUBE_FACES: [(Vec3, Vec3); CUBE_FACE_COUNT] = [
+    (Vec3::X, Vec3::Y),
+    (Vec3::NEG_X, Vec3::Y),
+    (Vec3::Y, Vec3::NEG_Z),
+    (Vec3::NEG_Y, Vec3::Z),
+    (Vec3::Z, Vec3::Y),
+    (Vec3::NEG_Z, Vec3::Y),
+];
+
+/// View matrix looking out of `position` through `face`, to be used with a square projection
+/// with a 90 degree field of view so the faces line up
+pub fn cube_face_view(position: Vec3, face: usize) -> Mat4 {
+    let (forward, up) = CUBE_FACES[face];
+
+    Mat4::look_at_lh(position, position + forward, up)
+}
+
+/// Direction through a point on a cube face, with (0, 0) the top left and (1, 1) the bottom right
+/// of the face. Not normalized.
+pub fn cube_face_direction(face: usize, uv: Vec2) -> Vec3 {
+    let (forward, up) = CUBE_FACES[face];
+    let right = up.cross(forward);
+    let st = uv * 2.0 - Vec2::ONE;
+
+    forward + right * st.x - up * st.y
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn face_views_match_face_directions() {
+        let projection = Mat4::perspective_lh(std::f32::consts::FRAC_PI_2, 1.0, 0.1, 100.0);
+        let position = Vec3::new(1.0, 2.0, 3.0);
+
+        for face in 0..CUBE_FACE_COUNT {
+            let view_projection = projection * cube_face_view(position, face);
+            for uv in [
+                Vec2::new(0.5, 0.5),
+                Vec2::new(0.1, 0.3),
+                Vec2::new(0.9, 0.7),
+            ] {
+                let point = position + cube_face_direction(face, uv) * 5.0;
+                let ndc = view_projection.project_point3(point);
+                let projected = Vec2::new(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+
+                assert!(projected.abs_diff_eq(uv, 1e-5), "face {} at {}", face, uv);
+            }
+        }
+    }
+
+    #[test]
+    fn faces_follow_d3d_layout() {
+        // The top left corner of +X points up and towards +Z
+        assert_eq!(cube_face_direction(0, Vec2::ZERO), Vec3::new(1.0, 1.0, 1.0));
+        // The top of +Y faces -Z
+        assert_eq!(
+            cube_face_direction(2, Vec2::new(0.5, 0.0)),
+            Vec3::new(0.0, 1.0, -1.0)
+        );
+        assert_eq!(
+            cube_face_direction(5, Vec2::new(1.0, 0.5)),
+            Vec3::new(-1.0, 0.0, -1.0)
+        );
+    }
+}
@@ -0,0 +1,319 @@
+use glam::{Mat4, Vec3, Vec4};
+
+use crate::Ray;
+
+const MAX_LEAF_SIZE: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Default for Aabb {
+    fn default() -> Self {
+        Self::EMPTY
+    }
+}
+
+impl Aabb {
+    pub const EMPTY: Self = Self {
+        min: Vec3::splat(f32::INFINITY),
+        max: Vec3::splat(f32::NEG_INFINITY),
+    };
+
+    pub fn from_points<I>(points: I) -> Self
+    where
+        I: IntoIterator<Item = Vec3>,
+    {
+        points
+            .into_iter()
+            .fold(Self::EMPTY, |aabb, point| aabb.extend(point))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.cmpgt(self.max).any()
+    }
+
+    pub fn extend(&self, point: Vec3) -> Self {
+        Self {
+            min: self.min.min(point),
+            max: self.max.max(point),
+        }
+    }
+
+    pub fn union(&self, other: &Aabb) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    /// The box around the transformed box
+    pub fn transform(&self, transform: &Mat4) -> Self {
+        if self.is_empty() {
+            return *self;
+        }
+
+        let center = transform.transform_point3(self.center());
+        let half_extent = (self.max - self.min) * 0.5;
+        let half_extent = transform.x_axis.truncate().abs() * half_extent.x
+            + transform.y_axis.truncate().abs() * half_extent.y
+            + transform.z_axis.truncate().abs() * half_extent.z;
+
+        Self {
+            min: center - half_extent,
+            max: center + half_extent,
+        }
+    }
+}
+
+/// The six planes of a view frustum, pointing inwards
+#[derive(Debug, Clone, Copy)]
+pub struct Frustum {
+    planes: [Vec4; 6],
+}
+
+impl Frustum {
+    /// Expects D3D clip space, with depth from 0 to 1
+    pub fn from_view_projection(view_projection: &Mat4) -> Self {
+        let row = |i| view_projection.row(i);
+
+        let planes = [
+            row(3) + row(0),
+            row(3) - row(0),
+            row(3) + row(1),
+            row(3) - row(1),
+            row(2),
+            row(3) - row(2),
+        ]
+        .map(|plane| plane / plane.truncate().length());
+
+        Self { planes }
+    }
+
+    /// Normalized, with the normals pointing inside, in left, right, bottom, top, near, far order.
+    /// Near and far swap with reversed depth.
+    pub fn planes(&self) -> &[Vec4; 6] {
+        &self.planes
+    }
+
+    pub fn intersects(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            let normal = plane.truncate();
+            // The corner furthest along the plane normal
+            let corner = Vec3::select(normal.cmpge(Vec3::ZERO), aabb.max, aabb.min);
+
+            normal.dot(corner) + plane.w >= 0.0
+        })
+    }
+}
+
+#[derive(Debug)]
+enum BvhNodeKind {
+    Leaf { first: usize, count: usize },
+    Inner { left: usize, right: usize },
+}
+
+#[derive(Debug)]
+struct BvhNode {
+    bounds: Aabb,
+    kind: BvhNodeKind,
+}
+
+/// A bounding volume hierarchy over item bounds, for finding the items inside a frustum or along a
+/// ray without testing all of them
+#[derive(Debug, Default)]
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    items: Vec<usize>,
+    item_bounds: Vec<Aabb>,
+}
+
+impl Bvh {
+    pub fn build(item_bounds: &[Aabb]) -> Self {
+        let mut bvh = Self {
+            nodes: Vec::new(),
+            items: (0..item_bounds.len()).collect(),
+            item_bounds: item_bounds.to_vec(),
+        };
+        if !item_bounds.is_empty() {
+            bvh.build_node(0, item_bounds.len());
+        }
+
+        bvh
+    }
+
+    /// Appends the indices of all items intersecting `frustum` to `visible`
+    pub fn query(&self, frustum: &Frustum, visible: &mut Vec<usize>) {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let mut stack = vec![0];
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+            if !frustum.intersects(&node.bounds) {
+                continue;
+            }
+
+            match node.kind {
+                BvhNodeKind::Leaf { first, count } => visible.extend(
+                    self.items[first..first + count]
+                        .iter()
+                        .filter(|item| frustum.intersects(&self.item_bounds[**item])),
+                ),
+                BvhNodeKind::Inner { left, right } => {
+                    stack.push(right);
+                    stack.push(left);
+                }
+            }
+        }
+    }
+
+    /// Appends the indices of all items whose bounds `ray` passes through to `hits`
+    pub fn query_ray(&self, ray: &Ray, hits: &mut Vec<usize>) {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let mut stack = vec![0];
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+            if ray.intersect_aabb(&node.bounds).is_none() {
+                continue;
+            }
+
+            match node.kind {
+                BvhNodeKind::Leaf { first, count } => hits.extend(
+                    self.items[first..first + count]
+                        .iter()
+                        .filter(|item| ray.intersect_aabb(&self.item_bounds[**item]).is_some()),
+                ),
+                BvhNodeKind::Inner { left, right } => {
+                    stack.push(right);
+                    stack.push(left);
+                }
+            }
+        }
+    }
+
+    fn build_node(&mut self, first: usize, count: usize) -> usize {
+        let items = &mut self.items[first..first + count];
+        let bounds = items.iter().fold(Aabb::EMPTY, |bounds, item| {
+            bounds.union(&self.item_bounds[*item])
+        });
+
+        let node_index = self.nodes.len();
+        self.nodes.push(BvhNode {
+            bounds,
+            kind: BvhNodeKind::Leaf { first, count },
+        });
+        if count <= MAX_LEAF_SIZE {
+            return node_index;
+        }
+
+        // Median split along the longest axis of the item centers
+        let centers = Aabb::from_points(items.iter().map(|item| self.item_bounds[*item].center()));
+        let extent = centers.max - centers.min;
+        let axis = if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        };
+        let item_bounds = &self.item_bounds;
+        items.sort_unstable_by(|a, b| {
+            item_bounds[*a].center()[axis].total_cmp(&item_bounds[*b].center()[axis])
+        });
+
+        let left_count = count / 2;
+        let left = self.build_node(first, left_count);
+        let right = self.build_node(first + left_count, count - left_count);
+        self.nodes[node_index].kind = BvhNodeKind::Inner { left, right };
+
+        node_index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_box_at(center: Vec3) -> Aabb {
+        Aabb {
+            min: center - Vec3::splat(0.5),
+            max: center + Vec3::splat(0.5),
+        }
+    }
+
+    fn test_frustum() -> Frustum {
+        let view = Mat4::look_at_lh(Vec3::ZERO, Vec3::Z, Vec3::Y);
+        let projection = Mat4::perspective_lh(std::f32::consts::FRAC_PI_2, 1.0, 0.1, 100.0);
+
+        Frustum::from_view_projection(&(projection * view))
+    }
+
+    #[test]
+    fn frustum_culls_boxes_outside() {
+        let frustum = test_frustum();
+
+        assert!(frustum.intersects(&unit_box_at(Vec3::new(0.0, 0.0, 10.0))));
+        assert!(!frustum.intersects(&unit_box_at(Vec3::new(0.0, 0.0, -10.0))));
+        assert!(!frustum.intersects(&unit_box_at(Vec3::new(0.0, 0.0, 200.0))));
+        assert!(!frustum.intersects(&unit_box_at(Vec3::new(20.0, 0.0, 10.0))));
+        // Straddling the left plane
+        assert!(frustum.intersects(&unit_box_at(Vec3::new(-10.0, 0.0, 10.0))));
+    }
+
+    #[test]
+    fn transformed_aabb_contains_rotated_box() {
+        let aabb = unit_box_at(Vec3::ZERO);
+        let rotated = aabb.transform(&Mat4::from_rotation_y(std::f32::consts::FRAC_PI_4));
+
+        let half_diagonal = std::f32::consts::SQRT_2 * 0.5;
+        assert!((rotated.max.x - half_diagonal).abs() < 1e-5);
+        assert!((rotated.max.y - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn bvh_query_matches_brute_force() {
+        let item_bounds: Vec<Aabb> = (0..100)
+            .map(|i| {
+                let i = i as f32;
+                unit_box_at(Vec3::new(
+                    (i * 7.0) % 40.0 - 20.0,
+                    0.0,
+                    (i * 3.0) % 30.0 - 10.0,
+                ))
+            })
+            .collect();
+        let frustum = test_frustum();
+
+        let mut visible = Vec::new();
+        Bvh::build(&item_bounds).query(&frustum, &mut visible);
+        visible.sort_unstable();
+
+        let expected: Vec<usize> = (0..item_bounds.len())
+            .filter(|i| frustum.intersects(&item_bounds[*i]))
+            .collect();
+        assert!(!expected.is_empty());
+        assert_eq!(visible, expected);
+
+        let ray = Ray::new(Vec3::new(-30.0, 0.0, 2.0), Vec3::new(1.0, 0.0, 0.1));
+        let mut hits = Vec::new();
+        Bvh::build(&item_bounds).query_ray(&ray, &mut hits);
+        hits.sort_unstable();
+
+        let expected: Vec<usize> = (0..item_bounds.len())
+            .filter(|i| ray.intersect_aabb(&item_bounds[*i]).is_some())
+            .collect();
+        assert!(!expected.is_empty());
+        assert_eq!(hits, expected);
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::VecDeque;
+
+/// Holds on to things the GPU may still be using until the fence value of the last submission
+/// using them has completed
+#[derive(Debug)]
+pub struct DeletionQueue<T> {
+    pending: VecDeque<(u64, T)>,
+}
+
+impl<T> Default for DeletionQueue<T> {
+    fn default() -> Self {
+        Self {
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl<T> DeletionQueue<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `fence_value` is the value signalled after the last submission using `item`
+    pub fn push(&mut self, fence_value: u64, item: T) {
+        // Fence values are usually pushed in order, keep the queue sorted when they're not
+        let index = self
+            .pending
+            .partition_point(|(pending_value, _)| *pending_value <= fence_value);
+        self.pending.insert(index, (fence_value, item));
+    }
+
+    /// Removes and returns everything the GPU is done with
+    pub fn retire(&mut self, completed_fence_value: u64) -> Vec<T> {
+        let num_completed = self
+            .pending
+            .partition_point(|(fence_value, _)| *fence_value <= completed_fence_value);
+
+        self.pending
+            .drain(..num_completed)
+            .map(|(_, item)| item)
+            .collect()
+    }
+
+    /// Drops pending items without waiting for the GPU, for ones that were deleted some other way
+    pub fn cancel(&mut self, mut predicate: impl FnMut(&T) -> bool) {
+        self.pending.retain(|(_, item)| !predicate(item));
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retires_completed_items_only() {
+        let mut queue = DeletionQueue::new();
+        queue.push(3, "c");
+        queue.push(1, "a");
+        queue.push(2, "b");
+
+        assert_eq!(queue.retire(0), Vec::<&str>::new());
+        assert_eq!(queue.retire(2), vec!["a", "b"]);
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.retire(3), vec!["c"]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn cancelled_items_are_never_retired() {
+        let mut queue = DeletionQueue::new();
+        queue.push(1, "a");
+        queue.push(2, "b");
+
+        queue.cancel(|item| *item == "a");
+        assert_eq!(queue.retire(2), vec!["b"]);
+    }
+}
@@ -0,0 +1,208 @@
+use anyhow::{ensure, Result};
+use glam::{Mat4, Vec2, Vec3, Vec4Swizzles};
+
+use crate::{CapturedFrame, DepthRange, ViewportRect};
+
+/// A downsampled depth buffer read back to the CPU, a few frames behind what is on screen.
+/// Each texel holds the closest depth of the pixels it covers.
+#[derive(Debug)]
+pub struct DepthSnapshot {
+    /// Order in which the snapshots were captured
+    pub sequence: u64,
+    pub width: u32,
+    pub height: u32,
+    /// The camera the depth was rendered with, not the current one
+    pub view_projection: Mat4,
+    /// Part of the screen the camera was rendered into
+    pub region: ViewportRect,
+    pub depth_range: DepthRange,
+    depths: Vec<f32>,
+}
+
+impl DepthSnapshot {
+    /// `frame` has to be a copy of an R32_FLOAT texture
+    pub fn from_captured(
+        frame: &CapturedFrame,
+        view_projection: Mat4,
+        region: ViewportRect,
+        depth_range: DepthRange,
+    ) -> Result<Self> {
+        let row_size = frame.width as usize * std::mem::size_of::<f32>();
+        ensure!(
+            frame.row_pitch >= row_size
+                && frame.data.len() >= frame.row_pitch * frame.height as usize,
+            "Captured frame is too small for {}x{} depths",
+            frame.width,
+            frame.height
+        );
+
+        let depths = frame
+            .data
+            .chunks_exact(frame.row_pitch)
+            .take(frame.height as usize)
+            .flat_map(|row| row[..row_size].chunks_exact(4))
+            .map(|depth| f32::from_ne_bytes([depth[0], depth[1], depth[2], depth[3]]))
+            .collect();
+
+        Ok(Self {
+            sequence: frame.sequence,
+            width: frame.width,
+            height: frame.height,
+            view_projection,
+            region,
+            depth_range,
+            depths,
+        })
+    }
+
+    /// Depth at a screen position, with (0, 0) the top left and (1, 1) the bottom right corner
+    pub fn depth_at(&self, uv: Vec2) -> Option<f32> {
+        if !(0.0..=1.0).contains(&uv.x) || !(0.0..=1.0).contains(&uv.y) {
+            return None;
+        }
+
+        let x = ((uv.x * self.width as f32) as u32).min(self.width - 1);
+        let y = ((uv.y * self.height as f32) as u32).min(self.height - 1);
+
+        self.depths.get((y * self.width + x) as usize).copied()
+    }
+
+    /// The closest surface under a screen position, None where nothing was drawn or outside
+    /// the region of the camera
+    pub fn world_position(&self, uv: Vec2) -> Option<Vec3> {
+        if !self.region.contains(uv) {
+            return None;
+        }
+        let depth = self.depth_at(uv)?;
+        if depth == self.depth_range.far_depth() {
+            return None;
+        }
+
+        let local_uv = self.region.to_local(uv);
+        let clip = Vec2::new(local_uv.x * 2.0 - 1.0, 1.0 - local_uv.y * 2.0).extend(depth);
+        let world = self.view_projection.inverse() * clip.extend(1.0);
+
+        Some(world.xyz() / world.w)
+    }
+
+    /// True when something closer to the camera covers `point`. Points off screen or behind the
+    /// camera are never occluded.
+    pub fn is_occluded(&self, point: Vec3) -> bool {
+        let clip = self.view_projection * point.extend(1.0);
+        if clip.w <= 0.0 {
+            return false;
+        }
+
+        let ndc = clip.xyz() / clip.w;
+        let local_uv = Vec2::new(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+        if !ViewportRect::FULL.contains(local_uv) {
+            return false;
+        }
+
+        self.depth_at(self.region.to_global(local_uv))
+            .is_some_and(|depth| self.depth_range.is_closer(depth, ndc.z))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CameraProjection;
+
+    const PERSPECTIVE: CameraProjection = CameraProjection::Perspective {
+        fov_y: std::f32::consts::FRAC_PI_2,
+        near: 0.1,
+        far: 100.0,
+    };
+
+    fn snapshot(
+        depths: &[f32],
+        width: u32,
+        row_pitch: usize,
+        region: ViewportRect,
+        depth_range: DepthRange,
+    ) -> DepthSnapshot {
+        let height = depths.len() as u32 / width;
+        let mut data = vec![0xff; row_pitch * height as usize];
+        for (i, depth) in depths.iter().enumerate() {
+            let offset = (i / width as usize) * row_pitch + (i % width as usize) * 4;
+            data[offset..offset + 4].copy_from_slice(&depth.to_ne_bytes());
+        }
+        let frame = CapturedFrame {
+            sequence: 3,
+            width,
+            height,
+            row_pitch,
+            data,
+        };
+        let view_projection = PERSPECTIVE.matrix(1.0, depth_range);
+
+        DepthSnapshot::from_captured(&frame, view_projection, region, depth_range).unwrap()
+    }
+
+    #[test]
+    fn row_pitch_padding_is_skipped() {
+        let snapshot = snapshot(
+            &[0.1, 0.2, 0.3, 0.4],
+            2,
+            256,
+            ViewportRect::FULL,
+            DepthRange::Standard,
+        );
+
+        assert_eq!(snapshot.depth_at(Vec2::new(0.25, 0.25)), Some(0.1));
+        assert_eq!(snapshot.depth_at(Vec2::new(0.75, 0.75)), Some(0.4));
+        assert_eq!(snapshot.depth_at(Vec2::new(1.0, 1.0)), Some(0.4));
+        assert_eq!(snapshot.depth_at(Vec2::new(1.5, 0.5)), None);
+    }
+
+    #[test]
+    fn world_positions_reproject_and_occlude() {
+        for depth_range in [DepthRange::Standard, DepthRange::Reversed] {
+            let projection = PERSPECTIVE.matrix(1.0, depth_range);
+            // On the ray through the centre of the left texel
+            let surface = Vec3::new(-5.0, 0.0, 10.0);
+            let clip = projection * surface.extend(1.0);
+            let snapshot = snapshot(
+                &[clip.z / clip.w, depth_range.far_depth()],
+                2,
+                8,
+                ViewportRect::FULL,
+                depth_range,
+            );
+
+            let picked = snapshot.world_position(Vec2::new(0.25, 0.5)).unwrap();
+            assert!(picked.abs_diff_eq(surface, 1e-3));
+            assert_eq!(snapshot.world_position(Vec2::new(0.75, 0.5)), None);
+
+            assert!(snapshot.is_occluded(Vec3::new(-1.0, 0.0, 20.0)));
+            assert!(!snapshot.is_occluded(Vec3::new(-1.0, 0.0, 5.0)));
+            assert!(!snapshot.is_occluded(Vec3::new(1.0, 0.0, 20.0)));
+            assert!(!snapshot.is_occluded(Vec3::new(0.0, 0.0, -5.0)));
+        }
+    }
+
+    #[test]
+    fn regions_map_to_their_part_of_the_screen() {
+        let projection = PERSPECTIVE.matrix(1.0, DepthRange::Standard);
+        let surface = Vec3::new(0.0, 0.0, 10.0);
+        let clip = projection * surface.extend(1.0);
+        let right_half = ViewportRect {
+            x: 0.5,
+            width: 0.5,
+            ..ViewportRect::FULL
+        };
+        let snapshot = snapshot(
+            &[1.0, 1.0, 1.0, clip.z / clip.w],
+            4,
+            16,
+            right_half,
+            DepthRange::Standard,
+        );
+
+        let picked = snapshot.world_position(Vec2::new(0.75, 0.5)).unwrap();
+        assert!(picked.abs_diff_eq(surface, 1e-3));
+        assert_eq!(snapshot.world_position(Vec2::new(0.25, 0.5)), None);
+        assert!(snapshot.is_occluded(Vec3::new(0.1, 0.0, 20.0)));
+    }
+}
@@ -0,0 +1,237 @@
+use std::ffi::c_void;
+
+use anyhow::{ensure, Result};
+use windows::{
+    core::Interface,
+    Win32::Graphics::{Direct3D12::*, Dxgi::Common::*},
+};
+
+use crate::DepthRange;
+
+/// Format of every depth buffer and the pipelines drawing into them. 32 bits of depth for
+/// reversed-Z precision, plus 8 bits of stencil.
+pub const DEPTH_STENCIL_FORMAT: DXGI_FORMAT = DXGI_FORMAT_D32_FLOAT_S8X24_UINT;
+
+/// What the stencil test does for triangles facing one way
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StencilFace {
+    pub fail_op: D3D12_STENCIL_OP,
+    pub depth_fail_op: D3D12_STENCIL_OP,
+    pub pass_op: D3D12_STENCIL_OP,
+    pub func: D3D12_COMPARISON_FUNC,
+}
+
+impl StencilFace {
+    /// Always passes and never writes
+    pub const KEEP: Self = Self {
+        fail_op: D3D12_STENCIL_OP_KEEP,
+        depth_fail_op: D3D12_STENCIL_OP_KEEP,
+        pass_op: D3D12_STENCIL_OP_KEEP,
+        func: D3D12_COMPARISON_FUNC_ALWAYS,
+    };
+
+    fn desc(&self) -> D3D12_DEPTH_STENCILOP_DESC {
+        D3D12_DEPTH_STENCILOP_DESC {
+            StencilFailOp: self.fail_op,
+            StencilDepthFailOp: self.depth_fail_op,
+            StencilPassOp: self.pass_op,
+            StencilFunc: self.func,
+        }
+    }
+}
+
+impl Default for StencilFace {
+    fn default() -> Self {
+        Self::KEEP
+    }
+}
+
+/// The stencil test, compared against the reference set with `set_stencil_ref`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StencilState {
+    pub read_mask: u8,
+    pub write_mask: u8,
+    pub front: StencilFace,
+    pub back: StencilFace,
+}
+
+impl StencilState {
+    /// Same test for both faces
+    pub fn both_faces(face: StencilFace) -> Self {
+        Self {
+            front: face,
+            back: face,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for StencilState {
+    fn default() -> Self {
+        Self {
+            read_mask: D3D12_DEFAULT_STENCIL_READ_MASK as u8,
+            write_mask: D3D12_DEFAULT_STENCIL_WRITE_MASK as u8,
+            front: StencilFace::KEEP,
+            back: StencilFace::KEEP,
+        }
+    }
+}
+
+/// Depth and stencil part of a graphics pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DepthStencilState {
+    pub depth_test: bool,
+    pub depth_write: bool,
+    pub depth_range: DepthRange,
+    /// Discards pixels whose depth buffer value lies outside the range set with
+    /// `set_depth_bounds`. Needs `depth_bounds_supported`.
+    pub depth_bounds_test: bool,
+    pub stencil: Option<StencilState>,
+}
+
+impl DepthStencilState {
+    /// Tests and writes depth, no stencil
+    pub fn new(depth_range: DepthRange) -> Self {
+        Self {
+            depth_test: true,
+            depth_write: true,
+            depth_range,
+            depth_bounds_test: false,
+            stencil: None,
+        }
+    }
+
+    /// Tests depth without writing it, e.g. for transparent or helper geometry
+    pub fn read_only(depth_range: DepthRange) -> Self {
+        Self {
+            depth_write: false,
+            ..Self::new(depth_range)
+        }
+    }
+
+    pub fn desc(&self) -> D3D12_DEPTH_STENCIL_DESC {
+        let stencil = self.stencil.unwrap_or_default();
+
+        D3D12_DEPTH_STENCIL_DESC {
+            DepthEnable: self.depth_test.into(),
+            DepthWriteMask: if self.depth_write {
+                D3D12_DEPTH_WRITE_MASK_ALL
+            } else {
+                D3D12_DEPTH_WRITE_MASK_ZERO
+            },
+            DepthFunc: self.depth_range.comparison_func(),
+            StencilEnable: self.stencil.is_some().into(),
+            StencilReadMask: stencil.read_mask,
+            StencilWriteMask: stencil.write_mask,
+            FrontFace: stencil.front.desc(),
+            BackFace: stencil.back.desc(),
+        }
+    }
+
+    /// `desc` with the depth bounds test, for pipeline state streams
+    pub fn desc1(&self) -> D3D12_DEPTH_STENCIL_DESC1 {
+        let desc = self.desc();
+
+        D3D12_DEPTH_STENCIL_DESC1 {
+            DepthEnable: desc.DepthEnable,
+            DepthWriteMask: desc.DepthWriteMask,
+            DepthFunc: desc.DepthFunc,
+            StencilEnable: desc.StencilEnable,
+            StencilReadMask: desc.StencilReadMask,
+            StencilWriteMask: desc.StencilWriteMask,
+            FrontFace: desc.FrontFace,
+            BackFace: desc.BackFace,
+            DepthBoundsTestEnable: self.depth_bounds_test.into(),
+        }
+    }
+}
+
+pub fn depth_bounds_supported(device: &ID3D12Device4) -> Result<bool> {
+    let mut options = D3D12_FEATURE_DATA_D3D12_OPTIONS2::default();
+    unsafe {
+        device.CheckFeatureSupport(
+            D3D12_FEATURE_D3D12_OPTIONS2,
+            std::ptr::addr_of_mut!(options) as *mut c_void,
+            std::mem::size_of_val(&options) as u32,
+        )?;
+    }
+
+    Ok(options.DepthBoundsTestSupported.as_bool())
+}
+
+pub fn set_stencil_ref(command_list: &ID3D12GraphicsCommandList, stencil_ref: u8) {
+    unsafe {
+        command_list.OMSetStencilRef(stencil_ref as u32);
+    }
+}
+
+/// Range of depth buffer values pipelines with `depth_bounds_test` let through. The values are
+/// the stored ones, so with reversed-Z `min` is the far end.
+pub fn set_depth_bounds(
+    command_list: &ID3D12GraphicsCommandList,
+    min: f32,
+    max: f32,
+) -> Result<()> {
+    ensure!(
+        (0.0..=max).contains(&min) && max <= 1.0,
+        "Depth bounds {}..{} are not an ordered range within 0..1",
+        min,
+        max
+    );
+
+    let command_list: ID3D12GraphicsCommandList1 = command_list.cast()?;
+    unsafe {
+        command_list.OMSetDepthBounds(min, max);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_state_tests_depth_without_stencil() {
+        let desc = DepthStencilState::new(DepthRange::Reversed).desc1();
+
+        assert!(desc.DepthEnable.as_bool());
+        assert_eq!(desc.DepthWriteMask, D3D12_DEPTH_WRITE_MASK_ALL);
+        assert_eq!(desc.DepthFunc, DepthRange::Reversed.comparison_func());
+        assert!(!desc.StencilEnable.as_bool());
+        assert!(!desc.DepthBoundsTestEnable.as_bool());
+
+        let read_only = DepthStencilState::read_only(DepthRange::Standard).desc();
+        assert_eq!(read_only.DepthWriteMask, D3D12_DEPTH_WRITE_MASK_ZERO);
+    }
+
+    #[test]
+    fn stencil_faces_map_to_desc() {
+        // Marks pixels behind a light volume's front faces, like a stencil light volume pass
+        let state = DepthStencilState {
+            stencil: Some(StencilState {
+                write_mask: 0x0f,
+                front: StencilFace {
+                    depth_fail_op: D3D12_STENCIL_OP_INCR,
+                    ..StencilFace::KEEP
+                },
+                back: StencilFace {
+                    depth_fail_op: D3D12_STENCIL_OP_DECR,
+                    ..StencilFace::KEEP
+                },
+                ..Default::default()
+            }),
+            depth_bounds_test: true,
+            ..DepthStencilState::read_only(DepthRange::Reversed)
+        };
+        let desc = state.desc1();
+
+        assert!(desc.StencilEnable.as_bool());
+        assert!(desc.DepthBoundsTestEnable.as_bool());
+        assert_eq!(desc.StencilReadMask, 0xff);
+        assert_eq!(desc.StencilWriteMask, 0x0f);
+        assert_eq!(desc.FrontFace.StencilDepthFailOp, D3D12_STENCIL_OP_INCR);
+        assert_eq!(desc.BackFace.StencilDepthFailOp, D3D12_STENCIL_OP_DECR);
+        assert_eq!(desc.BackFace.StencilFunc, D3D12_COMPARISON_FUNC_ALWAYS);
+    }
+}
@@ -1,6 +1,8 @@
-use anyhow::{ensure, Result};
+use anyhow::{ensure, Context, Result};
 use windows::Win32::Graphics::Direct3D12::*;
 
+use crate::PoolStats;
+
 #[derive(Debug)]
 pub struct DescriptorHeap {
     pub heap: ID3D12DescriptorHeap,
@@ -49,6 +51,19 @@ impl DescriptorHeap {
         )
     }
 
+    /// Not shader visible, for the CPU side of `ClearUnorderedAccessView*`
+    pub fn cpu_resource_descriptor_heap(
+        device: &ID3D12Device4,
+        num_descriptors: usize,
+    ) -> Result<DescriptorHeap> {
+        Self::create_heap(
+            device,
+            num_descriptors,
+            D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV,
+            D3D12_DESCRIPTOR_HEAP_FLAG_NONE,
+        )
+    }
+
     pub fn render_target_view_heap(
         device: &ID3D12Device4,
         num_descriptors: usize,
@@ -89,6 +104,19 @@ impl DescriptorHeap {
         Ok((self.num_allocated - 1, handle))
     }
 
+    /// Descriptors are never handed back to the heap, `num_free` of the allocated ones are
+    /// waiting on a free list to be reused
+    pub fn stats(&self, num_free: usize) -> PoolStats {
+        let used = self.num_allocated - num_free;
+
+        PoolStats {
+            used,
+            capacity: self.num_descriptors,
+            high_water_mark: self.num_allocated,
+            count: used,
+        }
+    }
+
     pub fn get_cpu_handle(&self, index: usize) -> Result<D3D12_CPU_DESCRIPTOR_HANDLE> {
         ensure!(index < self.num_allocated, "index out of bounds");
 
@@ -107,3 +135,126 @@ impl DescriptorHeap {
         })
     }
 }
+
+/// CPU only descriptor heaps that grow instead of running out, for RTVs and DSVs. Once a heap is
+/// full another one as big is created, the earlier ones stay where they are. An index encodes
+/// the heap and the slot in it, so it stays valid however many heaps come after.
+#[derive(Debug)]
+pub struct DescriptorHeapChain {
+    device: ID3D12Device4,
+    heap_type: D3D12_DESCRIPTOR_HEAP_TYPE,
+    descriptors_per_heap: usize,
+    heaps: Vec<DescriptorHeap>,
+}
+
+impl DescriptorHeapChain {
+    pub fn new(
+        device: &ID3D12Device4,
+        heap_type: D3D12_DESCRIPTOR_HEAP_TYPE,
+        descriptors_per_heap: usize,
+    ) -> Result<Self> {
+        ensure!(
+            descriptors_per_heap > 0,
+            "Descriptor heaps need at least one descriptor"
+        );
+
+        let first_heap = DescriptorHeap::create_heap(
+            device,
+            descriptors_per_heap,
+            heap_type,
+            D3D12_DESCRIPTOR_HEAP_FLAG_NONE,
+        )?;
+
+        Ok(Self {
+            device: device.clone(),
+            heap_type,
+            descriptors_per_heap,
+            heaps: vec![first_heap],
+        })
+    }
+
+    pub fn render_target_view_chain(
+        device: &ID3D12Device4,
+        descriptors_per_heap: usize,
+    ) -> Result<Self> {
+        Self::new(device, D3D12_DESCRIPTOR_HEAP_TYPE_RTV, descriptors_per_heap)
+    }
+
+    pub fn depth_stencil_view_chain(
+        device: &ID3D12Device4,
+        descriptors_per_heap: usize,
+    ) -> Result<Self> {
+        Self::new(device, D3D12_DESCRIPTOR_HEAP_TYPE_DSV, descriptors_per_heap)
+    }
+
+    pub fn allocate_handle(&mut self) -> Result<(usize, D3D12_CPU_DESCRIPTOR_HANDLE)> {
+        let last = self.heaps.len() - 1;
+        if self.heaps[last].num_allocated == self.descriptors_per_heap {
+            self.heaps.push(DescriptorHeap::create_heap(
+                &self.device,
+                self.descriptors_per_heap,
+                self.heap_type,
+                D3D12_DESCRIPTOR_HEAP_FLAG_NONE,
+            )?);
+        }
+
+        let heap_index = self.heaps.len() - 1;
+        let (slot, handle) = self.heaps[heap_index].allocate_handle()?;
+
+        Ok((
+            chain_index(heap_index, slot, self.descriptors_per_heap),
+            handle,
+        ))
+    }
+
+    pub fn get_cpu_handle(&self, index: usize) -> Result<D3D12_CPU_DESCRIPTOR_HANDLE> {
+        let (heap_index, slot) = chain_slot(index, self.descriptors_per_heap);
+        self.heaps
+            .get(heap_index)
+            .with_context(|| format!("No descriptor heap for index {}", index))?
+            .get_cpu_handle(slot)
+    }
+
+    pub fn num_heaps(&self) -> usize {
+        self.heaps.len()
+    }
+
+    /// Like `DescriptorHeap::stats` over every heap, the capacity grows with the chain
+    pub fn stats(&self, num_free: usize) -> PoolStats {
+        let num_allocated: usize = self.heaps.iter().map(|heap| heap.num_allocated).sum();
+        let used = num_allocated - num_free;
+
+        PoolStats {
+            used,
+            capacity: self.heaps.len() * self.descriptors_per_heap,
+            high_water_mark: num_allocated,
+            count: used,
+        }
+    }
+}
+
+fn chain_index(heap_index: usize, slot: usize, descriptors_per_heap: usize) -> usize {
+    heap_index * descriptors_per_heap + slot
+}
+
+fn chain_slot(index: usize, descriptors_per_heap: usize) -> (usize, usize) {
+    (index / descriptors_per_heap, index % descriptors_per_heap)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chain_indices_name_the_heap_and_slot() {
+        assert_eq!(chain_index(0, 7, 1000), 7);
+        assert_eq!(chain_index(2, 5, 1000), 2005);
+
+        for index in [0, 999, 1000, 1001, 123_456] {
+            let (heap_index, slot) = chain_slot(index, 1000);
+            assert!(slot < 1000);
+            assert_eq!(chain_index(heap_index, slot, 1000), index);
+        }
+        assert_eq!(chain_slot(1000, 1000), (1, 0));
+    }
+}
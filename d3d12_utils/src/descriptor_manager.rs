@@ -1,5 +1,7 @@
-use crate::DescriptorHeap;
-use anyhow::{ensure, Context, Result};
+use crate::{
+    AllocationCounts, DescriptorHeap, DescriptorHeapChain, PoolStats, TransientDescriptorRing,
+};
+use anyhow::{bail, ensure, Context, Result};
 use windows::Win32::Graphics::Direct3D12::*;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd)]
@@ -30,23 +32,35 @@ impl Default for DescriptorHandle {
     }
 }
 
+/// The resource heap is the one shader visible heap and has a fixed size, RTVs and DSVs get
+/// another heap whenever theirs are full. Part of the resource heap can be reserved for
+/// descriptors that only live for a frame, see `reserve_transient`.
 #[derive(Debug)]
 pub struct DescriptorManager {
     resource_descriptor_heap: DescriptorHeap,
-    depth_stencil_view_heap: DescriptorHeap,
-    render_target_view_heap: DescriptorHeap,
+    depth_stencil_view_heap: DescriptorHeapChain,
+    render_target_view_heap: DescriptorHeapChain,
 
     resource_free_list: Vec<usize>,
     dsv_free_list: Vec<usize>,
     rtv_free_list: Vec<usize>,
+
+    transient: Option<TransientDescriptorRing>,
+
+    // Of every type, freed ones included
+    num_allocations: u64,
+    num_transient_allocations: u64,
 }
 
-fn get_handle(heap: &mut DescriptorHeap, free_list: &mut Vec<usize>) -> Result<usize> {
+fn get_handle(
+    allocate_handle: impl FnOnce() -> Result<(usize, D3D12_CPU_DESCRIPTOR_HANDLE)>,
+    free_list: &mut Vec<usize>,
+) -> Result<usize> {
     if !free_list.is_empty() {
         return free_list.pop().context("Retrieving index from free list");
     }
 
-    let (index, _) = heap.allocate_handle()?;
+    let (index, _) = allocate_handle()?;
     Ok(index)
 }
 
@@ -54,12 +68,72 @@ impl DescriptorManager {
     pub fn new(device: &ID3D12Device4) -> Result<Self> {
         Ok(DescriptorManager {
             resource_descriptor_heap: DescriptorHeap::resource_descriptor_heap(device, 500_000)?,
-            depth_stencil_view_heap: DescriptorHeap::depth_stencil_view_heap(device, 1000)?,
-            render_target_view_heap: DescriptorHeap::render_target_view_heap(device, 1000)?,
+            depth_stencil_view_heap: DescriptorHeapChain::depth_stencil_view_chain(device, 1000)?,
+            render_target_view_heap: DescriptorHeapChain::render_target_view_chain(device, 1000)?,
 
             resource_free_list: Vec::new(),
             dsv_free_list: Vec::new(),
             rtv_free_list: Vec::new(),
+
+            transient: None,
+
+            num_allocations: 0,
+            num_transient_allocations: 0,
+        })
+    }
+
+    /// Sets aside `descriptors_per_frame` resource descriptors for each of `num_frames` frames in
+    /// flight, for `allocate_transient`. Can only be done once.
+    pub fn reserve_transient(
+        &mut self,
+        num_frames: usize,
+        descriptors_per_frame: usize,
+    ) -> Result<()> {
+        ensure!(
+            self.transient.is_none(),
+            "Transient descriptors are already reserved"
+        );
+        ensure!(
+            num_frames > 0 && descriptors_per_frame > 0,
+            "Transient descriptors need at least one frame and descriptor"
+        );
+
+        // The heap hands out descriptors in order, so the block is contiguous
+        let (first_index, _) = self.resource_descriptor_heap.allocate_handle()?;
+        for _ in 1..num_frames * descriptors_per_frame {
+            self.resource_descriptor_heap.allocate_handle()?;
+        }
+        self.transient = Some(TransientDescriptorRing::new(
+            first_index,
+            descriptors_per_frame,
+            num_frames,
+        ));
+
+        Ok(())
+    }
+
+    /// Hands the transient descriptors of `frame_index` out again, once the GPU is done with the
+    /// frame that last used them
+    pub fn begin_frame(&mut self, frame_index: usize) -> Result<()> {
+        match &mut self.transient {
+            Some(transient) => transient.begin_frame(frame_index),
+            None => Ok(()),
+        }
+    }
+
+    /// A resource descriptor that stays valid until the current frame's section comes round
+    /// again. There is no need to free it, `free` ignores it.
+    pub fn allocate_transient(&mut self) -> Result<DescriptorHandle> {
+        let index = self
+            .transient
+            .as_mut()
+            .context("No transient descriptors are reserved")?
+            .allocate()?;
+        self.num_transient_allocations += 1;
+
+        Ok(DescriptorHandle {
+            tag: DescriptorType::Resource,
+            index,
         })
     }
 
@@ -68,16 +142,19 @@ impl DescriptorManager {
         let index = match descriptor_type {
             DescriptorType::Unset => None.context("Invalid descriptor type"),
             DescriptorType::Resource => get_handle(
-                &mut self.resource_descriptor_heap,
+                || self.resource_descriptor_heap.allocate_handle(),
                 &mut self.resource_free_list,
             ),
-            DescriptorType::DepthStencilView => {
-                get_handle(&mut self.depth_stencil_view_heap, &mut self.dsv_free_list)
-            }
-            DescriptorType::RenderTargetView => {
-                get_handle(&mut self.render_target_view_heap, &mut self.rtv_free_list)
-            }
+            DescriptorType::DepthStencilView => get_handle(
+                || self.depth_stencil_view_heap.allocate_handle(),
+                &mut self.dsv_free_list,
+            ),
+            DescriptorType::RenderTargetView => get_handle(
+                || self.render_target_view_heap.allocate_handle(),
+                &mut self.rtv_free_list,
+            ),
         }?;
+        self.num_allocations += 1;
 
         Ok(DescriptorHandle {
             tag: descriptor_type,
@@ -88,7 +165,16 @@ impl DescriptorManager {
     pub fn free(&mut self, descriptor: DescriptorHandle) {
         match descriptor.tag {
             DescriptorType::Unset => (),
-            DescriptorType::Resource => self.resource_free_list.push(descriptor.index),
+            DescriptorType::Resource => {
+                // Reclaimed with their frame
+                if !self
+                    .transient
+                    .as_ref()
+                    .is_some_and(|transient| transient.contains(descriptor.index))
+                {
+                    self.resource_free_list.push(descriptor.index);
+                }
+            }
             DescriptorType::DepthStencilView => self.dsv_free_list.push(descriptor.index),
             DescriptorType::RenderTargetView => self.rtv_free_list.push(descriptor.index),
         };
@@ -121,12 +207,40 @@ impl DescriptorManager {
             DescriptorType::Resource => self
                 .resource_descriptor_heap
                 .get_gpu_handle(descriptor.index),
-            DescriptorType::DepthStencilView => self
-                .depth_stencil_view_heap
-                .get_gpu_handle(descriptor.index),
-            DescriptorType::RenderTargetView => self
-                .render_target_view_heap
-                .get_gpu_handle(descriptor.index),
+            DescriptorType::DepthStencilView | DescriptorType::RenderTargetView => {
+                bail!("{:?} descriptors are not shader visible", descriptor.tag)
+            }
+        }
+    }
+
+    pub fn stats(&self, descriptor_type: DescriptorType) -> PoolStats {
+        match descriptor_type {
+            DescriptorType::Unset => PoolStats::default(),
+            // The transient block counts as used, `transient_stats` shows how full it is
+            DescriptorType::Resource => self
+                .resource_descriptor_heap
+                .stats(self.resource_free_list.len()),
+            DescriptorType::DepthStencilView => {
+                self.depth_stencil_view_heap.stats(self.dsv_free_list.len())
+            }
+            DescriptorType::RenderTargetView => {
+                self.render_target_view_heap.stats(self.rtv_free_list.len())
+            }
+        }
+    }
+
+    /// Of the current frame's transient descriptors, empty when none are reserved
+    pub fn transient_stats(&self) -> PoolStats {
+        self.transient
+            .as_ref()
+            .map_or_else(PoolStats::default, TransientDescriptorRing::stats)
+    }
+
+    pub fn allocation_counts(&self) -> AllocationCounts {
+        AllocationCounts {
+            descriptors: self.num_allocations,
+            transient_allocations: self.num_transient_allocations,
+            ..Default::default()
         }
     }
 
@@ -134,8 +248,10 @@ impl DescriptorManager {
         match descriptor_type {
             DescriptorType::Unset => None.context("Invalid descriptor type"),
             DescriptorType::Resource => Ok(self.resource_descriptor_heap.heap.clone()),
-            DescriptorType::DepthStencilView => Ok(self.depth_stencil_view_heap.heap.clone()),
-            DescriptorType::RenderTargetView => Ok(self.render_target_view_heap.heap.clone()),
+            DescriptorType::DepthStencilView | DescriptorType::RenderTargetView => bail!(
+                "{:?} descriptors are spread over several heaps that are never bound",
+                descriptor_type
+            ),
         }
     }
 }
@@ -0,0 +1,104 @@
+use std::ffi::c_void;
+
+use anyhow::Result;
+use windows::Win32::Graphics::Direct3D12::*;
+
+use crate::{highest_root_signature_version, ShaderModel, VariableRateShadingSupport};
+
+/// What the device supports, queried once when it is created so passes can branch on it without
+/// calling `CheckFeatureSupport` themselves. Options newer than the runtime are left at their
+/// defaults, which report everything in them as unsupported.
+#[derive(Debug, Clone, Copy)]
+pub struct DeviceCapabilities {
+    pub shader_model: ShaderModel,
+    pub root_signature_version: D3D_ROOT_SIGNATURE_VERSION,
+    pub options: D3D12_FEATURE_DATA_D3D12_OPTIONS,
+    pub options1: D3D12_FEATURE_DATA_D3D12_OPTIONS1,
+    pub options2: D3D12_FEATURE_DATA_D3D12_OPTIONS2,
+    pub options3: D3D12_FEATURE_DATA_D3D12_OPTIONS3,
+    pub options4: D3D12_FEATURE_DATA_D3D12_OPTIONS4,
+    pub options5: D3D12_FEATURE_DATA_D3D12_OPTIONS5,
+    pub options6: D3D12_FEATURE_DATA_D3D12_OPTIONS6,
+    pub options7: D3D12_FEATURE_DATA_D3D12_OPTIONS7,
+    pub options8: D3D12_FEATURE_DATA_D3D12_OPTIONS8,
+    pub options9: D3D12_FEATURE_DATA_D3D12_OPTIONS9,
+    pub options10: D3D12_FEATURE_DATA_D3D12_OPTIONS10,
+    pub options11: D3D12_FEATURE_DATA_D3D12_OPTIONS11,
+    pub variable_rate_shading: VariableRateShadingSupport,
+}
+
+impl DeviceCapabilities {
+    pub fn query(device: &ID3D12Device4) -> Result<Self> {
+        let options6 = query_feature(device, D3D12_FEATURE_D3D12_OPTIONS6);
+
+        Ok(Self {
+            shader_model: ShaderModel::query(device)?,
+            root_signature_version: highest_root_signature_version(device),
+            options: query_feature(device, D3D12_FEATURE_D3D12_OPTIONS),
+            options1: query_feature(device, D3D12_FEATURE_D3D12_OPTIONS1),
+            options2: query_feature(device, D3D12_FEATURE_D3D12_OPTIONS2),
+            options3: query_feature(device, D3D12_FEATURE_D3D12_OPTIONS3),
+            options4: query_feature(device, D3D12_FEATURE_D3D12_OPTIONS4),
+            options5: query_feature(device, D3D12_FEATURE_D3D12_OPTIONS5),
+            options6,
+            options7: query_feature(device, D3D12_FEATURE_D3D12_OPTIONS7),
+            options8: query_feature(device, D3D12_FEATURE_D3D12_OPTIONS8),
+            options9: query_feature(device, D3D12_FEATURE_D3D12_OPTIONS9),
+            options10: query_feature(device, D3D12_FEATURE_D3D12_OPTIONS10),
+            options11: query_feature(device, D3D12_FEATURE_D3D12_OPTIONS11),
+            variable_rate_shading: VariableRateShadingSupport::from_options(&options6),
+        })
+    }
+
+    pub fn resource_binding_tier(&self) -> D3D12_RESOURCE_BINDING_TIER {
+        self.options.ResourceBindingTier
+    }
+
+    /// Tier 2 places buffers, textures and render targets in the same heap
+    pub fn resource_heap_tier(&self) -> D3D12_RESOURCE_HEAP_TIER {
+        self.options.ResourceHeapTier
+    }
+
+    pub fn raytracing_tier(&self) -> D3D12_RAYTRACING_TIER {
+        self.options5.RaytracingTier
+    }
+
+    pub fn mesh_shader_tier(&self) -> D3D12_MESH_SHADER_TIER {
+        self.options7.MeshShaderTier
+    }
+
+    pub fn sampler_feedback_tier(&self) -> D3D12_SAMPLER_FEEDBACK_TIER {
+        self.options7.SamplerFeedbackTier
+    }
+
+    /// Mesh and amplification shaders, which need mesh shader tier 1 and shader model 6.5
+    pub fn mesh_shaders(&self) -> bool {
+        self.mesh_shader_tier().0 >= D3D12_MESH_SHADER_TIER_1.0
+            && self.shader_model.supports_mesh_shaders()
+    }
+
+    /// `ResourceDescriptorHeap` in shaders, which needs shader model 6.6 and resource binding
+    /// tier 3. Without it resources have to be bound through descriptor tables.
+    pub fn dynamic_resources(&self) -> bool {
+        self.shader_model.supports_dynamic_resources()
+            && self.resource_binding_tier().0 >= D3D12_RESOURCE_BINDING_TIER_3.0
+    }
+
+    pub fn depth_bounds(&self) -> bool {
+        self.options2.DepthBoundsTestSupported.as_bool()
+    }
+}
+
+fn query_feature<T: Default>(device: &ID3D12Device4, feature: D3D12_FEATURE) -> T {
+    let mut data = T::default();
+    // Fails for features the runtime doesn't know, which leaves them unsupported
+    let _ = unsafe {
+        device.CheckFeatureSupport(
+            feature,
+            std::ptr::addr_of_mut!(data) as *mut c_void,
+            std::mem::size_of::<T>() as u32,
+        )
+    };
+
+    data
+}
@@ -0,0 +1,246 @@
+use std::fmt;
+
+use anyhow::Result;
+use windows::{
+    core::Interface,
+    Win32::Graphics::{
+        Direct3D12::*,
+        Dxgi::{IDXGIAdapter1, IDXGIDevice, DXGI_ADAPTER_FLAG, DXGI_ADAPTER_FLAG_SOFTWARE},
+    },
+};
+
+use crate::{format_bytes, DeviceCapabilities, Stats};
+
+/// The user mode driver version, as in 31.0.15.3179
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DriverVersion(pub [u16; 4]);
+
+impl DriverVersion {
+    /// From the packed version `CheckInterfaceSupport` returns, most significant part first
+    pub fn from_packed(version: i64) -> Self {
+        let version = version as u64;
+        Self([48, 32, 16, 0].map(|shift| (version >> shift) as u16))
+    }
+}
+
+impl fmt::Display for DriverVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [product, version, sub_version, build] = self.0;
+        write!(f, "{}.{}.{}.{}", product, version, sub_version, build)
+    }
+}
+
+/// What DXGI knows about an adapter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdapterInfo {
+    pub description: String,
+    pub vendor_id: u32,
+    pub device_id: u32,
+    pub sub_sys_id: u32,
+    pub revision: u32,
+    pub dedicated_video_memory: usize,
+    pub dedicated_system_memory: usize,
+    pub shared_system_memory: usize,
+    /// None when the driver doesn't report it
+    pub driver_version: Option<DriverVersion>,
+    pub software: bool,
+}
+
+impl AdapterInfo {
+    pub fn query(adapter: &IDXGIAdapter1) -> Result<Self> {
+        let desc = unsafe { adapter.GetDesc1()? };
+        let description_length = desc
+            .Description
+            .iter()
+            .position(|&c| c == 0)
+            .unwrap_or(desc.Description.len());
+        let driver_version = unsafe { adapter.CheckInterfaceSupport(&IDXGIDevice::IID) }
+            .ok()
+            .map(DriverVersion::from_packed);
+
+        Ok(Self {
+            description: String::from_utf16_lossy(&desc.Description[..description_length]),
+            vendor_id: desc.VendorId,
+            device_id: desc.DeviceId,
+            sub_sys_id: desc.SubSysId,
+            revision: desc.Revision,
+            dedicated_video_memory: desc.DedicatedVideoMemory,
+            dedicated_system_memory: desc.DedicatedSystemMemory,
+            shared_system_memory: desc.SharedSystemMemory,
+            driver_version,
+            software: (DXGI_ADAPTER_FLAG(desc.Flags) & DXGI_ADAPTER_FLAG_SOFTWARE)
+                == DXGI_ADAPTER_FLAG_SOFTWARE,
+        })
+    }
+}
+
+impl fmt::Display for AdapterInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Adapter:                {}{}",
+            self.description,
+            if self.software { " (software)" } else { "" }
+        )?;
+        writeln!(
+            f,
+            "Vendor / device:        {:04x} / {:04x}, subsystem {:08x}, revision {}",
+            self.vendor_id, self.device_id, self.sub_sys_id, self.revision
+        )?;
+        match self.driver_version {
+            Some(version) => writeln!(f, "Driver:                 {}", version)?,
+            None => writeln!(f, "Driver:                 unknown")?,
+        }
+        writeln!(
+            f,
+            "Dedicated video memory: {}",
+            format_bytes(self.dedicated_video_memory)
+        )?;
+        writeln!(
+            f,
+            "Reserved system memory: {}",
+            format_bytes(self.dedicated_system_memory)
+        )?;
+        writeln!(
+            f,
+            "Shared system memory:   {}",
+            format_bytes(self.shared_system_memory)
+        )
+    }
+}
+
+/// Everything worth attaching to a bug report about the GPU the renderer runs on: the adapter,
+/// what the device supports and how large the heaps and descriptor pools were made
+#[derive(Debug, Clone)]
+pub struct DiagnosticsReport {
+    pub adapter: AdapterInfo,
+    pub capabilities: DeviceCapabilities,
+    pub stats: Stats,
+}
+
+impl fmt::Display for DiagnosticsReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let capabilities = &self.capabilities;
+        let root_signature_version = match capabilities.root_signature_version {
+            D3D_ROOT_SIGNATURE_VERSION_1_0 => "1.0",
+            D3D_ROOT_SIGNATURE_VERSION_1_1 => "1.1",
+            _ => "unknown",
+        };
+        let variable_rate_shading = &capabilities.variable_rate_shading;
+
+        write!(f, "{}", self.adapter)?;
+        writeln!(f)?;
+        writeln!(f, "Shader model:           {}", capabilities.shader_model)?;
+        writeln!(f, "Root signature:         {}", root_signature_version)?;
+        writeln!(
+            f,
+            "Resource binding tier:  {}",
+            capabilities.resource_binding_tier().0
+        )?;
+        writeln!(
+            f,
+            "Resource heap tier:     {}",
+            capabilities.resource_heap_tier().0
+        )?;
+        writeln!(
+            f,
+            "Raytracing tier:        {}",
+            tier(
+                capabilities.raytracing_tier().0,
+                D3D12_RAYTRACING_TIER_1_0.0
+            )
+        )?;
+        writeln!(
+            f,
+            "Mesh shader tier:       {}",
+            tier(
+                capabilities.mesh_shader_tier().0,
+                D3D12_MESH_SHADER_TIER_1.0
+            )
+        )?;
+        writeln!(
+            f,
+            "Sampler feedback tier:  {}",
+            tier(
+                capabilities.sampler_feedback_tier().0,
+                D3D12_SAMPLER_FEEDBACK_TIER_1_0.0
+            )
+        )?;
+        writeln!(
+            f,
+            "Variable rate shading:  tier {}, {} additional rates, {} pixel tiles",
+            variable_rate_shading.tier.0,
+            if variable_rate_shading.additional_shading_rates {
+                "with"
+            } else {
+                "without"
+            },
+            variable_rate_shading.tile_size
+        )?;
+        writeln!(
+            f,
+            "Dynamic resources:      {}",
+            capabilities.dynamic_resources()
+        )?;
+        writeln!(f, "Depth bounds:           {}", capabilities.depth_bounds())?;
+        writeln!(f)?;
+        write!(f, "{}", self.stats)
+    }
+}
+
+// Newer tiers count up in tenths from `one`, the value of tier 1.0, like raytracing 1.1 or
+// sampler feedback 0.9
+fn tier(value: i32, one: i32) -> String {
+    if value == 0 {
+        "unsupported".to_string()
+    } else {
+        format!("{}.{}", value / one, value % one * 10 / one)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unpacks_driver_version() {
+        let packed = (31i64 << 48) | (15 << 16) | 3179;
+        let version = DriverVersion::from_packed(packed);
+
+        assert_eq!(version, DriverVersion([31, 0, 15, 3179]));
+        assert_eq!(version.to_string(), "31.0.15.3179");
+    }
+
+    #[test]
+    fn formats_tiers() {
+        assert_eq!(tier(0, 10), "unsupported");
+        assert_eq!(tier(10, 10), "1.0");
+        assert_eq!(tier(11, 10), "1.1");
+        assert_eq!(tier(90, 100), "0.9");
+        assert_eq!(tier(100, 100), "1.0");
+    }
+
+    #[test]
+    fn describes_adapter() {
+        let adapter = AdapterInfo {
+            description: "Microsoft Basic Render Driver".to_string(),
+            vendor_id: 0x1414,
+            device_id: 0x8c,
+            sub_sys_id: 0,
+            revision: 0,
+            dedicated_video_memory: 0,
+            dedicated_system_memory: 0,
+            shared_system_memory: 8 << 30,
+            driver_version: None,
+            software: true,
+        };
+
+        let report = adapter.to_string();
+        assert!(
+            report.starts_with("Adapter:                Microsoft Basic Render Driver (software)")
+        );
+        assert!(report.contains("1414 / 008c"));
+        assert!(report.contains("Driver:                 unknown"));
+        assert!(report.contains("Shared system memory:   8.0 GiB"));
+    }
+}
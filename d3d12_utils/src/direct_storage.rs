@@ -0,0 +1,273 @@
+//! Minimal bindings to the DirectStorage runtime (dstorage.dll), loaded at runtime so the
+//! renderer still starts on machines without it
+
+use std::{ffi::c_void, path::Path};
+
+use anyhow::{ensure, Context, Result};
+use windows::{
+    core::{Interface, GUID, HRESULT, PCWSTR},
+    s, w,
+    Win32::{
+        Graphics::Direct3D12::*,
+        System::LibraryLoader::{GetProcAddress, LoadLibraryW},
+    },
+};
+
+use crate::{AssetBlob, BlobCompression, CommandQueue};
+
+const IID_IDSTORAGE_FACTORY: GUID = GUID::from_u128(0x6924ea0c_c3cd_4826_b10a_f64f4ed927c1);
+const IID_IDSTORAGE_QUEUE: GUID = GUID::from_u128(0xcfdbd83f_9e06_4fda_8ea5_69042137f49b);
+const IID_IDSTORAGE_FILE: GUID = GUID::from_u128(0x5de95e7b_955a_4868_a73c_243b29f4b8da);
+
+const DSTORAGE_REQUEST_SOURCE_FILE: u32 = 0;
+const DSTORAGE_REQUEST_DESTINATION_BUFFER: u64 = 1;
+const DSTORAGE_COMPRESSION_FORMAT_NONE: u8 = 0;
+const DSTORAGE_COMPRESSION_FORMAT_GDEFLATE: u8 = 1;
+const DSTORAGE_PRIORITY_NORMAL: i8 = 0;
+const DSTORAGE_MAX_QUEUE_CAPACITY: u16 = 0x2000;
+
+type DStorageGetFactory = unsafe extern "system" fn(*const GUID, *mut *mut c_void) -> HRESULT;
+
+#[repr(C)]
+struct IUnknownVtbl {
+    query_interface: usize,
+    add_ref: usize,
+    release: unsafe extern "system" fn(*mut c_void) -> u32,
+}
+
+#[repr(C)]
+struct IDStorageFactoryVtbl {
+    base: IUnknownVtbl,
+    create_queue: unsafe extern "system" fn(
+        *mut c_void,
+        *const DStorageQueueDesc,
+        *const GUID,
+        *mut *mut c_void,
+    ) -> HRESULT,
+    open_file:
+        unsafe extern "system" fn(*mut c_void, PCWSTR, *const GUID, *mut *mut c_void) -> HRESULT,
+}
+
+#[repr(C)]
+struct IDStorageQueueVtbl {
+    base: IUnknownVtbl,
+    enqueue_request: unsafe extern "system" fn(*mut c_void, *const DStorageRequest),
+    enqueue_status: usize,
+    enqueue_signal: unsafe extern "system" fn(*mut c_void, *mut c_void, u64),
+    submit: unsafe extern "system" fn(*mut c_void),
+}
+
+#[repr(C)]
+struct DStorageQueueDesc {
+    source_type: u32,
+    capacity: u16,
+    priority: i8,
+    name: *const u8,
+    device: *mut c_void,
+}
+
+#[repr(C)]
+struct DStorageRequestOptions {
+    compression_format: u8,
+    reserved: [u8; 7],
+    // SourceType : 1, DestinationType : 7, Reserved : 48
+    types: u64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct DStorageSourceFile {
+    source: *mut c_void,
+    offset: u64,
+    size: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct DStorageDestinationBuffer {
+    resource: *mut c_void,
+    offset: u64,
+    size: u32,
+}
+
+#[repr(C)]
+union DStorageDestination {
+    buffer: DStorageDestinationBuffer,
+    // Texture regions and tiles are the largest members
+    _size: [u64; 5],
+}
+
+#[repr(C)]
+struct DStorageRequest {
+    options: DStorageRequestOptions,
+    source: DStorageSourceFile,
+    destination: DStorageDestination,
+    uncompressed_size: u32,
+    cancellation_tag: u64,
+    name: *const u8,
+}
+
+/// Owns one reference to a DirectStorage COM object
+#[derive(Debug)]
+struct ComObject(*mut c_void);
+
+impl ComObject {
+    unsafe fn vtable<T>(&self) -> &T {
+        &**(self.0 as *const *const T)
+    }
+}
+
+impl Drop for ComObject {
+    fn drop(&mut self) {
+        unsafe {
+            (self.vtable::<IUnknownVtbl>().release)(self.0);
+        }
+    }
+}
+
+/// A DirectStorage queue reading files straight into D3D12 buffers, GDeflate blobs are
+/// decompressed on the GPU when the driver supports it
+#[derive(Debug)]
+pub struct DirectStorage {
+    factory: ComObject,
+    queue: ComObject,
+    // Files have to stay open until the requests reading them are done, that is until the fence
+    // of their load reaches the value
+    open_files: Vec<(u64, ComObject)>,
+}
+
+impl DirectStorage {
+    pub fn new(device: &ID3D12Device4) -> Result<Self> {
+        let get_factory: DStorageGetFactory = unsafe {
+            let module = LoadLibraryW(w!("dstorage.dll"))?;
+            let proc = GetProcAddress(module, s!("DStorageGetFactory"))
+                .context("dstorage.dll has no DStorageGetFactory")?;
+            std::mem::transmute(proc)
+        };
+
+        let factory = unsafe {
+            let mut factory = std::ptr::null_mut();
+            get_factory(&IID_IDSTORAGE_FACTORY, &mut factory).ok()?;
+            ComObject(factory)
+        };
+
+        let queue = unsafe {
+            let desc = DStorageQueueDesc {
+                source_type: DSTORAGE_REQUEST_SOURCE_FILE,
+                capacity: DSTORAGE_MAX_QUEUE_CAPACITY,
+                priority: DSTORAGE_PRIORITY_NORMAL,
+                name: s!("Asset Loader Queue").0,
+                device: device.as_raw(),
+            };
+            let mut queue = std::ptr::null_mut();
+            (factory.vtable::<IDStorageFactoryVtbl>().create_queue)(
+                factory.0,
+                &desc,
+                &IID_IDSTORAGE_QUEUE,
+                &mut queue,
+            )
+            .ok()?;
+            ComObject(queue)
+        };
+
+        Ok(Self {
+            factory,
+            queue,
+            open_files: Vec::new(),
+        })
+    }
+
+    /// Queues all blobs and signals `fence_value` on `fence` once they have arrived.
+    /// `dependent_queue` waits for it. Every load has to signal a higher value on the same fence.
+    pub fn load(
+        &mut self,
+        blobs: &[AssetBlob],
+        fence: &ID3D12Fence,
+        fence_value: u64,
+        dependent_queue: Option<&CommandQueue>,
+    ) -> Result<()> {
+        let completed_value = unsafe { fence.GetCompletedValue() };
+        self.open_files
+            .retain(|(value, _)| *value > completed_value);
+
+        for blob in blobs {
+            let size = u32::try_from(blob.size).context("Blob too large for DirectStorage")?;
+            let destination = &blob.destination;
+            ensure!(
+                blob.uncompressed_size() <= destination.size,
+                "Blob does not fit its destination"
+            );
+
+            let file = self.open_file(&blob.path)?;
+            let (compression_format, uncompressed_size) = match blob.compression {
+                BlobCompression::None => (DSTORAGE_COMPRESSION_FORMAT_NONE, size),
+                BlobCompression::GDeflate { uncompressed_size } => (
+                    DSTORAGE_COMPRESSION_FORMAT_GDEFLATE,
+                    u32::try_from(uncompressed_size).context("Blob too large for DirectStorage")?,
+                ),
+            };
+
+            let request = DStorageRequest {
+                options: DStorageRequestOptions {
+                    compression_format,
+                    reserved: [0; 7],
+                    types: DSTORAGE_REQUEST_SOURCE_FILE as u64
+                        | (DSTORAGE_REQUEST_DESTINATION_BUFFER << 1),
+                },
+                source: DStorageSourceFile {
+                    source: file.0,
+                    offset: blob.file_offset,
+                    size,
+                },
+                destination: DStorageDestination {
+                    buffer: DStorageDestinationBuffer {
+                        resource: destination.resource.device_resource.as_raw(),
+                        offset: destination.offset as u64,
+                        size: uncompressed_size,
+                    },
+                },
+                uncompressed_size,
+                cancellation_tag: 0,
+                name: std::ptr::null(),
+            };
+
+            unsafe {
+                (self.queue.vtable::<IDStorageQueueVtbl>().enqueue_request)(self.queue.0, &request);
+            }
+            self.open_files.push((fence_value, file));
+        }
+
+        unsafe {
+            let queue = self.queue.vtable::<IDStorageQueueVtbl>();
+            (queue.enqueue_signal)(self.queue.0, fence.as_raw(), fence_value);
+            (queue.submit)(self.queue.0);
+        }
+
+        if let Some(dependent_queue) = dependent_queue {
+            unsafe { dependent_queue.queue.Wait(fence, fence_value) }?;
+        }
+
+        Ok(())
+    }
+
+    fn open_file(&self, path: &Path) -> Result<ComObject> {
+        let path: Vec<u16> = path
+            .to_str()
+            .context("Asset path is not valid unicode")?
+            .encode_utf16()
+            .chain(Some(0))
+            .collect();
+        let mut file = std::ptr::null_mut();
+        unsafe {
+            (self.factory.vtable::<IDStorageFactoryVtbl>().open_file)(
+                self.factory.0,
+                PCWSTR(path.as_ptr()),
+                &IID_IDSTORAGE_FILE,
+                &mut file,
+            )
+            .ok()?;
+        }
+
+        Ok(ComObject(file))
+    }
+}
@@ -0,0 +1,340 @@
+use std::{ffi::CStr, fmt};
+
+use anyhow::Result;
+use windows::{
+    core::{Interface, PCWSTR},
+    Win32::Graphics::Direct3D12::*,
+};
+
+// Operations shown before the one that didn't finish
+const HISTORY_CONTEXT: usize = 4;
+
+/// Turns on DRED's automatic breadcrumbs and page fault tracking for devices created after this.
+/// Costs some CPU time on every command, so meant for debug builds.
+pub fn enable_dred() -> Result<()> {
+    let mut settings: Option<ID3D12DeviceRemovedExtendedDataSettings> = None;
+    unsafe {
+        D3D12GetDebugInterface(&mut settings)?;
+        if let Some(settings) = settings {
+            settings.SetAutoBreadcrumbsEnablement(D3D12_DRED_ENABLEMENT_FORCED_ON);
+            settings.SetPageFaultEnablement(D3D12_DRED_ENABLEMENT_FORCED_ON);
+        }
+    }
+
+    Ok(())
+}
+
+/// The commands DRED saw a command list record, and how many of them the GPU finished
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandListHistory {
+    pub command_list: String,
+    pub queue: String,
+    pub completed: u32,
+    pub operations: Vec<D3D12_AUTO_BREADCRUMB_OP>,
+}
+
+impl CommandListHistory {
+    /// Every operation was finished, so this command list is not what hung
+    pub fn is_complete(&self) -> bool {
+        self.completed as usize >= self.operations.len()
+    }
+
+    /// The first operation that did not finish
+    pub fn failed_operation(&self) -> Option<D3D12_AUTO_BREADCRUMB_OP> {
+        self.operations.get(self.completed as usize).copied()
+    }
+}
+
+impl fmt::Display for CommandListHistory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} on {}, {} of {} operations finished",
+            self.command_list,
+            self.queue,
+            self.completed,
+            self.operations.len()
+        )?;
+
+        let completed = self.completed as usize;
+        let first = completed.saturating_sub(HISTORY_CONTEXT);
+        for (index, operation) in self.operations.iter().enumerate().skip(first) {
+            let marker = if index == completed { ">" } else { " " };
+            writeln!(f, "  {} {:5} {}", marker, index, operation_name(*operation))?;
+            if index > completed {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DredAllocation {
+    pub name: String,
+    pub allocation_type: D3D12_DRED_ALLOCATION_TYPE,
+}
+
+/// The GPU virtual address that faulted, with the objects that used to or still do cover it
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageFault {
+    pub address: u64,
+    pub existing: Vec<DredAllocation>,
+    pub recently_freed: Vec<DredAllocation>,
+}
+
+/// What DRED recorded before the device was removed
+#[derive(Debug, Clone, PartialEq)]
+pub struct DredReport {
+    /// Only the command lists the GPU did not get through
+    pub unfinished: Vec<CommandListHistory>,
+    pub page_fault: Option<PageFault>,
+}
+
+impl DredReport {
+    /// Fails if DRED was not enabled with `enable_dred` before the device was created
+    pub fn query(device: &ID3D12Device4) -> Result<Self> {
+        let dred: ID3D12DeviceRemovedExtendedData = device.cast()?;
+
+        let breadcrumbs = unsafe { dred.GetAutoBreadcrumbsOutput() }?;
+        let mut unfinished = vec![];
+        let mut node = breadcrumbs.pHeadAutoBreadcrumbNode;
+        while let Some(current) = unsafe { node.as_ref() } {
+            let operations = if current.pCommandHistory.is_null() {
+                vec![]
+            } else {
+                unsafe {
+                    std::slice::from_raw_parts(
+                        current.pCommandHistory,
+                        current.BreadcrumbCount as usize,
+                    )
+                }
+                .to_vec()
+            };
+            let history = CommandListHistory {
+                command_list: debug_name(
+                    current.pCommandListDebugNameW,
+                    current.pCommandListDebugNameA,
+                ),
+                queue: debug_name(
+                    current.pCommandQueueDebugNameW,
+                    current.pCommandQueueDebugNameA,
+                ),
+                completed: unsafe { current.pLastBreadcrumbValue.as_ref() }
+                    .copied()
+                    .unwrap_or(0),
+                operations,
+            };
+            if !history.is_complete() {
+                unfinished.push(history);
+            }
+            node = current.pNext;
+        }
+
+        let page_fault = unsafe { dred.GetPageFaultAllocationOutput() }?;
+        let page_fault = (page_fault.PageFaultVA != 0).then(|| PageFault {
+            address: page_fault.PageFaultVA,
+            existing: allocations(page_fault.pHeadExistingAllocationNode),
+            recently_freed: allocations(page_fault.pHeadRecentFreedAllocationNode),
+        });
+
+        Ok(Self {
+            unfinished,
+            page_fault,
+        })
+    }
+}
+
+impl fmt::Display for DredReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.unfinished.is_empty() {
+            writeln!(f, "Every command list finished")?;
+        }
+        for history in &self.unfinished {
+            write!(f, "{}", history)?;
+        }
+
+        if let Some(page_fault) = &self.page_fault {
+            writeln!(f, "Page fault at {:#018x}", page_fault.address)?;
+            for (label, allocations) in [
+                ("Existing", &page_fault.existing),
+                ("Recently freed", &page_fault.recently_freed),
+            ] {
+                for allocation in allocations {
+                    writeln!(
+                        f,
+                        "  {}: {} ({})",
+                        label,
+                        allocation.name,
+                        allocation_type_name(allocation.allocation_type)
+                    )?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returned when the device was removed, with everything that was recorded about why
+#[derive(Debug)]
+pub struct DeviceRemovedError {
+    pub reason: windows::core::Error,
+    /// None if DRED was not enabled
+    pub extended_data: Option<DredReport>,
+    /// Anything else the application recorded, e.g. its own progress markers
+    pub details: String,
+}
+
+impl DeviceRemovedError {
+    /// None while the device is fine
+    pub fn check(device: &ID3D12Device4, details: impl FnOnce() -> String) -> Option<Self> {
+        let reason = unsafe { device.GetDeviceRemovedReason() }.err()?;
+
+        Some(Self {
+            reason,
+            extended_data: DredReport::query(device).ok(),
+            details: details(),
+        })
+    }
+}
+
+impl fmt::Display for DeviceRemovedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Device removed: {}", self.reason)?;
+        match &self.extended_data {
+            Some(report) => write!(f, "{}", report)?,
+            None => writeln!(f, "No DRED data, it is only enabled in debug builds")?,
+        }
+
+        write!(f, "{}", self.details)
+    }
+}
+
+impl std::error::Error for DeviceRemovedError {}
+
+fn debug_name(wide: PCWSTR, narrow: *const u8) -> String {
+    if !wide.is_null() {
+        if let Ok(name) = unsafe { wide.to_string() } {
+            return name;
+        }
+    }
+    if !narrow.is_null() {
+        return unsafe { CStr::from_ptr(narrow as _) }
+            .to_string_lossy()
+            .into_owned();
+    }
+
+    "unnamed".to_string()
+}
+
+fn allocations(mut node: *const D3D12_DRED_ALLOCATION_NODE) -> Vec<DredAllocation> {
+    let mut allocations = vec![];
+    while let Some(current) = unsafe { node.as_ref() } {
+        allocations.push(DredAllocation {
+            name: debug_name(current.ObjectNameW, current.ObjectNameA),
+            allocation_type: current.AllocationType,
+        });
+        node = current.pNext;
+    }
+
+    allocations
+}
+
+fn operation_name(operation: D3D12_AUTO_BREADCRUMB_OP) -> String {
+    let name = match operation {
+        D3D12_AUTO_BREADCRUMB_OP_SETMARKER => "SetMarker",
+        D3D12_AUTO_BREADCRUMB_OP_BEGINEVENT => "BeginEvent",
+        D3D12_AUTO_BREADCRUMB_OP_ENDEVENT => "EndEvent",
+        D3D12_AUTO_BREADCRUMB_OP_DRAWINSTANCED => "DrawInstanced",
+        D3D12_AUTO_BREADCRUMB_OP_DRAWINDEXEDINSTANCED => "DrawIndexedInstanced",
+        D3D12_AUTO_BREADCRUMB_OP_EXECUTEINDIRECT => "ExecuteIndirect",
+        D3D12_AUTO_BREADCRUMB_OP_DISPATCH => "Dispatch",
+        D3D12_AUTO_BREADCRUMB_OP_DISPATCHMESH => "DispatchMesh",
+        D3D12_AUTO_BREADCRUMB_OP_COPYBUFFERREGION => "CopyBufferRegion",
+        D3D12_AUTO_BREADCRUMB_OP_COPYTEXTUREREGION => "CopyTextureRegion",
+        D3D12_AUTO_BREADCRUMB_OP_COPYRESOURCE => "CopyResource",
+        D3D12_AUTO_BREADCRUMB_OP_COPYTILES => "CopyTiles",
+        D3D12_AUTO_BREADCRUMB_OP_RESOLVESUBRESOURCE => "ResolveSubresource",
+        D3D12_AUTO_BREADCRUMB_OP_CLEARRENDERTARGETVIEW => "ClearRenderTargetView",
+        D3D12_AUTO_BREADCRUMB_OP_CLEARUNORDEREDACCESSVIEW => "ClearUnorderedAccessView",
+        D3D12_AUTO_BREADCRUMB_OP_CLEARDEPTHSTENCILVIEW => "ClearDepthStencilView",
+        D3D12_AUTO_BREADCRUMB_OP_RESOURCEBARRIER => "ResourceBarrier",
+        D3D12_AUTO_BREADCRUMB_OP_EXECUTEBUNDLE => "ExecuteBundle",
+        D3D12_AUTO_BREADCRUMB_OP_PRESENT => "Present",
+        D3D12_AUTO_BREADCRUMB_OP_RESOLVEQUERYDATA => "ResolveQueryData",
+        D3D12_AUTO_BREADCRUMB_OP_BEGINSUBMISSION => "BeginSubmission",
+        D3D12_AUTO_BREADCRUMB_OP_ENDSUBMISSION => "EndSubmission",
+        D3D12_AUTO_BREADCRUMB_OP_WRITEBUFFERIMMEDIATE => "WriteBufferImmediate",
+        D3D12_AUTO_BREADCRUMB_OP_SETPIPELINESTATE1 => "SetPipelineState1",
+        _ => return format!("Operation {}", operation.0),
+    };
+
+    name.to_string()
+}
+
+fn allocation_type_name(allocation_type: D3D12_DRED_ALLOCATION_TYPE) -> String {
+    let name = match allocation_type {
+        D3D12_DRED_ALLOCATION_TYPE_RESOURCE => "resource",
+        D3D12_DRED_ALLOCATION_TYPE_HEAP => "heap",
+        D3D12_DRED_ALLOCATION_TYPE_DESCRIPTOR_HEAP => "descriptor heap",
+        D3D12_DRED_ALLOCATION_TYPE_QUERY_HEAP => "query heap",
+        D3D12_DRED_ALLOCATION_TYPE_PIPELINE_STATE => "pipeline state",
+        D3D12_DRED_ALLOCATION_TYPE_COMMAND_LIST => "command list",
+        D3D12_DRED_ALLOCATION_TYPE_COMMAND_ALLOCATOR => "command allocator",
+        D3D12_DRED_ALLOCATION_TYPE_COMMAND_QUEUE => "command queue",
+        D3D12_DRED_ALLOCATION_TYPE_COMMAND_SIGNATURE => "command signature",
+        D3D12_DRED_ALLOCATION_TYPE_FENCE => "fence",
+        _ => return format!("type {}", allocation_type.0),
+    };
+
+    name.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(completed: u32) -> CommandListHistory {
+        CommandListHistory {
+            command_list: "Frame".to_string(),
+            queue: "Graphics".to_string(),
+            completed,
+            operations: vec![
+                D3D12_AUTO_BREADCRUMB_OP_RESOURCEBARRIER,
+                D3D12_AUTO_BREADCRUMB_OP_CLEARRENDERTARGETVIEW,
+                D3D12_AUTO_BREADCRUMB_OP_DRAWINSTANCED,
+                D3D12_AUTO_BREADCRUMB_OP_DISPATCH,
+                D3D12_AUTO_BREADCRUMB_OP_DRAWINDEXEDINSTANCED,
+                D3D12_AUTO_BREADCRUMB_OP_DRAWINSTANCED,
+                D3D12_AUTO_BREADCRUMB_OP_RESOURCEBARRIER,
+            ],
+        }
+    }
+
+    #[test]
+    fn finds_the_operation_that_did_not_finish() {
+        assert_eq!(
+            history(3).failed_operation(),
+            Some(D3D12_AUTO_BREADCRUMB_OP_DISPATCH)
+        );
+        assert!(!history(3).is_complete());
+
+        assert_eq!(history(7).failed_operation(), None);
+        assert!(history(7).is_complete());
+    }
+
+    #[test]
+    fn shows_the_operations_around_the_failure() {
+        let text = history(5).to_string();
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert_eq!(lines[0], "Frame on Graphics, 5 of 7 operations finished");
+        // Four finished ones, the failed one and the one after it
+        assert_eq!(lines.len(), 7);
+        assert!(lines[1].contains("ClearRenderTargetView"));
+        assert!(lines[5].starts_with("  >") && lines[5].contains("DrawInstanced"));
+        assert!(lines[6].contains("ResourceBarrier"));
+    }
+}
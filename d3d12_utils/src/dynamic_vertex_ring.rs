@@ -0,0 +1,213 @@
+use anyhow::{ensure, Context, Result};
+use windows::Win32::Graphics::{Direct3D12::*, Dxgi::Common::DXGI_SAMPLE_DESC};
+
+use crate::Resource;
+
+/// Vertices written into a `DynamicVertexRing`, only valid for the frame they were written in
+#[derive(Debug, Clone, Copy)]
+pub struct DynamicVertices {
+    pub vbv: D3D12_VERTEX_BUFFER_VIEW,
+    /// Index of the first vertex in the whole ring, for structured views over `resource` with the
+    /// vertex size as stride
+    pub first_vertex: u32,
+    pub vertex_count: u32,
+}
+
+/// A persistently mapped upload buffer that vertices generated every frame, e.g. debug lines and
+/// points, are written straight into. Space is handed out front to back and reused once the
+/// fence of the frame that wrote it has been waited on, so unlike `VersionedBuffer` a frame can
+/// use as much of it as the frames still in flight leave over.
+#[derive(Debug)]
+pub struct DynamicVertexRing<const FRAME_COUNT: usize> {
+    buffer: Resource,
+    space: RingSpace,
+    // Where the writes of each frame ended the last time it was recorded
+    frame_marks: [u64; FRAME_COUNT],
+    recording_frame: Option<usize>,
+}
+
+impl<const FRAME_COUNT: usize> DynamicVertexRing<FRAME_COUNT> {
+    pub fn new(device: &ID3D12Device4, size: usize) -> Result<Self> {
+        let buffer = Resource::create_committed(
+            device,
+            &D3D12_HEAP_PROPERTIES {
+                Type: D3D12_HEAP_TYPE_UPLOAD,
+                ..Default::default()
+            },
+            &D3D12_RESOURCE_DESC {
+                Dimension: D3D12_RESOURCE_DIMENSION_BUFFER,
+                Width: size as u64,
+                Height: 1,
+                DepthOrArraySize: 1,
+                MipLevels: 1,
+                SampleDesc: DXGI_SAMPLE_DESC {
+                    Count: 1,
+                    Quality: 0,
+                },
+                Layout: D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
+                ..Default::default()
+            },
+            D3D12_RESOURCE_STATE_GENERIC_READ,
+            None,
+            true,
+        )?;
+
+        Ok(Self {
+            buffer,
+            space: RingSpace::new(size as u64),
+            frame_marks: [0; FRAME_COUNT],
+            recording_frame: None,
+        })
+    }
+
+    /// Call once the fence of `frame_index` has been waited on, what it wrote is reused after
+    pub fn begin_frame(&mut self, frame_index: usize) -> Result<()> {
+        ensure!(
+            frame_index < FRAME_COUNT,
+            "Frame index {} out of range, only {} frames in flight",
+            frame_index,
+            FRAME_COUNT
+        );
+        self.space.free_up_to(self.frame_marks[frame_index]);
+        self.recording_frame = Some(frame_index);
+
+        Ok(())
+    }
+
+    /// Copies `vertices` into the ring, each starting on a multiple of its size so they can also
+    /// be read through a structured view of the whole ring
+    pub fn write_vertices<T: Sized>(&mut self, vertices: &[T]) -> Result<DynamicVertices> {
+        let frame_index = self
+            .recording_frame
+            .context("Writing vertices before begin_frame")?;
+        let stride = std::mem::size_of::<T>() as u64;
+        let size = std::mem::size_of_val(vertices) as u64;
+
+        let offset = self.space.allocate(size, stride).with_context(|| {
+            format!(
+                "{} bytes of vertices don't fit the {} bytes of the ring the frames in flight \
+                left over",
+                size,
+                self.space.available()
+            )
+        })?;
+        self.frame_marks[frame_index] = self.space.mark();
+        self.buffer
+            .create_sub_resource(size as usize, offset as usize)?
+            .copy_from(vertices)?;
+
+        Ok(DynamicVertices {
+            vbv: D3D12_VERTEX_BUFFER_VIEW {
+                BufferLocation: self.buffer.gpu_address() + offset,
+                SizeInBytes: size as u32,
+                StrideInBytes: stride as u32,
+            },
+            first_vertex: (offset / stride.max(1)) as u32,
+            vertex_count: vertices.len() as u32,
+        })
+    }
+
+    pub fn resource(&self) -> &Resource {
+        &self.buffer
+    }
+
+    pub fn size(&self) -> usize {
+        self.space.size as usize
+    }
+}
+
+// Bookkeeping of which bytes of the ring are in use. `allocated` and `freed` count every byte
+// ever handed out and given back, including the ends skipped when wrapping, so their
+// difference is what is in use.
+#[derive(Debug)]
+struct RingSpace {
+    size: u64,
+    head: u64,
+    allocated: u64,
+    freed: u64,
+}
+
+impl RingSpace {
+    fn new(size: u64) -> Self {
+        Self {
+            size,
+            head: 0,
+            allocated: 0,
+            freed: 0,
+        }
+    }
+
+    fn available(&self) -> u64 {
+        self.size - (self.allocated - self.freed)
+    }
+
+    // Offset of `size` bytes starting on a multiple of `alignment`, None when they don't fit
+    fn allocate(&mut self, size: u64, alignment: u64) -> Option<u64> {
+        let alignment = alignment.max(1);
+        let aligned = self.head.next_multiple_of(alignment);
+        // Skips the end when it is too short, it is freed along with this allocation
+        let wrapped = aligned + size > self.size;
+        let start = if wrapped { 0 } else { aligned };
+        if start + size > self.size {
+            return None;
+        }
+
+        let end = start + size;
+        let used = if wrapped {
+            self.size - self.head + end
+        } else {
+            end - self.head
+        };
+        if used > self.available() {
+            return None;
+        }
+
+        self.head = end % self.size;
+        self.allocated += used;
+        Some(start)
+    }
+
+    // Where the allocations so far end, to be freed with `free_up_to`
+    fn mark(&self) -> u64 {
+        self.allocated
+    }
+
+    fn free_up_to(&mut self, mark: u64) {
+        self.freed = self.freed.max(mark);
+        if self.freed == self.allocated {
+            // Nothing in use, so the next allocation doesn't have to skip the end
+            self.head = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_once_earlier_frames_are_freed() {
+        let mut space = RingSpace::new(64);
+
+        assert_eq!(space.allocate(24, 12), Some(0));
+        let first_frame = space.mark();
+        // Aligned past the 24 bytes to a multiple of 16
+        assert_eq!(space.allocate(16, 16), Some(32));
+        assert_eq!(space.available(), 16);
+
+        // 20 bytes don't fit the 16 at the end, and the start is still in use
+        assert_eq!(space.allocate(20, 4), None);
+
+        space.free_up_to(first_frame);
+        assert_eq!(space.allocate(20, 4), Some(0));
+        // The skipped end counts as used until this allocation is freed
+        assert_eq!(space.available(), 4);
+        assert_eq!(space.allocate(4, 4), Some(20));
+        assert_eq!(space.allocate(4, 4), None);
+
+        space.free_up_to(space.mark());
+        assert_eq!(space.available(), 64);
+        assert_eq!(space.allocate(64, 64), Some(0));
+        assert_eq!(space.allocate(1, 1), None);
+    }
+}
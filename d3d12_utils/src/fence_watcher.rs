@@ -0,0 +1,185 @@
+use std::{
+    sync::mpsc::{channel, Receiver, Sender},
+    thread::JoinHandle,
+};
+
+use anyhow::{Context, Result};
+use windows::Win32::{
+    Foundation::{CloseHandle, HANDLE},
+    Graphics::Direct3D12::*,
+    System::{
+        Threading::{CreateEventA, SetEvent, WaitForMultipleObjects, WaitForSingleObject},
+        WindowsProgramming::INFINITE,
+    },
+};
+
+use crate::CommandQueue;
+
+struct Watch {
+    fence: ID3D12Fence,
+    value: u64,
+    callback: Box<dyn FnOnce() + Send>,
+}
+
+/// Runs callbacks on a waiter thread once fences reach a value, so systems that wait on the GPU
+/// don't have to poll `is_fence_complete` every frame
+#[derive(Debug)]
+pub struct FenceWatcher {
+    sender: Option<Sender<Watch>>,
+    wake_event: HANDLE,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl FenceWatcher {
+    pub fn new(device: &ID3D12Device4) -> Result<Self> {
+        let wake_event = unsafe { CreateEventA(std::ptr::null(), false, false, None) }?;
+        let fence_event = unsafe { CreateEventA(std::ptr::null(), false, false, None) }?;
+        let (sender, receiver) = channel();
+
+        let device = device.clone();
+        let thread = std::thread::Builder::new()
+            .name("Fence Watcher".to_string())
+            .spawn(move || watch_fences(device, receiver, wake_event, fence_event))?;
+
+        Ok(Self {
+            sender: Some(sender),
+            wake_event,
+            thread: Some(thread),
+        })
+    }
+
+    /// `callback` runs on the watcher thread
+    pub fn on_completion<F>(&self, fence: &ID3D12Fence, value: u64, callback: F) -> Result<()>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.sender
+            .as_ref()
+            .context("Fence watcher is shut down")?
+            .send(Watch {
+                fence: fence.clone(),
+                value,
+                callback: Box::new(callback),
+            })
+            .ok()
+            .context("Fence watcher thread stopped")?;
+
+        unsafe {
+            SetEvent(self.wake_event);
+        }
+
+        Ok(())
+    }
+
+    pub fn on_queue_completion<F>(
+        &self,
+        queue: &CommandQueue,
+        value: u64,
+        callback: F,
+    ) -> Result<()>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.on_completion(queue.fence(), value, callback)
+    }
+
+    /// The receiver gets `value` once `queue` has reached it
+    pub fn completion_channel(&self, queue: &CommandQueue, value: u64) -> Result<Receiver<u64>> {
+        let (sender, receiver) = channel();
+        self.on_queue_completion(queue, value, move || {
+            // Nobody waiting on the result anymore is fine
+            let _ = sender.send(value);
+        })?;
+
+        Ok(receiver)
+    }
+}
+
+impl Drop for FenceWatcher {
+    fn drop(&mut self) {
+        // Dropping the sender tells the thread to finish
+        self.sender = None;
+        unsafe {
+            SetEvent(self.wake_event);
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        unsafe {
+            CloseHandle(self.wake_event);
+        }
+    }
+}
+
+fn watch_fences(
+    device: ID3D12Device4,
+    receiver: Receiver<Watch>,
+    wake_event: HANDLE,
+    fence_event: HANDLE,
+) {
+    let mut watches: Vec<Watch> = Vec::new();
+    let mut disconnected = false;
+
+    loop {
+        loop {
+            match receiver.try_recv() {
+                Ok(watch) => watches.push(watch),
+                Err(std::sync::mpsc::TryRecvError::Empty) => break,
+                Err(std::sync::mpsc::TryRecvError::Disconnected) => {
+                    disconnected = true;
+                    break;
+                }
+            }
+        }
+
+        let mut index = 0;
+        while index < watches.len() {
+            let watch = &watches[index];
+            if unsafe { watch.fence.GetCompletedValue() } >= watch.value {
+                (watches.swap_remove(index).callback)();
+            } else {
+                index += 1;
+            }
+        }
+
+        // Outstanding callbacks are dropped without running
+        if disconnected {
+            break;
+        }
+
+        if watches.is_empty() {
+            unsafe {
+                WaitForSingleObject(wake_event, INFINITE);
+            }
+            continue;
+        }
+
+        let fences: Vec<Option<ID3D12Fence>> = watches
+            .iter()
+            .map(|watch| Some(watch.fence.clone()))
+            .collect();
+        let values: Vec<u64> = watches.iter().map(|watch| watch.value).collect();
+        let waiting = unsafe {
+            device.SetEventOnMultipleFenceCompletion(
+                fences.as_ptr(),
+                values.as_ptr(),
+                fences.len() as u32,
+                D3D12_MULTIPLE_FENCE_WAIT_FLAG_ANY,
+                fence_event,
+            )
+        };
+
+        unsafe {
+            if waiting.is_ok() {
+                WaitForMultipleObjects(&[fence_event, wake_event], false, INFINITE);
+            } else {
+                // Fall back to polling rather than spinning
+                WaitForSingleObject(wake_event, 1);
+            }
+        }
+    }
+
+    unsafe {
+        CloseHandle(fence_event);
+    }
+}
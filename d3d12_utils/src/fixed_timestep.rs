@@ -0,0 +1,75 @@
+/// Splits frame times into whole steps of a fixed length, so a simulation behaves the same at any
+/// frame rate. What is left over carries into the next frame, and `alpha` tells how far into the
+/// next step the frame is, for interpolating between the last two simulated states.
+#[derive(Debug, Clone)]
+pub struct FixedTimestep {
+    step: f32,
+    // Steps taken at most per frame, a slow frame drops the time past this instead of making the
+    // next frame slower still
+    max_steps: u32,
+    accumulator: f32,
+}
+
+impl FixedTimestep {
+    pub fn new(steps_per_second: f32, max_steps: u32) -> Self {
+        Self {
+            step: 1.0 / steps_per_second,
+            max_steps,
+            accumulator: 0.0,
+        }
+    }
+
+    /// Seconds every step simulates
+    pub fn step(&self) -> f32 {
+        self.step
+    }
+
+    /// Adds a frame's `delta_time` seconds and returns how many steps to simulate for it
+    pub fn advance(&mut self, delta_time: f32) -> u32 {
+        self.accumulator += delta_time.max(0.0);
+
+        let steps = (self.accumulator / self.step).floor() as u32;
+        if steps > self.max_steps {
+            self.accumulator %= self.step;
+            return self.max_steps;
+        }
+
+        self.accumulator -= steps as f32 * self.step;
+        steps
+    }
+
+    /// From 0 right after a step to almost 1 just before the next
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator / self.step).clamp(0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn carries_partial_steps_over() {
+        let mut timestep = FixedTimestep::new(60.0, 8);
+
+        // 144 Hz frames take a step every two or three frames
+        let steps: u32 = (0..144).map(|_| timestep.advance(1.0 / 144.0)).sum();
+        assert!((59..=60).contains(&steps), "{}", steps);
+
+        let mut timestep = FixedTimestep::new(4.0, 8);
+        assert_eq!(timestep.advance(0.625), 2);
+        assert_eq!(timestep.alpha(), 0.5);
+        assert_eq!(timestep.advance(0.125), 1);
+        assert_eq!(timestep.alpha(), 0.0);
+    }
+
+    #[test]
+    fn drops_time_past_the_step_limit() {
+        let mut timestep = FixedTimestep::new(4.0, 3);
+
+        assert_eq!(timestep.advance(2.125), 3);
+        assert_eq!(timestep.alpha(), 0.5);
+        assert_eq!(timestep.advance(0.0), 0);
+        assert_eq!(timestep.advance(-1.0), 0);
+    }
+}
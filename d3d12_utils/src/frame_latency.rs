@@ -0,0 +1,84 @@
+use anyhow::{bail, ensure, Result};
+use windows::{
+    core::Interface,
+    Win32::{
+        Foundation::{CloseHandle, HANDLE, WAIT_OBJECT_0, WAIT_TIMEOUT},
+        Graphics::Dxgi::{IDXGISwapChain2, IDXGISwapChain3},
+        System::Threading::WaitForSingleObjectEx,
+    },
+};
+
+/// The latency waitable object of a swap chain created with
+/// DXGI_SWAP_CHAIN_FLAG_FRAME_LATENCY_WAITABLE_OBJECT. It is signalled once the swap chain can take
+/// another frame, so waiting on it before reading input keeps the input of a frame fresh.
+#[derive(Debug)]
+pub struct FrameLatencyWaiter {
+    swap_chain: IDXGISwapChain2,
+    handle: HANDLE,
+    maximum_frame_latency: u32,
+}
+
+impl FrameLatencyWaiter {
+    pub fn new(swap_chain: &IDXGISwapChain3, maximum_frame_latency: u32) -> Result<Self> {
+        let swap_chain: IDXGISwapChain2 = swap_chain.cast()?;
+        let handle = unsafe { swap_chain.GetFrameLatencyWaitableObject() };
+        ensure!(
+            !handle.is_invalid(),
+            "Swap chain was not created with a frame latency waitable object"
+        );
+
+        let mut waiter = FrameLatencyWaiter {
+            swap_chain,
+            handle,
+            maximum_frame_latency,
+        };
+        waiter.set_maximum_frame_latency(maximum_frame_latency)?;
+
+        Ok(waiter)
+    }
+
+    pub fn maximum_frame_latency(&self) -> u32 {
+        self.maximum_frame_latency
+    }
+
+    /// How many frames can be queued for presentation before `wait` blocks, 1 for the least latency
+    pub fn set_maximum_frame_latency(&mut self, maximum_frame_latency: u32) -> Result<()> {
+        ensure!(
+            (1..=16).contains(&maximum_frame_latency),
+            "Maximum frame latency {} is not between 1 and 16",
+            maximum_frame_latency
+        );
+
+        unsafe {
+            self.swap_chain
+                .SetMaximumFrameLatency(maximum_frame_latency)?;
+        }
+        self.maximum_frame_latency = maximum_frame_latency;
+
+        Ok(())
+    }
+
+    /// Blocks until the swap chain can take another frame. False when `timeout_ms` ran out first.
+    pub fn wait(&self, timeout_ms: u32) -> Result<bool> {
+        let result = unsafe { WaitForSingleObjectEx(self.handle, timeout_ms, true) };
+        match result {
+            _ if result == WAIT_OBJECT_0.0 => Ok(true),
+            _ if result == WAIT_TIMEOUT.0 => Ok(false),
+            _ => bail!("Waiting for the swap chain failed with {:#x}", result),
+        }
+    }
+
+    /// For frame loops that wait on it together with other handles. Signalled once per frame the
+    /// swap chain can take, and only valid as long as the waiter is alive.
+    pub fn handle(&self) -> HANDLE {
+        self.handle
+    }
+}
+
+impl Drop for FrameLatencyWaiter {
+    fn drop(&mut self) {
+        unsafe {
+            CloseHandle(self.handle);
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use anyhow::{ensure, Context, Result};
+use windows::Win32::Graphics::{Direct3D12::*, Dxgi::Common::DXGI_SAMPLE_DESC};
+
+use crate::{transition_barrier, Resource};
+
+/// Pixels of a texture copied back to the CPU, rows are `row_pitch` bytes apart
+#[derive(Debug)]
+pub struct CapturedFrame {
+    /// Order in which the frames were captured
+    pub sequence: u64,
+    pub width: u32,
+    pub height: u32,
+    pub row_pitch: usize,
+    pub data: Vec<u8>,
+}
+
+/// A readback buffer per frame in flight for copying a 2D texture back to the CPU every frame,
+/// e.g. the back buffer to record a video
+#[derive(Debug)]
+pub struct FrameReadback {
+    buffers: Vec<Resource>,
+    footprint: D3D12_PLACED_SUBRESOURCE_FOOTPRINT,
+    height: u32,
+    pending: Vec<Option<u64>>,
+    next_sequence: u64,
+}
+
+impl FrameReadback {
+    pub fn new(
+        device: &ID3D12Device4,
+        texture_desc: &D3D12_RESOURCE_DESC,
+        num_frames: usize,
+    ) -> Result<Self> {
+        ensure!(
+            texture_desc.Dimension == D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+            "Only 2D textures can be read back"
+        );
+
+        let mut footprint = D3D12_PLACED_SUBRESOURCE_FOOTPRINT::default();
+        let mut total_bytes = 0;
+        unsafe {
+            device.GetCopyableFootprints(
+                texture_desc,
+                0,
+                1,
+                0,
+                &mut footprint,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                &mut total_bytes,
+            );
+        }
+
+        let buffers = (0..num_frames)
+            .map(|_| {
+                Resource::create_committed(
+                    device,
+                    &D3D12_HEAP_PROPERTIES {
+                        Type: D3D12_HEAP_TYPE_READBACK,
+                        ..Default::default()
+                    },
+                    &D3D12_RESOURCE_DESC {
+                        Dimension: D3D12_RESOURCE_DIMENSION_BUFFER,
+                        Width: total_bytes,
+                        Height: 1,
+                        DepthOrArraySize: 1,
+                        MipLevels: 1,
+                        SampleDesc: DXGI_SAMPLE_DESC {
+                            Count: 1,
+                            Quality: 0,
+                        },
+                        Layout: D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
+                        ..Default::default()
+                    },
+                    D3D12_RESOURCE_STATE_COPY_DEST,
+                    None,
+                    true,
+                )
+            })
+            .collect::<Result<Vec<Resource>>>()?;
+
+        Ok(Self {
+            buffers,
+            footprint,
+            height: texture_desc.Height,
+            pending: vec![None; num_frames],
+            next_sequence: 0,
+        })
+    }
+
+    pub fn num_frames(&self) -> usize {
+        self.buffers.len()
+    }
+
+    /// Copies `texture` into the buffer of `frame_index`, leaving it in `texture_state`. It has
+    /// to have the size and format of the texture the readback was created for.
+    pub fn copy(
+        &mut self,
+        command_list: &ID3D12GraphicsCommandList,
+        texture: &ID3D12Resource,
+        texture_state: D3D12_RESOURCE_STATES,
+        frame_index: usize,
+    ) -> Result<()> {
+        let buffer = self
+            .buffers
+            .get(frame_index)
+            .context("Frame index out of range")?;
+        ensure!(
+            self.pending[frame_index].is_none(),
+            "Frame {} was not taken before being captured again",
+            frame_index
+        );
+        let desc = unsafe { texture.GetDesc() };
+        ensure!(
+            desc.Width == self.footprint.Footprint.Width as u64
+                && desc.Height == self.height
+                && desc.Format == self.footprint.Footprint.Format,
+            "Reading back a {}x{} {:?} texture into buffers for {}x{} {:?}",
+            desc.Width,
+            desc.Height,
+            desc.Format,
+            self.footprint.Footprint.Width,
+            self.height,
+            self.footprint.Footprint.Format
+        );
+
+        let to_copy_source =
+            transition_barrier(texture, texture_state, D3D12_RESOURCE_STATE_COPY_SOURCE);
+        let from_copy_source =
+            transition_barrier(texture, D3D12_RESOURCE_STATE_COPY_SOURCE, texture_state);
+
+        let from = D3D12_TEXTURE_COPY_LOCATION {
+            pResource: Some(texture.clone()),
+            Type: D3D12_TEXTURE_COPY_TYPE_SUBRESOURCE_INDEX,
+            Anonymous: D3D12_TEXTURE_COPY_LOCATION_0 {
+                SubresourceIndex: 0,
+            },
+        };
+        let to = D3D12_TEXTURE_COPY_LOCATION {
+            pResource: Some(buffer.device_resource.clone()),
+            Type: D3D12_TEXTURE_COPY_TYPE_PLACED_FOOTPRINT,
+            Anonymous: D3D12_TEXTURE_COPY_LOCATION_0 {
+                PlacedFootprint: self.footprint,
+            },
+        };
+
+        unsafe {
+            command_list.ResourceBarrier(std::slice::from_ref(&to_copy_source));
+            command_list.CopyTextureRegion(&to, 0, 0, 0, &from, std::ptr::null());
+            command_list.ResourceBarrier(std::slice::from_ref(&from_copy_source));
+
+            let _: D3D12_RESOURCE_TRANSITION_BARRIER =
+                std::mem::ManuallyDrop::into_inner(to_copy_source.Anonymous.Transition);
+            let _: D3D12_RESOURCE_TRANSITION_BARRIER =
+                std::mem::ManuallyDrop::into_inner(from_copy_source.Anonymous.Transition);
+        }
+
+        self.pending[frame_index] = Some(self.next_sequence);
+        self.next_sequence += 1;
+
+        Ok(())
+    }
+
+    /// Returns the frame copied for `frame_index`, if any.
+    /// The GPU has to be done with the command list the copy was recorded into.
+    pub fn take(&mut self, frame_index: usize) -> Option<CapturedFrame> {
+        let sequence = self.pending.get_mut(frame_index)?.take()?;
+        let buffer = &self.buffers[frame_index];
+        let size = self.footprint.Footprint.RowPitch as usize * self.height as usize;
+
+        let mut data = vec![0; size];
+        unsafe {
+            std::ptr::copy_nonoverlapping(buffer.mapped_data as *const u8, data.as_mut_ptr(), size);
+        }
+
+        Some(CapturedFrame {
+            sequence,
+            width: self.footprint.Footprint.Width,
+            height: self.height,
+            row_pitch: self.footprint.Footprint.RowPitch as usize,
+            data,
+        })
+    }
+
+    /// Takes all copied frames in the order they were captured, the GPU has to be idle
+    pub fn take_all(&mut self) -> Vec<CapturedFrame> {
+        let mut frames: Vec<CapturedFrame> = (0..self.buffers.len())
+            .filter_map(|frame_index| self.take(frame_index))
+            .collect();
+        frames.sort_by_key(|frame| frame.sequence);
+
+        frames
+    }
+}
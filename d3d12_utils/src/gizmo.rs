@@ -0,0 +1,370 @@
+use glam::{Quat, Vec3, Vec4};
+
+use crate::{Plane, Ray};
+
+// Handles can be grabbed this far from their lines, relative to the gizmo's size
+const HANDLE_TOLERANCE: f32 = 0.08;
+const CIRCLE_SEGMENTS: usize = 48;
+// Smallest factor a scale drag can shrink an object by
+const MIN_SCALE_FACTOR: f32 = 0.01;
+
+const HIGHLIGHT_COLOR: Vec4 = Vec4::new(1.0, 0.9, 0.1, 1.0);
+
+/// A coloured line in world space
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DebugLine {
+    pub start: Vec3,
+    pub end: Vec3,
+    pub color: Vec4,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GizmoMode {
+    #[default]
+    Translate,
+    Rotate,
+    Scale,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GizmoAxis {
+    X,
+    Y,
+    Z,
+}
+
+impl GizmoAxis {
+    pub const ALL: [GizmoAxis; 3] = [GizmoAxis::X, GizmoAxis::Y, GizmoAxis::Z];
+
+    pub fn index(&self) -> usize {
+        match self {
+            GizmoAxis::X => 0,
+            GizmoAxis::Y => 1,
+            GizmoAxis::Z => 2,
+        }
+    }
+
+    fn color(&self) -> Vec4 {
+        match self {
+            GizmoAxis::X => Vec4::new(1.0, 0.2, 0.2, 1.0),
+            GizmoAxis::Y => Vec4::new(0.2, 1.0, 0.2, 1.0),
+            GizmoAxis::Z => Vec4::new(0.2, 0.4, 1.0, 1.0),
+        }
+    }
+}
+
+/// Where a gizmo sits: at an object's world position, lined up with its axes. `size` is the
+/// length of the handles in world units, scaled with the distance to keep them the same size on
+/// screen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GizmoFrame {
+    pub center: Vec3,
+    pub orientation: Quat,
+    pub size: f32,
+}
+
+impl GizmoFrame {
+    /// Unit length, in world space
+    pub fn axis(&self, axis: GizmoAxis) -> Vec3 {
+        self.orientation * Vec3::AXES[axis.index()]
+    }
+}
+
+/// A change made by dragging a handle, relative to the object at the start of the drag
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GizmoEdit {
+    /// World space offset
+    Translate(Vec3),
+    /// World space rotation about the gizmo's center
+    Rotate(Quat),
+    /// Factor for the object's scale along one of its own axes
+    Scale(GizmoAxis, f32),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct GizmoDrag {
+    axis: GizmoAxis,
+    frame: GizmoFrame,
+    // Where the handle was grabbed, along the axis for translating and scaling and as a direction
+    // from the center for rotating
+    start: f32,
+    start_direction: Vec3,
+}
+
+/// Handles for moving, rotating and scaling an object with the mouse, one per axis, in the style
+/// of ImGuizmo. Works on rays through the cursor and hands back `GizmoEdit`s, what they are
+/// applied to is up to the caller.
+#[derive(Debug, Clone, Default)]
+pub struct Gizmo {
+    pub mode: GizmoMode,
+    hovered: Option<GizmoAxis>,
+    drag: Option<GizmoDrag>,
+}
+
+impl Gizmo {
+    pub fn new(mode: GizmoMode) -> Self {
+        Self {
+            mode,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_dragging(&self) -> bool {
+        self.drag.is_some()
+    }
+
+    /// The closest handle `ray` passes through
+    pub fn hit_test(&self, frame: &GizmoFrame, ray: &Ray) -> Option<GizmoAxis> {
+        let tolerance = frame.size * HANDLE_TOLERANCE;
+
+        GizmoAxis::ALL
+            .into_iter()
+            .filter_map(|axis| {
+                let direction = frame.axis(axis);
+                match self.mode {
+                    GizmoMode::Translate | GizmoMode::Scale => {
+                        let (t, distance) = ray.closest_to_segment(
+                            frame.center,
+                            frame.center + direction * frame.size,
+                        )?;
+                        (distance < tolerance).then_some((axis, t))
+                    }
+                    GizmoMode::Rotate => {
+                        let t = ray
+                            .intersect_plane(&Plane::from_point_normal(frame.center, direction))?;
+                        let radius = ray.at(t).distance(frame.center);
+                        ((radius - frame.size).abs() < tolerance).then_some((axis, t))
+                    }
+                }
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(axis, _)| axis)
+    }
+
+    /// Highlights the handle under the cursor
+    pub fn hover(&mut self, frame: &GizmoFrame, ray: &Ray) {
+        self.hovered = self.hit_test(frame, ray);
+    }
+
+    /// Grabs the handle `ray` passes through, false if there is none
+    pub fn begin_drag(&mut self, frame: &GizmoFrame, ray: &Ray) -> bool {
+        self.drag = self.hit_test(frame, ray).and_then(|axis| {
+            let direction = frame.axis(axis);
+            let (start, start_direction) = match self.mode {
+                GizmoMode::Translate => {
+                    (ray.closest_to_line(frame.center, direction)?.1, Vec3::ZERO)
+                }
+                // Grabbing the scale handle right at the center would scale by huge factors
+                GizmoMode::Scale => (
+                    ray.closest_to_line(frame.center, direction)?
+                        .1
+                        .max(frame.size * HANDLE_TOLERANCE),
+                    Vec3::ZERO,
+                ),
+                GizmoMode::Rotate => (0.0, self.direction_in_plane(frame, axis, ray)?),
+            };
+
+            Some(GizmoDrag {
+                axis,
+                frame: *frame,
+                start,
+                start_direction,
+            })
+        });
+
+        self.drag.is_some()
+    }
+
+    /// How far the grabbed handle has been dragged to `ray`. None without a drag or while the
+    /// ray runs along the axis or plane of the handle.
+    pub fn drag(&self, ray: &Ray) -> Option<GizmoEdit> {
+        let drag = self.drag?;
+        let frame = &drag.frame;
+        let direction = frame.axis(drag.axis);
+
+        match self.mode {
+            GizmoMode::Translate => {
+                let (_, along) = ray.closest_to_line(frame.center, direction)?;
+                Some(GizmoEdit::Translate(direction * (along - drag.start)))
+            }
+            GizmoMode::Rotate => {
+                let current = self.direction_in_plane(frame, drag.axis, ray)?;
+                let angle = direction
+                    .dot(drag.start_direction.cross(current))
+                    .atan2(drag.start_direction.dot(current));
+                Some(GizmoEdit::Rotate(Quat::from_axis_angle(direction, angle)))
+            }
+            GizmoMode::Scale => {
+                let (_, along) = ray.closest_to_line(frame.center, direction)?;
+                Some(GizmoEdit::Scale(
+                    drag.axis,
+                    (along / drag.start).max(MIN_SCALE_FACTOR),
+                ))
+            }
+        }
+    }
+
+    pub fn end_drag(&mut self) {
+        self.drag = None;
+    }
+
+    /// The handles to draw, the grabbed or hovered one highlighted
+    pub fn lines(&self, frame: &GizmoFrame) -> Vec<DebugLine> {
+        let active = self.drag.map(|drag| drag.axis).or(self.hovered);
+        let mut lines = Vec::new();
+
+        for axis in GizmoAxis::ALL {
+            let color = if active == Some(axis) {
+                HIGHLIGHT_COLOR
+            } else {
+                axis.color()
+            };
+            let direction = frame.axis(axis);
+            // The two other axes, spanning the plane the handle's axis is normal to
+            let side = frame.axis(GizmoAxis::ALL[(axis.index() + 1) % 3]);
+            let up = frame.axis(GizmoAxis::ALL[(axis.index() + 2) % 3]);
+            let tip = frame.center + direction * frame.size;
+            let mut line = |start: Vec3, end: Vec3| lines.push(DebugLine { start, end, color });
+
+            match self.mode {
+                GizmoMode::Translate => {
+                    line(frame.center, tip);
+                    let back = tip - direction * frame.size * 0.15;
+                    for offset in [side, -side, up, -up] {
+                        line(tip, back + offset * frame.size * 0.05);
+                    }
+                }
+                GizmoMode::Rotate => {
+                    let point = |i: usize| {
+                        let angle = i as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+                        frame.center + (side * angle.cos() + up * angle.sin()) * frame.size
+                    };
+                    for i in 0..CIRCLE_SEGMENTS {
+                        line(point(i), point(i + 1));
+                    }
+                }
+                GizmoMode::Scale => {
+                    line(frame.center, tip);
+                    // A cube on the end, drawn as its twelve edges
+                    let half = frame.size * 0.06;
+                    let corner =
+                        |x: f32, y: f32, z: f32| tip + (direction * x + side * y + up * z) * half;
+                    for a in [-1.0, 1.0] {
+                        for b in [-1.0, 1.0] {
+                            line(corner(-1.0, a, b), corner(1.0, a, b));
+                            line(corner(a, -1.0, b), corner(a, 1.0, b));
+                            line(corner(a, b, -1.0), corner(a, b, 1.0));
+                        }
+                    }
+                }
+            }
+        }
+
+        lines
+    }
+
+    // Unit direction from the center to where `ray` crosses the plane of a rotation handle
+    fn direction_in_plane(&self, frame: &GizmoFrame, axis: GizmoAxis, ray: &Ray) -> Option<Vec3> {
+        let t = ray.intersect_plane(&Plane::from_point_normal(frame.center, frame.axis(axis)))?;
+
+        (ray.at(t) - frame.center).try_normalize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame() -> GizmoFrame {
+        GizmoFrame {
+            center: Vec3::ZERO,
+            orientation: Quat::IDENTITY,
+            size: 1.0,
+        }
+    }
+
+    // Looking down at the gizmo from above
+    fn ray_at(x: f32, z: f32) -> Ray {
+        Ray::new(Vec3::new(x, 10.0, z), -Vec3::Y)
+    }
+
+    #[test]
+    fn hits_the_handle_under_the_ray() {
+        let gizmo = Gizmo::new(GizmoMode::Translate);
+        assert_eq!(
+            gizmo.hit_test(&frame(), &ray_at(0.5, 0.0)),
+            Some(GizmoAxis::X)
+        );
+        assert_eq!(
+            gizmo.hit_test(&frame(), &ray_at(0.0, 0.7)),
+            Some(GizmoAxis::Z)
+        );
+        assert_eq!(gizmo.hit_test(&frame(), &ray_at(1.5, 0.0)), None);
+        assert_eq!(gizmo.hit_test(&frame(), &ray_at(-0.5, 0.0)), None);
+
+        let rotated = GizmoFrame {
+            orientation: Quat::from_rotation_y(std::f32::consts::FRAC_PI_2),
+            ..frame()
+        };
+        assert_eq!(
+            gizmo.hit_test(&rotated, &ray_at(0.0, -0.5)),
+            Some(GizmoAxis::X)
+        );
+
+        // Seen from above only the ring about y is a circle, the others are edge on lines
+        let gizmo = Gizmo::new(GizmoMode::Rotate);
+        assert_eq!(
+            gizmo.hit_test(&frame(), &ray_at(0.6, 0.8)),
+            Some(GizmoAxis::Y)
+        );
+        assert_eq!(gizmo.hit_test(&frame(), &ray_at(0.3, 0.3)), None);
+    }
+
+    #[test]
+    fn drags_relative_to_where_the_handle_was_grabbed() {
+        let mut gizmo = Gizmo::new(GizmoMode::Translate);
+        assert!(!gizmo.begin_drag(&frame(), &ray_at(2.0, 2.0)));
+        assert!(gizmo.begin_drag(&frame(), &ray_at(0.5, 0.0)));
+        let GizmoEdit::Translate(offset) = gizmo.drag(&ray_at(1.5, 3.0)).unwrap() else {
+            panic!("Expected a translation");
+        };
+        assert!(offset.abs_diff_eq(Vec3::X, 1e-5));
+        gizmo.end_drag();
+        assert_eq!(gizmo.drag(&ray_at(1.5, 3.0)), None);
+
+        let mut gizmo = Gizmo::new(GizmoMode::Rotate);
+        assert!(gizmo.begin_drag(&frame(), &ray_at(1.0, 0.0)));
+        let GizmoEdit::Rotate(rotation) = gizmo.drag(&ray_at(0.0, -2.0)).unwrap() else {
+            panic!("Expected a rotation");
+        };
+        // From +x to -z is a quarter turn about y in a left-handed world
+        assert!((rotation * Vec3::X).abs_diff_eq(-Vec3::Z, 1e-5));
+
+        let mut gizmo = Gizmo::new(GizmoMode::Scale);
+        assert!(gizmo.begin_drag(&frame(), &ray_at(0.0, 0.5)));
+        assert_eq!(
+            gizmo.drag(&ray_at(0.0, 1.0)),
+            Some(GizmoEdit::Scale(GizmoAxis::Z, 2.0))
+        );
+        assert_eq!(
+            gizmo.drag(&ray_at(0.0, -1.0)),
+            Some(GizmoEdit::Scale(GizmoAxis::Z, MIN_SCALE_FACTOR))
+        );
+    }
+
+    #[test]
+    fn highlights_the_active_handle() {
+        let mut gizmo = Gizmo::new(GizmoMode::Scale);
+        let lines = gizmo.lines(&frame());
+        assert_eq!(lines.len(), 3 * 13);
+        assert!(lines.iter().all(|line| line.color != HIGHLIGHT_COLOR));
+
+        gizmo.hover(&frame(), &ray_at(0.0, 0.5));
+        let highlighted = gizmo
+            .lines(&frame())
+            .iter()
+            .filter(|line| line.color == HIGHLIGHT_COLOR)
+            .count();
+        assert_eq!(highlighted, 13);
+    }
+}
@@ -0,0 +1,116 @@
+use std::path::Path;
+
+use anyhow::Result;
+use glam::Vec2;
+use windows::Win32::Graphics::Direct3D12::ID3D12Device4;
+
+use crate::{write_generated_header, FrameFence, VersionedBuffer};
+
+/// Register space of the global constants, they sit at b0 in it
+pub const GLOBAL_CONSTANTS_REGISTER_SPACE: u32 = 1;
+
+/// Constants every pass gets without asking, uploaded once per frame. Has to match
+/// `GLOBALS_HLSL`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GlobalConstants {
+    /// Seconds the scene has moved on for, stands still while paused
+    pub time: f32,
+    /// Seconds the scene moved on this frame, 0 while paused
+    pub delta_time: f32,
+    /// Frames rendered since start up
+    pub frame_number: u32,
+    /// A bit per held mouse button, see `MOUSE_BUTTON_LEFT` and friends
+    pub mouse_buttons: u32,
+    /// Size of the output in pixels
+    pub resolution: Vec2,
+    /// Size the scene is rendered at, smaller than `resolution` with dynamic resolution
+    pub render_resolution: Vec2,
+    /// Cursor position in pixels from the top left of the window
+    pub mouse_position: Vec2,
+    pub padding: [u32; 2],
+}
+
+pub const MOUSE_BUTTON_LEFT: u32 = 1 << 0;
+pub const MOUSE_BUTTON_RIGHT: u32 = 1 << 1;
+pub const MOUSE_BUTTON_MIDDLE: u32 = 1 << 2;
+
+/// Declares `globals` for shaders, written out by `write_globals_header`
+pub const GLOBALS_HLSL: &str = r#"// Generated by d3d12_utils::write_globals_header, do not edit. Matches GlobalConstants in global_constants.rs.
+#pragma once
+
+#define MOUSE_BUTTON_LEFT 1
+#define MOUSE_BUTTON_RIGHT 2
+#define MOUSE_BUTTON_MIDDLE 4
+
+struct GlobalConstants
+{
+    float time;
+    float delta_time;
+    uint frame_number;
+    uint mouse_buttons;
+    float2 resolution;
+    float2 render_resolution;
+    float2 mouse_position;
+    uint2 padding;
+};
+
+ConstantBuffer<GlobalConstants> globals : register(b0, space1);
+"#;
+
+/// Writes `GLOBALS_HLSL` to `path`, leaving the file alone when it is already up to date
+pub fn write_globals_header(path: impl AsRef<Path>) -> Result<()> {
+    write_generated_header(path.as_ref(), GLOBALS_HLSL)
+}
+
+/// The global constants of every frame in flight, bound as a root constant buffer view by root
+/// signatures from `create_pass_root_signature`
+#[derive(Debug)]
+pub struct GlobalConstantBuffer<const FRAME_COUNT: usize> {
+    buffer: VersionedBuffer<FRAME_COUNT>,
+}
+
+impl<const FRAME_COUNT: usize> GlobalConstantBuffer<FRAME_COUNT> {
+    pub fn new(device: &ID3D12Device4) -> Result<Self> {
+        Ok(Self {
+            buffer: VersionedBuffer::new(device, std::mem::size_of::<GlobalConstants>())?,
+        })
+    }
+
+    /// Call once the fence of `frame.frame_index` has been waited on, before recording any pass
+    pub fn begin_frame(&mut self, frame: FrameFence, constants: &GlobalConstants) -> Result<()> {
+        self.buffer.begin_frame(frame)?;
+        self.buffer
+            .write_for_frame(frame.frame_index, &[*constants])
+    }
+
+    pub fn gpu_address(&self, frame_index: usize) -> u64 {
+        self.buffer.gpu_address(frame_index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layout_matches_the_shader_header() {
+        // Three full 16 byte rows, so HLSL packing rules don't move anything around
+        assert_eq!(std::mem::size_of::<GlobalConstants>(), 48);
+        assert_eq!(
+            GLOBALS_HLSL
+                .lines()
+                .filter(|line| line.starts_with("    ") && line.ends_with(';'))
+                .count(),
+            8
+        );
+
+        for (define, value) in [
+            ("MOUSE_BUTTON_LEFT", MOUSE_BUTTON_LEFT),
+            ("MOUSE_BUTTON_RIGHT", MOUSE_BUTTON_RIGHT),
+            ("MOUSE_BUTTON_MIDDLE", MOUSE_BUTTON_MIDDLE),
+        ] {
+            assert!(GLOBALS_HLSL.contains(&format!("#define {} {}", define, value)));
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use anyhow::Result;
+use windows::Win32::Graphics::Direct3D12::*;
+
+use crate::{CommandQueue, QueryHeap};
+
+/// Measures the GPU time between `begin` and `end` for each frame in flight
+#[derive(Debug)]
+pub struct GpuTimer {
+    timestamps: QueryHeap<u64>,
+    timestamp_frequency: u64,
+}
+
+impl GpuTimer {
+    pub fn new(device: &ID3D12Device4, queue: &CommandQueue, num_frames: usize) -> Result<Self> {
+        let timestamps = QueryHeap::new(
+            device,
+            D3D12_QUERY_HEAP_TYPE_TIMESTAMP,
+            D3D12_QUERY_TYPE_TIMESTAMP,
+            num_frames * 2,
+        )?;
+
+        let timestamp_frequency = unsafe { queue.queue.GetTimestampFrequency() }?;
+
+        Ok(GpuTimer {
+            timestamps,
+            timestamp_frequency,
+        })
+    }
+
+    pub fn begin(&self, command_list: &ID3D12GraphicsCommandList, frame_index: usize) {
+        self.timestamps.end(command_list, frame_index * 2);
+    }
+
+    pub fn end(&self, command_list: &ID3D12GraphicsCommandList, frame_index: usize) -> Result<()> {
+        let first_query = frame_index * 2;
+        self.timestamps.end(command_list, first_query + 1);
+        self.timestamps.resolve(command_list, first_query, 2)
+    }
+
+    /// Only meaningful once the fence for `frame_index` has been waited on
+    pub fn read_milliseconds(&self, frame_index: usize) -> Option<f32> {
+        let timestamps = self.timestamps.read(frame_index * 2, 2)?;
+        timestamp_milliseconds(timestamps[0], timestamps[1], self.timestamp_frequency)
+    }
+}
+
+// None for timestamps that weren't written or went backwards
+pub(crate) fn timestamp_milliseconds(start: u64, end: u64, frequency: u64) -> Option<f32> {
+    if start == 0 || end < start || frequency == 0 {
+        return None;
+    }
+
+    Some(((end - start) as f64 * 1000.0 / frequency as f64) as f32)
+}
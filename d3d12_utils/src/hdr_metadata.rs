@@ -0,0 +1,124 @@
+use windows::Win32::Graphics::Dxgi::DXGI_HDR_METADATA_HDR10;
+
+/// Chromaticity of the Rec. 2020 primaries HDR10 content is mastered in
+pub const REC2020_PRIMARIES: [[f32; 2]; 3] = [[0.708, 0.292], [0.170, 0.797], [0.131, 0.046]];
+/// The D65 white point
+pub const D65_WHITE_POINT: [f32; 2] = [0.3127, 0.3290];
+
+/// HDR10 static metadata, which displays use to tone map content brighter than they can show.
+/// Luminances are in nits, content light levels of 0 mean they are unknown.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HdrMetadata {
+    /// Red, green and blue chromaticity of the mastering display
+    pub primaries: [[f32; 2]; 3],
+    pub white_point: [f32; 2],
+    pub max_mastering_luminance: f32,
+    pub min_mastering_luminance: f32,
+    /// MaxCLL, the brightest pixel of the content
+    pub max_content_light_level: f32,
+    /// MaxFALL, the brightest average of a frame of the content
+    pub max_frame_average_light_level: f32,
+}
+
+impl HdrMetadata {
+    /// Mastered on a Rec. 2020 display with the given range, content light levels unknown
+    pub fn rec2020(max_mastering_luminance: f32, min_mastering_luminance: f32) -> Self {
+        Self {
+            primaries: REC2020_PRIMARIES,
+            white_point: D65_WHITE_POINT,
+            max_mastering_luminance,
+            min_mastering_luminance,
+            max_content_light_level: 0.0,
+            max_frame_average_light_level: 0.0,
+        }
+    }
+
+    pub fn with_content_light_levels(self, levels: &ContentLightLevels) -> Self {
+        Self {
+            max_content_light_level: levels.max_content_light_level,
+            max_frame_average_light_level: levels.max_frame_average_light_level,
+            ..self
+        }
+    }
+
+    /// In the fixed point units DXGI takes, clamped to what they can hold
+    pub fn to_dxgi(&self) -> DXGI_HDR_METADATA_HDR10 {
+        // Chromaticity in steps of 0.00002
+        let chromaticity =
+            |xy: [f32; 2]| xy.map(|c| (c * 50_000.0).round().clamp(0.0, 50_000.0) as u16);
+        let nits = |luminance: f32| luminance.round().clamp(0.0, u16::MAX as f32) as u16;
+
+        DXGI_HDR_METADATA_HDR10 {
+            RedPrimary: chromaticity(self.primaries[0]),
+            GreenPrimary: chromaticity(self.primaries[1]),
+            BluePrimary: chromaticity(self.primaries[2]),
+            WhitePoint: chromaticity(self.white_point),
+            MaxMasteringLuminance: self.max_mastering_luminance.round().max(0.0) as u32,
+            // In steps of 0.0001 nits
+            MinMasteringLuminance: (self.min_mastering_luminance * 10_000.0).round().max(0.0)
+                as u32,
+            MaxContentLightLevel: nits(self.max_content_light_level),
+            MaxFrameAverageLightLevel: nits(self.max_frame_average_light_level),
+        }
+    }
+}
+
+/// The MaxCLL and MaxFALL of the frames analysed so far, for metadata that matches what is
+/// actually shown rather than what the content could reach
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ContentLightLevels {
+    pub max_content_light_level: f32,
+    pub max_frame_average_light_level: f32,
+}
+
+impl ContentLightLevels {
+    /// A frame whose brightest pixel and average are `max` and `average` nits
+    pub fn add_frame(&mut self, max: f32, average: f32) {
+        self.max_content_light_level = self.max_content_light_level.max(max);
+        self.max_frame_average_light_level = self.max_frame_average_light_level.max(average);
+    }
+
+    /// A frame from the luminance of each of its pixels in nits
+    pub fn add_frame_luminances(&mut self, luminances: impl IntoIterator<Item = f32>) {
+        let (max, sum, count) = luminances
+            .into_iter()
+            .fold((0.0f32, 0.0f64, 0usize), |(max, sum, count), luminance| {
+                (max.max(luminance), sum + luminance as f64, count + 1)
+            });
+        if count > 0 {
+            self.add_frame(max, (sum / count as f64) as f32);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_to_dxgi_units() {
+        let mut levels = ContentLightLevels::default();
+        levels.add_frame_luminances([100.0, 300.0, 200.0]);
+        levels.add_frame_luminances([1200.0, 0.0, 0.0, 0.0]);
+        levels.add_frame_luminances([]);
+        assert_eq!(levels.max_content_light_level, 1200.0);
+        assert_eq!(levels.max_frame_average_light_level, 300.0);
+
+        let metadata = HdrMetadata::rec2020(1000.0, 0.001).with_content_light_levels(&levels);
+        let dxgi = metadata.to_dxgi();
+        assert_eq!(dxgi.RedPrimary, [35_400, 14_600]);
+        assert_eq!(dxgi.WhitePoint, [15_635, 16_450]);
+        assert_eq!(dxgi.MaxMasteringLuminance, 1000);
+        assert_eq!(dxgi.MinMasteringLuminance, 10);
+        assert_eq!(dxgi.MaxContentLightLevel, 1200);
+        assert_eq!(dxgi.MaxFrameAverageLightLevel, 300);
+
+        let out_of_range = HdrMetadata {
+            max_content_light_level: 100_000.0,
+            max_frame_average_light_level: -1.0,
+            ..metadata
+        };
+        assert_eq!(out_of_range.to_dxgi().MaxContentLightLevel, u16::MAX);
+        assert_eq!(out_of_range.to_dxgi().MaxFrameAverageLightLevel, 0);
+    }
+}
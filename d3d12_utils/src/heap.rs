@@ -1,15 +1,142 @@
-use anyhow::{ensure, Result};
-use windows::{core::PCWSTR, Win32::Graphics::Direct3D12::*};
+use anyhow::{ensure, Context, Result};
+use windows::{
+    core::PCWSTR,
+    Win32::{Foundation::HANDLE, Graphics::Direct3D12::*},
+};
 
-use crate::{align_data, Resource};
+use crate::{align_data, PoolStats, Resource};
+
+/// A range of a heap occupied by a placed resource, handed back to `Heap::free` once the resource
+/// is no longer used by the GPU
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapAllocation {
+    pub offset: usize,
+    pub size: usize,
+}
+
+/// Hands out ranges of something `size` bytes long, like a heap or a buffer that meshes are
+/// packed into. Freed ranges are reused first fit, everything else comes off the end.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeAllocator {
+    size: usize,
+    curr_offset: usize,
+    count: usize,
+    high_water_mark: usize,
+    free_ranges: Vec<HeapAllocation>,
+}
+
+impl RangeAllocator {
+    pub fn new(size: usize) -> Self {
+        Self {
+            size,
+            curr_offset: 0,
+            count: 0,
+            high_water_mark: 0,
+            free_ranges: Vec::new(),
+        }
+    }
+
+    pub fn allocate(&mut self, size: usize, alignment: usize) -> Result<HeapAllocation> {
+        match take_free_range(&mut self.free_ranges, size, alignment) {
+            Some(allocation) => {
+                self.count += 1;
+                self.high_water_mark = self.high_water_mark.max(self.used_size());
+                Ok(allocation)
+            }
+            None => self.allocate_at_end(size, alignment),
+        }
+    }
+
+    /// Skips the freed ranges, so allocations of sizes that are multiples of `alignment` made one
+    /// after the other are contiguous
+    pub fn allocate_at_end(&mut self, size: usize, alignment: usize) -> Result<HeapAllocation> {
+        let aligned_offset = align_data(self.curr_offset, alignment);
+        ensure!(
+            aligned_offset + size <= self.size,
+            "Not enough space: {} bytes remaining, requested {} bytes",
+            self.size - self.curr_offset,
+            aligned_offset + size - self.curr_offset
+        );
+
+        // The alignment padding is lost until everything after it is freed
+        if aligned_offset > self.curr_offset {
+            insert_free_range(
+                &mut self.free_ranges,
+                HeapAllocation {
+                    offset: self.curr_offset,
+                    size: aligned_offset - self.curr_offset,
+                },
+            );
+        }
+        self.curr_offset = aligned_offset + size;
+        self.count += 1;
+        self.high_water_mark = self.high_water_mark.max(self.used_size());
+
+        Ok(HeapAllocation {
+            offset: aligned_offset,
+            size,
+        })
+    }
+
+    /// Makes the allocation that was made last `size` bytes longer
+    pub fn grow(&mut self, allocation: &mut HeapAllocation, size: usize) -> Result<()> {
+        ensure!(
+            allocation.offset + allocation.size == self.curr_offset,
+            "Only the last allocation can grow"
+        );
+        ensure!(
+            self.curr_offset + size <= self.size,
+            "Not enough space: {} bytes remaining, requested {} bytes",
+            self.size - self.curr_offset,
+            size
+        );
+
+        allocation.size += size;
+        self.curr_offset += size;
+        self.high_water_mark = self.high_water_mark.max(self.used_size());
+
+        Ok(())
+    }
+
+    /// Nothing may use the range anymore. Contiguous allocations can be freed as one.
+    pub fn free(&mut self, allocation: HeapAllocation) {
+        self.count = self.count.saturating_sub(1);
+        insert_free_range(&mut self.free_ranges, allocation);
+
+        // Give the end back
+        if let Some(last) = self.free_ranges.last() {
+            if last.offset + last.size == self.curr_offset {
+                self.curr_offset = last.offset;
+                self.free_ranges.pop();
+            }
+        }
+    }
+
+    pub fn used_size(&self) -> usize {
+        self.curr_offset
+            - self
+                .free_ranges
+                .iter()
+                .map(|range| range.size)
+                .sum::<usize>()
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            used: self.used_size(),
+            capacity: self.size,
+            high_water_mark: self.high_water_mark,
+            count: self.count,
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Heap {
     heap: ID3D12Heap,
-    size: usize,
-    curr_offset: usize,
     name: String,
     num_objects: usize,
+    ranges: RangeAllocator,
 }
 
 impl Heap {
@@ -34,15 +161,32 @@ impl Heap {
 
         let mut heap: Option<ID3D12Heap> = None;
         unsafe { device.CreateHeap(&desc, &mut heap) }?;
-        let heap = heap.unwrap();
 
-        Ok(Heap {
+        Ok(Self::from_heap(heap.unwrap(), size, name))
+    }
+
+    /// Opens a heap created shared on another device, see `export_shared_handle`. Resources
+    /// placed at the same offsets on both devices alias the same memory.
+    pub fn open_shared(device: &ID3D12Device4, handle: HANDLE, name: String) -> Result<Self> {
+        let mut heap: Option<ID3D12Heap> = None;
+        unsafe { device.OpenSharedHandle(handle, &mut heap) }?;
+        let heap = heap.context("Shared handle is not a heap")?;
+        let size = unsafe { heap.GetDesc() }.SizeInBytes as usize;
+
+        Ok(Self::from_heap(heap, size, name))
+    }
+
+    fn from_heap(heap: ID3D12Heap, size: usize, name: String) -> Self {
+        Heap {
             heap,
-            size,
-            curr_offset: 0,
             name,
             num_objects: 0,
-        })
+            ranges: RangeAllocator::new(size),
+        }
+    }
+
+    pub fn device_heap(&self) -> &ID3D12Heap {
+        &self.heap
     }
 
     pub fn create_upload_heap(device: &ID3D12Device4, size: usize, name: &str) -> Result<Self> {
@@ -81,28 +225,40 @@ impl Heap {
         clear_value: Option<D3D12_CLEAR_VALUE>,
         mapped: bool,
     ) -> Result<Resource> {
+        let (resource, _) =
+            self.create_freeable_resource(device, desc, initial_state, clear_value, mapped)?;
+
+        Ok(resource)
+    }
+
+    /// Like `create_resource`, but also returns the range the resource occupies so it can be
+    /// reused after `free`
+    pub fn create_freeable_resource(
+        &mut self,
+        device: &ID3D12Device4,
+        desc: &D3D12_RESOURCE_DESC,
+        initial_state: D3D12_RESOURCE_STATES,
+        clear_value: Option<D3D12_CLEAR_VALUE>,
+        mapped: bool,
+    ) -> Result<(Resource, HeapAllocation)> {
         self.num_objects += 1;
 
         let resource_size = desc.Width as usize * desc.Height as usize;
 
         let allocation_info = unsafe { device.GetResourceAllocationInfo(0, &[*desc]) };
+        let alignment = allocation_info.Alignment as usize;
+        let allocation_size = allocation_info.SizeInBytes as usize;
 
-        let aligned_offset = align_data(self.curr_offset, allocation_info.Alignment as usize);
-
-        let total_size = (aligned_offset - self.curr_offset) + allocation_info.SizeInBytes as usize;
-
-        ensure!(
-            total_size < (self.size - self.curr_offset),
-            "Not enough space in heap: {} bytes remaining, requested resource size {} bytes",
-            (self.size - self.curr_offset),
-            total_size
-        );
+        let allocation = self
+            .ranges
+            .allocate(allocation_size, alignment)
+            .with_context(|| format!("Placing a resource in {}", self.name))?;
 
         let mut resource: Option<ID3D12Resource> = None;
         unsafe {
             device.CreatePlacedResource(
                 &self.heap,
-                aligned_offset as u64,
+                allocation.offset as u64,
                 desc,
                 initial_state,
                 if clear_value.is_none() {
@@ -121,8 +277,6 @@ impl Heap {
             ))?;
         }
 
-        self.curr_offset += total_size;
-
         let mut mapped_data = std::ptr::null_mut();
 
         if mapped {
@@ -131,10 +285,139 @@ impl Heap {
             }
         }
 
-        Ok(Resource {
-            device_resource: resource,
-            size: resource_size,
-            mapped_data,
-        })
+        Ok((
+            Resource {
+                device_resource: resource,
+                size: resource_size,
+                mapped_data,
+            },
+            allocation,
+        ))
+    }
+
+    pub fn used_size(&self) -> usize {
+        self.ranges.used_size()
+    }
+
+    /// Bytes in use, counting placed resources that were never freed
+    pub fn stats(&self) -> PoolStats {
+        self.ranges.stats()
+    }
+
+    /// The resource placed at `allocation` must have been released and must not be in use by the
+    /// GPU anymore
+    pub fn free(&mut self, allocation: HeapAllocation) {
+        self.ranges.free(allocation);
+    }
+}
+
+/// First fit, `free_ranges` is sorted by offset
+fn take_free_range(
+    free_ranges: &mut Vec<HeapAllocation>,
+    size: usize,
+    alignment: usize,
+) -> Option<HeapAllocation> {
+    let index = free_ranges.iter().position(|range| {
+        let aligned_offset = align_data(range.offset, alignment);
+        aligned_offset + size <= range.offset + range.size
+    })?;
+
+    let range = free_ranges.remove(index);
+    let aligned_offset = align_data(range.offset, alignment);
+    let allocation = HeapAllocation {
+        offset: aligned_offset,
+        size,
+    };
+
+    let end = range.offset + range.size;
+    if aligned_offset > range.offset {
+        insert_free_range(
+            free_ranges,
+            HeapAllocation {
+                offset: range.offset,
+                size: aligned_offset - range.offset,
+            },
+        );
+    }
+    if aligned_offset + size < end {
+        insert_free_range(
+            free_ranges,
+            HeapAllocation {
+                offset: aligned_offset + size,
+                size: end - (aligned_offset + size),
+            },
+        );
+    }
+
+    Some(allocation)
+}
+
+/// Keeps `free_ranges` sorted and merges neighbouring ranges
+fn insert_free_range(free_ranges: &mut Vec<HeapAllocation>, range: HeapAllocation) {
+    let index = free_ranges.partition_point(|other| other.offset < range.offset);
+    free_ranges.insert(index, range);
+
+    if index + 1 < free_ranges.len() {
+        let next = free_ranges[index + 1];
+        if range.offset + range.size == next.offset {
+            free_ranges[index].size += next.size;
+            free_ranges.remove(index + 1);
+        }
+    }
+    if index > 0 {
+        let previous = free_ranges[index - 1];
+        if previous.offset + previous.size == free_ranges[index].offset {
+            free_ranges[index - 1].size += free_ranges[index].size;
+            free_ranges.remove(index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(offset: usize, size: usize) -> HeapAllocation {
+        HeapAllocation { offset, size }
+    }
+
+    #[test]
+    fn free_ranges_merge() {
+        let mut free_ranges = Vec::new();
+        insert_free_range(&mut free_ranges, range(0, 256));
+        insert_free_range(&mut free_ranges, range(512, 256));
+        assert_eq!(free_ranges, vec![range(0, 256), range(512, 256)]);
+
+        insert_free_range(&mut free_ranges, range(256, 256));
+        assert_eq!(free_ranges, vec![range(0, 768)]);
+    }
+
+    #[test]
+    fn free_range_reuse_is_aligned() {
+        let mut free_ranges = vec![range(100, 1000)];
+
+        let allocation = take_free_range(&mut free_ranges, 256, 256).unwrap();
+        assert_eq!(allocation, range(256, 256));
+        assert_eq!(free_ranges, vec![range(100, 156), range(512, 588)]);
+
+        assert_eq!(take_free_range(&mut free_ranges, 1024, 256), None);
+    }
+
+    #[test]
+    fn freed_ranges_are_reused() {
+        let mut ranges = RangeAllocator::new(1024);
+        let first = ranges.allocate(256, 256).unwrap();
+        let second = ranges.allocate(256, 256).unwrap();
+        assert!(ranges.allocate(1024, 256).is_err());
+
+        ranges.free(first);
+        assert_eq!(ranges.stats().count, 1);
+        assert_eq!(ranges.allocate(128, 64).unwrap(), range(0, 128));
+
+        // Freeing the last range hands the end back
+        ranges.free(second);
+        assert_eq!(ranges.used_size(), 128);
+        assert_eq!(ranges.allocate_at_end(512, 256).unwrap(), range(256, 512));
+        assert_eq!(ranges.stats().high_water_mark, 512 + 128);
     }
 }
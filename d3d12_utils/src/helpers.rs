@@ -1,19 +1,99 @@
-use anyhow::{Context, Result};
+use std::ffi::c_void;
+
+use anyhow::{ensure, Context, Result};
 
-use hassle_rs::{compile_hlsl, validate_dxil};
 use windows::{
-    core::{Interface, PCWSTR},
-    Win32::{
-        Foundation::{HWND, RECT},
-        Graphics::{
-            Direct3D::*,
-            Direct3D12::*,
-            Dxgi::{Common::*, *},
-        },
+    core::Interface,
+    Win32::Graphics::{
+        Direct3D::*,
+        Direct3D12::*,
+        Dxgi::{Common::*, *},
     },
 };
 
-use crate::CommandQueue;
+use crate::{
+    compile_dxil, depth_bounds_supported, select_target, shader_model, validate_input_layout,
+    DepthRange, DepthStencilState, RootSignatureLayout, TargetFormats,
+    GLOBAL_CONSTANTS_REGISTER_SPACE,
+};
+
+/// Root parameter of the global constants in root signatures from `create_pass_root_signature`
+pub const GLOBAL_CONSTANTS_PARAMETER: u32 = 1;
+/// Root parameter of the texture in root signatures from `create_pass_table_root_signature`, a
+/// table of its shader resource view at t0
+pub const PASS_TEXTURE_PARAMETER: u32 = 2;
+/// Root parameter of the global constants in the root signature from `create_root_signature`
+pub const ROOT_SIGNATURE_GLOBAL_CONSTANTS_PARAMETER: u32 = 3;
+/// Root parameter of the material's texture in the root signature from
+/// `create_descriptor_table_root_signature`, a table of its shader resource view at t0
+pub const ROOT_SIGNATURE_MATERIAL_TEXTURE_PARAMETER: u32 = 4;
+
+fn global_constants_parameter() -> D3D12_ROOT_PARAMETER1 {
+    D3D12_ROOT_PARAMETER1 {
+        ParameterType: D3D12_ROOT_PARAMETER_TYPE_CBV,
+        ShaderVisibility: D3D12_SHADER_VISIBILITY_ALL,
+        Anonymous: D3D12_ROOT_PARAMETER1_0 {
+            Descriptor: D3D12_ROOT_DESCRIPTOR1 {
+                ShaderRegister: 0,
+                RegisterSpace: GLOBAL_CONSTANTS_REGISTER_SPACE,
+                // Written before the frame's command lists run
+                Flags: D3D12_ROOT_DESCRIPTOR_FLAG_DATA_STATIC_WHILE_SET_AT_EXECUTE,
+            },
+        },
+    }
+}
+
+/// Which adapter to create the device on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AdapterSelection {
+    /// The first hardware adapter that supports the feature level
+    #[default]
+    FirstHardware,
+    /// In the order DXGI enumerates adapters
+    Index(u32),
+    /// The software rasterizer
+    Warp,
+}
+
+impl std::str::FromStr for AdapterSelection {
+    type Err = anyhow::Error;
+
+    /// "warp", or an adapter index
+    fn from_str(s: &str) -> Result<Self> {
+        if s.eq_ignore_ascii_case("warp") {
+            return Ok(Self::Warp);
+        }
+
+        let index = s
+            .parse()
+            .with_context(|| format!("Expected an adapter index or \"warp\", got \"{}\"", s))?;
+        Ok(Self::Index(index))
+    }
+}
+
+pub fn get_adapter(
+    factory: &IDXGIFactory5,
+    feature_level: D3D_FEATURE_LEVEL,
+    selection: AdapterSelection,
+) -> Result<IDXGIAdapter1> {
+    let adapter: IDXGIAdapter1 = match selection {
+        AdapterSelection::FirstHardware => return get_hardware_adapter(factory, feature_level),
+        AdapterSelection::Index(index) => unsafe { factory.EnumAdapters1(index) }
+            .with_context(|| format!("No adapter {}", index))?,
+        AdapterSelection::Warp => unsafe { factory.EnumWarpAdapter() }?,
+    };
+
+    unsafe {
+        D3D12CreateDevice(
+            &adapter,
+            feature_level,
+            std::ptr::null_mut::<Option<ID3D12Device4>>(),
+        )
+    }
+    .with_context(|| format!("{:?} doesn't support {:?}", selection, feature_level))?;
+
+    Ok(adapter)
+}
 
 pub fn get_hardware_adapter(
     factory: &IDXGIFactory5,
@@ -64,15 +144,47 @@ pub fn create_device(
     Ok(device.unwrap())
 }
 
+/// A device for compute or offscreen rendering, without a window or a swap chain. The adapter
+/// comes along for its description.
+pub fn create_headless_device(
+    selection: AdapterSelection,
+    feature_level: D3D_FEATURE_LEVEL,
+) -> Result<(IDXGIAdapter1, ID3D12Device4)> {
+    let factory = create_dxgi_factory()?;
+    let adapter = get_adapter(&factory, feature_level, selection)?;
+    let device = create_device(&adapter, feature_level)?;
+
+    Ok((adapter, device))
+}
+
+/// `num_descriptors` descriptors from `base_register` in space 0, right after the previous range
+/// of the table. `flags` promise how long the descriptors and the data behind them stay the same,
+/// which lets drivers read them ahead of time.
+pub fn descriptor_range(
+    range_type: D3D12_DESCRIPTOR_RANGE_TYPE,
+    num_descriptors: u32,
+    base_register: u32,
+    flags: D3D12_DESCRIPTOR_RANGE_FLAGS,
+) -> D3D12_DESCRIPTOR_RANGE1 {
+    D3D12_DESCRIPTOR_RANGE1 {
+        RangeType: range_type,
+        NumDescriptors: num_descriptors,
+        BaseShaderRegister: base_register,
+        RegisterSpace: 0,
+        Flags: flags,
+        OffsetInDescriptorsFromTableStart: D3D12_DESCRIPTOR_RANGE_OFFSET_APPEND,
+    }
+}
+
 pub fn create_descriptor_table(
     shader_visiblity: D3D12_SHADER_VISIBILITY,
-    descriptor_ranges: &[D3D12_DESCRIPTOR_RANGE],
-) -> D3D12_ROOT_PARAMETER {
-    D3D12_ROOT_PARAMETER {
+    descriptor_ranges: &[D3D12_DESCRIPTOR_RANGE1],
+) -> D3D12_ROOT_PARAMETER1 {
+    D3D12_ROOT_PARAMETER1 {
         ParameterType: D3D12_ROOT_PARAMETER_TYPE_DESCRIPTOR_TABLE,
         ShaderVisibility: shader_visiblity,
-        Anonymous: D3D12_ROOT_PARAMETER_0 {
-            DescriptorTable: D3D12_ROOT_DESCRIPTOR_TABLE {
+        Anonymous: D3D12_ROOT_PARAMETER1_0 {
+            DescriptorTable: D3D12_ROOT_DESCRIPTOR_TABLE1 {
                 NumDescriptorRanges: descriptor_ranges.len() as u32,
                 pDescriptorRanges: descriptor_ranges.as_ptr(),
             },
@@ -80,42 +192,76 @@ pub fn create_descriptor_table(
     }
 }
 
+/// The root signature of the scene's draws, for shaders that find their textures and buffers
+/// through `ResourceDescriptorHeap`
 pub fn create_root_signature(device: &ID3D12Device4) -> Result<ID3D12RootSignature> {
-    let root_parameters = [
+    scene_root_signature(device, true)
+}
+
+/// `create_root_signature` for devices without shader model 6.6 dynamic resources. Heap indices
+/// in the constant buffers are meaningless there, each material binds its texture through the
+/// table at `ROOT_SIGNATURE_MATERIAL_TEXTURE_PARAMETER` instead.
+pub fn create_descriptor_table_root_signature(
+    device: &ID3D12Device4,
+) -> Result<ID3D12RootSignature> {
+    scene_root_signature(device, false)
+}
+
+fn scene_root_signature(
+    device: &ID3D12Device4,
+    dynamic_resources: bool,
+) -> Result<ID3D12RootSignature> {
+    let material_texture_range = [descriptor_range(
+        D3D12_DESCRIPTOR_RANGE_TYPE_SRV,
+        1,
+        0,
+        D3D12_DESCRIPTOR_RANGE_FLAG_DATA_STATIC_WHILE_SET_AT_EXECUTE,
+    )];
+    let mut root_parameters = vec![
         // CAMERA
         create_descriptor_table(
             D3D12_SHADER_VISIBILITY_ALL,
-            &[D3D12_DESCRIPTOR_RANGE {
-                RangeType: D3D12_DESCRIPTOR_RANGE_TYPE_CBV,
-                NumDescriptors: 1,
-                BaseShaderRegister: 0,
-                RegisterSpace: 0,
-                OffsetInDescriptorsFromTableStart: D3D12_DESCRIPTOR_RANGE_OFFSET_APPEND,
-            }],
+            &[descriptor_range(
+                D3D12_DESCRIPTOR_RANGE_TYPE_CBV,
+                1,
+                0,
+                D3D12_DESCRIPTOR_RANGE_FLAG_DATA_STATIC_WHILE_SET_AT_EXECUTE,
+            )],
         ),
         // MATERIAL
         create_descriptor_table(
             D3D12_SHADER_VISIBILITY_PIXEL,
-            &[D3D12_DESCRIPTOR_RANGE {
-                RangeType: D3D12_DESCRIPTOR_RANGE_TYPE_CBV,
-                NumDescriptors: 1,
-                BaseShaderRegister: 1,
-                RegisterSpace: 0,
-                OffsetInDescriptorsFromTableStart: D3D12_DESCRIPTOR_RANGE_OFFSET_APPEND,
-            }],
+            &[descriptor_range(
+                D3D12_DESCRIPTOR_RANGE_TYPE_CBV,
+                1,
+                1,
+                D3D12_DESCRIPTOR_RANGE_FLAG_DATA_STATIC_WHILE_SET_AT_EXECUTE,
+            )],
         ),
         // MODEL
         create_descriptor_table(
             D3D12_SHADER_VISIBILITY_ALL,
-            &[D3D12_DESCRIPTOR_RANGE {
-                RangeType: D3D12_DESCRIPTOR_RANGE_TYPE_CBV,
-                NumDescriptors: 1,
-                BaseShaderRegister: 2,
-                RegisterSpace: 0,
-                OffsetInDescriptorsFromTableStart: D3D12_DESCRIPTOR_RANGE_OFFSET_APPEND,
-            }],
+            &[descriptor_range(
+                D3D12_DESCRIPTOR_RANGE_TYPE_CBV,
+                1,
+                2,
+                D3D12_DESCRIPTOR_RANGE_FLAG_DATA_STATIC_WHILE_SET_AT_EXECUTE,
+            )],
         ),
+        // GLOBALS
+        global_constants_parameter(),
     ];
+    let mut flags = D3D12_ROOT_SIGNATURE_FLAG_ALLOW_INPUT_ASSEMBLER_INPUT_LAYOUT;
+    if dynamic_resources {
+        flags |= D3D12_ROOT_SIGNATURE_FLAG_CBV_SRV_UAV_HEAP_DIRECTLY_INDEXED
+            | D3D12_ROOT_SIGNATURE_FLAG_SAMPLER_HEAP_DIRECTLY_INDEXED;
+    } else {
+        // MATERIAL_TEXTURE
+        root_parameters.push(create_descriptor_table(
+            D3D12_SHADER_VISIBILITY_PIXEL,
+            &material_texture_range,
+        ));
+    }
 
     let static_samplers = [D3D12_STATIC_SAMPLER_DESC {
         Filter: D3D12_FILTER_MIN_MAG_MIP_POINT,
@@ -133,26 +279,60 @@ pub fn create_root_signature(device: &ID3D12Device4) -> Result<ID3D12RootSignatu
         ShaderVisibility: D3D12_SHADER_VISIBILITY_PIXEL,
     }];
 
-    let desc = D3D12_ROOT_SIGNATURE_DESC {
+    let desc = D3D12_ROOT_SIGNATURE_DESC1 {
         NumParameters: root_parameters.len() as u32,
         pParameters: root_parameters.as_ptr(),
-        Flags: D3D12_ROOT_SIGNATURE_FLAG_ALLOW_INPUT_ASSEMBLER_INPUT_LAYOUT
-            | D3D12_ROOT_SIGNATURE_FLAG_CBV_SRV_UAV_HEAP_DIRECTLY_INDEXED
-            | D3D12_ROOT_SIGNATURE_FLAG_SAMPLER_HEAP_DIRECTLY_INDEXED,
+        Flags: flags,
         pStaticSamplers: static_samplers.as_ptr(),
         NumStaticSamplers: static_samplers.len() as u32,
     };
 
-    let mut signature = None;
-    let signature = unsafe {
-        D3D12SerializeRootSignature(
-            &desc,
-            D3D_ROOT_SIGNATURE_VERSION_1,
-            &mut signature,
-            std::ptr::null_mut(),
+    serialize_root_signature(device, &desc)
+}
+
+/// Root signature 1.1 when the runtime knows it, 1.0 otherwise
+pub fn highest_root_signature_version(device: &ID3D12Device4) -> D3D_ROOT_SIGNATURE_VERSION {
+    let mut root_signature = D3D12_FEATURE_DATA_ROOT_SIGNATURE {
+        HighestVersion: D3D_ROOT_SIGNATURE_VERSION_1_1,
+    };
+    let queried = unsafe {
+        device.CheckFeatureSupport(
+            D3D12_FEATURE_ROOT_SIGNATURE,
+            std::ptr::addr_of_mut!(root_signature) as *mut c_void,
+            std::mem::size_of_val(&root_signature) as u32,
         )
+    };
+
+    if queried.is_ok() {
+        root_signature.HighestVersion
+    } else {
+        D3D_ROOT_SIGNATURE_VERSION_1_0
+    }
+}
+
+/// Serializes a 1.1 root signature, dropping the range and root descriptor flags on runtimes
+/// that only know 1.0. Those treat everything as volatile.
+pub fn serialize_root_signature(
+    device: &ID3D12Device4,
+    desc: &D3D12_ROOT_SIGNATURE_DESC1,
+) -> Result<ID3D12RootSignature> {
+    let mut signature = None;
+    if highest_root_signature_version(device) == D3D_ROOT_SIGNATURE_VERSION_1_1 {
+        let versioned_desc = D3D12_VERSIONED_ROOT_SIGNATURE_DESC {
+            Version: D3D_ROOT_SIGNATURE_VERSION_1_1,
+            Anonymous: D3D12_VERSIONED_ROOT_SIGNATURE_DESC_0 { Desc_1_1: *desc },
+        };
+        unsafe {
+            D3D12SerializeVersionedRootSignature(
+                &versioned_desc,
+                &mut signature,
+                std::ptr::null_mut(),
+            )
+        }?;
+    } else {
+        serialize_root_signature_1_0(desc, &mut signature)?;
     }
-    .map(|()| signature.unwrap())?;
+    let signature = signature.unwrap();
 
     let root_signature = unsafe {
         device.CreateRootSignature(
@@ -163,16 +343,204 @@ pub fn create_root_signature(device: &ID3D12Device4) -> Result<ID3D12RootSignatu
             ),
         )
     }?;
+    RootSignatureLayout::from_desc(desc).attach(&root_signature)?;
 
     Ok(root_signature)
 }
 
+// The 1.0 version of `desc`, without flags
+fn serialize_root_signature_1_0(
+    desc: &D3D12_ROOT_SIGNATURE_DESC1,
+    signature: &mut Option<ID3DBlob>,
+) -> Result<()> {
+    let parameters = if desc.NumParameters == 0 {
+        &[]
+    } else {
+        unsafe { std::slice::from_raw_parts(desc.pParameters, desc.NumParameters as usize) }
+    };
+    // Every table's ranges have to outlive the 1.0 parameters pointing at them
+    let ranges: Vec<Vec<D3D12_DESCRIPTOR_RANGE>> = parameters
+        .iter()
+        .map(|parameter| {
+            if parameter.ParameterType != D3D12_ROOT_PARAMETER_TYPE_DESCRIPTOR_TABLE {
+                return Vec::new();
+            }
+            let table = unsafe { parameter.Anonymous.DescriptorTable };
+            if table.NumDescriptorRanges == 0 {
+                return Vec::new();
+            }
+            unsafe {
+                std::slice::from_raw_parts(
+                    table.pDescriptorRanges,
+                    table.NumDescriptorRanges as usize,
+                )
+            }
+            .iter()
+            .map(|range| D3D12_DESCRIPTOR_RANGE {
+                RangeType: range.RangeType,
+                NumDescriptors: range.NumDescriptors,
+                BaseShaderRegister: range.BaseShaderRegister,
+                RegisterSpace: range.RegisterSpace,
+                OffsetInDescriptorsFromTableStart: range.OffsetInDescriptorsFromTableStart,
+            })
+            .collect()
+        })
+        .collect();
+    let parameters: Vec<D3D12_ROOT_PARAMETER> = parameters
+        .iter()
+        .zip(&ranges)
+        .map(|(parameter, ranges)| D3D12_ROOT_PARAMETER {
+            ParameterType: parameter.ParameterType,
+            ShaderVisibility: parameter.ShaderVisibility,
+            Anonymous: match parameter.ParameterType {
+                D3D12_ROOT_PARAMETER_TYPE_DESCRIPTOR_TABLE => D3D12_ROOT_PARAMETER_0 {
+                    DescriptorTable: D3D12_ROOT_DESCRIPTOR_TABLE {
+                        NumDescriptorRanges: ranges.len() as u32,
+                        pDescriptorRanges: ranges.as_ptr(),
+                    },
+                },
+                D3D12_ROOT_PARAMETER_TYPE_32BIT_CONSTANTS => D3D12_ROOT_PARAMETER_0 {
+                    Constants: unsafe { parameter.Anonymous.Constants },
+                },
+                _ => {
+                    let descriptor = unsafe { parameter.Anonymous.Descriptor };
+                    D3D12_ROOT_PARAMETER_0 {
+                        Descriptor: D3D12_ROOT_DESCRIPTOR {
+                            ShaderRegister: descriptor.ShaderRegister,
+                            RegisterSpace: descriptor.RegisterSpace,
+                        },
+                    }
+                }
+            },
+        })
+        .collect();
+
+    let desc_1_0 = D3D12_ROOT_SIGNATURE_DESC {
+        NumParameters: parameters.len() as u32,
+        pParameters: parameters.as_ptr(),
+        NumStaticSamplers: desc.NumStaticSamplers,
+        pStaticSamplers: desc.pStaticSamplers,
+        Flags: desc.Flags,
+    };
+    unsafe {
+        D3D12SerializeRootSignature(
+            &desc_1_0,
+            D3D_ROOT_SIGNATURE_VERSION_1_0,
+            signature,
+            std::ptr::null_mut(),
+        )
+    }?;
+
+    Ok(())
+}
+
+/// Root signature for fullscreen and compute passes: 32 bit constants at b0 and a linear clamp
+/// sampler at s0, textures come straight from the descriptor heap
+pub fn create_constants_root_signature(
+    device: &ID3D12Device4,
+    num_constants: u32,
+) -> Result<ID3D12RootSignature> {
+    constants_root_signature(device, num_constants, false, false)
+}
+
+/// `create_constants_root_signature` with the global constants as root parameter
+/// `GLOBAL_CONSTANTS_PARAMETER`, for passes that run every frame
+pub fn create_pass_root_signature(
+    device: &ID3D12Device4,
+    num_constants: u32,
+) -> Result<ID3D12RootSignature> {
+    constants_root_signature(device, num_constants, true, false)
+}
+
+/// `create_pass_root_signature` for devices without shader model 6.6 dynamic resources, the pass
+/// binds its texture through the table at `PASS_TEXTURE_PARAMETER` instead
+pub fn create_pass_table_root_signature(
+    device: &ID3D12Device4,
+    num_constants: u32,
+) -> Result<ID3D12RootSignature> {
+    constants_root_signature(device, num_constants, true, true)
+}
+
+fn constants_root_signature(
+    device: &ID3D12Device4,
+    num_constants: u32,
+    global_constants: bool,
+    texture_table: bool,
+) -> Result<ID3D12RootSignature> {
+    let texture_range = [descriptor_range(
+        D3D12_DESCRIPTOR_RANGE_TYPE_SRV,
+        1,
+        0,
+        D3D12_DESCRIPTOR_RANGE_FLAG_DATA_STATIC_WHILE_SET_AT_EXECUTE,
+    )];
+    let mut root_parameters = vec![D3D12_ROOT_PARAMETER1 {
+        ParameterType: D3D12_ROOT_PARAMETER_TYPE_32BIT_CONSTANTS,
+        ShaderVisibility: D3D12_SHADER_VISIBILITY_ALL,
+        Anonymous: D3D12_ROOT_PARAMETER1_0 {
+            Constants: D3D12_ROOT_CONSTANTS {
+                ShaderRegister: 0,
+                RegisterSpace: 0,
+                Num32BitValues: num_constants,
+            },
+        },
+    }];
+    if global_constants {
+        root_parameters.push(global_constants_parameter());
+    }
+    if texture_table {
+        root_parameters.push(create_descriptor_table(
+            D3D12_SHADER_VISIBILITY_PIXEL,
+            &texture_range,
+        ));
+    }
+    // The flag is invalid on devices that can't index the heap, passes that run on those don't
+    let flags = if shader_model().supports_dynamic_resources() {
+        D3D12_ROOT_SIGNATURE_FLAG_CBV_SRV_UAV_HEAP_DIRECTLY_INDEXED
+    } else {
+        D3D12_ROOT_SIGNATURE_FLAG_NONE
+    };
+
+    let static_samplers = [D3D12_STATIC_SAMPLER_DESC {
+        Filter: D3D12_FILTER_MIN_MAG_MIP_LINEAR,
+        AddressU: D3D12_TEXTURE_ADDRESS_MODE_CLAMP,
+        AddressV: D3D12_TEXTURE_ADDRESS_MODE_CLAMP,
+        AddressW: D3D12_TEXTURE_ADDRESS_MODE_CLAMP,
+        MipLODBias: 0.0f32,
+        MaxAnisotropy: 0,
+        ComparisonFunc: D3D12_COMPARISON_FUNC_NEVER,
+        BorderColor: D3D12_STATIC_BORDER_COLOR_TRANSPARENT_BLACK,
+        MinLOD: 0.0f32,
+        MaxLOD: D3D12_FLOAT32_MAX,
+        ShaderRegister: 0,
+        RegisterSpace: 0,
+        ShaderVisibility: D3D12_SHADER_VISIBILITY_PIXEL,
+    }];
+
+    let desc = D3D12_ROOT_SIGNATURE_DESC1 {
+        NumParameters: root_parameters.len() as u32,
+        pParameters: root_parameters.as_ptr(),
+        Flags: flags,
+        pStaticSamplers: static_samplers.as_ptr(),
+        NumStaticSamplers: static_samplers.len() as u32,
+    };
+
+    serialize_root_signature(device, &desc)
+}
+
 pub struct CompiledShader {
     pub name: String,
     pub byte_code: Vec<u8>,
 }
 
 impl CompiledShader {
+    /// Byte code compiled ahead of time, e.g. by `include_hlsl!`
+    pub fn from_dxil(name: &str, byte_code: &[u8]) -> Self {
+        Self {
+            name: name.to_string(),
+            byte_code: byte_code.to_vec(),
+        }
+    }
+
     pub fn get_handle(&self) -> D3D12_SHADER_BYTECODE {
         D3D12_SHADER_BYTECODE {
             pShaderBytecode: self.byte_code.as_ptr() as _,
@@ -187,7 +555,13 @@ const SHADER_COMPILE_FLAGS: &[&str] = if cfg!(debug_assertions) {
     &[]
 };
 
-fn compile_shader(filename: &str, entry_point: &str, shader_model: &str) -> Result<CompiledShader> {
+/// Compiles an entry point of an HLSL file at run time. `shader_model` is the oldest target it
+/// works with, e.g. "ps_6_6", it is compiled for the model picked with `set_shader_model`.
+pub fn compile_shader(
+    filename: &str,
+    entry_point: &str,
+    shader_model: &str,
+) -> Result<CompiledShader> {
     let path = std::path::Path::new(filename);
 
     let shader_source = std::fs::read_to_string(path)?;
@@ -198,15 +572,13 @@ fn compile_shader(filename: &str, entry_point: &str, shader_model: &str) -> Resu
         .map(|str| str.to_string())
         .context("Can't convert to string")?;
 
-    let ir = compile_hlsl(
+    let ir = compile_dxil(
         &name,
         &shader_source,
         entry_point,
-        shader_model,
+        &select_target(shader_model)?,
         SHADER_COMPILE_FLAGS,
-        &[],
     )?;
-    validate_dxil(&ir)?;
 
     Ok(CompiledShader {
         name,
@@ -214,69 +586,440 @@ fn compile_shader(filename: &str, entry_point: &str, shader_model: &str) -> Resu
     })
 }
 
+/// `compile_shader` as an `anyhow::Result`, or `include_hlsl!` when d3d12_utils is built with
+/// the `embedded_shaders` feature. Arguments have to be literals for the latter, which compiles
+/// for exactly the given target.
+#[cfg(not(feature = "embedded_shaders"))]
+#[macro_export]
+macro_rules! load_hlsl {
+    ($filename:literal, $entry_point:literal, $shader_model:literal) => {
+        $crate::compile_shader($filename, $entry_point, $shader_model)
+    };
+}
+
+#[cfg(feature = "embedded_shaders")]
+#[macro_export]
+macro_rules! load_hlsl {
+    ($filename:literal, $entry_point:literal, $shader_model:literal) => {
+        ::core::result::Result::<$crate::CompiledShader, ::anyhow::Error>::Ok(
+            $crate::include_hlsl!($filename, $entry_point, $shader_model),
+        )
+    };
+}
+
 pub fn compile_pixel_shader(filename: &str, entry_point: &str) -> Result<CompiledShader> {
-    compile_shader(filename, entry_point, "ps_6_6")
+    compile_shader(filename, entry_point, "ps_6_0")
 }
 
 pub fn compile_vertex_shader(filename: &str, entry_point: &str) -> Result<CompiledShader> {
-    compile_shader(filename, entry_point, "vs_6_6")
+    compile_shader(filename, entry_point, "vs_6_0")
+}
+
+pub fn compile_compute_shader(filename: &str, entry_point: &str) -> Result<CompiledShader> {
+    compile_shader(filename, entry_point, "cs_6_0")
+}
+
+pub fn compile_amplification_shader(filename: &str, entry_point: &str) -> Result<CompiledShader> {
+    compile_shader(filename, entry_point, "as_6_5")
+}
+
+pub fn compile_mesh_shader(filename: &str, entry_point: &str) -> Result<CompiledShader> {
+    compile_shader(filename, entry_point, "ms_6_5")
+}
+
+pub fn create_compute_pipeline_state(
+    device: &ID3D12Device4,
+    root_signature: &ID3D12RootSignature,
+    compute_shader: &CompiledShader,
+) -> Result<ID3D12PipelineState> {
+    let desc = D3D12_COMPUTE_PIPELINE_STATE_DESC {
+        pRootSignature: Some(root_signature.clone()),
+        CS: compute_shader.get_handle(),
+        ..Default::default()
+    };
+
+    let pso = unsafe { device.CreateComputePipelineState(&desc) }?;
+
+    Ok(pso)
+}
+
+fn rasterizer_desc() -> D3D12_RASTERIZER_DESC {
+    D3D12_RASTERIZER_DESC {
+        FillMode: D3D12_FILL_MODE_SOLID,
+        CullMode: D3D12_CULL_MODE_BACK,
+        DepthClipEnable: true.into(),
+        ..Default::default()
+    }
+}
+
+fn blend_desc() -> D3D12_BLEND_DESC {
+    D3D12_BLEND_DESC {
+        AlphaToCoverageEnable: false.into(),
+        IndependentBlendEnable: false.into(),
+        RenderTarget: [
+            D3D12_RENDER_TARGET_BLEND_DESC {
+                BlendEnable: false.into(),
+                LogicOpEnable: false.into(),
+                SrcBlend: D3D12_BLEND_ONE,
+                DestBlend: D3D12_BLEND_ZERO,
+                BlendOp: D3D12_BLEND_OP_ADD,
+                SrcBlendAlpha: D3D12_BLEND_ONE,
+                DestBlendAlpha: D3D12_BLEND_ZERO,
+                BlendOpAlpha: D3D12_BLEND_OP_ADD,
+                LogicOp: D3D12_LOGIC_OP_NOOP,
+                RenderTargetWriteMask: D3D12_COLOR_WRITE_ENABLE_ALL.0 as u8,
+            },
+            D3D12_RENDER_TARGET_BLEND_DESC::default(),
+            D3D12_RENDER_TARGET_BLEND_DESC::default(),
+            D3D12_RENDER_TARGET_BLEND_DESC::default(),
+            D3D12_RENDER_TARGET_BLEND_DESC::default(),
+            D3D12_RENDER_TARGET_BLEND_DESC::default(),
+            D3D12_RENDER_TARGET_BLEND_DESC::default(),
+            D3D12_RENDER_TARGET_BLEND_DESC::default(),
+        ],
+    }
 }
 
+// A subobject of a pipeline state stream, laid out like CD3DX12_PIPELINE_STATE_STREAM_SUBOBJECT
+#[repr(C, align(8))]
+struct StreamSubobject<T> {
+    subobject_type: D3D12_PIPELINE_STATE_SUBOBJECT_TYPE,
+    value: T,
+}
+
+impl<T> StreamSubobject<T> {
+    fn new(subobject_type: D3D12_PIPELINE_STATE_SUBOBJECT_TYPE, value: T) -> Self {
+        Self {
+            subobject_type,
+            value,
+        }
+    }
+}
+
+#[repr(C)]
+struct GraphicsPipelineStateStream {
+    root_signature: StreamSubobject<Option<ID3D12RootSignature>>,
+    input_layout: StreamSubobject<D3D12_INPUT_LAYOUT_DESC>,
+    primitive_topology: StreamSubobject<D3D12_PRIMITIVE_TOPOLOGY_TYPE>,
+    vertex_shader: StreamSubobject<D3D12_SHADER_BYTECODE>,
+    pixel_shader: StreamSubobject<D3D12_SHADER_BYTECODE>,
+    rasterizer: StreamSubobject<D3D12_RASTERIZER_DESC>,
+    blend: StreamSubobject<D3D12_BLEND_DESC>,
+    depth_stencil: StreamSubobject<D3D12_DEPTH_STENCIL_DESC1>,
+    depth_stencil_format: StreamSubobject<DXGI_FORMAT>,
+    render_target_formats: StreamSubobject<D3D12_RT_FORMAT_ARRAY>,
+    sample_desc: StreamSubobject<DXGI_SAMPLE_DESC>,
+    sample_mask: StreamSubobject<u32>,
+}
+
+fn ensure_depth_stencil_supported(
+    device: &ID3D12Device4,
+    depth_stencil: &DepthStencilState,
+) -> Result<()> {
+    if depth_stencil.depth_bounds_test {
+        ensure!(
+            depth_bounds_supported(device)?,
+            "The device does not support the depth bounds test"
+        );
+    }
+
+    Ok(())
+}
+
+/// Graphics pipeline with vertex input, drawing primitives of `topology_type` into targets of
+/// `formats`
+#[allow(clippy::too_many_arguments)]
 pub fn create_pipeline_state(
     device: &ID3D12Device4,
     root_signature: &ID3D12RootSignature,
     input_element_descs: &[D3D12_INPUT_ELEMENT_DESC],
     vertex_shader: &CompiledShader,
     pixel_shader: &CompiledShader,
-    num_render_targets: u32,
+    formats: &TargetFormats,
+    topology_type: D3D12_PRIMITIVE_TOPOLOGY_TYPE,
+    depth_stencil: &DepthStencilState,
 ) -> Result<ID3D12PipelineState> {
-    let stencil_op = D3D12_DEPTH_STENCILOP_DESC {
-        StencilFailOp: D3D12_STENCIL_OP_KEEP,
-        StencilDepthFailOp: D3D12_STENCIL_OP_KEEP,
-        StencilPassOp: D3D12_STENCIL_OP_KEEP,
-        StencilFunc: D3D12_COMPARISON_FUNC_ALWAYS,
+    validate_input_layout(vertex_shader, input_element_descs)?;
+    ensure_depth_stencil_supported(device, depth_stencil)?;
+
+    let mut stream = GraphicsPipelineStateStream {
+        root_signature: StreamSubobject::new(
+            D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_ROOT_SIGNATURE,
+            Some(root_signature.clone()),
+        ),
+        input_layout: StreamSubobject::new(
+            D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_INPUT_LAYOUT,
+            D3D12_INPUT_LAYOUT_DESC {
+                pInputElementDescs: input_element_descs.as_ptr(),
+                NumElements: input_element_descs.len() as u32,
+            },
+        ),
+        primitive_topology: StreamSubobject::new(
+            D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_PRIMITIVE_TOPOLOGY,
+            topology_type,
+        ),
+        vertex_shader: StreamSubobject::new(
+            D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_VS,
+            vertex_shader.get_handle(),
+        ),
+        pixel_shader: StreamSubobject::new(
+            D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_PS,
+            pixel_shader.get_handle(),
+        ),
+        rasterizer: StreamSubobject::new(
+            D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_RASTERIZER,
+            rasterizer_desc(),
+        ),
+        blend: StreamSubobject::new(D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_BLEND, blend_desc()),
+        depth_stencil: StreamSubobject::new(
+            D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_DEPTH_STENCIL1,
+            depth_stencil.desc1(),
+        ),
+        depth_stencil_format: StreamSubobject::new(
+            D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_DEPTH_STENCIL_FORMAT,
+            formats.depth,
+        ),
+        render_target_formats: StreamSubobject::new(
+            D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_RENDER_TARGET_FORMATS,
+            formats.rt_format_array(),
+        ),
+        sample_desc: StreamSubobject::new(
+            D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_SAMPLE_DESC,
+            DXGI_SAMPLE_DESC {
+                Count: 1,
+                ..Default::default()
+            },
+        ),
+        sample_mask: StreamSubobject::new(
+            D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_SAMPLE_MASK,
+            u32::MAX,
+        ),
     };
-    let depth_stencil_desc = D3D12_DEPTH_STENCIL_DESC {
-        DepthEnable: true.into(),
-        DepthWriteMask: D3D12_DEPTH_WRITE_MASK_ALL,
-        DepthFunc: D3D12_COMPARISON_FUNC_LESS,
-        StencilEnable: false.into(),
-        FrontFace: stencil_op,
-        BackFace: stencil_op,
-        StencilReadMask: D3D12_DEFAULT_STENCIL_READ_MASK as u8,
-        StencilWriteMask: D3D12_DEFAULT_STENCIL_READ_MASK as u8,
+
+    let desc = D3D12_PIPELINE_STATE_STREAM_DESC {
+        SizeInBytes: std::mem::size_of_val(&stream),
+        pPipelineStateSubobjectStream: std::ptr::addr_of_mut!(stream) as *mut _,
     };
+    let pso = unsafe { device.CreatePipelineState(&desc) }?;
 
-    let mut desc = D3D12_GRAPHICS_PIPELINE_STATE_DESC {
-        InputLayout: D3D12_INPUT_LAYOUT_DESC {
-            pInputElementDescs: input_element_descs.as_ptr(),
-            NumElements: input_element_descs.len() as u32,
+    Ok(pso)
+}
+
+#[repr(C)]
+struct MeshPipelineStateStream {
+    root_signature: StreamSubobject<Option<ID3D12RootSignature>>,
+    amplification_shader: StreamSubobject<D3D12_SHADER_BYTECODE>,
+    mesh_shader: StreamSubobject<D3D12_SHADER_BYTECODE>,
+    pixel_shader: StreamSubobject<D3D12_SHADER_BYTECODE>,
+    rasterizer: StreamSubobject<D3D12_RASTERIZER_DESC>,
+    blend: StreamSubobject<D3D12_BLEND_DESC>,
+    depth_stencil: StreamSubobject<D3D12_DEPTH_STENCIL_DESC1>,
+    depth_stencil_format: StreamSubobject<DXGI_FORMAT>,
+    render_target_formats: StreamSubobject<D3D12_RT_FORMAT_ARRAY>,
+    sample_desc: StreamSubobject<DXGI_SAMPLE_DESC>,
+    sample_mask: StreamSubobject<u32>,
+}
+
+/// Same fixed function state as `create_pipeline_state`, with an amplification and mesh shader
+/// in place of the input assembler and vertex shader
+pub fn create_mesh_pipeline_state(
+    device: &ID3D12Device4,
+    root_signature: &ID3D12RootSignature,
+    amplification_shader: Option<&CompiledShader>,
+    mesh_shader: &CompiledShader,
+    pixel_shader: &CompiledShader,
+    formats: &TargetFormats,
+    depth_stencil: &DepthStencilState,
+) -> Result<ID3D12PipelineState> {
+    ensure_depth_stencil_supported(device, depth_stencil)?;
+
+    let mut stream = MeshPipelineStateStream {
+        root_signature: StreamSubobject::new(
+            D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_ROOT_SIGNATURE,
+            Some(root_signature.clone()),
+        ),
+        amplification_shader: StreamSubobject::new(
+            D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_AS,
+            amplification_shader
+                .map(CompiledShader::get_handle)
+                .unwrap_or_default(),
+        ),
+        mesh_shader: StreamSubobject::new(
+            D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_MS,
+            mesh_shader.get_handle(),
+        ),
+        pixel_shader: StreamSubobject::new(
+            D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_PS,
+            pixel_shader.get_handle(),
+        ),
+        rasterizer: StreamSubobject::new(
+            D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_RASTERIZER,
+            rasterizer_desc(),
+        ),
+        blend: StreamSubobject::new(D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_BLEND, blend_desc()),
+        depth_stencil: StreamSubobject::new(
+            D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_DEPTH_STENCIL1,
+            depth_stencil.desc1(),
+        ),
+        depth_stencil_format: StreamSubobject::new(
+            D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_DEPTH_STENCIL_FORMAT,
+            formats.depth,
+        ),
+        render_target_formats: StreamSubobject::new(
+            D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_RENDER_TARGET_FORMATS,
+            formats.rt_format_array(),
+        ),
+        sample_desc: StreamSubobject::new(
+            D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_SAMPLE_DESC,
+            DXGI_SAMPLE_DESC {
+                Count: 1,
+                ..Default::default()
+            },
+        ),
+        sample_mask: StreamSubobject::new(
+            D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_SAMPLE_MASK,
+            u32::MAX,
+        ),
+    };
+
+    let desc = D3D12_PIPELINE_STATE_STREAM_DESC {
+        SizeInBytes: std::mem::size_of_val(&stream),
+        pPipelineStateSubobjectStream: std::ptr::addr_of_mut!(stream) as *mut _,
+    };
+    let pso = unsafe { device.CreatePipelineState(&desc) }?;
+
+    Ok(pso)
+}
+
+/// The sRGB variant of an 8 bit UNORM format. Views in it decode to linear when read and encode
+/// when written and blended, swap chain back buffers allow it for their render target views.
+pub fn srgb_format(format: DXGI_FORMAT) -> Option<DXGI_FORMAT> {
+    match format {
+        DXGI_FORMAT_R8G8B8A8_UNORM | DXGI_FORMAT_R8G8B8A8_UNORM_SRGB => {
+            Some(DXGI_FORMAT_R8G8B8A8_UNORM_SRGB)
+        }
+        DXGI_FORMAT_B8G8R8A8_UNORM | DXGI_FORMAT_B8G8R8A8_UNORM_SRGB => {
+            Some(DXGI_FORMAT_B8G8R8A8_UNORM_SRGB)
+        }
+        DXGI_FORMAT_B8G8R8X8_UNORM | DXGI_FORMAT_B8G8R8X8_UNORM_SRGB => {
+            Some(DXGI_FORMAT_B8G8R8X8_UNORM_SRGB)
+        }
+        DXGI_FORMAT_BC1_UNORM | DXGI_FORMAT_BC1_UNORM_SRGB => Some(DXGI_FORMAT_BC1_UNORM_SRGB),
+        DXGI_FORMAT_BC2_UNORM | DXGI_FORMAT_BC2_UNORM_SRGB => Some(DXGI_FORMAT_BC2_UNORM_SRGB),
+        DXGI_FORMAT_BC3_UNORM | DXGI_FORMAT_BC3_UNORM_SRGB => Some(DXGI_FORMAT_BC3_UNORM_SRGB),
+        DXGI_FORMAT_BC7_UNORM | DXGI_FORMAT_BC7_UNORM_SRGB => Some(DXGI_FORMAT_BC7_UNORM_SRGB),
+        _ => None,
+    }
+}
+
+/// Size of a single texel for uncompressed formats, None for block compressed or unknown formats
+pub fn format_bytes_per_pixel(format: DXGI_FORMAT) -> Option<usize> {
+    match format {
+        DXGI_FORMAT_R8_UNORM | DXGI_FORMAT_R8_UINT | DXGI_FORMAT_A8_UNORM => Some(1),
+        DXGI_FORMAT_R8G8_UNORM | DXGI_FORMAT_R16_FLOAT | DXGI_FORMAT_R16_UNORM => Some(2),
+        DXGI_FORMAT_R8G8B8A8_UNORM
+        | DXGI_FORMAT_R8G8B8A8_UNORM_SRGB
+        | DXGI_FORMAT_B8G8R8A8_UNORM
+        | DXGI_FORMAT_B8G8R8A8_UNORM_SRGB
+        | DXGI_FORMAT_R10G10B10A2_UNORM
+        | DXGI_FORMAT_R11G11B10_FLOAT
+        | DXGI_FORMAT_R16G16_FLOAT
+        | DXGI_FORMAT_R32_FLOAT
+        | DXGI_FORMAT_R32_UINT
+        | DXGI_FORMAT_D32_FLOAT => Some(4),
+        DXGI_FORMAT_R16G16B16A16_FLOAT | DXGI_FORMAT_R32G32_FLOAT => Some(8),
+        DXGI_FORMAT_R32G32B32A32_FLOAT => Some(16),
+        _ => None,
+    }
+}
+
+/// Pipeline for a fullscreen triangle generated from SV_VertexID, no vertex input and no depth
+pub fn create_fullscreen_pipeline_state(
+    device: &ID3D12Device4,
+    root_signature: &ID3D12RootSignature,
+    vertex_shader: &CompiledShader,
+    pixel_shader: &CompiledShader,
+    render_target_format: DXGI_FORMAT,
+) -> Result<ID3D12PipelineState> {
+    fullscreen_pipeline_state(
+        device,
+        root_signature,
+        vertex_shader,
+        pixel_shader,
+        render_target_format,
+        blend_desc().RenderTarget[0],
+        D3D12_PRIMITIVE_TOPOLOGY_TYPE_TRIANGLE,
+    )
+}
+
+/// `create_fullscreen_pipeline_state` for line lists, e.g. debug lines drawn on top of everything
+pub fn create_line_pipeline_state(
+    device: &ID3D12Device4,
+    root_signature: &ID3D12RootSignature,
+    vertex_shader: &CompiledShader,
+    pixel_shader: &CompiledShader,
+    render_target_format: DXGI_FORMAT,
+) -> Result<ID3D12PipelineState> {
+    fullscreen_pipeline_state(
+        device,
+        root_signature,
+        vertex_shader,
+        pixel_shader,
+        render_target_format,
+        blend_desc().RenderTarget[0],
+        D3D12_PRIMITIVE_TOPOLOGY_TYPE_LINE,
+    )
+}
+
+/// `create_fullscreen_pipeline_state` blending premultiplied alpha over the target, e.g. for
+/// compositing overlays
+pub fn create_composite_pipeline_state(
+    device: &ID3D12Device4,
+    root_signature: &ID3D12RootSignature,
+    vertex_shader: &CompiledShader,
+    pixel_shader: &CompiledShader,
+    render_target_format: DXGI_FORMAT,
+) -> Result<ID3D12PipelineState> {
+    fullscreen_pipeline_state(
+        device,
+        root_signature,
+        vertex_shader,
+        pixel_shader,
+        render_target_format,
+        D3D12_RENDER_TARGET_BLEND_DESC {
+            BlendEnable: true.into(),
+            SrcBlend: D3D12_BLEND_ONE,
+            DestBlend: D3D12_BLEND_INV_SRC_ALPHA,
+            SrcBlendAlpha: D3D12_BLEND_ONE,
+            DestBlendAlpha: D3D12_BLEND_INV_SRC_ALPHA,
+            ..blend_desc().RenderTarget[0]
         },
+        D3D12_PRIMITIVE_TOPOLOGY_TYPE_TRIANGLE,
+    )
+}
+
+fn fullscreen_pipeline_state(
+    device: &ID3D12Device4,
+    root_signature: &ID3D12RootSignature,
+    vertex_shader: &CompiledShader,
+    pixel_shader: &CompiledShader,
+    render_target_format: DXGI_FORMAT,
+    blend: D3D12_RENDER_TARGET_BLEND_DESC,
+    topology_type: D3D12_PRIMITIVE_TOPOLOGY_TYPE,
+) -> Result<ID3D12PipelineState> {
+    let mut desc = D3D12_GRAPHICS_PIPELINE_STATE_DESC {
         pRootSignature: Some(root_signature.clone()),
         VS: vertex_shader.get_handle(),
         PS: pixel_shader.get_handle(),
         RasterizerState: D3D12_RASTERIZER_DESC {
             FillMode: D3D12_FILL_MODE_SOLID,
-            CullMode: D3D12_CULL_MODE_BACK,
+            CullMode: D3D12_CULL_MODE_NONE,
             DepthClipEnable: true.into(),
             ..Default::default()
         },
         BlendState: D3D12_BLEND_DESC {
-            AlphaToCoverageEnable: false.into(),
-            IndependentBlendEnable: false.into(),
             RenderTarget: [
-                D3D12_RENDER_TARGET_BLEND_DESC {
-                    BlendEnable: false.into(),
-                    LogicOpEnable: false.into(),
-                    SrcBlend: D3D12_BLEND_ONE,
-                    DestBlend: D3D12_BLEND_ZERO,
-                    BlendOp: D3D12_BLEND_OP_ADD,
-                    SrcBlendAlpha: D3D12_BLEND_ONE,
-                    DestBlendAlpha: D3D12_BLEND_ZERO,
-                    BlendOpAlpha: D3D12_BLEND_OP_ADD,
-                    LogicOp: D3D12_LOGIC_OP_NOOP,
-                    RenderTargetWriteMask: D3D12_COLOR_WRITE_ENABLE_ALL.0 as u8,
-                },
+                blend,
                 D3D12_RENDER_TARGET_BLEND_DESC::default(),
                 D3D12_RENDER_TARGET_BLEND_DESC::default(),
                 D3D12_RENDER_TARGET_BLEND_DESC::default(),
@@ -285,21 +1028,70 @@ pub fn create_pipeline_state(
                 D3D12_RENDER_TARGET_BLEND_DESC::default(),
                 D3D12_RENDER_TARGET_BLEND_DESC::default(),
             ],
+            ..blend_desc()
+        },
+        DepthStencilState: D3D12_DEPTH_STENCIL_DESC {
+            DepthEnable: false.into(),
+            StencilEnable: false.into(),
+            ..Default::default()
         },
-        DepthStencilState: depth_stencil_desc,
-        DSVFormat: DXGI_FORMAT_D32_FLOAT,
         SampleMask: u32::MAX,
-        PrimitiveTopologyType: D3D12_PRIMITIVE_TOPOLOGY_TYPE_TRIANGLE,
-        NumRenderTargets: num_render_targets,
+        PrimitiveTopologyType: topology_type,
+        NumRenderTargets: 1,
+        SampleDesc: DXGI_SAMPLE_DESC {
+            Count: 1,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    desc.RTVFormats[0] = render_target_format;
+
+    let pso = unsafe { device.CreateGraphicsPipelineState(&desc) }?;
+
+    Ok(pso)
+}
+
+/// Pipeline for alpha blended helper geometry generated from SV_VertexID, e.g. grids and gizmos.
+/// Depth tested against the scene but never written, so it stays out of later depth reads.
+pub fn create_overlay_pipeline_state(
+    device: &ID3D12Device4,
+    root_signature: &ID3D12RootSignature,
+    vertex_shader: &CompiledShader,
+    pixel_shader: &CompiledShader,
+    formats: &TargetFormats,
+    topology_type: D3D12_PRIMITIVE_TOPOLOGY_TYPE,
+    depth_range: DepthRange,
+) -> Result<ID3D12PipelineState> {
+    let mut blend = blend_desc();
+    blend.RenderTarget[0] = D3D12_RENDER_TARGET_BLEND_DESC {
+        BlendEnable: true.into(),
+        SrcBlend: D3D12_BLEND_SRC_ALPHA,
+        DestBlend: D3D12_BLEND_INV_SRC_ALPHA,
+        ..blend.RenderTarget[0]
+    };
+
+    let rt_formats = formats.rt_format_array();
+    let desc = D3D12_GRAPHICS_PIPELINE_STATE_DESC {
+        pRootSignature: Some(root_signature.clone()),
+        VS: vertex_shader.get_handle(),
+        PS: pixel_shader.get_handle(),
+        RasterizerState: D3D12_RASTERIZER_DESC {
+            CullMode: D3D12_CULL_MODE_NONE,
+            ..rasterizer_desc()
+        },
+        BlendState: blend,
+        DepthStencilState: DepthStencilState::read_only(depth_range).desc(),
+        DSVFormat: formats.depth,
+        SampleMask: u32::MAX,
+        PrimitiveTopologyType: topology_type,
+        NumRenderTargets: rt_formats.NumRenderTargets,
+        RTVFormats: rt_formats.RTFormats,
         SampleDesc: DXGI_SAMPLE_DESC {
             Count: 1,
             ..Default::default()
         },
         ..Default::default()
     };
-    for i in 0..num_render_targets as usize {
-        desc.RTVFormats[i] = DXGI_FORMAT_R8G8B8A8_UNORM;
-    }
 
     let pso = unsafe { device.CreateGraphicsPipelineState(&desc) }?;
 
@@ -333,94 +1125,83 @@ pub fn transition_barrier(
     }
 }
 
-pub fn create_swapchain(
-    hwnd: HWND,
-    dxgi_factory: &IDXGIFactory5,
-    graphics_queue: &CommandQueue,
-    buffer_count: u32,
-    format: DXGI_FORMAT,
-    extent: (u32, u32),
-) -> Result<IDXGISwapChain3> {
-    let (width, height) = extent;
-
-    let swap_chain_desc = DXGI_SWAP_CHAIN_DESC1 {
-        BufferCount: buffer_count,
-        Width: width,
-        Height: height,
-        Format: format,
-        BufferUsage: DXGI_USAGE_RENDER_TARGET_OUTPUT,
-        SwapEffect: DXGI_SWAP_EFFECT_FLIP_DISCARD,
-        SampleDesc: DXGI_SAMPLE_DESC {
-            Count: 1,
-            ..Default::default()
+/// Makes later work wait for unordered access to `resource` to finish, or to every resource with
+/// None
+pub fn uav_barrier(resource: Option<&ID3D12Resource>) -> D3D12_RESOURCE_BARRIER {
+    D3D12_RESOURCE_BARRIER {
+        Type: D3D12_RESOURCE_BARRIER_TYPE_UAV,
+        Flags: D3D12_RESOURCE_BARRIER_FLAG_NONE,
+        Anonymous: D3D12_RESOURCE_BARRIER_0 {
+            UAV: std::mem::ManuallyDrop::new(D3D12_RESOURCE_UAV_BARRIER {
+                pResource: resource.cloned(),
+            }),
         },
-        ..Default::default()
-    };
-
-    let swap_chain: IDXGISwapChain3 = unsafe {
-        dxgi_factory.CreateSwapChainForHwnd(
-            &graphics_queue.queue,
-            hwnd,
-            &swap_chain_desc,
-            std::ptr::null_mut(),
-            None,
-        )?
     }
-    .cast()?;
-
-    Ok(swap_chain)
 }
 
-pub fn get_swapchain_render_targets<const N: usize>(
-    device: &ID3D12Device4,
-    rtv_handles: &[D3D12_CPU_DESCRIPTOR_HANDLE; N],
-    swap_chain: &IDXGISwapChain3,
-) -> Result<Vec<ID3D12Resource>> {
-    Ok((0..N)
-        .filter_map(|i: usize| {
-            let render_target: ID3D12Resource = unsafe { swap_chain.GetBuffer(i as u32) }.ok()?;
-            unsafe {
-                render_target
-                    .SetName(PCWSTR::from(&format!("Backbuffer {}", i).into()))
-                    .ok()?;
-            }
-            unsafe {
-                device.CreateRenderTargetView(&render_target, std::ptr::null(), rtv_handles[i]);
-            }
-
-            Some(render_target)
+/// Writes each value to its GPU address from the command list itself. The buffers written to
+/// have to be in the copy destination state.
+pub fn write_buffer_immediate(
+    command_list: &ID3D12GraphicsCommandList,
+    values: &[(u64, u32)],
+    mode: D3D12_WRITEBUFFERIMMEDIATE_MODE,
+) -> Result<()> {
+    let command_list: ID3D12GraphicsCommandList2 = command_list
+        .cast()
+        .context("Writing buffers immediately needs ID3D12GraphicsCommandList2")?;
+    let parameters: Vec<_> = values
+        .iter()
+        .map(|&(address, value)| D3D12_WRITEBUFFERIMMEDIATE_PARAMETER {
+            Dest: address,
+            Value: value,
         })
-        .collect())
-}
+        .collect();
+    let modes = vec![mode; values.len()];
 
-pub fn resize_swapchain<const N: usize>(
-    device: &ID3D12Device4,
-    swap_chain: &IDXGISwapChain3,
-    extent: (u32, u32),
-    rtv_handles: &[D3D12_CPU_DESCRIPTOR_HANDLE; N],
-) -> Result<(Vec<ID3D12Resource>, D3D12_VIEWPORT, RECT)> {
-    let (width, height) = extent;
     unsafe {
-        swap_chain.ResizeBuffers(N as u32, width, height, DXGI_FORMAT_UNKNOWN, 0)?;
+        command_list.WriteBufferImmediate(
+            parameters.len() as u32,
+            parameters.as_ptr(),
+            modes.as_ptr(),
+        );
     }
 
-    let render_targets = get_swapchain_render_targets(device, rtv_handles, swap_chain)?;
-
-    let viewport = D3D12_VIEWPORT {
-        TopLeftX: 0.0,
-        TopLeftY: 0.0,
-        Width: width as f32,
-        Height: height as f32,
-        MinDepth: D3D12_MIN_DEPTH,
-        MaxDepth: D3D12_MAX_DEPTH,
-    };
+    Ok(())
+}
 
-    let scissor_rect = RECT {
-        left: 0,
-        top: 0,
-        right: width as i32,
-        bottom: height as i32,
-    };
+/// Hands the memory shared by placed resources from `before` to `after`. None on either side
+/// stands for any resource in the heap.
+pub fn aliasing_barrier(
+    before: Option<&ID3D12Resource>,
+    after: Option<&ID3D12Resource>,
+) -> D3D12_RESOURCE_BARRIER {
+    D3D12_RESOURCE_BARRIER {
+        Type: D3D12_RESOURCE_BARRIER_TYPE_ALIASING,
+        Flags: D3D12_RESOURCE_BARRIER_FLAG_NONE,
+        Anonymous: D3D12_RESOURCE_BARRIER_0 {
+            Aliasing: std::mem::ManuallyDrop::new(D3D12_RESOURCE_ALIASING_BARRIER {
+                pResourceBefore: before.cloned(),
+                pResourceAfter: after.cloned(),
+            }),
+        },
+    }
+}
 
-    Ok((render_targets, viewport, scissor_rect))
+/// Drops the resource references of a barrier built by one of the functions above, once it has
+/// been recorded
+pub fn release_barrier(barrier: D3D12_RESOURCE_BARRIER) {
+    unsafe {
+        match barrier.Type {
+            D3D12_RESOURCE_BARRIER_TYPE_TRANSITION => {
+                std::mem::ManuallyDrop::into_inner(barrier.Anonymous.Transition);
+            }
+            D3D12_RESOURCE_BARRIER_TYPE_ALIASING => {
+                std::mem::ManuallyDrop::into_inner(barrier.Anonymous.Aliasing);
+            }
+            D3D12_RESOURCE_BARRIER_TYPE_UAV => {
+                std::mem::ManuallyDrop::into_inner(barrier.Anonymous.UAV);
+            }
+            _ => (),
+        }
+    }
 }
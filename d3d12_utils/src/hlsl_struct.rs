@@ -0,0 +1,242 @@
+use glam::{IVec2, IVec3, IVec4, Mat4, UVec2, UVec3, UVec4, Vec2, Vec3, Vec4};
+
+// Constant buffers are packed into registers of four 32 bit values
+const REGISTER_SIZE: usize = 16;
+
+/// A Rust type with an HLSL counterpart of the same size
+pub trait HlslType {
+    /// e.g. "float4x4"
+    const NAME: &'static str;
+    /// Elements of an array, 0 for anything else
+    const ARRAY_LEN: usize = 0;
+}
+
+macro_rules! impl_hlsl_type {
+    ($($ty:ty => $name:literal),* $(,)?) => {
+        $(impl HlslType for $ty {
+            const NAME: &'static str = $name;
+        })*
+    };
+}
+
+impl_hlsl_type! {
+    f32 => "float",
+    u32 => "uint",
+    i32 => "int",
+    Vec2 => "float2",
+    Vec3 => "float3",
+    Vec4 => "float4",
+    UVec2 => "uint2",
+    UVec3 => "uint3",
+    UVec4 => "uint4",
+    IVec2 => "int2",
+    IVec3 => "int3",
+    IVec4 => "int4",
+    Mat4 => "float4x4",
+    [f32; 2] => "float2",
+    [f32; 3] => "float3",
+    [f32; 4] => "float4",
+    [u32; 2] => "uint2",
+    [u32; 3] => "uint3",
+    [u32; 4] => "uint4",
+}
+
+// Only arrays of whole registers, HLSL pads every element of other arrays to one
+macro_rules! impl_hlsl_array {
+    ($($ty:ty),*) => {
+        $(impl<const N: usize> HlslType for [$ty; N] {
+            const NAME: &'static str = <$ty as HlslType>::NAME;
+            const ARRAY_LEN: usize = N;
+        })*
+    };
+}
+
+impl_hlsl_array!(Vec4, UVec4, IVec4, Mat4);
+
+/// A field of an `HlslStruct` and where it is in the Rust struct
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HlslField {
+    pub name: &'static str,
+    pub hlsl_type: &'static str,
+    pub array_len: usize,
+    pub offset: usize,
+    pub size: usize,
+}
+
+/// A struct shared with shaders, implemented by `hlsl_struct!`
+pub trait HlslStruct {
+    const NAME: &'static str;
+    const FIELDS: &'static [HlslField];
+}
+
+/// Declares a `#[repr(C)]` struct along with its HLSL counterpart, which `hlsl_struct_definition`
+/// and `hlsl_cbuffer` write out. Fails to compile when HLSL would pack the fields of a constant
+/// buffer at other offsets than Rust lays them out, e.g. a `float3` straddling two registers.
+#[macro_export]
+macro_rules! hlsl_struct {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $($(#[$field_meta:meta])* $field_vis:vis $field:ident : $ty:ty),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[repr(C)]
+        $vis struct $name {
+            $($(#[$field_meta])* $field_vis $field: $ty),*
+        }
+
+        impl $crate::HlslStruct for $name {
+            const NAME: &'static str = stringify!($name);
+            const FIELDS: &'static [$crate::HlslField] = &[$($crate::HlslField {
+                name: stringify!($field),
+                hlsl_type: <$ty as $crate::HlslType>::NAME,
+                array_len: <$ty as $crate::HlslType>::ARRAY_LEN,
+                offset: ::std::mem::offset_of!($name, $field),
+                size: ::std::mem::size_of::<$ty>(),
+            }),*];
+        }
+
+        const _: () = assert!(
+            $crate::matches_constant_buffer_packing(
+                <$name as $crate::HlslStruct>::FIELDS,
+                ::std::mem::size_of::<$name>(),
+            ),
+            concat!(
+                stringify!($name),
+                " is laid out differently than HLSL packs constant buffers"
+            )
+        );
+    };
+}
+
+/// Whether HLSL packs `fields` into a constant buffer at the offsets they have in a Rust struct
+/// of `size` bytes. Fields can't straddle a register and arrays start a new one.
+pub const fn matches_constant_buffer_packing(fields: &[HlslField], size: usize) -> bool {
+    let mut end: usize = 0;
+    let mut i = 0;
+    while i < fields.len() {
+        let field = &fields[i];
+        let start = match field.size.checked_div(field.array_len) {
+            Some(element_size) => {
+                if !element_size.is_multiple_of(REGISTER_SIZE) {
+                    return false;
+                }
+                end.next_multiple_of(REGISTER_SIZE)
+            }
+            None if end % REGISTER_SIZE + field.size > REGISTER_SIZE => {
+                end.next_multiple_of(REGISTER_SIZE)
+            }
+            None => end,
+        };
+
+        if field.offset != start {
+            return false;
+        }
+        end = start + field.size;
+        i += 1;
+    }
+
+    // Rust may pad the end up to its alignment, HLSL rounds up to a whole register
+    size >= end && size <= end.next_multiple_of(REGISTER_SIZE)
+}
+
+fn field_declarations<T: HlslStruct>() -> String {
+    T::FIELDS
+        .iter()
+        .map(|field| {
+            if field.array_len > 0 {
+                format!(
+                    "    {} {}[{}];\n",
+                    field.hlsl_type, field.name, field.array_len
+                )
+            } else {
+                format!("    {} {};\n", field.hlsl_type, field.name)
+            }
+        })
+        .collect()
+}
+
+/// `struct` declaration of `T`, e.g. for `ConstantBuffer<T>` or structured buffers
+pub fn hlsl_struct_definition<T: HlslStruct>() -> String {
+    format!("struct {}\n{{\n{}}};\n", T::NAME, field_declarations::<T>())
+}
+
+/// A constant buffer at register `b<register>` whose fields are those of `T`, so shaders use
+/// them without a prefix
+pub fn hlsl_cbuffer<T: HlslStruct>(name: &str, register: u32) -> String {
+    format!(
+        "// {} bytes, matches {}\ncbuffer {} : register(b{}) {{\n{}}}\n",
+        std::mem::size_of::<T>(),
+        T::NAME,
+        name,
+        register,
+        field_declarations::<T>()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    crate::hlsl_struct! {
+        #[derive(Debug, Clone, Copy)]
+        struct TestConstants {
+            transform: Mat4,
+            planes: [Vec4; 2],
+            position: Vec3,
+            count: u32,
+            size: Vec2,
+        }
+    }
+
+    fn field(offset: usize, size: usize, array_len: usize) -> HlslField {
+        HlslField {
+            name: "field",
+            hlsl_type: "float",
+            array_len,
+            offset,
+            size,
+        }
+    }
+
+    #[test]
+    fn writes_matching_declarations() {
+        assert_eq!(
+            hlsl_struct_definition::<TestConstants>(),
+            "struct TestConstants\n{\n    float4x4 transform;\n    float4 planes[2];\n    \
+            float3 position;\n    uint count;\n    float2 size;\n};\n"
+        );
+        assert!(hlsl_cbuffer::<TestConstants>("Test", 2)
+            .starts_with("// 128 bytes, matches TestConstants\ncbuffer Test : register(b2) {\n"));
+    }
+
+    #[test]
+    fn rejects_what_hlsl_packs_differently() {
+        // A float3 after a float2 starts a new register in HLSL, Rust puts it right after
+        assert!(!matches_constant_buffer_packing(
+            &[field(0, 8, 0), field(8, 12, 0), field(20, 4, 0)],
+            24
+        ));
+        assert!(matches_constant_buffer_packing(
+            &[field(0, 8, 0), field(16, 12, 0), field(28, 4, 0)],
+            32
+        ));
+        // After a float it still fits
+        assert!(matches_constant_buffer_packing(
+            &[field(0, 4, 0), field(4, 12, 0)],
+            16
+        ));
+
+        // Every array element takes a whole register
+        assert!(!matches_constant_buffer_packing(&[field(0, 8, 2)], 8));
+        assert!(!matches_constant_buffer_packing(
+            &[field(0, 4, 0), field(4, 32, 2)],
+            36
+        ));
+
+        // Trailing padding of up to a register is fine, more means a field is missing
+        assert!(matches_constant_buffer_packing(&[field(0, 12, 0)], 16));
+        assert!(!matches_constant_buffer_packing(&[field(0, 12, 0)], 32));
+    }
+}
@@ -0,0 +1,384 @@
+use anyhow::{bail, ensure, Result};
+use glam::{Vec3, Vec4};
+use windows::Win32::Graphics::Dxgi::Common::*;
+
+use crate::{f32_to_f16, DepthRange};
+
+// Channels of an EXR file are stored in alphabetical order
+const EXR_CHANNELS: [(&str, usize); 4] = [("A", 3), ("B", 2), ("G", 1), ("R", 0)];
+const EXR_HALF: i32 = 1;
+
+/// The file format float textures are saved in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HdrImageFormat {
+    #[default]
+    Exr,
+    /// Radiance RGBE, which drops alpha and negative values
+    Hdr,
+}
+
+impl std::str::FromStr for HdrImageFormat {
+    type Err = anyhow::Error;
+
+    /// "exr" or "hdr"
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "exr" => Ok(Self::Exr),
+            "hdr" => Ok(Self::Hdr),
+            _ => bail!("Expected \"exr\" or \"hdr\", got \"{}\"", s),
+        }
+    }
+}
+
+/// How a texture is written to disk for inspection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageExport {
+    /// 8 bit RGBA PNG, values are clamped to 0 to 1
+    Png,
+    /// Grayscale PNG with near depths white and far ones black, stretched over the depths in the
+    /// image so the little precision left far away still shows
+    DepthPng(DepthRange),
+    /// Half float RGBA EXR, keeping values outside 0 to 1
+    Exr,
+    /// Radiance HDR, keeping values above 1
+    Hdr,
+}
+
+impl ImageExport {
+    /// Depth buffers become grayscale, float formats `hdr_format` and everything else PNG
+    pub fn for_format(
+        format: DXGI_FORMAT,
+        depth_range: DepthRange,
+        hdr_format: HdrImageFormat,
+    ) -> Self {
+        match format {
+            DXGI_FORMAT_D32_FLOAT
+            | DXGI_FORMAT_D32_FLOAT_S8X24_UINT
+            | DXGI_FORMAT_D24_UNORM_S8_UINT
+            | DXGI_FORMAT_D16_UNORM => ImageExport::DepthPng(depth_range),
+            DXGI_FORMAT_R32G32B32A32_FLOAT
+            | DXGI_FORMAT_R32G32B32_FLOAT
+            | DXGI_FORMAT_R32G32_FLOAT
+            | DXGI_FORMAT_R32_FLOAT
+            | DXGI_FORMAT_R16G16B16A16_FLOAT
+            | DXGI_FORMAT_R16G16_FLOAT
+            | DXGI_FORMAT_R16_FLOAT
+            | DXGI_FORMAT_R11G11B10_FLOAT => match hdr_format {
+                HdrImageFormat::Exr => ImageExport::Exr,
+                HdrImageFormat::Hdr => ImageExport::Hdr,
+            },
+            _ => ImageExport::Png,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageExport::Png | ImageExport::DepthPng(_) => "png",
+            ImageExport::Exr => "exr",
+            ImageExport::Hdr => "hdr",
+        }
+    }
+
+    /// Encodes `texels`, rows top to bottom, into the file contents
+    pub fn encode(&self, width: u32, height: u32, texels: &[Vec4]) -> Result<Vec<u8>> {
+        ensure!(
+            texels.len() == width as usize * height as usize,
+            "Expected {} texels for {}x{}, got {}",
+            width as usize * height as usize,
+            width,
+            height,
+            texels.len()
+        );
+
+        match *self {
+            ImageExport::Png => {
+                let data: Vec<u8> = texels
+                    .iter()
+                    .flat_map(|texel| {
+                        (texel.clamp(Vec4::ZERO, Vec4::ONE) * 255.0)
+                            .round()
+                            .to_array()
+                            .map(|value| value as u8)
+                    })
+                    .collect();
+                encode_png(width, height, png::ColorType::Rgba, &data)
+            }
+            ImageExport::DepthPng(depth_range) => {
+                let depths: Vec<f32> = texels.iter().map(|texel| texel.x).collect();
+                let data = depth_to_grayscale(&depths, depth_range);
+                encode_png(width, height, png::ColorType::Grayscale, &data)
+            }
+            ImageExport::Exr => Ok(encode_exr(width, height, texels)),
+            ImageExport::Hdr => Ok(encode_hdr(width, height, texels)),
+        }
+    }
+}
+
+/// One byte per depth, the far plane (nothing drawn) is black
+pub fn depth_to_grayscale(depths: &[f32], depth_range: DepthRange) -> Vec<u8> {
+    let far = depth_range.far_depth();
+    let closeness = |depth: f32| (depth - far).abs();
+
+    let (min, max) = depths
+        .iter()
+        .filter(|&&depth| depth != far)
+        .fold((f32::MAX, f32::MIN), |(min, max), &depth| {
+            (min.min(closeness(depth)), max.max(closeness(depth)))
+        });
+
+    depths
+        .iter()
+        .map(|&depth| {
+            if depth == far {
+                return 0;
+            }
+            // Anything drawn stays brighter than the background
+            let t = if max > min {
+                (closeness(depth) - min) / (max - min)
+            } else {
+                1.0
+            };
+            (1.0 + t * 254.0).round() as u8
+        })
+        .collect()
+}
+
+fn encode_png(width: u32, height: u32, color: png::ColorType, data: &[u8]) -> Result<Vec<u8>> {
+    let mut bytes = vec![];
+    let mut encoder = png::Encoder::new(&mut bytes, width, height);
+    encoder.set_color(color);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(data)?;
+    writer.finish()?;
+
+    Ok(bytes)
+}
+
+/// A single part, uncompressed scanline EXR with half float RGBA channels
+fn encode_exr(width: u32, height: u32, texels: &[Vec4]) -> Vec<u8> {
+    let mut bytes = vec![0x76, 0x2f, 0x31, 0x01];
+    bytes.extend(2u32.to_le_bytes());
+
+    let mut attribute = |name: &str, kind: &str, value: &[u8]| {
+        for text in [name, kind] {
+            bytes.extend(text.as_bytes());
+            bytes.push(0);
+        }
+        bytes.extend((value.len() as i32).to_le_bytes());
+        bytes.extend(value);
+    };
+
+    let mut channels = vec![];
+    for (name, _) in EXR_CHANNELS {
+        channels.extend(name.as_bytes());
+        channels.push(0);
+        channels.extend(EXR_HALF.to_le_bytes());
+        // Not perceptually linear, then 3 reserved bytes
+        channels.extend([0; 4]);
+        // No subsampling along x or y
+        channels.extend(1i32.to_le_bytes());
+        channels.extend(1i32.to_le_bytes());
+    }
+    channels.push(0);
+
+    let window: Vec<u8> = [0, 0, width as i32 - 1, height as i32 - 1]
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect();
+
+    attribute("channels", "chlist", &channels);
+    attribute("compression", "compression", &[0]);
+    attribute("dataWindow", "box2i", &window);
+    attribute("displayWindow", "box2i", &window);
+    attribute("lineOrder", "lineOrder", &[0]);
+    attribute("pixelAspectRatio", "float", &1.0f32.to_le_bytes());
+    attribute("screenWindowCenter", "v2f", &[0; 8]);
+    attribute("screenWindowWidth", "float", &1.0f32.to_le_bytes());
+    bytes.push(0);
+
+    // Every block is one scanline: its y, its size and then each channel's row in turn
+    let row_size = width as usize * EXR_CHANNELS.len() * std::mem::size_of::<u16>();
+    let block_size = 2 * std::mem::size_of::<i32>() + row_size;
+    let first_block = bytes.len() + height as usize * std::mem::size_of::<u64>();
+    for y in 0..height as usize {
+        bytes.extend(((first_block + y * block_size) as u64).to_le_bytes());
+    }
+
+    for (y, row) in texels.chunks(width as usize).enumerate() {
+        bytes.extend((y as i32).to_le_bytes());
+        bytes.extend((row_size as i32).to_le_bytes());
+        for (_, component) in EXR_CHANNELS {
+            for texel in row {
+                bytes.extend(f32_to_f16(texel[component]).to_le_bytes());
+            }
+        }
+    }
+
+    bytes
+}
+
+/// Uncompressed Radiance RGBE scanlines, top to bottom
+fn encode_hdr(width: u32, height: u32, texels: &[Vec4]) -> Vec<u8> {
+    let mut bytes = format!(
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        height, width
+    )
+    .into_bytes();
+
+    for texel in texels {
+        bytes.extend(rgbe(texel.truncate().max(Vec3::ZERO)));
+    }
+
+    bytes
+}
+
+// A mantissa per channel sharing the exponent of the largest
+fn rgbe(color: Vec3) -> [u8; 4] {
+    let largest = color.max_element();
+    if largest < 1e-32 {
+        return [0; 4];
+    }
+
+    // The largest channel's mantissa lands in 128 to 255
+    let exponent = largest.log2().floor() as i32 + 1;
+    let scale = 256.0 / 2f32.powi(exponent);
+    let mantissas = (color * scale).min(Vec3::splat(255.0));
+
+    [
+        mantissas.x as u8,
+        mantissas.y as u8,
+        mantissas.z as u8,
+        (exponent + 128).clamp(0, 255) as u8,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::f16_to_f32;
+
+    use super::*;
+
+    #[test]
+    fn picks_the_export_for_the_format() {
+        assert_eq!(
+            ImageExport::for_format(
+                DXGI_FORMAT_D32_FLOAT_S8X24_UINT,
+                DepthRange::Reversed,
+                HdrImageFormat::Exr
+            ),
+            ImageExport::DepthPng(DepthRange::Reversed)
+        );
+        assert_eq!(
+            ImageExport::for_format(
+                DXGI_FORMAT_R16G16B16A16_FLOAT,
+                DepthRange::Standard,
+                HdrImageFormat::Exr
+            ),
+            ImageExport::Exr
+        );
+        assert_eq!(
+            ImageExport::for_format(
+                DXGI_FORMAT_R11G11B10_FLOAT,
+                DepthRange::Standard,
+                HdrImageFormat::Hdr
+            ),
+            ImageExport::Hdr
+        );
+        assert_eq!(
+            ImageExport::for_format(
+                DXGI_FORMAT_R8G8B8A8_UNORM,
+                DepthRange::Standard,
+                HdrImageFormat::Hdr
+            ),
+            ImageExport::Png
+        );
+        assert_eq!(
+            "HDR".parse::<HdrImageFormat>().unwrap(),
+            HdrImageFormat::Hdr
+        );
+        assert!("tiff".parse::<HdrImageFormat>().is_err());
+    }
+
+    #[test]
+    fn stretches_depth_over_the_drawn_range() {
+        let gray = depth_to_grayscale(&[1.0, 0.25, 0.5, 0.75], DepthRange::Standard);
+        assert_eq!(gray, [0, 255, 128, 1]);
+
+        let gray = depth_to_grayscale(&[0.0, 0.5, 0.5], DepthRange::Reversed);
+        assert_eq!(gray, [0, 255, 255]);
+    }
+
+    #[test]
+    fn encodes_pngs() {
+        let texels = [
+            Vec4::new(1.0, 0.0, 0.0, 1.0),
+            Vec4::new(2.0, -1.0, 0.5, 0.0),
+        ];
+        let bytes = ImageExport::Png.encode(2, 1, &texels).unwrap();
+
+        let mut reader = png::Decoder::new(bytes.as_slice()).read_info().unwrap();
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data).unwrap();
+        assert_eq!((info.width, info.height), (2, 1));
+        assert_eq!(info.color_type, png::ColorType::Rgba);
+        assert_eq!(&data[..8], [255, 0, 0, 255, 255, 0, 128, 0]);
+
+        assert!(ImageExport::Png.encode(2, 2, &texels).is_err());
+    }
+
+    #[test]
+    fn encodes_exr_scanlines() {
+        let texels = [
+            Vec4::new(1.0, 2.0, 3.0, 4.0),
+            Vec4::new(-1.0, 0.5, 8.0, 0.0),
+            Vec4::new(0.25, 0.0, 0.0, 1.0),
+            Vec4::ZERO,
+        ];
+        let bytes = ImageExport::Exr.encode(2, 2, &texels).unwrap();
+        assert_eq!(bytes[..4], [0x76, 0x2f, 0x31, 0x01]);
+
+        let read_u64 = |offset: usize| {
+            u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap()) as usize
+        };
+        let read_i32 =
+            |offset: usize| i32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let read_half =
+            |offset: usize| f16_to_f32(u16::from_le_bytes([bytes[offset], bytes[offset + 1]]));
+
+        // The offset table sits between the header and the two scanlines
+        let block_size = 8 + 2 * 4 * 2;
+        let table = bytes.len() - 2 * block_size - 2 * 8;
+        let (first, second) = (read_u64(table), read_u64(table + 8));
+        assert_eq!(first, table + 16);
+        assert_eq!(second, first + block_size);
+
+        // Second scanline, channels A, B, G, R with both pixels each
+        assert_eq!(read_i32(second), 1);
+        assert_eq!(read_i32(second + 4), 16);
+        let channels: Vec<f32> = (0..8).map(|i| read_half(second + 8 + i * 2)).collect();
+        assert_eq!(channels, [1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.25, 0.0]);
+
+        let first_channels: Vec<f32> = (0..8).map(|i| read_half(first + 8 + i * 2)).collect();
+        assert_eq!(first_channels, [4.0, 0.0, 3.0, 8.0, 2.0, 0.5, 1.0, -1.0]);
+    }
+
+    #[test]
+    fn encodes_hdr_as_rgbe() {
+        let texels = [
+            Vec4::new(1.0, 0.5, 0.25, 1.0),
+            Vec4::new(6.0, -1.0, 0.0, 0.0),
+            Vec4::ZERO,
+        ];
+        let bytes = ImageExport::Hdr.encode(3, 1, &texels).unwrap();
+
+        let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 3\n";
+        assert_eq!(&bytes[..header.len()], header);
+        // 6 is 0.75 * 2^3, negative values clamp to 0
+        assert_eq!(
+            &bytes[header.len()..],
+            [128, 64, 32, 129, 192, 0, 0, 131, 0, 0, 0, 0]
+        );
+    }
+}
@@ -0,0 +1,242 @@
+use anyhow::{ensure, Context, Result};
+use windows::Win32::Graphics::{
+    Direct3D12::*,
+    Dxgi::Common::{DXGI_FORMAT_UNKNOWN, DXGI_SAMPLE_DESC},
+};
+
+use crate::{
+    write_buffer_immediate, AppendBuffer, BarrierBatcher, DescriptorHandle, DescriptorManager,
+    DescriptorType, Resource,
+};
+
+const ARGUMENTS_SIZE: usize = std::mem::size_of::<D3D12_DISPATCH_ARGUMENTS>();
+
+/// A command signature for `ExecuteIndirect` that reads one `D3D12_DISPATCH_ARGUMENTS` per
+/// command and changes no root arguments
+pub fn create_dispatch_command_signature(device: &ID3D12Device4) -> Result<ID3D12CommandSignature> {
+    let argument = D3D12_INDIRECT_ARGUMENT_DESC {
+        Type: D3D12_INDIRECT_ARGUMENT_TYPE_DISPATCH,
+        ..Default::default()
+    };
+
+    let mut command_signature: Option<ID3D12CommandSignature> = None;
+    unsafe {
+        device.CreateCommandSignature(
+            &D3D12_COMMAND_SIGNATURE_DESC {
+                ByteStride: ARGUMENTS_SIZE as u32,
+                NumArgumentDescs: 1,
+                pArgumentDescs: &argument,
+                NodeMask: 0,
+            },
+            None,
+            &mut command_signature,
+        )?;
+    }
+
+    command_signature.context("No command signature was created")
+}
+
+/// The thread groups of `group_size` threads along x it takes to cover `count` items, what
+/// shaders writing the arguments compute as well
+pub fn dispatch_arguments(count: u32, group_size: u32) -> D3D12_DISPATCH_ARGUMENTS {
+    D3D12_DISPATCH_ARGUMENTS {
+        ThreadGroupCountX: count.div_ceil(group_size.max(1)),
+        ThreadGroupCountY: 1,
+        ThreadGroupCountZ: 1,
+    }
+}
+
+/// Dispatches whose thread group counts compute shaders write, so a pass can size its work from
+/// a count the GPU came up with, e.g. surviving particles or visible objects, without reading it
+/// back. Shaders write the arguments through a `RWStructuredBuffer<uint3>` between
+/// `begin_writes` and `finish_writes`, one element per dispatch.
+#[derive(Debug)]
+pub struct IndirectDispatch {
+    command_signature: ID3D12CommandSignature,
+    arguments: Resource,
+    uav: DescriptorHandle,
+    capacity: usize,
+    // Buffers decay to common after every submission, see `submitted`
+    state: D3D12_RESOURCE_STATES,
+}
+
+impl IndirectDispatch {
+    pub fn new(
+        device: &ID3D12Device4,
+        descriptor_manager: &mut DescriptorManager,
+        capacity: usize,
+    ) -> Result<Self> {
+        ensure!(
+            capacity > 0,
+            "Indirect dispatches need room for one dispatch"
+        );
+
+        let command_signature = create_dispatch_command_signature(device)?;
+        let arguments = Resource::create_committed(
+            device,
+            &D3D12_HEAP_PROPERTIES {
+                Type: D3D12_HEAP_TYPE_DEFAULT,
+                ..Default::default()
+            },
+            &D3D12_RESOURCE_DESC {
+                Dimension: D3D12_RESOURCE_DIMENSION_BUFFER,
+                Width: (capacity * ARGUMENTS_SIZE) as u64,
+                Height: 1,
+                DepthOrArraySize: 1,
+                MipLevels: 1,
+                SampleDesc: DXGI_SAMPLE_DESC {
+                    Count: 1,
+                    Quality: 0,
+                },
+                Layout: D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
+                Flags: D3D12_RESOURCE_FLAG_ALLOW_UNORDERED_ACCESS,
+                ..Default::default()
+            },
+            D3D12_RESOURCE_STATE_COMMON,
+            None,
+            false,
+        )?;
+
+        let uav = descriptor_manager.allocate(DescriptorType::Resource)?;
+        unsafe {
+            device.CreateUnorderedAccessView(
+                &arguments.device_resource,
+                None,
+                &D3D12_UNORDERED_ACCESS_VIEW_DESC {
+                    Format: DXGI_FORMAT_UNKNOWN,
+                    ViewDimension: D3D12_UAV_DIMENSION_BUFFER,
+                    Anonymous: D3D12_UNORDERED_ACCESS_VIEW_DESC_0 {
+                        Buffer: D3D12_BUFFER_UAV {
+                            FirstElement: 0,
+                            NumElements: capacity as u32,
+                            StructureByteStride: ARGUMENTS_SIZE as u32,
+                            CounterOffsetInBytes: 0,
+                            Flags: D3D12_BUFFER_UAV_FLAG_NONE,
+                        },
+                    },
+                },
+                descriptor_manager.get_cpu_handle(&uav)?,
+            );
+        }
+
+        Ok(Self {
+            command_signature,
+            arguments,
+            uav,
+            capacity,
+            state: D3D12_RESOURCE_STATE_COMMON,
+        })
+    }
+
+    /// Where shaders write the arguments, one `uint3` of thread group counts per dispatch
+    pub fn uav(&self) -> &DescriptorHandle {
+        &self.uav
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Makes the arguments writable by the shaders recorded after the barriers are flushed
+    pub fn begin_writes(&mut self, barriers: &mut BarrierBatcher) {
+        self.transition(barriers, D3D12_RESOURCE_STATE_UNORDERED_ACCESS);
+    }
+
+    /// Makes what was written readable by `dispatch`
+    pub fn finish_writes(&mut self, barriers: &mut BarrierBatcher) {
+        self.transition(barriers, D3D12_RESOURCE_STATE_INDIRECT_ARGUMENT);
+    }
+
+    /// Makes dispatch `index` run a thread group for each element of `elements`, e.g. for every
+    /// object that passed culling, instead of having a shader write the arguments
+    pub fn copy_count_from(
+        &mut self,
+        command_list: &ID3D12GraphicsCommandList,
+        barriers: &mut BarrierBatcher,
+        elements: &mut AppendBuffer,
+        index: usize,
+    ) -> Result<()> {
+        ensure!(
+            index < self.capacity,
+            "Dispatch {} of {} is out of range",
+            index,
+            self.capacity
+        );
+
+        self.transition(barriers, D3D12_RESOURCE_STATE_COPY_DEST);
+        let offset = index * ARGUMENTS_SIZE;
+        elements.copy_count(command_list, barriers, &self.arguments, offset)?;
+
+        let group_counts = self.arguments.gpu_address() + offset as u64;
+        write_buffer_immediate(
+            command_list,
+            &[
+                (group_counts + std::mem::size_of::<u32>() as u64, 1),
+                (group_counts + 2 * std::mem::size_of::<u32>() as u64, 1),
+            ],
+            D3D12_WRITEBUFFERIMMEDIATE_MODE_DEFAULT,
+        )
+    }
+
+    /// Records dispatch `index` with the pipeline and root arguments already set. The barriers
+    /// of `finish_writes` have to be flushed first.
+    pub fn dispatch(&self, command_list: &ID3D12GraphicsCommandList, index: usize) -> Result<()> {
+        ensure!(
+            index < self.capacity,
+            "Dispatch {} of {} is out of range",
+            index,
+            self.capacity
+        );
+        ensure!(
+            self.state == D3D12_RESOURCE_STATE_INDIRECT_ARGUMENT,
+            "Dispatching before the arguments were finished"
+        );
+
+        unsafe {
+            command_list.ExecuteIndirect(
+                &self.command_signature,
+                1,
+                &self.arguments.device_resource,
+                (index * ARGUMENTS_SIZE) as u64,
+                None,
+                0,
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Call once the command list is submitted, the arguments are back in the common state then
+    pub fn submitted(&mut self) {
+        self.state = D3D12_RESOURCE_STATE_COMMON;
+    }
+
+    fn transition(&mut self, barriers: &mut BarrierBatcher, state: D3D12_RESOURCE_STATES) {
+        if self.state != state {
+            barriers.transition(&self.arguments.device_resource, self.state, state);
+            self.state = state;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arguments_cover_every_item() {
+        let covered = |count, group_size| {
+            let arguments = dispatch_arguments(count, group_size);
+            (
+                arguments.ThreadGroupCountX,
+                arguments.ThreadGroupCountY,
+                arguments.ThreadGroupCountZ,
+            )
+        };
+
+        assert_eq!(covered(0, 64), (0, 1, 1));
+        assert_eq!(covered(64, 64), (1, 1, 1));
+        assert_eq!(covered(65, 64), (2, 1, 1));
+        assert_eq!(covered(5, 0), (5, 1, 1));
+    }
+}
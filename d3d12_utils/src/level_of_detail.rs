@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+
+use glam::{UVec3, Vec2, Vec3};
+
+use crate::{Aabb, ObjChunk, ObjVertex};
+
+/// Simplifies a mesh by merging all vertices that fall into the same cell of a
+/// `grid_resolution`³ grid over its bounds, dropping triangles that collapse
+pub fn simplify_mesh(vertices: &[ObjVertex], indices: &[u32], grid_resolution: u32) -> ObjChunk {
+    let bounds = Aabb::from_points(vertices.iter().map(|vertex| vertex.position));
+    let cell_size =
+        (bounds.max - bounds.min).max(Vec3::splat(f32::EPSILON)) / grid_resolution as f32;
+    let cell_of = |position: Vec3| {
+        ((position - bounds.min) / cell_size)
+            .as_uvec3()
+            .min(UVec3::splat(grid_resolution - 1))
+    };
+
+    // Sums of all vertex attributes in a cell, averaged at the end
+    let mut cells: HashMap<UVec3, u32> = HashMap::new();
+    let mut sums: Vec<(Vec3, Vec3, Vec2, f32)> = Vec::new();
+    let remap: Vec<u32> = vertices
+        .iter()
+        .map(|vertex| {
+            let cell_index = *cells.entry(cell_of(vertex.position)).or_insert_with(|| {
+                sums.push((Vec3::ZERO, Vec3::ZERO, Vec2::ZERO, 0.0));
+                sums.len() as u32 - 1
+            });
+            let sum = &mut sums[cell_index as usize];
+            sum.0 += vertex.position;
+            sum.1 += vertex.normal;
+            sum.2 += vertex.uv;
+            sum.3 += 1.0;
+
+            cell_index
+        })
+        .collect();
+
+    let vertices = sums
+        .into_iter()
+        .map(|(position, normal, uv, count)| ObjVertex {
+            position: position / count,
+            normal: normal.normalize_or_zero(),
+            uv: uv / count,
+        })
+        .collect();
+
+    let indices = indices
+        .chunks_exact(3)
+        .map(|triangle| {
+            [
+                remap[triangle[0] as usize],
+                remap[triangle[1] as usize],
+                remap[triangle[2] as usize],
+            ]
+        })
+        .filter(|[a, b, c]| a != b && b != c && a != c)
+        .flatten()
+        .collect();
+
+    ObjChunk { vertices, indices }
+}
+
+/// The levels of detail coarser than the mesh, one simplified level for each grid resolution
+pub fn generate_lods(
+    vertices: &[ObjVertex],
+    indices: &[u32],
+    grid_resolutions: &[u32],
+) -> Vec<ObjChunk> {
+    grid_resolutions
+        .iter()
+        .map(|grid_resolution| simplify_mesh(vertices, indices, *grid_resolution))
+        .collect()
+}
+
+/// Fraction of half the screen height covered by a sphere of `radius` at `view_depth`
+pub fn screen_coverage(radius: f32, view_depth: f32, projection_scale_y: f32) -> f32 {
+    if view_depth <= radius {
+        return f32::INFINITY;
+    }
+
+    radius * projection_scale_y / view_depth
+}
+
+/// `thresholds[i]` is the screen coverage below which LOD `i + 1` is used instead of LOD `i`.
+/// A LOD only changes once the coverage is `hysteresis` (relative) past a threshold, so objects
+/// sitting right at a threshold don't keep popping between two LODs.
+pub fn select_lod(coverage: f32, current_lod: usize, thresholds: &[f32], hysteresis: f32) -> usize {
+    let mut lod = current_lod.min(thresholds.len());
+
+    while lod < thresholds.len() && coverage < thresholds[lod] * (1.0 - hysteresis) {
+        lod += 1;
+    }
+    while lod > 0 && coverage > thresholds[lod - 1] * (1.0 + hysteresis) {
+        lod -= 1;
+    }
+
+    lod
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid_mesh(size: u32) -> (Vec<ObjVertex>, Vec<u32>) {
+        let vertices = (0..=size)
+            .flat_map(|y| (0..=size).map(move |x| (x, y)))
+            .map(|(x, y)| ObjVertex {
+                position: Vec3::new(x as f32, y as f32, 0.0),
+                normal: Vec3::Z,
+                uv: Vec2::new(x as f32, y as f32) / size as f32,
+            })
+            .collect();
+        let indices = (0..size)
+            .flat_map(|y| (0..size).map(move |x| (x, y)))
+            .flat_map(|(x, y)| {
+                let i = y * (size + 1) + x;
+                [i, i + 1, i + size + 1, i + 1, i + size + 2, i + size + 1]
+            })
+            .collect();
+
+        (vertices, indices)
+    }
+
+    #[test]
+    fn simplification_reduces_triangles() {
+        let (vertices, indices) = grid_mesh(16);
+        let lods = generate_lods(&vertices, &indices, &[8, 4]);
+
+        assert_eq!(lods.len(), 2);
+        assert!(lods[0].indices.len() < indices.len());
+        assert!(lods[1].indices.len() < lods[0].indices.len());
+        assert!(!lods[1].indices.is_empty());
+        for lod in &lods {
+            assert!(lod
+                .indices
+                .iter()
+                .all(|index| (*index as usize) < lod.vertices.len()));
+        }
+    }
+
+    #[test]
+    fn lod_selection_has_hysteresis() {
+        let thresholds = [0.5, 0.25];
+
+        assert_eq!(select_lod(1.0, 0, &thresholds, 0.1), 0);
+        assert_eq!(select_lod(0.1, 0, &thresholds, 0.1), 2);
+        // Just below the threshold isn't enough to switch
+        assert_eq!(select_lod(0.48, 0, &thresholds, 0.1), 0);
+        assert_eq!(select_lod(0.44, 0, &thresholds, 0.1), 1);
+        // And just above isn't enough to switch back
+        assert_eq!(select_lod(0.52, 1, &thresholds, 0.1), 1);
+        assert_eq!(select_lod(0.56, 1, &thresholds, 0.1), 0);
+    }
+}
@@ -27,3 +27,205 @@ pub use descriptor_manager::*;
 
 mod mesh_manager;
 pub use mesh_manager::*;
+
+mod render_target;
+pub use render_target::*;
+
+mod texture_atlas;
+pub use texture_atlas::*;
+
+mod query_heap;
+pub use query_heap::*;
+
+mod gpu_timer;
+pub use gpu_timer::*;
+
+mod variable_rate_shading;
+pub use variable_rate_shading::*;
+
+mod sampler_feedback;
+pub use sampler_feedback::*;
+
+mod asset_loader;
+pub use asset_loader::*;
+
+#[cfg(feature = "direct_storage")]
+mod direct_storage;
+#[cfg(feature = "direct_storage")]
+pub use direct_storage::*;
+
+mod fence_watcher;
+pub use fence_watcher::*;
+
+mod versioned_buffer;
+pub use versioned_buffer::*;
+
+mod deletion_queue;
+pub use deletion_queue::*;
+
+mod shared_handle;
+pub use shared_handle::*;
+
+mod frame_readback;
+pub use frame_readback::*;
+
+mod culling;
+pub use culling::*;
+
+mod level_of_detail;
+pub use level_of_detail::*;
+
+mod meshlet;
+pub use meshlet::*;
+
+mod depth_readback;
+pub use depth_readback::*;
+
+mod projection;
+pub use projection::*;
+
+mod cubemap;
+pub use cubemap::*;
+
+mod light_clustering;
+pub use light_clustering::*;
+
+mod packing;
+pub use packing::*;
+
+mod shader_signature;
+pub use shader_signature::*;
+
+#[cfg(feature = "presentation")]
+mod outputs;
+#[cfg(feature = "presentation")]
+pub use outputs::*;
+
+#[cfg(feature = "presentation")]
+mod frame_latency;
+#[cfg(feature = "presentation")]
+pub use frame_latency::*;
+
+mod depth_stencil;
+pub use depth_stencil::*;
+
+mod memory_stats;
+pub use memory_stats::*;
+
+mod barrier_batcher;
+pub use barrier_batcher::*;
+
+#[cfg(feature = "presentation")]
+mod present_statistics;
+#[cfg(feature = "presentation")]
+pub use present_statistics::*;
+
+mod target_formats;
+pub use target_formats::*;
+
+mod topology;
+pub use topology::*;
+
+mod plane;
+pub use plane::*;
+
+mod procedural_texture;
+pub use procedural_texture::*;
+
+mod builtin_textures;
+pub use builtin_textures::*;
+
+mod cube_lut;
+pub use cube_lut::*;
+
+mod breadcrumbs;
+pub use breadcrumbs::*;
+
+mod dred;
+pub use dred::*;
+
+mod fixed_timestep;
+pub use fixed_timestep::*;
+
+mod image_export;
+pub use image_export::*;
+
+mod ray;
+pub use ray::*;
+
+mod gizmo;
+pub use gizmo::*;
+
+mod raycast;
+pub use raycast::*;
+
+mod global_constants;
+pub use global_constants::*;
+
+mod raw_upload;
+pub use raw_upload::*;
+
+#[cfg(feature = "presentation")]
+mod swap_chain;
+#[cfg(feature = "presentation")]
+pub use swap_chain::*;
+
+mod cross_adapter;
+pub use cross_adapter::*;
+
+mod shader_compiler;
+pub use shader_compiler::*;
+
+mod shader_model;
+pub use shader_model::*;
+
+mod device_capabilities;
+pub use device_capabilities::*;
+
+mod bundle;
+pub use bundle::*;
+
+mod dynamic_vertex_ring;
+pub use dynamic_vertex_ring::*;
+
+mod pipeline_statistics;
+pub use pipeline_statistics::*;
+
+mod indirect_dispatch;
+pub use indirect_dispatch::*;
+
+mod append_buffer;
+pub use append_buffer::*;
+
+mod hlsl_struct;
+pub use hlsl_struct::*;
+
+mod binding_validation;
+pub use binding_validation::*;
+
+mod hdr_metadata;
+pub use hdr_metadata::*;
+
+mod texture_usage;
+pub use texture_usage::*;
+
+mod stable_power_state;
+pub use stable_power_state::*;
+
+mod diagnostics;
+pub use diagnostics::*;
+
+mod transient_descriptors;
+pub use transient_descriptors::*;
+
+mod morph_target;
+pub use morph_target::*;
+
+mod animation;
+pub use animation::*;
+
+mod plot;
+pub use plot::*;
+
+#[cfg(feature = "embedded_shaders")]
+pub use d3d12_utils_macros::include_hlsl;
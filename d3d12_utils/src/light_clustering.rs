@@ -0,0 +1,171 @@
+use glam::{Vec2, Vec3};
+
+use crate::{pack_half2, pack_rgba8};
+
+/// Matches MAX_LIGHTS_PER_CLUSTER in light_culling.hlsl and bindless_texture.hlsl, lights past it
+/// are dropped from the cluster
+pub const MAX_LIGHTS_PER_CLUSTER: usize = 64;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PointLight {
+    pub position: Vec3,
+    /// Distance at which the light has faded out completely, it only reaches clusters within it
+    pub radius: f32,
+    /// 0 to 1, scaled by `intensity`
+    pub color: Vec3,
+    pub intensity: f32,
+}
+
+impl PointLight {
+    /// Radius and intensity as halfs and the colour as RGBA8, which is plenty for lighting
+    pub fn pack(&self) -> PackedPointLight {
+        PackedPointLight {
+            position: self.position,
+            radius_intensity: pack_half2(Vec2::new(self.radius, self.intensity)),
+            color: pack_rgba8(self.color.extend(1.0)),
+        }
+    }
+}
+
+/// How lights are stored on the GPU, laid out like PackedPointLight in the shaders
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PackedPointLight {
+    pub position: Vec3,
+    pub radius_intensity: u32,
+    pub color: u32,
+}
+
+/// The view frustum split into a screen space grid of tiles, each cut into depth slices that get
+/// exponentially thicker away from the camera so clusters stay roughly as deep as they are wide
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClusterGrid {
+    pub tiles_x: u32,
+    pub tiles_y: u32,
+    pub slices: u32,
+    /// View space depths the slices are spread over, usually the clip planes of the camera
+    pub near: f32,
+    pub far: f32,
+}
+
+impl ClusterGrid {
+    pub fn cluster_count(&self) -> usize {
+        (self.tiles_x * self.tiles_y * self.slices) as usize
+    }
+
+    /// Clusters are stored row by row within a slice, slice by slice
+    pub fn cluster_index(&self, tile_x: u32, tile_y: u32, slice: u32) -> usize {
+        ((slice * self.tiles_y + tile_y) * self.tiles_x + tile_x) as usize
+    }
+
+    /// View space depths at the front and back of `slice`
+    pub fn slice_bounds(&self, slice: u32) -> (f32, f32) {
+        let depth =
+            |slice: u32| self.near * (self.far / self.near).powf(slice as f32 / self.slices as f32);
+
+        (depth(slice), depth(slice + 1))
+    }
+
+    /// `slice = ln(depth) * scale + bias`, so shaders find the slice of a pixel with a single log
+    pub fn slice_scale_bias(&self) -> (f32, f32) {
+        let scale = self.slices as f32 / (self.far / self.near).ln();
+
+        (scale, -self.near.ln() * scale)
+    }
+
+    /// Depths in front of or behind the grid land in the first or last slice
+    pub fn slice(&self, view_depth: f32) -> u32 {
+        let (scale, bias) = self.slice_scale_bias();
+        let slice = view_depth.max(self.near).ln() * scale + bias;
+
+        (slice.max(0.0) as u32).min(self.slices - 1)
+    }
+
+    /// The cluster holding a point at `uv` of the view, (0, 0) being its top left corner
+    pub fn cluster_at(&self, uv: Vec2, view_depth: f32) -> usize {
+        let tile = |coordinate: f32, tiles: u32| {
+            ((coordinate * tiles as f32).max(0.0) as u32).min(tiles - 1)
+        };
+
+        self.cluster_index(
+            tile(uv.x, self.tiles_x),
+            tile(uv.y, self.tiles_y),
+            self.slice(view_depth),
+        )
+    }
+}
+
+/// Whether a light reaches a cluster, given the view space bounds of the cluster
+pub fn sphere_intersects_aabb(center: Vec3, radius: f32, min: Vec3, max: Vec3) -> bool {
+    center.clamp(min, max).distance_squared(center) <= radius * radius
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRID: ClusterGrid = ClusterGrid {
+        tiles_x: 16,
+        tiles_y: 9,
+        slices: 24,
+        near: 0.1,
+        far: 100.0,
+    };
+
+    #[test]
+    fn slices_cover_depth_range() {
+        assert!((GRID.slice_bounds(0).0 - GRID.near).abs() < 1e-6);
+        assert!((GRID.slice_bounds(GRID.slices - 1).1 - GRID.far).abs() < 1e-3);
+
+        for slice in 0..GRID.slices {
+            let (front, back) = GRID.slice_bounds(slice);
+            assert!(front < back);
+            assert_eq!(GRID.slice((front + back) * 0.5), slice);
+            if slice > 0 {
+                assert_eq!(GRID.slice_bounds(slice - 1).1, front);
+            }
+        }
+
+        assert_eq!(GRID.slice(0.01), 0);
+        assert_eq!(GRID.slice(1000.0), GRID.slices - 1);
+    }
+
+    #[test]
+    fn clusters_are_laid_out_row_by_row() {
+        assert_eq!(GRID.cluster_count(), 16 * 9 * 24);
+        assert_eq!(GRID.cluster_at(Vec2::ZERO, GRID.near), 0);
+        assert_eq!(GRID.cluster_at(Vec2::new(0.99, 0.0), GRID.near), 15);
+        assert_eq!(GRID.cluster_at(Vec2::new(0.0, 0.2), GRID.near), 16);
+        assert_eq!(
+            GRID.cluster_at(Vec2::ONE, GRID.far),
+            GRID.cluster_count() - 1
+        );
+    }
+
+    #[test]
+    fn spheres_reach_boxes_within_their_radius() {
+        let (min, max) = (Vec3::ZERO, Vec3::ONE);
+
+        assert!(sphere_intersects_aabb(Vec3::splat(0.5), 0.1, min, max));
+        assert!(sphere_intersects_aabb(
+            Vec3::new(1.5, 0.5, 0.5),
+            0.6,
+            min,
+            max
+        ));
+        assert!(!sphere_intersects_aabb(
+            Vec3::new(1.5, 0.5, 0.5),
+            0.4,
+            min,
+            max
+        ));
+        // Diagonally off the corner is further than off a face
+        assert!(!sphere_intersects_aabb(
+            Vec3::new(1.5, 1.5, 1.5),
+            0.6,
+            min,
+            max
+        ));
+    }
+}
@@ -0,0 +1,260 @@
+use std::{
+    fmt,
+    ops::{Add, Sub},
+};
+
+/// How full a fixed size pool is, in bytes for heaps and upload rings and in descriptors for
+/// descriptor heaps
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    pub used: usize,
+    pub capacity: usize,
+    /// The most that was ever used at once
+    pub high_water_mark: usize,
+    /// Live allocations, or in flight submissions for upload rings
+    pub count: usize,
+}
+
+impl PoolStats {
+    /// Used part of the capacity from 0 to 1
+    pub fn usage(&self) -> f32 {
+        fraction(self.used, self.capacity)
+    }
+
+    pub fn peak_usage(&self) -> f32 {
+        fraction(self.high_water_mark, self.capacity)
+    }
+}
+
+fn fraction(part: usize, whole: usize) -> f32 {
+    if whole == 0 {
+        0.0
+    } else {
+        (part as f64 / whole as f64) as f32
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolUnit {
+    Bytes,
+    Descriptors,
+}
+
+/// What every resource manager has allocated, for spotting pools that are about to run out
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Counts textures, external and committed ones included, rather than placed resources
+    pub texture_heap: PoolStats,
+    /// Counts meshes with their own buffers, rather than placed resources
+    pub mesh_heap: PoolStats,
+    /// Streamed meshes are appended and never freed, so usage only grows
+    pub mesh_pool_vertices: PoolStats,
+    pub mesh_pool_indices: PoolStats,
+    pub resource_descriptors: PoolStats,
+    pub render_target_views: PoolStats,
+    pub depth_stencil_views: PoolStats,
+    /// The current frame's section, sections are reset rather than freed
+    pub transient_descriptors: PoolStats,
+    pub high_priority_uploads: PoolStats,
+    pub background_uploads: PoolStats,
+}
+
+impl Stats {
+    pub const NUM_POOLS: usize = 10;
+
+    /// Every pool with its name and unit, always in the same order
+    pub fn pools(&self) -> [(&'static str, PoolUnit, PoolStats); Self::NUM_POOLS] {
+        [
+            ("Texture heap", PoolUnit::Bytes, self.texture_heap),
+            ("Mesh heap", PoolUnit::Bytes, self.mesh_heap),
+            (
+                "Mesh pool vertices",
+                PoolUnit::Bytes,
+                self.mesh_pool_vertices,
+            ),
+            ("Mesh pool indices", PoolUnit::Bytes, self.mesh_pool_indices),
+            (
+                "Resource descriptors",
+                PoolUnit::Descriptors,
+                self.resource_descriptors,
+            ),
+            (
+                "Render target views",
+                PoolUnit::Descriptors,
+                self.render_target_views,
+            ),
+            (
+                "Depth stencil views",
+                PoolUnit::Descriptors,
+                self.depth_stencil_views,
+            ),
+            (
+                "Transient descriptors",
+                PoolUnit::Descriptors,
+                self.transient_descriptors,
+            ),
+            (
+                "High priority uploads",
+                PoolUnit::Bytes,
+                self.high_priority_uploads,
+            ),
+            (
+                "Background uploads",
+                PoolUnit::Bytes,
+                self.background_uploads,
+            ),
+        ]
+    }
+}
+
+/// What was allocated from the pools that run out when too much is asked of them in one frame,
+/// counted since they were created. The difference of two is what was allocated in between.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AllocationCounts {
+    /// Upload ring submissions, only 16 of each ring can be in flight, and transient
+    /// descriptors
+    pub transient_allocations: u64,
+    pub descriptors: u64,
+    pub upload_bytes: u64,
+}
+
+impl Add for AllocationCounts {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            transient_allocations: self.transient_allocations + other.transient_allocations,
+            descriptors: self.descriptors + other.descriptors,
+            upload_bytes: self.upload_bytes + other.upload_bytes,
+        }
+    }
+}
+
+impl Sub for AllocationCounts {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self {
+            transient_allocations: self
+                .transient_allocations
+                .saturating_sub(other.transient_allocations),
+            descriptors: self.descriptors.saturating_sub(other.descriptors),
+            upload_bytes: self.upload_bytes.saturating_sub(other.upload_bytes),
+        }
+    }
+}
+
+impl fmt::Display for AllocationCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} transient allocations, {} descriptors, {} of uploads",
+            self.transient_allocations,
+            self.descriptors,
+            format_bytes(self.upload_bytes as usize)
+        )
+    }
+}
+
+/// e.g. 1.5 MiB
+pub fn format_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, unit, pool) in self.pools() {
+            let amount = |amount: usize| match unit {
+                PoolUnit::Bytes => format_bytes(amount),
+                PoolUnit::Descriptors => amount.to_string(),
+            };
+            writeln!(
+                f,
+                "{:<22} {:>10} / {:<10} {:>5.1}%, peak {}, {} allocations",
+                name,
+                amount(pool.used),
+                amount(pool.capacity),
+                pool.usage() * 100.0,
+                amount(pool.high_water_mark),
+                pool.count
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_bytes_in_binary_units() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(16 * 1024 * 1024), "16.0 MiB");
+        assert_eq!(format_bytes(500_000_000), "476.8 MiB");
+    }
+
+    #[test]
+    fn usage_handles_empty_pools() {
+        let pool = PoolStats {
+            used: 256,
+            capacity: 1024,
+            high_water_mark: 512,
+            count: 2,
+        };
+        assert_eq!(pool.usage(), 0.25);
+        assert_eq!(pool.peak_usage(), 0.5);
+        assert_eq!(PoolStats::default().usage(), 0.0);
+
+        let stats = Stats {
+            texture_heap: pool,
+            ..Default::default()
+        };
+        assert_eq!(stats.pools()[0].2, pool);
+        assert!(stats.to_string().starts_with("Texture heap"));
+    }
+
+    #[test]
+    fn allocations_between_two_counts() {
+        let before = AllocationCounts {
+            transient_allocations: 3,
+            descriptors: 10,
+            upload_bytes: 1024,
+        };
+        let after = before
+            + AllocationCounts {
+                transient_allocations: 2,
+                descriptors: 0,
+                upload_bytes: 512,
+            };
+        assert_eq!(
+            after - before,
+            AllocationCounts {
+                transient_allocations: 2,
+                descriptors: 0,
+                upload_bytes: 512,
+            }
+        );
+        assert_eq!(before - after, AllocationCounts::default());
+        assert_eq!(
+            (after - before).to_string(),
+            "2 transient allocations, 0 descriptors, 512 B of uploads"
+        );
+    }
+}
@@ -1,73 +1,1120 @@
-use anyhow::{Context, Result};
-use windows::Win32::Graphics::{Direct3D12::*, Dxgi::Common::DXGI_FORMAT_R32_UINT};
+use std::collections::HashMap;
 
-use crate::{Heap, Resource};
+use anyhow::{ensure, Context, Result};
+use windows::Win32::Graphics::{
+    Direct3D12::*,
+    Dxgi::Common::{DXGI_FORMAT_R32_UINT, DXGI_FORMAT_UNKNOWN, DXGI_SAMPLE_DESC},
+};
+
+use crate::{
+    batch_size, pack_morph_deltas, Aabb, CommandQueue, CpuMesh, DeletionQueue, DescriptorHandle,
+    DescriptorManager, DescriptorType, Heap, HeapAllocation, MeshletData, MorphOutput, MorphTarget,
+    MorphTargetSet, ObjChunk, ObjVertex, PoolStats, PrimitiveTopology, RangeAllocator, Resource,
+    UploadBatch, UploadRingBuffer, BUFFER_UPLOAD_ALIGNMENT,
+};
 
 #[derive(Debug, Default, Clone, Copy)]
 pub struct MeshHandle {
+    index: usize,
     vb_index: usize,
     ib_index: usize,
     pub num_vertices: usize,
     pub vbv: Option<D3D12_VERTEX_BUFFER_VIEW>,
     pub ibv: Option<D3D12_INDEX_BUFFER_VIEW>,
+    /// Object space bounds, meshes without them are never culled
+    pub bounds: Option<Aabb>,
+    /// How the indices are drawn, streamed meshes are always triangle lists
+    pub topology: PrimitiveTopology,
+    lod_group: Option<usize>,
+}
+
+impl MeshHandle {
+    /// Whether both handles draw the same mesh, meshes in the pool share their buffers
+    pub fn is_same(&self, other: &MeshHandle) -> bool {
+        let view = |view: Option<D3D12_VERTEX_BUFFER_VIEW>| {
+            view.map(|view| (view.BufferLocation, view.SizeInBytes))
+        };
+        let index_view = |view: Option<D3D12_INDEX_BUFFER_VIEW>| {
+            view.map(|view| (view.BufferLocation, view.SizeInBytes))
+        };
+
+        self.index == other.index
+            && self.vb_index == other.vb_index
+            && self.ib_index == other.ib_index
+            && self.lod_group == other.lod_group
+            && view(self.vbv) == view(other.vbv)
+            && index_view(self.ibv) == index_view(other.ibv)
+    }
+}
+
+/// Structured buffer SRVs for drawing a mesh with the mesh shader
+#[derive(Debug, Clone, Copy)]
+pub struct MeshletSet {
+    pub num_meshlets: usize,
+    /// The whole vertex pool, `vertex_indices` already include the mesh's base vertex
+    pub vertices: DescriptorHandle,
+    pub meshlets: DescriptorHandle,
+    pub bounds: DescriptorHandle,
+    pub vertex_indices: DescriptorHandle,
+    pub primitive_indices: DescriptorHandle,
+}
+
+#[derive(Debug)]
+struct MeshPool {
+    vb_index: usize,
+    ib_index: usize,
+    vertices: RangeAllocator,
+    indices: RangeAllocator,
+    vertex_srv: Option<DescriptorHandle>,
+}
+
+impl MeshPool {
+    // Empty ranges at the end of the buffers, for a mesh to grow into chunk by chunk
+    fn begin_mesh(&mut self) -> Result<(HeapAllocation, HeapAllocation)> {
+        let vertices = self
+            .vertices
+            .allocate_at_end(0, std::mem::size_of::<ObjVertex>())?;
+        let indices = match self.indices.allocate_at_end(0, std::mem::size_of::<u32>()) {
+            Ok(indices) => indices,
+            Err(err) => {
+                self.vertices.free(vertices);
+                return Err(err);
+            }
+        };
+
+        Ok((vertices, indices))
+    }
+
+    // First fit, for meshes whose size is known up front
+    fn reserve_mesh(
+        &mut self,
+        vertex_bytes: usize,
+        index_bytes: usize,
+    ) -> Result<(HeapAllocation, HeapAllocation)> {
+        let vertices = self
+            .vertices
+            .allocate(vertex_bytes, std::mem::size_of::<ObjVertex>())
+            .context("Not enough space in mesh pool vertex buffer")?;
+        let indices = match self
+            .indices
+            .allocate(index_bytes, std::mem::size_of::<u32>())
+            .context("Not enough space in mesh pool index buffer")
+        {
+            Ok(indices) => indices,
+            Err(err) => {
+                self.vertices.free(vertices);
+                return Err(err);
+            }
+        };
+
+        Ok((vertices, indices))
+    }
+
+    fn grow_mesh(
+        &mut self,
+        (vertices, indices): &mut (HeapAllocation, HeapAllocation),
+        vertex_bytes: usize,
+        index_bytes: usize,
+    ) -> Result<()> {
+        self.vertices
+            .grow(vertices, vertex_bytes)
+            .context("Not enough space in mesh pool vertex buffer")?;
+        self.indices
+            .grow(indices, index_bytes)
+            .context("Not enough space in mesh pool index buffer")
+    }
+
+    fn free_mesh(&mut self, (vertices, indices): (HeapAllocation, HeapAllocation)) {
+        self.vertices.free(vertices);
+        self.indices.free(indices);
+    }
+}
+
+// Everything a mesh holds on to, deleted with it once its last reference is unloaded
+#[derive(Debug, Default)]
+struct MeshEntry {
+    ref_count: u32,
+    // The mesh's vertex and index buffers, unless they're the pool's
+    buffer_index: Option<usize>,
+    // Where the mesh is in the pool buffers
+    pool_ranges: Option<(HeapAllocation, HeapAllocation)>,
+    // What the meshlet set, morph target set and CPU copy of the mesh are keyed by
+    vertex_location: u64,
+    // Meshlet and morph target buffers, and the views of them
+    buffers: Vec<(Resource, HeapAllocation)>,
+    descriptors: Vec<DescriptorHandle>,
+    // The coarser levels of detail of the mesh, which are deleted with it
+    coarser_lods: Vec<usize>,
+    lod_group: Option<usize>,
 }
 
 #[derive(Debug)]
 pub struct MeshManager {
     pub heap: Heap,
-    vertex_buffers: Vec<Resource>,
-    index_buffers: Vec<Resource>,
+    /// Whether meshes streamed in from here on keep a copy of their positions and indices on the
+    /// CPU, for raycasts and acceleration structure builds without parsing the files again
+    pub retain_cpu_copies: bool,
+    // Vertex and index buffers are always added in pairs, so they share indices
+    vertex_buffers: Vec<Option<Resource>>,
+    index_buffers: Vec<Option<Resource>>,
+    // Where buffers placed in the heap live, to reuse the space after they're deleted
+    buffer_allocations: Vec<Option<(HeapAllocation, HeapAllocation)>>,
+    meshes: Vec<MeshEntry>,
+    deletion_queue: DeletionQueue<usize>,
+    pool: Option<MeshPool>,
+    // Each group holds the handles of a mesh's levels of detail, finest first. Emptied once the
+    // mesh is deleted.
+    lod_groups: Vec<Vec<MeshHandle>>,
+    // Keyed by the vertex buffer location of the mesh
+    meshlet_sets: HashMap<u64, MeshletSet>,
+    // Keyed like the meshlet sets
+    cpu_meshes: HashMap<u64, CpuMesh>,
+    // Keyed like the meshlet sets
+    morph_target_sets: HashMap<u64, MorphTargetSet>,
+}
+
+fn buffer_desc(size: usize) -> D3D12_RESOURCE_DESC {
+    D3D12_RESOURCE_DESC {
+        Dimension: D3D12_RESOURCE_DIMENSION_BUFFER,
+        Width: size as u64,
+        Height: 1,
+        DepthOrArraySize: 1,
+        MipLevels: 1,
+        SampleDesc: DXGI_SAMPLE_DESC {
+            Count: 1,
+            Quality: 0,
+        },
+        Layout: D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
+        ..Default::default()
+    }
 }
 
 impl MeshManager {
     pub fn new(device: &ID3D12Device4) -> Result<Self> {
         Ok(MeshManager {
             heap: Heap::create_default_heap(device, 2e7 as usize, "Mesh Manager Heap")?,
+            retain_cpu_copies: false,
             vertex_buffers: Vec::new(),
             index_buffers: Vec::new(),
+            buffer_allocations: Vec::new(),
+            meshes: Vec::new(),
+            deletion_queue: DeletionQueue::new(),
+            pool: None,
+            lod_groups: Vec::new(),
+            meshlet_sets: HashMap::new(),
+            cpu_meshes: HashMap::new(),
+            morph_target_sets: HashMap::new(),
         })
     }
 
+    /// Buffers placed in `heap` come with their allocations, so the space is reused once the mesh
+    /// is deleted
     pub fn add(
         &mut self,
         vertex_buffer: Resource,
         index_buffer: Resource,
+        allocations: Option<(HeapAllocation, HeapAllocation)>,
         vertex_buffer_stride: u32,
         num_vertices: usize,
+        topology: PrimitiveTopology,
     ) -> Result<MeshHandle> {
-        let vertex_buffer_size = vertex_buffer.size;
-        let index_buffer_size = index_buffer.size;
-        self.vertex_buffers.push(vertex_buffer);
-        self.index_buffers.push(index_buffer);
+        let vbv = D3D12_VERTEX_BUFFER_VIEW {
+            BufferLocation: vertex_buffer.gpu_address(),
+            StrideInBytes: vertex_buffer_stride,
+            SizeInBytes: vertex_buffer.size as u32,
+        };
+        let ibv = D3D12_INDEX_BUFFER_VIEW {
+            BufferLocation: index_buffer.gpu_address(),
+            SizeInBytes: index_buffer.size as u32,
+            Format: DXGI_FORMAT_R32_UINT,
+        };
+        let buffer_index = self.push_buffers(vertex_buffer, index_buffer, allocations);
+        let index = self.push_mesh(MeshEntry {
+            buffer_index: Some(buffer_index),
+            vertex_location: vbv.BufferLocation,
+            ..Default::default()
+        });
 
         Ok(MeshHandle {
-            vb_index: self.vertex_buffers.len() - 1,
-            ib_index: self.index_buffers.len() - 1,
+            index,
+            vb_index: buffer_index,
+            ib_index: buffer_index,
             num_vertices,
+            vbv: Some(vbv),
+            ibv: Some(ibv),
+            bounds: None,
+            topology,
+            lod_group: None,
+        })
+    }
+
+    /// Creates the shared vertex and index buffers that streamed meshes are appended to
+    pub fn create_pool(
+        &mut self,
+        device: &ID3D12Device4,
+        vertex_pool_size: usize,
+        index_pool_size: usize,
+    ) -> Result<()> {
+        ensure!(self.pool.is_none(), "Mesh pool already created");
+
+        let vertex_buffer = self.heap.create_resource(
+            device,
+            &buffer_desc(vertex_pool_size),
+            D3D12_RESOURCE_STATE_COMMON,
+            None,
+            false,
+        )?;
+        let index_buffer = self.heap.create_resource(
+            device,
+            &buffer_desc(index_pool_size),
+            D3D12_RESOURCE_STATE_COMMON,
+            None,
+            false,
+        )?;
+
+        // The pool lives as long as the manager
+        let index = self.push_buffers(vertex_buffer, index_buffer, None);
+
+        self.pool = Some(MeshPool {
+            vb_index: index,
+            ib_index: index,
+            vertices: RangeAllocator::new(vertex_pool_size),
+            indices: RangeAllocator::new(index_pool_size),
+            vertex_srv: None,
+        });
+
+        Ok(())
+    }
+
+    /// Uploads a mesh chunk by chunk into the mesh pool, so only one chunk has to live on the CPU at a time.
+    /// The mesh goes after everything else in the pool, its space is reused by the levels of detail
+    /// of other meshes once it is unloaded. With `retain_cpu_copies` the positions and indices are
+    /// gathered up as the chunks go by.
+    pub fn add_streamed<I>(
+        &mut self,
+        uploader: &mut UploadRingBuffer,
+        dependent_queue: Option<&CommandQueue>,
+        chunks: I,
+    ) -> Result<MeshHandle>
+    where
+        I: IntoIterator<Item = Result<ObjChunk>>,
+    {
+        let pool = self.pool.as_mut().context("Mesh pool not created")?;
+        let vertex_pool = self.vertex_buffers[pool.vb_index]
+            .as_ref()
+            .context("Mesh pool vertex buffer deleted")?;
+        let index_pool = self.index_buffers[pool.ib_index]
+            .as_ref()
+            .context("Mesh pool index buffer deleted")?;
+
+        let mut ranges = pool.begin_mesh()?;
+        let mut bounds = Aabb::EMPTY;
+        let mut cpu_mesh = self.retain_cpu_copies.then(CpuMesh::default);
+
+        let upload_chunks = || -> Result<()> {
+            for chunk in chunks {
+                let chunk = chunk?;
+                if chunk.vertices.is_empty() {
+                    continue;
+                }
+                bounds = chunk
+                    .vertices
+                    .iter()
+                    .fold(bounds, |bounds, vertex| bounds.extend(vertex.position));
+                if let Some(cpu_mesh) = &mut cpu_mesh {
+                    cpu_mesh
+                        .positions
+                        .extend(chunk.vertices.iter().map(|vertex| vertex.position));
+                    cpu_mesh.indices.extend_from_slice(&chunk.indices);
+                }
+
+                let offsets = (
+                    ranges.0.offset + ranges.0.size,
+                    ranges.1.offset + ranges.1.size,
+                );
+                pool.grow_mesh(
+                    &mut ranges,
+                    std::mem::size_of_val(chunk.vertices.as_slice()),
+                    std::mem::size_of_val(chunk.indices.as_slice()),
+                )?;
+                upload_chunk(
+                    uploader,
+                    dependent_queue,
+                    &chunk,
+                    (vertex_pool, index_pool),
+                    offsets,
+                )?;
+            }
+
+            ensure!(ranges.0.size > 0, "Streamed mesh has no vertices");
+            Ok(())
+        };
+        if let Err(err) = upload_chunks() {
+            // Copies already submitted land before those of whatever gets the ranges next
+            pool.free_mesh(ranges);
+            return Err(err);
+        }
+
+        self.push_pool_mesh(ranges, bounds, cpu_mesh)
+    }
+
+    /// Uploads the coarser levels of detail of a mesh already in the mesh pool, in order of
+    /// increasing coarseness. The returned handle is the finest level, use `get_lod` for the others.
+    pub fn add_lods(
+        &mut self,
+        uploader: &mut UploadRingBuffer,
+        dependent_queue: Option<&CommandQueue>,
+        finest: &MeshHandle,
+        coarser: Vec<ObjChunk>,
+    ) -> Result<MeshHandle> {
+        ensure!(
+            finest.lod_group.is_none(),
+            "Mesh already has levels of detail"
+        );
+        ensure!(
+            self.meshes
+                .get(finest.index)
+                .is_some_and(|mesh| mesh.ref_count > 0),
+            "Mesh already unloaded"
+        );
+
+        let mut handles = std::iter::once(Ok(*finest))
+            .chain(
+                coarser
+                    .into_iter()
+                    .map(|lod| self.add_to_pool(uploader, dependent_queue, &lod)),
+            )
+            .collect::<Result<Vec<MeshHandle>>>()?;
+
+        // Culling uses the bounds of the finest level for all of them
+        let bounds = handles[0].bounds;
+        let lod_group = self.lod_groups.len();
+        for handle in &mut handles {
+            handle.bounds = bounds;
+            handle.lod_group = Some(lod_group);
+        }
+        let finest = &mut self.meshes[finest.index];
+        finest.coarser_lods = handles[1..].iter().map(|handle| handle.index).collect();
+        finest.lod_group = Some(lod_group);
+        self.lod_groups.push(handles.clone());
+
+        Ok(handles[0])
+    }
+
+    // Uploads a mesh in one go, into the first space of the pool it fits in
+    fn add_to_pool(
+        &mut self,
+        uploader: &mut UploadRingBuffer,
+        dependent_queue: Option<&CommandQueue>,
+        mesh: &ObjChunk,
+    ) -> Result<MeshHandle> {
+        ensure!(!mesh.vertices.is_empty(), "Mesh has no vertices");
+        let pool = self.pool.as_mut().context("Mesh pool not created")?;
+        let vertex_pool = self.vertex_buffers[pool.vb_index]
+            .as_ref()
+            .context("Mesh pool vertex buffer deleted")?;
+        let index_pool = self.index_buffers[pool.ib_index]
+            .as_ref()
+            .context("Mesh pool index buffer deleted")?;
+
+        let ranges = pool.reserve_mesh(
+            std::mem::size_of_val(mesh.vertices.as_slice()),
+            std::mem::size_of_val(mesh.indices.as_slice()),
+        )?;
+        if let Err(err) = upload_chunk(
+            uploader,
+            dependent_queue,
+            mesh,
+            (vertex_pool, index_pool),
+            (ranges.0.offset, ranges.1.offset),
+        ) {
+            pool.free_mesh(ranges);
+            return Err(err);
+        }
+
+        let bounds = Aabb::from_points(mesh.vertices.iter().map(|vertex| vertex.position));
+        self.push_pool_mesh(ranges, bounds, None)
+    }
+
+    fn push_pool_mesh(
+        &mut self,
+        (vertex_range, index_range): (HeapAllocation, HeapAllocation),
+        bounds: Aabb,
+        cpu_mesh: Option<CpuMesh>,
+    ) -> Result<MeshHandle> {
+        let pool = self.pool.as_mut().context("Mesh pool not created")?;
+        let vertex_pool = self.vertex_buffers[pool.vb_index]
+            .as_ref()
+            .context("Mesh pool vertex buffer deleted")?;
+        let index_pool = self.index_buffers[pool.ib_index]
+            .as_ref()
+            .context("Mesh pool index buffer deleted")?;
+
+        let vertex_location = vertex_pool.gpu_address() + vertex_range.offset as u64;
+        let handle = MeshHandle {
+            index: self.meshes.len(),
+            vb_index: pool.vb_index,
+            ib_index: pool.ib_index,
+            num_vertices: index_range.size / std::mem::size_of::<u32>(),
             vbv: Some(D3D12_VERTEX_BUFFER_VIEW {
-                BufferLocation: self.vertex_buffers[self.vertex_buffers.len() - 1].gpu_address(),
-                StrideInBytes: vertex_buffer_stride,
-                SizeInBytes: vertex_buffer_size as u32,
+                BufferLocation: vertex_location,
+                StrideInBytes: std::mem::size_of::<ObjVertex>() as u32,
+                SizeInBytes: vertex_range.size as u32,
             }),
             ibv: Some(D3D12_INDEX_BUFFER_VIEW {
-                BufferLocation: self.index_buffers[self.index_buffers.len() - 1].gpu_address(),
-                SizeInBytes: index_buffer_size as u32,
+                BufferLocation: index_pool.gpu_address() + index_range.offset as u64,
+                SizeInBytes: index_range.size as u32,
                 Format: DXGI_FORMAT_R32_UINT,
             }),
-        })
+            bounds: Some(bounds),
+            topology: PrimitiveTopology::TriangleList,
+            lod_group: None,
+        };
+        if let Some(cpu_mesh) = cpu_mesh {
+            self.cpu_meshes.insert(vertex_location, cpu_mesh);
+        }
+        self.push_mesh(MeshEntry {
+            pool_ranges: Some((vertex_range, index_range)),
+            vertex_location,
+            ..Default::default()
+        });
+
+        Ok(handle)
+    }
+
+    pub fn num_lods(&self, handle: &MeshHandle) -> usize {
+        handle
+            .lod_group
+            .and_then(|lod_group| self.lod_groups.get(lod_group))
+            .filter(|lods| !lods.is_empty())
+            .map_or(1, Vec::len)
+    }
+
+    /// Coarser levels than the mesh has return its coarsest level
+    pub fn get_lod(&self, handle: &MeshHandle, lod: usize) -> MeshHandle {
+        match handle
+            .lod_group
+            .and_then(|lod_group| self.lod_groups.get(lod_group))
+            .filter(|lods| !lods.is_empty())
+        {
+            Some(lods) => lods[lod.min(lods.len() - 1)],
+            None => *handle,
+        }
+    }
+
+    /// Uploads the meshlets of a mesh streamed into the pool, so it can be drawn with the mesh shader
+    pub fn add_meshlets(
+        &mut self,
+        device: &ID3D12Device4,
+        descriptor_manager: &mut DescriptorManager,
+        uploader: &mut UploadRingBuffer,
+        dependent_queue: Option<&CommandQueue>,
+        handle: &MeshHandle,
+        meshlets: &MeshletData,
+    ) -> Result<()> {
+        ensure!(!meshlets.meshlets.is_empty(), "Mesh has no meshlets");
+        let (vertices, base_vertex) =
+            self.pool_vertices(device, descriptor_manager, handle, "Meshlets")?;
+        let vbv = handle.vbv.context("Mesh has no vertex buffer view")?;
+        let vertex_indices: Vec<u32> = meshlets
+            .vertex_indices
+            .iter()
+            .map(|index| index + base_vertex)
+            .collect();
+
+        // The four buffers share one submission of the ring
+        let mut batch = uploader.begin_batch(batch_size(
+            [
+                std::mem::size_of_val(meshlets.meshlets.as_slice()),
+                std::mem::size_of_val(meshlets.bounds.as_slice()),
+                std::mem::size_of_val(vertex_indices.as_slice()),
+                std::mem::size_of_val(meshlets.primitive_indices.as_slice()),
+            ],
+            BUFFER_UPLOAD_ALIGNMENT,
+        ))?;
+        let meshlet_set = MeshletSet {
+            num_meshlets: meshlets.meshlets.len(),
+            vertices,
+            meshlets: self.upload_structured_buffer(
+                device,
+                descriptor_manager,
+                &mut batch,
+                handle.index,
+                &meshlets.meshlets,
+            )?,
+            bounds: self.upload_structured_buffer(
+                device,
+                descriptor_manager,
+                &mut batch,
+                handle.index,
+                &meshlets.bounds,
+            )?,
+            vertex_indices: self.upload_structured_buffer(
+                device,
+                descriptor_manager,
+                &mut batch,
+                handle.index,
+                &vertex_indices,
+            )?,
+            primitive_indices: self.upload_structured_buffer(
+                device,
+                descriptor_manager,
+                &mut batch,
+                handle.index,
+                &meshlets.primitive_indices,
+            )?,
+        };
+        batch.submit(dependent_queue)?;
+
+        self.meshlet_sets.insert(vbv.BufferLocation, meshlet_set);
+
+        Ok(())
+    }
+
+    pub fn get_meshlets(&self, handle: &MeshHandle) -> Option<&MeshletSet> {
+        self.meshlet_sets.get(&handle.vbv?.BufferLocation)
+    }
+
+    /// Uploads the morph targets of a mesh streamed into the pool and creates `num_outputs` vertex
+    /// buffers to blend them into, one for every frame in flight. The returned handle has bounds
+    /// grown to fit the mesh at any weights of at most 1, and replaces `handle`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_morph_targets(
+        &mut self,
+        device: &ID3D12Device4,
+        descriptor_manager: &mut DescriptorManager,
+        uploader: &mut UploadRingBuffer,
+        dependent_queue: Option<&CommandQueue>,
+        handle: &MeshHandle,
+        targets: &[MorphTarget],
+        num_outputs: usize,
+    ) -> Result<MeshHandle> {
+        ensure!(!targets.is_empty(), "Mesh has no morph targets");
+        ensure!(
+            num_outputs > 0,
+            "Morphed meshes need an output vertex buffer"
+        );
+        let (vertices, base_vertex) =
+            self.pool_vertices(device, descriptor_manager, handle, "Morph targets")?;
+        let vbv = handle.vbv.context("Mesh has no vertex buffer view")?;
+        let num_vertices = vbv.SizeInBytes as usize / std::mem::size_of::<ObjVertex>();
+
+        let deltas = pack_morph_deltas(targets, num_vertices)?;
+        let mut batch = uploader.begin_batch(batch_size(
+            [std::mem::size_of_val(deltas.as_slice())],
+            BUFFER_UPLOAD_ALIGNMENT,
+        ))?;
+        let deltas = self.upload_structured_buffer(
+            device,
+            descriptor_manager,
+            &mut batch,
+            handle.index,
+            &deltas,
+        )?;
+        batch.submit(dependent_queue)?;
+
+        let outputs = (0..num_outputs)
+            .map(|_| -> Result<MorphOutput> {
+                let (buffer, allocation) = self.heap.create_freeable_resource(
+                    device,
+                    &D3D12_RESOURCE_DESC {
+                        Flags: D3D12_RESOURCE_FLAG_ALLOW_UNORDERED_ACCESS,
+                        ..buffer_desc(vbv.SizeInBytes as usize)
+                    },
+                    D3D12_RESOURCE_STATE_COMMON,
+                    None,
+                    false,
+                )?;
+                let output = MorphOutput {
+                    buffer: buffer.device_resource.clone(),
+                    uav: create_structured_uav(
+                        device,
+                        descriptor_manager,
+                        &buffer,
+                        std::mem::size_of::<ObjVertex>(),
+                        num_vertices,
+                    )?,
+                    vbv: D3D12_VERTEX_BUFFER_VIEW {
+                        BufferLocation: buffer.gpu_address(),
+                        ..vbv
+                    },
+                };
+                let mesh = &mut self.meshes[handle.index];
+                mesh.buffers.push((buffer, allocation));
+                mesh.descriptors.push(output.uav);
+
+                Ok(output)
+            })
+            .collect::<Result<Vec<MorphOutput>>>()?;
+
+        // At weights of at most 1 no vertex moves further than every target's largest
+        // displacement together
+        let max_displacement: f32 = targets.iter().map(MorphTarget::max_displacement).sum();
+        let bounds = handle.bounds.map(|bounds| Aabb {
+            min: bounds.min - max_displacement,
+            max: bounds.max + max_displacement,
+        });
+        if let Some(lods) = handle
+            .lod_group
+            .and_then(|lod_group| self.lod_groups.get_mut(lod_group))
+        {
+            for lod in lods {
+                lod.bounds = bounds;
+            }
+        }
+
+        self.morph_target_sets.insert(
+            vbv.BufferLocation,
+            MorphTargetSet {
+                num_targets: targets.len(),
+                num_vertices,
+                vertices,
+                base_vertex,
+                deltas,
+                outputs,
+                max_displacement,
+            },
+        );
+
+        Ok(MeshHandle { bounds, ..*handle })
+    }
+
+    pub fn get_morph_targets(&self, handle: &MeshHandle) -> Option<&MorphTargetSet> {
+        self.morph_target_sets.get(&handle.vbv?.BufferLocation)
+    }
+
+    /// The mesh drawn from the vertices blended into `output`, or the mesh itself if it has no
+    /// morph targets. Levels of detail of the returned handle are those of the unmorphed mesh.
+    pub fn morphed(&self, handle: &MeshHandle, output: usize) -> MeshHandle {
+        match self.get_morph_targets(handle) {
+            Some(set) => MeshHandle {
+                vbv: Some(set.outputs[output % set.outputs.len()].vbv),
+                ..*handle
+            },
+            None => *handle,
+        }
+    }
+
+    /// Keeps a copy of a mesh's geometry on the CPU, replacing an earlier one. For meshes that were
+    /// not streamed in with `retain_cpu_copies`.
+    pub fn set_cpu_mesh(&mut self, handle: &MeshHandle, mesh: CpuMesh) -> Result<()> {
+        let vbv = handle.vbv.context("Mesh has no vertex buffer")?;
+        self.cpu_meshes.insert(vbv.BufferLocation, mesh);
+
+        Ok(())
+    }
+
+    pub fn get_cpu_mesh(&self, handle: &MeshHandle) -> Option<&CpuMesh> {
+        self.cpu_meshes.get(&handle.vbv?.BufferLocation)
+    }
+
+    /// Frees the CPU copy of a mesh, queries fall back to what works without one
+    pub fn drop_cpu_mesh(&mut self, handle: &MeshHandle) {
+        if let Some(vbv) = handle.vbv {
+            self.cpu_meshes.remove(&vbv.BufferLocation);
+        }
+    }
+
+    /// Bytes held by CPU copies of meshes
+    pub fn cpu_mesh_bytes(&self) -> usize {
+        self.cpu_meshes
+            .values()
+            .map(|mesh| {
+                std::mem::size_of_val(mesh.positions.as_slice())
+                    + std::mem::size_of_val(mesh.indices.as_slice())
+            })
+            .sum()
     }
 
     pub fn get_buffers(&self, handle: &MeshHandle) -> Result<(&Resource, &Resource)> {
         let vertex_buffer = self
             .vertex_buffers
             .get(handle.vb_index)
+            .and_then(Option::as_ref)
             .context("Invalid vertex buffer handle")?;
 
         let index_buffer = self
             .index_buffers
             .get(handle.ib_index)
+            .and_then(Option::as_ref)
             .context("Invalid vertex buffer handle")?;
 
         Ok((vertex_buffer, index_buffer))
     }
+
+    /// Returns a new reference to the mesh, which has to be unloaded separately
+    pub fn add_ref(&mut self, handle: &MeshHandle) -> Result<MeshHandle> {
+        let owner = self.owner(handle);
+        let ref_count = &mut self
+            .meshes
+            .get_mut(owner)
+            .context("Invalid mesh handle")?
+            .ref_count;
+        ensure!(*ref_count > 0, "Mesh already unloaded");
+        *ref_count += 1;
+
+        Ok(*handle)
+    }
+
+    /// Drops a reference to the mesh. Once there are none left its buffers are deleted as soon as
+    /// `fence_value`, signalled after the last use of the mesh, has completed.
+    pub fn unload(&mut self, handle: MeshHandle, fence_value: u64) -> Result<()> {
+        let owner = self.owner(&handle);
+        let ref_count = &mut self
+            .meshes
+            .get_mut(owner)
+            .context("Invalid mesh handle")?
+            .ref_count;
+        ensure!(*ref_count > 0, "Mesh already unloaded");
+        *ref_count -= 1;
+
+        if *ref_count == 0 {
+            self.deletion_queue.push(fence_value, owner);
+        }
+
+        Ok(())
+    }
+
+    /// Heap usage, counting meshes with buffers of their own. The pool buffers count as one.
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            count: self
+                .vertex_buffers
+                .iter()
+                .filter(|buffer| buffer.is_some())
+                .count(),
+            ..self.heap.stats()
+        }
+    }
+
+    /// Vertex and index bytes of the meshes in the mesh pool, empty without a pool
+    pub fn pool_stats(&self) -> (PoolStats, PoolStats) {
+        match &self.pool {
+            Some(pool) => (pool.vertices.stats(), pool.indices.stats()),
+            None => Default::default(),
+        }
+    }
+
+    /// Deletes unloaded meshes the GPU is done with, freeing their heap and pool space, their
+    /// descriptors and everything kept for them on the CPU
+    pub fn collect_garbage(
+        &mut self,
+        descriptor_manager: &mut DescriptorManager,
+        completed_fence_value: u64,
+    ) {
+        for index in self.deletion_queue.retire(completed_fence_value) {
+            self.delete(descriptor_manager, index);
+        }
+    }
+
+    // An SRV of the whole vertex pool, created the first time it's asked for, and the index of
+    // the mesh's first vertex in it
+    fn pool_vertices(
+        &mut self,
+        device: &ID3D12Device4,
+        descriptor_manager: &mut DescriptorManager,
+        handle: &MeshHandle,
+        feature: &str,
+    ) -> Result<(DescriptorHandle, u32)> {
+        ensure!(
+            self.meshes
+                .get(handle.index)
+                .is_some_and(|mesh| mesh.ref_count > 0),
+            "Mesh already unloaded"
+        );
+        let pool = self.pool.as_mut().context("Mesh pool not created")?;
+        ensure!(
+            handle.vb_index == pool.vb_index,
+            "{} are only supported for meshes in the mesh pool",
+            feature
+        );
+        let vertex_pool = self.vertex_buffers[pool.vb_index]
+            .as_ref()
+            .context("Mesh pool vertex buffer deleted")?;
+        let vbv = handle.vbv.context("Mesh has no vertex buffer view")?;
+
+        let vertex_stride = std::mem::size_of::<ObjVertex>();
+        let vertices = match pool.vertex_srv {
+            Some(vertex_srv) => vertex_srv,
+            None => create_structured_srv(
+                device,
+                descriptor_manager,
+                vertex_pool,
+                vertex_stride,
+                vertex_pool.size / vertex_stride,
+            )?,
+        };
+        pool.vertex_srv = Some(vertices);
+        let base_vertex =
+            ((vbv.BufferLocation - vertex_pool.gpu_address()) as usize / vertex_stride) as u32;
+
+        Ok((vertices, base_vertex))
+    }
+
+    fn push_buffers(
+        &mut self,
+        vertex_buffer: Resource,
+        index_buffer: Resource,
+        allocations: Option<(HeapAllocation, HeapAllocation)>,
+    ) -> usize {
+        self.vertex_buffers.push(Some(vertex_buffer));
+        self.index_buffers.push(Some(index_buffer));
+        self.buffer_allocations.push(allocations);
+
+        self.vertex_buffers.len() - 1
+    }
+
+    // With the reference of whoever added the mesh
+    fn push_mesh(&mut self, mesh: MeshEntry) -> usize {
+        self.meshes.push(MeshEntry {
+            ref_count: 1,
+            ..mesh
+        });
+
+        self.meshes.len() - 1
+    }
+
+    // The GPU must be done with the mesh
+    fn delete(&mut self, descriptor_manager: &mut DescriptorManager, index: usize) {
+        let mesh = std::mem::take(&mut self.meshes[index]);
+
+        if let Some(ranges) = mesh.pool_ranges {
+            if let Some(pool) = &mut self.pool {
+                pool.free_mesh(ranges);
+            }
+        }
+        if let Some(buffer_index) = mesh.buffer_index {
+            self.vertex_buffers[buffer_index] = None;
+            self.index_buffers[buffer_index] = None;
+            if let Some((vertices, indices)) = self.buffer_allocations[buffer_index].take() {
+                self.heap.free(vertices);
+                self.heap.free(indices);
+            }
+        }
+        for (buffer, allocation) in mesh.buffers {
+            drop(buffer);
+            self.heap.free(allocation);
+        }
+        for descriptor in mesh.descriptors {
+            descriptor_manager.free(descriptor);
+        }
+
+        self.meshlet_sets.remove(&mesh.vertex_location);
+        self.morph_target_sets.remove(&mesh.vertex_location);
+        self.cpu_meshes.remove(&mesh.vertex_location);
+        if let Some(lod_group) = mesh.lod_group {
+            self.lod_groups[lod_group].clear();
+        }
+        for lod in mesh.coarser_lods {
+            self.delete(descriptor_manager, lod);
+        }
+    }
+
+    // Levels of detail are counted as references to the finest one
+    fn owner(&self, handle: &MeshHandle) -> usize {
+        handle
+            .lod_group
+            .and_then(|lod_group| self.lod_groups.get(lod_group))
+            .and_then(|lods| lods.first())
+            .map_or(handle.index, |finest| finest.index)
+    }
+
+    fn upload_structured_buffer<T>(
+        &mut self,
+        device: &ID3D12Device4,
+        descriptor_manager: &mut DescriptorManager,
+        batch: &mut UploadBatch,
+        mesh: usize,
+        data: &[T],
+    ) -> Result<DescriptorHandle> {
+        let size = std::mem::size_of_val(data);
+        let (buffer, allocation) = self.heap.create_freeable_resource(
+            device,
+            &buffer_desc(size),
+            D3D12_RESOURCE_STATE_COMMON,
+            None,
+            false,
+        )?;
+
+        batch.upload_buffer(data, &buffer.create_sub_resource(size, 0)?)?;
+
+        let srv = create_structured_srv(
+            device,
+            descriptor_manager,
+            &buffer,
+            std::mem::size_of::<T>(),
+            data.len(),
+        )?;
+        let mesh = &mut self.meshes[mesh];
+        mesh.buffers.push((buffer, allocation));
+        mesh.descriptors.push(srv);
+
+        Ok(srv)
+    }
+}
+
+// Copies the vertices and indices of a chunk to the offsets of the vertex and index buffers
+fn upload_chunk(
+    uploader: &mut UploadRingBuffer,
+    dependent_queue: Option<&CommandQueue>,
+    chunk: &ObjChunk,
+    (vertex_buffer, index_buffer): (&Resource, &Resource),
+    (vertex_offset, index_offset): (usize, usize),
+) -> Result<()> {
+    let vertex_bytes = std::mem::size_of_val(chunk.vertices.as_slice());
+    let index_bytes = std::mem::size_of_val(chunk.indices.as_slice());
+
+    let upload = uploader.allocate(vertex_bytes + index_bytes)?;
+    upload.sub_resource.copy_from(&chunk.vertices)?;
+    upload
+        .sub_resource
+        .copy_to_offset_from(vertex_bytes, &chunk.indices)?;
+
+    let staging = upload.sub_resource.resource;
+    staging
+        .create_sub_resource(vertex_bytes, upload.sub_resource.offset)?
+        .copy_to_sub_resource(
+            &upload.command_list,
+            &vertex_buffer.create_sub_resource(vertex_bytes, vertex_offset)?,
+        )?;
+    staging
+        .create_sub_resource(index_bytes, upload.sub_resource.offset + vertex_bytes)?
+        .copy_to_sub_resource(
+            &upload.command_list,
+            &index_buffer.create_sub_resource(index_bytes, index_offset)?,
+        )?;
+    upload.submit(dependent_queue)
+}
+
+fn create_structured_srv(
+    device: &ID3D12Device4,
+    descriptor_manager: &mut DescriptorManager,
+    buffer: &Resource,
+    stride: usize,
+    num_elements: usize,
+) -> Result<DescriptorHandle> {
+    let srv = descriptor_manager.allocate(DescriptorType::Resource)?;
+
+    unsafe {
+        device.CreateShaderResourceView(
+            &buffer.device_resource,
+            &D3D12_SHADER_RESOURCE_VIEW_DESC {
+                Format: DXGI_FORMAT_UNKNOWN,
+                ViewDimension: D3D12_SRV_DIMENSION_BUFFER,
+                Shader4ComponentMapping: D3D12_DEFAULT_SHADER_4_COMPONENT_MAPPING,
+                Anonymous: D3D12_SHADER_RESOURCE_VIEW_DESC_0 {
+                    Buffer: D3D12_BUFFER_SRV {
+                        FirstElement: 0,
+                        NumElements: num_elements as u32,
+                        StructureByteStride: stride as u32,
+                        Flags: D3D12_BUFFER_SRV_FLAG_NONE,
+                    },
+                },
+            },
+            descriptor_manager.get_cpu_handle(&srv)?,
+        );
+    }
+
+    Ok(srv)
+}
+
+fn create_structured_uav(
+    device: &ID3D12Device4,
+    descriptor_manager: &mut DescriptorManager,
+    buffer: &Resource,
+    stride: usize,
+    num_elements: usize,
+) -> Result<DescriptorHandle> {
+    let uav = descriptor_manager.allocate(DescriptorType::Resource)?;
+
+    unsafe {
+        device.CreateUnorderedAccessView(
+            &buffer.device_resource,
+            None,
+            &D3D12_UNORDERED_ACCESS_VIEW_DESC {
+                Format: DXGI_FORMAT_UNKNOWN,
+                ViewDimension: D3D12_UAV_DIMENSION_BUFFER,
+                Anonymous: D3D12_UNORDERED_ACCESS_VIEW_DESC_0 {
+                    Buffer: D3D12_BUFFER_UAV {
+                        FirstElement: 0,
+                        NumElements: num_elements as u32,
+                        StructureByteStride: stride as u32,
+                        CounterOffsetInBytes: 0,
+                        Flags: D3D12_BUFFER_UAV_FLAG_NONE,
+                    },
+                },
+            },
+            descriptor_manager.get_cpu_handle(&uav)?,
+        );
+    }
+
+    Ok(uav)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VERTEX_SIZE: usize = std::mem::size_of::<ObjVertex>();
+    const INDEX_SIZE: usize = std::mem::size_of::<u32>();
+
+    fn pool() -> MeshPool {
+        MeshPool {
+            vb_index: 0,
+            ib_index: 0,
+            vertices: RangeAllocator::new(100 * VERTEX_SIZE),
+            indices: RangeAllocator::new(300 * INDEX_SIZE),
+            vertex_srv: None,
+        }
+    }
+
+    fn stream(pool: &mut MeshPool, chunks: &[usize]) -> (HeapAllocation, HeapAllocation) {
+        let mut ranges = pool.begin_mesh().unwrap();
+        for &num_vertices in chunks {
+            pool.grow_mesh(
+                &mut ranges,
+                num_vertices * VERTEX_SIZE,
+                num_vertices * INDEX_SIZE,
+            )
+            .unwrap();
+        }
+
+        ranges
+    }
+
+    #[test]
+    fn unloaded_meshes_make_room_for_new_ones() {
+        let mut pool = pool();
+        let first = stream(&mut pool, &[30, 30]);
+        let second = stream(&mut pool, &[30]);
+        assert_eq!(first.0.size, 60 * VERTEX_SIZE);
+        assert_eq!(second.0.offset, 60 * VERTEX_SIZE);
+        assert!(pool
+            .reserve_mesh(20 * VERTEX_SIZE, 20 * INDEX_SIZE)
+            .is_err());
+
+        // A reloaded mesh goes where the unloaded one was
+        pool.free_mesh(first);
+        let reloaded = pool
+            .reserve_mesh(60 * VERTEX_SIZE, 60 * INDEX_SIZE)
+            .unwrap();
+        assert_eq!(reloaded, first);
+        assert_eq!(pool.vertices.stats().count, 2);
+
+        // Streamed meshes don't fit in gaps, but get the end of the pool back
+        pool.free_mesh(second);
+        assert_eq!(stream(&mut pool, &[40]).0.offset, 60 * VERTEX_SIZE);
+    }
+
+    #[test]
+    fn streamed_meshes_stop_at_the_end_of_the_pool() {
+        let mut pool = pool();
+        let mut ranges = pool.begin_mesh().unwrap();
+        assert!(pool
+            .grow_mesh(&mut ranges, 101 * VERTEX_SIZE, INDEX_SIZE)
+            .is_err());
+
+        pool.free_mesh(ranges);
+        assert_eq!(pool.vertices.used_size(), 0);
+    }
 }
@@ -0,0 +1,262 @@
+use std::ffi::c_void;
+
+use anyhow::{ensure, Result};
+use glam::Vec3;
+use windows::Win32::Graphics::Direct3D12::*;
+
+use crate::{Aabb, ObjVertex};
+
+// Matches the limits of the mesh shader in bindless_texture.hlsl
+pub const MAX_MESHLET_VERTICES: usize = 64;
+pub const MAX_MESHLET_TRIANGLES: usize = 126;
+
+/// Ranges into `MeshletData::vertex_indices` and `MeshletData::primitive_indices`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Meshlet {
+    pub vertex_offset: u32,
+    pub vertex_count: u32,
+    pub triangle_offset: u32,
+    pub triangle_count: u32,
+}
+
+/// Bounding sphere and normal cone of a meshlet, in object space
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeshletBounds {
+    pub center: Vec3,
+    pub radius: f32,
+    pub cone_axis: Vec3,
+    /// 1 when the normals are spread too far for the meshlet to ever be backfacing
+    pub cone_cutoff: f32,
+}
+
+impl MeshletBounds {
+    /// True when every triangle faces away from `camera_position`, same test as the
+    /// amplification shader
+    pub fn is_backfacing(&self, camera_position: Vec3) -> bool {
+        let to_center = self.center - camera_position;
+
+        to_center.dot(self.cone_axis) >= self.cone_cutoff * to_center.length() + self.radius
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MeshletData {
+    pub meshlets: Vec<Meshlet>,
+    pub bounds: Vec<MeshletBounds>,
+    /// Indices into the mesh's vertex buffer
+    pub vertex_indices: Vec<u32>,
+    /// Three 8 bit indices into the meshlet's vertices per triangle
+    pub primitive_indices: Vec<u32>,
+}
+
+/// Splits an indexed triangle list into meshlets of at most `MAX_MESHLET_VERTICES` vertices and
+/// `MAX_MESHLET_TRIANGLES` triangles, filling each one in index order
+pub fn build_meshlets(vertices: &[ObjVertex], indices: &[u32]) -> Result<MeshletData> {
+    ensure!(
+        indices.len().is_multiple_of(3),
+        "Index count is not a multiple of 3"
+    );
+    ensure!(
+        indices
+            .iter()
+            .all(|&index| (index as usize) < vertices.len()),
+        "Index out of range"
+    );
+
+    let mut data = MeshletData::default();
+    // Index of each mesh vertex in the meshlet being built
+    let mut local_indices = vec![u32::MAX; vertices.len()];
+    let mut meshlet = Meshlet {
+        vertex_offset: 0,
+        vertex_count: 0,
+        triangle_offset: 0,
+        triangle_count: 0,
+    };
+
+    for triangle in indices.chunks_exact(3) {
+        let new_vertices = triangle
+            .iter()
+            .filter(|&&index| local_indices[index as usize] == u32::MAX)
+            .count();
+        if meshlet.vertex_count as usize + new_vertices > MAX_MESHLET_VERTICES
+            || meshlet.triangle_count as usize + 1 > MAX_MESHLET_TRIANGLES
+        {
+            finish_meshlet(&mut data, &mut meshlet, &mut local_indices, vertices);
+        }
+
+        let mut primitive = 0;
+        for (corner, &index) in triangle.iter().enumerate() {
+            if local_indices[index as usize] == u32::MAX {
+                local_indices[index as usize] = meshlet.vertex_count;
+                data.vertex_indices.push(index);
+                meshlet.vertex_count += 1;
+            }
+            primitive |= local_indices[index as usize] << (corner * 8);
+        }
+        data.primitive_indices.push(primitive);
+        meshlet.triangle_count += 1;
+    }
+    if meshlet.triangle_count > 0 {
+        finish_meshlet(&mut data, &mut meshlet, &mut local_indices, vertices);
+    }
+
+    Ok(data)
+}
+
+fn finish_meshlet(
+    data: &mut MeshletData,
+    meshlet: &mut Meshlet,
+    local_indices: &mut [u32],
+    vertices: &[ObjVertex],
+) {
+    let meshlet_vertices = &data.vertex_indices[meshlet.vertex_offset as usize..];
+    let position =
+        |local_index: u32| vertices[meshlet_vertices[local_index as usize] as usize].position;
+
+    let aabb = Aabb::from_points(
+        meshlet_vertices
+            .iter()
+            .map(|&i| vertices[i as usize].position),
+    );
+    let center = aabb.center();
+    let radius = meshlet_vertices
+        .iter()
+        .map(|&i| vertices[i as usize].position.distance(center))
+        .fold(0.0, f32::max);
+
+    let normals: Vec<Vec3> = data.primitive_indices[meshlet.triangle_offset as usize..]
+        .iter()
+        .map(|primitive| {
+            let a = position(primitive & 0xff);
+            let b = position((primitive >> 8) & 0xff);
+            let c = position((primitive >> 16) & 0xff);
+            (b - a).cross(c - a).normalize_or_zero()
+        })
+        .filter(|normal| *normal != Vec3::ZERO)
+        .collect();
+    let cone_axis = normals.iter().sum::<Vec3>().normalize_or_zero();
+    let min_dot = normals
+        .iter()
+        .map(|normal| normal.dot(cone_axis))
+        .fold(1.0, f32::min);
+    let cone_cutoff = if cone_axis == Vec3::ZERO || min_dot <= 0.0 {
+        1.0
+    } else {
+        (1.0 - min_dot * min_dot).sqrt()
+    };
+
+    for &index in meshlet_vertices {
+        local_indices[index as usize] = u32::MAX;
+    }
+    data.meshlets.push(*meshlet);
+    data.bounds.push(MeshletBounds {
+        center,
+        radius,
+        cone_axis,
+        cone_cutoff,
+    });
+
+    *meshlet = Meshlet {
+        vertex_offset: data.vertex_indices.len() as u32,
+        vertex_count: 0,
+        triangle_offset: data.primitive_indices.len() as u32,
+        triangle_count: 0,
+    };
+}
+
+/// Mesh and amplification shaders need mesh shader tier 1
+pub fn mesh_shaders_supported(device: &ID3D12Device4) -> bool {
+    let mut options = D3D12_FEATURE_DATA_D3D12_OPTIONS7::default();
+    let queried = unsafe {
+        device.CheckFeatureSupport(
+            D3D12_FEATURE_D3D12_OPTIONS7,
+            std::ptr::addr_of_mut!(options) as *mut c_void,
+            std::mem::size_of_val(&options) as u32,
+        )
+    };
+
+    queried.is_ok() && options.MeshShaderTier.0 >= D3D12_MESH_SHADER_TIER_1.0
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+
+    use super::*;
+
+    fn grid_mesh(size: u32) -> (Vec<ObjVertex>, Vec<u32>) {
+        let vertices = (0..=size)
+            .flat_map(|y| (0..=size).map(move |x| (x, y)))
+            .map(|(x, y)| ObjVertex {
+                position: Vec3::new(x as f32, y as f32, 0.0),
+                normal: Vec3::Z,
+                uv: Vec2::new(x as f32, y as f32) / size as f32,
+            })
+            .collect();
+        let indices = (0..size)
+            .flat_map(|y| (0..size).map(move |x| (x, y)))
+            .flat_map(|(x, y)| {
+                let i = y * (size + 1) + x;
+                [i, i + 1, i + size + 1, i + 1, i + size + 2, i + size + 1]
+            })
+            .collect();
+
+        (vertices, indices)
+    }
+
+    #[test]
+    fn meshlets_respect_limits_and_cover_mesh() {
+        let (vertices, indices) = grid_mesh(32);
+        let data = build_meshlets(&vertices, &indices).unwrap();
+
+        assert!(data.meshlets.len() > 1);
+        assert_eq!(data.meshlets.len(), data.bounds.len());
+
+        let mut triangles = Vec::new();
+        for meshlet in &data.meshlets {
+            assert!(meshlet.vertex_count as usize <= MAX_MESHLET_VERTICES);
+            assert!(meshlet.triangle_count as usize <= MAX_MESHLET_TRIANGLES);
+
+            let start = meshlet.triangle_offset as usize;
+            let end = start + meshlet.triangle_count as usize;
+            for primitive in &data.primitive_indices[start..end] {
+                let corners = [primitive & 0xff, (primitive >> 8) & 0xff, primitive >> 16];
+                assert!(corners.iter().all(|&corner| corner < meshlet.vertex_count));
+                triangles.extend(
+                    corners.map(|corner| {
+                        data.vertex_indices[(meshlet.vertex_offset + corner) as usize]
+                    }),
+                );
+            }
+        }
+        assert_eq!(triangles, indices);
+    }
+
+    #[test]
+    fn flat_meshlet_cone_culls_from_behind() {
+        let (vertices, indices) = grid_mesh(4);
+        let data = build_meshlets(&vertices, &indices).unwrap();
+
+        assert_eq!(data.meshlets.len(), 1);
+        let bounds = data.bounds[0];
+        assert!(bounds.cone_axis.abs_diff_eq(Vec3::Z, 1e-5));
+        assert!(!bounds.is_backfacing(Vec3::new(2.0, 2.0, 5.0)));
+        assert!(bounds.is_backfacing(Vec3::new(2.0, 2.0, -5.0)));
+    }
+
+    #[test]
+    fn opposing_normals_are_never_culled() {
+        let (mut vertices, mut indices) = grid_mesh(1);
+        let base = vertices.len() as u32;
+        vertices.extend(grid_mesh(1).0);
+        // The same quad wound the other way
+        indices.extend([base, base + 2, base + 1]);
+
+        let bounds = build_meshlets(&vertices, &indices).unwrap().bounds[0];
+        assert_eq!(bounds.cone_cutoff, 1.0);
+        assert!(!bounds.is_backfacing(Vec3::new(0.5, 0.5, -5.0)));
+        assert!(!bounds.is_backfacing(Vec3::new(0.5, 0.5, 5.0)));
+    }
+}
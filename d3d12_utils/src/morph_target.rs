@@ -0,0 +1,201 @@
+use anyhow::{ensure, Result};
+use glam::Vec3;
+use windows::Win32::Graphics::Direct3D12::{ID3D12Resource, D3D12_VERTEX_BUFFER_VIEW};
+
+use crate::{DescriptorHandle, ObjVertex};
+
+/// How far every vertex of a mesh moves towards one shape, added to the mesh scaled by the
+/// target's weight. Blend shapes for faces, corrective shapes and the like.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MorphTarget {
+    pub position_deltas: Vec<Vec3>,
+    /// Added before the blended normal is normalized again
+    pub normal_deltas: Vec<Vec3>,
+}
+
+impl MorphTarget {
+    /// The target that moves `base` onto `shape`, which has the same vertices in the same order
+    pub fn from_shape(base: &[ObjVertex], shape: &[ObjVertex]) -> Result<Self> {
+        ensure!(
+            base.len() == shape.len(),
+            "The shape has {} vertices, the mesh {}",
+            shape.len(),
+            base.len()
+        );
+
+        Ok(Self {
+            position_deltas: base
+                .iter()
+                .zip(shape)
+                .map(|(base, shape)| shape.position - base.position)
+                .collect(),
+            normal_deltas: base
+                .iter()
+                .zip(shape)
+                .map(|(base, shape)| shape.normal - base.normal)
+                .collect(),
+        })
+    }
+
+    /// The furthest any vertex moves at a weight of 1
+    pub fn max_displacement(&self) -> f32 {
+        self.position_deltas
+            .iter()
+            .map(|delta| delta.length())
+            .fold(0.0, f32::max)
+    }
+}
+
+/// A vertex's offsets for one target, as the blend shader reads them
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MorphDelta {
+    pub position: Vec3,
+    pub normal: Vec3,
+}
+
+/// The deltas of every target one after the other, each with one for every one of the
+/// `num_vertices` vertices of the mesh
+pub fn pack_morph_deltas(targets: &[MorphTarget], num_vertices: usize) -> Result<Vec<MorphDelta>> {
+    let mut deltas = Vec::with_capacity(targets.len() * num_vertices);
+    for (index, target) in targets.iter().enumerate() {
+        ensure!(
+            target.position_deltas.len() == num_vertices
+                && target.normal_deltas.len() == num_vertices,
+            "Morph target {} doesn't have deltas for all {} vertices",
+            index,
+            num_vertices
+        );
+        deltas.extend(
+            target
+                .position_deltas
+                .iter()
+                .zip(&target.normal_deltas)
+                .map(|(&position, &normal)| MorphDelta { position, normal }),
+        );
+    }
+
+    Ok(deltas)
+}
+
+/// What the blend shader writes for `base` at `weights`, one for each target, for CPU side
+/// queries against a morphed mesh. Missing weights count as 0.
+pub fn morph_vertices(
+    base: &[ObjVertex],
+    targets: &[MorphTarget],
+    weights: &[f32],
+) -> Vec<ObjVertex> {
+    base.iter()
+        .enumerate()
+        .map(|(index, vertex)| {
+            let (position, normal) = targets.iter().zip(weights).fold(
+                (vertex.position, vertex.normal),
+                |(position, normal), (target, &weight)| {
+                    (
+                        position + target.position_deltas[index] * weight,
+                        normal + target.normal_deltas[index] * weight,
+                    )
+                },
+            );
+
+            ObjVertex {
+                position,
+                normal: normal.normalize_or_zero(),
+                uv: vertex.uv,
+            }
+        })
+        .collect()
+}
+
+/// One of the vertex buffers the blended vertices of a mesh are written to
+#[derive(Debug, Clone)]
+pub struct MorphOutput {
+    pub buffer: ID3D12Resource,
+    /// Structured, for the blend shader to write
+    pub uav: DescriptorHandle,
+    pub vbv: D3D12_VERTEX_BUFFER_VIEW,
+}
+
+/// The morph targets of a mesh in the pool and the buffers blending them writes to. There is an
+/// output for every frame in flight, so a frame can write its own while the GPU still draws the
+/// vertices of the previous one.
+#[derive(Debug, Clone)]
+pub struct MorphTargetSet {
+    pub num_targets: usize,
+    pub num_vertices: usize,
+    /// The whole vertex pool, the mesh's vertices start at `base_vertex`
+    pub vertices: DescriptorHandle,
+    pub base_vertex: u32,
+    /// `MorphDelta`s as laid out by `pack_morph_deltas`
+    pub deltas: DescriptorHandle,
+    pub outputs: Vec<MorphOutput>,
+    /// How much further than the mesh's own bounds the targets reach at weights of at most 1
+    pub max_displacement: f32,
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+
+    use super::*;
+
+    fn vertex(position: Vec3) -> ObjVertex {
+        ObjVertex {
+            position,
+            normal: Vec3::Y,
+            uv: Vec2::ZERO,
+        }
+    }
+
+    #[test]
+    fn targets_reach_their_shape_at_full_weight() {
+        let base = [vertex(Vec3::ZERO), vertex(Vec3::X)];
+        let shape = [vertex(Vec3::new(0.0, 2.0, 0.0)), vertex(Vec3::X)];
+        let target = MorphTarget::from_shape(&base, &shape).unwrap();
+        assert_eq!(target.max_displacement(), 2.0);
+
+        let morphed = morph_vertices(&base, std::slice::from_ref(&target), &[0.5]);
+        assert_eq!(morphed[0].position, Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(morphed[1].position, Vec3::X);
+
+        let morphed = morph_vertices(&base, &[target], &[1.0]);
+        assert_eq!(morphed, shape);
+    }
+
+    #[test]
+    fn weights_add_up_and_normals_stay_unit_length() {
+        let base = [vertex(Vec3::ZERO)];
+        let up = MorphTarget {
+            position_deltas: vec![Vec3::Y],
+            normal_deltas: vec![Vec3::ZERO],
+        };
+        let tilt = MorphTarget {
+            position_deltas: vec![Vec3::X],
+            normal_deltas: vec![Vec3::X],
+        };
+
+        let morphed = morph_vertices(&base, &[up, tilt], &[1.0, 1.0]);
+        assert_eq!(morphed[0].position, Vec3::new(1.0, 1.0, 0.0));
+        assert!((morphed[0].normal.length() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn packs_targets_one_after_the_other() {
+        let target = |delta: Vec3| MorphTarget {
+            position_deltas: vec![delta; 2],
+            normal_deltas: vec![Vec3::ZERO; 2],
+        };
+
+        let deltas = pack_morph_deltas(&[target(Vec3::X), target(Vec3::Y)], 2).unwrap();
+        assert_eq!(deltas.len(), 4);
+        assert_eq!(deltas[1].position, Vec3::X);
+        assert_eq!(deltas[2].position, Vec3::Y);
+        assert!(pack_morph_deltas(&[target(Vec3::X)], 3).is_err());
+    }
+
+    #[test]
+    fn shapes_need_the_same_vertices() {
+        let base = [vertex(Vec3::ZERO)];
+        assert!(MorphTarget::from_shape(&base, &[]).is_err());
+    }
+}
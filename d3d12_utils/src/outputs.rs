@@ -0,0 +1,333 @@
+use anyhow::{ensure, Result};
+use glam::Vec2;
+use windows::{
+    core::Interface,
+    Win32::{
+        Foundation::{HWND, RECT},
+        Graphics::Dxgi::{Common::*, *},
+        UI::WindowsAndMessaging::{GetWindowRect, SetWindowPos, SWP_NOSIZE, SWP_NOZORDER},
+    },
+};
+
+/// A resolution and refresh rate a monitor can be switched to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayMode {
+    pub width: u32,
+    pub height: u32,
+    pub refresh_numerator: u32,
+    pub refresh_denominator: u32,
+}
+
+impl DisplayMode {
+    pub fn refresh_rate(&self) -> f32 {
+        self.refresh_numerator as f32 / self.refresh_denominator.max(1) as f32
+    }
+
+    pub fn desc(&self, format: DXGI_FORMAT) -> DXGI_MODE_DESC {
+        DXGI_MODE_DESC {
+            Width: self.width,
+            Height: self.height,
+            RefreshRate: DXGI_RATIONAL {
+                Numerator: self.refresh_numerator,
+                Denominator: self.refresh_denominator,
+            },
+            Format: format,
+            ..Default::default()
+        }
+    }
+}
+
+/// How a monitor is turned against its native orientation, e.g. a landscape panel stood up as a
+/// portrait monitor. The desktop is laid out turned, display modes are not.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DisplayRotation {
+    #[default]
+    Identity,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+impl DisplayRotation {
+    pub fn from_dxgi(rotation: DXGI_MODE_ROTATION) -> Self {
+        match rotation {
+            DXGI_MODE_ROTATION_ROTATE90 => Self::Rotate90,
+            DXGI_MODE_ROTATION_ROTATE180 => Self::Rotate180,
+            DXGI_MODE_ROTATION_ROTATE270 => Self::Rotate270,
+            _ => Self::Identity,
+        }
+    }
+
+    /// Clockwise quarter turns from the desktop image to the native scan out
+    pub fn quarter_turns(self) -> u32 {
+        self as u32
+    }
+
+    pub fn swaps_axes(self) -> bool {
+        self.quarter_turns() % 2 == 1
+    }
+
+    /// Size of the native scan out, and of non-prerotated back buffers, for a desktop `extent`
+    pub fn native_extent(self, extent: (u32, u32)) -> (u32, u32) {
+        let (width, height) = extent;
+        if self.swaps_axes() {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    /// The uv of the desktop image that ends up at `native_uv` of a non-prerotated back buffer,
+    /// both going from (0, 0) at the top left to (1, 1) at the bottom right. `DesktopUv` in
+    /// rotation.hlsli does the same on the GPU.
+    pub fn desktop_uv(self, native_uv: Vec2) -> Vec2 {
+        let Vec2 { x: u, y: v } = native_uv;
+        match self {
+            Self::Identity => Vec2::new(u, v),
+            Self::Rotate90 => Vec2::new(v, 1.0 - u),
+            Self::Rotate180 => Vec2::new(1.0 - u, 1.0 - v),
+            Self::Rotate270 => Vec2::new(1.0 - v, u),
+        }
+    }
+}
+
+/// The fastest mode at `extent`, or the largest and then fastest one when none has that size
+pub fn best_display_mode(modes: &[DisplayMode], extent: (u32, u32)) -> Option<DisplayMode> {
+    let by_refresh_rate =
+        |a: &&DisplayMode, b: &&DisplayMode| a.refresh_rate().total_cmp(&b.refresh_rate());
+
+    modes
+        .iter()
+        .filter(|mode| (mode.width, mode.height) == extent)
+        .max_by(by_refresh_rate)
+        .or_else(|| {
+            modes.iter().max_by(|a, b| {
+                let area = |mode: &DisplayMode| mode.width as u64 * mode.height as u64;
+                area(a).cmp(&area(b)).then(by_refresh_rate(a, b))
+            })
+        })
+        .copied()
+}
+
+/// A monitor connected to an adapter
+#[derive(Debug, Clone)]
+pub struct Output {
+    pub output: IDXGIOutput6,
+    /// What Windows calls the monitor, e.g. \\.\DISPLAY1
+    pub name: String,
+    /// Where the monitor sits on the desktop, in pixels
+    pub desktop_coordinates: RECT,
+    /// Whether the monitor is in HDR10 mode right now
+    pub hdr: bool,
+    pub rotation: DisplayRotation,
+    /// In nits
+    pub max_luminance: f32,
+    /// Modes for the format the output was enumerated with
+    pub display_modes: Vec<DisplayMode>,
+}
+
+impl Output {
+    pub fn new(output: IDXGIOutput6, format: DXGI_FORMAT) -> Result<Self> {
+        let desc = unsafe { output.GetDesc1()? };
+        let name_length = desc
+            .DeviceName
+            .iter()
+            .position(|&c| c == 0)
+            .unwrap_or(desc.DeviceName.len());
+
+        let mut mode_count = 0;
+        unsafe {
+            output.GetDisplayModeList1(format, 0, &mut mode_count, std::ptr::null_mut())?;
+        }
+        let mut modes = vec![DXGI_MODE_DESC1::default(); mode_count as usize];
+        unsafe {
+            output.GetDisplayModeList1(format, 0, &mut mode_count, modes.as_mut_ptr())?;
+        }
+        modes.truncate(mode_count as usize);
+
+        Ok(Output {
+            name: String::from_utf16_lossy(&desc.DeviceName[..name_length]),
+            desktop_coordinates: desc.DesktopCoordinates,
+            hdr: desc.ColorSpace == DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020,
+            rotation: DisplayRotation::from_dxgi(desc.Rotation),
+            max_luminance: desc.MaxLuminance,
+            display_modes: modes
+                .iter()
+                .map(|mode| DisplayMode {
+                    width: mode.Width,
+                    height: mode.Height,
+                    refresh_numerator: mode.RefreshRate.Numerator,
+                    refresh_denominator: mode.RefreshRate.Denominator,
+                })
+                .collect(),
+            output,
+        })
+    }
+
+    /// Desktop resolution of the monitor
+    pub fn extent(&self) -> (u32, u32) {
+        let RECT {
+            left,
+            top,
+            right,
+            bottom,
+        } = self.desktop_coordinates;
+
+        ((right - left) as u32, (bottom - top) as u32)
+    }
+
+    /// Desktop resolution of the monitor in its native orientation, which display modes are in
+    pub fn native_extent(&self) -> (u32, u32) {
+        self.rotation.native_extent(self.extent())
+    }
+
+    /// Refresh rates available at the desktop resolution, fastest first
+    pub fn refresh_rates(&self) -> Vec<f32> {
+        let mut refresh_rates: Vec<f32> = self
+            .display_modes
+            .iter()
+            .filter(|mode| (mode.width, mode.height) == self.native_extent())
+            .map(DisplayMode::refresh_rate)
+            .collect();
+        refresh_rates.sort_by(|a, b| b.total_cmp(a));
+        refresh_rates.dedup_by(|a, b| (*a - *b).abs() < 0.01);
+
+        refresh_rates
+    }
+
+    /// The mode to go fullscreen with, keeping the desktop resolution
+    pub fn fullscreen_mode(&self) -> Option<DisplayMode> {
+        best_display_mode(&self.display_modes, self.native_extent())
+    }
+
+    /// Moves a window to the middle of the monitor, keeping its size
+    pub fn center_window(&self, hwnd: HWND) -> Result<()> {
+        let mut window = RECT::default();
+        ensure!(
+            unsafe { GetWindowRect(hwnd, &mut window) }.as_bool(),
+            "Failed to get the window rectangle"
+        );
+
+        let (width, height) = (window.right - window.left, window.bottom - window.top);
+        let (output_width, output_height) = self.extent();
+        let x = self.desktop_coordinates.left + (output_width as i32 - width) / 2;
+        let y = self.desktop_coordinates.top + (output_height as i32 - height) / 2;
+        ensure!(
+            unsafe {
+                SetWindowPos(
+                    hwnd,
+                    HWND::default(),
+                    x.max(self.desktop_coordinates.left),
+                    y.max(self.desktop_coordinates.top),
+                    0,
+                    0,
+                    SWP_NOSIZE | SWP_NOZORDER,
+                )
+            }
+            .as_bool(),
+            "Failed to move the window to {}",
+            self.name
+        );
+
+        Ok(())
+    }
+}
+
+/// Every monitor connected to `adapter`, with display modes for `format`
+pub fn enumerate_outputs(adapter: &IDXGIAdapter1, format: DXGI_FORMAT) -> Result<Vec<Output>> {
+    let mut outputs = vec![];
+    for i in 0.. {
+        let output = match unsafe { adapter.EnumOutputs(i) } {
+            Ok(output) => output,
+            Err(error) if error.code() == DXGI_ERROR_NOT_FOUND => break,
+            Err(error) => return Err(error.into()),
+        };
+        outputs.push(Output::new(output.cast()?, format)?);
+    }
+
+    Ok(outputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mode(width: u32, height: u32, refresh_numerator: u32) -> DisplayMode {
+        DisplayMode {
+            width,
+            height,
+            refresh_numerator,
+            refresh_denominator: 1000,
+        }
+    }
+
+    #[test]
+    fn picks_fastest_mode_at_desktop_resolution() {
+        let modes = [
+            mode(1920, 1080, 60_000),
+            mode(2560, 1440, 59_951),
+            mode(2560, 1440, 143_998),
+            mode(2560, 1440, 120_000),
+            mode(3840, 2160, 60_000),
+        ];
+
+        assert_eq!(
+            best_display_mode(&modes, (2560, 1440)),
+            Some(mode(2560, 1440, 143_998))
+        );
+        // Falls back to the largest mode
+        assert_eq!(
+            best_display_mode(&modes, (1280, 720)),
+            Some(mode(3840, 2160, 60_000))
+        );
+        assert_eq!(best_display_mode(&[], (1280, 720)), None);
+    }
+
+    #[test]
+    fn rotated_displays_turn_uvs_clockwise() {
+        let top_left = Vec2::new(0.0, 0.0);
+        let top_right = Vec2::new(1.0, 0.0);
+
+        // A quarter turn puts the top left of the desktop image at the top right of the scan out
+        assert_eq!(DisplayRotation::Rotate90.desktop_uv(top_right), top_left);
+        assert_eq!(DisplayRotation::Rotate180.desktop_uv(top_right), Vec2::Y);
+        assert_eq!(
+            DisplayRotation::Rotate270.desktop_uv(top_right),
+            Vec2::new(1.0, 1.0)
+        );
+
+        // Two quarter turns are a half turn
+        let uv = Vec2::new(0.25, 0.75);
+        let quarter = DisplayRotation::Rotate90;
+        assert_eq!(
+            quarter.desktop_uv(quarter.desktop_uv(uv)),
+            DisplayRotation::Rotate180.desktop_uv(uv)
+        );
+
+        assert_eq!(
+            DisplayRotation::Rotate270.native_extent((1080, 1920)),
+            (1920, 1080)
+        );
+        assert_eq!(
+            DisplayRotation::Rotate180.native_extent((1080, 1920)),
+            (1080, 1920)
+        );
+    }
+
+    #[test]
+    fn refresh_rate_divides_rational() {
+        let mode = DisplayMode {
+            width: 1920,
+            height: 1080,
+            refresh_numerator: 60_000,
+            refresh_denominator: 1001,
+        };
+        assert!((mode.refresh_rate() - 59.94).abs() < 0.01);
+
+        let unknown = DisplayMode {
+            refresh_denominator: 0,
+            ..mode
+        };
+        assert_eq!(unknown.refresh_rate(), 60_000.0);
+    }
+}
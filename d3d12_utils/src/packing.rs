@@ -0,0 +1,255 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use glam::{Vec2, Vec3, Vec4};
+
+/// Decoders for everything packed here, written out by `write_packing_header` for shaders to
+/// include
+pub const PACKING_HLSL: &str = r#"// Generated by d3d12_utils::write_packing_header, do not edit. Decodes what packing.rs packs.
+#pragma once
+
+float2 UnpackHalf2(uint packed)
+{
+    return f16tof32(uint2(packed, packed >> 16));
+}
+
+float2 UnpackSnorm2x16(uint packed)
+{
+    int2 value = asint(uint2(packed << 16, packed)) >> 16;
+
+    return max(float2(value) / 32767.0, -1.0);
+}
+
+float3 UnpackOctahedral(uint packed)
+{
+    float2 encoded = UnpackSnorm2x16(packed);
+    float3 normal = float3(encoded, 1.0 - abs(encoded.x) - abs(encoded.y));
+    float fold = saturate(-normal.z);
+    normal.x -= normal.x >= 0.0 ? fold : -fold;
+    normal.y -= normal.y >= 0.0 ? fold : -fold;
+
+    return normalize(normal);
+}
+
+float4 UnpackRgba8(uint packed)
+{
+    return float4(packed & 0xFF, (packed >> 8) & 0xFF, (packed >> 16) & 0xFF, packed >> 24) / 255.0;
+}
+"#;
+
+/// Writes `PACKING_HLSL` to `path`, leaving the file alone when it is already up to date
+pub fn write_packing_header(path: impl AsRef<Path>) -> Result<()> {
+    write_generated_header(path.as_ref(), PACKING_HLSL)
+}
+
+/// Writes a header shaders include, leaving the file alone when it already holds `contents`
+pub fn write_generated_header(path: &Path, contents: &str) -> Result<()> {
+    if std::fs::read_to_string(path).is_ok_and(|existing| existing == contents) {
+        return Ok(());
+    }
+
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory)?;
+    }
+    std::fs::write(path, contents)
+        .with_context(|| format!("Failed to write shader header {}", path.display()))
+}
+
+/// Rounds to the nearest half, ties to even. Out of range values become infinity.
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xFF) as i32;
+    let mantissa = bits & 0x7F_FFFF;
+
+    if exponent == 0xFF {
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7C00 | nan;
+    }
+
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1F {
+        return sign | 0x7C00;
+    }
+
+    // Shifts the mantissa into place, rounding what falls off. A carry out of the mantissa
+    // correctly bumps the exponent.
+    let round = |mantissa: u32, shift: u32| {
+        let half = mantissa >> shift;
+        let remainder = mantissa & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+
+        half + (remainder > halfway || (remainder == halfway && half & 1 == 1)) as u32
+    };
+
+    if exponent <= 0 {
+        if exponent < -10 {
+            return sign;
+        }
+        // Subnormal, the implicit leading one becomes explicit
+        return sign | round(mantissa | 0x80_0000, (14 - exponent) as u32) as u16;
+    }
+
+    sign | round(((exponent as u32) << 23) | mantissa, 13) as u16
+}
+
+pub fn f16_to_f32(half: u16) -> f32 {
+    let sign = ((half & 0x8000) as u32) << 16;
+    let exponent = ((half >> 10) & 0x1F) as u32;
+    let mantissa = (half & 0x3FF) as u32;
+
+    let bits = match exponent {
+        0 => {
+            let magnitude = mantissa as f32 / (1 << 24) as f32;
+            return f32::from_bits(sign | magnitude.to_bits());
+        }
+        0x1F => sign | 0x7F80_0000 | (mantissa << 13),
+        _ => sign | ((exponent + 127 - 15) << 23) | (mantissa << 13),
+    };
+
+    f32::from_bits(bits)
+}
+
+/// x in the low 16 bits, decoded by UnpackHalf2
+pub fn pack_half2(value: Vec2) -> u32 {
+    f32_to_f16(value.x) as u32 | (f32_to_f16(value.y) as u32) << 16
+}
+
+pub fn unpack_half2(packed: u32) -> Vec2 {
+    Vec2::new(f16_to_f32(packed as u16), f16_to_f32((packed >> 16) as u16))
+}
+
+/// Maps a unit vector onto the [-1, 1] square by projecting it onto an octahedron and folding
+/// the lower half over the upper one
+pub fn encode_octahedral(normal: Vec3) -> Vec2 {
+    let normal = normal / (normal.x.abs() + normal.y.abs() + normal.z.abs());
+    if normal.z >= 0.0 {
+        return normal.truncate();
+    }
+
+    let sign = |value: f32| if value >= 0.0 { 1.0 } else { -1.0 };
+    Vec2::new(
+        (1.0 - normal.y.abs()) * sign(normal.x),
+        (1.0 - normal.x.abs()) * sign(normal.y),
+    )
+}
+
+pub fn decode_octahedral(encoded: Vec2) -> Vec3 {
+    let mut normal = encoded.extend(1.0 - encoded.x.abs() - encoded.y.abs());
+    let fold = (-normal.z).max(0.0);
+    let signed_fold = |value: f32| if value >= 0.0 { fold } else { -fold };
+    normal.x -= signed_fold(normal.x);
+    normal.y -= signed_fold(normal.y);
+
+    normal.normalize()
+}
+
+/// A unit vector in two signed 16 bit components, decoded by UnpackOctahedral
+pub fn pack_octahedral(normal: Vec3) -> u32 {
+    let snorm = |value: f32| (value.clamp(-1.0, 1.0) * 32767.0).round() as i16 as u16 as u32;
+    let encoded = encode_octahedral(normal);
+
+    snorm(encoded.x) | snorm(encoded.y) << 16
+}
+
+pub fn unpack_octahedral(packed: u32) -> Vec3 {
+    let snorm = |bits: u32| (bits as u16 as i16 as f32 / 32767.0).max(-1.0);
+
+    decode_octahedral(Vec2::new(snorm(packed), snorm(packed >> 16)))
+}
+
+/// A colour in 0 to 1 with red in the low byte, decoded by UnpackRgba8
+pub fn pack_rgba8(color: Vec4) -> u32 {
+    let unorm = (color.clamp(Vec4::ZERO, Vec4::ONE) * 255.0).round();
+
+    unorm.x as u32 | (unorm.y as u32) << 8 | (unorm.z as u32) << 16 | (unorm.w as u32) << 24
+}
+
+pub fn unpack_rgba8(packed: u32) -> Vec4 {
+    Vec4::new(
+        (packed & 0xFF) as f32,
+        ((packed >> 8) & 0xFF) as f32,
+        ((packed >> 16) & 0xFF) as f32,
+        (packed >> 24) as f32,
+    ) / 255.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn halfs_round_trip() {
+        for value in [
+            0.0,
+            -0.0,
+            1.0,
+            -2.5,
+            0.333_251_95,
+            65504.0,
+            6.103_515_6e-5,
+            5.960_464_5e-8,
+        ] {
+            assert_eq!(
+                f16_to_f32(f32_to_f16(value)).to_bits(),
+                value.to_bits(),
+                "{}",
+                value
+            );
+        }
+
+        assert_eq!(f32_to_f16(1.0), 0x3C00);
+        assert_eq!(f32_to_f16(-2.0), 0xC000);
+        assert_eq!(f32_to_f16(1e6), 0x7C00);
+        assert_eq!(f32_to_f16(1e-9), 0);
+        assert!(f16_to_f32(f32_to_f16(f32::NAN)).is_nan());
+        // Halfway between 1 and the next half rounds to even, just above it rounds up
+        assert_eq!(f32_to_f16(1.0 + 2f32.powi(-11)), 0x3C00);
+        assert_eq!(f32_to_f16(1.0 + 2f32.powi(-11) + 2f32.powi(-20)), 0x3C01);
+        // The largest finite half rounds up to infinity
+        assert_eq!(f32_to_f16(65520.0), 0x7C00);
+
+        let packed = pack_half2(Vec2::new(0.5, -8.0));
+        assert_eq!(unpack_half2(packed), Vec2::new(0.5, -8.0));
+    }
+
+    #[test]
+    fn octahedral_normals_round_trip() {
+        let normals = [
+            Vec3::X,
+            Vec3::NEG_Y,
+            Vec3::Z,
+            Vec3::NEG_Z,
+            Vec3::new(1.0, -2.0, 3.0).normalize(),
+            Vec3::new(-0.3, 0.4, -0.8).normalize(),
+            Vec3::new(0.7, 0.7, -0.1).normalize(),
+        ];
+
+        for normal in normals {
+            let exact = decode_octahedral(encode_octahedral(normal));
+            assert!(
+                exact.abs_diff_eq(normal, 1e-6),
+                "{} became {}",
+                normal,
+                exact
+            );
+
+            let packed = unpack_octahedral(pack_octahedral(normal));
+            assert!(
+                packed.angle_between(normal) < 1e-3,
+                "{} became {}",
+                normal,
+                packed
+            );
+        }
+    }
+
+    #[test]
+    fn colors_pack_red_first() {
+        assert_eq!(pack_rgba8(Vec4::new(1.0, 0.0, 0.0, 1.0)), 0xFF00_00FF);
+        assert_eq!(pack_rgba8(Vec4::new(2.0, -1.0, 0.5, 0.0)), 0x0080_00FF);
+
+        let color = Vec4::new(0.2, 0.4, 0.6, 0.8);
+        assert!(unpack_rgba8(pack_rgba8(color)).abs_diff_eq(color, 0.5 / 255.0));
+    }
+}
@@ -3,7 +3,7 @@ use glam::{Vec2, Vec3};
 use lazy_static::lazy_static;
 use regex::Regex;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 #[repr(C)]
 pub struct ObjVertex {
     pub position: Vec3,
@@ -28,41 +28,118 @@ pub fn parse_obj<'a, I>(lines: I) -> Result<(Vec<ObjVertex>, Vec<u32>)>
 where
     I: IntoIterator<Item = &'a str>,
 {
-    let mut positions = Vec::<Vec3>::new();
-    let mut normals = Vec::<Vec3>::new();
-    let mut uvs = Vec::<Vec2>::new();
-
     let mut vertices = Vec::<ObjVertex>::new();
     let mut indices = Vec::<u32>::new();
 
-    for line in lines.into_iter() {
-        if line.trim().is_empty() {
-            continue;
-        }
-        let parsed = parse_line(line).context("Invalid line")?;
-        match parsed {
-            ObjLine::Position(pos) => positions.push(pos),
-            ObjLine::Normal(normal) => normals.push(normal),
-            ObjLine::UV(uv) => uvs.push(uv),
-            ObjLine::Face(verts) => verts.iter().for_each(|(p, t, n)| {
-                vertices.push(ObjVertex {
-                    position: positions[(p - 1) as usize],
-                    normal: normals[(n - 1) as usize],
-                    uv: uvs[(t - 1) as usize],
-                });
-                indices.push(vertices.len() as u32 - 1);
-            }),
-            ObjLine::Comment(_)
-            | ObjLine::Object(_)
-            | ObjLine::Material(_)
-            | ObjLine::SmoothShading(_)
-            | ObjLine::Group(_) => (),
-        }
+    for chunk in parse_obj_chunked(lines, usize::MAX) {
+        let chunk = chunk?;
+        vertices.extend(chunk.vertices);
+        indices.extend(chunk.indices);
     }
 
     Ok((vertices, indices))
 }
 
+#[derive(Debug, Default, PartialEq)]
+pub struct ObjChunk {
+    pub vertices: Vec<ObjVertex>,
+    /// Indices are relative to the start of the whole mesh, not the chunk
+    pub indices: Vec<u32>,
+}
+
+pub struct ObjChunks<I> {
+    lines: I,
+    chunk_size: usize,
+
+    positions: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<Vec2>,
+
+    num_vertices: u32,
+    finished: bool,
+}
+
+/// Parses an OBJ file lazily, yielding batches of at most `chunk_size` vertices.
+/// Only the raw attribute streams are kept in memory for the whole file.
+pub fn parse_obj_chunked<S, I>(lines: I, chunk_size: usize) -> ObjChunks<I::IntoIter>
+where
+    S: AsRef<str>,
+    I: IntoIterator<Item = S>,
+{
+    ObjChunks {
+        lines: lines.into_iter(),
+        chunk_size: usize::max(chunk_size, 3),
+        positions: Vec::new(),
+        normals: Vec::new(),
+        uvs: Vec::new(),
+        num_vertices: 0,
+        finished: false,
+    }
+}
+
+impl<S, I> Iterator for ObjChunks<I>
+where
+    S: AsRef<str>,
+    I: Iterator<Item = S>,
+{
+    type Item = Result<ObjChunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        let mut chunk = ObjChunk::default();
+
+        while chunk.vertices.len() + 3 <= self.chunk_size {
+            let line = match self.lines.next() {
+                Some(line) => line,
+                None => {
+                    self.finished = true;
+                    break;
+                }
+            };
+            let line = line.as_ref();
+
+            if line.trim().is_empty() {
+                continue;
+            }
+            let parsed = match parse_line(line).context("Invalid line") {
+                Ok(parsed) => parsed,
+                Err(err) => {
+                    self.finished = true;
+                    return Some(Err(err));
+                }
+            };
+            match parsed {
+                ObjLine::Position(pos) => self.positions.push(pos),
+                ObjLine::Normal(normal) => self.normals.push(normal),
+                ObjLine::UV(uv) => self.uvs.push(uv),
+                ObjLine::Face(verts) => verts.iter().for_each(|(p, t, n)| {
+                    chunk.vertices.push(ObjVertex {
+                        position: self.positions[(p - 1) as usize],
+                        normal: self.normals[(n - 1) as usize],
+                        uv: self.uvs[(t - 1) as usize],
+                    });
+                    chunk.indices.push(self.num_vertices);
+                    self.num_vertices += 1;
+                }),
+                ObjLine::Comment(_)
+                | ObjLine::Object(_)
+                | ObjLine::Material(_)
+                | ObjLine::SmoothShading(_)
+                | ObjLine::Group(_) => (),
+            }
+        }
+
+        if self.finished && chunk.vertices.is_empty() && self.num_vertices > 0 {
+            return None;
+        }
+
+        Some(Ok(chunk))
+    }
+}
+
 fn parse_line(line: &str) -> Result<ObjLine> {
     lazy_static! {
         static ref POSITION_RE: Regex =
@@ -380,4 +457,36 @@ f 1/1/1 2/2/2 3/3/3"
         );
         assert_eq!(vec![0, 1, 2], indices);
     }
+
+    #[test]
+    fn parse_chunked_obj() {
+        let obj_file = "v 0.0 0.0 0.0
+v 1.0 0.0 0.0
+v 0.0 1.0 0.0
+vt 0.0 0.0
+vn 0.0 0.0 1.0
+f 1/1/1 2/1/1 3/1/1
+f 3/1/1 2/1/1 1/1/1
+f 1/1/1 3/1/1 2/1/1";
+
+        let chunks = parse_obj_chunked(obj_file.lines(), 6)
+            .collect::<Result<Vec<ObjChunk>>>()
+            .unwrap();
+
+        assert_eq!(2, chunks.len());
+        assert_eq!(6, chunks[0].vertices.len());
+        assert_eq!(vec![0, 1, 2, 3, 4, 5], chunks[0].indices);
+        assert_eq!(3, chunks[1].vertices.len());
+        assert_eq!(vec![6, 7, 8], chunks[1].indices);
+
+        let (vertices, indices) = parse_obj(obj_file.lines()).unwrap();
+        assert_eq!(
+            vertices,
+            chunks
+                .into_iter()
+                .flat_map(|chunk| chunk.vertices)
+                .collect::<Vec<ObjVertex>>()
+        );
+        assert_eq!((0..9).collect::<Vec<u32>>(), indices);
+    }
 }
@@ -0,0 +1,232 @@
+use std::fmt;
+
+use anyhow::{ensure, Result};
+use windows::Win32::Graphics::Direct3D12::*;
+
+use crate::{gpu_timer::timestamp_milliseconds, AllocationCounts, CommandQueue, QueryHeap};
+
+/// Most passes a frame can count the work of, later ones aren't counted
+pub const MAX_STATISTICS_PASSES: usize = 32;
+
+/// How much work the GPU did in each pipeline stage. Mesh shader draws skip the input assembler
+/// and vertex shaders, so they only show up as pixel shader invocations.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PipelineStatistics {
+    pub ia_vertices: u64,
+    pub ia_primitives: u64,
+    pub vs_invocations: u64,
+    /// Primitives sent to the rasterizer, and of those the ones that weren't clipped away
+    pub clipper_invocations: u64,
+    pub clipper_primitives: u64,
+    pub ps_invocations: u64,
+    pub cs_invocations: u64,
+}
+
+impl From<D3D12_QUERY_DATA_PIPELINE_STATISTICS> for PipelineStatistics {
+    fn from(data: D3D12_QUERY_DATA_PIPELINE_STATISTICS) -> Self {
+        Self {
+            ia_vertices: data.IAVertices,
+            ia_primitives: data.IAPrimitives,
+            vs_invocations: data.VSInvocations,
+            clipper_invocations: data.CInvocations,
+            clipper_primitives: data.CPrimitives,
+            ps_invocations: data.PSInvocations,
+            cs_invocations: data.CSInvocations,
+        }
+    }
+}
+
+impl fmt::Display for PipelineStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} primitives, {} vertex, {} pixel and {} compute invocations",
+            self.ia_primitives, self.vs_invocations, self.ps_invocations, self.cs_invocations
+        )
+    }
+}
+
+/// The work one pass of a frame did, how long the GPU took for it and what it allocated
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PassStatistics {
+    pub name: &'static str,
+    pub statistics: PipelineStatistics,
+    /// None when the timestamps around the pass weren't usable
+    pub gpu_time_ms: Option<f32>,
+    pub allocations: AllocationCounts,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CountedPass {
+    name: &'static str,
+    allocations: AllocationCounts,
+}
+
+/// Counts the work of each pass of a frame with a pipeline statistics query around it, for
+/// telling where the cost of geometry goes, and what it allocated from the pools that run out.
+/// Timestamps on either side give the GPU time of the pass. Passes don't nest.
+#[derive(Debug)]
+pub struct PipelineStatisticsQueries {
+    queries: QueryHeap<D3D12_QUERY_DATA_PIPELINE_STATISTICS>,
+    // Two for each query, at the start and end of its pass
+    timestamps: QueryHeap<u64>,
+    timestamp_frequency: u64,
+    // The passes counted for each frame in flight, in order
+    passes: Vec<Vec<CountedPass>>,
+    frame_index: usize,
+    open: bool,
+    // Of the open pass, None when the frame ran out of queries
+    open_query: Option<usize>,
+}
+
+impl PipelineStatisticsQueries {
+    pub fn new(device: &ID3D12Device4, queue: &CommandQueue, num_frames: usize) -> Result<Self> {
+        Ok(Self {
+            queries: QueryHeap::new(
+                device,
+                D3D12_QUERY_HEAP_TYPE_PIPELINE_STATISTICS,
+                D3D12_QUERY_TYPE_PIPELINE_STATISTICS,
+                num_frames * MAX_STATISTICS_PASSES,
+            )?,
+            timestamps: QueryHeap::new(
+                device,
+                D3D12_QUERY_HEAP_TYPE_TIMESTAMP,
+                D3D12_QUERY_TYPE_TIMESTAMP,
+                num_frames * MAX_STATISTICS_PASSES * 2,
+            )?,
+            timestamp_frequency: unsafe { queue.queue.GetTimestampFrequency() }?,
+            passes: vec![vec![]; num_frames],
+            frame_index: 0,
+            open: false,
+            open_query: None,
+        })
+    }
+
+    /// Call once the fence of `frame_index` has been waited on and its statistics were read
+    pub fn begin_frame(&mut self, frame_index: usize) -> Result<()> {
+        ensure!(
+            frame_index < self.passes.len(),
+            "Frame index {} is out of range",
+            frame_index
+        );
+
+        self.frame_index = frame_index;
+        self.passes[frame_index].clear();
+        self.open = false;
+        self.open_query = None;
+
+        Ok(())
+    }
+
+    /// `allocations` are the counts before the pass, `end` takes them after it
+    pub fn begin(
+        &mut self,
+        command_list: &ID3D12GraphicsCommandList,
+        name: &'static str,
+        allocations: AllocationCounts,
+    ) -> Result<()> {
+        ensure!(!self.open, "Pass {} starts inside another", name);
+        self.open = true;
+
+        let passes = &mut self.passes[self.frame_index];
+        self.open_query = (passes.len() < MAX_STATISTICS_PASSES)
+            .then(|| self.frame_index * MAX_STATISTICS_PASSES + passes.len());
+        if let Some(query) = self.open_query {
+            self.timestamps.end(command_list, query * 2);
+            self.queries.begin(command_list, query);
+            passes.push(CountedPass { name, allocations });
+        }
+
+        Ok(())
+    }
+
+    pub fn end(
+        &mut self,
+        command_list: &ID3D12GraphicsCommandList,
+        allocations: AllocationCounts,
+    ) -> Result<()> {
+        ensure!(self.open, "Pass ended without being started");
+        self.open = false;
+
+        if let Some(query) = self.open_query.take() {
+            self.queries.end(command_list, query);
+            self.timestamps.end(command_list, query * 2 + 1);
+            if let Some(pass) = self.passes[self.frame_index].last_mut() {
+                pass.allocations = allocations - pass.allocations;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Call after the last pass of the frame
+    pub fn resolve(&self, command_list: &ID3D12GraphicsCommandList) -> Result<()> {
+        ensure!(!self.open, "Resolving while a pass is still counted");
+
+        let count = self.passes[self.frame_index].len();
+        if count == 0 {
+            return Ok(());
+        }
+        let first_query = self.frame_index * MAX_STATISTICS_PASSES;
+        self.queries.resolve(command_list, first_query, count)?;
+        self.timestamps
+            .resolve(command_list, first_query * 2, count * 2)
+    }
+
+    /// The work each pass of `frame_index` did, in order. Only meaningful once its fence has
+    /// been waited on and before its next `begin_frame`.
+    pub fn read(&self, frame_index: usize) -> Vec<PassStatistics> {
+        let Some(passes) = self.passes.get(frame_index) else {
+            return vec![];
+        };
+
+        let first_query = frame_index * MAX_STATISTICS_PASSES;
+        let Some(results) = self.queries.read(first_query, passes.len()) else {
+            return vec![];
+        };
+        let timestamps = self.timestamps.read(first_query * 2, passes.len() * 2);
+
+        passes
+            .iter()
+            .zip(results)
+            .enumerate()
+            .map(|(index, (pass, &data))| PassStatistics {
+                name: pass.name,
+                statistics: data.into(),
+                gpu_time_ms: timestamps.and_then(|timestamps| {
+                    timestamp_milliseconds(
+                        timestamps[index * 2],
+                        timestamps[index * 2 + 1],
+                        self.timestamp_frequency,
+                    )
+                }),
+                allocations: pass.allocations,
+            })
+            .collect()
+    }
+}
+
+/// The GPU time of a completed frame with the work of each of its passes
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FrameProfile {
+    pub gpu_time_ms: Option<f32>,
+    pub passes: Vec<PassStatistics>,
+}
+
+impl fmt::Display for FrameProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.gpu_time_ms {
+            Some(gpu_time_ms) => write!(f, "GPU frame time {:.2} ms", gpu_time_ms)?,
+            None => write!(f, "GPU frame time unknown")?,
+        }
+        for pass in &self.passes {
+            match pass.gpu_time_ms {
+                Some(gpu_time_ms) => write!(f, "\n    {} ({:.2} ms): ", pass.name, gpu_time_ms)?,
+                None => write!(f, "\n    {}: ", pass.name)?,
+            }
+            write!(f, "{}\n        {}", pass.statistics, pass.allocations)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+use glam::{Mat4, Vec3, Vec4};
+
+/// The points where `dot(normal, point) + distance` is zero, with a unit length normal
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Plane {
+    pub normal: Vec3,
+    pub distance: f32,
+}
+
+impl Plane {
+    pub fn from_point_normal(point: Vec3, normal: Vec3) -> Self {
+        let normal = normal.normalize();
+
+        Self {
+            normal,
+            distance: -normal.dot(point),
+        }
+    }
+
+    /// Positive on the side the normal points to
+    pub fn signed_distance(&self, point: Vec3) -> f32 {
+        self.normal.dot(point) + self.distance
+    }
+
+    /// The same points, facing the other way
+    pub fn flipped(&self) -> Self {
+        Self {
+            normal: -self.normal,
+            distance: -self.distance,
+        }
+    }
+
+    /// Normal in xyz and distance in w, for shaders
+    pub fn as_vec4(&self) -> Vec4 {
+        self.normal.extend(self.distance)
+    }
+
+    /// Mirrors points about the plane. Flips handedness, so triangles change winding.
+    pub fn reflection(&self) -> Mat4 {
+        let n = self.normal;
+
+        Mat4::from_cols(
+            (Vec3::X - 2.0 * n.x * n).extend(0.0),
+            (Vec3::Y - 2.0 * n.y * n).extend(0.0),
+            (Vec3::Z - 2.0 * n.z * n).extend(0.0),
+            (-2.0 * self.distance * n).extend(1.0),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn water() -> Plane {
+        Plane::from_point_normal(Vec3::new(0.0, 0.5, 0.0), Vec3::Y)
+    }
+
+    #[test]
+    fn reflects_across_the_plane() {
+        let reflection = water().reflection();
+
+        let above = Vec3::new(1.0, 2.0, 3.0);
+        let mirrored = reflection.transform_point3(above);
+        assert!((mirrored - Vec3::new(1.0, -1.0, 3.0)).length() < 1e-5);
+        assert!((reflection.transform_point3(mirrored) - above).length() < 1e-5);
+
+        let on_plane = Vec3::new(-4.0, 0.5, 2.0);
+        assert!((reflection.transform_point3(on_plane) - on_plane).length() < 1e-5);
+
+        assert!(reflection.determinant() < 0.0);
+    }
+
+    #[test]
+    fn tilted_plane_mirrors_distances() {
+        let plane = Plane::from_point_normal(Vec3::new(1.0, 2.0, 3.0), Vec3::new(1.0, 1.0, 0.0));
+        let point = Vec3::new(0.0, 0.0, 5.0);
+
+        let mirrored = plane.reflection().transform_point3(point);
+        assert!((plane.signed_distance(mirrored) + plane.signed_distance(point)).abs() < 1e-5);
+        assert!(
+            (plane.flipped().signed_distance(point) + plane.signed_distance(point)).abs() < 1e-5
+        );
+    }
+}
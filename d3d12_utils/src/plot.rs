@@ -0,0 +1,201 @@
+use crate::FrameProfile;
+
+/// The last `capacity` values of something measured every frame, the oldest overwritten first.
+/// The samples are kept in ring order, so they can be copied to the GPU as they are and read
+/// from `head` on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SampleRing {
+    samples: Vec<f32>,
+    // Where the next sample goes, which is the oldest one once the ring is full
+    head: usize,
+}
+
+impl SampleRing {
+    /// Starts out full of zeros, so graphs of it scroll in from the right
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: vec![0.0; capacity],
+            head: 0,
+        }
+    }
+
+    pub fn push(&mut self, sample: f32) {
+        if self.samples.is_empty() {
+            return;
+        }
+        self.samples[self.head] = sample;
+        self.head = (self.head + 1) % self.samples.len();
+    }
+
+    /// In ring order, the oldest is at `head`
+    pub fn as_slice(&self) -> &[f32] {
+        &self.samples
+    }
+
+    pub fn head(&self) -> usize {
+        self.head
+    }
+
+    /// 0 before anything was pushed
+    pub fn latest(&self) -> f32 {
+        let len = self.samples.len();
+        if len == 0 {
+            return 0.0;
+        }
+        self.samples[(self.head + len - 1) % len]
+    }
+
+    /// Oldest first
+    pub fn iter(&self) -> impl Iterator<Item = f32> + '_ {
+        let (newer, older) = self.samples.split_at(self.head);
+        older.iter().chain(newer).copied()
+    }
+
+    pub fn max(&self) -> f32 {
+        self.iter().fold(0.0, f32::max)
+    }
+}
+
+/// The smallest of 1, 2 and 5 times a power of 10 that `max` fits under, for the top of a graph
+/// that doesn't rescale with every frame
+pub fn plot_scale(max: f32) -> f32 {
+    if max.is_nan() || max <= 0.0 {
+        return 1.0;
+    }
+
+    let power = 10f32.powf(max.log10().floor());
+    [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|step| step * power)
+        .find(|&scale| scale >= max)
+        .unwrap_or(10.0 * power)
+}
+
+/// Recent GPU times of frames and of each of their passes, a ring for each, all of them advanced
+/// together. Passes that didn't run in a frame count as 0 for it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfilerHistory {
+    capacity: usize,
+    max_passes: usize,
+    frame: SampleRing,
+    // In the order they first ran, later ones than `max_passes` aren't kept
+    passes: Vec<(&'static str, SampleRing)>,
+}
+
+impl ProfilerHistory {
+    /// Keeps at least one sample
+    pub fn new(capacity: usize, max_passes: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            capacity,
+            max_passes,
+            frame: SampleRing::new(capacity),
+            passes: Vec::new(),
+        }
+    }
+
+    /// Adds the times of a completed frame, unknown ones as 0
+    pub fn record(&mut self, profile: &FrameProfile) {
+        self.frame.push(profile.gpu_time_ms.unwrap_or(0.0));
+
+        for pass in &profile.passes {
+            if self.passes.len() < self.max_passes
+                && !self.passes.iter().any(|(name, _)| *name == pass.name)
+            {
+                // Lined up with the others once this frame's time is in
+                let head = (self.frame.head + self.capacity - 1) % self.capacity;
+                self.passes.push((
+                    pass.name,
+                    SampleRing {
+                        samples: vec![0.0; self.capacity],
+                        head,
+                    },
+                ));
+            }
+        }
+        for (name, samples) in &mut self.passes {
+            let time = profile
+                .passes
+                .iter()
+                .filter(|pass| pass.name == *name)
+                .filter_map(|pass| pass.gpu_time_ms)
+                .sum();
+            samples.push(time);
+        }
+    }
+
+    pub fn frame(&self) -> &SampleRing {
+        &self.frame
+    }
+
+    pub fn passes(&self) -> &[(&'static str, SampleRing)] {
+        &self.passes
+    }
+
+    /// Where the oldest sample of every ring is
+    pub fn head(&self) -> usize {
+        self.frame.head()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AllocationCounts, PassStatistics, PipelineStatistics};
+
+    fn pass(name: &'static str, gpu_time_ms: f32) -> PassStatistics {
+        PassStatistics {
+            name,
+            statistics: PipelineStatistics::default(),
+            gpu_time_ms: Some(gpu_time_ms),
+            allocations: AllocationCounts::default(),
+        }
+    }
+
+    #[test]
+    fn rings_overwrite_the_oldest() {
+        let mut ring = SampleRing::new(3);
+        assert_eq!(ring.latest(), 0.0);
+        for sample in [1.0, 2.0, 3.0, 4.0] {
+            ring.push(sample);
+        }
+
+        assert_eq!(ring.as_slice(), [4.0, 2.0, 3.0]);
+        assert_eq!(ring.head(), 1);
+        assert_eq!(ring.iter().collect::<Vec<_>>(), [2.0, 3.0, 4.0]);
+        assert_eq!(ring.latest(), 4.0);
+        assert_eq!(ring.max(), 4.0);
+    }
+
+    #[test]
+    fn scales_round_up_to_one_two_five() {
+        assert_eq!(plot_scale(0.0), 1.0);
+        assert_eq!(plot_scale(f32::NAN), 1.0);
+        assert_eq!(plot_scale(1.0), 1.0);
+        assert_eq!(plot_scale(1.5), 2.0);
+        assert_eq!(plot_scale(16.7), 20.0);
+        assert_eq!(plot_scale(0.3), 0.5);
+        assert!((plot_scale(0.06) - 0.1).abs() < 1e-6);
+    }
+
+    #[test]
+    fn passes_advance_together() {
+        let mut history = ProfilerHistory::new(4, 2);
+        history.record(&FrameProfile {
+            gpu_time_ms: Some(10.0),
+            passes: vec![pass("Scene", 6.0)],
+        });
+        history.record(&FrameProfile {
+            gpu_time_ms: None,
+            passes: vec![pass("Probe", 1.0), pass("Scene", 5.0), pass("Bloom", 1.0)],
+        });
+
+        assert_eq!(history.frame().latest(), 0.0);
+        assert_eq!(history.head(), 2);
+        let passes = history.passes();
+        assert_eq!(passes.len(), 2);
+        assert_eq!((passes[0].0, passes[0].1.latest()), ("Scene", 5.0));
+        assert_eq!(passes[1].1.iter().collect::<Vec<_>>(), [0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(passes[1].1.head(), history.head());
+    }
+}
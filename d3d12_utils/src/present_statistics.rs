@@ -0,0 +1,160 @@
+use std::fmt;
+
+use anyhow::{ensure, Result};
+use windows::{
+    core::Interface,
+    Win32::{
+        Foundation::{BOOL, RECT},
+        Graphics::Dxgi::*,
+        UI::WindowsAndMessaging::GetClientRect,
+    },
+};
+
+/// How the last present got to the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresentationMode {
+    /// Copied into the desktop by the compositor
+    Composed,
+    /// Scanned out from a hardware overlay plane
+    Overlay,
+    /// Flipped straight to the screen without the compositor, i.e. independent flip
+    IndependentFlip,
+    /// An overlay was asked for but the compositor had to step in
+    CompositionFailure,
+}
+
+impl PresentationMode {
+    fn from_raw(mode: DXGI_FRAME_PRESENTATION_MODE) -> Option<Self> {
+        match mode {
+            DXGI_FRAME_PRESENTATION_MODE_COMPOSED => Some(Self::Composed),
+            DXGI_FRAME_PRESENTATION_MODE_OVERLAY => Some(Self::Overlay),
+            DXGI_FRAME_PRESENTATION_MODE_NONE => Some(Self::IndependentFlip),
+            DXGI_FRAME_PRESENTATION_MODE_COMPOSITION_FAILURE => Some(Self::CompositionFailure),
+            _ => None,
+        }
+    }
+}
+
+/// Frame statistics of a flip model swap chain, to check what the presentation actually ended up
+/// as. Counts only move on once a present reaches the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PresentStatistics {
+    pub present_count: u32,
+    /// Vertical blank the last present was shown at
+    pub present_refresh_count: u32,
+    /// Vertical blank the statistics were sampled at
+    pub sync_refresh_count: u32,
+    pub mode: Option<PresentationMode>,
+    /// Back buffers have to be exactly the size of the window to skip the compositor
+    pub matches_window: bool,
+}
+
+impl PresentStatistics {
+    /// None while the statistics are disjoint, e.g. right after a mode change or before the first
+    /// present has been shown
+    pub fn query(swap_chain: &IDXGISwapChain3) -> Result<Option<Self>> {
+        let media: IDXGISwapChainMedia = swap_chain.cast()?;
+        let statistics = match unsafe { media.GetFrameStatisticsMedia() } {
+            Ok(statistics) => statistics,
+            Err(err) if err.code() == DXGI_ERROR_FRAME_STATISTICS_DISJOINT => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        let desc = unsafe { swap_chain.GetDesc1()? };
+        let hwnd = unsafe { swap_chain.GetHwnd()? };
+        let mut client = RECT::default();
+        ensure!(
+            unsafe { GetClientRect(hwnd, &mut client) }.as_bool(),
+            "Failed to get the window size"
+        );
+        let window_extent = (
+            (client.right - client.left) as u32,
+            (client.bottom - client.top) as u32,
+        );
+
+        Ok(Some(Self {
+            present_count: statistics.PresentCount,
+            present_refresh_count: statistics.PresentRefreshCount,
+            sync_refresh_count: statistics.SyncRefreshCount,
+            mode: PresentationMode::from_raw(statistics.CompositionMode),
+            matches_window: (desc.Width, desc.Height) == window_extent,
+        }))
+    }
+
+    /// Vertical blanks since `earlier` that didn't get a new frame. Zero while keeping up with
+    /// the refresh rate at a sync interval of 1.
+    pub fn missed_refreshes_since(&self, earlier: &PresentStatistics) -> u32 {
+        let refreshes = self
+            .sync_refresh_count
+            .wrapping_sub(earlier.sync_refresh_count);
+        let presents = self.present_count.wrapping_sub(earlier.present_count);
+
+        refreshes.saturating_sub(presents)
+    }
+}
+
+impl fmt::Display for PresentStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mode = match self.mode {
+            Some(mode) => format!("{:?}", mode),
+            None => "unknown".to_string(),
+        };
+        write!(
+            f,
+            "{} presents, last at refresh {}, {}",
+            self.present_count, self.present_refresh_count, mode
+        )?;
+        if !self.matches_window {
+            write!(f, ", back buffers don't match the window size")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether presents can tear with vsync off, needed for variable refresh rate displays
+pub fn tearing_supported(factory: &IDXGIFactory5) -> bool {
+    let mut allow_tearing = BOOL::default();
+    let result = unsafe {
+        factory.CheckFeatureSupport(
+            DXGI_FEATURE_PRESENT_ALLOW_TEARING,
+            std::ptr::addr_of_mut!(allow_tearing).cast(),
+            std::mem::size_of::<BOOL>() as u32,
+        )
+    };
+
+    result.is_ok() && allow_tearing.as_bool()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn statistics(present_count: u32, sync_refresh_count: u32) -> PresentStatistics {
+        PresentStatistics {
+            present_count,
+            present_refresh_count: sync_refresh_count,
+            sync_refresh_count,
+            mode: Some(PresentationMode::IndependentFlip),
+            matches_window: true,
+        }
+    }
+
+    #[test]
+    fn counts_refreshes_without_a_new_frame() {
+        let earlier = statistics(100, 200);
+
+        assert_eq!(statistics(160, 260).missed_refreshes_since(&earlier), 0);
+        assert_eq!(statistics(150, 260).missed_refreshes_since(&earlier), 10);
+        // Presenting faster than the refresh rate with tearing misses nothing
+        assert_eq!(statistics(200, 260).missed_refreshes_since(&earlier), 0);
+    }
+
+    #[test]
+    fn counts_survive_wrapping() {
+        let earlier = statistics(u32::MAX - 4, u32::MAX - 9);
+
+        assert_eq!(statistics(5, 0).missed_refreshes_since(&earlier), 0);
+        assert_eq!(statistics(0, 5).missed_refreshes_since(&earlier), 10);
+    }
+}
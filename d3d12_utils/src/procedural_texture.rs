@@ -0,0 +1,186 @@
+use anyhow::{ensure, Result};
+use windows::Win32::Graphics::Direct3D12::*;
+
+use crate::{
+    create_compute_pipeline_state, create_constants_root_signature, transition_barrier,
+    CommandQueue, CompiledShader, DescriptorManager, DescriptorType, TextureDimension,
+    TextureHandle, TextureInfo, TextureManager,
+};
+
+/// Root constants every procedural texture shader gets, as
+/// ```hlsl
+/// cbuffer Constants : register(b0) {
+///     uint destination_index; // RWTexture2D or RWTexture3D in ResourceDescriptorHeap
+///     uint3 size;
+///     float4 parameters;
+/// }
+/// ```
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct ProceduralTextureConstants {
+    pub destination_index: u32,
+    pub size: [u32; 3],
+    pub parameters: [f32; 4],
+}
+
+/// Fills textures with compute shaders at load time, for noise, gradients and lookup tables that
+/// would otherwise have to be shipped as asset files. Each texture is generated with its own
+/// submission and waited for, so this is not meant for textures that change every frame.
+#[derive(Debug)]
+pub struct ProceduralTextureGenerator {
+    root_signature: ID3D12RootSignature,
+    command_allocator: ID3D12CommandAllocator,
+    command_list: ID3D12GraphicsCommandList,
+}
+
+impl ProceduralTextureGenerator {
+    /// Records for direct queues, which can leave the textures ready for pixel shaders
+    pub fn new(device: &ID3D12Device4) -> Result<Self> {
+        let root_signature = create_constants_root_signature(
+            device,
+            (std::mem::size_of::<ProceduralTextureConstants>() / 4) as u32,
+        )?;
+        let command_allocator =
+            unsafe { device.CreateCommandAllocator(D3D12_COMMAND_LIST_TYPE_DIRECT) }?;
+        let command_list = unsafe {
+            device.CreateCommandList1(
+                0,
+                D3D12_COMMAND_LIST_TYPE_DIRECT,
+                D3D12_COMMAND_LIST_FLAG_NONE,
+            )
+        }?;
+
+        Ok(Self {
+            root_signature,
+            command_allocator,
+            command_list,
+        })
+    }
+
+    /// Creates a texture and runs `compute_shader` over it on the direct `queue`, with one thread
+    /// per texel in groups of `thread_group_size`, which has to match the shader's `numthreads`.
+    /// Only the top mip is written. The texture is ready to be sampled by anything submitted after
+    /// this returns.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_texture(
+        &mut self,
+        device: &ID3D12Device4,
+        queue: &mut CommandQueue,
+        texture_manager: &mut TextureManager,
+        descriptor_manager: &mut DescriptorManager,
+        texture_info: TextureInfo,
+        compute_shader: &CompiledShader,
+        thread_group_size: [u32; 3],
+        parameters: [f32; 4],
+    ) -> Result<TextureHandle> {
+        ensure!(
+            texture_info.array_size == 1 && !texture_info.is_cube,
+            "Procedural textures can't be arrays or cubes"
+        );
+        ensure!(
+            !texture_info.is_depth_buffer,
+            "Procedural textures can't be depth buffers"
+        );
+
+        let pso = create_compute_pipeline_state(device, &self.root_signature, compute_shader)?;
+        let texture = texture_manager.create_empty_texture(
+            device,
+            TextureInfo {
+                is_unordered_access: true,
+                ..texture_info
+            },
+            None,
+            D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+            descriptor_manager,
+            false,
+        )?;
+
+        let size = texture_size(&texture_info.dimension);
+        let constants = ProceduralTextureConstants {
+            destination_index: texture_manager.get_uav(&texture)?.index as u32,
+            size,
+            parameters,
+        };
+        let [x, y, z] = dispatch_size(size, thread_group_size)?;
+        let resource = &texture_manager
+            .get_texture(&texture)?
+            .get_resource()?
+            .device_resource;
+
+        unsafe {
+            self.command_allocator.Reset()?;
+            self.command_list.Reset(&self.command_allocator, &pso)?;
+
+            self.command_list.SetDescriptorHeaps(&[Some(
+                descriptor_manager.get_heap(DescriptorType::Resource)?,
+            )]);
+            self.command_list
+                .SetComputeRootSignature(&self.root_signature);
+            self.command_list.SetComputeRoot32BitConstants(
+                0,
+                (std::mem::size_of::<ProceduralTextureConstants>() / 4) as u32,
+                std::ptr::addr_of!(constants) as _,
+                0,
+            );
+            self.command_list.Dispatch(x, y, z);
+
+            self.command_list.ResourceBarrier(&[transition_barrier(
+                resource,
+                D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+                D3D12_RESOURCE_STATE_ALL_SHADER_RESOURCE,
+            )]);
+            self.command_list.Close()?;
+        }
+
+        let fence_value =
+            queue.execute_command_list(&ID3D12CommandList::from(&self.command_list))?;
+        // The allocator and the pipeline have to outlive the work
+        queue.wait_for_fence_blocking(fence_value)?;
+
+        Ok(texture)
+    }
+}
+
+fn texture_size(dimension: &TextureDimension) -> [u32; 3] {
+    match *dimension {
+        TextureDimension::One(width) => [width as u32, 1, 1],
+        TextureDimension::Two(width, height) => [width as u32, height, 1],
+        TextureDimension::Three(width, height, depth) => [width as u32, height, depth as u32],
+    }
+}
+
+/// Thread groups covering every texel of `size`
+fn dispatch_size(size: [u32; 3], thread_group_size: [u32; 3]) -> Result<[u32; 3]> {
+    ensure!(
+        thread_group_size.iter().all(|&threads| threads > 0),
+        "Thread groups need at least one thread along every axis, got {:?}",
+        thread_group_size
+    );
+
+    Ok([0, 1, 2].map(|axis| size[axis].div_ceil(thread_group_size[axis])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dispatch_covers_partial_groups() {
+        assert_eq!(
+            dispatch_size([256, 100, 1], [8, 8, 1]).unwrap(),
+            [32, 13, 1]
+        );
+        assert_eq!(dispatch_size([33, 33, 33], [4, 4, 4]).unwrap(), [9, 9, 9]);
+        assert!(dispatch_size([16, 16, 1], [8, 0, 1]).is_err());
+    }
+
+    #[test]
+    fn sizes_fill_missing_axes_with_one() {
+        assert_eq!(texture_size(&TextureDimension::One(64)), [64, 1, 1]);
+        assert_eq!(texture_size(&TextureDimension::Two(64, 32)), [64, 32, 1]);
+        assert_eq!(
+            texture_size(&TextureDimension::Three(16, 16, 16)),
+            [16, 16, 16]
+        );
+    }
+}
@@ -0,0 +1,336 @@
+use anyhow::{bail, Result};
+use glam::{Mat4, Vec4};
+use windows::Win32::Graphics::Direct3D12::*;
+
+/// Which end of the 0 to 1 depth range is near. Projections, depth tests and depth clears all have
+/// to agree on it. Reversed puts the precision of float depth where perspective needs it most.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DepthRange {
+    #[default]
+    Standard,
+    Reversed,
+}
+
+impl DepthRange {
+    pub fn near_depth(&self) -> f32 {
+        match self {
+            DepthRange::Standard => 0.0,
+            DepthRange::Reversed => 1.0,
+        }
+    }
+
+    /// Also the value depth buffers are cleared to
+    pub fn far_depth(&self) -> f32 {
+        match self {
+            DepthRange::Standard => 1.0,
+            DepthRange::Reversed => 0.0,
+        }
+    }
+
+    pub fn comparison_func(&self) -> D3D12_COMPARISON_FUNC {
+        match self {
+            DepthRange::Standard => D3D12_COMPARISON_FUNC_LESS,
+            // Equal passes so geometry drawn exactly at the cleared depth, e.g. at infinity, shows
+            DepthRange::Reversed => D3D12_COMPARISON_FUNC_GREATER_EQUAL,
+        }
+    }
+
+    pub fn is_closer(&self, depth: f32, than: f32) -> bool {
+        match self {
+            DepthRange::Standard => depth < than,
+            DepthRange::Reversed => depth > than,
+        }
+    }
+
+    /// The view space distances that map to depth 0 and depth 1. Reversed projections are built
+    /// by swapping them rather than flipping a standard projection, which would throw away the
+    /// precision gained.
+    fn depth_planes(&self, near: f32, far: f32) -> (f32, f32) {
+        match self {
+            DepthRange::Standard => (near, far),
+            DepthRange::Reversed => (far, near),
+        }
+    }
+}
+
+/// How a camera maps view space, left handed, to clip space
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CameraProjection {
+    Perspective {
+        fov_y: f32,
+        near: f32,
+        far: f32,
+    },
+    /// `height` of the view volume in view space units, the width follows the aspect ratio
+    Orthographic {
+        height: f32,
+        near: f32,
+        far: f32,
+    },
+    /// A perspective projection with the extents of its near plane given in view space, e.g. for
+    /// tiled or stereo rendering. Ignores the aspect ratio.
+    OffCenter {
+        left: f32,
+        right: f32,
+        bottom: f32,
+        top: f32,
+        near: f32,
+        far: f32,
+    },
+    /// A perspective projection whose near plane is replaced by `clip_plane`, a view space plane
+    /// with its normal pointing at what is kept, so nothing behind a portal or mirror is drawn
+    Oblique {
+        fov_y: f32,
+        near: f32,
+        far: f32,
+        clip_plane: Vec4,
+    },
+}
+
+impl CameraProjection {
+    pub fn matrix(&self, aspect_ratio: f32, depth_range: DepthRange) -> Mat4 {
+        match *self {
+            CameraProjection::Perspective { fov_y, near, far } => {
+                let (z_0, z_1) = depth_range.depth_planes(near, far);
+                Mat4::perspective_lh(fov_y, aspect_ratio, z_0, z_1)
+            }
+            CameraProjection::Orthographic { height, near, far } => {
+                let (z_0, z_1) = depth_range.depth_planes(near, far);
+                let half_height = height * 0.5;
+                let half_width = half_height * aspect_ratio;
+                Mat4::orthographic_lh(-half_width, half_width, -half_height, half_height, z_0, z_1)
+            }
+            CameraProjection::OffCenter {
+                left,
+                right,
+                bottom,
+                top,
+                near,
+                far,
+            } => off_center_lh(left, right, bottom, top, near, far, depth_range),
+            CameraProjection::Oblique {
+                fov_y,
+                near,
+                far,
+                clip_plane,
+            } => {
+                let (z_0, z_1) = depth_range.depth_planes(near, far);
+                oblique_near_plane(
+                    Mat4::perspective_lh(fov_y, aspect_ratio, z_0, z_1),
+                    clip_plane,
+                    depth_range,
+                )
+            }
+        }
+    }
+
+    /// View space distances of the near and far plane
+    pub fn clip_planes(&self) -> (f32, f32) {
+        match *self {
+            CameraProjection::Perspective { near, far, .. }
+            | CameraProjection::Orthographic { near, far, .. }
+            | CameraProjection::OffCenter { near, far, .. }
+            | CameraProjection::Oblique { near, far, .. } => (near, far),
+        }
+    }
+
+    pub fn set_clip_planes(&mut self, new_near: f32, new_far: f32) {
+        match self {
+            CameraProjection::Perspective { near, far, .. }
+            | CameraProjection::Orthographic { near, far, .. }
+            | CameraProjection::OffCenter { near, far, .. }
+            | CameraProjection::Oblique { near, far, .. } => {
+                *near = new_near;
+                *far = new_far;
+            }
+        }
+    }
+
+    /// Only perspective and oblique projections have a field of view
+    pub fn set_fov_y(&mut self, new_fov_y: f32) -> Result<()> {
+        match self {
+            CameraProjection::Perspective { fov_y, .. }
+            | CameraProjection::Oblique { fov_y, .. } => {
+                *fov_y = new_fov_y;
+                Ok(())
+            }
+            _ => bail!("{:?} has no field of view", self),
+        }
+    }
+}
+
+fn off_center_lh(
+    left: f32,
+    right: f32,
+    bottom: f32,
+    top: f32,
+    near: f32,
+    far: f32,
+    depth_range: DepthRange,
+) -> Mat4 {
+    let width = right - left;
+    let height = top - bottom;
+    let (z_0, z_1) = depth_range.depth_planes(near, far);
+    let depth = z_1 / (z_1 - z_0);
+
+    Mat4::from_cols(
+        Vec4::new(2.0 * near / width, 0.0, 0.0, 0.0),
+        Vec4::new(0.0, 2.0 * near / height, 0.0, 0.0),
+        Vec4::new(
+            -(right + left) / width,
+            -(top + bottom) / height,
+            depth,
+            1.0,
+        ),
+        Vec4::new(0.0, 0.0, -z_0 * depth, 0.0),
+    )
+}
+
+/// Lengyel's oblique near plane clipping, adapted to 0 to 1 depth in either direction
+fn oblique_near_plane(projection: Mat4, clip_plane: Vec4, depth_range: DepthRange) -> Mat4 {
+    // The far corner of the view volume on the side of the plane, the far plane is moved so it
+    // still passes through it
+    let corner = projection.inverse()
+        * Vec4::new(
+            clip_plane.x.signum(),
+            clip_plane.y.signum(),
+            depth_range.far_depth(),
+            1.0,
+        );
+    let w_row = projection.row(3);
+    let scaled_plane = clip_plane * (w_row.dot(corner) / clip_plane.dot(corner));
+
+    // Near is where depth reaches 0 with standard depth, and where it reaches w with reversed
+    let mut rows = projection.transpose();
+    rows.z_axis = match depth_range {
+        DepthRange::Standard => scaled_plane,
+        DepthRange::Reversed => w_row - scaled_plane,
+    };
+
+    rows.transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::*;
+
+    fn ndc(projection: &Mat4, point: Vec3) -> Vec3 {
+        projection.project_point3(point)
+    }
+
+    const PERSPECTIVE: CameraProjection = CameraProjection::Perspective {
+        fov_y: std::f32::consts::FRAC_PI_2,
+        near: 0.1,
+        far: 100.0,
+    };
+
+    #[test]
+    fn reversed_depth_swaps_near_and_far() {
+        for depth_range in [DepthRange::Standard, DepthRange::Reversed] {
+            for projection in [
+                PERSPECTIVE,
+                CameraProjection::Orthographic {
+                    height: 10.0,
+                    near: 0.1,
+                    far: 100.0,
+                },
+            ] {
+                let matrix = projection.matrix(1.0, depth_range);
+                let near = ndc(&matrix, Vec3::new(0.0, 0.0, 0.1)).z;
+                let far = ndc(&matrix, Vec3::new(0.0, 0.0, 100.0)).z;
+                let middle = ndc(&matrix, Vec3::new(0.0, 0.0, 50.0)).z;
+                let off_axis = Vec3::new(1.0, 1.0, 5.0);
+                let standard = projection.matrix(1.0, DepthRange::Standard);
+
+                assert!((near - depth_range.near_depth()).abs() < 1e-4);
+                assert!((far - depth_range.far_depth()).abs() < 1e-4);
+                assert!(depth_range.is_closer(near, middle));
+                assert!(ndc(&matrix, off_axis)
+                    .truncate()
+                    .abs_diff_eq(ndc(&standard, off_axis).truncate(), 1e-5));
+            }
+        }
+    }
+
+    #[test]
+    fn reversed_depth_separates_distant_surfaces() {
+        let depths = |depth_range| {
+            let matrix = PERSPECTIVE.matrix(1.0, depth_range);
+            (
+                ndc(&matrix, Vec3::new(0.0, 0.0, 99.0)).z,
+                ndc(&matrix, Vec3::new(0.0, 0.0, 99.001)).z,
+            )
+        };
+
+        let (closer, further) = depths(DepthRange::Standard);
+        assert_eq!(closer, further);
+        let (closer, further) = depths(DepthRange::Reversed);
+        assert!(DepthRange::Reversed.is_closer(closer, further));
+    }
+
+    #[test]
+    fn symmetric_off_center_matches_perspective() {
+        let off_center = CameraProjection::OffCenter {
+            left: -0.2,
+            right: 0.2,
+            bottom: -0.1,
+            top: 0.1,
+            near: 0.1,
+            far: 100.0,
+        };
+        let perspective = CameraProjection::Perspective {
+            fov_y: 2.0 * (0.1f32 / 0.1).atan(),
+            near: 0.1,
+            far: 100.0,
+        };
+
+        assert!(off_center
+            .matrix(1.0, DepthRange::Standard)
+            .abs_diff_eq(perspective.matrix(2.0, DepthRange::Standard), 1e-5));
+    }
+
+    #[test]
+    fn oblique_near_plane_clips_at_the_portal() {
+        // Everything closer than z = 5 is behind the portal
+        let clip_plane = Vec4::new(0.0, 0.0, 1.0, -5.0);
+        for depth_range in [DepthRange::Standard, DepthRange::Reversed] {
+            let matrix = CameraProjection::Oblique {
+                fov_y: std::f32::consts::FRAC_PI_2,
+                near: 0.1,
+                far: 100.0,
+                clip_plane,
+            }
+            .matrix(1.0, depth_range);
+
+            let on_portal = ndc(&matrix, Vec3::new(1.0, 2.0, 5.0)).z;
+            let beyond = ndc(&matrix, Vec3::new(0.0, 0.0, 20.0)).z;
+            assert!((on_portal - depth_range.near_depth()).abs() < 1e-4);
+            assert!(depth_range.is_closer(on_portal, beyond));
+            assert!((0.0..=1.0).contains(&beyond));
+        }
+    }
+
+    #[test]
+    fn fov_only_for_perspective() {
+        let mut perspective = PERSPECTIVE;
+        perspective.set_fov_y(1.0).unwrap();
+        perspective.set_clip_planes(1.0, 10.0);
+        assert_eq!(
+            perspective,
+            CameraProjection::Perspective {
+                fov_y: 1.0,
+                near: 1.0,
+                far: 10.0,
+            }
+        );
+
+        let mut orthographic = CameraProjection::Orthographic {
+            height: 10.0,
+            near: 0.1,
+            far: 100.0,
+        };
+        assert!(orthographic.set_fov_y(1.0).is_err());
+    }
+}
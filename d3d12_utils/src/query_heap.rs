@@ -0,0 +1,133 @@
+use std::marker::PhantomData;
+
+use anyhow::{ensure, Context, Result};
+use windows::Win32::Graphics::{Direct3D12::*, Dxgi::Common::DXGI_SAMPLE_DESC};
+
+use crate::Resource;
+
+/// Queries of one type with a readback buffer they are resolved into, each result a `T`, e.g.
+/// `u64` for timestamps
+#[derive(Debug)]
+pub struct QueryHeap<T: Copy> {
+    query_heap: ID3D12QueryHeap,
+    readback_buffer: Resource,
+    query_type: D3D12_QUERY_TYPE,
+    count: usize,
+    result: PhantomData<T>,
+}
+
+impl<T: Copy> QueryHeap<T> {
+    pub fn new(
+        device: &ID3D12Device4,
+        heap_type: D3D12_QUERY_HEAP_TYPE,
+        query_type: D3D12_QUERY_TYPE,
+        count: usize,
+    ) -> Result<Self> {
+        let mut query_heap: Option<ID3D12QueryHeap> = None;
+        unsafe {
+            device.CreateQueryHeap(
+                &D3D12_QUERY_HEAP_DESC {
+                    Type: heap_type,
+                    Count: count as u32,
+                    NodeMask: 0,
+                },
+                &mut query_heap,
+            )?;
+        }
+        let query_heap = query_heap.context("No query heap was created")?;
+
+        let readback_buffer = Resource::create_committed(
+            device,
+            &D3D12_HEAP_PROPERTIES {
+                Type: D3D12_HEAP_TYPE_READBACK,
+                ..Default::default()
+            },
+            &D3D12_RESOURCE_DESC {
+                Dimension: D3D12_RESOURCE_DIMENSION_BUFFER,
+                Width: (count * std::mem::size_of::<T>()) as u64,
+                Height: 1,
+                DepthOrArraySize: 1,
+                MipLevels: 1,
+                SampleDesc: DXGI_SAMPLE_DESC {
+                    Count: 1,
+                    Quality: 0,
+                },
+                Layout: D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
+                ..Default::default()
+            },
+            D3D12_RESOURCE_STATE_COPY_DEST,
+            None,
+            true,
+        )?;
+
+        Ok(Self {
+            query_heap,
+            readback_buffer,
+            query_type,
+            count,
+            result: PhantomData,
+        })
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Not for timestamps, which only end
+    pub fn begin(&self, command_list: &ID3D12GraphicsCommandList, index: usize) {
+        unsafe {
+            command_list.BeginQuery(&self.query_heap, self.query_type, index as u32);
+        }
+    }
+
+    pub fn end(&self, command_list: &ID3D12GraphicsCommandList, index: usize) {
+        unsafe {
+            command_list.EndQuery(&self.query_heap, self.query_type, index as u32);
+        }
+    }
+
+    /// Copies the results of `count` queries from `first` on into the readback buffer, where
+    /// `read` finds them once the command list has finished
+    pub fn resolve(
+        &self,
+        command_list: &ID3D12GraphicsCommandList,
+        first: usize,
+        count: usize,
+    ) -> Result<()> {
+        ensure!(
+            first + count <= self.count,
+            "Resolving queries {}..{} of {}",
+            first,
+            first + count,
+            self.count
+        );
+
+        unsafe {
+            command_list.ResolveQueryData(
+                &self.query_heap,
+                self.query_type,
+                first as u32,
+                count as u32,
+                &self.readback_buffer.device_resource,
+                (first * std::mem::size_of::<T>()) as u64,
+            );
+        }
+
+        Ok(())
+    }
+
+    /// The last resolved results, only meaningful once the fence after the resolve has been
+    /// waited on
+    pub fn read(&self, first: usize, count: usize) -> Option<&[T]> {
+        if first + count > self.count || self.readback_buffer.mapped_data.is_null() {
+            return None;
+        }
+
+        Some(unsafe {
+            std::slice::from_raw_parts(
+                (self.readback_buffer.mapped_data as *const T).add(first),
+                count,
+            )
+        })
+    }
+}
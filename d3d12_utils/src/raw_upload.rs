@@ -0,0 +1,162 @@
+use std::{fs::File, path::Path};
+
+use anyhow::{ensure, Context, Result};
+use memmap2::{Mmap, MmapOptions};
+
+use crate::{CommandQueue, SubResource, UploadRingBuffer};
+
+/// Where the rows of one subresource are in memory someone else owns, e.g. a video decoder's
+/// frame with padded rows. Rows are copied straight from there into the upload buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceLayout {
+    /// Bytes from the start of the data to the first row
+    pub offset: usize,
+    /// Bytes from the start of one row to the next, at least the size of a row
+    pub row_pitch: usize,
+    /// Bytes from the start of one depth slice to the next, only used by 3D textures
+    pub slice_pitch: usize,
+}
+
+impl SourceLayout {
+    /// Rows and slices right after each other
+    pub fn tightly_packed(offset: usize, row_bytes: usize, rows: usize) -> Self {
+        Self {
+            offset,
+            row_pitch: row_bytes,
+            slice_pitch: row_bytes * rows,
+        }
+    }
+
+    /// Offset of a row in the data
+    pub fn row_offset(&self, row: usize, slice: usize) -> usize {
+        self.offset + slice * self.slice_pitch + row * self.row_pitch
+    }
+
+    /// Bytes of data a subresource of `depth` slices of `rows` rows, each `row_bytes` long, needs
+    /// to be read with this layout. Fails when rows or slices would overlap.
+    pub fn required_size(&self, row_bytes: usize, rows: usize, depth: usize) -> Result<usize> {
+        ensure!(
+            self.row_pitch >= row_bytes,
+            "Row pitch of {} bytes is smaller than a row of {} bytes",
+            self.row_pitch,
+            row_bytes
+        );
+        ensure!(
+            depth <= 1 || self.slice_pitch >= self.row_pitch * rows,
+            "Slice pitch of {} bytes is smaller than {} rows of {} bytes",
+            self.slice_pitch,
+            rows,
+            self.row_pitch
+        );
+
+        if rows == 0 || depth == 0 {
+            return Ok(self.offset);
+        }
+        Ok(self.row_offset(rows - 1, depth - 1) + row_bytes)
+    }
+}
+
+/// Copies `data` into `destination`, right away when it is in a mapped upload heap and through
+/// `uploader` otherwise. `dependent_queue` won't run anything that comes after until the copy is
+/// done.
+pub fn upload_buffer(
+    uploader: &mut UploadRingBuffer,
+    dependent_queue: Option<&CommandQueue>,
+    data: &[u8],
+    destination: &SubResource,
+) -> Result<()> {
+    ensure!(
+        data.len() <= destination.size,
+        "{} bytes don't fit a buffer of {} bytes",
+        data.len(),
+        destination.size
+    );
+
+    // Upload heap buffers can be written directly
+    if destination.get_mapped_data().is_some() {
+        return destination.copy_from(data);
+    }
+
+    let upload = uploader.allocate(data.len())?;
+    upload.sub_resource.copy_from(data)?;
+    upload
+        .sub_resource
+        .copy_to_sub_resource(&upload.command_list, destination)?;
+    upload.submit(dependent_queue)
+}
+
+/// `upload_buffer` for memory that is not a Rust slice, e.g. a buffer a decoder library owns.
+/// The data is read once while this runs and not held on to.
+///
+/// # Safety
+///
+/// `data` has to be valid for reads of `len` bytes and must not be written to until this returns.
+pub unsafe fn upload_buffer_from_ptr(
+    uploader: &mut UploadRingBuffer,
+    dependent_queue: Option<&CommandQueue>,
+    data: *const u8,
+    len: usize,
+    destination: &SubResource,
+) -> Result<()> {
+    ensure!(!data.is_null(), "Uploading from a null pointer");
+
+    upload_buffer(
+        uploader,
+        dependent_queue,
+        std::slice::from_raw_parts(data, len),
+        destination,
+    )
+}
+
+/// Maps `len` bytes of a file starting at `offset` read only, so they can be uploaded without
+/// reading them into memory first.
+///
+/// # Safety
+///
+/// Nothing, in this process or another, may change or truncate the file while the map is alive.
+/// Reads from the map are undefined behaviour otherwise.
+pub unsafe fn map_file_range(path: &Path, offset: u64, len: usize) -> Result<Mmap> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let file_size = file.metadata()?.len();
+    ensure!(
+        offset + len as u64 <= file_size,
+        "{} bytes at offset {} run past the end of {}, which is {} bytes",
+        len,
+        offset,
+        path.display(),
+        file_size
+    );
+
+    MmapOptions::new()
+        .offset(offset)
+        .len(len)
+        .map(&file)
+        .with_context(|| format!("Failed to map {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn padded_rows_need_the_last_row_only_once() {
+        // 3 rows of 10 bytes, each padded to 16, after a 4 byte header
+        let layout = SourceLayout {
+            offset: 4,
+            row_pitch: 16,
+            slice_pitch: 48,
+        };
+        assert_eq!(layout.row_offset(2, 0), 36);
+        assert_eq!(layout.required_size(10, 3, 1).unwrap(), 46);
+        assert_eq!(layout.required_size(10, 3, 2).unwrap(), 94);
+        assert_eq!(layout.required_size(10, 0, 1).unwrap(), 4);
+
+        assert!(layout.required_size(20, 3, 1).is_err());
+        assert!(layout.required_size(10, 4, 2).is_err());
+        // A single slice never steps by the slice pitch
+        assert!(layout.required_size(10, 4, 1).is_ok());
+
+        let packed = SourceLayout::tightly_packed(0, 10, 3);
+        assert_eq!(packed.required_size(10, 3, 2).unwrap(), 60);
+    }
+}
@@ -0,0 +1,219 @@
+use glam::{Mat4, Vec2, Vec3, Vec4Swizzles};
+
+use crate::{Aabb, DepthRange, Plane};
+
+/// A half line from `origin` along a unit length `direction`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ray {
+    pub origin: Vec3,
+    pub direction: Vec3,
+}
+
+impl Ray {
+    pub fn new(origin: Vec3, direction: Vec3) -> Self {
+        Self {
+            origin,
+            direction: direction.normalize(),
+        }
+    }
+
+    /// The ray through a point on screen, starting on the near plane. `uv` goes from (0, 0) at the
+    /// top left to (1, 1) at the bottom right of the region the camera renders into.
+    pub fn from_screen(view_projection: &Mat4, uv: Vec2, depth_range: DepthRange) -> Self {
+        let inverse_view_projection = view_projection.inverse();
+        let clip = Vec2::new(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+        let unproject = |depth: f32| {
+            let world = inverse_view_projection * clip.extend(depth).extend(1.0);
+            world.xyz() / world.w
+        };
+
+        // Halfway into the depth range rather than the far plane, which can be at infinity
+        let near = unproject(depth_range.near_depth());
+        let middle = unproject(0.5);
+
+        Self::new(near, middle - near)
+    }
+
+    pub fn at(&self, t: f32) -> Vec3 {
+        self.origin + self.direction * t
+    }
+
+    /// Distance along the ray to where it crosses `plane`, None when it runs parallel to it or
+    /// points away from it
+    pub fn intersect_plane(&self, plane: &Plane) -> Option<f32> {
+        let facing = plane.normal.dot(self.direction);
+        if facing.abs() < f32::EPSILON {
+            return None;
+        }
+
+        let t = -plane.signed_distance(self.origin) / facing;
+        (t >= 0.0).then_some(t)
+    }
+
+    /// Parameters of the closest points between the ray's line and the line through `point`
+    /// along the unit length `direction`, as (along the ray, along the line). None for parallel
+    /// lines.
+    pub fn closest_to_line(&self, point: Vec3, direction: Vec3) -> Option<(f32, f32)> {
+        let alignment = self.direction.dot(direction);
+        let denominator = 1.0 - alignment * alignment;
+        if denominator < 1e-6 {
+            return None;
+        }
+
+        let offset = self.origin - point;
+        let along_ray = self.direction.dot(offset);
+        let along_line = direction.dot(offset);
+
+        Some((
+            (alignment * along_line - along_ray) / denominator,
+            (along_line - alignment * along_ray) / denominator,
+        ))
+    }
+
+    /// Distance along the ray to where it enters `aabb`, 0 when it starts inside
+    pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<f32> {
+        if aabb.is_empty() {
+            return None;
+        }
+
+        // Slabs the ray runs parallel to give infinite distances, which the min and max sort out
+        let inverse_direction = self.direction.recip();
+        let to_min = (aabb.min - self.origin) * inverse_direction;
+        let to_max = (aabb.max - self.origin) * inverse_direction;
+        let enter = to_min.min(to_max).max_element().max(0.0);
+        let exit = to_min.max(to_max).min_element();
+
+        (enter <= exit).then_some(enter)
+    }
+
+    /// Distance along the ray to where it hits the triangle, from either side
+    pub fn intersect_triangle(&self, triangle: [Vec3; 3]) -> Option<f32> {
+        let [a, b, c] = triangle;
+        let edge_1 = b - a;
+        let edge_2 = c - a;
+
+        let p = self.direction.cross(edge_2);
+        let determinant = edge_1.dot(p);
+        if determinant.abs() < f32::EPSILON {
+            return None;
+        }
+        let inverse_determinant = determinant.recip();
+
+        let offset = self.origin - a;
+        let u = offset.dot(p) * inverse_determinant;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = offset.cross(edge_1);
+        let v = self.direction.dot(q) * inverse_determinant;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let t = edge_2.dot(q) * inverse_determinant;
+        (t >= 0.0).then_some(t)
+    }
+
+    /// Distance along the ray to the point closest to the segment from `start` to `end`, and how
+    /// far apart the two are there
+    pub fn closest_to_segment(&self, start: Vec3, end: Vec3) -> Option<(f32, f32)> {
+        let length = (end - start).length();
+        let direction = (end - start) / length;
+        let (_, along_line) = self.closest_to_line(start, direction)?;
+
+        let on_segment = start + direction * along_line.clamp(0.0, length);
+        let t = (on_segment - self.origin).dot(self.direction).max(0.0);
+
+        Some((t, self.at(t).distance(on_segment)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CameraProjection;
+
+    #[test]
+    fn screen_rays_go_through_the_pixel() {
+        let projection = CameraProjection::Perspective {
+            fov_y: std::f32::consts::FRAC_PI_2,
+            near: 0.1,
+            far: 100.0,
+        };
+
+        for depth_range in [DepthRange::Standard, DepthRange::Reversed] {
+            let view_projection = projection.matrix(1.0, depth_range);
+            let center = Ray::from_screen(&view_projection, Vec2::splat(0.5), depth_range);
+            assert!(center.origin.abs_diff_eq(Vec3::new(0.0, 0.0, 0.1), 1e-4));
+            assert!(center.direction.abs_diff_eq(Vec3::Z, 1e-4));
+
+            // A 90 degree field of view reaches x = z at the right edge
+            let right = Ray::from_screen(&view_projection, Vec2::new(1.0, 0.5), depth_range);
+            assert!(right
+                .direction
+                .abs_diff_eq(Vec3::new(1.0, 0.0, 1.0).normalize(), 1e-4));
+        }
+    }
+
+    #[test]
+    fn hits_boxes_and_triangles() {
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::Z);
+        let unit_box = Aabb {
+            min: Vec3::splat(-1.0),
+            max: Vec3::ONE,
+        };
+
+        assert_eq!(ray.intersect_aabb(&unit_box), Some(4.0));
+        assert_eq!(
+            Ray::new(Vec3::ZERO, Vec3::X).intersect_aabb(&unit_box),
+            Some(0.0)
+        );
+        assert_eq!(
+            Ray::new(Vec3::new(0.0, 2.0, -5.0), Vec3::Z).intersect_aabb(&unit_box),
+            None
+        );
+        assert_eq!(
+            Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::Z).intersect_aabb(&unit_box),
+            None
+        );
+        assert_eq!(ray.intersect_aabb(&Aabb::EMPTY), None);
+
+        let triangle = [
+            Vec3::new(-1.0, -1.0, 1.0),
+            Vec3::new(1.0, -1.0, 1.0),
+            Vec3::new(0.0, 1.0, 1.0),
+        ];
+        assert_eq!(ray.intersect_triangle(triangle), Some(6.0));
+        let [a, b, c] = triangle;
+        assert_eq!(ray.intersect_triangle([a, c, b]), Some(6.0));
+        assert_eq!(
+            Ray::new(Vec3::new(0.9, 0.9, -5.0), Vec3::Z).intersect_triangle(triangle),
+            None
+        );
+        assert_eq!(
+            Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::Z).intersect_triangle(triangle),
+            None
+        );
+    }
+
+    #[test]
+    fn closest_points_to_lines_and_segments() {
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::Z);
+
+        assert_eq!(
+            ray.closest_to_line(Vec3::new(-3.0, 1.0, 0.0), Vec3::X),
+            Some((5.0, 3.0))
+        );
+        assert_eq!(ray.closest_to_line(Vec3::X, Vec3::Z), None);
+
+        let (t, distance) = ray
+            .closest_to_segment(Vec3::new(1.0, 0.0, 0.0), Vec3::new(3.0, 0.0, 0.0))
+            .unwrap();
+        assert_eq!((t, distance), (5.0, 1.0));
+
+        let ground = Plane::from_point_normal(Vec3::ZERO, Vec3::Z);
+        assert_eq!(ray.intersect_plane(&ground), Some(5.0));
+        assert_eq!(ray.intersect_plane(&ground.flipped()), Some(5.0));
+        assert_eq!(Ray::new(Vec3::ONE, Vec3::Z).intersect_plane(&ground), None);
+    }
+}
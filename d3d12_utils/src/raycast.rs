@@ -0,0 +1,177 @@
+use glam::{Mat4, Vec3};
+
+use crate::{Aabb, ObjVertex, Ray};
+
+/// Positions and triangle list indices of a mesh kept on the CPU, for queries like raycasts
+#[derive(Debug, Clone, Default)]
+pub struct CpuMesh {
+    pub positions: Vec<Vec3>,
+    pub indices: Vec<u32>,
+}
+
+impl CpuMesh {
+    pub fn from_vertices(vertices: &[ObjVertex], indices: &[u32]) -> Self {
+        Self {
+            positions: vertices.iter().map(|vertex| vertex.position).collect(),
+            indices: indices.to_vec(),
+        }
+    }
+
+    /// The closest triangle `ray` hits, as the distance along the ray and the triangle's unit
+    /// normal turned towards the ray. Triangles with indices out of range are skipped.
+    pub fn raycast(&self, ray: &Ray) -> Option<(f32, Vec3)> {
+        self.indices
+            .chunks_exact(3)
+            .filter_map(|triangle| {
+                let [a, b, c] = [0, 1, 2].map(|i| self.positions.get(triangle[i] as usize));
+                let triangle = [*a?, *b?, *c?];
+                let t = ray.intersect_triangle(triangle)?;
+
+                Some((t, triangle))
+            })
+            .min_by(|(a, _), (b, _)| a.total_cmp(b))
+            .and_then(|(t, [a, b, c])| {
+                let normal = (b - a).cross(c - a).try_normalize()?;
+                Some((t, facing(normal, ray)))
+            })
+    }
+}
+
+/// Where a ray hit something in the scene, in world space
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RaycastHit {
+    /// Index of the object that was hit
+    pub object: usize,
+    /// Along the ray
+    pub distance: f32,
+    pub position: Vec3,
+    /// Unit length, turned towards the ray
+    pub normal: Vec3,
+}
+
+impl RaycastHit {
+    /// The closest hit of a world space `ray` on `mesh` placed with `transform`
+    pub fn on_mesh(object: usize, ray: &Ray, transform: &Mat4, mesh: &CpuMesh) -> Option<Self> {
+        let inverse = transform.inverse();
+        let local_ray = Ray::new(
+            inverse.transform_point3(ray.origin),
+            inverse.transform_vector3(ray.direction),
+        );
+        let (t, local_normal) = mesh.raycast(&local_ray)?;
+
+        let position = transform.transform_point3(local_ray.at(t));
+        // Normals go through the inverse transpose to stay perpendicular under non-uniform scale
+        let normal = inverse
+            .transpose()
+            .transform_vector3(local_normal)
+            .try_normalize()?;
+
+        Some(Self {
+            object,
+            distance: (position - ray.origin).dot(ray.direction),
+            position,
+            normal: facing(normal, ray),
+        })
+    }
+
+    /// Where a world space `ray` enters `bounds`, for objects without CPU geometry. The normal is
+    /// that of the face it enters through.
+    pub fn on_bounds(object: usize, ray: &Ray, bounds: &Aabb) -> Option<Self> {
+        let distance = ray.intersect_aabb(bounds)?;
+        let position = ray.at(distance);
+
+        // The face the entry point lies on, relative to the size of the box
+        let half_extent = ((bounds.max - bounds.min) * 0.5).max(Vec3::splat(f32::EPSILON));
+        let offset = (position - bounds.center()) / half_extent;
+        let distances = offset.abs();
+        let axis = (0..3)
+            .max_by(|a, b| distances[*a].total_cmp(&distances[*b]))
+            .unwrap_or(0);
+        let mut normal = Vec3::ZERO;
+        normal[axis] = offset[axis].signum();
+
+        Some(Self {
+            object,
+            distance,
+            position,
+            normal: facing(normal, ray),
+        })
+    }
+}
+
+fn facing(normal: Vec3, ray: &Ray) -> Vec3 {
+    if normal.dot(ray.direction) > 0.0 {
+        -normal
+    } else {
+        normal
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Quat;
+
+    // Two triangles making up a unit quad at z = 0, facing -z
+    fn quad() -> CpuMesh {
+        CpuMesh {
+            positions: vec![
+                Vec3::new(-0.5, -0.5, 0.0),
+                Vec3::new(-0.5, 0.5, 0.0),
+                Vec3::new(0.5, 0.5, 0.0),
+                Vec3::new(0.5, -0.5, 0.0),
+            ],
+            indices: vec![0, 1, 2, 0, 2, 3],
+        }
+    }
+
+    #[test]
+    fn hits_the_closest_triangle() {
+        let mut mesh = quad();
+        // A second quad in front of the first one
+        mesh.positions
+            .extend(quad().positions.iter().map(|position| *position - Vec3::Z));
+        mesh.indices.extend([4, 5, 6, 4, 6, 7]);
+
+        let ray = Ray::new(Vec3::new(0.2, 0.1, -5.0), Vec3::Z);
+        assert_eq!(mesh.raycast(&ray), Some((4.0, -Vec3::Z)));
+
+        // Hit from behind, the normal turns around
+        let ray = Ray::new(Vec3::new(0.2, 0.1, 5.0), -Vec3::Z);
+        assert_eq!(mesh.raycast(&ray), Some((5.0, Vec3::Z)));
+
+        let ray = Ray::new(Vec3::new(2.0, 0.0, -5.0), Vec3::Z);
+        assert_eq!(mesh.raycast(&ray), None);
+
+        mesh.indices.extend([0, 1, 100]);
+        assert_eq!(
+            mesh.raycast(&Ray::new(Vec3::new(0.2, 0.1, -5.0), Vec3::Z)),
+            Some((4.0, -Vec3::Z))
+        );
+    }
+
+    #[test]
+    fn hits_are_in_world_space() {
+        // Turned to face +x and stretched along its own y
+        let transform = Mat4::from_scale_rotation_translation(
+            Vec3::new(1.0, 4.0, 1.0),
+            Quat::from_rotation_y(-std::f32::consts::FRAC_PI_2),
+            Vec3::new(10.0, 0.0, 0.0),
+        );
+        let ray = Ray::new(Vec3::new(20.0, 1.5, 0.0), -Vec3::X);
+
+        let hit = RaycastHit::on_mesh(7, &ray, &transform, &quad()).unwrap();
+        assert_eq!(hit.object, 7);
+        assert!((hit.distance - 10.0).abs() < 1e-4);
+        assert!(hit.position.abs_diff_eq(Vec3::new(10.0, 1.5, 0.0), 1e-4));
+        assert!(hit.normal.abs_diff_eq(Vec3::X, 1e-4));
+
+        let bounds = Aabb {
+            min: Vec3::new(9.0, -1.0, -1.0),
+            max: Vec3::new(11.0, 2.0, 1.0),
+        };
+        let hit = RaycastHit::on_bounds(3, &ray, &bounds).unwrap();
+        assert_eq!(hit.distance, 9.0);
+        assert_eq!(hit.normal, Vec3::X);
+    }
+}
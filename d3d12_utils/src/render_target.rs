@@ -0,0 +1,524 @@
+use anyhow::{Context, Result};
+use glam::Vec2;
+use windows::Win32::{
+    Foundation::RECT,
+    Graphics::{Direct3D12::*, Dxgi::Common::*},
+};
+
+use crate::{
+    srgb_format, BarrierBatcher, DepthRange, DescriptorHandle, DescriptorManager, TargetFormats,
+    TextureDimension, TextureHandle, TextureInfo, TextureManager, DEPTH_STENCIL_FORMAT,
+};
+
+/// A part of a render target in fractions of its render extent, e.g. one player's half of the
+/// screen. (0, 0) is the top left corner.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewportRect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl ViewportRect {
+    pub const FULL: Self = Self {
+        x: 0.0,
+        y: 0.0,
+        width: 1.0,
+        height: 1.0,
+    };
+
+    /// Pixels covered inside `render_extent`. Edges are rounded so regions sharing an edge
+    /// neither overlap nor leave a gap.
+    pub fn pixel_rect(&self, render_extent: (u32, u32)) -> RECT {
+        let (width, height) = render_extent;
+        let to_pixel = |fraction: f32, size: u32| (fraction * size as f32).round() as i32;
+
+        RECT {
+            left: to_pixel(self.x, width),
+            top: to_pixel(self.y, height),
+            right: to_pixel(self.x + self.width, width),
+            bottom: to_pixel(self.y + self.height, height),
+        }
+    }
+
+    pub fn aspect_ratio(&self, render_extent: (u32, u32)) -> f32 {
+        let (width, height) = render_extent;
+
+        (self.width * width as f32) / (self.height * height as f32).max(f32::EPSILON)
+    }
+
+    pub fn contains(&self, uv: Vec2) -> bool {
+        (self.x..=self.x + self.width).contains(&uv.x)
+            && (self.y..=self.y + self.height).contains(&uv.y)
+    }
+
+    /// Maps a position on the whole target to a position inside the region
+    pub fn to_local(&self, uv: Vec2) -> Vec2 {
+        (uv - Vec2::new(self.x, self.y)) / Vec2::new(self.width, self.height)
+    }
+
+    /// Maps a position inside the region to a position on the whole target
+    pub fn to_global(&self, local_uv: Vec2) -> Vec2 {
+        Vec2::new(self.x, self.y) + local_uv * Vec2::new(self.width, self.height)
+    }
+}
+
+/// What `RenderTarget::begin` does with what a texture held before
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadOp {
+    /// Fill colour with the target's clear colour, depth with the far plane and stencil with 0
+    Clear,
+    /// Keep it, e.g. to draw on top of an earlier pass
+    Load,
+    /// Every pixel gets drawn over, so the driver may throw the old contents away instead of
+    /// clearing or keeping them. Reading a pixel that was not drawn gives garbage.
+    DontCare,
+}
+
+/// A colour + depth pair that can be rendered into and then sampled or presented.
+/// The colour texture lives in `resting_state` outside of `begin`/`end`.
+#[derive(Debug)]
+pub struct RenderTarget {
+    pub color: TextureHandle,
+    pub depth: TextureHandle,
+    pub extent: (u32, u32),
+    pub viewport: D3D12_VIEWPORT,
+    pub scissor_rect: RECT,
+    /// Fast to clear to, as the colour texture was created with it
+    pub clear_color: [f32; 4],
+    pub color_load: LoadOp,
+    pub depth_load: LoadOp,
+    /// Decides the depth clear value, has to match the projections and pipelines drawing into it
+    pub depth_range: DepthRange,
+    resting_state: D3D12_RESOURCE_STATES,
+    srgb_rtv: Option<DescriptorHandle>,
+}
+
+impl RenderTarget {
+    /// Creates an offscreen target whose colour texture can be read by other passes, compute included
+    pub fn new(
+        device: &ID3D12Device4,
+        texture_manager: &mut TextureManager,
+        descriptor_manager: &mut DescriptorManager,
+        extent: (u32, u32),
+        format: DXGI_FORMAT,
+        clear_color: [f32; 4],
+        depth_range: DepthRange,
+    ) -> Result<Self> {
+        let (width, height) = extent;
+
+        let color = texture_manager.create_empty_texture(
+            device,
+            TextureInfo {
+                dimension: TextureDimension::Two(width as usize, height),
+                format,
+                is_render_target: true,
+                ..Default::default()
+            },
+            Some(D3D12_CLEAR_VALUE {
+                Format: format,
+                Anonymous: D3D12_CLEAR_VALUE_0 { Color: clear_color },
+            }),
+            D3D12_RESOURCE_STATE_ALL_SHADER_RESOURCE,
+            descriptor_manager,
+            true,
+        )?;
+
+        let depth = create_depth_buffer(
+            device,
+            texture_manager,
+            descriptor_manager,
+            extent,
+            depth_range,
+        )?;
+
+        let mut render_target = Self::from_textures(
+            color,
+            depth,
+            extent,
+            D3D12_RESOURCE_STATE_ALL_SHADER_RESOURCE,
+            depth_range,
+        );
+        render_target.clear_color = clear_color;
+
+        Ok(render_target)
+    }
+
+    /// Wraps existing textures, e.g. swapchain back buffers that rest in the present state
+    pub fn from_textures(
+        color: TextureHandle,
+        depth: TextureHandle,
+        extent: (u32, u32),
+        resting_state: D3D12_RESOURCE_STATES,
+        depth_range: DepthRange,
+    ) -> Self {
+        let (width, height) = extent;
+
+        Self {
+            color,
+            depth,
+            extent,
+            viewport: D3D12_VIEWPORT {
+                TopLeftX: 0.0,
+                TopLeftY: 0.0,
+                Width: width as f32,
+                Height: height as f32,
+                MinDepth: D3D12_MIN_DEPTH,
+                MaxDepth: D3D12_MAX_DEPTH,
+            },
+            scissor_rect: RECT {
+                left: 0,
+                top: 0,
+                right: width as i32,
+                bottom: height as i32,
+            },
+            clear_color: [0.0, 0.0, 0.0, 1.0],
+            color_load: LoadOp::Clear,
+            depth_load: LoadOp::Clear,
+            depth_range,
+            resting_state,
+            srgb_rtv: None,
+        }
+    }
+
+    /// Adds a view of the colour texture in its sRGB format, see `srgb_format`. Shaders writing
+    /// through it output linear colours and blending happens in linear space.
+    pub fn add_srgb_view(
+        &mut self,
+        device: &ID3D12Device4,
+        texture_manager: &TextureManager,
+        descriptor_manager: &mut DescriptorManager,
+    ) -> Result<()> {
+        let format = texture_manager.get_texture(&self.color)?.info.format;
+        let srgb_format =
+            srgb_format(format).with_context(|| format!("{:?} has no sRGB variant", format))?;
+
+        if let Some(rtv) = self.srgb_rtv.take() {
+            descriptor_manager.free(rtv);
+        }
+        self.srgb_rtv = Some(texture_manager.create_rtv_with_format(
+            device,
+            descriptor_manager,
+            &self.color,
+            srgb_format,
+        )?);
+
+        Ok(())
+    }
+
+    pub fn srgb_rtv(&self) -> Result<DescriptorHandle> {
+        self.srgb_rtv.context("Render target has no sRGB view")
+    }
+
+    /// Formats of the colour and depth views `begin` clears and passes bind
+    pub fn formats(&self, texture_manager: &TextureManager) -> Result<TargetFormats> {
+        let color = texture_manager.get_texture(&self.color)?.info.format;
+        let depth = texture_manager.get_texture(&self.depth)?.info.format;
+
+        TargetFormats::new(&[color], depth)
+    }
+
+    /// Checks that a pipeline created for `formats` can draw into the target. Only in debug
+    /// builds, as it looks up both textures on every draw.
+    pub fn validate_formats(
+        &self,
+        texture_manager: &TextureManager,
+        formats: &TargetFormats,
+    ) -> Result<()> {
+        if cfg!(debug_assertions) {
+            formats.ensure_matches(&self.formats(texture_manager)?)?;
+        }
+
+        Ok(())
+    }
+
+    /// Restricts rendering to the top left `render_extent` of the target, e.g. for dynamic resolution
+    pub fn set_render_extent(&mut self, render_extent: (u32, u32)) {
+        let (width, height) = render_extent;
+
+        self.viewport.Width = width as f32;
+        self.viewport.Height = height as f32;
+        self.scissor_rect.right = width as i32;
+        self.scissor_rect.bottom = height as i32;
+    }
+
+    pub fn render_extent(&self) -> (u32, u32) {
+        (
+            self.scissor_rect.right as u32,
+            self.scissor_rect.bottom as u32,
+        )
+    }
+
+    /// Viewport and scissor rect of `region` within the render extent
+    pub fn region_viewport(&self, region: &ViewportRect) -> (D3D12_VIEWPORT, RECT) {
+        let rect = region.pixel_rect(self.render_extent());
+        let viewport = D3D12_VIEWPORT {
+            TopLeftX: rect.left as f32,
+            TopLeftY: rect.top as f32,
+            Width: (rect.right - rect.left) as f32,
+            Height: (rect.bottom - rect.top) as f32,
+            MinDepth: D3D12_MIN_DEPTH,
+            MaxDepth: D3D12_MAX_DEPTH,
+        };
+
+        (viewport, rect)
+    }
+
+    /// Transitions the colour texture for rendering and loads both textures with the target's
+    /// `color_load` and `depth_load`. Flushes `barriers`, including whatever was pushed before.
+    pub fn begin(
+        &self,
+        command_list: &ID3D12GraphicsCommandList,
+        barriers: &mut BarrierBatcher,
+        texture_manager: &TextureManager,
+        descriptor_manager: &DescriptorManager,
+    ) -> Result<()> {
+        self.begin_with(
+            command_list,
+            barriers,
+            texture_manager,
+            descriptor_manager,
+            self.color_load,
+            self.depth_load,
+        )
+    }
+
+    /// `begin` for a pass that knows better than the target, e.g. one that draws a skybox over
+    /// every pixel and can skip the colour clear
+    pub fn begin_with(
+        &self,
+        command_list: &ID3D12GraphicsCommandList,
+        barriers: &mut BarrierBatcher,
+        texture_manager: &TextureManager,
+        descriptor_manager: &DescriptorManager,
+        color_load: LoadOp,
+        depth_load: LoadOp,
+    ) -> Result<()> {
+        self.transition(
+            barriers,
+            texture_manager,
+            self.resting_state,
+            D3D12_RESOURCE_STATE_RENDER_TARGET,
+        )?;
+        // Clears and discards need the render target state
+        barriers.flush(command_list);
+
+        match depth_load {
+            LoadOp::Clear => {
+                let dsv =
+                    descriptor_manager.get_cpu_handle(&texture_manager.get_dsv(&self.depth)?)?;
+                unsafe {
+                    command_list.ClearDepthStencilView(
+                        dsv,
+                        D3D12_CLEAR_FLAG_DEPTH | D3D12_CLEAR_FLAG_STENCIL,
+                        self.depth_range.far_depth(),
+                        0,
+                        &[],
+                    );
+                }
+            }
+            LoadOp::Load => (),
+            LoadOp::DontCare => {
+                let depth = texture_manager.get_texture(&self.depth)?;
+                unsafe {
+                    command_list
+                        .DiscardResource(&depth.get_resource()?.device_resource, std::ptr::null());
+                }
+            }
+        }
+
+        match color_load {
+            LoadOp::Clear => {
+                let rtv =
+                    descriptor_manager.get_cpu_handle(&texture_manager.get_rtv(&self.color)?)?;
+                unsafe {
+                    command_list.ClearRenderTargetView(rtv, self.clear_color.as_ptr(), &[]);
+                }
+            }
+            LoadOp::Load => (),
+            LoadOp::DontCare => {
+                let color = texture_manager.get_texture(&self.color)?;
+                unsafe {
+                    command_list
+                        .DiscardResource(&color.get_resource()?.device_resource, std::ptr::null());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Transitions the colour texture back to its resting state so it can be sampled or presented.
+    /// The transition stays in `barriers` until the next flush.
+    pub fn end(
+        &self,
+        barriers: &mut BarrierBatcher,
+        texture_manager: &TextureManager,
+    ) -> Result<()> {
+        self.transition(
+            barriers,
+            texture_manager,
+            D3D12_RESOURCE_STATE_RENDER_TARGET,
+            self.resting_state,
+        )
+    }
+
+    /// Instead of `begin`, for compute shaders that write the colour texture through its UAV,
+    /// e.g. a back buffer created with unordered access. Ended with `end_unordered_access`.
+    pub fn begin_unordered_access(
+        &self,
+        barriers: &mut BarrierBatcher,
+        texture_manager: &TextureManager,
+    ) -> Result<()> {
+        self.transition(
+            barriers,
+            texture_manager,
+            self.resting_state,
+            D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+        )
+    }
+
+    pub fn end_unordered_access(
+        &self,
+        barriers: &mut BarrierBatcher,
+        texture_manager: &TextureManager,
+    ) -> Result<()> {
+        self.transition(
+            barriers,
+            texture_manager,
+            D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+            self.resting_state,
+        )
+    }
+
+    /// `end` as a split barrier, for targets that are sampled a few passes later. Starts moving
+    /// the colour texture to its resting state, `finish_end` completes it before it is used.
+    pub fn start_end(
+        &self,
+        barriers: &mut BarrierBatcher,
+        texture_manager: &TextureManager,
+    ) -> Result<()> {
+        let color = texture_manager.get_texture(&self.color)?;
+        barriers.begin_split(
+            &color.get_resource()?.device_resource,
+            D3D12_RESOURCE_STATE_RENDER_TARGET,
+            self.resting_state,
+        )
+    }
+
+    pub fn finish_end(
+        &self,
+        barriers: &mut BarrierBatcher,
+        texture_manager: &TextureManager,
+    ) -> Result<()> {
+        let color = texture_manager.get_texture(&self.color)?;
+        barriers.end_split(
+            &color.get_resource()?.device_resource,
+            D3D12_RESOURCE_STATE_RENDER_TARGET,
+            self.resting_state,
+        )
+    }
+
+    pub fn delete(
+        self,
+        texture_manager: &mut TextureManager,
+        descriptor_manager: &mut DescriptorManager,
+    ) {
+        if let Some(rtv) = self.srgb_rtv {
+            descriptor_manager.free(rtv);
+        }
+        texture_manager.delete(descriptor_manager, self.color);
+        texture_manager.delete(descriptor_manager, self.depth);
+    }
+
+    fn transition(
+        &self,
+        barriers: &mut BarrierBatcher,
+        texture_manager: &TextureManager,
+        state_before: D3D12_RESOURCE_STATES,
+        state_after: D3D12_RESOURCE_STATES,
+    ) -> Result<()> {
+        let color = texture_manager.get_texture(&self.color)?;
+        barriers.transition(
+            &color.get_resource()?.device_resource,
+            state_before,
+            state_after,
+        );
+
+        Ok(())
+    }
+}
+
+pub fn create_depth_buffer(
+    device: &ID3D12Device4,
+    texture_manager: &mut TextureManager,
+    descriptor_manager: &mut DescriptorManager,
+    extent: (u32, u32),
+    depth_range: DepthRange,
+) -> Result<TextureHandle> {
+    let (width, height) = extent;
+
+    texture_manager.create_empty_texture(
+        device,
+        TextureInfo {
+            dimension: TextureDimension::Two(width as usize, height),
+            format: DEPTH_STENCIL_FORMAT,
+            is_depth_buffer: true,
+            ..Default::default()
+        },
+        Some(D3D12_CLEAR_VALUE {
+            Format: DEPTH_STENCIL_FORMAT,
+            Anonymous: D3D12_CLEAR_VALUE_0 {
+                DepthStencil: D3D12_DEPTH_STENCIL_VALUE {
+                    Depth: depth_range.far_depth(),
+                    Stencil: 0,
+                },
+            },
+        }),
+        D3D12_RESOURCE_STATE_DEPTH_WRITE,
+        descriptor_manager,
+        true,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adjacent_regions_share_edges() {
+        let left = ViewportRect {
+            width: 1.0 / 3.0,
+            ..ViewportRect::FULL
+        };
+        let right = ViewportRect {
+            x: 1.0 / 3.0,
+            width: 2.0 / 3.0,
+            ..ViewportRect::FULL
+        };
+
+        let left_rect = left.pixel_rect((1001, 500));
+        let right_rect = right.pixel_rect((1001, 500));
+        assert_eq!(left_rect.left, 0);
+        assert_eq!(left_rect.right, right_rect.left);
+        assert_eq!(right_rect.right, 1001);
+        assert_eq!(right_rect.bottom, 500);
+    }
+
+    #[test]
+    fn local_positions_round_trip() {
+        let region = ViewportRect {
+            x: 0.5,
+            y: 0.0,
+            width: 0.5,
+            height: 0.5,
+        };
+
+        assert!(region.contains(Vec2::new(0.75, 0.25)));
+        assert!(!region.contains(Vec2::new(0.25, 0.25)));
+        assert_eq!(region.to_local(Vec2::new(0.75, 0.25)), Vec2::new(0.5, 0.5));
+        assert_eq!(region.to_global(Vec2::new(0.5, 0.5)), Vec2::new(0.75, 0.25));
+        assert_eq!(region.aspect_ratio((1920, 1080)), 16.0 / 9.0);
+    }
+}
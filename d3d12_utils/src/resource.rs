@@ -91,13 +91,33 @@ impl Resource {
         initial_state: D3D12_RESOURCE_STATES,
         clear_value: Option<D3D12_CLEAR_VALUE>,
         mapped: bool,
+    ) -> Result<Self> {
+        Self::create_committed_with_flags(
+            device,
+            heap_properties,
+            D3D12_HEAP_FLAG_NONE,
+            desc,
+            initial_state,
+            clear_value,
+            mapped,
+        )
+    }
+
+    pub fn create_committed_with_flags(
+        device: &ID3D12Device4,
+        heap_properties: &D3D12_HEAP_PROPERTIES,
+        heap_flags: D3D12_HEAP_FLAGS,
+        desc: &D3D12_RESOURCE_DESC,
+        initial_state: D3D12_RESOURCE_STATES,
+        clear_value: Option<D3D12_CLEAR_VALUE>,
+        mapped: bool,
     ) -> Result<Self> {
         let mut resource: Option<ID3D12Resource> = None;
 
         unsafe {
             device.CreateCommittedResource(
                 heap_properties,
-                D3D12_HEAP_FLAG_NONE,
+                heap_flags,
                 desc,
                 initial_state,
                 if clear_value.is_none() {
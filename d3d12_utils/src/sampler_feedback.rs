@@ -0,0 +1,408 @@
+use std::ffi::c_void;
+
+use anyhow::{ensure, Result};
+use windows::{
+    core::Interface,
+    Win32::Graphics::{Direct3D12::*, Dxgi::Common::*},
+};
+
+use crate::{
+    align_data, BarrierBatcher, DescriptorHandle, DescriptorHeap, DescriptorManager,
+    DescriptorType, Resource,
+};
+
+/// Value in a decoded MinMip feedback map for regions that weren't sampled at all
+pub const FEEDBACK_NOT_SAMPLED: u8 = 0xFF;
+
+pub fn query_sampler_feedback_tier(device: &ID3D12Device4) -> Result<D3D12_SAMPLER_FEEDBACK_TIER> {
+    let mut options = D3D12_FEATURE_DATA_D3D12_OPTIONS7::default();
+    unsafe {
+        device.CheckFeatureSupport(
+            D3D12_FEATURE_D3D12_OPTIONS7,
+            std::ptr::addr_of_mut!(options) as *mut c_void,
+            std::mem::size_of_val(&options) as u32,
+        )?;
+    }
+
+    Ok(options.SamplerFeedbackTier)
+}
+
+/// A MinMip feedback map paired with a texture. Shaders write to it through `uav` with
+/// `WriteSamplerFeedback`, `resolve` decodes it to one mip per region for the CPU to read.
+#[derive(Debug)]
+pub struct SamplerFeedbackMap {
+    feedback: ID3D12Resource,
+    /// R8_UINT, one texel per region. Readback buffers can't be resolved into, so the feedback is
+    /// decoded here and copied on. Rests in RESOLVE_DEST.
+    decoded: Resource,
+    readback_buffer: Resource,
+    pub uav: DescriptorHandle,
+    // ClearUnorderedAccessViewUint needs the UAV in a CPU only heap as well
+    clear_heap: DescriptorHeap,
+    /// Number of mip regions in each direction
+    pub extent: (u32, u32),
+    readback_row_pitch: usize,
+}
+
+impl SamplerFeedbackMap {
+    pub fn new(
+        device: &ID3D12Device4,
+        descriptor_manager: &mut DescriptorManager,
+        paired_texture: &ID3D12Resource,
+        mip_region: u32,
+    ) -> Result<Self> {
+        let texture_desc = unsafe { paired_texture.GetDesc() };
+        ensure!(
+            texture_desc.Dimension == D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+            "Sampler feedback needs a 2D texture"
+        );
+        ensure!(
+            mip_region >= 4
+                && mip_region.is_power_of_two()
+                && mip_region as u64 <= texture_desc.Width / 2
+                && mip_region <= texture_desc.Height / 2,
+            "Invalid sampler feedback mip region {}",
+            mip_region
+        );
+
+        let device8: ID3D12Device8 = device.cast()?;
+
+        let mut feedback: Option<ID3D12Resource> = None;
+        unsafe {
+            device8.CreateCommittedResource2(
+                &D3D12_HEAP_PROPERTIES {
+                    Type: D3D12_HEAP_TYPE_DEFAULT,
+                    ..Default::default()
+                },
+                D3D12_HEAP_FLAG_NONE,
+                &D3D12_RESOURCE_DESC1 {
+                    Dimension: D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+                    Width: texture_desc.Width,
+                    Height: texture_desc.Height,
+                    DepthOrArraySize: 1,
+                    MipLevels: texture_desc.MipLevels,
+                    Format: DXGI_FORMAT_SAMPLER_FEEDBACK_MIN_MIP_OPAQUE,
+                    SampleDesc: DXGI_SAMPLE_DESC {
+                        Count: 1,
+                        Quality: 0,
+                    },
+                    Layout: D3D12_TEXTURE_LAYOUT_UNKNOWN,
+                    Flags: D3D12_RESOURCE_FLAG_ALLOW_UNORDERED_ACCESS,
+                    SamplerFeedbackMipRegion: D3D12_MIP_REGION {
+                        Width: mip_region,
+                        Height: mip_region,
+                        Depth: 1,
+                    },
+                    ..Default::default()
+                },
+                D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+                std::ptr::null(),
+                None::<&ID3D12ProtectedResourceSession>,
+                &mut feedback,
+            )?;
+        }
+        let feedback = feedback.unwrap();
+
+        let uav = descriptor_manager.allocate(DescriptorType::Resource)?;
+        let mut clear_heap = DescriptorHeap::cpu_resource_descriptor_heap(device, 1)?;
+        let (_, clear_handle) = clear_heap.allocate_handle()?;
+        unsafe {
+            device8.CreateSamplerFeedbackUnorderedAccessView(
+                paired_texture,
+                &feedback,
+                descriptor_manager.get_cpu_handle(&uav)?,
+            );
+            device8.CreateSamplerFeedbackUnorderedAccessView(
+                paired_texture,
+                &feedback,
+                clear_handle,
+            );
+        }
+
+        let extent = (
+            (texture_desc.Width as u32).div_ceil(mip_region),
+            texture_desc.Height.div_ceil(mip_region),
+        );
+        let readback_row_pitch = align_data(
+            extent.0 as usize,
+            D3D12_TEXTURE_DATA_PITCH_ALIGNMENT as usize,
+        );
+
+        let decoded = Resource::create_committed(
+            device,
+            &D3D12_HEAP_PROPERTIES {
+                Type: D3D12_HEAP_TYPE_DEFAULT,
+                ..Default::default()
+            },
+            &D3D12_RESOURCE_DESC {
+                Dimension: D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+                Width: extent.0 as u64,
+                Height: extent.1,
+                DepthOrArraySize: 1,
+                MipLevels: 1,
+                Format: DXGI_FORMAT_R8_UINT,
+                SampleDesc: DXGI_SAMPLE_DESC {
+                    Count: 1,
+                    Quality: 0,
+                },
+                Layout: D3D12_TEXTURE_LAYOUT_UNKNOWN,
+                ..Default::default()
+            },
+            D3D12_RESOURCE_STATE_RESOLVE_DEST,
+            None,
+            false,
+        )?;
+
+        let readback_buffer = Resource::create_committed(
+            device,
+            &D3D12_HEAP_PROPERTIES {
+                Type: D3D12_HEAP_TYPE_READBACK,
+                ..Default::default()
+            },
+            &D3D12_RESOURCE_DESC {
+                Dimension: D3D12_RESOURCE_DIMENSION_BUFFER,
+                Width: (readback_row_pitch * extent.1 as usize) as u64,
+                Height: 1,
+                DepthOrArraySize: 1,
+                MipLevels: 1,
+                SampleDesc: DXGI_SAMPLE_DESC {
+                    Count: 1,
+                    Quality: 0,
+                },
+                Layout: D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
+                ..Default::default()
+            },
+            D3D12_RESOURCE_STATE_COPY_DEST,
+            None,
+            true,
+        )?;
+
+        Ok(Self {
+            feedback,
+            decoded,
+            readback_buffer,
+            uav,
+            clear_heap,
+            extent,
+            readback_row_pitch,
+        })
+    }
+
+    /// Marks every region as not sampled. The resource descriptor heap has to be bound. Leaves a
+    /// UAV barrier in `barriers` so draws writing feedback wait for the clear.
+    pub fn clear(
+        &self,
+        command_list: &ID3D12GraphicsCommandList,
+        barriers: &mut BarrierBatcher,
+        descriptor_manager: &DescriptorManager,
+    ) -> Result<()> {
+        let values = [FEEDBACK_NOT_SAMPLED as u32; 4];
+        unsafe {
+            command_list.ClearUnorderedAccessViewUint(
+                descriptor_manager.get_gpu_handle(&self.uav)?,
+                self.clear_heap.get_cpu_handle(0)?,
+                &self.feedback,
+                values.as_ptr(),
+                &[],
+            );
+        }
+        barriers.uav(Some(&self.feedback));
+
+        Ok(())
+    }
+
+    /// Decodes the feedback, copies it into the readback buffer and clears it for the next round
+    pub fn resolve(
+        &self,
+        command_list: &ID3D12GraphicsCommandList,
+        barriers: &mut BarrierBatcher,
+        descriptor_manager: &DescriptorManager,
+    ) -> Result<()> {
+        let command_list1: ID3D12GraphicsCommandList1 = command_list.cast()?;
+
+        barriers.transition(
+            &self.feedback,
+            D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+            D3D12_RESOURCE_STATE_RESOLVE_SOURCE,
+        );
+        barriers.flush(command_list);
+        let decoded = &self.decoded.device_resource;
+        unsafe {
+            command_list1.ResolveSubresourceRegion(
+                decoded,
+                0,
+                0,
+                0,
+                &self.feedback,
+                0,
+                std::ptr::null(),
+                DXGI_FORMAT_R8_UINT,
+                D3D12_RESOLVE_MODE_DECODE_SAMPLER_FEEDBACK,
+            );
+        }
+        barriers.transition(
+            &self.feedback,
+            D3D12_RESOURCE_STATE_RESOLVE_SOURCE,
+            D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+        );
+        barriers.transition(
+            decoded,
+            D3D12_RESOURCE_STATE_RESOLVE_DEST,
+            D3D12_RESOURCE_STATE_COPY_SOURCE,
+        );
+        barriers.flush(command_list);
+
+        let from = D3D12_TEXTURE_COPY_LOCATION {
+            pResource: Some(decoded.clone()),
+            Type: D3D12_TEXTURE_COPY_TYPE_SUBRESOURCE_INDEX,
+            Anonymous: D3D12_TEXTURE_COPY_LOCATION_0 {
+                SubresourceIndex: 0,
+            },
+        };
+        let to = D3D12_TEXTURE_COPY_LOCATION {
+            pResource: Some(self.readback_buffer.device_resource.clone()),
+            Type: D3D12_TEXTURE_COPY_TYPE_PLACED_FOOTPRINT,
+            Anonymous: D3D12_TEXTURE_COPY_LOCATION_0 {
+                PlacedFootprint: D3D12_PLACED_SUBRESOURCE_FOOTPRINT {
+                    Offset: 0,
+                    Footprint: D3D12_SUBRESOURCE_FOOTPRINT {
+                        Format: DXGI_FORMAT_R8_UINT,
+                        Width: self.extent.0,
+                        Height: self.extent.1,
+                        Depth: 1,
+                        RowPitch: self.readback_row_pitch as u32,
+                    },
+                },
+            },
+        };
+        unsafe {
+            command_list.CopyTextureRegion(&to, 0, 0, 0, &from, std::ptr::null());
+        }
+        barriers.transition(
+            decoded,
+            D3D12_RESOURCE_STATE_COPY_SOURCE,
+            D3D12_RESOURCE_STATE_RESOLVE_DEST,
+        );
+
+        self.clear(command_list, barriers, descriptor_manager)
+    }
+
+    /// The finest mip sampled in each region, row by row. Only meaningful once the command list
+    /// with the last `resolve` has finished.
+    pub fn read(&self) -> Vec<u8> {
+        let (width, height) = (self.extent.0 as usize, self.extent.1 as usize);
+        let data = unsafe {
+            std::slice::from_raw_parts(
+                self.readback_buffer.mapped_data as *const u8,
+                self.readback_row_pitch * height,
+            )
+        };
+
+        data.chunks(self.readback_row_pitch)
+            .flat_map(|row| row[..width].iter().copied())
+            .collect()
+    }
+
+    pub fn delete(self, descriptor_manager: &mut DescriptorManager) {
+        descriptor_manager.free(self.uav);
+    }
+}
+
+/// The finest mip requested anywhere in a decoded feedback map
+pub fn finest_requested_mip(feedback: &[u8]) -> Option<u8> {
+    feedback
+        .iter()
+        .copied()
+        .filter(|mip| *mip != FEEDBACK_NOT_SAMPLED)
+        .min()
+}
+
+/// Decides which mips of a texture should stay resident from its sampler feedback.
+/// Finer mips are made resident straight away, coarser ones only after `eviction_delay` updates
+/// without being requested so textures don't flicker between mips.
+#[derive(Debug, Clone, Copy)]
+pub struct MipResidency {
+    pub finest_resident_mip: u8,
+    num_mips: u8,
+    eviction_delay: u32,
+    updates_since_requested: u32,
+}
+
+impl MipResidency {
+    /// Starts out with only the smallest mip resident
+    pub fn new(num_mips: u8, eviction_delay: u32) -> Self {
+        Self {
+            finest_resident_mip: num_mips.saturating_sub(1),
+            num_mips,
+            eviction_delay,
+            updates_since_requested: 0,
+        }
+    }
+
+    /// Returns the new finest resident mip when it changed
+    pub fn update(&mut self, requested_mip: Option<u8>) -> Option<u8> {
+        match requested_mip {
+            Some(mip) if mip < self.finest_resident_mip => {
+                self.finest_resident_mip = mip;
+                self.updates_since_requested = 0;
+                Some(mip)
+            }
+            Some(mip) if mip == self.finest_resident_mip => {
+                self.updates_since_requested = 0;
+                None
+            }
+            _ => {
+                self.updates_since_requested += 1;
+                if self.updates_since_requested >= self.eviction_delay
+                    && self.finest_resident_mip + 1 < self.num_mips
+                {
+                    self.finest_resident_mip += 1;
+                    self.updates_since_requested = 0;
+                    Some(self.finest_resident_mip)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finest_mip_ignores_unsampled_regions() {
+        assert_eq!(
+            Some(2),
+            finest_requested_mip(&[FEEDBACK_NOT_SAMPLED, 3, 2, FEEDBACK_NOT_SAMPLED])
+        );
+        assert_eq!(None, finest_requested_mip(&[FEEDBACK_NOT_SAMPLED; 4]));
+    }
+
+    #[test]
+    fn residency_streams_in_immediately() {
+        let mut residency = MipResidency::new(8, 4);
+        assert_eq!(7, residency.finest_resident_mip);
+
+        assert_eq!(Some(2), residency.update(Some(2)));
+        assert_eq!(None, residency.update(Some(2)));
+        assert_eq!(2, residency.finest_resident_mip);
+    }
+
+    #[test]
+    fn residency_evicts_after_delay() {
+        let mut residency = MipResidency::new(4, 2);
+        residency.update(Some(0));
+
+        assert_eq!(None, residency.update(Some(2)));
+        assert_eq!(Some(1), residency.update(None));
+        assert_eq!(None, residency.update(None));
+        assert_eq!(Some(2), residency.update(None));
+        assert_eq!(None, residency.update(None));
+        assert_eq!(Some(3), residency.update(None));
+        assert_eq!(None, residency.update(None));
+        assert_eq!(None, residency.update(None));
+        assert_eq!(3, residency.finest_resident_mip);
+    }
+}
@@ -0,0 +1,193 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{anyhow, bail, ensure, Result};
+use hassle_rs::{Dxc, DxcIncludeHandler, Dxil};
+
+pub const DXCOMPILER_LIBRARY: &str = "dxcompiler.dll";
+/// Signs what dxcompiler.dll compiles, drivers refuse unsigned DXIL
+pub const DXIL_LIBRARY: &str = "dxil.dll";
+
+// Set by `set_dxc_directory`, or the first directory `dxc_directory` found
+static DXC_DIRECTORY: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Makes shaders compile with the DXC in `directory` instead of searching for one. Fails when
+/// the libraries aren't there.
+pub fn set_dxc_directory(directory: impl Into<PathBuf>) -> Result<()> {
+    let directory = directory.into();
+    for library in [DXCOMPILER_LIBRARY, DXIL_LIBRARY] {
+        ensure!(
+            directory.join(library).is_file(),
+            "{} is not in {}",
+            library,
+            directory.display()
+        );
+    }
+
+    *DXC_DIRECTORY.lock().unwrap() = Some(directory);
+    Ok(())
+}
+
+/// The directory shaders are compiled with DXC from. Unless one was set with
+/// `set_dxc_directory`, the first of these with both libraries in it: the executable's
+/// directory, the current directory, the directories on PATH and the newest Windows SDK.
+pub fn dxc_directory() -> Result<PathBuf> {
+    let mut directory = DXC_DIRECTORY.lock().unwrap();
+    if let Some(directory) = directory.as_ref() {
+        return Ok(directory.clone());
+    }
+
+    let candidates = dxc_candidate_directories();
+    let found = candidates
+        .iter()
+        .find(|candidate| {
+            [DXCOMPILER_LIBRARY, DXIL_LIBRARY]
+                .iter()
+                .all(|library| candidate.join(library).is_file())
+        })
+        .ok_or_else(|| {
+            anyhow!(
+                "Couldn't find {} and {} to compile shaders with, looked in:\n{}\n\
+                Install the Windows SDK, copy both next to the executable or call \
+                set_dxc_directory",
+                DXCOMPILER_LIBRARY,
+                DXIL_LIBRARY,
+                candidates
+                    .iter()
+                    .map(|candidate| format!("    {}", candidate.display()))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            )
+        })?;
+
+    *directory = Some(found.clone());
+    Ok(found.clone())
+}
+
+fn dxc_candidate_directories() -> Vec<PathBuf> {
+    let mut candidates = Vec::new();
+
+    if let Some(directory) = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+    {
+        candidates.push(directory);
+    }
+    if let Ok(directory) = std::env::current_dir() {
+        candidates.push(directory);
+    }
+    if let Some(path) = std::env::var_os("PATH") {
+        candidates.extend(std::env::split_paths(&path));
+    }
+    if let Some(program_files) = std::env::var_os("ProgramFiles(x86)") {
+        let sdk_bin = Path::new(&program_files).join("Windows Kits/10/bin");
+        let versions = std::fs::read_dir(&sdk_bin)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        if let Some(version) = newest_sdk_version(&versions) {
+            candidates.push(sdk_bin.join(version).join("x64"));
+        }
+    }
+
+    candidates
+}
+
+/// The highest of the SDK's version directories, e.g. "10.0.22621.0". Other directories are
+/// skipped.
+fn newest_sdk_version(names: &[String]) -> Option<&str> {
+    names
+        .iter()
+        .filter_map(|name| {
+            let parts = name
+                .split('.')
+                .map(|part| part.parse::<u32>().ok())
+                .collect::<Option<Vec<_>>>()?;
+            (parts.len() == 4).then_some((parts, name.as_str()))
+        })
+        .max_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(_, name)| name)
+}
+
+// Includes are read relative to the current directory, like the paths shaders are loaded from
+struct IncludeHandler;
+
+impl DxcIncludeHandler for IncludeHandler {
+    fn load_source(&mut self, filename: String) -> Option<String> {
+        std::fs::read_to_string(filename).ok()
+    }
+}
+
+/// Compiles `source` to DXIL with the DXC from `dxc_directory` and validates it
+pub fn compile_dxil(
+    name: &str,
+    source: &str,
+    entry_point: &str,
+    shader_model: &str,
+    args: &[&str],
+) -> Result<Vec<u8>> {
+    let directory = dxc_directory()?;
+    let dxc = Dxc::new(Some(directory.clone()))?;
+    let compiler = dxc.create_compiler()?;
+    let library = dxc.create_library()?;
+
+    let blob = library.create_blob_with_encoding_from_str(source)?;
+    let byte_code = match compiler.compile(
+        &blob,
+        name,
+        entry_point,
+        shader_model,
+        args,
+        Some(&mut IncludeHandler),
+        &[],
+    ) {
+        Ok(result) => result.get_result()?.to_vec(),
+        Err((result, _)) => {
+            let errors = result.get_error_buffer()?;
+            bail!(
+                "Failed to compile {} of {}:\n{}",
+                entry_point,
+                name,
+                library.get_blob_as_string(&errors.into())?
+            );
+        }
+    };
+
+    let validator = Dxil::new(Some(directory))?.create_validator()?;
+    let blob = library.create_blob_with_encoding(&byte_code)?;
+    if let Err((result, _)) = validator.validate(blob.into()) {
+        let errors = result.get_error_buffer()?;
+        bail!(
+            "{} of {} is invalid:\n{}",
+            entry_point,
+            name,
+            library.get_blob_as_string(&errors.into())?
+        );
+    }
+
+    Ok(byte_code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_the_newest_sdk() {
+        let names = [
+            "10.0.19041.0",
+            "10.0.22621.0",
+            "10.0.9999.0",
+            "arm64",
+            "x64",
+        ]
+        .map(String::from);
+        assert_eq!(newest_sdk_version(&names), Some("10.0.22621.0"));
+        assert_eq!(newest_sdk_version(&names[3..]), None);
+    }
+}
@@ -0,0 +1,135 @@
+use std::{ffi::c_void, fmt, sync::Mutex};
+
+use anyhow::{bail, ensure, Context, Result};
+use windows::Win32::Graphics::Direct3D12::*;
+
+/// A shader model, ordered so that newer ones compare greater
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ShaderModel {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl ShaderModel {
+    pub const SM_6_0: Self = Self::new(6, 0);
+    /// Mesh and amplification shaders
+    pub const SM_6_5: Self = Self::new(6, 5);
+    /// `ResourceDescriptorHeap` and `SamplerDescriptorHeap`, which bindless shaders index
+    pub const SM_6_6: Self = Self::new(6, 6);
+    /// The newest model shaders are compiled for, the one they are written against
+    pub const HIGHEST: Self = Self::SM_6_6;
+
+    pub const fn new(major: u32, minor: u32) -> Self {
+        Self { major, minor }
+    }
+
+    /// The newest model the device runs, at most `HIGHEST`
+    pub fn query(device: &ID3D12Device4) -> Result<Self> {
+        // Runtimes that don't know the model asked about fail, so ask about older ones until
+        // one is answered
+        for highest in (D3D_SHADER_MODEL_6_0.0..=D3D_SHADER_MODEL_6_7.0).rev() {
+            let mut shader_model = D3D12_FEATURE_DATA_SHADER_MODEL {
+                HighestShaderModel: D3D_SHADER_MODEL(highest),
+            };
+            let queried = unsafe {
+                device.CheckFeatureSupport(
+                    D3D12_FEATURE_SHADER_MODEL,
+                    std::ptr::addr_of_mut!(shader_model) as *mut c_void,
+                    std::mem::size_of_val(&shader_model) as u32,
+                )
+            };
+
+            if queried.is_ok() {
+                let model = shader_model.HighestShaderModel.0 as u32;
+                return Ok(Self::new(model >> 4, model & 0xf).min(Self::HIGHEST));
+            }
+        }
+
+        bail!("The device doesn't run shader model 6.0")
+    }
+
+    /// Splits a compile target like "ps_6_6" into its stage and model
+    pub fn parse_target(target: &str) -> Result<(&str, Self)> {
+        let parse = || {
+            let mut parts = target.split('_');
+            let stage = parts.next()?;
+            let major = parts.next()?.parse().ok()?;
+            let minor = parts.next()?.parse().ok()?;
+            parts
+                .next()
+                .is_none()
+                .then_some((stage, Self::new(major, minor)))
+        };
+
+        parse().with_context(|| format!("{} is not a shader target like ps_6_6", target))
+    }
+
+    /// The compile target for `stage`, e.g. "ps" or "cs"
+    pub fn target(&self, stage: &str) -> String {
+        format!("{}_{}_{}", stage, self.major, self.minor)
+    }
+
+    pub fn supports_mesh_shaders(&self) -> bool {
+        *self >= Self::SM_6_5
+    }
+
+    pub fn supports_dynamic_resources(&self) -> bool {
+        *self >= Self::SM_6_6
+    }
+}
+
+impl fmt::Display for ShaderModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+static SELECTED_SHADER_MODEL: Mutex<ShaderModel> = Mutex::new(ShaderModel::HIGHEST);
+
+/// Makes shaders compile for `model`, usually what `ShaderModel::query` returned for the device
+/// they run on. `HIGHEST` until this is called.
+pub fn set_shader_model(model: ShaderModel) {
+    *SELECTED_SHADER_MODEL.lock().unwrap() = model.min(ShaderModel::HIGHEST);
+}
+
+pub fn shader_model() -> ShaderModel {
+    *SELECTED_SHADER_MODEL.lock().unwrap()
+}
+
+/// The target to compile for when a shader needs at least the model in `target`: the selected
+/// model, which is at least as new. Fails early rather than when the pipeline is created.
+pub fn select_target(target: &str) -> Result<String> {
+    let (stage, required) = ShaderModel::parse_target(target)?;
+    let selected = shader_model();
+    ensure!(
+        selected >= required,
+        "{} needs shader model {}, the device runs {}",
+        target,
+        required,
+        selected
+    );
+
+    Ok(selected.target(stage))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn targets_round_trip() {
+        assert_eq!(
+            ShaderModel::parse_target("ms_6_5").unwrap(),
+            ("ms", ShaderModel::SM_6_5)
+        );
+        assert_eq!(ShaderModel::SM_6_6.target("cs"), "cs_6_6");
+        assert!(ShaderModel::parse_target("ps_6").is_err());
+        assert!(ShaderModel::parse_target("ps_6_6_1").is_err());
+        assert!(ShaderModel::parse_target("ps_six_six").is_err());
+
+        assert!(ShaderModel::new(6, 10) > ShaderModel::SM_6_6);
+        assert!(!ShaderModel::new(6, 4).supports_mesh_shaders());
+        assert!(ShaderModel::SM_6_5.supports_mesh_shaders());
+        assert!(!ShaderModel::SM_6_5.supports_dynamic_resources());
+    }
+}
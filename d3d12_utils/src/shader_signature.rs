@@ -0,0 +1,328 @@
+use anyhow::{bail, ensure, Context, Result};
+use windows::Win32::Graphics::{Direct3D12::D3D12_INPUT_ELEMENT_DESC, Dxgi::Common::*};
+
+use crate::CompiledShader;
+
+/// Scalar type of a shader input, sizes are not told apart
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComponentType {
+    Unknown,
+    UInt,
+    SInt,
+    Float,
+}
+
+/// One input of a shader, as listed in the input signature of its container
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureElement {
+    pub semantic_name: String,
+    pub semantic_index: u32,
+    /// D3D_NAME of a system value such as SV_VertexID, 0 for semantics fed by the input layout
+    pub system_value: u32,
+    pub component_type: ComponentType,
+    pub register: u32,
+    /// Components that are declared, x in the lowest bit
+    pub mask: u8,
+}
+
+// Part holding the input signature of a DXIL container, and of an older DXBC one
+const DXIL_INPUT_SIGNATURE: &[u8; 4] = b"ISG1";
+const DXBC_INPUT_SIGNATURE: &[u8; 4] = b"ISGN";
+// Fourcc, hash, version, size and part count come before the part offsets
+const CONTAINER_HEADER_SIZE: usize = 32;
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    let bytes = data
+        .get(offset..offset + 4)
+        .context("Shader container is truncated")?;
+
+    Ok(u32::from_le_bytes(bytes.try_into()?))
+}
+
+fn read_string(data: &[u8], offset: usize) -> Result<String> {
+    let bytes = data
+        .get(offset..)
+        .context("Shader container is truncated")?;
+    let length = bytes
+        .iter()
+        .position(|&byte| byte == 0)
+        .context("Unterminated string in shader container")?;
+
+    Ok(String::from_utf8(bytes[..length].to_vec())?)
+}
+
+/// Reads the vertex inputs from compiled DXIL or DXBC byte code. The offsets in the signature are
+/// relative to the start of its part.
+pub fn input_signature(byte_code: &[u8]) -> Result<Vec<SignatureElement>> {
+    ensure!(
+        byte_code.starts_with(b"DXBC"),
+        "Not a shader container, it does not start with DXBC"
+    );
+
+    let part_count = read_u32(byte_code, CONTAINER_HEADER_SIZE - 4)? as usize;
+    for part in 0..part_count {
+        let part_offset = read_u32(byte_code, CONTAINER_HEADER_SIZE + part * 4)? as usize;
+        let fourcc = byte_code
+            .get(part_offset..part_offset + 4)
+            .context("Shader container is truncated")?;
+        let element_size = match fourcc {
+            _ if fourcc == DXIL_INPUT_SIGNATURE => 32,
+            _ if fourcc == DXBC_INPUT_SIGNATURE => 24,
+            _ => continue,
+        };
+
+        let part_size = read_u32(byte_code, part_offset + 4)? as usize;
+        let data = byte_code
+            .get(part_offset + 8..part_offset + 8 + part_size)
+            .context("Shader container is truncated")?;
+
+        // ISG1 elements start with a stream index and end with a minimum precision
+        let skip = if element_size == 32 { 4 } else { 0 };
+        let element_count = read_u32(data, 0)? as usize;
+        let elements_offset = read_u32(data, 4)? as usize;
+
+        return (0..element_count)
+            .map(|element| {
+                let offset = elements_offset + element * element_size + skip;
+                let mask = *data
+                    .get(offset + 20)
+                    .context("Shader container is truncated")?;
+
+                Ok(SignatureElement {
+                    semantic_name: read_string(data, read_u32(data, offset)? as usize)?,
+                    semantic_index: read_u32(data, offset + 4)?,
+                    system_value: read_u32(data, offset + 8)?,
+                    component_type: match read_u32(data, offset + 12)? {
+                        1 | 4 | 7 => ComponentType::UInt,
+                        2 | 5 | 8 => ComponentType::SInt,
+                        3 | 6 | 9 => ComponentType::Float,
+                        _ => ComponentType::Unknown,
+                    },
+                    register: read_u32(data, offset + 16)?,
+                    mask,
+                })
+            })
+            .collect();
+    }
+
+    bail!("Shader container has no input signature")
+}
+
+/// What a shader reads from a vertex in the given format, unknown for formats that are not
+/// expected in vertex buffers
+pub fn format_component_type(format: DXGI_FORMAT) -> ComponentType {
+    match format {
+        DXGI_FORMAT_R32G32B32A32_FLOAT
+        | DXGI_FORMAT_R32G32B32_FLOAT
+        | DXGI_FORMAT_R32G32_FLOAT
+        | DXGI_FORMAT_R32_FLOAT
+        | DXGI_FORMAT_R16G16B16A16_FLOAT
+        | DXGI_FORMAT_R16G16_FLOAT
+        | DXGI_FORMAT_R16_FLOAT
+        | DXGI_FORMAT_R16G16B16A16_UNORM
+        | DXGI_FORMAT_R16G16B16A16_SNORM
+        | DXGI_FORMAT_R16G16_UNORM
+        | DXGI_FORMAT_R16G16_SNORM
+        | DXGI_FORMAT_R10G10B10A2_UNORM
+        | DXGI_FORMAT_R8G8B8A8_UNORM
+        | DXGI_FORMAT_R8G8B8A8_SNORM
+        | DXGI_FORMAT_B8G8R8A8_UNORM
+        | DXGI_FORMAT_R8G8_UNORM
+        | DXGI_FORMAT_R8G8_SNORM => ComponentType::Float,
+        DXGI_FORMAT_R32G32B32A32_UINT
+        | DXGI_FORMAT_R32G32B32_UINT
+        | DXGI_FORMAT_R32G32_UINT
+        | DXGI_FORMAT_R32_UINT
+        | DXGI_FORMAT_R16G16B16A16_UINT
+        | DXGI_FORMAT_R16G16_UINT
+        | DXGI_FORMAT_R16_UINT
+        | DXGI_FORMAT_R8G8B8A8_UINT
+        | DXGI_FORMAT_R8G8_UINT => ComponentType::UInt,
+        DXGI_FORMAT_R32G32B32A32_SINT
+        | DXGI_FORMAT_R32G32B32_SINT
+        | DXGI_FORMAT_R32G32_SINT
+        | DXGI_FORMAT_R32_SINT
+        | DXGI_FORMAT_R16G16B16A16_SINT
+        | DXGI_FORMAT_R16G16_SINT
+        | DXGI_FORMAT_R16_SINT
+        | DXGI_FORMAT_R8G8B8A8_SINT
+        | DXGI_FORMAT_R8G8_SINT => ComponentType::SInt,
+        _ => ComponentType::Unknown,
+    }
+}
+
+/// Every way `layout`, given as (semantic name, index, format), fails to feed `signature`.
+/// Elements the shader does not read are fine, as are formats with fewer components than the
+/// shader declares.
+pub fn input_layout_mismatches(
+    signature: &[SignatureElement],
+    layout: &[(String, u32, DXGI_FORMAT)],
+) -> Vec<String> {
+    signature
+        .iter()
+        .filter(|input| input.system_value == 0)
+        .filter_map(|input| {
+            let semantic = format!("{}{}", input.semantic_name, input.semantic_index);
+            let Some((_, _, format)) = layout.iter().find(|(name, index, _)| {
+                name.eq_ignore_ascii_case(&input.semantic_name) && *index == input.semantic_index
+            }) else {
+                return Some(format!(
+                    "{} is read by the shader but missing from the layout",
+                    semantic
+                ));
+            };
+
+            let format_type = format_component_type(*format);
+            (format_type != ComponentType::Unknown
+                && input.component_type != ComponentType::Unknown
+                && format_type != input.component_type)
+                .then(|| {
+                    format!(
+                        "{} is {:?} in the shader but {:?} ({:?}) in the layout",
+                        semantic, input.component_type, format_type, format
+                    )
+                })
+        })
+        .collect()
+}
+
+/// Checks the input layout of a pipeline against the vertex shader, with a readable error rather
+/// than the E_INVALIDARG creating the pipeline would fail with
+pub fn validate_input_layout(
+    vertex_shader: &CompiledShader,
+    input_element_descs: &[D3D12_INPUT_ELEMENT_DESC],
+) -> Result<()> {
+    let signature = input_signature(&vertex_shader.byte_code)
+        .with_context(|| format!("Failed to reflect {}", vertex_shader.name))?;
+    let layout = input_element_descs
+        .iter()
+        .map(|desc| {
+            // The names are null terminated strings the caller keeps alive for the pipeline
+            let name = unsafe { desc.SemanticName.as_bytes() };
+            Ok((
+                String::from_utf8(name.to_vec())?,
+                desc.SemanticIndex,
+                desc.Format,
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mismatches = input_layout_mismatches(&signature, &layout);
+    ensure!(
+        mismatches.is_empty(),
+        "Input layout does not match the vertex shader in {}:\n  {}",
+        vertex_shader.name,
+        mismatches.join("\n  ")
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // (name, index, system value, component type, register, mask)
+    type Element = (&'static str, u32, u32, u32, u32, u8);
+
+    // A DXIL container with a made up part before the input signature
+    fn container(elements: &[Element]) -> Vec<u8> {
+        let mut signature = vec![];
+        let strings_offset = 8 + elements.len() * 32;
+        let mut strings = vec![];
+        signature.extend((elements.len() as u32).to_le_bytes());
+        signature.extend(8u32.to_le_bytes());
+        for (name, index, system_value, component_type, register, mask) in elements {
+            signature.extend(0u32.to_le_bytes());
+            signature.extend(((strings_offset + strings.len()) as u32).to_le_bytes());
+            for value in [*index, *system_value, *component_type, *register] {
+                signature.extend(value.to_le_bytes());
+            }
+            signature.extend([*mask, 0, 0, 0]);
+            signature.extend(0u32.to_le_bytes());
+            strings.extend(name.bytes());
+            strings.push(0);
+        }
+        signature.extend(strings);
+
+        let other_part = b"STAT\x04\x00\x00\x00abcd";
+        let mut byte_code = b"DXBC".to_vec();
+        byte_code.extend([0; 16]);
+        byte_code.extend(1u32.to_le_bytes());
+        byte_code.extend(0u32.to_le_bytes());
+        byte_code.extend(2u32.to_le_bytes());
+        byte_code.extend((CONTAINER_HEADER_SIZE as u32 + 8).to_le_bytes());
+        byte_code
+            .extend((CONTAINER_HEADER_SIZE as u32 + 8 + other_part.len() as u32).to_le_bytes());
+        byte_code.extend(other_part);
+        byte_code.extend(DXIL_INPUT_SIGNATURE);
+        byte_code.extend((signature.len() as u32).to_le_bytes());
+        byte_code.extend(signature);
+
+        byte_code
+    }
+
+    const VERTEX_INPUTS: [Element; 4] = [
+        ("POSITION", 0, 0, 3, 0, 0b111),
+        ("NORMAL", 0, 0, 3, 1, 0b111),
+        ("TEXCOORD", 0, 0, 3, 2, 0b11),
+        ("SV_InstanceID", 0, 8, 1, 3, 0b1),
+    ];
+
+    fn layout(elements: &[(&str, u32, DXGI_FORMAT)]) -> Vec<(String, u32, DXGI_FORMAT)> {
+        elements
+            .iter()
+            .map(|(name, index, format)| (name.to_string(), *index, *format))
+            .collect()
+    }
+
+    #[test]
+    fn reads_input_signature() {
+        let signature = input_signature(&container(&VERTEX_INPUTS)).unwrap();
+
+        assert_eq!(signature.len(), 4);
+        assert_eq!(
+            signature[2],
+            SignatureElement {
+                semantic_name: "TEXCOORD".to_string(),
+                semantic_index: 0,
+                system_value: 0,
+                component_type: ComponentType::Float,
+                register: 2,
+                mask: 0b11,
+            }
+        );
+        assert_eq!(signature[3].component_type, ComponentType::UInt);
+
+        assert!(input_signature(b"not a shader").is_err());
+        assert!(input_signature(&container(&VERTEX_INPUTS)[..60]).is_err());
+    }
+
+    #[test]
+    fn matching_layout_passes() {
+        let signature = input_signature(&container(&VERTEX_INPUTS)).unwrap();
+        let layout = layout(&[
+            ("POSITION", 0, DXGI_FORMAT_R32G32B32_FLOAT),
+            ("normal", 0, DXGI_FORMAT_R16G16B16A16_SNORM),
+            ("TEXCOORD", 0, DXGI_FORMAT_R32G32_FLOAT),
+            ("COLOR", 0, DXGI_FORMAT_R8G8B8A8_UNORM),
+        ]);
+
+        assert!(input_layout_mismatches(&signature, &layout).is_empty());
+    }
+
+    #[test]
+    fn lists_every_mismatch() {
+        let signature = input_signature(&container(&VERTEX_INPUTS)).unwrap();
+        let layout = layout(&[
+            ("POSITION", 0, DXGI_FORMAT_R32G32B32_UINT),
+            ("TEXCOORD", 1, DXGI_FORMAT_R32G32_FLOAT),
+        ]);
+
+        let mismatches = input_layout_mismatches(&signature, &layout);
+        assert_eq!(mismatches.len(), 3, "{:?}", mismatches);
+        assert!(mismatches[0].starts_with("POSITION0 is Float in the shader but UInt"));
+        assert!(mismatches[1].starts_with("NORMAL0 is read by the shader"));
+        assert!(mismatches[2].starts_with("TEXCOORD0 is read by the shader"));
+    }
+}
@@ -0,0 +1,108 @@
+use anyhow::{Context, Result};
+use windows::{
+    core::{HSTRING, PCWSTR},
+    Win32::{
+        Foundation::{CloseHandle, HANDLE},
+        Graphics::Direct3D12::*,
+        System::SystemServices::GENERIC_ALL,
+    },
+};
+
+use crate::{
+    DescriptorManager, ExternalTexture, Resource, Texture, TextureHandle, TextureInfo,
+    TextureManager,
+};
+
+/// An NT handle to a shared D3D12 object, closed on drop.
+/// Other processes or APIs (Vulkan external memory, Media Foundation, ...) open it to access the
+/// object.
+#[derive(Debug)]
+pub struct SharedHandle(pub HANDLE);
+
+impl Drop for SharedHandle {
+    fn drop(&mut self) {
+        unsafe {
+            CloseHandle(self.0);
+        }
+    }
+}
+
+/// `object` has to be created shared, e.g. with `create_shared_texture` or `create_shared_fence`.
+/// With a `name` the handle can also be opened by name from other processes.
+pub fn export_shared_handle(
+    device: &ID3D12Device4,
+    object: &ID3D12DeviceChild,
+    name: Option<&str>,
+) -> Result<SharedHandle> {
+    let name = name.map(HSTRING::from);
+    let handle = unsafe {
+        device.CreateSharedHandle(
+            object,
+            std::ptr::null(),
+            GENERIC_ALL,
+            name.as_ref()
+                .map_or(PCWSTR::null(), |name| PCWSTR(name.as_ptr())),
+        )
+    }?;
+
+    Ok(SharedHandle(handle))
+}
+
+/// Creates a committed texture in a shared heap, its resource can be passed to `export_shared_handle`
+pub fn create_shared_texture(
+    device: &ID3D12Device4,
+    texture_manager: &mut TextureManager,
+    descriptor_manager: &mut DescriptorManager,
+    texture_info: TextureInfo,
+    initial_state: D3D12_RESOURCE_STATES,
+) -> Result<TextureHandle> {
+    let resource = Resource::create_committed_with_flags(
+        device,
+        &D3D12_HEAP_PROPERTIES {
+            Type: D3D12_HEAP_TYPE_DEFAULT,
+            ..Default::default()
+        },
+        D3D12_HEAP_FLAG_SHARED,
+        &texture_info.resource_desc(),
+        initial_state,
+        None,
+        false,
+    )?;
+
+    texture_manager.add_texture(
+        device,
+        descriptor_manager,
+        Texture {
+            info: texture_info,
+            resource: Some(resource),
+            external_state: None,
+        },
+    )
+}
+
+pub fn create_shared_fence(device: &ID3D12Device4, initial_value: u64) -> Result<ID3D12Fence> {
+    Ok(unsafe { device.CreateFence(initial_value, D3D12_FENCE_FLAG_SHARED) }?)
+}
+
+/// Opens a texture exported by another device or process, to be added with
+/// `TextureManager::add_external_texture`
+pub fn open_shared_texture(
+    device: &ID3D12Device4,
+    handle: HANDLE,
+    state: D3D12_RESOURCE_STATES,
+) -> Result<ExternalTexture> {
+    let mut resource: Option<ID3D12Resource> = None;
+    unsafe { device.OpenSharedHandle(handle, &mut resource) }?;
+
+    Ok(ExternalTexture {
+        resource: resource.context("Shared handle is not a resource")?,
+        state,
+    })
+}
+
+pub fn open_shared_fence(device: &ID3D12Device4, handle: HANDLE) -> Result<ID3D12Fence> {
+    let mut fence: Option<ID3D12Fence> = None;
+    unsafe { device.OpenSharedHandle(handle, &mut fence) }?;
+
+    fence.context("Shared handle is not a fence")
+}
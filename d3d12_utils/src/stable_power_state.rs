@@ -0,0 +1,45 @@
+use anyhow::Result;
+use windows::{
+    core::{HSTRING, PCWSTR},
+    Win32::{
+        Foundation::ERROR_SUCCESS,
+        Graphics::Direct3D12::ID3D12Device4,
+        System::Registry::{RegGetValueW, HKEY_LOCAL_MACHINE, RRF_RT_REG_DWORD},
+    },
+};
+
+/// Whether Windows is in developer mode, which `SetStablePowerState` needs. Outside of it the
+/// call removes the device.
+pub fn developer_mode_enabled() -> bool {
+    let key = HSTRING::from(r"SOFTWARE\Microsoft\Windows\CurrentVersion\AppModelUnlock");
+    let value = HSTRING::from("AllowDevelopmentWithoutDevLicense");
+
+    let mut enabled = 0u32;
+    let mut size = std::mem::size_of_val(&enabled) as u32;
+    let status = unsafe {
+        RegGetValueW(
+            HKEY_LOCAL_MACHINE,
+            PCWSTR::from(&key),
+            PCWSTR::from(&value),
+            RRF_RT_REG_DWORD,
+            std::ptr::null_mut(),
+            std::ptr::addr_of_mut!(enabled) as _,
+            &mut size,
+        )
+    };
+
+    status == ERROR_SUCCESS && enabled != 0
+}
+
+/// Locks the GPU clocks to their base frequency for as long as the device lives, so timings stay
+/// comparable between runs at the cost of speed. Returns whether they were, which they can only be
+/// in developer mode.
+pub fn enable_stable_power_state(device: &ID3D12Device4) -> Result<bool> {
+    if !developer_mode_enabled() {
+        return Ok(false);
+    }
+
+    unsafe { device.SetStablePowerState(true) }?;
+
+    Ok(true)
+}
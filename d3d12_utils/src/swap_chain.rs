@@ -0,0 +1,160 @@
+use anyhow::Result;
+use windows::{
+    core::{Interface, PCWSTR},
+    Win32::{
+        Foundation::{HWND, RECT},
+        Graphics::{
+            Direct3D12::*,
+            Dxgi::{Common::*, *},
+        },
+    },
+};
+
+use crate::{CommandQueue, HdrMetadata};
+
+/// Flags every swap chain is created and resized with. Every swap chain has a frame latency
+/// waitable object, see `FrameLatencyWaiter`.
+pub const SWAP_CHAIN_FLAGS: DXGI_SWAP_CHAIN_FLAG = DXGI_SWAP_CHAIN_FLAG(
+    DXGI_SWAP_CHAIN_FLAG_ALLOW_MODE_SWITCH.0 | DXGI_SWAP_CHAIN_FLAG_FRAME_LATENCY_WAITABLE_OBJECT.0,
+);
+
+/// With `unordered_access` the back buffers get UAVs, so compute shaders can write the final
+/// image. `format` can't be an sRGB one then. With `non_prerotated` DXGI leaves turning the image
+/// for a rotated display to the app in fullscreen, and back buffers have to be sized and drawn in
+/// the display's native orientation, see `DisplayRotation`.
+#[allow(clippy::too_many_arguments)]
+pub fn create_swapchain(
+    hwnd: HWND,
+    dxgi_factory: &IDXGIFactory5,
+    graphics_queue: &CommandQueue,
+    buffer_count: u32,
+    format: DXGI_FORMAT,
+    extent: (u32, u32),
+    allow_tearing: bool,
+    unordered_access: bool,
+    non_prerotated: bool,
+) -> Result<IDXGISwapChain3> {
+    let (width, height) = extent;
+    let mut flags = SWAP_CHAIN_FLAGS;
+    if allow_tearing {
+        flags.0 |= DXGI_SWAP_CHAIN_FLAG_ALLOW_TEARING.0;
+    }
+    if non_prerotated {
+        flags.0 |= DXGI_SWAP_CHAIN_FLAG_NONPREROTATED.0;
+    }
+    let mut usage = DXGI_USAGE_RENDER_TARGET_OUTPUT;
+    if unordered_access {
+        usage |= DXGI_USAGE_UNORDERED_ACCESS;
+    }
+
+    let swap_chain_desc = DXGI_SWAP_CHAIN_DESC1 {
+        BufferCount: buffer_count,
+        Width: width,
+        Height: height,
+        Format: format,
+        BufferUsage: usage,
+        SwapEffect: DXGI_SWAP_EFFECT_FLIP_DISCARD,
+        SampleDesc: DXGI_SAMPLE_DESC {
+            Count: 1,
+            ..Default::default()
+        },
+        Flags: flags.0 as u32,
+        ..Default::default()
+    };
+
+    let swap_chain: IDXGISwapChain3 = unsafe {
+        dxgi_factory.CreateSwapChainForHwnd(
+            &graphics_queue.queue,
+            hwnd,
+            &swap_chain_desc,
+            std::ptr::null_mut(),
+            None,
+        )?
+    }
+    .cast()?;
+
+    Ok(swap_chain)
+}
+
+/// Sends HDR10 metadata with the presented frames, or stops sending any with None. Swap chains
+/// start without any, so it has to be set again on every new one. Only meaningful once the swap
+/// chain's colour space is `DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020`.
+pub fn set_hdr_metadata(
+    swap_chain: &IDXGISwapChain3,
+    metadata: Option<&HdrMetadata>,
+) -> Result<()> {
+    let swap_chain: IDXGISwapChain4 = swap_chain.cast()?;
+    unsafe {
+        match metadata {
+            Some(metadata) => {
+                let metadata = metadata.to_dxgi();
+                swap_chain.SetHDRMetaData(
+                    DXGI_HDR_METADATA_TYPE_HDR10,
+                    std::slice::from_raw_parts(
+                        std::ptr::addr_of!(metadata) as *const u8,
+                        std::mem::size_of_val(&metadata),
+                    ),
+                )?
+            }
+            None => swap_chain.SetHDRMetaData(DXGI_HDR_METADATA_TYPE_NONE, &[])?,
+        }
+    }
+
+    Ok(())
+}
+
+pub fn get_swapchain_render_targets<const N: usize>(
+    device: &ID3D12Device4,
+    rtv_handles: &[D3D12_CPU_DESCRIPTOR_HANDLE; N],
+    swap_chain: &IDXGISwapChain3,
+) -> Result<Vec<ID3D12Resource>> {
+    Ok((0..N)
+        .filter_map(|i: usize| {
+            let render_target: ID3D12Resource = unsafe { swap_chain.GetBuffer(i as u32) }.ok()?;
+            unsafe {
+                render_target
+                    .SetName(PCWSTR::from(&format!("Backbuffer {}", i).into()))
+                    .ok()?;
+            }
+            unsafe {
+                device.CreateRenderTargetView(&render_target, std::ptr::null(), rtv_handles[i]);
+            }
+
+            Some(render_target)
+        })
+        .collect())
+}
+
+pub fn resize_swapchain<const N: usize>(
+    device: &ID3D12Device4,
+    swap_chain: &IDXGISwapChain3,
+    extent: (u32, u32),
+    rtv_handles: &[D3D12_CPU_DESCRIPTOR_HANDLE; N],
+) -> Result<(Vec<ID3D12Resource>, D3D12_VIEWPORT, RECT)> {
+    let (width, height) = extent;
+    unsafe {
+        // Resizing has to keep the flags the swap chain was created with
+        let flags = swap_chain.GetDesc1()?.Flags;
+        swap_chain.ResizeBuffers(N as u32, width, height, DXGI_FORMAT_UNKNOWN, flags)?;
+    }
+
+    let render_targets = get_swapchain_render_targets(device, rtv_handles, swap_chain)?;
+
+    let viewport = D3D12_VIEWPORT {
+        TopLeftX: 0.0,
+        TopLeftY: 0.0,
+        Width: width as f32,
+        Height: height as f32,
+        MinDepth: D3D12_MIN_DEPTH,
+        MaxDepth: D3D12_MAX_DEPTH,
+    };
+
+    let scissor_rect = RECT {
+        left: 0,
+        top: 0,
+        right: width as i32,
+        bottom: height as i32,
+    };
+
+    Ok((render_targets, viewport, scissor_rect))
+}
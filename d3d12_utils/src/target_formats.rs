@@ -0,0 +1,141 @@
+use anyhow::{ensure, Result};
+use windows::Win32::Graphics::{
+    Direct3D12::{D3D12_RT_FORMAT_ARRAY, D3D12_SIMULTANEOUS_RENDER_TARGET_COUNT},
+    Dxgi::Common::{DXGI_FORMAT, DXGI_FORMAT_UNKNOWN},
+};
+
+use crate::DEPTH_STENCIL_FORMAT;
+
+const MAX_RENDER_TARGETS: usize = D3D12_SIMULTANEOUS_RENDER_TARGET_COUNT as usize;
+
+/// Colour and depth formats a graphics pipeline is created for. They are baked into the pipeline,
+/// so the views bound when drawing with it have to have the same formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TargetFormats {
+    color: [DXGI_FORMAT; MAX_RENDER_TARGETS],
+    num_color: usize,
+    /// DXGI_FORMAT_UNKNOWN without a depth buffer
+    pub depth: DXGI_FORMAT,
+}
+
+impl TargetFormats {
+    pub fn new(color: &[DXGI_FORMAT], depth: DXGI_FORMAT) -> Result<Self> {
+        ensure!(
+            color.len() <= MAX_RENDER_TARGETS,
+            "At most {} render targets can be bound, got {}",
+            MAX_RENDER_TARGETS,
+            color.len()
+        );
+
+        let mut formats = [DXGI_FORMAT_UNKNOWN; MAX_RENDER_TARGETS];
+        formats[..color.len()].copy_from_slice(color);
+
+        Ok(Self {
+            color: formats,
+            num_color: color.len(),
+            depth,
+        })
+    }
+
+    /// A single colour target with a `DEPTH_STENCIL_FORMAT` depth buffer, like `RenderTarget::new`
+    /// creates
+    pub fn single(color: DXGI_FORMAT) -> Self {
+        let mut formats = [DXGI_FORMAT_UNKNOWN; MAX_RENDER_TARGETS];
+        formats[0] = color;
+
+        Self {
+            color: formats,
+            num_color: 1,
+            depth: DEPTH_STENCIL_FORMAT,
+        }
+    }
+
+    pub fn color(&self) -> &[DXGI_FORMAT] {
+        &self.color[..self.num_color]
+    }
+
+    pub fn rt_format_array(&self) -> D3D12_RT_FORMAT_ARRAY {
+        D3D12_RT_FORMAT_ARRAY {
+            RTFormats: self.color,
+            NumRenderTargets: self.num_color as u32,
+        }
+    }
+
+    /// Fails with what differs if a pipeline created for these formats can't draw into `bound`
+    pub fn ensure_matches(&self, bound: &TargetFormats) -> Result<()> {
+        ensure!(
+            self.num_color == bound.num_color,
+            "Pipeline draws into {} render targets, {} are bound",
+            self.num_color,
+            bound.num_color
+        );
+        for (slot, (expected, actual)) in self.color().iter().zip(bound.color()).enumerate() {
+            ensure!(
+                expected == actual,
+                "Pipeline expects {:?} in render target {}, {:?} is bound",
+                expected,
+                slot,
+                actual
+            );
+        }
+        ensure!(
+            self.depth == bound.depth,
+            "Pipeline expects a {:?} depth buffer, {:?} is bound",
+            self.depth,
+            bound.depth
+        );
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use windows::Win32::Graphics::Dxgi::Common::{
+        DXGI_FORMAT_R16G16B16A16_FLOAT, DXGI_FORMAT_R8G8B8A8_UNORM,
+    };
+
+    use super::*;
+
+    #[test]
+    fn matching_formats_pass() {
+        let formats = TargetFormats::new(
+            &[DXGI_FORMAT_R8G8B8A8_UNORM, DXGI_FORMAT_R16G16B16A16_FLOAT],
+            DEPTH_STENCIL_FORMAT,
+        )
+        .unwrap();
+
+        assert!(formats.ensure_matches(&formats).is_ok());
+        assert_eq!(formats.rt_format_array().NumRenderTargets, 2);
+        assert_eq!(
+            formats.rt_format_array().RTFormats[1],
+            DXGI_FORMAT_R16G16B16A16_FLOAT
+        );
+    }
+
+    #[test]
+    fn mismatches_fail() {
+        let pipeline = TargetFormats::single(DXGI_FORMAT_R8G8B8A8_UNORM);
+
+        let other_color = TargetFormats::single(DXGI_FORMAT_R16G16B16A16_FLOAT);
+        assert!(pipeline.ensure_matches(&other_color).is_err());
+
+        let no_depth =
+            TargetFormats::new(&[DXGI_FORMAT_R8G8B8A8_UNORM], DXGI_FORMAT_UNKNOWN).unwrap();
+        assert!(pipeline.ensure_matches(&no_depth).is_err());
+
+        let two_targets = TargetFormats::new(
+            &[DXGI_FORMAT_R8G8B8A8_UNORM, DXGI_FORMAT_R8G8B8A8_UNORM],
+            DEPTH_STENCIL_FORMAT,
+        )
+        .unwrap();
+        assert!(pipeline.ensure_matches(&two_targets).is_err());
+    }
+
+    #[test]
+    fn too_many_targets_fail() {
+        assert!(
+            TargetFormats::new(&[DXGI_FORMAT_R8G8B8A8_UNORM; 9], DEPTH_STENCIL_FORMAT).is_err()
+        );
+    }
+}
@@ -0,0 +1,382 @@
+use anyhow::{ensure, Context, Result};
+use glam::Vec2;
+use windows::Win32::Graphics::{Direct3D12::*, Dxgi::Common::DXGI_FORMAT};
+
+use crate::{
+    format_bytes_per_pixel, CommandQueue, DescriptorManager, TextureDimension, TextureHandle,
+    TextureInfo, TextureManager, UploadRingBuffer,
+};
+
+/// Where an image ended up inside a `TextureAtlas`.
+/// Sample with `uv * uv_scale + uv_offset` on page `page`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AtlasRegion {
+    pub page: usize,
+    pub uv_offset: Vec2,
+    pub uv_scale: Vec2,
+}
+
+#[derive(Debug)]
+pub struct TextureAtlas {
+    pub pages: Vec<TextureHandle>,
+    pub regions: Vec<AtlasRegion>,
+}
+
+impl TextureAtlas {
+    pub fn get(&self, image_id: usize) -> Result<(&TextureHandle, AtlasRegion)> {
+        let region = *self
+            .regions
+            .get(image_id)
+            .context("Invalid atlas image id")?;
+        let page = self.pages.get(region.page).context("Invalid atlas page")?;
+
+        Ok((page, region))
+    }
+
+    pub fn delete(
+        self,
+        texture_manager: &mut TextureManager,
+        descriptor_manager: &mut DescriptorManager,
+    ) {
+        for page in self.pages {
+            texture_manager.delete(descriptor_manager, page);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct AtlasImage {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+/// Packs many small images into a few large pages so they share a handful of descriptors
+#[derive(Debug)]
+pub struct TextureAtlasBuilder {
+    page_extent: (u32, u32),
+    format: DXGI_FORMAT,
+    bytes_per_pixel: usize,
+    padding: u32,
+    images: Vec<AtlasImage>,
+}
+
+impl TextureAtlasBuilder {
+    pub fn new(page_extent: (u32, u32), format: DXGI_FORMAT) -> Result<Self> {
+        let bytes_per_pixel =
+            format_bytes_per_pixel(format).context("Unsupported texture atlas format")?;
+
+        Ok(Self {
+            page_extent,
+            format,
+            bytes_per_pixel,
+            padding: 1,
+            images: Vec::new(),
+        })
+    }
+
+    /// Border left around each image to stop filtering from bleeding into its neighbours, filled
+    /// with the image's edge texels so bilinear samples at the edge don't pick up anything else
+    pub fn with_padding(mut self, padding: u32) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// Returns the id used to look the image up in the built atlas
+    pub fn add_image(&mut self, width: u32, height: u32, data: &[u8]) -> Result<usize> {
+        ensure!(
+            data.len() == width as usize * height as usize * self.bytes_per_pixel,
+            "Image data does not match its dimensions"
+        );
+
+        self.images.push(AtlasImage {
+            width,
+            height,
+            data: data.to_vec(),
+        });
+
+        Ok(self.images.len() - 1)
+    }
+
+    pub fn build(
+        self,
+        device: &ID3D12Device4,
+        texture_manager: &mut TextureManager,
+        uploader: &mut UploadRingBuffer,
+        dependent_queue: Option<&CommandQueue>,
+        descriptor_manager: &mut DescriptorManager,
+    ) -> Result<TextureAtlas> {
+        let sizes: Vec<(u32, u32)> = self
+            .images
+            .iter()
+            .map(|image| (image.width, image.height))
+            .collect();
+        let placements = pack_shelves(self.page_extent, &sizes, self.padding)?;
+        let num_pages = placements
+            .iter()
+            .map(|placement| placement.page + 1)
+            .max()
+            .unwrap_or(0);
+
+        let (page_width, page_height) = self.page_extent;
+        let page_row_bytes = page_width as usize * self.bytes_per_pixel;
+        let mut page_data = vec![vec![0u8; page_row_bytes * page_height as usize]; num_pages];
+
+        let mut regions = Vec::with_capacity(self.images.len());
+        for (image, placement) in self.images.iter().zip(placements.iter()) {
+            copy_with_edges(
+                &mut page_data[placement.page],
+                page_row_bytes,
+                image,
+                placement,
+                self.padding,
+                self.bytes_per_pixel,
+            );
+
+            regions.push(AtlasRegion {
+                page: placement.page,
+                uv_offset: Vec2::new(
+                    placement.x as f32 / page_width as f32,
+                    placement.y as f32 / page_height as f32,
+                ),
+                uv_scale: Vec2::new(
+                    image.width as f32 / page_width as f32,
+                    image.height as f32 / page_height as f32,
+                ),
+            });
+        }
+
+        let pages = page_data
+            .iter()
+            .map(|data| {
+                texture_manager.create_texture(
+                    device,
+                    uploader,
+                    dependent_queue,
+                    descriptor_manager,
+                    TextureInfo {
+                        dimension: TextureDimension::Two(page_width as usize, page_height),
+                        format: self.format,
+                        ..Default::default()
+                    },
+                    data,
+                )
+            })
+            .collect::<Result<Vec<TextureHandle>>>()?;
+
+        Ok(TextureAtlas { pages, regions })
+    }
+}
+
+// Copies `image` to its place on the page, along with its edge texels repeated across the
+// `padding` around it
+fn copy_with_edges(
+    page: &mut [u8],
+    page_row_bytes: usize,
+    image: &AtlasImage,
+    placement: &Placement,
+    padding: u32,
+    bytes_per_pixel: usize,
+) {
+    if image.width == 0 || image.height == 0 {
+        return;
+    }
+
+    let padding = padding as i64;
+    for y in -padding..image.height as i64 + padding {
+        let src_y = y.clamp(0, image.height as i64 - 1) as usize;
+        let dst_y = (placement.y as i64 + y) as usize;
+        for x in -padding..image.width as i64 + padding {
+            let src_x = x.clamp(0, image.width as i64 - 1) as usize;
+            let dst_x = (placement.x as i64 + x) as usize;
+
+            let src = (src_y * image.width as usize + src_x) * bytes_per_pixel;
+            let dst = dst_y * page_row_bytes + dst_x * bytes_per_pixel;
+            page[dst..dst + bytes_per_pixel]
+                .copy_from_slice(&image.data[src..src + bytes_per_pixel]);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Placement {
+    page: usize,
+    x: u32,
+    y: u32,
+}
+
+/// Shelf packing: tallest images first, filling rows left to right and opening a new page when full
+fn pack_shelves(
+    page_extent: (u32, u32),
+    sizes: &[(u32, u32)],
+    padding: u32,
+) -> Result<Vec<Placement>> {
+    let (page_width, page_height) = page_extent;
+
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by(|a, b| sizes[*b].1.cmp(&sizes[*a].1));
+
+    let mut placements = vec![
+        Placement {
+            page: 0,
+            x: 0,
+            y: 0
+        };
+        sizes.len()
+    ];
+
+    let mut page = 0;
+    let mut shelf_x = 0;
+    let mut shelf_y = 0;
+    let mut shelf_height = 0;
+
+    for index in order {
+        let (width, height) = sizes[index];
+        let padded_width = width + 2 * padding;
+        let padded_height = height + 2 * padding;
+        ensure!(
+            padded_width <= page_width && padded_height <= page_height,
+            "Image {} ({}x{}) does not fit in an atlas page",
+            index,
+            width,
+            height
+        );
+
+        if shelf_x + padded_width > page_width {
+            shelf_x = 0;
+            shelf_y += shelf_height;
+            shelf_height = 0;
+        }
+
+        if shelf_y + padded_height > page_height {
+            page += 1;
+            shelf_x = 0;
+            shelf_y = 0;
+            shelf_height = 0;
+        }
+
+        placements[index] = Placement {
+            page,
+            x: shelf_x + padding,
+            y: shelf_y + padding,
+        };
+
+        shelf_x += padded_width;
+        shelf_height = u32::max(shelf_height, padded_height);
+    }
+
+    Ok(placements)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pack_single_page() {
+        let placements = pack_shelves((8, 8), &[(4, 4), (4, 2), (4, 4)], 0).unwrap();
+
+        assert_eq!(
+            vec![
+                Placement {
+                    page: 0,
+                    x: 0,
+                    y: 0
+                },
+                Placement {
+                    page: 0,
+                    x: 0,
+                    y: 4
+                },
+                Placement {
+                    page: 0,
+                    x: 4,
+                    y: 0
+                },
+            ],
+            placements
+        );
+    }
+
+    #[test]
+    fn pack_overflows_to_new_page() {
+        let placements = pack_shelves((4, 4), &[(4, 4), (2, 2)], 0).unwrap();
+
+        assert_eq!(
+            Placement {
+                page: 0,
+                x: 0,
+                y: 0
+            },
+            placements[0]
+        );
+        assert_eq!(
+            Placement {
+                page: 1,
+                x: 0,
+                y: 0
+            },
+            placements[1]
+        );
+    }
+
+    #[test]
+    fn pack_with_padding() {
+        let placements = pack_shelves((8, 8), &[(2, 2), (2, 2)], 1).unwrap();
+
+        assert_eq!(
+            Placement {
+                page: 0,
+                x: 1,
+                y: 1
+            },
+            placements[0]
+        );
+        assert_eq!(
+            Placement {
+                page: 0,
+                x: 5,
+                y: 1
+            },
+            placements[1]
+        );
+    }
+
+    #[test]
+    fn padding_repeats_the_edges() {
+        // 2x2 image of one byte texels on a 4x4 page with a texel of padding
+        let image = AtlasImage {
+            width: 2,
+            height: 2,
+            data: vec![1, 2, 3, 4],
+        };
+        let mut page = vec![0; 16];
+        copy_with_edges(
+            &mut page,
+            4,
+            &image,
+            &Placement {
+                page: 0,
+                x: 1,
+                y: 1,
+            },
+            1,
+            1,
+        );
+
+        assert_eq!(
+            page,
+            [
+                1, 1, 2, 2, //
+                1, 1, 2, 2, //
+                3, 3, 4, 4, //
+                3, 3, 4, 4,
+            ]
+        );
+    }
+
+    #[test]
+    fn pack_too_large() {
+        assert!(pack_shelves((4, 4), &[(4, 4)], 1).is_err());
+    }
+}
@@ -1,13 +1,18 @@
 use crate::{
-    CommandQueue, DescriptorHandle, DescriptorManager, DescriptorType, Heap, Resource,
-    UploadRingBuffer,
+    BarrierBatcher, CommandQueue, DeletionQueue, DescriptorHandle, DescriptorManager,
+    DescriptorType, Heap, HeapAllocation, PoolStats, Resource, SourceLayout, TextureLru,
+    TextureUsage, UploadRingBuffer, CUBE_FACE_COUNT,
 };
 use anyhow::{ensure, Context, Result};
+use std::cell::Cell;
 use windows::Win32::Graphics::Direct3D12::*;
 use windows::Win32::Graphics::Dxgi::Common::*;
 
 const DEFAULT_TEXTURE_HEAP_SIZE: usize = 2160 * 3840 * 4 * 100;
 
+/// The state textures created from data are left in by `TextureManager::transition_uploads`
+pub const UPLOADED_TEXTURE_STATE: D3D12_RESOURCE_STATES = D3D12_RESOURCE_STATE_ALL_SHADER_RESOURCE;
+
 #[derive(Debug, Clone, Copy)]
 pub enum TextureDimension {
     One(usize),
@@ -30,6 +35,93 @@ pub struct TextureInfo {
     pub is_render_target: bool,
     pub is_depth_buffer: bool,
     pub is_unordered_access: bool,
+    /// A 2D texture array sampled as cubes, `array_size` has to be a multiple of 6
+    pub is_cube: bool,
+}
+
+impl TextureInfo {
+    pub fn resource_desc(&self) -> D3D12_RESOURCE_DESC {
+        let (dimension, width, height, depth) = match self.dimension {
+            TextureDimension::One(width) => (D3D12_RESOURCE_DIMENSION_TEXTURE1D, width, 1, 1),
+            TextureDimension::Two(width, height) => (
+                D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+                width,
+                height,
+                self.array_size,
+            ),
+            TextureDimension::Three(width, height, depth) => {
+                (D3D12_RESOURCE_DIMENSION_TEXTURE3D, width, height, depth)
+            }
+        };
+
+        let mut flags: u32 = 0;
+        if self.is_depth_buffer {
+            flags |= D3D12_RESOURCE_FLAG_ALLOW_DEPTH_STENCIL.0;
+        }
+        if self.is_render_target {
+            flags |= D3D12_RESOURCE_FLAG_ALLOW_RENDER_TARGET.0;
+        }
+        if self.is_unordered_access {
+            flags |= D3D12_RESOURCE_FLAG_ALLOW_UNORDERED_ACCESS.0;
+        }
+
+        D3D12_RESOURCE_DESC {
+            Dimension: dimension,
+            Width: width as u64,
+            Height: height as u32,
+            DepthOrArraySize: depth as u16,
+            MipLevels: self.num_mips as u16,
+            Format: if self.is_depth_buffer {
+                depth_view_formats(self.format).map_or(self.format, |(typeless, _)| typeless)
+            } else {
+                self.format
+            },
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            Layout: D3D12_TEXTURE_LAYOUT_UNKNOWN,
+            Flags: D3D12_RESOURCE_FLAGS(flags),
+            ..Default::default()
+        }
+    }
+
+    /// Depth buffers are created typeless so they can be sampled through a colour format
+    pub fn srv_format(&self) -> Option<DXGI_FORMAT> {
+        if self.is_depth_buffer {
+            depth_view_formats(self.format).map(|(_, srv_format)| srv_format)
+        } else {
+            Some(self.format)
+        }
+    }
+
+    pub fn num_subresources(&self) -> u16 {
+        let depth = match self.dimension {
+            TextureDimension::Two(_, _) => self.array_size,
+            // All depth slices of a mip are in one subresource
+            TextureDimension::Three(_, _, _) => 1,
+            TextureDimension::One(_) => 1,
+        };
+
+        depth * self.num_mips
+    }
+}
+
+/// Typeless resource format and SRV format for a depth format
+fn depth_view_formats(format: DXGI_FORMAT) -> Option<(DXGI_FORMAT, DXGI_FORMAT)> {
+    match format {
+        DXGI_FORMAT_D32_FLOAT => Some((DXGI_FORMAT_R32_TYPELESS, DXGI_FORMAT_R32_FLOAT)),
+        DXGI_FORMAT_D16_UNORM => Some((DXGI_FORMAT_R16_TYPELESS, DXGI_FORMAT_R16_UNORM)),
+        DXGI_FORMAT_D24_UNORM_S8_UINT => Some((
+            DXGI_FORMAT_R24G8_TYPELESS,
+            DXGI_FORMAT_R24_UNORM_X8_TYPELESS,
+        )),
+        DXGI_FORMAT_D32_FLOAT_S8X24_UINT => Some((
+            DXGI_FORMAT_R32G8X24_TYPELESS,
+            DXGI_FORMAT_R32_FLOAT_X8X24_TYPELESS,
+        )),
+        _ => None,
+    }
 }
 
 impl Default for TextureInfo {
@@ -42,6 +134,7 @@ impl Default for TextureInfo {
             is_render_target: false,
             is_depth_buffer: false,
             is_unordered_access: false,
+            is_cube: false,
         }
     }
 }
@@ -50,12 +143,28 @@ impl Default for TextureInfo {
 pub struct Texture {
     pub info: TextureInfo,
     pub resource: Option<Resource>,
+    /// Set for textures whose memory the texture manager does not own, to the state they were
+    /// handed over in. Their first barrier starts from it and their last has to end in it.
+    pub external_state: Option<D3D12_RESOURCE_STATES>,
 }
 
 impl Texture {
     pub fn get_resource(&self) -> Result<&Resource> {
         self.resource.as_ref().context("Invalid resource")
     }
+
+    pub fn is_external(&self) -> bool {
+        self.external_state.is_some()
+    }
+}
+
+/// A resource not placed in the texture manager's heap, like a swapchain buffer or a resource
+/// opened from a shared handle
+#[derive(Debug, Clone)]
+pub struct ExternalTexture {
+    pub resource: ID3D12Resource,
+    /// The state the resource is in when it is handed over, and has to be left in
+    pub state: D3D12_RESOURCE_STATES,
 }
 
 #[derive(Debug)]
@@ -66,6 +175,17 @@ pub struct TextureManager {
     uav_descriptors: Vec<DescriptorHandle>,
     dsv_descriptors: Vec<DescriptorHandle>,
     textures: Vec<Texture>,
+    // Where textures placed in the texture heap live, to reuse the space after they're deleted
+    texture_allocations: Vec<Option<HeapAllocation>>,
+    ref_counts: Vec<u32>,
+    deletion_queue: DeletionQueue<TextureHandle>,
+    // Textures uploaded on the copy queue that are still in COMMON
+    pending_upload_transitions: Vec<usize>,
+    // Fence value of the last submission that referenced each texture
+    last_used: Vec<Option<u64>>,
+    // Whether a view of each texture was looked up since the last submission. Lookups only
+    // borrow the manager, passes hold on to it while recording.
+    referenced: Vec<Cell<bool>>,
 }
 
 #[derive(Debug, Default, Clone)]
@@ -95,12 +215,36 @@ impl TextureManager {
             uav_descriptors: Vec::new(),
             dsv_descriptors: Vec::new(),
             textures: Vec::new(),
+            texture_allocations: Vec::new(),
+            ref_counts: Vec::new(),
+            deletion_queue: DeletionQueue::new(),
+            pending_upload_transitions: Vec::new(),
+            last_used: Vec::new(),
+            referenced: Vec::new(),
         })
     }
 
+    /// Deletes the texture right away, regardless of other references to it. The GPU must be done
+    /// with it. If it was unloaded too, it is taken out of the deletion queue.
     pub fn delete(&mut self, descriptor_manager: &mut DescriptorManager, handle: TextureHandle) {
+        self.deletion_queue
+            .cancel(|pending| pending.index == handle.index);
+        self.destroy(descriptor_manager, handle);
+    }
+
+    // Deleting twice would free the views and heap space twice
+    fn destroy(&mut self, descriptor_manager: &mut DescriptorManager, handle: TextureHandle) {
         let texture_index = handle.index;
+        if self.textures[texture_index].resource.is_none() {
+            return;
+        }
         self.textures[texture_index] = Texture::default();
+        self.ref_counts[texture_index] = 0;
+        self.last_used[texture_index] = None;
+        self.referenced[texture_index].set(false);
+        if let Some(allocation) = self.texture_allocations[texture_index].take() {
+            self.texture_heap.free(allocation);
+        }
 
         if let Some(rtv_index) = handle.rtv_index {
             descriptor_manager.free(self.rtv_descriptors[rtv_index]);
@@ -120,6 +264,118 @@ impl TextureManager {
         }
     }
 
+    /// Returns a new reference to the texture, which has to be unloaded separately
+    pub fn add_ref(&mut self, handle: &TextureHandle) -> Result<TextureHandle> {
+        let ref_count = self
+            .ref_counts
+            .get_mut(handle.index)
+            .context("Invalid texture handle")?;
+        ensure!(*ref_count > 0, "Texture already unloaded");
+        *ref_count += 1;
+
+        Ok(handle.clone())
+    }
+
+    /// Drops a reference to the texture. Once there are none left it is deleted as soon as
+    /// `fence_value`, signalled after the last use of the texture, has completed.
+    pub fn unload(&mut self, handle: TextureHandle, fence_value: u64) -> Result<()> {
+        let ref_count = self
+            .ref_counts
+            .get_mut(handle.index)
+            .context("Invalid texture handle")?;
+        ensure!(*ref_count > 0, "Texture already unloaded");
+        *ref_count -= 1;
+
+        if *ref_count == 0 {
+            self.deletion_queue.push(fence_value, handle);
+        }
+
+        Ok(())
+    }
+
+    /// Deletes unloaded textures the GPU is done with, freeing their heap space and descriptors
+    pub fn collect_garbage(
+        &mut self,
+        descriptor_manager: &mut DescriptorManager,
+        completed_fence_value: u64,
+    ) {
+        for handle in self.deletion_queue.retire(completed_fence_value) {
+            self.destroy(descriptor_manager, handle);
+        }
+    }
+
+    /// Wraps an external resource, its description decides which views are created
+    pub fn add_external_texture(
+        &mut self,
+        device: &ID3D12Device4,
+        descriptor_manager: &mut DescriptorManager,
+        external: ExternalTexture,
+    ) -> Result<TextureHandle> {
+        let desc = unsafe { external.resource.GetDesc() };
+        let (dimension, array_size) = match desc.Dimension {
+            D3D12_RESOURCE_DIMENSION_TEXTURE1D => (
+                TextureDimension::One(desc.Width as usize),
+                desc.DepthOrArraySize,
+            ),
+            D3D12_RESOURCE_DIMENSION_TEXTURE2D => (
+                TextureDimension::Two(desc.Width as usize, desc.Height),
+                desc.DepthOrArraySize,
+            ),
+            D3D12_RESOURCE_DIMENSION_TEXTURE3D => (
+                TextureDimension::Three(desc.Width as usize, desc.Height, desc.DepthOrArraySize),
+                1,
+            ),
+            _ => return None.context("External resource is not a texture"),
+        };
+
+        let flags = desc.Flags.0;
+        let is_depth_buffer = flags & D3D12_RESOURCE_FLAG_ALLOW_DEPTH_STENCIL.0 != 0;
+        let info = TextureInfo {
+            dimension,
+            format: desc.Format,
+            array_size,
+            num_mips: desc.MipLevels,
+            is_render_target: flags & D3D12_RESOURCE_FLAG_ALLOW_RENDER_TARGET.0 != 0,
+            is_depth_buffer,
+            is_unordered_access: flags & D3D12_RESOURCE_FLAG_ALLOW_UNORDERED_ACCESS.0 != 0,
+            // Nothing in the resource says how it's meant to be viewed
+            is_cube: false,
+        };
+
+        let allocation_info = unsafe { device.GetResourceAllocationInfo(0, &[desc]) };
+
+        self.add_texture(
+            device,
+            descriptor_manager,
+            Texture {
+                info,
+                resource: Some(Resource {
+                    device_resource: external.resource,
+                    size: allocation_info.SizeInBytes as usize,
+                    mapped_data: std::ptr::null_mut(),
+                }),
+                external_state: Some(external.state),
+            },
+        )
+    }
+
+    /// Bytes of the texture heap in use, external textures and committed textures are not counted
+    pub fn heap_usage(&self) -> usize {
+        self.texture_heap.used_size()
+    }
+
+    /// Texture heap usage, counting every live texture whether it's in the heap or not
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            count: self
+                .textures
+                .iter()
+                .filter(|texture| texture.resource.is_some())
+                .count(),
+            ..self.texture_heap.stats()
+        }
+    }
+
     pub fn add_texture(
         &mut self,
         device: &ID3D12Device4,
@@ -129,14 +385,15 @@ impl TextureManager {
         let texture_info = &texture.info;
 
         let rtv_index = if texture_info.is_render_target {
-            let rtv_handle = self.create_rtv(device, descriptor_manager, &texture)?;
+            let rtv_handle =
+                Self::create_rtv(device, descriptor_manager, &texture, texture_info.format)?;
             self.rtv_descriptors.push(rtv_handle);
             Some(self.rtv_descriptors.len() - 1)
         } else {
             None
         };
 
-        let srv_index = if !texture_info.is_depth_buffer {
+        let srv_index = if texture_info.srv_format().is_some() {
             let srv_handle = self.create_srv(device, descriptor_manager, &texture)?;
             self.srv_descriptors.push(srv_handle);
             Some(self.srv_descriptors.len() - 1)
@@ -160,8 +417,7 @@ impl TextureManager {
             None
         };
 
-        self.textures.push(texture);
-        let index = self.textures.len() - 1;
+        let index = self.push_texture(texture, None);
 
         Ok(TextureHandle {
             index,
@@ -181,52 +437,14 @@ impl TextureManager {
         descriptor_manager: &mut DescriptorManager,
         committed_heap: bool,
     ) -> Result<TextureHandle> {
-        let (dimension, width, height, depth) = match texture_info.dimension {
-            TextureDimension::One(width) => (D3D12_RESOURCE_DIMENSION_TEXTURE1D, width, 1, 1),
-            TextureDimension::Two(width, height) => (
-                D3D12_RESOURCE_DIMENSION_TEXTURE2D,
-                width,
-                height,
-                texture_info.array_size,
-            ),
-            TextureDimension::Three(width, height, depth) => {
-                (D3D12_RESOURCE_DIMENSION_TEXTURE3D, width, height, depth)
-            }
-        };
-
-        let num_subresources = depth * texture_info.num_mips;
+        let num_subresources = texture_info.num_subresources();
 
         ensure!(num_subresources as usize <= MAX_NUM_SUBRESOURCES);
 
-        let mut flags: u32 = 0;
-        if texture_info.is_depth_buffer {
-            flags |= D3D12_RESOURCE_FLAG_ALLOW_DEPTH_STENCIL.0;
-        }
-        if texture_info.is_render_target {
-            flags |= D3D12_RESOURCE_FLAG_ALLOW_RENDER_TARGET.0;
-        }
-        if texture_info.is_unordered_access {
-            flags |= D3D12_RESOURCE_FLAG_ALLOW_UNORDERED_ACCESS.0;
-        }
-
-        let texture_desc = D3D12_RESOURCE_DESC {
-            Dimension: dimension,
-            Width: width as u64,
-            Height: height as u32,
-            DepthOrArraySize: depth as u16,
-            MipLevels: texture_info.num_mips as u16,
-            Format: texture_info.format,
-            SampleDesc: DXGI_SAMPLE_DESC {
-                Count: 1,
-                Quality: 0,
-            },
-            Layout: D3D12_TEXTURE_LAYOUT_UNKNOWN,
-            Flags: D3D12_RESOURCE_FLAGS(flags),
-            ..Default::default()
-        };
+        let texture_desc = texture_info.resource_desc();
 
-        let texture_resource = if committed_heap {
-            Resource::create_committed(
+        let (texture_resource, allocation) = if committed_heap {
+            let resource = Resource::create_committed(
                 device,
                 &D3D12_HEAP_PROPERTIES {
                     Type: D3D12_HEAP_TYPE_DEFAULT,
@@ -236,30 +454,34 @@ impl TextureManager {
                 initial_state,
                 clear_value,
                 false,
-            )?
+            )?;
+            (resource, None)
         } else {
-            self.texture_heap.create_resource(
+            let (resource, allocation) = self.texture_heap.create_freeable_resource(
                 device,
                 &texture_desc,
                 initial_state,
                 clear_value,
                 false,
-            )?
+            )?;
+            (resource, Some(allocation))
         };
         let texture = Texture {
             info: texture_info,
             resource: Some(texture_resource),
+            external_state: None,
         };
 
         let rtv_index = if texture_info.is_render_target {
-            let rtv_handle = self.create_rtv(device, descriptor_manager, &texture)?;
+            let rtv_handle =
+                Self::create_rtv(device, descriptor_manager, &texture, texture_info.format)?;
             self.rtv_descriptors.push(rtv_handle);
             Some(self.rtv_descriptors.len() - 1)
         } else {
             None
         };
 
-        let srv_index = if !texture_info.is_depth_buffer {
+        let srv_index = if texture_info.srv_format().is_some() {
             let srv_handle = self.create_srv(device, descriptor_manager, &texture)?;
             self.srv_descriptors.push(srv_handle);
             Some(self.srv_descriptors.len() - 1)
@@ -283,8 +505,7 @@ impl TextureManager {
             None
         };
 
-        self.textures.push(texture);
-        let texture_index = self.textures.len() - 1;
+        let texture_index = self.push_texture(texture, allocation);
 
         Ok(TextureHandle {
             index: texture_index,
@@ -295,6 +516,7 @@ impl TextureManager {
         })
     }
 
+    /// `data` holds every subresource in order, each one tightly packed
     pub fn create_texture(
         &mut self,
         device: &ID3D12Device4,
@@ -304,6 +526,71 @@ impl TextureManager {
         texture_info: TextureInfo,
         data: &[u8],
     ) -> Result<TextureHandle> {
+        self.create_texture_with_layouts(
+            device,
+            uploader,
+            dependent_queue,
+            descriptor_manager,
+            texture_info,
+            data,
+            &[],
+        )
+    }
+
+    /// `create_texture` for memory that is not a Rust slice, e.g. a frame a decoder library owns,
+    /// read straight into the upload buffer. `source_layouts` has one entry per subresource, or
+    /// none for tightly packed data.
+    ///
+    /// # Safety
+    ///
+    /// `data` has to be valid for reads of `len` bytes and must not be written to until this
+    /// returns.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn create_texture_from_ptr(
+        &mut self,
+        device: &ID3D12Device4,
+        uploader: &mut UploadRingBuffer,
+        dependent_queue: Option<&CommandQueue>,
+        descriptor_manager: &mut DescriptorManager,
+        texture_info: TextureInfo,
+        data: *const u8,
+        len: usize,
+        source_layouts: &[SourceLayout],
+    ) -> Result<TextureHandle> {
+        ensure!(!data.is_null(), "Uploading from a null pointer");
+
+        self.create_texture_with_layouts(
+            device,
+            uploader,
+            dependent_queue,
+            descriptor_manager,
+            texture_info,
+            std::slice::from_raw_parts(data, len),
+            source_layouts,
+        )
+    }
+
+    /// `create_texture` with the rows of every subresource wherever `source_layouts` says, one
+    /// entry per subresource. Without any layouts the data is tightly packed.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_texture_with_layouts(
+        &mut self,
+        device: &ID3D12Device4,
+        uploader: &mut UploadRingBuffer,
+        dependent_queue: Option<&CommandQueue>,
+        descriptor_manager: &mut DescriptorManager,
+        texture_info: TextureInfo,
+        data: &[u8],
+        source_layouts: &[SourceLayout],
+    ) -> Result<TextureHandle> {
+        let num_subresources = texture_info.num_subresources() as usize;
+        ensure!(
+            source_layouts.is_empty() || source_layouts.len() == num_subresources,
+            "{} source layouts for {} subresources",
+            source_layouts.len(),
+            num_subresources
+        );
+
         let texture_handle = self.create_empty_texture(
             device,
             texture_info,
@@ -327,8 +614,6 @@ impl TextureManager {
             }
         };
 
-        let num_subresources = depth * texture_info.num_mips;
-
         let texture_desc = D3D12_RESOURCE_DESC {
             Dimension: dimension,
             Width: width as u64,
@@ -365,31 +650,43 @@ impl TextureManager {
 
         let upload_context = uploader.allocate(total_bytes as usize)?;
 
-        let mut data_offset = 0;
-        for array_index in 0..texture_info.array_size {
-            for mip_index in 0..texture_info.num_mips {
-                let layout_index = (mip_index + (array_index * texture_info.num_mips)) as usize;
-                let layout = &layouts[layout_index];
-                let row_bytes = row_size_bytes[layout_index];
-                let mut resource_offset = layout.Offset;
-
-                for _ in 0..layout.Footprint.Depth {
-                    for _ in 0..layout.Footprint.Height {
-                        let row = &data[data_offset as usize..(data_offset + row_bytes) as usize];
-
-                        upload_context
-                            .sub_resource
-                            .copy_to_offset_from(resource_offset as usize, row)?;
-
-                        data_offset += row_bytes;
-                        resource_offset += layout.Footprint.RowPitch as u64;
-                    }
+        // Subresources are in order, the mips of one array slice after each other
+        let mut packed_offset = 0;
+        for (subresource_index, layout) in layouts.iter().enumerate().take(num_subresources) {
+            let row_bytes = row_size_bytes[subresource_index] as usize;
+            let rows = num_rows[subresource_index] as usize;
+            let depth = layout.Footprint.Depth as usize;
+
+            let source = match source_layouts.get(subresource_index) {
+                Some(source) => *source,
+                None => SourceLayout::tightly_packed(packed_offset, row_bytes, rows),
+            };
+            let source_end = source.required_size(row_bytes, rows, depth)?;
+            ensure!(
+                source_end <= data.len(),
+                "Subresource {} needs {} bytes of data, there are only {}",
+                subresource_index,
+                source_end,
+                data.len()
+            );
+            packed_offset = source_end;
+
+            let mut resource_offset = layout.Offset as usize;
+            for slice in 0..depth {
+                for row in 0..rows {
+                    let row_offset = source.row_offset(row, slice);
+                    upload_context.sub_resource.copy_to_offset_from(
+                        resource_offset,
+                        &data[row_offset..row_offset + row_bytes],
+                    )?;
+
+                    resource_offset += layout.Footprint.RowPitch as usize;
                 }
             }
         }
 
-        for subresource_index in 0..num_subresources {
-            let mut layout = layouts[subresource_index as usize];
+        for (subresource_index, layout) in layouts.iter().enumerate().take(num_subresources) {
+            let mut layout = *layout;
             layout.Offset += upload_context.sub_resource.offset as u64;
 
             let from = D3D12_TEXTURE_COPY_LOCATION {
@@ -420,10 +717,81 @@ impl TextureManager {
         }
 
         upload_context.submit(dependent_queue)?;
+        self.pending_upload_transitions.push(texture_handle.index);
 
         Ok(texture_handle)
     }
 
+    /// Moves the textures uploaded since the last call from COMMON, which copy queue work
+    /// leaves them in, to `UPLOADED_TEXTURE_STATE`. Call before the passes of every command list
+    /// recorded for the uploads' dependent queue. Textures sampled before they are transitioned
+    /// are still read correctly: shader reads promote textures out of COMMON and they decay back
+    /// to it when the command list finishes.
+    pub fn transition_uploads(&mut self, barriers: &mut BarrierBatcher) {
+        for index in self.pending_upload_transitions.drain(..) {
+            // Deleted before they were used
+            let Some(resource) = &self.textures[index].resource else {
+                continue;
+            };
+            barriers.transition(
+                &resource.device_resource,
+                D3D12_RESOURCE_STATE_COMMON,
+                UPLOADED_TEXTURE_STATE,
+            );
+        }
+    }
+
+    fn push_texture(&mut self, texture: Texture, allocation: Option<HeapAllocation>) -> usize {
+        self.textures.push(texture);
+        self.texture_allocations.push(allocation);
+        self.ref_counts.push(1);
+        self.last_used.push(None);
+        self.referenced.push(Cell::new(false));
+
+        self.textures.len() - 1
+    }
+
+    /// Call with the fence value signalled after every submission. Textures count as referenced
+    /// by it when a view of them was looked up while recording it.
+    pub fn submitted(&mut self, fence_value: u64) {
+        for (last_used, referenced) in self.last_used.iter_mut().zip(&self.referenced) {
+            if referenced.take() {
+                *last_used = Some(fence_value);
+            }
+        }
+    }
+
+    /// Fence value of the last submission that referenced the texture, None if none has yet
+    pub fn last_used(&self, handle: &TextureHandle) -> Option<u64> {
+        self.last_used.get(handle.index).copied().flatten()
+    }
+
+    /// Every live texture, the ones the GPU is done with as of `completed_fence_value` and used
+    /// longest ago first
+    pub fn least_recently_used(&self, completed_fence_value: u64) -> TextureLru {
+        let textures = self
+            .textures
+            .iter()
+            .zip(&self.last_used)
+            .enumerate()
+            .filter_map(|(index, (texture, &last_used))| {
+                texture.resource.as_ref().map(|resource| TextureUsage {
+                    index,
+                    last_used,
+                    size: resource.size,
+                })
+            })
+            .collect();
+
+        TextureLru::new(textures, completed_fence_value)
+    }
+
+    fn mark_referenced(&self, handle: &TextureHandle) {
+        if let Some(referenced) = self.referenced.get(handle.index) {
+            referenced.set(true);
+        }
+    }
+
     pub fn get_texture(&self, handle: &TextureHandle) -> Result<&Texture> {
         self.textures
             .get(handle.index)
@@ -432,14 +800,34 @@ impl TextureManager {
 
     pub fn get_rtv(&self, handle: &TextureHandle) -> Result<DescriptorHandle> {
         let rtv_index = handle.rtv_index.context("No rtv for texture")?;
+        self.mark_referenced(handle);
         self.rtv_descriptors
             .get(rtv_index)
             .copied()
             .context("Invalid rtv index")
     }
 
+    /// An extra render target view that reinterprets the texture in another format of the same
+    /// family, e.g. sRGB. It isn't freed with the texture, the caller has to.
+    pub fn create_rtv_with_format(
+        &self,
+        device: &ID3D12Device4,
+        descriptor_manager: &mut DescriptorManager,
+        handle: &TextureHandle,
+        format: DXGI_FORMAT,
+    ) -> Result<DescriptorHandle> {
+        let texture = self.get_texture(handle)?;
+        ensure!(
+            texture.info.is_render_target,
+            "Texture can't be rendered to"
+        );
+
+        Self::create_rtv(device, descriptor_manager, texture, format)
+    }
+
     pub fn get_dsv(&self, handle: &TextureHandle) -> Result<DescriptorHandle> {
         let dsv_index = handle.dsv_index.context("No dsv for texture")?;
+        self.mark_referenced(handle);
         self.dsv_descriptors
             .get(dsv_index)
             .copied()
@@ -447,6 +835,7 @@ impl TextureManager {
     }
     pub fn get_uav(&self, handle: &TextureHandle) -> Result<DescriptorHandle> {
         let uav_index = handle.uav_index.context("No uav for texture")?;
+        self.mark_referenced(handle);
         self.uav_descriptors
             .get(uav_index)
             .copied()
@@ -607,10 +996,10 @@ impl TextureManager {
     }
 
     fn create_rtv(
-        &mut self,
         device: &ID3D12Device4,
         descriptor_manager: &mut DescriptorManager,
         texture: &Texture,
+        format: DXGI_FORMAT,
     ) -> Result<DescriptorHandle> {
         let descriptor = descriptor_manager.allocate(DescriptorType::RenderTargetView)?;
 
@@ -677,7 +1066,7 @@ impl TextureManager {
             device.CreateRenderTargetView(
                 &texture.get_resource()?.device_resource,
                 &D3D12_RENDER_TARGET_VIEW_DESC {
-                    Format: texture.info.format,
+                    Format: format,
                     ViewDimension: view_dimension,
                     Anonymous: anonymous_member,
                 },
@@ -723,6 +1112,38 @@ impl TextureManager {
                     )
                 }
             }
+            TextureDimension::Two(_, _) if texture.info.is_cube => {
+                ensure!(
+                    (texture.info.array_size as usize).is_multiple_of(CUBE_FACE_COUNT),
+                    "Cube texture with {} faces",
+                    texture.info.array_size
+                );
+                if texture.info.array_size as usize > CUBE_FACE_COUNT {
+                    (
+                        D3D12_SRV_DIMENSION_TEXTURECUBEARRAY,
+                        D3D12_SHADER_RESOURCE_VIEW_DESC_0 {
+                            TextureCubeArray: D3D12_TEXCUBE_ARRAY_SRV {
+                                MostDetailedMip: 0,
+                                MipLevels: texture.info.num_mips as u32,
+                                First2DArrayFace: 0,
+                                NumCubes: texture.info.array_size as u32 / CUBE_FACE_COUNT as u32,
+                                ResourceMinLODClamp: 0.0,
+                            },
+                        },
+                    )
+                } else {
+                    (
+                        D3D12_SRV_DIMENSION_TEXTURECUBE,
+                        D3D12_SHADER_RESOURCE_VIEW_DESC_0 {
+                            TextureCube: D3D12_TEXCUBE_SRV {
+                                MostDetailedMip: 0,
+                                MipLevels: texture.info.num_mips as u32,
+                                ResourceMinLODClamp: 0.0,
+                            },
+                        },
+                    )
+                }
+            }
             TextureDimension::Two(_, _) => {
                 if texture.info.array_size > 1 {
                     (
@@ -768,7 +1189,10 @@ impl TextureManager {
             device.CreateShaderResourceView(
                 &texture.get_resource()?.device_resource,
                 &D3D12_SHADER_RESOURCE_VIEW_DESC {
-                    Format: texture.info.format,
+                    Format: texture
+                        .info
+                        .srv_format()
+                        .context("Texture can't be sampled")?,
                     ViewDimension: view_dimension,
                     Shader4ComponentMapping: D3D12_DEFAULT_SHADER_4_COMPONENT_MAPPING,
                     Anonymous: anonymous_member,
@@ -782,6 +1206,7 @@ impl TextureManager {
 
     pub fn get_srv(&self, handle: &TextureHandle) -> Result<DescriptorHandle> {
         let srv_index = handle.srv_index.context("No SRV for texture")?;
+        self.mark_referenced(handle);
         self.srv_descriptors
             .get(srv_index)
             .copied()
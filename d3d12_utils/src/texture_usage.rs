@@ -0,0 +1,132 @@
+use std::fmt;
+
+use crate::format_bytes;
+
+/// When a live texture was last referenced by work submitted to the GPU
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextureUsage {
+    /// Of the texture's handle
+    pub index: usize,
+    /// Fence value of the last submission that referenced it, None if none has yet
+    pub last_used: Option<u64>,
+    pub size: usize,
+}
+
+impl TextureUsage {
+    /// Whether the GPU is done with it, so it can be evicted or deleted without waiting
+    pub fn is_idle(&self, completed_fence_value: u64) -> bool {
+        self.last_used
+            .is_none_or(|fence_value| fence_value <= completed_fence_value)
+    }
+}
+
+/// Live textures from the least to the most recently used, so what is safest to evict comes first
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TextureLru {
+    pub textures: Vec<TextureUsage>,
+    /// What the idle textures were decided by
+    pub completed_fence_value: u64,
+}
+
+impl TextureLru {
+    pub fn new(mut textures: Vec<TextureUsage>, completed_fence_value: u64) -> Self {
+        // Never used sorts first, ties go by age of the texture
+        textures.sort_by_key(|usage| (usage.last_used, usage.index));
+
+        Self {
+            textures,
+            completed_fence_value,
+        }
+    }
+
+    /// The textures the GPU is done with, least recently used first
+    pub fn idle(&self) -> impl Iterator<Item = &TextureUsage> {
+        self.textures
+            .iter()
+            .take_while(|usage| usage.is_idle(self.completed_fence_value))
+    }
+}
+
+impl fmt::Display for TextureLru {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let idle_size: usize = self.idle().map(|usage| usage.size).sum();
+        write!(
+            f,
+            "{} textures, {} idle taking up {}",
+            self.textures.len(),
+            self.idle().count(),
+            format_bytes(idle_size)
+        )?;
+        for usage in &self.textures {
+            let last_used = match usage.last_used {
+                Some(fence_value) => format!("fence {}", fence_value),
+                None => "never".to_string(),
+            };
+            let state = if usage.is_idle(self.completed_fence_value) {
+                "idle"
+            } else {
+                "in flight"
+            };
+            write!(
+                f,
+                "\n    #{:<5} {:>10}, last used {}, {}",
+                usage.index,
+                format_bytes(usage.size),
+                last_used,
+                state
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(index: usize, last_used: Option<u64>) -> TextureUsage {
+        TextureUsage {
+            index,
+            last_used,
+            size: 1024,
+        }
+    }
+
+    #[test]
+    fn orders_never_used_then_oldest_first() {
+        let lru = TextureLru::new(
+            vec![
+                usage(0, Some(7)),
+                usage(1, None),
+                usage(2, Some(3)),
+                usage(3, Some(7)),
+            ],
+            0,
+        );
+
+        let order: Vec<usize> = lru.textures.iter().map(|usage| usage.index).collect();
+        assert_eq!(order, [1, 2, 0, 3]);
+    }
+
+    #[test]
+    fn idle_stops_at_the_first_texture_in_flight() {
+        let lru = TextureLru::new(
+            vec![usage(0, Some(5)), usage(1, Some(4)), usage(2, None)],
+            4,
+        );
+
+        let idle: Vec<usize> = lru.idle().map(|usage| usage.index).collect();
+        assert_eq!(idle, [2, 1]);
+        assert!(!lru.textures[2].is_idle(4));
+    }
+
+    #[test]
+    fn summarises_idle_textures() {
+        let lru = TextureLru::new(vec![usage(0, Some(2)), usage(1, Some(9))], 2);
+
+        let report = lru.to_string();
+        assert!(report.starts_with("2 textures, 1 idle taking up 1.0 KiB"));
+        assert!(report.contains("last used fence 9, in flight"));
+    }
+}
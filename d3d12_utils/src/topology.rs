@@ -0,0 +1,62 @@
+use windows::Win32::Graphics::{Direct3D::*, Direct3D12::*};
+
+/// How the vertices of a draw are put together. Pipelines are created for a topology type, so
+/// lists and strips of the same primitive can share one.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PrimitiveTopology {
+    #[default]
+    TriangleList,
+    TriangleStrip,
+    /// e.g. debug lines
+    LineList,
+    LineStrip,
+    /// e.g. particles
+    PointList,
+}
+
+impl PrimitiveTopology {
+    /// What `IASetPrimitiveTopology` takes
+    pub fn d3d(self) -> D3D_PRIMITIVE_TOPOLOGY {
+        match self {
+            Self::TriangleList => D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST,
+            Self::TriangleStrip => D3D_PRIMITIVE_TOPOLOGY_TRIANGLESTRIP,
+            Self::LineList => D3D_PRIMITIVE_TOPOLOGY_LINELIST,
+            Self::LineStrip => D3D_PRIMITIVE_TOPOLOGY_LINESTRIP,
+            Self::PointList => D3D_PRIMITIVE_TOPOLOGY_POINTLIST,
+        }
+    }
+
+    /// What the pipeline drawing it has to be created with
+    pub fn topology_type(self) -> D3D12_PRIMITIVE_TOPOLOGY_TYPE {
+        match self {
+            Self::TriangleList | Self::TriangleStrip => D3D12_PRIMITIVE_TOPOLOGY_TYPE_TRIANGLE,
+            Self::LineList | Self::LineStrip => D3D12_PRIMITIVE_TOPOLOGY_TYPE_LINE,
+            Self::PointList => D3D12_PRIMITIVE_TOPOLOGY_TYPE_POINT,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_and_strips_share_pipelines() {
+        assert_eq!(
+            PrimitiveTopology::TriangleList.topology_type(),
+            PrimitiveTopology::TriangleStrip.topology_type()
+        );
+        assert_eq!(
+            PrimitiveTopology::LineList.topology_type(),
+            PrimitiveTopology::LineStrip.topology_type()
+        );
+        assert_ne!(
+            PrimitiveTopology::LineList.topology_type(),
+            PrimitiveTopology::PointList.topology_type()
+        );
+        assert_ne!(
+            PrimitiveTopology::TriangleStrip.d3d(),
+            PrimitiveTopology::TriangleList.d3d()
+        );
+    }
+}
@@ -0,0 +1,144 @@
+use anyhow::{ensure, Result};
+
+use crate::PoolStats;
+
+/// A block of the shader visible heap for views that only live for a frame, such as UAVs of
+/// single mips of targets recreated on resize. It is split into a section per frame in flight,
+/// which is reset as a whole when its frame comes round again, so nothing is ever freed one by
+/// one. Indices are into the heap the block was taken from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransientDescriptorRing {
+    first_index: usize,
+    descriptors_per_frame: usize,
+    num_frames: usize,
+    frame_index: usize,
+    // In the section of `frame_index`
+    num_allocated: usize,
+    high_water_mark: usize,
+}
+
+impl TransientDescriptorRing {
+    pub fn new(first_index: usize, descriptors_per_frame: usize, num_frames: usize) -> Self {
+        Self {
+            first_index,
+            descriptors_per_frame,
+            num_frames,
+            frame_index: 0,
+            num_allocated: 0,
+            high_water_mark: 0,
+        }
+    }
+
+    /// Descriptors of every frame, the size of the block
+    pub fn len(&self) -> usize {
+        self.descriptors_per_frame * self.num_frames
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Reuses the section of `frame_index`, the GPU has to be done with the frame that last used it
+    pub fn begin_frame(&mut self, frame_index: usize) -> Result<()> {
+        ensure!(
+            frame_index < self.num_frames,
+            "Frame {} has no transient descriptors, there are sections for {} frames",
+            frame_index,
+            self.num_frames
+        );
+        self.frame_index = frame_index;
+        self.num_allocated = 0;
+
+        Ok(())
+    }
+
+    pub fn allocate(&mut self) -> Result<usize> {
+        ensure!(
+            self.num_allocated < self.descriptors_per_frame,
+            "Out of transient descriptors, {} can be allocated per frame",
+            self.descriptors_per_frame
+        );
+        let index =
+            self.first_index + self.frame_index * self.descriptors_per_frame + self.num_allocated;
+        self.num_allocated += 1;
+        self.high_water_mark = self.high_water_mark.max(self.num_allocated);
+
+        Ok(index)
+    }
+
+    pub fn contains(&self, index: usize) -> bool {
+        (self.first_index..self.first_index + self.len()).contains(&index)
+    }
+
+    /// Of the current frame's section
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            used: self.num_allocated,
+            capacity: self.descriptors_per_frame,
+            high_water_mark: self.high_water_mark,
+            count: self.num_allocated,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_allocate_from_their_own_section() {
+        let mut ring = TransientDescriptorRing::new(100, 4, 2);
+        assert_eq!(ring.allocate().unwrap(), 100);
+        assert_eq!(ring.allocate().unwrap(), 101);
+
+        ring.begin_frame(1).unwrap();
+        assert_eq!(ring.allocate().unwrap(), 104);
+
+        ring.begin_frame(0).unwrap();
+        assert_eq!(ring.allocate().unwrap(), 100);
+        assert_eq!(ring.stats().high_water_mark, 2);
+        assert!(ring.begin_frame(2).is_err());
+    }
+
+    #[test]
+    fn sections_run_out_until_reset() {
+        let mut ring = TransientDescriptorRing::new(0, 2, 3);
+        ring.allocate().unwrap();
+        ring.allocate().unwrap();
+        assert!(ring.allocate().is_err());
+        assert_eq!(ring.stats().usage(), 1.0);
+
+        ring.begin_frame(0).unwrap();
+        assert_eq!(ring.allocate().unwrap(), 0);
+    }
+
+    #[test]
+    fn sections_are_independent_of_each_other() {
+        let mut ring = TransientDescriptorRing::new(8, 2, 3);
+        for frame_index in 0..3 {
+            ring.begin_frame(frame_index).unwrap();
+            assert_eq!(ring.stats().used, 0);
+            assert_eq!(ring.allocate().unwrap(), 8 + frame_index * 2);
+            assert_eq!(ring.allocate().unwrap(), 9 + frame_index * 2);
+            // Running out doesn't spill over into the next section
+            assert!(ring.allocate().is_err());
+            assert_eq!(ring.stats().used, 2);
+        }
+
+        // Coming round again starts at the beginning of the section
+        ring.begin_frame(1).unwrap();
+        assert_eq!(ring.stats().used, 0);
+        assert_eq!(ring.allocate().unwrap(), 10);
+        assert_eq!(ring.stats().high_water_mark, 2);
+    }
+
+    #[test]
+    fn contains_only_the_block() {
+        let ring = TransientDescriptorRing::new(10, 4, 2);
+        assert_eq!(ring.len(), 8);
+        assert!(!ring.contains(9));
+        assert!(ring.contains(10));
+        assert!(ring.contains(17));
+        assert!(!ring.contains(18));
+    }
+}
@@ -1,10 +1,10 @@
-use anyhow::{ensure, Result};
+use anyhow::{ensure, Context, Result};
 use windows::{
     core::PCWSTR,
     Win32::Graphics::{Direct3D12::*, Dxgi::Common::DXGI_SAMPLE_DESC},
 };
 
-use crate::{align_data, CommandQueue, Heap, Resource, SubResource};
+use crate::{align_data, AllocationCounts, CommandQueue, Heap, PoolStats, Resource, SubResource};
 
 #[derive(Debug)]
 struct Submission {
@@ -52,13 +52,91 @@ impl Submission {
 }
 
 const MAX_NUMBER_SUBMISSIONS: usize = 16;
+
+/// Buffer copies have no alignment requirement, this keeps what is written into them aligned
+/// for vector loads and raw views
+pub const BUFFER_UPLOAD_ALIGNMENT: usize = D3D12_RAW_UAV_SRV_BYTE_ALIGNMENT as usize;
+/// Texture footprints have to start on a multiple of this
+pub const TEXTURE_UPLOAD_ALIGNMENT: usize = D3D12_TEXTURE_DATA_PLACEMENT_ALIGNMENT as usize;
+
+// Which bytes of the ring the uploads in flight take up, from `tail` up to `head`. Uploads are
+// released in the order they were reserved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RingSpace {
+    size: usize,
+    head: usize,
+    tail: usize,
+    num_reserved: usize,
+}
+
+impl RingSpace {
+    fn new(size: usize) -> Self {
+        Self {
+            size,
+            head: 0,
+            tail: 0,
+            num_reserved: 0,
+        }
+    }
+
+    // The offset of `size` free bytes, None until enough uploads in flight are released. Bytes
+    // left at the end when an upload starts over at 0 stay in use until the tail wraps too.
+    fn reserve(&mut self, size: usize) -> Option<usize> {
+        let wrapped = self.head < self.tail || (self.head == self.tail && self.num_reserved > 0);
+        let offset = if wrapped {
+            (self.head + size <= self.tail).then_some(self.head)?
+        } else if self.head + size <= self.size {
+            self.head
+        } else {
+            (size <= self.tail).then_some(0)?
+        };
+
+        self.head = offset + size;
+        self.num_reserved += 1;
+        Some(offset)
+    }
+
+    // Of the oldest upload in flight
+    fn release(&mut self, offset: usize, size: usize) -> Result<()> {
+        ensure!(
+            self.num_reserved > 0 && (offset == self.tail || offset == 0),
+            "Upload at {} released out of order, the oldest is at {}",
+            offset,
+            self.tail
+        );
+
+        self.num_reserved -= 1;
+        if self.num_reserved == 0 {
+            self.head = 0;
+            self.tail = 0;
+        } else {
+            self.tail = offset + size;
+        }
+
+        Ok(())
+    }
+
+    fn used(&self) -> usize {
+        if self.num_reserved == 0 {
+            0
+        } else if self.head > self.tail {
+            self.head - self.tail
+        } else {
+            self.size - self.tail + self.head
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct UploadRingBuffer {
     buffer_size: usize,
     buffer: Resource,
 
-    buffer_head: usize,
-    buffer_tail: usize,
+    space: RingSpace,
+    high_water_mark: usize,
+    // Since creation, padding included
+    num_allocations: u64,
+    allocated_bytes: u64,
 
     submissions: [Submission; MAX_NUMBER_SUBMISSIONS],
     submissions_start: usize,
@@ -92,11 +170,112 @@ impl<'a> Upload<'a> {
     }
 }
 
+/// Several copies out of one chunk of the ring, recorded into one command list and submitted
+/// together, so many small uploads take one of the 16 submissions instead of one each. The
+/// whole chunk stays in use until the submission completes, so ask for about what will be
+/// uploaded.
+pub struct UploadBatch<'resource> {
+    buffer: &'resource Resource,
+    submission: &'resource mut Submission,
+    pub command_list: ID3D12GraphicsCommandList1,
+    upload_queue: &'resource mut CommandQueue,
+    // Of the batch's chunk in the ring
+    offset: usize,
+    capacity: usize,
+    used: usize,
+}
+
+impl<'resource> UploadBatch<'resource> {
+    /// `size` bytes of the chunk starting on a multiple of `alignment`, to be written and
+    /// copied out of with `command_list`
+    pub fn allocate(&mut self, size: usize, alignment: usize) -> Result<SubResource<'resource>> {
+        let start =
+            pack_into_batch(self.used, size, alignment, self.capacity).with_context(|| {
+                format!(
+                    "{} bytes don't fit the {} of {} bytes left in the upload batch",
+                    size,
+                    self.capacity - self.used,
+                    self.capacity
+                )
+            })?;
+        self.used = start + size;
+
+        self.buffer.create_sub_resource(size, self.offset + start)
+    }
+
+    pub fn allocate_buffer(&mut self, size: usize) -> Result<SubResource<'resource>> {
+        self.allocate(size, BUFFER_UPLOAD_ALIGNMENT)
+    }
+
+    /// The offset of the sub resource has to be added to the footprints copied out of it
+    pub fn allocate_texture(&mut self, size: usize) -> Result<SubResource<'resource>> {
+        self.allocate(size, TEXTURE_UPLOAD_ALIGNMENT)
+    }
+
+    /// Writes `data` into the batch and records copying it into `destination`
+    pub fn upload_buffer<T: Sized>(&mut self, data: &[T], destination: &SubResource) -> Result<()> {
+        let source = self.allocate_buffer(std::mem::size_of_val(data))?;
+        source.copy_from(data)?;
+        source.copy_to_sub_resource(&self.command_list, destination)
+    }
+
+    /// Bytes left for allocations without alignment padding
+    pub fn remaining(&self) -> usize {
+        self.capacity - self.used
+    }
+
+    pub fn submit(self, dependent_queue: Option<&CommandQueue>) -> Result<()> {
+        unsafe {
+            self.command_list.Close()?;
+        }
+        let fence_value = self
+            .upload_queue
+            .execute_command_list(&self.submission.command_list.clone().into())?;
+        self.submission.fence_value = fence_value;
+
+        if let Some(queue) = dependent_queue {
+            queue.insert_wait_for_queue_fence(self.upload_queue, fence_value)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// How much of a batch `sizes` take up when each of them is aligned to `alignment`, for sizing
+/// `UploadRingBuffer::begin_batch`
+pub fn batch_size(sizes: impl IntoIterator<Item = usize>, alignment: usize) -> usize {
+    sizes.into_iter().fold(0, |used, size| {
+        used.next_multiple_of(alignment.max(1)) + size
+    })
+}
+
+// Start of `size` bytes on a multiple of `alignment` after the first `used` bytes of a batch
+fn pack_into_batch(used: usize, size: usize, alignment: usize, capacity: usize) -> Option<usize> {
+    let start = used.next_multiple_of(alignment.max(1));
+    (start + size <= capacity).then_some(start)
+}
+
 impl UploadRingBuffer {
     pub fn new(
         device: &ID3D12Device4,
         upload_heap: Option<&mut Heap>,
         size: Option<usize>,
+    ) -> Result<UploadRingBuffer> {
+        Self::with_queue_priority(
+            device,
+            upload_heap,
+            size,
+            D3D12_COMMAND_QUEUE_PRIORITY_NORMAL,
+            "Upload Ring Buffer Copy Command Queue",
+        )
+    }
+
+    pub fn with_queue_priority(
+        device: &ID3D12Device4,
+        upload_heap: Option<&mut Heap>,
+        size: Option<usize>,
+        queue_priority: D3D12_COMMAND_QUEUE_PRIORITY,
+        queue_name: &str,
     ) -> Result<UploadRingBuffer> {
         let size = size.unwrap_or(64 * 1024 * 1024);
 
@@ -140,10 +319,11 @@ impl UploadRingBuffer {
         let submissions =
             array_init::try_array_init(|_| -> Result<Submission> { Submission::new(device) })?;
 
-        let upload_queue = CommandQueue::new(
+        let upload_queue = CommandQueue::with_priority(
             device,
             D3D12_COMMAND_LIST_TYPE_COPY,
-            "Upload Ring Buffer Copy Command Queue",
+            queue_priority,
+            queue_name,
         )?;
 
         Ok(UploadRingBuffer {
@@ -151,8 +331,10 @@ impl UploadRingBuffer {
             buffer,
             submissions,
 
-            buffer_head: 0,
-            buffer_tail: 0,
+            space: RingSpace::new(size),
+            high_water_mark: 0,
+            num_allocations: 0,
+            allocated_bytes: 0,
 
             submissions_start: 0,
             submissions_used: 0,
@@ -162,6 +344,39 @@ impl UploadRingBuffer {
     }
 
     pub fn allocate(&mut self, size: usize) -> Result<Upload> {
+        let (submission_index, offset) = self.begin_submission(size)?;
+
+        let submission = &mut self.submissions[submission_index];
+        let command_list = submission.command_list.clone();
+        Ok(Upload {
+            sub_resource: self.buffer.create_sub_resource(size, offset)?,
+            submission,
+            command_list,
+            upload_queue: &mut self.upload_queue,
+        })
+    }
+
+    /// Takes one submission and `capacity` bytes of the ring for several uploads, see
+    /// `UploadBatch`
+    pub fn begin_batch(&mut self, capacity: usize) -> Result<UploadBatch<'_>> {
+        let (submission_index, offset) = self.begin_submission(capacity)?;
+
+        let submission = &mut self.submissions[submission_index];
+        let command_list = submission.command_list.clone();
+        Ok(UploadBatch {
+            buffer: &self.buffer,
+            submission,
+            command_list,
+            upload_queue: &mut self.upload_queue,
+            offset,
+            capacity,
+            used: 0,
+        })
+    }
+
+    // Reserves `size` bytes of the ring for the next submission and resets its command list.
+    // Returns the submission's index and the offset of its bytes.
+    fn begin_submission(&mut self, size: usize) -> Result<(usize, usize)> {
         let raw_size = size; // Keep track of the actual size of the user data
         let size = align_data(size, D3D12_TEXTURE_DATA_PLACEMENT_ALIGNMENT as usize);
 
@@ -169,17 +384,45 @@ impl UploadRingBuffer {
             self.clean_up_submissions()?;
         }
 
-        ensure!(self.submissions_used < MAX_NUMBER_SUBMISSIONS);
-        ensure!(size < self.buffer_size);
-        ensure!((self.buffer_head + size < self.buffer_size) || size < self.buffer_tail);
+        // Streaming uploads can outpace the copy queue, block on the oldest submission instead of failing
+        if self.submissions_used >= MAX_NUMBER_SUBMISSIONS {
+            let oldest_fence_value = self.submissions[self.submissions_start].fence_value;
+            self.upload_queue
+                .wait_for_fence_blocking(oldest_fence_value)?;
+            self.clean_up_submissions()?;
+        }
 
-        let offset = if self.buffer_head + size > self.buffer_size {
-            0
-        } else {
-            self.buffer_head
+        ensure!(self.submissions_used < MAX_NUMBER_SUBMISSIONS);
+        ensure!(
+            size <= self.buffer_size,
+            "Upload of {} bytes doesn't fit into a {} byte ring",
+            size,
+            self.buffer_size
+        );
+
+        // A full ring waits for its own oldest submission, so a full background ring never holds
+        // up the high priority one
+        let offset = loop {
+            if let Some(offset) = self.space.reserve(size) {
+                break offset;
+            }
+            // Nothing in flight would ever give the space back
+            ensure!(
+                self.submissions_used > 0,
+                "Upload of {} bytes doesn't fit into the {} free bytes of an idle {} byte ring",
+                size,
+                self.buffer_size - self.used_size(),
+                self.buffer_size
+            );
+            let oldest_fence_value = self.submissions[self.submissions_start].fence_value;
+            self.upload_queue
+                .wait_for_fence_blocking(oldest_fence_value)?;
+            self.clean_up_submissions()?;
         };
 
-        self.buffer_head = offset + size;
+        self.high_water_mark = self.high_water_mark.max(self.used_size());
+        self.num_allocations += 1;
+        self.allocated_bytes += size as u64;
 
         let submission_index =
             (self.submissions_start + self.submissions_used) % self.submissions.len();
@@ -197,13 +440,7 @@ impl UploadRingBuffer {
         submission.padding = size - raw_size;
         submission.size = raw_size;
 
-        let command_list = submission.command_list.clone();
-        Ok(Upload {
-            sub_resource: self.buffer.create_sub_resource(raw_size, offset)?,
-            submission,
-            command_list,
-            upload_queue: &mut self.upload_queue,
-        })
+        Ok((submission_index, offset))
     }
 
     pub fn submit(&mut self, upload: Upload, dependent_queue: Option<&CommandQueue>) -> Result<()> {
@@ -228,13 +465,8 @@ impl UploadRingBuffer {
             let submission = &mut self.submissions[index];
             let fence = submission.fence_value;
             if self.upload_queue.is_fence_complete(fence) {
-                ensure!(self.buffer_tail == submission.offset);
-
-                if self.buffer_tail + submission.size + submission.padding > self.buffer_size {
-                    self.buffer_tail = 0;
-                } else {
-                    self.buffer_tail += submission.size + submission.padding;
-                }
+                self.space
+                    .release(submission.offset, submission.size + submission.padding)?;
 
                 self.submissions_start = (self.submissions_start + 1) % MAX_NUMBER_SUBMISSIONS;
                 self.submissions_used -= 1;
@@ -248,7 +480,158 @@ impl UploadRingBuffer {
         Ok(())
     }
 
+    /// Bytes between the oldest upload still in flight and the newest one
+    pub fn used_size(&self) -> usize {
+        self.space.used()
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            used: self.used_size(),
+            capacity: self.buffer_size,
+            high_water_mark: self.high_water_mark,
+            count: self.submissions_used,
+        }
+    }
+
+    pub fn allocation_counts(&self) -> AllocationCounts {
+        AllocationCounts {
+            transient_allocations: self.num_allocations,
+            upload_bytes: self.allocated_bytes,
+            ..Default::default()
+        }
+    }
+
+    /// The copy queue the uploads run on
+    pub fn queue(&self) -> &CommandQueue {
+        &self.upload_queue
+    }
+
     pub fn wait_on_pending(&mut self) {
         todo!()
     }
 }
+
+/// Which ring an upload is routed through
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UploadPriority {
+    /// Small data the next frames need, which shouldn't wait behind streaming
+    High,
+    /// Bulk and streaming uploads
+    Background,
+}
+
+/// A small ring on a high priority copy queue next to a large one on a normal priority queue
+#[derive(Debug)]
+pub struct UploadRings {
+    high: UploadRingBuffer,
+    background: UploadRingBuffer,
+}
+
+impl UploadRings {
+    pub fn new(
+        device: &ID3D12Device4,
+        high_priority_size: usize,
+        background_size: usize,
+    ) -> Result<Self> {
+        Ok(Self {
+            high: UploadRingBuffer::with_queue_priority(
+                device,
+                None,
+                Some(high_priority_size),
+                D3D12_COMMAND_QUEUE_PRIORITY_HIGH,
+                "High Priority Upload Copy Command Queue",
+            )?,
+            background: UploadRingBuffer::with_queue_priority(
+                device,
+                None,
+                Some(background_size),
+                D3D12_COMMAND_QUEUE_PRIORITY_NORMAL,
+                "Background Upload Copy Command Queue",
+            )?,
+        })
+    }
+
+    pub fn get_mut(&mut self, priority: UploadPriority) -> &mut UploadRingBuffer {
+        match priority {
+            UploadPriority::High => &mut self.high,
+            UploadPriority::Background => &mut self.background,
+        }
+    }
+
+    pub fn allocate(&mut self, priority: UploadPriority, size: usize) -> Result<Upload<'_>> {
+        self.get_mut(priority).allocate(size)
+    }
+
+    pub fn begin_batch(
+        &mut self,
+        priority: UploadPriority,
+        capacity: usize,
+    ) -> Result<UploadBatch<'_>> {
+        self.get_mut(priority).begin_batch(capacity)
+    }
+
+    pub fn clean_up_submissions(&mut self) -> Result<()> {
+        self.high.clean_up_submissions()?;
+        self.background.clean_up_submissions()
+    }
+
+    pub fn stats(&self, priority: UploadPriority) -> PoolStats {
+        match priority {
+            UploadPriority::High => self.high.stats(),
+            UploadPriority::Background => self.background.stats(),
+        }
+    }
+
+    /// Of both rings together
+    pub fn allocation_counts(&self) -> AllocationCounts {
+        self.high.allocation_counts() + self.background.allocation_counts()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ring_usage_wraps() {
+        let mut space = RingSpace::new(1024);
+        assert_eq!(space.used(), 0);
+        assert_eq!(space.reserve(256), Some(0));
+        assert_eq!(space.reserve(512), Some(256));
+        space.release(0, 256).unwrap();
+        assert_eq!(space.used(), 512);
+
+        // Neither fits before the end nor before the tail
+        assert_eq!(space.reserve(384), None);
+        assert_eq!(space.reserve(192), Some(768));
+        // Starts over, the skipped 64 bytes count as used
+        assert_eq!(space.reserve(128), Some(0));
+        assert_eq!(space.used(), 1024 - 256 + 128);
+        assert_eq!(space.reserve(256), None);
+        assert_eq!(space.reserve(128), Some(128));
+        assert_eq!(space.used(), 1024);
+
+        space.release(256, 512).unwrap();
+        space.release(768, 192).unwrap();
+        assert_eq!(space.used(), 64 + 256);
+        space.release(0, 128).unwrap();
+        space.release(128, 128).unwrap();
+        assert_eq!(space.used(), 0);
+        assert_eq!(space.reserve(1024), Some(0));
+    }
+
+    #[test]
+    fn batches_pack_aligned_allocations() {
+        assert_eq!(pack_into_batch(0, 100, 16, 256), Some(0));
+        assert_eq!(pack_into_batch(100, 40, 16, 256), Some(112));
+        assert_eq!(pack_into_batch(152, 100, 16, 256), None);
+        assert_eq!(pack_into_batch(152, 96, 8, 256), Some(152));
+        assert_eq!(pack_into_batch(10, 0, 0, 16), Some(10));
+
+        // Sized so that the same allocations fit exactly
+        let capacity = batch_size([100, 40, 96], 16);
+        assert_eq!(capacity, 112 + 40 + 8 + 96);
+        assert_eq!(pack_into_batch(152, 96, 16, capacity), Some(160));
+    }
+}
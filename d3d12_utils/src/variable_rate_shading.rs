@@ -0,0 +1,136 @@
+use std::ffi::c_void;
+
+use anyhow::{ensure, Result};
+use windows::{core::Interface, Win32::Graphics::Direct3D12::*};
+
+/// What the device can do with variable rate shading, from `D3D12_FEATURE_D3D12_OPTIONS6`
+#[derive(Debug, Clone, Copy)]
+pub struct VariableRateShadingSupport {
+    pub tier: D3D12_VARIABLE_SHADING_RATE_TIER,
+    pub additional_shading_rates: bool,
+    pub tile_size: u32,
+}
+
+impl VariableRateShadingSupport {
+    pub fn query(device: &ID3D12Device4) -> Result<Self> {
+        let mut options = D3D12_FEATURE_DATA_D3D12_OPTIONS6::default();
+        unsafe {
+            device.CheckFeatureSupport(
+                D3D12_FEATURE_D3D12_OPTIONS6,
+                std::ptr::addr_of_mut!(options) as *mut c_void,
+                std::mem::size_of_val(&options) as u32,
+            )?;
+        }
+
+        Ok(Self::from_options(&options))
+    }
+
+    pub fn from_options(options: &D3D12_FEATURE_DATA_D3D12_OPTIONS6) -> Self {
+        Self {
+            tier: options.VariableShadingRateTier,
+            additional_shading_rates: options.AdditionalShadingRatesSupported.as_bool(),
+            tile_size: options.ShadingRateImageTileSize,
+        }
+    }
+
+    /// Tier 1, a single rate for each draw
+    pub fn per_draw(&self) -> bool {
+        self.tier.0 >= D3D12_VARIABLE_SHADING_RATE_TIER_1.0
+    }
+
+    /// Tier 2, a rate per screen tile read from a shading rate image
+    pub fn screen_space(&self) -> bool {
+        self.tier.0 >= D3D12_VARIABLE_SHADING_RATE_TIER_2.0 && self.tile_size > 0
+    }
+
+    /// 2x4, 4x2 and 4x4 are optional, fall back to 2x2 when they aren't there
+    pub fn clamp_rate(&self, rate: D3D12_SHADING_RATE) -> D3D12_SHADING_RATE {
+        match rate {
+            D3D12_SHADING_RATE_2X4 | D3D12_SHADING_RATE_4X2 | D3D12_SHADING_RATE_4X4
+                if !self.additional_shading_rates =>
+            {
+                D3D12_SHADING_RATE_2X2
+            }
+            rate => rate,
+        }
+    }
+
+    /// Size of the shading rate image covering a target of `extent` pixels
+    pub fn shading_rate_image_extent(&self, extent: (u32, u32)) -> (u32, u32) {
+        let (width, height) = extent;
+        let tile_size = self.tile_size.max(1);
+
+        (width.div_ceil(tile_size), height.div_ceil(tile_size))
+    }
+}
+
+/// Sets the rate for the following draws. With a shading rate image bound the coarser of the two
+/// rates wins for every tile. Shading rate images, even unbinding them, need screen space support,
+/// so without it the image has to be None and is left alone.
+pub fn set_shading_rate(
+    command_list: &ID3D12GraphicsCommandList,
+    support: &VariableRateShadingSupport,
+    rate: D3D12_SHADING_RATE,
+    shading_rate_image: Option<&ID3D12Resource>,
+) -> Result<()> {
+    ensure!(
+        support.screen_space() || shading_rate_image.is_none(),
+        "The device has no shading rate images"
+    );
+    let command_list: ID3D12GraphicsCommandList5 = command_list.cast()?;
+    let combiners = [
+        D3D12_SHADING_RATE_COMBINER_PASSTHROUGH,
+        if shading_rate_image.is_some() {
+            D3D12_SHADING_RATE_COMBINER_MAX
+        } else {
+            D3D12_SHADING_RATE_COMBINER_PASSTHROUGH
+        },
+    ];
+
+    unsafe {
+        command_list.RSSetShadingRate(rate, combiners.as_ptr());
+        if support.screen_space() {
+            command_list.RSSetShadingRateImage(shading_rate_image);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn support(additional_shading_rates: bool) -> VariableRateShadingSupport {
+        VariableRateShadingSupport {
+            tier: D3D12_VARIABLE_SHADING_RATE_TIER_2,
+            additional_shading_rates,
+            tile_size: 16,
+        }
+    }
+
+    #[test]
+    fn clamp_additional_rates() {
+        assert_eq!(
+            D3D12_SHADING_RATE_2X2,
+            support(false).clamp_rate(D3D12_SHADING_RATE_4X4)
+        );
+        assert_eq!(
+            D3D12_SHADING_RATE_4X4,
+            support(true).clamp_rate(D3D12_SHADING_RATE_4X4)
+        );
+        assert_eq!(
+            D3D12_SHADING_RATE_1X2,
+            support(false).clamp_rate(D3D12_SHADING_RATE_1X2)
+        );
+    }
+
+    #[test]
+    fn shading_rate_image_extent_rounds_up() {
+        assert_eq!(
+            (80, 45),
+            support(true).shading_rate_image_extent((1280, 720))
+        );
+        assert_eq!((2, 1), support(true).shading_rate_image_extent((17, 16)));
+    }
+}
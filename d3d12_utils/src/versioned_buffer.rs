@@ -0,0 +1,197 @@
+use anyhow::{ensure, Result};
+use windows::Win32::Graphics::{Direct3D12::*, Dxgi::Common::DXGI_SAMPLE_DESC};
+
+use crate::{align_data, Resource, SubResource};
+
+/// The frame about to be recorded and how far the GPU has got, for checking that it is done with
+/// per-frame data before that is overwritten
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameFence {
+    /// Which of the frames in flight it is
+    pub frame_index: usize,
+    /// What the queue signals once the GPU is done with the frame
+    pub fence_value: u64,
+    /// The last value the queue signalled
+    pub completed_fence_value: u64,
+}
+
+// The fence value of the frame that last wrote each copy, and the copy of the frame being recorded
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CopyFences<const FRAME_COUNT: usize> {
+    fence_values: [u64; FRAME_COUNT],
+    recording_frame: Option<usize>,
+}
+
+impl<const FRAME_COUNT: usize> CopyFences<FRAME_COUNT> {
+    fn new() -> Self {
+        Self {
+            fence_values: [0; FRAME_COUNT],
+            recording_frame: None,
+        }
+    }
+
+    fn begin_frame(&mut self, frame: FrameFence) -> Result<()> {
+        ensure!(
+            frame.frame_index < FRAME_COUNT,
+            "Frame index {} out of range, only {} frames in flight",
+            frame.frame_index,
+            FRAME_COUNT
+        );
+        let last_fence_value = self.fence_values[frame.frame_index];
+        ensure!(
+            last_fence_value <= frame.completed_fence_value,
+            "The copy of frame {} is still read by the GPU until fence value {}, it is at {}",
+            frame.frame_index,
+            last_fence_value,
+            frame.completed_fence_value
+        );
+
+        self.fence_values[frame.frame_index] = frame.fence_value;
+        self.recording_frame = Some(frame.frame_index);
+
+        Ok(())
+    }
+
+    fn check_write(&self, frame_index: usize) -> Result<()> {
+        ensure!(
+            self.recording_frame == Some(frame_index),
+            "Writing the copy of frame {} while recording frame {:?}, the GPU may still be reading it",
+            frame_index,
+            self.recording_frame
+        );
+
+        Ok(())
+    }
+}
+
+/// A persistently mapped upload buffer with a copy for every frame in flight, so the CPU only ever
+/// writes the copy of the frame being recorded while the GPU reads the others
+#[derive(Debug)]
+pub struct VersionedBuffer<const FRAME_COUNT: usize> {
+    buffer: Resource,
+    version_size: usize,
+    copy_fences: CopyFences<FRAME_COUNT>,
+}
+
+impl<const FRAME_COUNT: usize> VersionedBuffer<FRAME_COUNT> {
+    /// Each copy is `size` bytes, aligned so it can be used as a constant buffer
+    pub fn new(device: &ID3D12Device4, size: usize) -> Result<Self> {
+        let version_size = align_data(
+            size,
+            D3D12_CONSTANT_BUFFER_DATA_PLACEMENT_ALIGNMENT as usize,
+        );
+
+        let buffer = Resource::create_committed(
+            device,
+            &D3D12_HEAP_PROPERTIES {
+                Type: D3D12_HEAP_TYPE_UPLOAD,
+                ..Default::default()
+            },
+            &D3D12_RESOURCE_DESC {
+                Dimension: D3D12_RESOURCE_DIMENSION_BUFFER,
+                Width: (version_size * FRAME_COUNT) as u64,
+                Height: 1,
+                DepthOrArraySize: 1,
+                MipLevels: 1,
+                SampleDesc: DXGI_SAMPLE_DESC {
+                    Count: 1,
+                    Quality: 0,
+                },
+                Layout: D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
+                ..Default::default()
+            },
+            D3D12_RESOURCE_STATE_GENERIC_READ,
+            None,
+            true,
+        )?;
+
+        Ok(Self {
+            buffer,
+            version_size,
+            copy_fences: CopyFences::new(),
+        })
+    }
+
+    pub fn version_size(&self) -> usize {
+        self.version_size
+    }
+
+    /// Call once the GPU is done with the last frame that wrote the copy of `frame.frame_index`,
+    /// only that copy can be written after. Fails if the fence says it isn't.
+    pub fn begin_frame(&mut self, frame: FrameFence) -> Result<()> {
+        self.copy_fences.begin_frame(frame)
+    }
+
+    pub fn write_for_frame<T: Sized>(&self, frame_index: usize, data: &[T]) -> Result<()> {
+        self.write_for_frame_at_offset(frame_index, 0, data)
+    }
+
+    pub fn write_for_frame_at_offset<T: Sized>(
+        &self,
+        frame_index: usize,
+        offset: usize,
+        data: &[T],
+    ) -> Result<()> {
+        self.copy_fences.check_write(frame_index)?;
+        ensure!(
+            offset + std::mem::size_of_val(data) <= self.version_size,
+            "Write of {} bytes at offset {} overflows a {} byte buffer",
+            std::mem::size_of_val(data),
+            offset,
+            self.version_size
+        );
+
+        self.version(frame_index)?.copy_to_offset_from(offset, data)
+    }
+
+    pub fn version(&self, frame_index: usize) -> Result<SubResource<'_>> {
+        ensure!(frame_index < FRAME_COUNT, "Frame index out of range");
+
+        self.buffer
+            .create_sub_resource(self.version_size, frame_index * self.version_size)
+    }
+
+    /// Every version, for views that select one with an offset
+    pub fn resource(&self) -> &Resource {
+        &self.buffer
+    }
+
+    pub fn gpu_address(&self, frame_index: usize) -> u64 {
+        self.buffer.gpu_address() + (frame_index * self.version_size) as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(frame_index: usize, fence_value: u64, completed_fence_value: u64) -> FrameFence {
+        FrameFence {
+            frame_index,
+            fence_value,
+            completed_fence_value,
+        }
+    }
+
+    #[test]
+    fn copies_are_written_once_their_frame_completed() {
+        let mut fences = CopyFences::<2>::new();
+        fences.begin_frame(frame(0, 1, 0)).unwrap();
+        fences.check_write(0).unwrap();
+        fences.begin_frame(frame(1, 2, 0)).unwrap();
+        fences.begin_frame(frame(0, 3, 1)).unwrap();
+        fences.check_write(0).unwrap();
+    }
+
+    #[test]
+    fn copies_the_gpu_still_reads_are_not_written() {
+        let mut fences = CopyFences::<2>::new();
+        fences.begin_frame(frame(0, 1, 0)).unwrap();
+        fences.begin_frame(frame(1, 2, 0)).unwrap();
+
+        // Copy 0 comes round again before the GPU is done with the frame that wrote it
+        assert!(fences.begin_frame(frame(0, 3, 0)).is_err());
+        assert!(fences.check_write(0).is_err());
+        assert!(fences.begin_frame(frame(2, 3, 2)).is_err());
+    }
+}
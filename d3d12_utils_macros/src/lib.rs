@@ -0,0 +1,95 @@
+//! Macros re-exported by d3d12_utils, see its `embedded_shaders` feature
+
+use std::path::Path;
+
+use hassle_rs::{compile_hlsl, validate_dxil};
+use proc_macro::TokenStream;
+use proc_macro2::{Literal, Span};
+use quote::quote;
+use syn::{parse::Parser, punctuated::Punctuated, Error, LitStr, Token};
+
+/// Compiles an entry point of an HLSL file with DXC while building and expands to a
+/// `d3d12_utils::CompiledShader` holding the DXIL, e.g.
+/// `include_hlsl!("renderer/src/shaders/upscale.hlsl", "VSMain", "vs_6_6")`.
+///
+/// Paths are relative to the directory cargo builds from, the workspace root, and so are the
+/// shader's `#include`s. The crate is rebuilt when the file changes, but not when only a file it
+/// includes does.
+#[proc_macro]
+pub fn include_hlsl(input: TokenStream) -> TokenStream {
+    expand_include_hlsl(input).unwrap_or_else(|error| error.to_compile_error().into())
+}
+
+fn expand_include_hlsl(input: TokenStream) -> syn::Result<TokenStream> {
+    let arguments = Punctuated::<LitStr, Token![,]>::parse_terminated.parse(input)?;
+    let (path, entry_point, target) = match arguments.iter().collect::<Vec<_>>()[..] {
+        [path, entry_point, target] => (path, entry_point, target),
+        _ => {
+            return Err(Error::new(
+                Span::call_site(),
+                "Expected a path, an entry point and a target, e.g. \"shader.hlsl\", \"VSMain\", \"vs_6_6\"",
+            ))
+        }
+    };
+
+    let filename = path.value();
+    let source_path = Path::new(&filename);
+    let source = std::fs::read_to_string(source_path).map_err(|error| {
+        Error::new(
+            path.span(),
+            format!("Failed to read {}: {}", filename, error),
+        )
+    })?;
+    let name = source_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| Error::new(path.span(), "No filename"))?
+        .to_string();
+
+    let byte_code = compile_hlsl(
+        &name,
+        &source,
+        &entry_point.value(),
+        &target.value(),
+        &[],
+        &[],
+    )
+    .map_err(|error| {
+        Error::new(
+            entry_point.span(),
+            format!(
+                "Failed to compile {} of {}: {}",
+                entry_point.value(),
+                filename,
+                error
+            ),
+        )
+    })?;
+    validate_dxil(&byte_code).map_err(|error| {
+        Error::new(
+            entry_point.span(),
+            format!(
+                "{} of {} is invalid: {}",
+                entry_point.value(),
+                filename,
+                error
+            ),
+        )
+    })?;
+
+    // include_bytes of the source makes cargo rebuild when it changes
+    let tracked_path = std::env::current_dir()
+        .map(|directory| directory.join(source_path))
+        .unwrap_or_else(|_| source_path.to_path_buf())
+        .to_string_lossy()
+        .into_owned();
+    let byte_code = Literal::byte_string(&byte_code);
+
+    Ok(quote! {
+        {
+            const _: &[u8] = include_bytes!(#tracked_path);
+            ::d3d12_utils::CompiledShader::from_dxil(#name, #byte_code)
+        }
+    }
+    .into())
+}
@@ -0,0 +1,20 @@
+use anyhow::Result;
+use d3d12_utils::{write_globals_header, write_packing_header};
+
+#[path = "src/shader_types.rs"]
+mod shader_types;
+
+fn main() -> Result<()> {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/shader_types.rs");
+
+    // Shaders compiled while building include the generated headers, which the renderer otherwise
+    // only writes at start up
+    if std::env::var_os("CARGO_FEATURE_EMBEDDED_SHADERS").is_some() {
+        write_packing_header("src/shaders/generated/packing.hlsli")?;
+        write_globals_header("src/shaders/generated/globals.hlsli")?;
+        shader_types::write_shader_types_header("src/shaders/generated/shader_types.hlsli")?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,107 @@
+use glam::{Mat4, Vec2, Vec3};
+use winit::event::{MouseButton, VirtualKeyCode};
+
+use crate::input::{GamepadButton, InputSnapshot};
+use crate::renderer::Camera;
+
+// World units per second
+const MOVE_SPEED: f32 = 2.0;
+const FAST_MOVE_MULTIPLIER: f32 = 4.0;
+// Radians per raw mouse count
+const MOUSE_SENSITIVITY: f32 = 0.003;
+// Radians per second at full stick
+const GAMEPAD_LOOK_SPEED: f32 = 2.5;
+// Looking straight up or down would flip the view
+const MAX_PITCH: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
+
+/// Flies a camera around: WASD and QE or the left stick and triggers move it, dragging with the
+/// right mouse button or the right stick turns it. Shift or the left shoulder moves faster. The
+/// keys don't move while Ctrl is held, which is left for shortcuts.
+#[derive(Debug, Clone, Copy)]
+pub struct CameraController {
+    position: Vec3,
+    yaw: f32,
+    pitch: f32,
+}
+
+impl CameraController {
+    /// Picks up where `camera` is and where it looks
+    pub fn new(camera: &Camera) -> Self {
+        let forward = camera.view().inverse().z_axis.truncate().normalize();
+
+        Self {
+            position: camera.position(),
+            yaw: forward.x.atan2(forward.z),
+            pitch: forward
+                .y
+                .clamp(-1.0, 1.0)
+                .asin()
+                .clamp(-MAX_PITCH, MAX_PITCH),
+        }
+    }
+
+    pub fn forward(&self) -> Vec3 {
+        Vec3::new(
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            self.pitch.cos() * self.yaw.cos(),
+        )
+    }
+
+    /// Moves `camera` by a frame of input, leaving it alone when nothing moved
+    pub fn update(&mut self, camera: &mut Camera, input: &InputSnapshot, delta_time: f32) {
+        let keys = &input.keys;
+        let shortcut =
+            keys.is_held(VirtualKeyCode::LControl) || keys.is_held(VirtualKeyCode::RControl);
+        let axis = |positive: VirtualKeyCode, negative: VirtualKeyCode| {
+            if shortcut {
+                return 0.0;
+            }
+            keys.is_held(positive) as i32 as f32 - keys.is_held(negative) as i32 as f32
+        };
+
+        // x right, y up, z forward
+        let mut movement = Vec3::new(
+            axis(VirtualKeyCode::D, VirtualKeyCode::A),
+            axis(VirtualKeyCode::E, VirtualKeyCode::Q),
+            axis(VirtualKeyCode::W, VirtualKeyCode::S),
+        );
+        let mut turn = Vec2::ZERO;
+        if input.mouse_buttons.is_held(MouseButton::Right) {
+            // Moving the mouse down looks down
+            turn += Vec2::new(input.mouse_delta.x, -input.mouse_delta.y) * MOUSE_SENSITIVITY;
+        }
+        let mut fast = keys.is_held(VirtualKeyCode::LShift) || keys.is_held(VirtualKeyCode::RShift);
+
+        if let Some(gamepad) = &input.gamepad {
+            movement += Vec3::new(
+                gamepad.left_stick.x,
+                gamepad.right_trigger - gamepad.left_trigger,
+                gamepad.left_stick.y,
+            );
+            turn += gamepad.right_stick * GAMEPAD_LOOK_SPEED * delta_time;
+            fast |= gamepad.buttons.is_held(GamepadButton::LeftShoulder);
+        }
+
+        if movement == Vec3::ZERO && turn == Vec2::ZERO {
+            return;
+        }
+
+        self.yaw += turn.x;
+        self.pitch = (self.pitch + turn.y).clamp(-MAX_PITCH, MAX_PITCH);
+
+        let forward = self.forward();
+        let right = Vec3::Y.cross(forward).normalize();
+        let speed = MOVE_SPEED * if fast { FAST_MOVE_MULTIPLIER } else { 1.0 };
+        self.position += (right * movement.x + Vec3::Y * movement.y + forward * movement.z)
+            .clamp_length_max(1.0)
+            * speed
+            * delta_time;
+
+        camera.set_view(Mat4::look_at_lh(
+            self.position,
+            self.position + forward,
+            Vec3::Y,
+        ));
+    }
+}
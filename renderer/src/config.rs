@@ -0,0 +1,143 @@
+use std::path::PathBuf;
+
+use clap::{Parser, ValueEnum};
+use d3d12_utils::{AdapterSelection, HdrImageFormat};
+use winit::dpi::{LogicalSize, PhysicalSize, Size};
+
+use crate::renderer::{FRAME_COUNT, MAXIMUM_FRAME_LATENCY};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Switch {
+    On,
+    Off,
+}
+
+/// What the window keeps when it moves to a monitor with a different DPI
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SizePolicy {
+    /// The window size is in logical pixels, so it covers the same part of any monitor
+    Logical,
+    /// The window size is in physical pixels, so the swap chain keeps its resolution
+    Physical,
+}
+
+/// When frames are drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum RenderMode {
+    /// As fast as the swap chain takes them
+    Continuous,
+    /// Only when something changed, input, the window or the scene. Animations stop in between.
+    OnDemand,
+}
+
+/// Everything the renderer example can be started with
+#[derive(Debug, Clone, Parser)]
+#[command(about = "A bindless D3D12 renderer")]
+pub struct Config {
+    /// Window width, in pixels of the size policy
+    #[arg(long, default_value_t = 1920)]
+    pub width: u32,
+
+    /// Window height, in pixels of the size policy
+    #[arg(long, default_value_t = 1080)]
+    pub height: u32,
+
+    #[arg(long, value_enum, default_value_t = SizePolicy::Logical)]
+    pub size_policy: SizePolicy,
+
+    #[arg(long, value_enum, default_value_t = RenderMode::Continuous)]
+    pub render_mode: RenderMode,
+
+    /// OBJ mesh to show
+    #[arg(long, default_value = "assets/bunny.obj")]
+    pub scene: PathBuf,
+
+    /// Adapter index in DXGI's order, or "warp" for the software rasterizer. Defaults to the first
+    /// hardware adapter that can run the renderer.
+    #[arg(long)]
+    pub adapter: Option<AdapterSelection>,
+
+    /// Off presents as soon as a frame is done instead of waiting for the vertical blank.
+    /// Overrides the settings file.
+    #[arg(long, value_enum)]
+    pub vsync: Option<Switch>,
+
+    /// Swap chain buffers, 3 keeps the GPU busy when a frame misses a refresh with vsync on
+    #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u32).range(2..=3))]
+    pub back_buffers: u32,
+
+    /// Frames the swap chain may queue for presentation before the CPU waits, at most the frames
+    /// in flight the renderer is built with
+    #[arg(
+        long,
+        default_value_t = MAXIMUM_FRAME_LATENCY,
+        value_parser = clap::value_parser!(u32).range(1..=FRAME_COUNT as i64)
+    )]
+    pub max_frame_latency: u32,
+
+    /// Saves the back buffer of this frame, counting from 0, to capture_<N>.dds
+    #[arg(long, value_name = "N")]
+    pub capture_frame: Option<u64>,
+
+    /// File format for dumps of float textures, "exr" or "hdr"
+    #[arg(long, default_value = "exr")]
+    pub hdr_format: HdrImageFormat,
+
+    /// 3D colour lookup table applied to the final image, a .cube file or a 3D DDS texture
+    #[arg(long)]
+    pub color_lut: Option<PathBuf>,
+
+    /// Scene file Ctrl+S saves the objects, lights and camera to and Ctrl+O loads them from
+    #[arg(long, default_value = "scene.json")]
+    pub scene_file: PathBuf,
+
+    /// Writes the final image into the back buffer with a compute shader instead of draws. The
+    /// swap chain is created with unordered access for it.
+    #[arg(long)]
+    pub compute_composite: bool,
+
+    /// Turns the final image for rotated monitors itself in fullscreen, instead of leaving it to
+    /// DXGI, which costs an extra copy every frame
+    #[arg(long)]
+    pub pre_rotate: bool,
+
+    /// Records the scene's draws into bundles once and replays them while the objects and their
+    /// meshes stay the same
+    #[arg(long)]
+    pub bundles: bool,
+
+    /// Leaves the scene's objects hidden behind others out of their draws on the GPU, tested
+    /// against the depth of the previous frame and again against what that let through. The
+    /// scene's draws are recorded without bundles then.
+    #[arg(long)]
+    pub occlusion_culling: bool,
+
+    /// Makes GPU timings comparable between runs: locks the GPU clocks when Windows is in
+    /// developer mode, renders at the full resolution and moves the scene by a fixed step every
+    /// frame
+    #[arg(long)]
+    pub profiling: bool,
+
+    /// Where the report of the adapter, driver, device capabilities and heap and descriptor pool
+    /// sizes is written at start up
+    #[arg(long, default_value = "diagnostics.txt")]
+    pub diagnostics: PathBuf,
+
+    /// Drops the scene's objects onto the ground with rigid body physics
+    #[cfg(feature = "physics")]
+    #[arg(long)]
+    pub physics: bool,
+
+    /// Settings file, loaded at start up and saved on exit
+    #[arg(long, default_value = "settings.toml")]
+    pub settings: PathBuf,
+}
+
+impl Config {
+    pub fn window_size(&self) -> Size {
+        match self.size_policy {
+            SizePolicy::Logical => LogicalSize::new(self.width, self.height).into(),
+            SizePolicy::Physical => PhysicalSize::new(self.width, self.height).into(),
+        }
+    }
+}
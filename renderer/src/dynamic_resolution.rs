@@ -0,0 +1,133 @@
+#[derive(Debug, Clone, Copy)]
+pub enum RenderScaleMode {
+    Fixed(f32),
+    /// Scale is adjusted every frame to keep the measured GPU frame time under the target
+    Automatic {
+        target_frame_time_ms: f32,
+    },
+}
+
+#[derive(Debug)]
+pub struct DynamicResolution {
+    pub mode: RenderScaleMode,
+    pub min_scale: f32,
+    scale: f32,
+}
+
+// How much of the way to the ideal scale to move each frame, keeps the resolution from oscillating
+const SCALE_SMOOTHING: f32 = 0.1;
+// Frame times between this much of the target and the target leave the scale where it is, so it
+// doesn't go up and down around the target with every bit of noise in the GPU timings
+const SCALE_UP_HEADROOM: f32 = 0.85;
+
+impl DynamicResolution {
+    pub fn new(mode: RenderScaleMode) -> Self {
+        let scale = match mode {
+            RenderScaleMode::Fixed(scale) => scale,
+            RenderScaleMode::Automatic { .. } => 1.0,
+        };
+
+        Self {
+            mode,
+            min_scale: 0.5,
+            scale,
+        }
+    }
+
+    pub fn update(&mut self, gpu_frame_time_ms: Option<f32>) -> f32 {
+        self.scale = match self.mode {
+            RenderScaleMode::Fixed(scale) => scale,
+            RenderScaleMode::Automatic {
+                target_frame_time_ms,
+            } => match gpu_frame_time_ms {
+                Some(frame_time_ms)
+                    if frame_time_ms > target_frame_time_ms
+                        || (frame_time_ms > 0.0
+                            && frame_time_ms < target_frame_time_ms * SCALE_UP_HEADROOM) =>
+                {
+                    // GPU time scales roughly with pixel count, i.e. with scale squared
+                    let ideal_scale = self.scale * (target_frame_time_ms / frame_time_ms).sqrt();
+                    self.scale + (ideal_scale - self.scale) * SCALE_SMOOTHING
+                }
+                _ => self.scale,
+            },
+        }
+        .clamp(self.min_scale, 1.0);
+
+        self.scale
+    }
+
+    pub fn render_extent(&self, extent: (u32, u32)) -> (u32, u32) {
+        let (width, height) = extent;
+
+        (
+            u32::max((width as f32 * self.scale) as u32, 1),
+            u32::max((height as f32 * self.scale) as u32, 1),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn automatic() -> DynamicResolution {
+        DynamicResolution::new(RenderScaleMode::Automatic {
+            target_frame_time_ms: 10.0,
+        })
+    }
+
+    #[test]
+    fn slow_frames_lower_the_scale_down_to_the_minimum() {
+        let mut resolution = automatic();
+        let scale = resolution.update(Some(20.0));
+        assert!(scale < 1.0 && scale > 0.9);
+        assert!(resolution.update(Some(20.0)) < scale);
+
+        for _ in 0..100 {
+            resolution.update(Some(40.0));
+        }
+        assert_eq!(resolution.update(Some(40.0)), resolution.min_scale);
+        assert_eq!(resolution.render_extent((1000, 500)), (500, 250));
+    }
+
+    #[test]
+    fn fast_frames_raise_the_scale_up_to_one() {
+        let mut resolution = automatic();
+        for _ in 0..100 {
+            resolution.update(Some(40.0));
+        }
+
+        let scale = resolution.update(Some(5.0));
+        assert!(scale > resolution.min_scale);
+        for _ in 0..100 {
+            resolution.update(Some(1.0));
+        }
+        assert_eq!(resolution.update(Some(1.0)), 1.0);
+        assert_eq!(resolution.render_extent((1000, 500)), (1000, 500));
+    }
+
+    #[test]
+    fn times_just_under_the_target_keep_the_scale() {
+        let mut resolution = automatic();
+        let scale = resolution.update(Some(15.0));
+
+        assert_eq!(resolution.update(Some(10.0)), scale);
+        assert_eq!(resolution.update(Some(9.0)), scale);
+        assert_eq!(resolution.update(None), scale);
+        assert_eq!(resolution.update(Some(0.0)), scale);
+        assert!(resolution.update(Some(8.0)) > scale);
+    }
+
+    #[test]
+    fn fixed_scales_are_clamped() {
+        let mut resolution = DynamicResolution::new(RenderScaleMode::Fixed(0.75));
+        assert_eq!(resolution.update(Some(100.0)), 0.75);
+
+        resolution.mode = RenderScaleMode::Fixed(2.0);
+        assert_eq!(resolution.update(None), 1.0);
+        resolution.mode = RenderScaleMode::Fixed(0.1);
+        assert_eq!(resolution.update(None), resolution.min_scale);
+        assert_eq!(resolution.render_extent((1, 1)), (1, 1));
+    }
+}
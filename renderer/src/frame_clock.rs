@@ -0,0 +1,149 @@
+// How far a single step moves the scene, wall clock time means nothing while paused
+const STEP_DELTA_TIME: f32 = 1.0 / 60.0;
+// Slow motion and fast forward stop at 1/16 and 16 times real time
+const MIN_TIME_SCALE: f32 = 1.0 / 16.0;
+const MAX_TIME_SCALE: f32 = 16.0;
+
+/// Decides whether the scene moves on this frame. Pausing freezes the camera, dynamic resolution
+/// and texture streaming while frames keep being rendered, so the profiler keeps capturing the
+/// same frame and temporal effects can be looked at one step at a time.
+#[derive(Debug)]
+pub struct FrameClock {
+    paused: bool,
+    pending_steps: u32,
+    advanced: bool,
+    frame: u64,
+    time_scale: f32,
+}
+
+impl Default for FrameClock {
+    fn default() -> Self {
+        Self {
+            paused: false,
+            pending_steps: 0,
+            advanced: true,
+            frame: 0,
+            time_scale: 1.0,
+        }
+    }
+}
+
+impl FrameClock {
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.pending_steps = 0;
+    }
+
+    /// Pauses, then lets exactly one more frame through
+    pub fn step(&mut self) {
+        self.paused = true;
+        self.pending_steps += 1;
+    }
+
+    /// Scene frames so far, not counting the ones rendered while paused
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// How much faster than real time the scene runs, steps always move it by the same amount
+    pub fn time_scale(&self) -> f32 {
+        self.time_scale
+    }
+
+    /// Multiplies the time scale by `factor`, within 1/16 and 16 times real time
+    pub fn scale_time(&mut self, factor: f32) {
+        self.time_scale = (self.time_scale * factor).clamp(MIN_TIME_SCALE, MAX_TIME_SCALE);
+    }
+
+    /// Seconds the scene moves on this frame, None when it stays put
+    pub fn advance(&mut self, delta_time: f32) -> Option<f32> {
+        let delta_time = if !self.paused {
+            Some(delta_time * self.time_scale)
+        } else if self.pending_steps > 0 {
+            self.pending_steps -= 1;
+            Some(STEP_DELTA_TIME)
+        } else {
+            None
+        };
+
+        self.advanced = delta_time.is_some();
+        if self.advanced {
+            self.frame += 1;
+        }
+
+        delta_time
+    }
+
+    /// Whether the last `advance` held the scene still
+    pub fn is_frozen(&self) -> bool {
+        !self.advanced
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pausing_holds_the_scene_still() {
+        let mut clock = FrameClock::default();
+        assert_eq!(clock.advance(0.1), Some(0.1));
+        assert!(!clock.is_frozen());
+
+        clock.toggle_pause();
+        assert!(clock.is_paused());
+        assert_eq!(clock.advance(0.1), None);
+        assert_eq!(clock.advance(0.1), None);
+        assert!(clock.is_frozen());
+        assert_eq!(clock.frame(), 1);
+
+        clock.toggle_pause();
+        assert_eq!(clock.advance(0.1), Some(0.1));
+        assert_eq!(clock.frame(), 2);
+    }
+
+    #[test]
+    fn steps_let_exactly_one_frame_through() {
+        let mut clock = FrameClock::default();
+        clock.step();
+        assert!(clock.is_paused());
+        assert_eq!(clock.advance(0.5), Some(STEP_DELTA_TIME));
+        assert_eq!(clock.advance(0.5), None);
+        assert_eq!(clock.frame(), 1);
+
+        clock.step();
+        clock.step();
+        assert_eq!(clock.advance(0.5), Some(STEP_DELTA_TIME));
+        assert_eq!(clock.advance(0.5), Some(STEP_DELTA_TIME));
+        assert_eq!(clock.advance(0.5), None);
+
+        // Resuming drops steps that haven't been taken yet
+        clock.step();
+        clock.toggle_pause();
+        clock.toggle_pause();
+        assert_eq!(clock.advance(0.5), None);
+    }
+
+    #[test]
+    fn time_scale_speeds_up_running_frames_but_not_steps() {
+        let mut clock = FrameClock::default();
+        clock.scale_time(0.5);
+        assert_eq!(clock.advance(0.1), Some(0.05));
+
+        clock.step();
+        assert_eq!(clock.advance(0.1), Some(STEP_DELTA_TIME));
+
+        for _ in 0..10 {
+            clock.scale_time(2.0);
+        }
+        assert_eq!(clock.time_scale(), MAX_TIME_SCALE);
+        for _ in 0..20 {
+            clock.scale_time(0.5);
+        }
+        assert_eq!(clock.time_scale(), MIN_TIME_SCALE);
+    }
+}
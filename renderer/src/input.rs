@@ -0,0 +1,344 @@
+use std::collections::HashSet;
+use std::hash::Hash;
+
+use glam::Vec2;
+use windows::Win32::UI::Input::XboxController::*;
+use winit::event::{
+    DeviceEvent, ElementState, Event, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode,
+    WindowEvent,
+};
+use winit::window::WindowId;
+
+// Pixels of a touchpad scroll that count as one wheel line
+const PIXELS_PER_SCROLL_LINE: f32 = 100.0;
+const GAMEPAD_USER_INDEX: u32 = 0;
+
+/// Buttons that are down, and the ones that went down or up since the last snapshot
+#[derive(Debug, Clone)]
+pub struct ButtonStates<T> {
+    held: HashSet<T>,
+    pressed: HashSet<T>,
+    released: HashSet<T>,
+}
+
+impl<T> Default for ButtonStates<T> {
+    fn default() -> Self {
+        Self {
+            held: HashSet::new(),
+            pressed: HashSet::new(),
+            released: HashSet::new(),
+        }
+    }
+}
+
+impl<T: Copy + Eq + Hash> ButtonStates<T> {
+    /// Held down right now, including the frame it was pressed in
+    pub fn is_held(&self, button: T) -> bool {
+        self.held.contains(&button)
+    }
+
+    /// Went down since the last frame. Key repeats don't count.
+    pub fn was_pressed(&self, button: T) -> bool {
+        self.pressed.contains(&button)
+    }
+
+    #[allow(dead_code)]
+    pub fn was_released(&self, button: T) -> bool {
+        self.released.contains(&button)
+    }
+
+    fn set(&mut self, button: T, down: bool) {
+        if down {
+            if self.held.insert(button) {
+                self.pressed.insert(button);
+            }
+        } else if self.held.remove(&button) {
+            self.released.insert(button);
+        }
+    }
+
+    fn release_all(&mut self) {
+        self.released.extend(self.held.drain());
+    }
+
+    fn clear_edges(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GamepadButton {
+    A,
+    B,
+    X,
+    Y,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+    LeftShoulder,
+    RightShoulder,
+    LeftThumb,
+    RightThumb,
+    Start,
+    Back,
+}
+
+impl GamepadButton {
+    const ALL: [(GamepadButton, u32); 14] = [
+        (GamepadButton::A, XINPUT_GAMEPAD_A),
+        (GamepadButton::B, XINPUT_GAMEPAD_B),
+        (GamepadButton::X, XINPUT_GAMEPAD_X),
+        (GamepadButton::Y, XINPUT_GAMEPAD_Y),
+        (GamepadButton::DPadUp, XINPUT_GAMEPAD_DPAD_UP),
+        (GamepadButton::DPadDown, XINPUT_GAMEPAD_DPAD_DOWN),
+        (GamepadButton::DPadLeft, XINPUT_GAMEPAD_DPAD_LEFT),
+        (GamepadButton::DPadRight, XINPUT_GAMEPAD_DPAD_RIGHT),
+        (GamepadButton::LeftShoulder, XINPUT_GAMEPAD_LEFT_SHOULDER),
+        (GamepadButton::RightShoulder, XINPUT_GAMEPAD_RIGHT_SHOULDER),
+        (GamepadButton::LeftThumb, XINPUT_GAMEPAD_LEFT_THUMB),
+        (GamepadButton::RightThumb, XINPUT_GAMEPAD_RIGHT_THUMB),
+        (GamepadButton::Start, XINPUT_GAMEPAD_START),
+        (GamepadButton::Back, XINPUT_GAMEPAD_BACK),
+    ];
+}
+
+/// The first XInput controller. Sticks go from -1 to 1 with +y up and are zero inside the dead
+/// zone, triggers go from 0 to 1.
+#[derive(Debug, Clone, Default)]
+pub struct Gamepad {
+    pub buttons: ButtonStates<GamepadButton>,
+    pub left_stick: Vec2,
+    pub right_stick: Vec2,
+    pub left_trigger: f32,
+    pub right_trigger: f32,
+}
+
+impl Gamepad {
+    /// None when no controller is connected
+    fn poll(&mut self) -> Option<()> {
+        let mut state = XINPUT_STATE::default();
+        // ERROR_SUCCESS
+        if unsafe { XInputGetState(GAMEPAD_USER_INDEX, &mut state) } != 0 {
+            self.buttons.release_all();
+            return None;
+        }
+
+        let gamepad = state.Gamepad;
+        for (button, mask) in GamepadButton::ALL {
+            self.buttons
+                .set(button, gamepad.wButtons as u32 & mask != 0);
+        }
+        self.left_stick = stick(
+            gamepad.sThumbLX,
+            gamepad.sThumbLY,
+            XINPUT_GAMEPAD_LEFT_THUMB_DEADZONE,
+        );
+        self.right_stick = stick(
+            gamepad.sThumbRX,
+            gamepad.sThumbRY,
+            XINPUT_GAMEPAD_RIGHT_THUMB_DEADZONE,
+        );
+        self.left_trigger = trigger(gamepad.bLeftTrigger);
+        self.right_trigger = trigger(gamepad.bRightTrigger);
+
+        Some(())
+    }
+}
+
+// Radial dead zone, rescaled so the stick still reaches 1 at the edge
+fn stick(x: i16, y: i16, dead_zone: u32) -> Vec2 {
+    let stick = Vec2::new(x as f32, y as f32);
+    let length = stick.length();
+    let dead_zone = dead_zone as f32;
+    if length <= dead_zone {
+        return Vec2::ZERO;
+    }
+
+    let scaled = ((length - dead_zone) / (i16::MAX as f32 - dead_zone)).min(1.0);
+    stick / length * scaled
+}
+
+fn trigger(value: u8) -> f32 {
+    let threshold = XINPUT_GAMEPAD_TRIGGER_THRESHOLD as f32;
+    ((value as f32 - threshold) / (u8::MAX as f32 - threshold)).max(0.0)
+}
+
+/// Everything the keyboard, mouse and gamepad did during one frame
+#[derive(Debug, Clone, Default)]
+pub struct InputSnapshot {
+    pub keys: ButtonStates<VirtualKeyCode>,
+    pub mouse_buttons: ButtonStates<MouseButton>,
+    /// In window pixels
+    pub cursor_position: Vec2,
+    /// From (0, 0) at the top left of the window to (1, 1) at the bottom right
+    pub cursor_uv: Vec2,
+    /// Raw mouse movement, unaffected by pointer acceleration and the window edges
+    pub mouse_delta: Vec2,
+    /// In wheel lines, positive away from the user
+    pub scroll: f32,
+    pub gamepad: Option<Gamepad>,
+}
+
+/// Collects winit events of one window as they come in and hands them out once per frame
+#[derive(Debug)]
+pub struct Input {
+    current: InputSnapshot,
+    gamepad: Gamepad,
+    window_id: WindowId,
+    window_size: Vec2,
+}
+
+impl Input {
+    pub fn new(window_id: WindowId, window_size: (u32, u32)) -> Self {
+        Self {
+            current: InputSnapshot::default(),
+            gamepad: Gamepad::default(),
+            window_id,
+            window_size: Vec2::new(window_size.0 as f32, window_size.1 as f32),
+        }
+    }
+
+    /// Events of other windows are ignored
+    pub fn handle_event<T>(&mut self, event: &Event<T>) {
+        let current = &mut self.current;
+        match event {
+            Event::WindowEvent { window_id, event } if *window_id == self.window_id => {
+                match event {
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state,
+                                virtual_keycode: Some(key),
+                                ..
+                            },
+                        ..
+                    } => current.keys.set(*key, *state == ElementState::Pressed),
+                    WindowEvent::MouseInput { state, button, .. } => current
+                        .mouse_buttons
+                        .set(*button, *state == ElementState::Pressed),
+                    WindowEvent::CursorMoved { position, .. } => {
+                        current.cursor_position = Vec2::new(position.x as f32, position.y as f32);
+                    }
+                    WindowEvent::MouseWheel { delta, .. } => {
+                        current.scroll += match delta {
+                            MouseScrollDelta::LineDelta(_, lines) => *lines,
+                            MouseScrollDelta::PixelDelta(pixels) => {
+                                pixels.y as f32 / PIXELS_PER_SCROLL_LINE
+                            }
+                        };
+                    }
+                    WindowEvent::Resized(size) => {
+                        self.window_size = Vec2::new(size.width as f32, size.height as f32);
+                    }
+                    // Releases never arrive while another window has focus
+                    WindowEvent::Focused(false) => {
+                        current.keys.release_all();
+                        current.mouse_buttons.release_all();
+                    }
+                    _ => (),
+                }
+            }
+            Event::DeviceEvent {
+                event: DeviceEvent::MouseMotion { delta },
+                ..
+            } => {
+                current.mouse_delta += Vec2::new(delta.0 as f32, delta.1 as f32);
+            }
+            _ => (),
+        }
+    }
+
+    /// Everything since the last call, then starts collecting the next frame
+    pub fn snapshot(&mut self) -> InputSnapshot {
+        self.current.cursor_uv = self.current.cursor_position / self.window_size.max(Vec2::ONE);
+        self.current.gamepad = self.gamepad.poll().map(|_| self.gamepad.clone());
+        let snapshot = self.current.clone();
+
+        self.current.keys.clear_edges();
+        self.current.mouse_buttons.clear_edges();
+        self.gamepad.buttons.clear_edges();
+        self.current.mouse_delta = Vec2::ZERO;
+        self.current.scroll = 0.0;
+
+        snapshot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edges_last_until_they_are_cleared() {
+        let mut keys = ButtonStates::default();
+        keys.set(VirtualKeyCode::A, true);
+        assert!(keys.is_held(VirtualKeyCode::A));
+        assert!(keys.was_pressed(VirtualKeyCode::A));
+
+        // Key repeats aren't presses
+        keys.clear_edges();
+        keys.set(VirtualKeyCode::A, true);
+        assert!(keys.is_held(VirtualKeyCode::A));
+        assert!(!keys.was_pressed(VirtualKeyCode::A));
+
+        keys.set(VirtualKeyCode::A, false);
+        assert!(!keys.is_held(VirtualKeyCode::A));
+        assert!(keys.was_released(VirtualKeyCode::A));
+
+        // Releasing something that wasn't held does nothing
+        keys.clear_edges();
+        keys.set(VirtualKeyCode::A, false);
+        assert!(!keys.was_released(VirtualKeyCode::A));
+    }
+
+    #[test]
+    fn pressing_and_releasing_in_one_frame_counts_as_both() {
+        let mut keys = ButtonStates::default();
+        keys.set(VirtualKeyCode::A, true);
+        keys.set(VirtualKeyCode::A, false);
+        assert!(!keys.is_held(VirtualKeyCode::A));
+        assert!(keys.was_pressed(VirtualKeyCode::A));
+        assert!(keys.was_released(VirtualKeyCode::A));
+
+        keys.set(VirtualKeyCode::B, true);
+        keys.clear_edges();
+        keys.release_all();
+        assert!(!keys.is_held(VirtualKeyCode::B));
+        assert!(keys.was_released(VirtualKeyCode::B));
+    }
+
+    #[test]
+    fn sticks_are_zero_inside_the_dead_zone() {
+        let dead_zone = XINPUT_GAMEPAD_LEFT_THUMB_DEADZONE;
+        assert_eq!(stick(0, 0, dead_zone), Vec2::ZERO);
+        assert_eq!(stick(dead_zone as i16, 0, dead_zone), Vec2::ZERO);
+        assert_eq!(stick(0, -(dead_zone as i16), dead_zone), Vec2::ZERO);
+
+        let just_outside = stick(dead_zone as i16 + 100, 0, dead_zone);
+        assert!(just_outside.x > 0.0 && just_outside.x < 0.02);
+        assert_eq!(just_outside.y, 0.0);
+    }
+
+    #[test]
+    fn sticks_reach_one_at_the_edge() {
+        let dead_zone = XINPUT_GAMEPAD_LEFT_THUMB_DEADZONE;
+        assert_eq!(stick(i16::MAX, 0, dead_zone), Vec2::X);
+        assert_eq!(stick(0, i16::MIN, dead_zone), Vec2::NEG_Y);
+
+        // Corners are further out than the edge, they still stop at 1
+        let corner = stick(i16::MAX, i16::MAX, dead_zone);
+        assert!((corner.length() - 1.0).abs() < 1e-6);
+        assert!((corner.x - corner.y).abs() < 1e-6);
+    }
+
+    #[test]
+    fn triggers_scale_from_the_threshold_to_one() {
+        let threshold = XINPUT_GAMEPAD_TRIGGER_THRESHOLD as u8;
+        assert_eq!(trigger(0), 0.0);
+        assert_eq!(trigger(threshold), 0.0);
+        assert!(trigger(threshold + 1) > 0.0);
+        assert_eq!(trigger(u8::MAX), 1.0);
+    }
+}
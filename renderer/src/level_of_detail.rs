@@ -0,0 +1,47 @@
+use d3d12_utils::{screen_coverage, select_lod, MeshManager};
+
+use crate::{object::Object, transform_cache::TransformCache, view::View};
+
+// Screen coverage below which the next coarser level of detail is drawn
+const LOD_COVERAGE_THRESHOLDS: [f32; 2] = [0.4, 0.15];
+const LOD_HYSTERESIS: f32 = 0.1;
+
+/// Picks the level of detail of every object from how much of the screen it covers in the view
+/// that sees it largest
+pub fn update_lods(
+    objects: &mut [Object],
+    transform_cache: &TransformCache,
+    views: &[View],
+    mesh_manager: &MeshManager,
+) {
+    for (index, object) in objects.iter_mut().enumerate() {
+        let num_lods = mesh_manager.num_lods(&object.mesh);
+        let (bounds, transform) = match (object.mesh.bounds, transform_cache.get(index)) {
+            (Some(bounds), Some(transform)) if num_lods > 1 => (bounds, transform),
+            _ => continue,
+        };
+
+        let scale = transform
+            .x_axis
+            .truncate()
+            .length()
+            .max(transform.y_axis.truncate().length())
+            .max(transform.z_axis.truncate().length());
+        let radius = (bounds.max - bounds.min).length() * 0.5 * scale;
+        let center = transform.transform_point3(bounds.center());
+
+        let coverage = views
+            .iter()
+            .map(|view| {
+                screen_coverage(
+                    radius,
+                    view.camera.view_depth(center),
+                    view.camera.projection_scale_y(),
+                )
+            })
+            .fold(0.0, f32::max);
+        let thresholds =
+            &LOD_COVERAGE_THRESHOLDS[..(num_lods - 1).min(LOD_COVERAGE_THRESHOLDS.len())];
+        object.lod = select_lod(coverage, object.lod, thresholds, LOD_HYSTERESIS);
+    }
+}
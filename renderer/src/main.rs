@@ -1,25 +1,233 @@
+use std::time::{Duration, Instant};
+
+use clap::Parser;
+use d3d12_utils::{DeviceRemovedError, GizmoMode};
 use windows::Win32::{Foundation::HWND, Graphics::Dxgi::*};
 use winit::{
-    dpi::{LogicalSize, PhysicalSize},
-    event::{Event, WindowEvent},
+    dpi::PhysicalSize,
+    event::{Event, MouseButton, VirtualKeyCode, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
     platform::windows::WindowExtWindows,
-    window::WindowBuilder,
+    window::{Fullscreen, Window, WindowBuilder},
 };
 
 mod renderer;
 use renderer::Application;
 
+mod input;
+use input::{Input, InputSnapshot};
+
+mod config;
+use config::{Config, RenderMode, SizePolicy, Switch};
+
+mod settings;
+use settings::Settings;
+
+mod camera_controller;
+mod dynamic_resolution;
+mod frame_clock;
+mod level_of_detail;
+mod material;
 mod object;
+#[cfg(feature = "physics")]
+mod physics;
 mod render_pass;
+mod scene_file;
+mod shader_types;
+mod texture_streaming;
+mod transform_cache;
+mod video_recorder;
+mod view;
+mod visibility;
+
+/// How often an occluded window checks whether it can be seen again
+const OCCLUDED_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+const RECORDING_PATH: &str = "recording.mp4";
+const RECORDING_FRAMES_PER_SECOND: u32 = 60;
+const WINDOW_TITLE: &str = "rust_d3d12";
+
+/// Borderless fullscreen on the monitor the window is on, or back to a window. The window gets
+/// resized to the monitor resolution, which resizes the swap chain.
+fn toggle_borderless(window: &Window) {
+    let fullscreen = match window.fullscreen() {
+        Some(_) => None,
+        None => Some(Fullscreen::Borderless(window.current_monitor())),
+    };
+    window.set_fullscreen(fullscreen);
+}
+
+/// Hotkeys for the renderer's debug features, and picking with the left mouse button. 1, 2 and 3
+/// switch the gizmo on the picked object between moving, rotating and scaling. Control with [ and ]
+/// halves and doubles how fast the scene runs.
+fn debug_controls(
+    application: &mut Application,
+    window: &Window,
+    input: &InputSnapshot,
+    config: &Config,
+) {
+    let keys = &input.keys;
+    let control = keys.is_held(VirtualKeyCode::LControl) || keys.is_held(VirtualKeyCode::RControl);
+    if control && keys.was_pressed(VirtualKeyCode::S) {
+        match application.save_scene(&config.scene_file) {
+            Ok(()) => println!("Saved the scene to {}", config.scene_file.display()),
+            Err(err) => eprintln!("Saving the scene failed: {:?}", err),
+        }
+    }
+    if control && keys.was_pressed(VirtualKeyCode::O) {
+        match application.load_scene(&config.scene_file) {
+            Ok(()) => println!("Loaded the scene from {}", config.scene_file.display()),
+            Err(err) => eprintln!("Loading the scene failed: {:?}", err),
+        }
+    }
+    if control && keys.was_pressed(VirtualKeyCode::P) {
+        match application.frame_profile() {
+            Ok(profile) => println!("{}", profile),
+            Err(err) => eprintln!("Frame profile failed: {:?}", err),
+        }
+    }
+    if control && keys.was_pressed(VirtualKeyCode::T) {
+        match application.texture_usage() {
+            Ok(usage) => println!("{}", usage),
+            Err(err) => eprintln!("Texture usage failed: {:?}", err),
+        }
+    }
+    if control && keys.was_pressed(VirtualKeyCode::E) {
+        application
+            .toggle_auto_exposure()
+            .expect("Toggling auto exposure");
+    }
+    if control && keys.was_pressed(VirtualKeyCode::D) {
+        application
+            .toggle_depth_of_field()
+            .expect("Toggling depth of field");
+    }
+    if control && keys.was_pressed(VirtualKeyCode::M) {
+        match application.step_morph_weight() {
+            Ok(Some(weight)) => println!("Morph weight {:.2}", weight),
+            Ok(None) => println!("Nothing selected"),
+            Err(err) => eprintln!("Morphing failed: {:?}", err),
+        }
+    }
+    if control && keys.was_pressed(VirtualKeyCode::G) {
+        application
+            .toggle_profiler_graphs()
+            .expect("Toggling profiler graphs");
+    }
+    if control && keys.was_pressed(VirtualKeyCode::F) {
+        match application.focus_at(input.cursor_uv) {
+            Ok(Some(distance)) => println!("Focused at {:.2}", distance),
+            Ok(None) => println!("Nothing to focus on"),
+            Err(err) => eprintln!("Focusing failed: {:?}", err),
+        }
+    }
+    for (key, mode) in [
+        (VirtualKeyCode::Key1, GizmoMode::Translate),
+        (VirtualKeyCode::Key2, GizmoMode::Rotate),
+        (VirtualKeyCode::Key3, GizmoMode::Scale),
+    ] {
+        if keys.was_pressed(key) {
+            application
+                .set_gizmo_mode(mode)
+                .expect("Switching the gizmo mode");
+        }
+    }
+    if keys.was_pressed(VirtualKeyCode::F1) {
+        match application.present_statistics() {
+            Ok(Some(statistics)) => println!("{}", statistics),
+            Ok(None) => println!("No present statistics yet"),
+            Err(err) => eprintln!("Present statistics failed: {:?}", err),
+        }
+    }
+    if keys.was_pressed(VirtualKeyCode::F8) {
+        toggle_borderless(window);
+    }
+    if keys.was_pressed(VirtualKeyCode::F9) {
+        let res = if application.is_recording() {
+            application.stop_recording()
+        } else {
+            application.start_recording(
+                std::path::Path::new(RECORDING_PATH),
+                RECORDING_FRAMES_PER_SECOND,
+            )
+        };
+        if let Err(err) = res {
+            eprintln!("Recording failed: {:?}", err);
+        }
+    }
+    if keys.was_pressed(VirtualKeyCode::F2) {
+        application
+            .toggle_split_screen()
+            .expect("Toggling split screen");
+    }
+    if keys.was_pressed(VirtualKeyCode::F3) {
+        application.toggle_grid().expect("Toggling grid");
+    }
+    if keys.was_pressed(VirtualKeyCode::F4) {
+        application
+            .bake_environment_probe()
+            .expect("Baking environment probe");
+    }
+    if keys.was_pressed(VirtualKeyCode::F5) {
+        application.toggle_pause().expect("Pausing");
+    }
+    if keys.was_pressed(VirtualKeyCode::F6) {
+        application.step_frame().expect("Stepping a frame");
+    }
+    if control && keys.was_pressed(VirtualKeyCode::LBracket) {
+        application.scale_time(0.5).expect("Slowing down time");
+    }
+    if control && keys.was_pressed(VirtualKeyCode::RBracket) {
+        application.scale_time(2.0).expect("Speeding up time");
+    }
+    if keys.was_pressed(VirtualKeyCode::F7) {
+        application
+            .toggle_memory_hud()
+            .expect("Toggling the memory HUD");
+    }
+    if keys.was_pressed(VirtualKeyCode::F10) {
+        application
+            .move_to_next_output()
+            .expect("Moving to the next monitor");
+    }
+    if keys.was_pressed(VirtualKeyCode::F11) {
+        application
+            .toggle_fullscreen()
+            .expect("Toggling fullscreen");
+    }
+    if keys.was_pressed(VirtualKeyCode::F12) {
+        application.dump_textures().expect("Dumping textures");
+    }
+    if input.mouse_buttons.was_pressed(MouseButton::Left) {
+        application.mark_picked_surface(input.cursor_uv);
+    }
+}
+
+fn render_frame(application: &mut Application) {
+    if let Err(err) = application.render() {
+        if let Some(removed) = err.downcast_ref::<DeviceRemovedError>() {
+            panic!("{}", removed);
+        }
+    }
+}
 
 fn main() {
+    let config = Config::parse();
+    let saved_settings = Settings::load(&config.settings).unwrap_or_else(|err| {
+        eprintln!("Using the default settings: {:?}", err);
+        Settings::default()
+    });
+    // Command line overrides only last for this run, only what changes while running is saved
+    let mut settings = saved_settings.clone();
+    if let Some(vsync) = config.vsync {
+        settings.vsync = vsync == Switch::On;
+    }
+    let initial_settings = settings.clone();
+
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new()
-        .with_inner_size(LogicalSize {
-            width: 1920,
-            height: 1080,
-        })
+        .with_title(WINDOW_TITLE)
+        .with_inner_size(config.window_size())
         .build(&event_loop)
         .unwrap();
 
@@ -29,68 +237,160 @@ fn main() {
         mut width,
         mut height,
     } = window.inner_size();
-    let mut application = Application::new(hwnd, (width, height)).unwrap();
+    let mut application = Application::new(hwnd, (width, height), &config, settings).unwrap();
     let mut is_closing = false;
+    let mut is_minimized = false;
+    let mut input = Input::new(window.id(), (width, height));
+    let mut last_frame = Instant::now();
+    // Nothing moves while the loop waits for events, so the time spent waiting is skipped
+    let mut waited = false;
+    let mut clock_status = None;
 
     event_loop.run(move |event, _, control_flow| {
-        *control_flow = ControlFlow::Wait;
-
+        input.handle_event(&event);
         match event {
-            Event::WindowEvent { window_id, event } if window_id == window.id() => match event {
-                WindowEvent::CloseRequested => {
-                    is_closing = true;
-
-                    if cfg!(debug_assertions) {
-                        if let Ok(debug_interface) =
-                            unsafe { DXGIGetDebugInterface1::<IDXGIDebug1>(0) }
-                        {
-                            unsafe {
-                                debug_interface
-                                    .ReportLiveObjects(
-                                        DXGI_DEBUG_ALL,
-                                        DXGI_DEBUG_RLO_DETAIL | DXGI_DEBUG_RLO_IGNORE_INTERNAL,
-                                    )
-                                    .expect("Report live objects")
-                            };
+            // Input, resizes and focus changes can all change what is on screen
+            Event::WindowEvent { window_id, event } if window_id == window.id() => {
+                application.request_redraw();
+
+                match event {
+                    WindowEvent::CloseRequested => {
+                        is_closing = true;
+
+                        if cfg!(debug_assertions) {
+                            if let Ok(debug_interface) =
+                                unsafe { DXGIGetDebugInterface1::<IDXGIDebug1>(0) }
+                            {
+                                unsafe {
+                                    debug_interface
+                                        .ReportLiveObjects(
+                                            DXGI_DEBUG_ALL,
+                                            DXGI_DEBUG_RLO_DETAIL | DXGI_DEBUG_RLO_IGNORE_INTERNAL,
+                                        )
+                                        .expect("Report live objects")
+                                };
+                            }
+                        }
+
+                        if application.is_recording() {
+                            application
+                                .stop_recording()
+                                .expect("Finishing the recording");
+                        }
+                        if let Err(err) = application.settings().and_then(|settings| {
+                            saved_settings
+                                .with_changes(&initial_settings, settings)
+                                .save(&config.settings)
+                        }) {
+                            eprintln!("Saving the settings failed: {:?}", err);
                         }
+                        application.wait_for_idle().unwrap();
+                        application = Application::null();
+                        *control_flow = ControlFlow::Exit
                     }
+                    // winit makes the process per-monitor DPI aware, so this comes in whenever the
+                    // window moves to a monitor with a different scale
+                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                        if config.size_policy == SizePolicy::Physical {
+                            *new_inner_size = PhysicalSize::new(width, height);
+                        }
 
-                    application.wait_for_idle().unwrap();
-                    application = Application::null();
-                    *control_flow = ControlFlow::Exit
-                }
-                WindowEvent::Resized(PhysicalSize {
-                    width: w,
-                    height: h,
-                }) => {
-                    if w != width || h != height {
-                        application
-                            .resize((width, height))
-                            .expect("Resizing should not fail");
-
-                        width = w;
-                        height = h;
+                        let PhysicalSize {
+                            width: w,
+                            height: h,
+                        } = *new_inner_size;
+                        if w != width || h != height {
+                            application
+                                .resize((w, h))
+                                .expect("Resizing should not fail");
+
+                            width = w;
+                            height = h;
+                        }
+                    }
+                    WindowEvent::Resized(PhysicalSize {
+                        width: w,
+                        height: h,
+                    }) => {
+                        // Minimizing shrinks the window to nothing, the swap chain keeps its size
+                        is_minimized = w == 0 || h == 0;
+                        if !is_minimized && (w != width || h != height) {
+                            application
+                                .resize((w, h))
+                                .expect("Resizing should not fail");
+
+                            width = w;
+                            height = h;
+                        }
                     }
+                    _ => (),
                 }
-                _ => (),
-            },
+            }
             Event::MainEventsCleared => {
                 if !is_closing {
-                    let res = application.render();
-                    if res.is_err() && application.renderer.is_some() {
-                        unsafe {
-                            application
-                                .renderer
-                                .as_ref()
-                                .unwrap()
-                                .resources
-                                .device
-                                .GetDeviceRemovedReason()
-                                .unwrap()
-                        };
+                    let now = Instant::now();
+                    let delta_time = if waited {
+                        0.0
+                    } else {
+                        (now - last_frame).as_secs_f32()
+                    };
+                    last_frame = now;
+
+                    let snapshot = input.snapshot();
+                    application
+                        .update(&snapshot, delta_time)
+                        .expect("Moving the camera");
+                    debug_controls(&mut application, &window, &snapshot, &config);
+
+                    let status = application.clock_status();
+                    if status != clock_status {
+                        window.set_title(&match &status {
+                            Some(status) => format!("{} - {}", WINDOW_TITLE, status),
+                            None => WINDOW_TITLE.to_string(),
+                        });
+                        clock_status = status;
                     }
+
+                    let frame_wanted = match config.render_mode {
+                        RenderMode::Continuous => {
+                            if !is_minimized {
+                                render_frame(&mut application);
+                            }
+                            true
+                        }
+                        // Drawn on the RedrawRequested that follows
+                        RenderMode::OnDemand => {
+                            let frame_wanted = application.take_redraw_request();
+                            if frame_wanted && !is_minimized {
+                                window.request_redraw();
+                            }
+                            frame_wanted
+                        }
+                    };
+
+                    // Held keys and sticks move the camera without any events coming in, but there
+                    // is nothing to move while the window can't be seen. On demand, something
+                    // that changed this frame probably changes again in the next one.
+                    *control_flow = if is_minimized {
+                        ControlFlow::Wait
+                    } else if application.is_occluded() {
+                        ControlFlow::WaitUntil(Instant::now() + OCCLUDED_POLL_INTERVAL)
+                    } else if frame_wanted {
+                        ControlFlow::Poll
+                    } else {
+                        ControlFlow::Wait
+                    };
+                    waited = *control_flow == ControlFlow::Wait;
                 }
             }
+            Event::RedrawRequested(window_id)
+                if window_id == window.id()
+                    && config.render_mode == RenderMode::OnDemand
+                    && !is_closing
+                    && !is_minimized =>
+            {
+                render_frame(&mut application);
+            }
             _ => (),
         };
     });
@@ -0,0 +1,46 @@
+use anyhow::Result;
+use d3d12_utils::{TextureAtlas, TextureHandle};
+use glam::{Vec2, Vec3, Vec4};
+
+#[derive(Debug, Clone)]
+pub struct Material {
+    pub texture: TextureHandle,
+    pub uv_offset: Vec2,
+    pub uv_scale: Vec2,
+    /// Sampler feedback map written when sampling `texture`
+    pub feedback_index: Option<u32>,
+    /// Finest mip that may be sampled
+    pub min_lod: f32,
+    /// Multiplies the texture colour
+    pub base_color_factor: Vec4,
+    /// Added on top of the lit colour
+    pub emissive_factor: Vec3,
+    /// From 0 for a mirror to 1, picks how blurry a mip of the environment probe is reflected
+    pub roughness: f32,
+}
+
+impl Material {
+    pub fn from_texture(texture: TextureHandle) -> Self {
+        Self {
+            texture,
+            uv_offset: Vec2::ZERO,
+            uv_scale: Vec2::ONE,
+            feedback_index: None,
+            min_lod: 0.0,
+            base_color_factor: Vec4::ONE,
+            emissive_factor: Vec3::ZERO,
+            roughness: 1.0,
+        }
+    }
+
+    /// Samples the image `image_id` of `atlas`, through the UV transform of its region
+    pub fn from_atlas_region(atlas: &TextureAtlas, image_id: usize) -> Result<Self> {
+        let (page, region) = atlas.get(image_id)?;
+
+        Ok(Self {
+            uv_offset: region.uv_offset,
+            uv_scale: region.uv_scale,
+            ..Self::from_texture(page.clone())
+        })
+    }
+}
@@ -1,9 +1,42 @@
-use d3d12_utils::{MeshHandle, TextureHandle};
-use glam::Vec3;
+use d3d12_utils::MeshHandle;
+use glam::{Quat, Vec3};
+
+use crate::material::Material;
 
 #[derive(Debug)]
 pub struct Object {
+    /// Relative to the parent, if there is one
     pub position: Vec3,
-    pub texture: TextureHandle,
+    pub rotation: Quat,
+    /// Along the object's own axes, before it is rotated
+    pub scale: Vec3,
+    /// Index of the parent in the scene's objects
+    pub parent: Option<usize>,
+    pub material: Material,
     pub mesh: MeshHandle,
+    /// Level of detail of `mesh` that gets drawn
+    pub lod: usize,
+    /// Radians per second the object turns about its parent's y axis
+    pub spin: f32,
+    /// One for each morph target of `mesh`, from 0 for none of it to 1 for all of it
+    pub morph_weights: Vec<f32>,
+}
+
+impl Object {
+    pub fn local_transform(&self) -> glam::Mat4 {
+        glam::Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.position)
+    }
+
+    /// Whether any of its morph targets are blended in
+    pub fn is_morphed(&self) -> bool {
+        self.morph_weights.iter().any(|&weight| weight != 0.0)
+    }
+
+    /// Moves the object on by one simulation step
+    pub fn simulate(&mut self, delta_time: f32) {
+        if self.spin != 0.0 {
+            self.rotation =
+                (Quat::from_rotation_y(self.spin * delta_time) * self.rotation).normalize();
+        }
+    }
 }
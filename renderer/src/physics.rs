@@ -0,0 +1,200 @@
+use anyhow::{ensure, Context, Result};
+use d3d12_utils::MeshManager;
+use glam::{Quat, Vec3};
+use rapier3d::{na as nalgebra, prelude::*};
+
+use crate::object::Object;
+
+/// What a collider is built from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColliderShape {
+    /// The mesh's bounds
+    Aabb,
+    /// The convex hull of the CPU copy of the mesh, see `MeshManager::retain_cpu_copies`
+    ConvexHull,
+}
+
+/// Rigid bodies for some of the scene's objects, stepped along with the fixed simulation steps.
+/// Bodies are placed at the origin of their objects, which only works for objects without a
+/// parent. Objects with a dynamic body follow it instead of their spin.
+pub struct Physics {
+    gravity: Vector<Real>,
+    integration_parameters: IntegrationParameters,
+    pipeline: PhysicsPipeline,
+    islands: IslandManager,
+    broad_phase: BroadPhase,
+    narrow_phase: NarrowPhase,
+    bodies: RigidBodySet,
+    colliders: ColliderSet,
+    impulse_joints: ImpulseJointSet,
+    multibody_joints: MultibodyJointSet,
+    ccd_solver: CCDSolver,
+    // Object index of every body
+    object_bodies: Vec<(usize, RigidBodyHandle)>,
+}
+
+impl std::fmt::Debug for Physics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Physics")
+            .field("bodies", &self.bodies.len())
+            .field("colliders", &self.colliders.len())
+            .finish()
+    }
+}
+
+impl Physics {
+    /// `step` is the seconds every `step` call simulates
+    pub fn new(step: f32) -> Self {
+        Self {
+            gravity: vector![0.0, -9.81, 0.0],
+            integration_parameters: IntegrationParameters {
+                dt: step,
+                ..Default::default()
+            },
+            pipeline: PhysicsPipeline::new(),
+            islands: IslandManager::new(),
+            broad_phase: BroadPhase::new(),
+            narrow_phase: NarrowPhase::new(),
+            bodies: RigidBodySet::new(),
+            colliders: ColliderSet::new(),
+            impulse_joints: ImpulseJointSet::new(),
+            multibody_joints: MultibodyJointSet::new(),
+            ccd_solver: CCDSolver::new(),
+            object_bodies: Vec::new(),
+        }
+    }
+
+    /// A fixed floor at `height`, endless in x and z
+    pub fn add_ground(&mut self, height: f32) {
+        self.colliders.insert(
+            ColliderBuilder::halfspace(Vector::y_axis())
+                .translation(vector![0.0, height, 0.0])
+                .build(),
+        );
+    }
+
+    /// Gives object `index` a body, dynamic ones fall and collide, fixed ones only get in the way.
+    /// The collider is scaled with the object as it is now.
+    pub fn add_object(
+        &mut self,
+        index: usize,
+        object: &Object,
+        mesh_manager: &MeshManager,
+        shape: ColliderShape,
+        dynamic: bool,
+    ) -> Result<()> {
+        ensure!(
+            object.parent.is_none(),
+            "Object {} has a parent, only root objects can have bodies",
+            index
+        );
+        ensure!(
+            !self.object_bodies.iter().any(|(other, _)| *other == index),
+            "Object {} already has a body",
+            index
+        );
+
+        let collider = match shape {
+            ColliderShape::Aabb => {
+                let bounds = object
+                    .mesh
+                    .bounds
+                    .with_context(|| format!("The mesh of object {} has no bounds", index))?;
+                let half_extent = (bounds.max - bounds.min) * 0.5 * object.scale;
+                let center = bounds.center() * object.scale;
+                ColliderBuilder::cuboid(half_extent.x, half_extent.y, half_extent.z)
+                    .translation(vector![center.x, center.y, center.z])
+            }
+            ColliderShape::ConvexHull => {
+                let mesh = mesh_manager.get_cpu_mesh(&object.mesh).with_context(|| {
+                    format!(
+                        "The mesh of object {} has no CPU copy to build a hull of",
+                        index
+                    )
+                })?;
+                let points: Vec<Point<Real>> = mesh
+                    .positions
+                    .iter()
+                    .map(|position| {
+                        let position = *position * object.scale;
+                        point![position.x, position.y, position.z]
+                    })
+                    .collect();
+                ColliderBuilder::convex_hull(&points)
+                    .with_context(|| format!("The mesh of object {} has no convex hull", index))?
+            }
+        };
+
+        let body = if dynamic {
+            RigidBodyBuilder::dynamic()
+        } else {
+            RigidBodyBuilder::fixed()
+        }
+        .position(isometry(object.position, object.rotation))
+        .build();
+        let handle = self.bodies.insert(body);
+        self.colliders
+            .insert_with_parent(collider.build(), handle, &mut self.bodies);
+        self.object_bodies.push((index, handle));
+
+        Ok(())
+    }
+
+    /// Puts the body of object `index` where the object is, e.g. after it was dragged somewhere.
+    /// Does nothing for objects without a body.
+    pub fn teleport(&mut self, index: usize, object: &Object) {
+        let handle = self
+            .object_bodies
+            .iter()
+            .find(|(other, _)| *other == index)
+            .map(|(_, handle)| *handle);
+        if let Some(body) = handle.and_then(|handle| self.bodies.get_mut(handle)) {
+            body.set_position(isometry(object.position, object.rotation), true);
+            body.set_linvel(Vector::zeros(), true);
+            body.set_angvel(Vector::zeros(), true);
+        }
+    }
+
+    /// Simulates one step and moves the objects of dynamic bodies to where their bodies are
+    pub fn step(&mut self, objects: &mut [Object]) {
+        self.pipeline.step(
+            &self.gravity,
+            &self.integration_parameters,
+            &mut self.islands,
+            &mut self.broad_phase,
+            &mut self.narrow_phase,
+            &mut self.bodies,
+            &mut self.colliders,
+            &mut self.impulse_joints,
+            &mut self.multibody_joints,
+            &mut self.ccd_solver,
+            None,
+            &(),
+            &(),
+        );
+
+        for (index, handle) in &self.object_bodies {
+            let (Some(object), Some(body)) = (objects.get_mut(*index), self.bodies.get(*handle))
+            else {
+                continue;
+            };
+            if !body.is_dynamic() {
+                continue;
+            }
+
+            let translation = body.translation();
+            let rotation = body.rotation().coords;
+            object.position = Vec3::new(translation.x, translation.y, translation.z);
+            object.rotation = Quat::from_xyzw(rotation.x, rotation.y, rotation.z, rotation.w);
+        }
+    }
+}
+
+fn isometry(position: Vec3, rotation: Quat) -> Isometry<Real> {
+    Isometry::from_parts(
+        Translation::new(position.x, position.y, position.z),
+        Rotation::from_quaternion(nalgebra::Quaternion::new(
+            rotation.w, rotation.x, rotation.y, rotation.z,
+        )),
+    )
+}
@@ -1 +1,21 @@
+pub mod auto_exposure_pass;
 pub mod bindless_texture_pass;
+pub mod color_grading_pass;
+pub mod composite_pass;
+pub mod compute_composite_pass;
+pub mod debug_line_pass;
+pub mod depth_of_field_pass;
+pub mod depth_readback_pass;
+pub mod environment_probe_pass;
+pub mod grid_pass;
+pub mod hi_z_pass;
+pub mod light_culling_pass;
+pub mod memory_hud_pass;
+pub mod morph_target_pass;
+pub mod motion_blur_pass;
+pub mod occlusion_culling_pass;
+pub mod profiler_graph_pass;
+pub mod shading_rate_pass;
+pub mod texture_dump_pass;
+pub mod upscale_pass;
+pub mod water_pass;
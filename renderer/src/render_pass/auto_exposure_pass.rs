@@ -0,0 +1,179 @@
+use anyhow::Result;
+use d3d12_utils::{
+    create_compute_pipeline_state, create_pass_root_signature, load_hlsl, BarrierBatcher,
+    DescriptorHandle, DescriptorType, RenderTarget, Resource, GLOBAL_CONSTANTS_PARAMETER,
+};
+use windows::Win32::Graphics::Direct3D12::*;
+
+use crate::{
+    render_pass::light_culling_pass::{
+        create_buffer, create_structured_srv, create_structured_uav,
+    },
+    renderer::Resources,
+    settings::AutoExposure,
+};
+
+// Matches HISTOGRAM_BINS in auto_exposure.hlsl, the histogram's thread groups are 8 by 8
+const HISTOGRAM_BINS: usize = 64;
+const THREAD_GROUP_SIZE: u32 = 8;
+// Log2 luminances the histogram covers, the scene is in display range so nothing is above 1
+const MIN_LOG_LUMINANCE: f32 = -12.0;
+const LOG_LUMINANCE_RANGE: f32 = 12.0;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct AutoExposureConstants {
+    pub scene_index: u32,
+    pub histogram_index: u32,
+    pub exposure_index: u32,
+    pub use_manual_exposure: u32,
+    pub render_extent: [u32; 2],
+    pub min_log_luminance: f32,
+    pub log_luminance_range: f32,
+    pub min_exposure: f32,
+    pub max_exposure: f32,
+    pub manual_exposure: f32,
+    pub speed_up: f32,
+    pub speed_down: f32,
+    pub padding: [u32; 3],
+}
+
+/// Measures the luminance of the scene in a histogram and moves the exposure towards the one
+/// that brings its average to middle grey, a bit every frame like eyes adapting. The exposure,
+/// in stops, stays on the GPU for colour grading to read.
+#[derive(Debug)]
+pub struct AutoExposurePass {
+    root_signature: ID3D12RootSignature,
+    histogram_pso: ID3D12PipelineState,
+    adapt_pso: ID3D12PipelineState,
+    // Emptied again by the adapt dispatch. Buffers decay to common after every submission, so
+    // both start each frame in the common state.
+    histogram: Resource,
+    histogram_uav: DescriptorHandle,
+    // Kept from frame to frame, starts at 0 stops
+    exposure: Resource,
+    exposure_uav: DescriptorHandle,
+    exposure_srv: DescriptorHandle,
+    pub settings: AutoExposure,
+}
+
+impl AutoExposurePass {
+    pub fn new(resources: &mut Resources) -> Result<Self> {
+        let root_signature = create_pass_root_signature(
+            &resources.device,
+            (std::mem::size_of::<AutoExposureConstants>() / 4) as u32,
+        )?;
+        let histogram_shader = load_hlsl!(
+            "renderer/src/shaders/auto_exposure.hlsl",
+            "Histogram",
+            "cs_6_6"
+        )?;
+        let adapt_shader =
+            load_hlsl!("renderer/src/shaders/auto_exposure.hlsl", "Adapt", "cs_6_6")?;
+        let histogram_pso =
+            create_compute_pipeline_state(&resources.device, &root_signature, &histogram_shader)?;
+        let adapt_pso =
+            create_compute_pipeline_state(&resources.device, &root_signature, &adapt_shader)?;
+
+        let histogram = create_buffer(resources, HISTOGRAM_BINS * 4)?;
+        let exposure = create_buffer(resources, 4)?;
+
+        Ok(AutoExposurePass {
+            root_signature,
+            histogram_pso,
+            adapt_pso,
+            histogram_uav: create_structured_uav(resources, &histogram, HISTOGRAM_BINS)?,
+            histogram,
+            exposure_uav: create_structured_uav(resources, &exposure, 1)?,
+            exposure_srv: create_structured_srv(resources, &exposure, 0, 1, 4)?,
+            exposure,
+            settings: AutoExposure::default(),
+        })
+    }
+
+    /// Adapts the exposure to the rendered part of `scene`, which has to be ready to be sampled
+    /// once `barriers` is flushed. Returns the index of a float buffer with the exposure in stops,
+    /// readable by pixel shaders once `barriers` is flushed again. Disabled, returns None.
+    pub fn render(
+        &self,
+        command_list: &ID3D12GraphicsCommandList,
+        barriers: &mut BarrierBatcher,
+        resources: &Resources,
+        scene: &RenderTarget,
+    ) -> Result<Option<u32>> {
+        if !self.settings.enabled {
+            return Ok(None);
+        }
+
+        let (width, height) = scene.render_extent();
+        let constants = AutoExposureConstants {
+            scene_index: resources.texture_manager.get_srv(&scene.color)?.index as u32,
+            histogram_index: self.histogram_uav.index as u32,
+            exposure_index: self.exposure_uav.index as u32,
+            use_manual_exposure: self.settings.manual_exposure.is_some() as u32,
+            render_extent: [width, height],
+            min_log_luminance: MIN_LOG_LUMINANCE,
+            log_luminance_range: LOG_LUMINANCE_RANGE,
+            min_exposure: self.settings.min_exposure,
+            max_exposure: self.settings.max_exposure.max(self.settings.min_exposure),
+            manual_exposure: self.settings.manual_exposure.unwrap_or(0.0),
+            speed_up: self.settings.speed_up,
+            speed_down: self.settings.speed_down,
+            padding: [0; 3],
+        };
+
+        for buffer in [&self.histogram, &self.exposure] {
+            barriers.transition(
+                &buffer.device_resource,
+                D3D12_RESOURCE_STATE_COMMON,
+                D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+            );
+        }
+        barriers.flush(command_list);
+
+        unsafe {
+            command_list.SetDescriptorHeaps(&[Some(
+                resources
+                    .descriptor_manager
+                    .get_heap(DescriptorType::Resource)?,
+            )]);
+            command_list.SetComputeRootSignature(&self.root_signature);
+            command_list.SetComputeRootConstantBufferView(
+                GLOBAL_CONSTANTS_PARAMETER,
+                resources.global_constants_address(),
+            );
+            command_list.SetComputeRoot32BitConstants(
+                0,
+                (std::mem::size_of::<AutoExposureConstants>() / 4) as u32,
+                std::ptr::addr_of!(constants) as _,
+                0,
+            );
+
+            // A manual exposure doesn't need the scene measured
+            if self.settings.manual_exposure.is_none() {
+                command_list.SetPipelineState(&self.histogram_pso);
+                command_list.Dispatch(
+                    width.div_ceil(THREAD_GROUP_SIZE),
+                    height.div_ceil(THREAD_GROUP_SIZE),
+                    1,
+                );
+            }
+        }
+
+        barriers.uav(Some(&self.histogram.device_resource));
+        barriers.flush(command_list);
+
+        unsafe {
+            command_list.SetPipelineState(&self.adapt_pso);
+            command_list.Dispatch(1, 1, 1);
+        }
+
+        barriers.transition(
+            &self.exposure.device_resource,
+            D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+            D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE,
+        );
+
+        Ok(Some(self.exposure_srv.index as u32))
+    }
+}
@@ -1,56 +1,214 @@
-use anyhow::{Context, Result};
+use std::hash::{Hash, Hasher};
+
+use anyhow::{ensure, Context, Result};
 use d3d12_utils::{
-    align_data, compile_pixel_shader, compile_vertex_shader, create_pipeline_state,
-    create_root_signature, DescriptorHandle, DescriptorType, Resource, TextureHandle,
+    align_data, create_descriptor_table_root_signature, create_mesh_pipeline_state,
+    create_pipeline_state, create_root_signature, load_hlsl, set_shading_rate, Aabb,
+    BarrierBatcher, BindingValidator, Bundle, DepthStencilState, DescriptorHandle, DescriptorType,
+    FrameFence, Frustum, MeshletSet, PrimitiveTopology, RenderTarget, TargetFormats, TextureHandle,
+    VersionedBuffer, ViewportRect, ROOT_SIGNATURE_GLOBAL_CONSTANTS_PARAMETER,
+    ROOT_SIGNATURE_MATERIAL_TEXTURE_PARAMETER,
 };
 use windows::{
-    core::PCSTR,
-    Win32::Graphics::{
-        Direct3D::D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST, Direct3D12::*, Dxgi::Common::*,
+    core::{Interface, PCSTR},
+    Win32::{
+        Foundation::RECT,
+        Graphics::{Direct3D12::*, Dxgi::Common::*},
     },
 };
 
 use crate::{
     object::Object,
+    render_pass::{
+        environment_probe_pass::{nearest_environment, EnvironmentLight},
+        light_culling_pass::ClusteredLights,
+    },
     renderer::{Camera, Resources},
+    shader_types::{
+        CameraConstantBuffer, MaterialConstantBuffer, ModelConstantBuffer, NO_ENVIRONMENT,
+        NO_FEEDBACK, NO_LIGHTS,
+    },
 };
 
-#[repr(C)]
+// Every point is in front of it
+const NO_CLIP_PLANE: glam::Vec4 = glam::Vec4::W;
+
+impl ModelConstantBuffer {
+    fn new(transform: &glam::Mat4, camera: &Camera, meshlets: Option<&MeshletSet>) -> Self {
+        let frustum = Frustum::from_view_projection(&(camera.view_projection() * *transform));
+        let index = |handle: &DescriptorHandle| handle.index as u32;
+
+        Self {
+            model: *transform,
+            frustum_planes: *frustum.planes(),
+            camera_position: transform.inverse().transform_point3(camera.position()),
+            meshlet_count: meshlets.map_or(0, |meshlets| meshlets.num_meshlets as u32),
+            vertex_buffer_index: meshlets.map_or(0, |meshlets| index(&meshlets.vertices)),
+            meshlet_buffer_index: meshlets.map_or(0, |meshlets| index(&meshlets.meshlets)),
+            meshlet_bounds_index: meshlets.map_or(0, |meshlets| index(&meshlets.bounds)),
+            vertex_index_buffer_index: meshlets
+                .map_or(0, |meshlets| index(&meshlets.vertex_indices)),
+            primitive_index_buffer_index: meshlets
+                .map_or(0, |meshlets| index(&meshlets.primitive_indices)),
+        }
+    }
+}
+
+// What the draw of one object records, everything else about it is in constant buffers
 #[derive(Debug, Clone, Copy)]
-struct MaterialConstantBuffer {
-    pub texture_index: u32,
+pub(crate) struct ObjectDraw {
+    pub(crate) slot: usize,
+    topology: PrimitiveTopology,
+    pub(crate) geometry: DrawGeometry,
+    // In world space, only used by occlusion culling
+    pub(crate) bounds: Option<Aabb>,
+    // The material's texture, bound through a table when shaders can't index the heap
+    texture: Option<DescriptorHandle>,
 }
 
-#[repr(C)]
 #[derive(Debug, Clone, Copy)]
-struct ModelConstantBuffer {
-    pub M: glam::Mat4,
+pub(crate) enum DrawGeometry {
+    Meshlets(u32),
+    Indexed {
+        vbv: D3D12_VERTEX_BUFFER_VIEW,
+        ibv: D3D12_INDEX_BUFFER_VIEW,
+        num_indices: u32,
+    },
 }
 
+impl Hash for ObjectDraw {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.slot.hash(state);
+        self.topology.hash(state);
+        self.texture.map(|texture| texture.index).hash(state);
+        match self.geometry {
+            DrawGeometry::Meshlets(num_meshlets) => num_meshlets.hash(state),
+            DrawGeometry::Indexed {
+                vbv,
+                ibv,
+                num_indices,
+            } => {
+                vbv.BufferLocation.hash(state);
+                vbv.StrideInBytes.hash(state);
+                ibv.BufferLocation.hash(state);
+                ibv.Format.0.hash(state);
+                num_indices.hash(state);
+            }
+        }
+    }
+}
+
+// Matches MESHLETS_PER_GROUP in bindless_texture.hlsl
+pub(crate) const MESHLETS_PER_GROUP: u32 = 32;
+
+// Every object and view drawn in a frame needs its own constant buffer slot, the GPU reads them
+// after recording
+pub const MAX_OBJECTS: usize = 64;
+pub const MAX_VIEWS: usize = 4;
+
+/// Indirect draws read the arguments of object slot `n` at `n * DRAW_ARGUMENTS_STRIDE` of their
+/// buffer. Meshlet draws use the start of their room for a `D3D12_DISPATCH_MESH_ARGUMENTS`.
+pub const DRAW_ARGUMENTS_STRIDE: usize = std::mem::size_of::<D3D12_DRAW_INDEXED_ARGUMENTS>();
+
+/// The draws of one view, with their constants written, waiting to be recorded by `draw`
+#[derive(Debug)]
+pub struct PreparedView {
+    camera_slot: usize,
+    viewport: D3D12_VIEWPORT,
+    scissor_rect: RECT,
+    draws: Vec<ObjectDraw>,
+}
+
+impl PreparedView {
+    // In slot order, the slots of a view follow each other
+    pub(crate) fn draws(&self) -> &[ObjectDraw] {
+        &self.draws
+    }
+}
+
+// A pipeline is created up front for each, so meshes of any topology can be drawn in the pass
+const TOPOLOGY_TYPES: [D3D12_PRIMITIVE_TOPOLOGY_TYPE; 3] = [
+    D3D12_PRIMITIVE_TOPOLOGY_TYPE_TRIANGLE,
+    D3D12_PRIMITIVE_TOPOLOGY_TYPE_LINE,
+    D3D12_PRIMITIVE_TOPOLOGY_TYPE_POINT,
+];
+
 #[derive(Debug)]
 pub struct BindlessTexturePass<const FRAME_COUNT: usize> {
-    #[allow(dead_code)]
-    camera_constant_buffers: [Resource; FRAME_COUNT],
-    camera_cbv_descriptors: [DescriptorHandle; FRAME_COUNT],
-    #[allow(dead_code)]
-    material_constant_buffers: [Resource; FRAME_COUNT],
-    material_descriptors: [DescriptorHandle; FRAME_COUNT],
-    #[allow(dead_code)]
-    model_constant_buffers: [Resource; FRAME_COUNT],
-    model_descriptors: [DescriptorHandle; FRAME_COUNT],
+    camera_constants: VersionedBuffer<FRAME_COUNT>,
+    camera_slot_size: usize,
+    camera_descriptors: [Vec<DescriptorHandle>; FRAME_COUNT],
+    material_constants: VersionedBuffer<FRAME_COUNT>,
+    material_slot_size: usize,
+    material_descriptors: [Vec<DescriptorHandle>; FRAME_COUNT],
+    model_constants: VersionedBuffer<FRAME_COUNT>,
+    model_slot_size: usize,
+    model_descriptors: [Vec<DescriptorHandle>; FRAME_COUNT],
+    // Slots already used this frame
+    next_camera_slot: usize,
+    next_object_slot: usize,
 
     root_signature: ID3D12RootSignature,
-    pso: ID3D12PipelineState,
+    // Without them every draw binds its material's texture through a table
+    dynamic_resources: bool,
+    formats: TargetFormats,
+    // In the order of TOPOLOGY_TYPES
+    psos: Vec<ID3D12PipelineState>,
+    // Only created when the device supports mesh shaders
+    mesh_shader_pso: Option<ID3D12PipelineState>,
+    // For every frame in flight and camera slot, created when first used
+    bundles: [Vec<Bundle>; FRAME_COUNT],
+    draw_command_signature: ID3D12CommandSignature,
+    // Only created along with the mesh shader pipeline
+    mesh_command_signature: Option<ID3D12CommandSignature>,
+
+    /// Records the draws into a bundle and replays it in later frames while the objects and their
+    /// meshes stay the same. Saves recording time in static scenes, transforms and materials still
+    /// change freely.
+    pub record_bundles: bool,
+    /// Shading rate for every draw in the pass, ignored without VRS tier 1
+    pub shading_rate: D3D12_SHADING_RATE,
+    /// Per tile shading rates, combined with `shading_rate` and ignored without VRS tier 2
+    pub shading_rate_image: Option<TextureHandle>,
+    /// Every object is lit by the nearest of them, ignored without dynamic resources
+    pub environment_lights: Vec<EnvironmentLight>,
 }
 
 impl<const FRAME_COUNT: usize> BindlessTexturePass<FRAME_COUNT> {
-    pub fn new(resources: &mut Resources) -> Result<Self> {
-        let root_signature = create_root_signature(&resources.device)?;
-
-        let vertex_shader =
-            compile_vertex_shader("renderer/src/shaders/bindless_texture.hlsl", "VSMain")?;
-        let pixel_shader =
-            compile_pixel_shader("renderer/src/shaders/bindless_texture.hlsl", "PSMain")?;
+    /// Draws into render targets of `formats`
+    pub fn new(resources: &mut Resources, formats: TargetFormats) -> Result<Self> {
+        // Below shader model 6.6 the shaders compile without ResourceDescriptorHeap, binding the
+        // material's texture through a table and leaving out clustered lights and feedback
+        let dynamic_resources = resources.capabilities.dynamic_resources();
+        let (root_signature, vertex_shader, pixel_shader) = if dynamic_resources {
+            (
+                create_root_signature(&resources.device)?,
+                load_hlsl!(
+                    "renderer/src/shaders/bindless_texture.hlsl",
+                    "VSMain",
+                    "vs_6_6"
+                )?,
+                load_hlsl!(
+                    "renderer/src/shaders/bindless_texture.hlsl",
+                    "PSMain",
+                    "ps_6_6"
+                )?,
+            )
+        } else {
+            (
+                create_descriptor_table_root_signature(&resources.device)?,
+                load_hlsl!(
+                    "renderer/src/shaders/bindless_texture.hlsl",
+                    "VSMain",
+                    "vs_6_5"
+                )?,
+                load_hlsl!(
+                    "renderer/src/shaders/bindless_texture.hlsl",
+                    "PSMain",
+                    "ps_6_5"
+                )?,
+            )
+        };
 
         let input_element_descs: [D3D12_INPUT_ELEMENT_DESC; 3] = [
             D3D12_INPUT_ELEMENT_DESC {
@@ -81,268 +239,617 @@ impl<const FRAME_COUNT: usize> BindlessTexturePass<FRAME_COUNT> {
                 InstanceDataStepRate: 0,
             },
         ];
-        let pso = create_pipeline_state(
-            &resources.device,
-            &root_signature,
-            &input_element_descs,
-            &vertex_shader,
-            &pixel_shader,
-            1,
-        )?;
+        let psos = TOPOLOGY_TYPES
+            .iter()
+            .map(|&topology_type| {
+                create_pipeline_state(
+                    &resources.device,
+                    &root_signature,
+                    &input_element_descs,
+                    &vertex_shader,
+                    &pixel_shader,
+                    &formats,
+                    topology_type,
+                    &DepthStencilState::new(resources.depth_range),
+                )
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // Falls back to the vertex shader path on devices without mesh shaders, meshlets are read
+        // through the heap
+        let mesh_shader_pso = if resources.capabilities.mesh_shaders() && dynamic_resources {
+            let amplification_shader = load_hlsl!(
+                "renderer/src/shaders/bindless_texture.hlsl",
+                "ASMain",
+                "as_6_6"
+            )?;
+            let mesh_shader = load_hlsl!(
+                "renderer/src/shaders/bindless_texture.hlsl",
+                "MSMain",
+                "ms_6_6"
+            )?;
+            Some(create_mesh_pipeline_state(
+                &resources.device,
+                &root_signature,
+                Some(&amplification_shader),
+                &mesh_shader,
+                &pixel_shader,
+                &formats,
+                &DepthStencilState::new(resources.depth_range),
+            )?)
+        } else {
+            None
+        };
+        let draw_command_signature =
+            create_command_signature(&resources.device, D3D12_INDIRECT_ARGUMENT_TYPE_DRAW_INDEXED)?;
+        let mesh_command_signature = mesh_shader_pso
+            .as_ref()
+            .map(|_| {
+                create_command_signature(
+                    &resources.device,
+                    D3D12_INDIRECT_ARGUMENT_TYPE_DISPATCH_MESH,
+                )
+            })
+            .transpose()?;
 
-        let camera_buffer_size = align_data(
-            std::mem::size_of::<Camera>(),
+        let camera_slot_size = align_data(
+            std::mem::size_of::<CameraConstantBuffer>(),
             D3D12_CONSTANT_BUFFER_DATA_PLACEMENT_ALIGNMENT as usize,
         );
-
-        let mut camera_cbv_descriptors: [DescriptorHandle; FRAME_COUNT] =
-            array_init::array_init(|_| DescriptorHandle::default());
-        let camera_constant_buffers: [Resource; FRAME_COUNT] =
-            array_init::try_array_init(|i| -> Result<Resource> {
-                let buffer = Resource::create_committed(
-                    &resources.device,
-                    &D3D12_HEAP_PROPERTIES {
-                        Type: D3D12_HEAP_TYPE_UPLOAD,
-                        ..Default::default()
-                    },
-                    &D3D12_RESOURCE_DESC {
-                        Dimension: D3D12_RESOURCE_DIMENSION_BUFFER,
-                        Width: camera_buffer_size as u64,
-                        Height: 1,
-                        DepthOrArraySize: 1,
-                        MipLevels: 1,
-                        SampleDesc: DXGI_SAMPLE_DESC {
-                            Count: 1,
-                            Quality: 0,
-                        },
-                        Layout: D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
-                        ..Default::default()
-                    },
-                    D3D12_RESOURCE_STATE_GENERIC_READ,
-                    None,
-                    true,
-                )?;
-
-                buffer.copy_from(&[resources.camera])?;
-
-                let cbv_descriptor = resources
-                    .descriptor_manager
-                    .allocate(DescriptorType::Resource)?;
-                camera_cbv_descriptors[i] = cbv_descriptor;
-
-                unsafe {
-                    resources.device.CreateConstantBufferView(
-                        &D3D12_CONSTANT_BUFFER_VIEW_DESC {
-                            BufferLocation: buffer.gpu_address(),
-                            SizeInBytes: buffer.size as u32,
-                        },
-                        resources
-                            .descriptor_manager
-                            .get_cpu_handle(&cbv_descriptor)?,
+        let camera_constants =
+            VersionedBuffer::<FRAME_COUNT>::new(&resources.device, camera_slot_size * MAX_VIEWS)?;
+        let camera_descriptors = array_init::try_array_init(|i| {
+            (0..MAX_VIEWS)
+                .map(|slot| {
+                    create_cbv(
+                        resources,
+                        camera_constants.gpu_address(i) + (slot * camera_slot_size) as u64,
+                        camera_slot_size,
                     )
-                };
-
-                Ok(buffer)
-            })?;
+                })
+                .collect::<Result<Vec<DescriptorHandle>>>()
+        })?;
 
-        let material_buffer_size = align_data(
+        let material_slot_size = align_data(
             std::mem::size_of::<MaterialConstantBuffer>(),
             D3D12_CONSTANT_BUFFER_DATA_PLACEMENT_ALIGNMENT as usize,
         );
-        let mut material_descriptors: [DescriptorHandle; FRAME_COUNT] =
-            array_init::array_init(|_| DescriptorHandle::default());
-        let material_constant_buffers: [Resource; FRAME_COUNT] =
-            array_init::try_array_init(|i| -> Result<Resource> {
-                let buffer = Resource::create_committed(
-                    &resources.device,
-                    &D3D12_HEAP_PROPERTIES {
-                        Type: D3D12_HEAP_TYPE_UPLOAD,
-                        ..Default::default()
-                    },
-                    &D3D12_RESOURCE_DESC {
-                        Dimension: D3D12_RESOURCE_DIMENSION_BUFFER,
-                        Width: material_buffer_size as u64,
-                        Height: 1,
-                        DepthOrArraySize: 1,
-                        MipLevels: 1,
-                        SampleDesc: DXGI_SAMPLE_DESC {
-                            Count: 1,
-                            Quality: 0,
-                        },
-                        Layout: D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
-                        ..Default::default()
-                    },
-                    D3D12_RESOURCE_STATE_GENERIC_READ,
-                    None,
-                    true,
-                )?;
-
-                let cbv_descriptor = resources
-                    .descriptor_manager
-                    .allocate(DescriptorType::Resource)?;
-                material_descriptors[i] = cbv_descriptor;
-
-                unsafe {
-                    resources.device.CreateConstantBufferView(
-                        &D3D12_CONSTANT_BUFFER_VIEW_DESC {
-                            BufferLocation: buffer.gpu_address(),
-                            SizeInBytes: buffer.size as u32,
-                        },
-                        resources
-                            .descriptor_manager
-                            .get_cpu_handle(&cbv_descriptor)?,
+        let material_constants = VersionedBuffer::<FRAME_COUNT>::new(
+            &resources.device,
+            material_slot_size * MAX_OBJECTS,
+        )?;
+        let material_descriptors = array_init::try_array_init(|i| {
+            (0..MAX_OBJECTS)
+                .map(|slot| {
+                    create_cbv(
+                        resources,
+                        material_constants.gpu_address(i) + (slot * material_slot_size) as u64,
+                        material_slot_size,
                     )
-                };
+                })
+                .collect::<Result<Vec<DescriptorHandle>>>()
+        })?;
 
-                Ok(buffer)
-            })?;
-
-        let model_data = ModelConstantBuffer {
-            M: glam::Mat4::from_translation(glam::Vec3::new(2.0, 0.0, 0.0)),
-        };
-        let model_buffer_size = align_data(
-            std::mem::size_of_val(&model_data),
+        let model_slot_size = align_data(
+            std::mem::size_of::<ModelConstantBuffer>(),
             D3D12_CONSTANT_BUFFER_DATA_PLACEMENT_ALIGNMENT as usize,
         );
-        let mut model_descriptors: [DescriptorHandle; FRAME_COUNT] =
-            array_init::array_init(|_| DescriptorHandle::default());
-        let model_constant_buffers: [Resource; FRAME_COUNT] =
-            array_init::try_array_init(|i| -> Result<Resource> {
-                let buffer = Resource::create_committed(
-                    &resources.device,
-                    &D3D12_HEAP_PROPERTIES {
-                        Type: D3D12_HEAP_TYPE_UPLOAD,
-                        ..Default::default()
-                    },
-                    &D3D12_RESOURCE_DESC {
-                        Dimension: D3D12_RESOURCE_DIMENSION_BUFFER,
-                        Width: model_buffer_size as u64,
-                        Height: 1,
-                        DepthOrArraySize: 1,
-                        MipLevels: 1,
-                        SampleDesc: DXGI_SAMPLE_DESC {
-                            Count: 1,
-                            Quality: 0,
-                        },
-                        Layout: D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
-                        ..Default::default()
-                    },
-                    D3D12_RESOURCE_STATE_GENERIC_READ,
-                    None,
-                    true,
-                )?;
-
-                buffer.copy_from(&[model_data])?;
-
-                let cbv_descriptor = resources
-                    .descriptor_manager
-                    .allocate(DescriptorType::Resource)?;
-                model_descriptors[i] = cbv_descriptor;
-
-                unsafe {
-                    resources.device.CreateConstantBufferView(
-                        &D3D12_CONSTANT_BUFFER_VIEW_DESC {
-                            BufferLocation: buffer.gpu_address(),
-                            SizeInBytes: buffer.size as u32,
-                        },
-                        resources
-                            .descriptor_manager
-                            .get_cpu_handle(&cbv_descriptor)?,
+        let model_constants =
+            VersionedBuffer::<FRAME_COUNT>::new(&resources.device, model_slot_size * MAX_OBJECTS)?;
+        let model_descriptors = array_init::try_array_init(|i| {
+            (0..MAX_OBJECTS)
+                .map(|slot| {
+                    create_cbv(
+                        resources,
+                        model_constants.gpu_address(i) + (slot * model_slot_size) as u64,
+                        model_slot_size,
                     )
-                };
-
-                Ok(buffer)
-            })?;
+                })
+                .collect::<Result<Vec<DescriptorHandle>>>()
+        })?;
 
         Ok(BindlessTexturePass {
-            camera_constant_buffers,
-            camera_cbv_descriptors,
-            material_constant_buffers,
+            camera_constants,
+            camera_slot_size,
+            camera_descriptors,
+            material_constants,
+            material_slot_size,
             material_descriptors,
-            model_constant_buffers,
+            model_constants,
+            model_slot_size,
             model_descriptors,
+            next_camera_slot: 0,
+            next_object_slot: 0,
             root_signature,
-            pso,
+            dynamic_resources,
+            formats,
+            psos,
+            mesh_shader_pso,
+            bundles: array_init::array_init(|_| Vec::new()),
+            draw_command_signature,
+            mesh_command_signature,
+            record_bundles: false,
+            shading_rate: D3D12_SHADING_RATE_1X1,
+            shading_rate_image: None,
+            environment_lights: Vec::new(),
         })
     }
 }
 
 impl<const FRAME_COUNT: usize> BindlessTexturePass<FRAME_COUNT> {
-    pub fn render(
+    fn pso(&self, topology: PrimitiveTopology) -> &ID3D12PipelineState {
+        let index = TOPOLOGY_TYPES
+            .iter()
+            .position(|&topology_type| topology_type == topology.topology_type())
+            .expect("Every topology type has a pipeline");
+        &self.psos[index]
+    }
+
+    /// Call once per frame before any `render`, once the frame's fence has been waited on
+    pub fn begin_frame(&mut self, frame: FrameFence) -> Result<()> {
+        self.camera_constants.begin_frame(frame)?;
+        self.material_constants.begin_frame(frame)?;
+        self.model_constants.begin_frame(frame)?;
+        self.next_camera_slot = 0;
+        self.next_object_slot = 0;
+
+        Ok(())
+    }
+
+    /// Draws the objects from `camera` into `region` of the target. Can be called for several
+    /// views a frame, each takes up one camera slot. `lights` have to be culled for the same camera.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render<'a, I>(
         &mut self,
         command_list: &ID3D12GraphicsCommandList,
-        resources: &mut Resources,
-        render_target_handle: &TextureHandle,
-        depth_buffer_handle: &TextureHandle,
-        objects: &[Object],
-    ) -> Result<()> {
-        unsafe {
-            command_list.SetPipelineState(&self.pso);
+        resources: &Resources,
+        camera: &Camera,
+        render_target: &RenderTarget,
+        region: &ViewportRect,
+        lights: Option<&ClusteredLights>,
+        objects: I,
+    ) -> Result<()>
+    where
+        I: IntoIterator<Item = (&'a Object, &'a glam::Mat4)>,
+    {
+        let view = self.prepare(resources, camera, render_target, region, lights, objects)?;
+        self.draw(command_list, resources, render_target, &view, None)
+    }
+
+    /// Writes the constants of the objects seen from `camera` for a later `draw`, takes up a
+    /// camera slot and an object slot for each object like `render` does
+    pub fn prepare<'a, I>(
+        &mut self,
+        resources: &Resources,
+        camera: &Camera,
+        render_target: &RenderTarget,
+        region: &ViewportRect,
+        lights: Option<&ClusteredLights>,
+        objects: I,
+    ) -> Result<PreparedView>
+    where
+        I: IntoIterator<Item = (&'a Object, &'a glam::Mat4)>,
+    {
+        let frame_index = resources.frame_index as usize;
+        render_target.validate_formats(&resources.texture_manager, &self.formats)?;
+
+        let camera_slot = self.next_camera_slot;
+        ensure!(
+            camera_slot < MAX_VIEWS,
+            "Too many views, at most {} can be drawn per frame",
+            MAX_VIEWS
+        );
+        self.next_camera_slot += 1;
+
+        let (viewport, scissor_rect) = render_target.region_viewport(region);
+        let lights = lights.copied().unwrap_or(ClusteredLights {
+            light_buffer_index: NO_LIGHTS,
+            cluster_light_counts_index: NO_LIGHTS,
+            cluster_light_indices_index: NO_LIGHTS,
+            cluster_counts: [1; 3],
+            slice_scale: 0.0,
+            slice_bias: 0.0,
+        });
+        self.camera_constants.write_for_frame_at_offset(
+            frame_index,
+            camera_slot * self.camera_slot_size,
+            &[CameraConstantBuffer {
+                view: camera.view(),
+                projection: camera.projection_matrix(),
+                light_buffer_index: lights.light_buffer_index,
+                cluster_light_counts_index: lights.cluster_light_counts_index,
+                cluster_light_indices_index: lights.cluster_light_indices_index,
+                slice_scale: lights.slice_scale,
+                cluster_counts: lights.cluster_counts,
+                slice_bias: lights.slice_bias,
+                region_offset: glam::Vec2::new(viewport.TopLeftX, viewport.TopLeftY),
+                region_size: glam::Vec2::new(viewport.Width, viewport.Height),
+                clip_plane: camera
+                    .clip_plane()
+                    .map_or(NO_CLIP_PLANE, |plane| plane.as_vec4()),
+            }],
+        )?;
+
+        let mut draws = Vec::new();
+        for (object, transform) in objects {
+            let slot = self.next_object_slot;
+            ensure!(
+                slot < MAX_OBJECTS,
+                "Too many objects, at most {} can be drawn per frame",
+                MAX_OBJECTS
+            );
+            self.next_object_slot += 1;
+
+            let material = &object.material;
+            let texture = resources.texture_manager.get_srv(&material.texture)?;
+            let environment =
+                nearest_environment(&self.environment_lights, transform.w_axis.truncate());
+            self.material_constants.write_for_frame_at_offset(
+                frame_index,
+                slot * self.material_slot_size,
+                &[MaterialConstantBuffer {
+                    uv_offset: material.uv_offset,
+                    uv_scale: material.uv_scale,
+                    texture_index: texture.index as u32,
+                    feedback_index: material.feedback_index.unwrap_or(NO_FEEDBACK),
+                    min_lod: material.min_lod,
+                    base_color_factor: material.base_color_factor,
+                    emissive_factor: material.emissive_factor,
+                    roughness: material.roughness,
+                    environment_index: environment.map_or(NO_ENVIRONMENT, |light| light.srv_index),
+                    environment_max_mip: environment.map_or(0.0, |light| light.max_mip),
+                }],
+            )?;
+
+            let mut mesh = resources.mesh_manager.get_lod(&object.mesh, object.lod);
+            // Drawn from what the morph target pass blended this frame, without meshlets
+            if object.is_morphed() {
+                mesh = resources.mesh_manager.morphed(&mesh, frame_index);
+            }
+            // Meshlets are built from triangle lists only
+            let meshlets = self
+                .mesh_shader_pso
+                .as_ref()
+                .filter(|_| mesh.topology == PrimitiveTopology::TriangleList)
+                .and(resources.mesh_manager.get_meshlets(&mesh));
+            self.model_constants.write_for_frame_at_offset(
+                frame_index,
+                slot * self.model_slot_size,
+                &[ModelConstantBuffer::new(transform, camera, meshlets)],
+            )?;
+
+            let geometry = match meshlets {
+                Some(meshlets) => DrawGeometry::Meshlets(meshlets.num_meshlets as u32),
+                None => DrawGeometry::Indexed {
+                    vbv: mesh.vbv.context("Object vertex buffer view")?,
+                    ibv: mesh.ibv.context("Object index buffer view")?,
+                    num_indices: mesh.num_vertices as u32,
+                },
+            };
+            draws.push(ObjectDraw {
+                slot,
+                topology: mesh.topology,
+                geometry,
+                bounds: object.mesh.bounds.map(|bounds| bounds.transform(transform)),
+                texture: (!self.dynamic_resources).then_some(texture),
+            });
         }
-        let camera_cb_handle = resources
-            .descriptor_manager
-            .get_gpu_handle(&self.camera_cbv_descriptors[resources.frame_index as usize])?;
 
-        let model_cb_handle = resources
-            .descriptor_manager
-            .get_gpu_handle(&self.model_descriptors[resources.frame_index as usize])?;
+        Ok(PreparedView {
+            camera_slot,
+            viewport,
+            scissor_rect,
+            draws,
+        })
+    }
 
-        let material_cb_handle = resources
+    /// Records the draws of `view` into the target it was prepared for. With `arguments` every
+    /// draw reads its counts from the buffer instead, see `DRAW_ARGUMENTS_STRIDE`, so the GPU can
+    /// leave some out. The buffer has to be in the indirect argument state. Bundles are only
+    /// recorded without it.
+    pub fn draw(
+        &mut self,
+        command_list: &ID3D12GraphicsCommandList,
+        resources: &Resources,
+        render_target: &RenderTarget,
+        view: &PreparedView,
+        arguments: Option<&ID3D12Resource>,
+    ) -> Result<()> {
+        let frame_index = resources.frame_index as usize;
+        let camera_slot = view.camera_slot;
+        let camera_cb_handle = resources
             .descriptor_manager
-            .get_gpu_handle(&self.material_descriptors[resources.frame_index as usize])?;
-
-        let camera_cb = &self.camera_constant_buffers[resources.frame_index as usize];
-        camera_cb.copy_from(&[resources.camera])?;
+            .get_gpu_handle(&self.camera_descriptors[frame_index][camera_slot])?;
 
+        let descriptor_heap = resources
+            .descriptor_manager
+            .get_heap(DescriptorType::Resource)?;
+        let descriptor_heaps = [Some(descriptor_heap.clone())];
+        let mut bindings = BindingValidator::new();
+        bindings.set_descriptor_heaps(&descriptor_heaps);
+        bindings.set_root_signature(&self.root_signature);
+        bindings.set_root_argument(ROOT_SIGNATURE_GLOBAL_CONSTANTS_PARAMETER);
+        bindings.set_root_argument(0);
         unsafe {
-            command_list.SetDescriptorHeaps(&[Some(
-                resources
-                    .descriptor_manager
-                    .get_heap(DescriptorType::Resource)?,
-            )]);
+            command_list.SetDescriptorHeaps(&descriptor_heaps);
             command_list.SetGraphicsRootSignature(&self.root_signature);
+            command_list.SetGraphicsRootConstantBufferView(
+                ROOT_SIGNATURE_GLOBAL_CONSTANTS_PARAMETER,
+                resources.global_constants_address(),
+            );
 
             command_list.SetGraphicsRootDescriptorTable(0, camera_cb_handle);
-            command_list.SetGraphicsRootDescriptorTable(1, material_cb_handle);
-            command_list.SetGraphicsRootDescriptorTable(2, model_cb_handle);
 
-            command_list.RSSetViewports(&[resources.viewport]);
-            command_list.RSSetScissorRects(&[resources.scissor_rect]);
+            command_list.RSSetViewports(&[view.viewport]);
+            command_list.RSSetScissorRects(&[view.scissor_rect]);
         }
 
-        let rtv_handle = resources.texture_manager.get_rtv(render_target_handle)?;
+        let rtv_handle = resources.texture_manager.get_rtv(&render_target.color)?;
         let rtv = resources.descriptor_manager.get_cpu_handle(&rtv_handle)?;
 
-        let dsv_handle = resources.texture_manager.get_dsv(depth_buffer_handle)?;
+        let dsv_handle = resources.texture_manager.get_dsv(&render_target.depth)?;
         let dsv = resources.descriptor_manager.get_cpu_handle(&dsv_handle)?;
 
         unsafe {
             command_list.OMSetRenderTargets(1, &rtv, false, &dsv);
-            command_list.IASetPrimitiveTopology(D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST);
         }
 
-        for object in objects {
-            let material_cb = &self.material_constant_buffers[resources.frame_index as usize];
-            material_cb.copy_from(&[MaterialConstantBuffer {
-                texture_index: object.texture.srv_index.context("Need srv")? as u32,
-            }])?;
+        let variable_rate_shading = &resources.capabilities.variable_rate_shading;
+        if variable_rate_shading.per_draw() {
+            let shading_rate_image = match &self.shading_rate_image {
+                Some(image) if variable_rate_shading.screen_space() => Some(
+                    &resources
+                        .texture_manager
+                        .get_texture(image)?
+                        .get_resource()?
+                        .device_resource,
+                ),
+                _ => None,
+            };
+            set_shading_rate(
+                command_list,
+                variable_rate_shading,
+                variable_rate_shading.clamp_rate(self.shading_rate),
+                shading_rate_image,
+            )?;
+        }
+
+        let draws = &view.draws;
+        if self.record_bundles && arguments.is_none() {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            draws.hash(&mut hasher);
+            let key = hasher.finish();
+
+            let bundles = &mut self.bundles[frame_index];
+            while bundles.len() <= camera_slot {
+                bundles.push(Bundle::new(
+                    &resources.device,
+                    &format!("Bindless Texture Bundle {}", bundles.len()),
+                )?);
+            }
+            let initial_state = self.pso(PrimitiveTopology::default()).clone();
+            if let Some(bundle) =
+                self.bundles[frame_index][camera_slot].record(key, Some(&initial_state))?
+            {
+                unsafe {
+                    bundle.SetDescriptorHeaps(&descriptor_heaps);
+                }
+                self.record_draws(&bundle, resources, draws, None, &mut bindings)?;
+                self.bundles[frame_index][camera_slot].finish(key)?;
+            }
+            self.bundles[frame_index][camera_slot].execute(command_list);
+        } else {
+            self.record_draws(command_list, resources, draws, arguments, &mut bindings)?;
+        }
+
+        // Leave full rate shading behind for the passes that follow
+        if variable_rate_shading.per_draw() {
+            set_shading_rate(
+                command_list,
+                variable_rate_shading,
+                D3D12_SHADING_RATE_1X1,
+                None,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    // Sets the pipeline and topology itself, bundles start without them. `bindings` follows the
+    // root arguments the caller set, which bundles inherit.
+    fn record_draws(
+        &self,
+        command_list: &ID3D12GraphicsCommandList,
+        resources: &Resources,
+        draws: &[ObjectDraw],
+        arguments: Option<&ID3D12Resource>,
+        bindings: &mut BindingValidator,
+    ) -> Result<()> {
+        let frame_index = resources.frame_index as usize;
 
-            let model_cb = &self.model_constant_buffers[resources.frame_index as usize];
-            model_cb.copy_from(&[ModelConstantBuffer {
-                M: glam::Mat4::from_translation(object.position)
-                    * glam::Mat4::from_rotation_y(std::f32::consts::PI * -0.9),
-            }])?;
+        // Pipeline and input assembler topology are only switched when the next mesh needs it
+        let mut topology = PrimitiveTopology::default();
+        unsafe {
+            command_list.SetPipelineState(self.pso(topology));
+            command_list.IASetPrimitiveTopology(topology.d3d());
+        }
+
+        for draw in draws {
+            let material_cb_handle = resources
+                .descriptor_manager
+                .get_gpu_handle(&self.material_descriptors[frame_index][draw.slot])?;
+            let model_cb_handle = resources
+                .descriptor_manager
+                .get_gpu_handle(&self.model_descriptors[frame_index][draw.slot])?;
 
-            let vbv = object.mesh.vbv.context("Object vertex buffer view")?;
-            let ibv = object.mesh.ibv.context("Object index buffer view")?;
+            unsafe {
+                command_list.SetGraphicsRootDescriptorTable(1, material_cb_handle);
+                command_list.SetGraphicsRootDescriptorTable(2, model_cb_handle);
+            }
+            bindings.set_root_argument(1);
+            bindings.set_root_argument(2);
+            if let Some(texture) = &draw.texture {
+                let texture_handle = resources.descriptor_manager.get_gpu_handle(texture)?;
+                unsafe {
+                    command_list.SetGraphicsRootDescriptorTable(
+                        ROOT_SIGNATURE_MATERIAL_TEXTURE_PARAMETER,
+                        texture_handle,
+                    );
+                }
+                bindings.set_root_argument(ROOT_SIGNATURE_MATERIAL_TEXTURE_PARAMETER);
+            }
+            bindings.validate(&resources.descriptor_manager)?;
+            let arguments_offset = (draw.slot * DRAW_ARGUMENTS_STRIDE) as u64;
+
+            let (vbv, ibv, num_indices) = match draw.geometry {
+                DrawGeometry::Meshlets(num_meshlets) => {
+                    let mesh_shader_pso = self
+                        .mesh_shader_pso
+                        .as_ref()
+                        .context("Meshlets are only drawn with mesh shaders")?;
+                    let mesh_command_list: ID3D12GraphicsCommandList6 = command_list.cast()?;
+                    unsafe {
+                        command_list.SetPipelineState(mesh_shader_pso);
+                        match arguments {
+                            Some(arguments) => command_list.ExecuteIndirect(
+                                self.mesh_command_signature
+                                    .as_ref()
+                                    .context("Meshlets are only drawn with mesh shaders")?,
+                                1,
+                                arguments,
+                                arguments_offset,
+                                None,
+                                0,
+                            ),
+                            None => mesh_command_list.DispatchMesh(
+                                num_meshlets.div_ceil(MESHLETS_PER_GROUP),
+                                1,
+                                1,
+                            ),
+                        }
+                        command_list.SetPipelineState(self.pso(topology));
+                    }
+                    continue;
+                }
+                DrawGeometry::Indexed {
+                    vbv,
+                    ibv,
+                    num_indices,
+                } => (vbv, ibv, num_indices),
+            };
+
+            if draw.topology != topology {
+                if draw.topology.topology_type() != topology.topology_type() {
+                    unsafe {
+                        command_list.SetPipelineState(self.pso(draw.topology));
+                    }
+                }
+                unsafe {
+                    command_list.IASetPrimitiveTopology(draw.topology.d3d());
+                }
+                topology = draw.topology;
+            }
 
             unsafe {
                 command_list.IASetVertexBuffers(0, &[vbv]);
                 command_list.IASetIndexBuffer(&ibv);
-                command_list.DrawIndexedInstanced(object.mesh.num_vertices as u32, 1, 0, 0, 0);
+                match arguments {
+                    Some(arguments) => command_list.ExecuteIndirect(
+                        &self.draw_command_signature,
+                        1,
+                        arguments,
+                        arguments_offset,
+                        None,
+                        0,
+                    ),
+                    None => command_list.DrawIndexedInstanced(num_indices, 1, 0, 0, 0),
+                }
             }
         }
 
         Ok(())
     }
+
+    /// Renders the objects from `camera` into an offscreen target, which is ready to be sampled
+    /// once `barriers` is flushed
+    pub fn render_to_target<'a, I>(
+        &mut self,
+        command_list: &ID3D12GraphicsCommandList,
+        barriers: &mut BarrierBatcher,
+        resources: &Resources,
+        camera: &Camera,
+        render_target: &RenderTarget,
+        objects: I,
+    ) -> Result<()>
+    where
+        I: IntoIterator<Item = (&'a Object, &'a glam::Mat4)>,
+    {
+        render_target.begin(
+            command_list,
+            barriers,
+            &resources.texture_manager,
+            &resources.descriptor_manager,
+        )?;
+        self.render(
+            command_list,
+            resources,
+            camera,
+            render_target,
+            &ViewportRect::FULL,
+            None,
+            objects,
+        )?;
+        render_target.end(barriers, &resources.texture_manager)
+    }
+}
+
+fn create_cbv(
+    resources: &mut Resources,
+    buffer_location: u64,
+    size: usize,
+) -> Result<DescriptorHandle> {
+    let cbv_descriptor = resources
+        .descriptor_manager
+        .allocate(DescriptorType::Resource)?;
+
+    unsafe {
+        resources.device.CreateConstantBufferView(
+            &D3D12_CONSTANT_BUFFER_VIEW_DESC {
+                BufferLocation: buffer_location,
+                SizeInBytes: size as u32,
+            },
+            resources
+                .descriptor_manager
+                .get_cpu_handle(&cbv_descriptor)?,
+        )
+    };
+
+    Ok(cbv_descriptor)
+}
+
+// One draw of `argument_type` per command, changing no root arguments
+fn create_command_signature(
+    device: &ID3D12Device4,
+    argument_type: D3D12_INDIRECT_ARGUMENT_TYPE,
+) -> Result<ID3D12CommandSignature> {
+    let argument = D3D12_INDIRECT_ARGUMENT_DESC {
+        Type: argument_type,
+        ..Default::default()
+    };
+
+    let mut command_signature: Option<ID3D12CommandSignature> = None;
+    unsafe {
+        device.CreateCommandSignature(
+            &D3D12_COMMAND_SIGNATURE_DESC {
+                ByteStride: DRAW_ARGUMENTS_STRIDE as u32,
+                NumArgumentDescs: 1,
+                pArgumentDescs: &argument,
+                NodeMask: 0,
+            },
+            None,
+            &mut command_signature,
+        )?;
+    }
+
+    command_signature.context("No command signature was created")
 }
@@ -0,0 +1,244 @@
+use anyhow::{bail, Result};
+use d3d12_utils::{
+    create_fullscreen_pipeline_state, create_pass_root_signature, load_hlsl, BarrierBatcher,
+    DescriptorType, LoadOp, RenderTarget, TextureDimension, TextureHandle, ViewportRect,
+    GLOBAL_CONSTANTS_PARAMETER,
+};
+use windows::Win32::Graphics::{
+    Direct3D::D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST, Direct3D12::*, Dxgi::Common::DXGI_FORMAT,
+};
+
+use crate::{renderer::Resources, settings::ColorGrading};
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct ColorGradingConstants {
+    pub domain_min: glam::Vec3,
+    pub source_index: u32,
+    pub domain_max: glam::Vec3,
+    pub lut_index: u32,
+    pub uv_scale: glam::Vec2,
+    pub exposure_scale: f32,
+    pub lut_size: f32,
+    pub contrast: f32,
+    pub saturation: f32,
+    pub exposure_index: u32,
+    pub padding: u32,
+}
+
+// Tells the shader there is no adapted exposure to add
+const NO_EXPOSURE: u32 = u32::MAX;
+
+/// A 3D lookup table with the same number of texels along every side
+#[derive(Debug, Clone)]
+pub struct ColorLut {
+    pub texture: TextureHandle,
+    /// Input colours mapped to the first and last texel along each axis
+    pub domain_min: glam::Vec3,
+    pub domain_max: glam::Vec3,
+}
+
+impl ColorLut {
+    /// A LUT covering colours from 0 to 1
+    pub fn new(texture: TextureHandle) -> Self {
+        Self {
+            texture,
+            domain_min: glam::Vec3::ZERO,
+            domain_max: glam::Vec3::ONE,
+        }
+    }
+}
+
+/// Grades the rendered part of the scene into a target of its own, with exposure, contrast and
+/// saturation followed by a colour LUT. The scene is already in display range, so this stands in
+/// the place a tonemapper's output would.
+#[derive(Debug)]
+pub struct ColorGradingPass {
+    root_signature: ID3D12RootSignature,
+    pso: ID3D12PipelineState,
+    target: RenderTarget,
+    pub lut: ColorLut,
+    pub grading: ColorGrading,
+    pub enabled: bool,
+}
+
+impl ColorGradingPass {
+    /// The graded target has the `extent` and `format` of the scene target
+    pub fn new(
+        resources: &mut Resources,
+        extent: (u32, u32),
+        format: DXGI_FORMAT,
+        lut: ColorLut,
+    ) -> Result<Self> {
+        let root_signature = create_pass_root_signature(
+            &resources.device,
+            (std::mem::size_of::<ColorGradingConstants>() / 4) as u32,
+        )?;
+
+        let vertex_shader = load_hlsl!(
+            "renderer/src/shaders/color_grading.hlsl",
+            "VSMain",
+            "vs_6_6"
+        )?;
+        let pixel_shader = load_hlsl!(
+            "renderer/src/shaders/color_grading.hlsl",
+            "PSMain",
+            "ps_6_6"
+        )?;
+
+        let pso = create_fullscreen_pipeline_state(
+            &resources.device,
+            &root_signature,
+            &vertex_shader,
+            &pixel_shader,
+            format,
+        )?;
+
+        Ok(ColorGradingPass {
+            root_signature,
+            pso,
+            target: create_graded_target(resources, extent, format)?,
+            lut,
+            grading: ColorGrading::default(),
+            enabled: true,
+        })
+    }
+
+    pub fn resize(&mut self, resources: &mut Resources, extent: (u32, u32)) -> Result<()> {
+        let format = resources
+            .texture_manager
+            .get_texture(&self.target.color)?
+            .info
+            .format;
+        let target = create_graded_target(resources, extent, format)?;
+        std::mem::replace(&mut self.target, target).delete(
+            &mut resources.texture_manager,
+            &mut resources.descriptor_manager,
+        );
+
+        Ok(())
+    }
+
+    /// Grades `source`, which has to be ready to be sampled, and returns the target to show
+    /// instead. That is ready to be sampled once `barriers` is flushed. `exposure` is the index of
+    /// a float buffer with stops to add to the exposure, e.g. from `AutoExposurePass`. Disabled,
+    /// `source` itself is returned.
+    pub fn render<'a>(
+        &'a mut self,
+        command_list: &ID3D12GraphicsCommandList,
+        barriers: &mut BarrierBatcher,
+        resources: &Resources,
+        source: &'a RenderTarget,
+        exposure: Option<u32>,
+    ) -> Result<&'a RenderTarget> {
+        if !self.enabled {
+            return Ok(source);
+        }
+
+        let (source_width, source_height) = match resources
+            .texture_manager
+            .get_texture(&source.color)?
+            .info
+            .dimension
+        {
+            TextureDimension::Two(width, height) => (width as f32, height as f32),
+            _ => bail!("Colour grading source has to be a 2D texture"),
+        };
+        let lut_size = match resources
+            .texture_manager
+            .get_texture(&self.lut.texture)?
+            .info
+            .dimension
+        {
+            TextureDimension::Three(width, height, depth)
+                if height == width as u32 && depth as usize == width =>
+            {
+                width as f32
+            }
+            _ => bail!("Colour LUTs have to be 3D textures with equal sides"),
+        };
+
+        // Only the part the scene was rendered to gets graded
+        let (render_width, render_height) = source.render_extent();
+        self.target.set_render_extent((render_width, render_height));
+
+        let constants = ColorGradingConstants {
+            domain_min: self.lut.domain_min,
+            source_index: resources.texture_manager.get_srv(&source.color)?.index as u32,
+            domain_max: self.lut.domain_max,
+            lut_index: resources.texture_manager.get_srv(&self.lut.texture)?.index as u32,
+            uv_scale: glam::Vec2::new(
+                render_width as f32 / source_width,
+                render_height as f32 / source_height,
+            ),
+            exposure_scale: self.grading.exposure.exp2(),
+            lut_size,
+            contrast: self.grading.contrast,
+            saturation: self.grading.saturation,
+            exposure_index: exposure.unwrap_or(NO_EXPOSURE),
+            padding: 0,
+        };
+
+        self.target.begin(
+            command_list,
+            barriers,
+            &resources.texture_manager,
+            &resources.descriptor_manager,
+        )?;
+
+        let (viewport, scissor_rect) = self.target.region_viewport(&ViewportRect::FULL);
+        let rtv_handle = resources.texture_manager.get_rtv(&self.target.color)?;
+        let rtv = resources.descriptor_manager.get_cpu_handle(&rtv_handle)?;
+
+        unsafe {
+            command_list.SetPipelineState(&self.pso);
+            command_list.SetDescriptorHeaps(&[Some(
+                resources
+                    .descriptor_manager
+                    .get_heap(DescriptorType::Resource)?,
+            )]);
+            command_list.SetGraphicsRootSignature(&self.root_signature);
+            command_list.SetGraphicsRootConstantBufferView(
+                GLOBAL_CONSTANTS_PARAMETER,
+                resources.global_constants_address(),
+            );
+            command_list.SetGraphicsRoot32BitConstants(
+                0,
+                (std::mem::size_of::<ColorGradingConstants>() / 4) as u32,
+                std::ptr::addr_of!(constants) as _,
+                0,
+            );
+
+            command_list.RSSetViewports(&[viewport]);
+            command_list.RSSetScissorRects(&[scissor_rect]);
+            command_list.OMSetRenderTargets(1, &rtv, false, std::ptr::null());
+            command_list.IASetPrimitiveTopology(D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST);
+            command_list.DrawInstanced(3, 1, 0, 0);
+        }
+
+        self.target.end(barriers, &resources.texture_manager)?;
+
+        Ok(&self.target)
+    }
+}
+
+fn create_graded_target(
+    resources: &mut Resources,
+    extent: (u32, u32),
+    format: DXGI_FORMAT,
+) -> Result<RenderTarget> {
+    let mut target = RenderTarget::new(
+        &resources.device,
+        &mut resources.texture_manager,
+        &mut resources.descriptor_manager,
+        extent,
+        format,
+        [0.0; 4],
+        resources.depth_range,
+    )?;
+    // The rendered part is drawn over and the rest is never sampled
+    target.color_load = LoadOp::DontCare;
+    target.depth_load = LoadOp::DontCare;
+
+    Ok(target)
+}
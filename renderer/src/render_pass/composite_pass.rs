@@ -0,0 +1,122 @@
+use anyhow::{Context, Result};
+use d3d12_utils::{
+    create_composite_pipeline_state, create_pass_root_signature, create_pass_table_root_signature,
+    load_hlsl, srgb_format, DescriptorType, DisplayRotation, RenderTarget,
+    GLOBAL_CONSTANTS_PARAMETER, PASS_TEXTURE_PARAMETER,
+};
+use windows::Win32::Graphics::{
+    Direct3D::D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST, Direct3D12::*, Dxgi::Common::DXGI_FORMAT,
+};
+
+use crate::renderer::Resources;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct CompositeConstants {
+    pub overlay_index: u32,
+    pub quarter_turns: u32,
+}
+
+/// Blends the overlay target over the upscaled scene in linear space. Overlays pick their colours
+/// in sRGB like any UI, so drawing them straight into the back buffer would blend the encoded
+/// values, and an sRGB view would encode them a second time.
+#[derive(Debug)]
+pub struct CompositePass {
+    root_signature: ID3D12RootSignature,
+    pso: ID3D12PipelineState,
+    /// Without them the overlay is bound through `PASS_TEXTURE_PARAMETER`
+    dynamic_resources: bool,
+}
+
+impl CompositePass {
+    /// `render_target_format` is the UNORM format of the destination, which needs an sRGB view
+    pub fn new(resources: &Resources, render_target_format: DXGI_FORMAT) -> Result<Self> {
+        let dynamic_resources = resources.capabilities.dynamic_resources();
+        let num_constants = (std::mem::size_of::<CompositeConstants>() / 4) as u32;
+        // Below shader model 6.6 the shaders compile to read the overlay through a table, the root
+        // signature has to come from the same choice as the model they are compiled for
+        let (root_signature, vertex_shader, pixel_shader) = if dynamic_resources {
+            (
+                create_pass_root_signature(&resources.device, num_constants)?,
+                load_hlsl!("renderer/src/shaders/composite.hlsl", "VSMain", "vs_6_6")?,
+                load_hlsl!("renderer/src/shaders/composite.hlsl", "PSMain", "ps_6_6")?,
+            )
+        } else {
+            (
+                create_pass_table_root_signature(&resources.device, num_constants)?,
+                load_hlsl!("renderer/src/shaders/composite.hlsl", "VSMain", "vs_6_0")?,
+                load_hlsl!("renderer/src/shaders/composite.hlsl", "PSMain", "ps_6_0")?,
+            )
+        };
+
+        let pso = create_composite_pipeline_state(
+            &resources.device,
+            &root_signature,
+            &vertex_shader,
+            &pixel_shader,
+            srgb_format(render_target_format)
+                .context("Composite destination has no sRGB format")?,
+        )?;
+
+        Ok(CompositePass {
+            root_signature,
+            pso,
+            dynamic_resources,
+        })
+    }
+
+    /// Draws into a destination that is between `begin` and `end`, `overlay` has to be the same
+    /// size, turned by `rotation`, and ready to be sampled
+    pub fn render(
+        &self,
+        command_list: &ID3D12GraphicsCommandList,
+        resources: &Resources,
+        overlay: &RenderTarget,
+        destination: &RenderTarget,
+        rotation: DisplayRotation,
+    ) -> Result<()> {
+        let overlay_srv = resources.texture_manager.get_srv(&overlay.color)?;
+        let constants = CompositeConstants {
+            overlay_index: overlay_srv.index as u32,
+            quarter_turns: rotation.quarter_turns(),
+        };
+
+        let rtv = resources
+            .descriptor_manager
+            .get_cpu_handle(&destination.srgb_rtv()?)?;
+
+        unsafe {
+            command_list.SetPipelineState(&self.pso);
+            command_list.SetDescriptorHeaps(&[Some(
+                resources
+                    .descriptor_manager
+                    .get_heap(DescriptorType::Resource)?,
+            )]);
+            command_list.SetGraphicsRootSignature(&self.root_signature);
+            command_list.SetGraphicsRootConstantBufferView(
+                GLOBAL_CONSTANTS_PARAMETER,
+                resources.global_constants_address(),
+            );
+            command_list.SetGraphicsRoot32BitConstants(
+                0,
+                (std::mem::size_of::<CompositeConstants>() / 4) as u32,
+                std::ptr::addr_of!(constants) as _,
+                0,
+            );
+            if !self.dynamic_resources {
+                command_list.SetGraphicsRootDescriptorTable(
+                    PASS_TEXTURE_PARAMETER,
+                    resources.descriptor_manager.get_gpu_handle(&overlay_srv)?,
+                );
+            }
+
+            command_list.RSSetViewports(&[destination.viewport]);
+            command_list.RSSetScissorRects(&[destination.scissor_rect]);
+            command_list.OMSetRenderTargets(1, &rtv, false, std::ptr::null());
+            command_list.IASetPrimitiveTopology(D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST);
+            command_list.DrawInstanced(3, 1, 0, 0);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,115 @@
+use anyhow::Result;
+use d3d12_utils::{
+    create_compute_pipeline_state, create_pass_root_signature, load_hlsl, BarrierBatcher,
+    DescriptorType, DisplayRotation, RenderTarget, GLOBAL_CONSTANTS_PARAMETER,
+};
+use windows::Win32::Graphics::Direct3D12::*;
+
+use crate::{render_pass::upscale_pass::source_mapping, renderer::Resources};
+
+const THREAD_GROUP_SIZE: u32 = 8;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct ComputeCompositeConstants {
+    pub scene_index: u32,
+    pub sharpness: f32,
+    pub uv_scale: glam::Vec2,
+    pub texel_size: glam::Vec2,
+    pub overlay_index: u32,
+    pub destination_index: u32,
+    pub destination_size: [u32; 2],
+    pub quarter_turns: u32,
+    pub padding: u32,
+}
+
+/// The upscale and composite passes as a single compute dispatch that writes the final image
+/// straight into a back buffer UAV, without any draws. Needs a swap chain created with unordered
+/// access.
+#[derive(Debug)]
+pub struct ComputeCompositePass {
+    root_signature: ID3D12RootSignature,
+    pso: ID3D12PipelineState,
+}
+
+impl ComputeCompositePass {
+    pub fn new(resources: &Resources) -> Result<Self> {
+        let root_signature = create_pass_root_signature(
+            &resources.device,
+            (std::mem::size_of::<ComputeCompositeConstants>() / 4) as u32,
+        )?;
+        let compute_shader = load_hlsl!(
+            "renderer/src/shaders/compute_composite.hlsl",
+            "CSMain",
+            "cs_6_6"
+        )?;
+        let pso =
+            create_compute_pipeline_state(&resources.device, &root_signature, &compute_shader)?;
+
+        Ok(ComputeCompositePass {
+            root_signature,
+            pso,
+        })
+    }
+
+    /// Upscales `scene` with `sharpness` like the upscale pass and blends `overlay` over it.
+    /// Both have to be ready to be sampled, `destination` has to be in its resting state and
+    /// have a UAV. `rotation` turns the image like it does for the upscale pass.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(
+        &self,
+        command_list: &ID3D12GraphicsCommandList,
+        barriers: &mut BarrierBatcher,
+        resources: &Resources,
+        scene: &RenderTarget,
+        sharpness: f32,
+        overlay: &RenderTarget,
+        destination: &RenderTarget,
+        rotation: DisplayRotation,
+    ) -> Result<()> {
+        let texture_manager = &resources.texture_manager;
+        let (uv_scale, texel_size) = source_mapping(resources, scene)?;
+        let (width, height) = destination.extent;
+        let constants = ComputeCompositeConstants {
+            scene_index: texture_manager.get_srv(&scene.color)?.index as u32,
+            sharpness,
+            uv_scale,
+            texel_size,
+            overlay_index: texture_manager.get_srv(&overlay.color)?.index as u32,
+            destination_index: texture_manager.get_uav(&destination.color)?.index as u32,
+            destination_size: [width, height],
+            quarter_turns: rotation.quarter_turns(),
+            padding: 0,
+        };
+
+        destination.begin_unordered_access(barriers, texture_manager)?;
+        barriers.flush(command_list);
+
+        unsafe {
+            command_list.SetPipelineState(&self.pso);
+            command_list.SetDescriptorHeaps(&[Some(
+                resources
+                    .descriptor_manager
+                    .get_heap(DescriptorType::Resource)?,
+            )]);
+            command_list.SetComputeRootSignature(&self.root_signature);
+            command_list.SetComputeRootConstantBufferView(
+                GLOBAL_CONSTANTS_PARAMETER,
+                resources.global_constants_address(),
+            );
+            command_list.SetComputeRoot32BitConstants(
+                0,
+                (std::mem::size_of::<ComputeCompositeConstants>() / 4) as u32,
+                std::ptr::addr_of!(constants) as _,
+                0,
+            );
+            command_list.Dispatch(
+                width.div_ceil(THREAD_GROUP_SIZE),
+                height.div_ceil(THREAD_GROUP_SIZE),
+                1,
+            );
+        }
+
+        destination.end_unordered_access(barriers, texture_manager)
+    }
+}
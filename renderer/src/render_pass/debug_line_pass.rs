@@ -0,0 +1,166 @@
+use anyhow::{ensure, Result};
+use d3d12_utils::{
+    create_line_pipeline_state, create_pass_root_signature, load_hlsl, pack_rgba8, DebugLine,
+    DescriptorHandle, DescriptorType, DynamicVertexRing, DynamicVertices, RenderTarget,
+    ViewportRect, GLOBAL_CONSTANTS_PARAMETER,
+};
+use windows::Win32::Graphics::{
+    Direct3D::D3D_PRIMITIVE_TOPOLOGY_LINELIST, Direct3D12::*, Dxgi::Common::DXGI_FORMAT,
+};
+
+use crate::{
+    render_pass::light_culling_pass::create_structured_srv,
+    renderer::{Camera, Resources},
+};
+
+pub const MAX_DEBUG_LINES: usize = 4096;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct DebugLineConstants {
+    pub view_projection: glam::Mat4,
+    pub vertex_buffer_index: u32,
+    pub padding: [u32; 3],
+}
+
+// Has to match DebugLineVertex in debug_lines.hlsl
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct DebugLineVertex {
+    pub position: glam::Vec3,
+    /// See `pack_rgba8`
+    pub color: u32,
+}
+
+/// Lines in world space drawn on top of everything without depth, e.g. for gizmos. The lines of
+/// a frame are handed over in `begin_frame` and written straight into a ring the frames in flight
+/// share.
+#[derive(Debug)]
+pub struct DebugLinePass<const FRAME_COUNT: usize> {
+    root_signature: ID3D12RootSignature,
+    pso: ID3D12PipelineState,
+    vertices: DynamicVertexRing<FRAME_COUNT>,
+    // Over the whole ring, the vertices of a frame are selected with the first vertex of the draw
+    vertex_srv: DescriptorHandle,
+    lines: Option<DynamicVertices>,
+}
+
+impl<const FRAME_COUNT: usize> DebugLinePass<FRAME_COUNT> {
+    pub fn new(resources: &mut Resources, render_target_format: DXGI_FORMAT) -> Result<Self> {
+        let root_signature = create_pass_root_signature(
+            &resources.device,
+            (std::mem::size_of::<DebugLineConstants>() / 4) as u32,
+        )?;
+
+        let vertex_shader =
+            load_hlsl!("renderer/src/shaders/debug_lines.hlsl", "VSMain", "vs_6_6")?;
+        let pixel_shader = load_hlsl!("renderer/src/shaders/debug_lines.hlsl", "PSMain", "ps_6_6")?;
+        let pso = create_line_pipeline_state(
+            &resources.device,
+            &root_signature,
+            &vertex_shader,
+            &pixel_shader,
+            render_target_format,
+        )?;
+
+        let vertex_stride = std::mem::size_of::<DebugLineVertex>();
+        let ring_vertices = MAX_DEBUG_LINES * 2 * FRAME_COUNT;
+        let vertices = DynamicVertexRing::<FRAME_COUNT>::new(
+            &resources.device,
+            vertex_stride * ring_vertices,
+        )?;
+        let vertex_srv = create_structured_srv(
+            resources,
+            vertices.resource(),
+            0,
+            ring_vertices,
+            vertex_stride,
+        )?;
+
+        Ok(DebugLinePass {
+            root_signature,
+            pso,
+            vertices,
+            vertex_srv,
+            lines: None,
+        })
+    }
+
+    /// Call once the fence of `frame_index` has been waited on, uploads the lines of the frame
+    pub fn begin_frame(&mut self, frame_index: usize, lines: &[DebugLine]) -> Result<()> {
+        ensure!(
+            lines.len() <= MAX_DEBUG_LINES,
+            "Too many debug lines, at most {} are supported",
+            MAX_DEBUG_LINES
+        );
+
+        self.vertices.begin_frame(frame_index)?;
+        let vertices: Vec<DebugLineVertex> = lines
+            .iter()
+            .flat_map(|line| {
+                let color = pack_rgba8(line.color);
+                [line.start, line.end].map(|position| DebugLineVertex { position, color })
+            })
+            .collect();
+        self.lines = if vertices.is_empty() {
+            None
+        } else {
+            Some(self.vertices.write_vertices(&vertices)?)
+        };
+
+        Ok(())
+    }
+
+    /// Draws into `region` of a target that is between `begin` and `end`
+    pub fn render(
+        &self,
+        command_list: &ID3D12GraphicsCommandList,
+        resources: &Resources,
+        camera: &Camera,
+        render_target: &RenderTarget,
+        region: &ViewportRect,
+    ) -> Result<()> {
+        let Some(lines) = self.lines else {
+            return Ok(());
+        };
+
+        let constants = DebugLineConstants {
+            view_projection: camera.view_projection(),
+            vertex_buffer_index: self.vertex_srv.index as u32,
+            padding: [0; 3],
+        };
+
+        let (viewport, scissor_rect) = render_target.region_viewport(region);
+        let rtv_handle = resources.texture_manager.get_rtv(&render_target.color)?;
+        let rtv = resources.descriptor_manager.get_cpu_handle(&rtv_handle)?;
+
+        unsafe {
+            command_list.SetPipelineState(&self.pso);
+            command_list.SetDescriptorHeaps(&[Some(
+                resources
+                    .descriptor_manager
+                    .get_heap(DescriptorType::Resource)?,
+            )]);
+            command_list.SetGraphicsRootSignature(&self.root_signature);
+            command_list.SetGraphicsRootConstantBufferView(
+                GLOBAL_CONSTANTS_PARAMETER,
+                resources.global_constants_address(),
+            );
+            command_list.SetGraphicsRoot32BitConstants(
+                0,
+                (std::mem::size_of::<DebugLineConstants>() / 4) as u32,
+                std::ptr::addr_of!(constants) as _,
+                0,
+            );
+
+            command_list.RSSetViewports(&[viewport]);
+            command_list.RSSetScissorRects(&[scissor_rect]);
+            command_list.OMSetRenderTargets(1, &rtv, false, std::ptr::null());
+            command_list.IASetPrimitiveTopology(D3D_PRIMITIVE_TOPOLOGY_LINELIST);
+            // SV_VertexID starts at the first vertex, which indexes the whole ring
+            command_list.DrawInstanced(lines.vertex_count, 1, lines.first_vertex, 0);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,214 @@
+use anyhow::Result;
+use d3d12_utils::{
+    create_compute_pipeline_state, create_pass_root_signature, load_hlsl, BarrierBatcher,
+    CameraProjection, DescriptorType, RenderTarget, TextureDimension, TextureHandle, TextureInfo,
+    GLOBAL_CONSTANTS_PARAMETER,
+};
+use windows::Win32::Graphics::{Direct3D12::*, Dxgi::Common::DXGI_FORMAT};
+
+use crate::{
+    render_pass::motion_blur_pass::copy_over_scene, renderer::Resources, settings::DepthOfField,
+    view::View,
+};
+
+const THREAD_GROUP_SIZE: u32 = 8;
+// Of a full frame camera, which the field of view is taken to be of
+const SENSOR_HEIGHT: f32 = 0.024;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct DepthOfFieldConstants {
+    pub region: [i32; 4],
+    pub depth_to_view: glam::Vec4,
+    pub color_index: u32,
+    pub depth_index: u32,
+    pub output_index: u32,
+    pub focus_distance: f32,
+    pub coc_scale: f32,
+    pub padding: [u32; 3],
+}
+
+/// Blurs what is out of focus in the main view like a thin lens would, with the circle of
+/// confusion of each pixel following from its depth, the focus distance and the aperture.
+/// Orthographic views have everything in focus.
+#[derive(Debug)]
+pub struct DepthOfFieldPass {
+    root_signature: ID3D12RootSignature,
+    pso: ID3D12PipelineState,
+    // Rests in the unordered access state
+    blurred: TextureHandle,
+    pub settings: DepthOfField,
+}
+
+impl DepthOfFieldPass {
+    /// The blurred copy has the `extent` and `format` of the scene target
+    pub fn new(resources: &mut Resources, extent: (u32, u32), format: DXGI_FORMAT) -> Result<Self> {
+        let root_signature = create_pass_root_signature(
+            &resources.device,
+            (std::mem::size_of::<DepthOfFieldConstants>() / 4) as u32,
+        )?;
+        let compute_shader = load_hlsl!(
+            "renderer/src/shaders/depth_of_field.hlsl",
+            "Gather",
+            "cs_6_6"
+        )?;
+        let pso =
+            create_compute_pipeline_state(&resources.device, &root_signature, &compute_shader)?;
+
+        Ok(DepthOfFieldPass {
+            root_signature,
+            pso,
+            blurred: create_blurred(resources, extent, format)?,
+            settings: DepthOfField::default(),
+        })
+    }
+
+    /// Recreates the blurred copy, the GPU must be done with all frames in flight
+    pub fn resize(&mut self, resources: &mut Resources, extent: (u32, u32)) -> Result<()> {
+        let format = resources
+            .texture_manager
+            .get_texture(&self.blurred)?
+            .info
+            .format;
+        let blurred = create_blurred(resources, extent, format)?;
+        let old_blurred = std::mem::replace(&mut self.blurred, blurred);
+        resources
+            .texture_manager
+            .delete(&mut resources.descriptor_manager, old_blurred);
+
+        Ok(())
+    }
+
+    /// Blurs `view` in the colour of `scene`, which has to be ready to be sampled once `barriers`
+    /// is flushed, and is again once they are flushed after. Its depth has to be in the depth
+    /// write state, and is left in it.
+    pub fn render(
+        &self,
+        command_list: &ID3D12GraphicsCommandList,
+        barriers: &mut BarrierBatcher,
+        resources: &Resources,
+        scene: &RenderTarget,
+        view: &View,
+    ) -> Result<()> {
+        if !self.settings.enabled
+            || matches!(
+                view.camera.projection(),
+                CameraProjection::Orthographic { .. }
+            )
+        {
+            return Ok(());
+        }
+
+        let texture_manager = &resources.texture_manager;
+        let (render_width, render_height) = scene.render_extent();
+        let region = view.region.pixel_rect((render_width, render_height));
+        let projection = view.camera.projection_matrix();
+        let inverse_projection = projection.inverse();
+
+        // Thin lens, with the focal length giving the field of view on the sensor
+        let focal_length = projection.y_axis.y * SENSOR_HEIGHT / 2.0;
+        let aperture = focal_length / self.settings.f_number.max(f32::EPSILON);
+        let focus_distance = self.settings.focus_distance.max(focal_length * 2.0);
+        let coc_metres = aperture * focal_length / (focus_distance - focal_length);
+        // The circle of confusion is a radius, the sensor covers the height of the region
+        let coc_scale = coc_metres / SENSOR_HEIGHT * (region.bottom - region.top) as f32 / 2.0;
+
+        let constants = DepthOfFieldConstants {
+            region: [region.left, region.top, region.right, region.bottom],
+            depth_to_view: glam::Vec4::new(
+                inverse_projection.z_axis.z,
+                inverse_projection.w_axis.z,
+                inverse_projection.z_axis.w,
+                inverse_projection.w_axis.w,
+            ),
+            color_index: texture_manager.get_srv(&scene.color)?.index as u32,
+            depth_index: texture_manager.get_srv(&scene.depth)?.index as u32,
+            output_index: texture_manager.get_uav(&self.blurred)?.index as u32,
+            focus_distance,
+            coc_scale,
+            padding: [0; 3],
+        };
+
+        let color = &texture_manager
+            .get_texture(&scene.color)?
+            .get_resource()?
+            .device_resource;
+        let depth = &texture_manager
+            .get_texture(&scene.depth)?
+            .get_resource()?
+            .device_resource;
+        let blurred = &texture_manager
+            .get_texture(&self.blurred)?
+            .get_resource()?
+            .device_resource;
+
+        barriers.transition(
+            depth,
+            D3D12_RESOURCE_STATE_DEPTH_WRITE,
+            D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE,
+        );
+        barriers.flush(command_list);
+
+        unsafe {
+            command_list.SetPipelineState(&self.pso);
+            command_list.SetDescriptorHeaps(&[Some(
+                resources
+                    .descriptor_manager
+                    .get_heap(DescriptorType::Resource)?,
+            )]);
+            command_list.SetComputeRootSignature(&self.root_signature);
+            command_list.SetComputeRootConstantBufferView(
+                GLOBAL_CONSTANTS_PARAMETER,
+                resources.global_constants_address(),
+            );
+            command_list.SetComputeRoot32BitConstants(
+                0,
+                (std::mem::size_of::<DepthOfFieldConstants>() / 4) as u32,
+                std::ptr::addr_of!(constants) as _,
+                0,
+            );
+            command_list.Dispatch(
+                render_width.div_ceil(THREAD_GROUP_SIZE),
+                render_height.div_ceil(THREAD_GROUP_SIZE),
+                1,
+            );
+        }
+
+        barriers.transition(
+            depth,
+            D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE,
+            D3D12_RESOURCE_STATE_DEPTH_WRITE,
+        );
+        copy_over_scene(
+            command_list,
+            barriers,
+            blurred,
+            color,
+            (render_width, render_height),
+        );
+
+        Ok(())
+    }
+}
+
+fn create_blurred(
+    resources: &mut Resources,
+    extent: (u32, u32),
+    format: DXGI_FORMAT,
+) -> Result<TextureHandle> {
+    let (width, height) = extent;
+
+    resources.texture_manager.create_empty_texture(
+        &resources.device,
+        TextureInfo {
+            dimension: TextureDimension::Two(width as usize, height),
+            format,
+            is_unordered_access: true,
+            ..Default::default()
+        },
+        None,
+        D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+        &mut resources.descriptor_manager,
+        true,
+    )
+}
@@ -0,0 +1,213 @@
+use anyhow::Result;
+use d3d12_utils::{
+    create_compute_pipeline_state, create_pass_root_signature, load_hlsl, BarrierBatcher,
+    DepthRange, DepthSnapshot, DescriptorType, FrameReadback, RenderTarget, TextureDimension,
+    TextureHandle, TextureInfo, ViewportRect, GLOBAL_CONSTANTS_PARAMETER,
+};
+use windows::Win32::Graphics::{Direct3D12::*, Dxgi::Common::DXGI_FORMAT_R32_FLOAT};
+
+use crate::{renderer::Resources, view::View};
+
+const THREAD_GROUP_SIZE: u32 = 8;
+// Each texel of the read back depth covers this many pixels in both directions
+const DOWNSAMPLE_FACTOR: u32 = 8;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct DepthDownsampleConstants {
+    pub depth_index: u32,
+    pub downsampled_index: u32,
+    pub render_extent: [u32; 2],
+    pub reversed_depth: u32,
+}
+
+/// Downsamples the scene depth every frame and copies it back to the CPU without stalling, for
+/// systems like picking or line of sight checks. Snapshots arrive once the GPU has finished the
+/// frame, so they lag a few frames behind.
+#[derive(Debug)]
+pub struct DepthReadbackPass {
+    root_signature: ID3D12RootSignature,
+    pso: ID3D12PipelineState,
+    downsampled: TextureHandle,
+    readback: FrameReadback,
+    // The view each frame in flight was rendered with
+    views: Vec<Option<(glam::Mat4, ViewportRect, DepthRange)>>,
+    latest: Option<DepthSnapshot>,
+}
+
+impl DepthReadbackPass {
+    pub fn new(resources: &mut Resources, extent: (u32, u32), num_frames: usize) -> Result<Self> {
+        let root_signature = create_pass_root_signature(
+            &resources.device,
+            (std::mem::size_of::<DepthDownsampleConstants>() / 4) as u32,
+        )?;
+        let compute_shader = load_hlsl!(
+            "renderer/src/shaders/depth_downsample.hlsl",
+            "CSMain",
+            "cs_6_6"
+        )?;
+        let pso =
+            create_compute_pipeline_state(&resources.device, &root_signature, &compute_shader)?;
+
+        let (downsampled, readback) = create_downsampled_depth(resources, extent, num_frames)?;
+
+        Ok(DepthReadbackPass {
+            root_signature,
+            pso,
+            downsampled,
+            readback,
+            views: vec![None; num_frames],
+            latest: None,
+        })
+    }
+
+    /// Recreates the downsampled depth, the GPU must be done with all frames in flight
+    pub fn resize(&mut self, resources: &mut Resources, extent: (u32, u32)) -> Result<()> {
+        let (downsampled, readback) =
+            create_downsampled_depth(resources, extent, self.views.len())?;
+        let old_downsampled = std::mem::replace(&mut self.downsampled, downsampled);
+        resources
+            .texture_manager
+            .delete(&mut resources.descriptor_manager, old_downsampled);
+        self.readback = readback;
+        self.latest = None;
+
+        Ok(())
+    }
+
+    /// Picks up the snapshot of `frame_index`, call once its fence has been waited on
+    pub fn collect(&mut self, frame_index: usize) -> Result<()> {
+        if let (Some(frame), Some((view_projection, region, depth_range))) =
+            (self.readback.take(frame_index), self.views[frame_index])
+        {
+            self.latest = Some(DepthSnapshot::from_captured(
+                &frame,
+                view_projection,
+                region,
+                depth_range,
+            )?);
+        }
+
+        Ok(())
+    }
+
+    /// The most recent snapshot that made it back to the CPU
+    pub fn latest(&self) -> Option<&DepthSnapshot> {
+        self.latest.as_ref()
+    }
+
+    /// `scene` has to contain `view` already. Flushes `barriers` before reading the depth, and
+    /// leaves the transition back to the depth write state in them.
+    pub fn render(
+        &mut self,
+        command_list: &ID3D12GraphicsCommandList,
+        barriers: &mut BarrierBatcher,
+        resources: &Resources,
+        scene: &RenderTarget,
+        view: &View,
+        frame_index: usize,
+    ) -> Result<()> {
+        let (render_width, render_height) = scene.render_extent();
+        let (width, height) = downsampled_extent(scene.extent);
+        let downsampled = resources.texture_manager.get_texture(&self.downsampled)?;
+        let depth = &resources
+            .texture_manager
+            .get_texture(&scene.depth)?
+            .get_resource()?
+            .device_resource;
+
+        let constants = DepthDownsampleConstants {
+            depth_index: resources.texture_manager.get_srv(&scene.depth)?.index as u32,
+            downsampled_index: resources.texture_manager.get_uav(&self.downsampled)?.index as u32,
+            render_extent: [render_width, render_height],
+            reversed_depth: (scene.depth_range == DepthRange::Reversed) as u32,
+        };
+
+        barriers.transition(
+            depth,
+            D3D12_RESOURCE_STATE_DEPTH_WRITE,
+            D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE,
+        );
+        barriers.flush(command_list);
+
+        unsafe {
+            command_list.SetPipelineState(&self.pso);
+            command_list.SetDescriptorHeaps(&[Some(
+                resources
+                    .descriptor_manager
+                    .get_heap(DescriptorType::Resource)?,
+            )]);
+            command_list.SetComputeRootSignature(&self.root_signature);
+            command_list.SetComputeRootConstantBufferView(
+                GLOBAL_CONSTANTS_PARAMETER,
+                resources.global_constants_address(),
+            );
+            command_list.SetComputeRoot32BitConstants(
+                0,
+                (std::mem::size_of::<DepthDownsampleConstants>() / 4) as u32,
+                std::ptr::addr_of!(constants) as _,
+                0,
+            );
+            command_list.Dispatch(
+                width.div_ceil(THREAD_GROUP_SIZE),
+                height.div_ceil(THREAD_GROUP_SIZE),
+                1,
+            );
+        }
+
+        barriers.transition(
+            depth,
+            D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE,
+            D3D12_RESOURCE_STATE_DEPTH_WRITE,
+        );
+
+        self.readback.copy(
+            command_list,
+            &downsampled.get_resource()?.device_resource,
+            D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+            frame_index,
+        )?;
+        self.views[frame_index] = Some((
+            view.camera.view_projection(),
+            view.region,
+            view.camera.depth_range(),
+        ));
+
+        Ok(())
+    }
+}
+
+fn downsampled_extent(extent: (u32, u32)) -> (u32, u32) {
+    let (width, height) = extent;
+
+    (
+        width.div_ceil(DOWNSAMPLE_FACTOR),
+        height.div_ceil(DOWNSAMPLE_FACTOR),
+    )
+}
+
+fn create_downsampled_depth(
+    resources: &mut Resources,
+    extent: (u32, u32),
+    num_frames: usize,
+) -> Result<(TextureHandle, FrameReadback)> {
+    let (width, height) = downsampled_extent(extent);
+    let info = TextureInfo {
+        dimension: TextureDimension::Two(width as usize, height),
+        format: DXGI_FORMAT_R32_FLOAT,
+        is_unordered_access: true,
+        ..Default::default()
+    };
+
+    let readback = FrameReadback::new(&resources.device, &info.resource_desc(), num_frames)?;
+    let downsampled = resources.texture_manager.create_empty_texture(
+        &resources.device,
+        info,
+        None,
+        D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+        &mut resources.descriptor_manager,
+        true,
+    )?;
+
+    Ok((downsampled, readback))
+}
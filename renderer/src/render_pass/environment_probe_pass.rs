@@ -0,0 +1,431 @@
+use std::collections::VecDeque;
+
+use anyhow::{Context, Result};
+use d3d12_utils::{
+    create_compute_pipeline_state, create_pass_root_signature, cube_face_view, load_hlsl,
+    BarrierBatcher, DescriptorHandle, DescriptorType, FrameFence, RenderTarget, TargetFormats,
+    TextureDimension, TextureHandle, TextureInfo, CUBE_FACE_COUNT, GLOBAL_CONSTANTS_PARAMETER,
+};
+use windows::Win32::Graphics::{
+    Direct3D12::*,
+    Dxgi::Common::{DXGI_FORMAT, DXGI_FORMAT_R8G8B8A8_UNORM},
+};
+
+use crate::{
+    object::Object,
+    render_pass::bindless_texture_pass::BindlessTexturePass,
+    renderer::{Camera, Resources},
+};
+
+const PROBE_SIZE: u32 = 128;
+// Down to 8x8, the roughest mip is fully rough
+const PROBE_MIPS: u16 = 5;
+const PROBE_FORMAT: DXGI_FORMAT = DXGI_FORMAT_R8G8B8A8_UNORM;
+const THREAD_GROUP_SIZE: u32 = 8;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct PrefilterConstants {
+    pub source_index: u32,
+    pub destination_index: u32,
+    pub size: u32,
+    pub roughness: f32,
+}
+
+/// The surroundings of a point as a cubemap, for image based lighting. Each mip is prefiltered
+/// for a roughness from 0 at the top to 1 at the last mip.
+#[derive(Debug)]
+pub struct EnvironmentProbe {
+    pub position: glam::Vec3,
+    pub texture: TextureHandle,
+    pub num_mips: u16,
+    // Prefiltering writes each mip through its own view
+    mip_uavs: Vec<DescriptorHandle>,
+}
+
+/// Where the shading pass finds a baked probe to light objects near it with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EnvironmentLight {
+    pub position: glam::Vec3,
+    /// Of the cube view of all mips
+    pub srv_index: u32,
+    pub max_mip: f32,
+}
+
+/// The light of the probe closest to `position`
+pub fn nearest_environment(
+    lights: &[EnvironmentLight],
+    position: glam::Vec3,
+) -> Option<&EnvironmentLight> {
+    lights.iter().min_by(|a, b| {
+        a.position
+            .distance_squared(position)
+            .total_cmp(&b.position.distance_squared(position))
+    })
+}
+
+#[derive(Debug)]
+struct Bake {
+    probe: EnvironmentProbe,
+    next_face: usize,
+}
+
+/// Bakes environment probes by rendering the scene into the faces of a cubemap, one face per
+/// frame so a bake never adds more than one extra scene render to a frame. Once all faces are
+/// captured the probe is prefiltered and registered.
+#[derive(Debug)]
+pub struct EnvironmentProbePass<const FRAME_COUNT: usize> {
+    capture_pass: BindlessTexturePass<FRAME_COUNT>,
+    face_target: RenderTarget,
+    // The raw capture, rests in the copy destination state
+    capture_cube: TextureHandle,
+    root_signature: ID3D12RootSignature,
+    pso: ID3D12PipelineState,
+    queued: VecDeque<glam::Vec3>,
+    bake: Option<Bake>,
+    probes: Vec<EnvironmentProbe>,
+}
+
+impl<const FRAME_COUNT: usize> EnvironmentProbePass<FRAME_COUNT> {
+    pub fn new(resources: &mut Resources) -> Result<Self> {
+        let capture_pass =
+            BindlessTexturePass::new(resources, TargetFormats::single(PROBE_FORMAT))?;
+        let face_target = RenderTarget::new(
+            &resources.device,
+            &mut resources.texture_manager,
+            &mut resources.descriptor_manager,
+            (PROBE_SIZE, PROBE_SIZE),
+            PROBE_FORMAT,
+            [0.0, 0.0, 0.0, 1.0],
+            resources.depth_range,
+        )?;
+        let capture_cube = resources.texture_manager.create_empty_texture(
+            &resources.device,
+            TextureInfo {
+                dimension: TextureDimension::Two(PROBE_SIZE as usize, PROBE_SIZE),
+                format: PROBE_FORMAT,
+                array_size: CUBE_FACE_COUNT as u16,
+                is_cube: true,
+                ..Default::default()
+            },
+            None,
+            D3D12_RESOURCE_STATE_COPY_DEST,
+            &mut resources.descriptor_manager,
+            true,
+        )?;
+
+        let root_signature = create_pass_root_signature(
+            &resources.device,
+            (std::mem::size_of::<PrefilterConstants>() / 4) as u32,
+        )?;
+        let compute_shader = load_hlsl!(
+            "renderer/src/shaders/environment_probe.hlsl",
+            "CSMain",
+            "cs_6_6"
+        )?;
+        let pso =
+            create_compute_pipeline_state(&resources.device, &root_signature, &compute_shader)?;
+
+        Ok(EnvironmentProbePass {
+            capture_pass,
+            face_target,
+            capture_cube,
+            root_signature,
+            pso,
+            queued: VecDeque::new(),
+            bake: None,
+            probes: Vec::new(),
+        })
+    }
+
+    /// Queues a probe at `position`, it shows up in `lights` a few frames later
+    pub fn bake(&mut self, position: glam::Vec3) {
+        self.queued.push_back(position);
+    }
+
+    /// The baked probes, for the shading pass to pick the nearest of
+    pub fn lights(&self, resources: &Resources) -> Result<Vec<EnvironmentLight>> {
+        self.probes
+            .iter()
+            .map(|probe| {
+                Ok(EnvironmentLight {
+                    position: probe.position,
+                    srv_index: resources.texture_manager.get_srv(&probe.texture)?.index as u32,
+                    max_mip: (probe.num_mips - 1) as f32,
+                })
+            })
+            .collect()
+    }
+
+    /// Starts the next queued bake if none is running. Returns the camera of the face captured
+    /// this frame, the objects it sees have to be passed to `render`.
+    pub fn begin_frame(
+        &mut self,
+        resources: &mut Resources,
+        frame: FrameFence,
+    ) -> Result<Option<Camera>> {
+        if self.bake.is_none() {
+            if let Some(position) = self.queued.pop_front() {
+                self.bake = Some(Bake {
+                    probe: create_probe(resources, position)?,
+                    next_face: 0,
+                });
+            }
+        }
+
+        if self.bake.is_some() {
+            self.capture_pass.begin_frame(frame)?;
+        }
+
+        Ok(self.bake.as_ref().map(|bake| {
+            Camera::perspective(cube_face_view(bake.probe.position, bake.next_face), 1.0)
+        }))
+    }
+
+    /// Captures one face of the running bake, does nothing when no probe is being baked. Leaves
+    /// the transitions after the last copy or dispatch in `barriers`.
+    pub fn render<'a, I>(
+        &mut self,
+        command_list: &ID3D12GraphicsCommandList,
+        barriers: &mut BarrierBatcher,
+        resources: &Resources,
+        camera: &Camera,
+        objects: I,
+    ) -> Result<()>
+    where
+        I: IntoIterator<Item = (&'a Object, &'a glam::Mat4)>,
+    {
+        let Some(bake) = &mut self.bake else {
+            return Ok(());
+        };
+
+        self.capture_pass.render_to_target(
+            command_list,
+            barriers,
+            resources,
+            camera,
+            &self.face_target,
+            objects,
+        )?;
+        copy_face(
+            command_list,
+            barriers,
+            resources,
+            &self.face_target.color,
+            &self.capture_cube,
+            bake.next_face,
+        )?;
+
+        bake.next_face += 1;
+        if bake.next_face == CUBE_FACE_COUNT {
+            let bake = self.bake.take().context("No bake running")?;
+            self.prefilter(command_list, barriers, resources, &bake.probe)?;
+            self.probes.push(bake.probe);
+        }
+
+        Ok(())
+    }
+
+    fn prefilter(
+        &self,
+        command_list: &ID3D12GraphicsCommandList,
+        barriers: &mut BarrierBatcher,
+        resources: &Resources,
+        probe: &EnvironmentProbe,
+    ) -> Result<()> {
+        let capture_cube = &resources
+            .texture_manager
+            .get_texture(&self.capture_cube)?
+            .get_resource()?
+            .device_resource;
+        let probe_texture = &resources
+            .texture_manager
+            .get_texture(&probe.texture)?
+            .get_resource()?
+            .device_resource;
+
+        barriers.transition(
+            capture_cube,
+            D3D12_RESOURCE_STATE_COPY_DEST,
+            D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE,
+        );
+        barriers.flush(command_list);
+
+        unsafe {
+            command_list.SetPipelineState(&self.pso);
+            command_list.SetDescriptorHeaps(&[Some(
+                resources
+                    .descriptor_manager
+                    .get_heap(DescriptorType::Resource)?,
+            )]);
+            command_list.SetComputeRootSignature(&self.root_signature);
+            command_list.SetComputeRootConstantBufferView(
+                GLOBAL_CONSTANTS_PARAMETER,
+                resources.global_constants_address(),
+            );
+        }
+
+        for (mip, uav) in probe.mip_uavs.iter().enumerate() {
+            let size = (PROBE_SIZE >> mip).max(1);
+            let constants = PrefilterConstants {
+                source_index: resources.texture_manager.get_srv(&self.capture_cube)?.index as u32,
+                destination_index: uav.index as u32,
+                size,
+                roughness: mip as f32 / (probe.num_mips - 1).max(1) as f32,
+            };
+
+            unsafe {
+                command_list.SetComputeRoot32BitConstants(
+                    0,
+                    (std::mem::size_of::<PrefilterConstants>() / 4) as u32,
+                    std::ptr::addr_of!(constants) as _,
+                    0,
+                );
+                command_list.Dispatch(
+                    size.div_ceil(THREAD_GROUP_SIZE),
+                    size.div_ceil(THREAD_GROUP_SIZE),
+                    CUBE_FACE_COUNT as u32,
+                );
+            }
+        }
+
+        barriers.transition(
+            capture_cube,
+            D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE,
+            D3D12_RESOURCE_STATE_COPY_DEST,
+        );
+        barriers.transition(
+            probe_texture,
+            D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+            D3D12_RESOURCE_STATE_ALL_SHADER_RESOURCE,
+        );
+
+        Ok(())
+    }
+}
+
+fn create_probe(resources: &mut Resources, position: glam::Vec3) -> Result<EnvironmentProbe> {
+    let texture = resources.texture_manager.create_empty_texture(
+        &resources.device,
+        TextureInfo {
+            dimension: TextureDimension::Two(PROBE_SIZE as usize, PROBE_SIZE),
+            format: PROBE_FORMAT,
+            array_size: CUBE_FACE_COUNT as u16,
+            num_mips: PROBE_MIPS,
+            is_unordered_access: true,
+            is_cube: true,
+            ..Default::default()
+        },
+        None,
+        D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+        &mut resources.descriptor_manager,
+        false,
+    )?;
+
+    // The texture manager only creates a view of the top mip
+    let mut mip_uavs = vec![resources.texture_manager.get_uav(&texture)?];
+    for mip in 1..PROBE_MIPS {
+        mip_uavs.push(create_mip_uav(resources, &texture, mip)?);
+    }
+
+    Ok(EnvironmentProbe {
+        position,
+        texture,
+        num_mips: PROBE_MIPS,
+        mip_uavs,
+    })
+}
+
+fn create_mip_uav(
+    resources: &mut Resources,
+    texture: &TextureHandle,
+    mip: u16,
+) -> Result<DescriptorHandle> {
+    let descriptor = resources
+        .descriptor_manager
+        .allocate(DescriptorType::Resource)?;
+    let resource = &resources
+        .texture_manager
+        .get_texture(texture)?
+        .get_resource()?
+        .device_resource;
+
+    unsafe {
+        resources.device.CreateUnorderedAccessView(
+            resource,
+            None,
+            &D3D12_UNORDERED_ACCESS_VIEW_DESC {
+                Format: PROBE_FORMAT,
+                ViewDimension: D3D12_UAV_DIMENSION_TEXTURE2DARRAY,
+                Anonymous: D3D12_UNORDERED_ACCESS_VIEW_DESC_0 {
+                    Texture2DArray: D3D12_TEX2D_ARRAY_UAV {
+                        MipSlice: mip as u32,
+                        FirstArraySlice: 0,
+                        ArraySize: CUBE_FACE_COUNT as u32,
+                        PlaneSlice: 0,
+                    },
+                },
+            },
+            resources.descriptor_manager.get_cpu_handle(&descriptor)?,
+        );
+    }
+
+    Ok(descriptor)
+}
+
+/// Copies the colour of a render target resting in the shader resource state into the top mip of
+/// a cube face. The target's pending `end` transition folds into the one to the copy source.
+fn copy_face(
+    command_list: &ID3D12GraphicsCommandList,
+    barriers: &mut BarrierBatcher,
+    resources: &Resources,
+    face_texture: &TextureHandle,
+    cube: &TextureHandle,
+    face: usize,
+) -> Result<()> {
+    let face_texture = &resources
+        .texture_manager
+        .get_texture(face_texture)?
+        .get_resource()?
+        .device_resource;
+    let cube = &resources
+        .texture_manager
+        .get_texture(cube)?
+        .get_resource()?
+        .device_resource;
+
+    let from = D3D12_TEXTURE_COPY_LOCATION {
+        pResource: Some(face_texture.clone()),
+        Type: D3D12_TEXTURE_COPY_TYPE_SUBRESOURCE_INDEX,
+        Anonymous: D3D12_TEXTURE_COPY_LOCATION_0 {
+            SubresourceIndex: 0,
+        },
+    };
+    let to = D3D12_TEXTURE_COPY_LOCATION {
+        pResource: Some(cube.clone()),
+        Type: D3D12_TEXTURE_COPY_TYPE_SUBRESOURCE_INDEX,
+        Anonymous: D3D12_TEXTURE_COPY_LOCATION_0 {
+            // The capture cube has a single mip, so the face is the subresource
+            SubresourceIndex: face as u32,
+        },
+    };
+
+    barriers.transition(
+        face_texture,
+        D3D12_RESOURCE_STATE_ALL_SHADER_RESOURCE,
+        D3D12_RESOURCE_STATE_COPY_SOURCE,
+    );
+    barriers.flush(command_list);
+
+    unsafe {
+        command_list.CopyTextureRegion(&to, 0, 0, 0, &from, std::ptr::null());
+    }
+
+    barriers.transition(
+        face_texture,
+        D3D12_RESOURCE_STATE_COPY_SOURCE,
+        D3D12_RESOURCE_STATE_ALL_SHADER_RESOURCE,
+    );
+
+    Ok(())
+}
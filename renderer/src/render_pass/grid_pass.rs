@@ -0,0 +1,151 @@
+use anyhow::Result;
+use d3d12_utils::{
+    create_overlay_pipeline_state, create_pass_root_signature, load_hlsl, RenderTarget,
+    TargetFormats, ViewportRect, GLOBAL_CONSTANTS_PARAMETER,
+};
+use windows::Win32::Graphics::{
+    Direct3D::{D3D_PRIMITIVE_TOPOLOGY_LINELIST, D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST},
+    Direct3D12::*,
+};
+
+use crate::renderer::{Camera, Resources};
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct GridConstants {
+    pub view_projection: glam::Mat4,
+    pub inverse_view_projection: glam::Mat4,
+    pub camera_position: glam::Vec3,
+    pub cell_size: f32,
+    pub fade_distance: f32,
+    pub axis_length: f32,
+    pub near_depth: f32,
+    pub padding: u32,
+}
+
+/// An endless ground grid at y = 0 with the world axes on top, for finding your way around a
+/// scene. Blended over what is already drawn without writing depth.
+#[derive(Debug)]
+pub struct GridPass {
+    root_signature: ID3D12RootSignature,
+    formats: TargetFormats,
+    grid_pso: ID3D12PipelineState,
+    axis_pso: ID3D12PipelineState,
+    pub enabled: bool,
+    /// World units between two grid lines
+    pub cell_size: f32,
+    /// Distance from the camera at which the grid has faded out completely
+    pub fade_distance: f32,
+    pub axis_length: f32,
+}
+
+impl GridPass {
+    /// Draws into render targets of `formats`
+    pub fn new(resources: &Resources, formats: TargetFormats) -> Result<Self> {
+        let root_signature = create_pass_root_signature(
+            &resources.device,
+            (std::mem::size_of::<GridConstants>() / 4) as u32,
+        )?;
+
+        let grid_vertex_shader =
+            load_hlsl!("renderer/src/shaders/grid.hlsl", "GridVSMain", "vs_6_0")?;
+        let grid_pixel_shader =
+            load_hlsl!("renderer/src/shaders/grid.hlsl", "GridPSMain", "ps_6_0")?;
+        let grid_pso = create_overlay_pipeline_state(
+            &resources.device,
+            &root_signature,
+            &grid_vertex_shader,
+            &grid_pixel_shader,
+            &formats,
+            D3D12_PRIMITIVE_TOPOLOGY_TYPE_TRIANGLE,
+            resources.depth_range,
+        )?;
+
+        let axis_vertex_shader =
+            load_hlsl!("renderer/src/shaders/grid.hlsl", "AxisVSMain", "vs_6_0")?;
+        let axis_pixel_shader =
+            load_hlsl!("renderer/src/shaders/grid.hlsl", "AxisPSMain", "ps_6_0")?;
+        let axis_pso = create_overlay_pipeline_state(
+            &resources.device,
+            &root_signature,
+            &axis_vertex_shader,
+            &axis_pixel_shader,
+            &formats,
+            D3D12_PRIMITIVE_TOPOLOGY_TYPE_LINE,
+            resources.depth_range,
+        )?;
+
+        Ok(GridPass {
+            root_signature,
+            formats,
+            grid_pso,
+            axis_pso,
+            enabled: true,
+            cell_size: 1.0,
+            fade_distance: 50.0,
+            axis_length: 1.0,
+        })
+    }
+
+    /// Draws into `region` of a target that is between `begin` and `end`, after the opaque objects
+    pub fn render(
+        &self,
+        command_list: &ID3D12GraphicsCommandList,
+        resources: &Resources,
+        camera: &Camera,
+        render_target: &RenderTarget,
+        region: &ViewportRect,
+    ) -> Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+
+        let view_projection = camera.view_projection();
+        let constants = GridConstants {
+            view_projection,
+            inverse_view_projection: view_projection.inverse(),
+            camera_position: camera.position(),
+            cell_size: self.cell_size,
+            fade_distance: self.fade_distance,
+            axis_length: self.axis_length,
+            near_depth: camera.depth_range().near_depth(),
+            padding: 0,
+        };
+
+        render_target.validate_formats(&resources.texture_manager, &self.formats)?;
+
+        let (viewport, scissor_rect) = render_target.region_viewport(region);
+        let rtv_handle = resources.texture_manager.get_rtv(&render_target.color)?;
+        let rtv = resources.descriptor_manager.get_cpu_handle(&rtv_handle)?;
+        let dsv_handle = resources.texture_manager.get_dsv(&render_target.depth)?;
+        let dsv = resources.descriptor_manager.get_cpu_handle(&dsv_handle)?;
+
+        unsafe {
+            command_list.SetGraphicsRootSignature(&self.root_signature);
+            command_list.SetGraphicsRootConstantBufferView(
+                GLOBAL_CONSTANTS_PARAMETER,
+                resources.global_constants_address(),
+            );
+            command_list.SetGraphicsRoot32BitConstants(
+                0,
+                (std::mem::size_of::<GridConstants>() / 4) as u32,
+                std::ptr::addr_of!(constants) as _,
+                0,
+            );
+
+            command_list.RSSetViewports(&[viewport]);
+            command_list.RSSetScissorRects(&[scissor_rect]);
+            command_list.OMSetRenderTargets(1, &rtv, false, &dsv);
+
+            command_list.SetPipelineState(&self.grid_pso);
+            command_list.IASetPrimitiveTopology(D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST);
+            command_list.DrawInstanced(3, 1, 0, 0);
+
+            command_list.SetPipelineState(&self.axis_pso);
+            command_list.IASetPrimitiveTopology(D3D_PRIMITIVE_TOPOLOGY_LINELIST);
+            command_list.DrawInstanced(6, 1, 0, 0);
+        }
+
+        Ok(())
+    }
+}
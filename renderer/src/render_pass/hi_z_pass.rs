@@ -0,0 +1,311 @@
+use anyhow::Result;
+use d3d12_utils::{
+    create_compute_pipeline_state, create_pass_root_signature, load_hlsl, BarrierBatcher,
+    DescriptorHandle, DescriptorType, RenderTarget, TextureDimension, TextureHandle, TextureInfo,
+    GLOBAL_CONSTANTS_PARAMETER,
+};
+use windows::Win32::Graphics::{Direct3D12::*, Dxgi::Common::DXGI_FORMAT_R32_FLOAT};
+
+use crate::renderer::Resources;
+
+const THREAD_GROUP_SIZE: u32 = 8;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct HiZConstants {
+    pub source_min_index: u32,
+    pub source_max_index: u32,
+    pub destination_min_index: u32,
+    pub destination_max_index: u32,
+    pub source_size: [u32; 2],
+    pub destination_size: [u32; 2],
+}
+
+/// Where the shaders of the next passes find the depth pyramid built this frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HiZ {
+    /// Views of all mips of the nearest and furthest depth under each texel, as raw depth
+    /// values. Which one is nearest depends on the depth range.
+    pub min_index: u32,
+    pub max_index: u32,
+    pub num_mips: u32,
+    /// Of the top mip, which has a texel per pixel of the scene target
+    pub extent: (u32, u32),
+    /// The part of the top mip that was rendered to, the rest holds the whole depth range
+    pub render_extent: (u32, u32),
+}
+
+// The mips of one of the pyramids, written through views of their own that are created every
+// frame from the transient descriptors, so resizing has none to free
+#[derive(Debug)]
+struct DepthPyramid {
+    texture: TextureHandle,
+    num_mips: u16,
+}
+
+/// Builds min and max mip pyramids of the scene depth every frame, for screen space reflections,
+/// occlusion culling and cone traced effects to skip over large empty or hidden areas at once.
+/// Both rest in the shader resource state between frames.
+#[derive(Debug)]
+pub struct HiZPass {
+    root_signature: ID3D12RootSignature,
+    copy_pso: ID3D12PipelineState,
+    reduce_pso: ID3D12PipelineState,
+    extent: (u32, u32),
+    min: DepthPyramid,
+    max: DepthPyramid,
+    latest: Option<HiZ>,
+}
+
+impl HiZPass {
+    pub fn new(resources: &mut Resources, extent: (u32, u32)) -> Result<Self> {
+        let root_signature = create_pass_root_signature(
+            &resources.device,
+            (std::mem::size_of::<HiZConstants>() / 4) as u32,
+        )?;
+        let copy_shader = load_hlsl!("renderer/src/shaders/hi_z.hlsl", "CopyDepth", "cs_6_6")?;
+        let reduce_shader = load_hlsl!("renderer/src/shaders/hi_z.hlsl", "Reduce", "cs_6_6")?;
+
+        Ok(HiZPass {
+            copy_pso: create_compute_pipeline_state(
+                &resources.device,
+                &root_signature,
+                &copy_shader,
+            )?,
+            reduce_pso: create_compute_pipeline_state(
+                &resources.device,
+                &root_signature,
+                &reduce_shader,
+            )?,
+            root_signature,
+            extent,
+            min: create_pyramid(resources, extent)?,
+            max: create_pyramid(resources, extent)?,
+            latest: None,
+        })
+    }
+
+    /// Recreates the pyramids, the GPU must be done with all frames in flight
+    pub fn resize(&mut self, resources: &mut Resources, extent: (u32, u32)) -> Result<()> {
+        let min = create_pyramid(resources, extent)?;
+        let max = create_pyramid(resources, extent)?;
+        for pyramid in [
+            std::mem::replace(&mut self.min, min),
+            std::mem::replace(&mut self.max, max),
+        ] {
+            resources
+                .texture_manager
+                .delete(&mut resources.descriptor_manager, pyramid.texture);
+        }
+        self.extent = extent;
+        self.latest = None;
+
+        Ok(())
+    }
+
+    /// The pyramid built by the last `render`, None before the first one and after a resize
+    pub fn latest(&self) -> Option<HiZ> {
+        self.latest
+    }
+
+    /// Builds the pyramids from the depth of `scene`, which has to be in the depth write state
+    /// and is left in it. The pyramids can be read by shaders once `barriers` is flushed.
+    pub fn render(
+        &mut self,
+        command_list: &ID3D12GraphicsCommandList,
+        barriers: &mut BarrierBatcher,
+        resources: &mut Resources,
+        scene: &RenderTarget,
+    ) -> Result<HiZ> {
+        let min_uavs = mip_uavs(resources, &self.min)?;
+        let max_uavs = mip_uavs(resources, &self.max)?;
+
+        let texture_manager = &resources.texture_manager;
+        let resource = |texture: &TextureHandle| -> Result<ID3D12Resource> {
+            Ok(texture_manager
+                .get_texture(texture)?
+                .get_resource()?
+                .device_resource
+                .clone())
+        };
+        let depth = &resource(&scene.depth)?;
+        let min = resource(&self.min.texture)?;
+        let max = resource(&self.max.texture)?;
+
+        barriers.transition(
+            depth,
+            D3D12_RESOURCE_STATE_DEPTH_WRITE,
+            D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE,
+        );
+        for pyramid in [&min, &max] {
+            barriers.transition(
+                pyramid,
+                D3D12_RESOURCE_STATE_ALL_SHADER_RESOURCE,
+                D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+            );
+        }
+        barriers.flush(command_list);
+
+        unsafe {
+            command_list.SetDescriptorHeaps(&[Some(
+                resources
+                    .descriptor_manager
+                    .get_heap(DescriptorType::Resource)?,
+            )]);
+            command_list.SetComputeRootSignature(&self.root_signature);
+            command_list.SetComputeRootConstantBufferView(
+                GLOBAL_CONSTANTS_PARAMETER,
+                resources.global_constants_address(),
+            );
+        }
+
+        let render_extent = scene.render_extent();
+        let num_mips = min_uavs.len();
+        let depth_index = texture_manager.get_srv(&scene.depth)?.index as u32;
+        let mut source_size = render_extent;
+        for mip in 0..num_mips {
+            let destination_size = mip_extent(self.extent, mip);
+            let constants = if mip == 0 {
+                HiZConstants {
+                    source_min_index: depth_index,
+                    source_max_index: depth_index,
+                    destination_min_index: min_uavs[0].index as u32,
+                    destination_max_index: max_uavs[0].index as u32,
+                    source_size: source_size.into(),
+                    destination_size: destination_size.into(),
+                }
+            } else {
+                // Each mip reads the one written before it
+                barriers.uav(Some(&min));
+                barriers.uav(Some(&max));
+                barriers.flush(command_list);
+
+                HiZConstants {
+                    source_min_index: min_uavs[mip - 1].index as u32,
+                    source_max_index: max_uavs[mip - 1].index as u32,
+                    destination_min_index: min_uavs[mip].index as u32,
+                    destination_max_index: max_uavs[mip].index as u32,
+                    source_size: source_size.into(),
+                    destination_size: destination_size.into(),
+                }
+            };
+
+            unsafe {
+                command_list.SetPipelineState(if mip == 0 {
+                    &self.copy_pso
+                } else {
+                    &self.reduce_pso
+                });
+                command_list.SetComputeRoot32BitConstants(
+                    0,
+                    (std::mem::size_of::<HiZConstants>() / 4) as u32,
+                    std::ptr::addr_of!(constants) as _,
+                    0,
+                );
+                command_list.Dispatch(
+                    destination_size.0.div_ceil(THREAD_GROUP_SIZE),
+                    destination_size.1.div_ceil(THREAD_GROUP_SIZE),
+                    1,
+                );
+            }
+            source_size = destination_size;
+        }
+
+        barriers.transition(
+            depth,
+            D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE,
+            D3D12_RESOURCE_STATE_DEPTH_WRITE,
+        );
+        for pyramid in [&min, &max] {
+            barriers.transition(
+                pyramid,
+                D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+                D3D12_RESOURCE_STATE_ALL_SHADER_RESOURCE,
+            );
+        }
+
+        let hi_z = HiZ {
+            min_index: texture_manager.get_srv(&self.min.texture)?.index as u32,
+            max_index: texture_manager.get_srv(&self.max.texture)?.index as u32,
+            num_mips: num_mips as u32,
+            extent: self.extent,
+            render_extent,
+        };
+        self.latest = Some(hi_z);
+
+        Ok(hi_z)
+    }
+}
+
+fn mip_extent((width, height): (u32, u32), mip: usize) -> (u32, u32) {
+    ((width >> mip).max(1), (height >> mip).max(1))
+}
+
+// Down to a single texel
+fn mip_count((width, height): (u32, u32)) -> u16 {
+    (u32::BITS - width.max(height).max(1).leading_zeros()) as u16
+}
+
+fn create_pyramid(resources: &mut Resources, extent: (u32, u32)) -> Result<DepthPyramid> {
+    let (width, height) = extent;
+    let num_mips = mip_count(extent);
+    let texture = resources.texture_manager.create_empty_texture(
+        &resources.device,
+        TextureInfo {
+            dimension: TextureDimension::Two(width as usize, height),
+            format: DXGI_FORMAT_R32_FLOAT,
+            num_mips,
+            is_unordered_access: true,
+            ..Default::default()
+        },
+        None,
+        D3D12_RESOURCE_STATE_ALL_SHADER_RESOURCE,
+        &mut resources.descriptor_manager,
+        true,
+    )?;
+
+    Ok(DepthPyramid { texture, num_mips })
+}
+
+// The texture manager only creates a view of the top mip, the rest only live for the frame
+fn mip_uavs(resources: &mut Resources, pyramid: &DepthPyramid) -> Result<Vec<DescriptorHandle>> {
+    let mut uavs = vec![resources.texture_manager.get_uav(&pyramid.texture)?];
+    for mip in 1..pyramid.num_mips {
+        uavs.push(create_mip_uav(resources, &pyramid.texture, mip)?);
+    }
+
+    Ok(uavs)
+}
+
+fn create_mip_uav(
+    resources: &mut Resources,
+    texture: &TextureHandle,
+    mip: u16,
+) -> Result<DescriptorHandle> {
+    let descriptor = resources.descriptor_manager.allocate_transient()?;
+    let resource = &resources
+        .texture_manager
+        .get_texture(texture)?
+        .get_resource()?
+        .device_resource;
+
+    unsafe {
+        resources.device.CreateUnorderedAccessView(
+            resource,
+            None,
+            &D3D12_UNORDERED_ACCESS_VIEW_DESC {
+                Format: DXGI_FORMAT_R32_FLOAT,
+                ViewDimension: D3D12_UAV_DIMENSION_TEXTURE2D,
+                Anonymous: D3D12_UNORDERED_ACCESS_VIEW_DESC_0 {
+                    Texture2D: D3D12_TEX2D_UAV {
+                        MipSlice: mip as u32,
+                        PlaneSlice: 0,
+                    },
+                },
+            },
+            resources.descriptor_manager.get_cpu_handle(&descriptor)?,
+        );
+    }
+
+    Ok(descriptor)
+}
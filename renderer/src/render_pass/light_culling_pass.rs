@@ -0,0 +1,368 @@
+use anyhow::{ensure, Result};
+use d3d12_utils::{
+    create_compute_pipeline_state, create_pass_root_signature, load_hlsl, transition_barrier,
+    ClusterGrid, DescriptorHandle, DescriptorType, FrameFence, PackedPointLight, PointLight,
+    Resource, VersionedBuffer, GLOBAL_CONSTANTS_PARAMETER, MAX_LIGHTS_PER_CLUSTER,
+};
+use std::mem::ManuallyDrop;
+use windows::Win32::Graphics::{
+    Direct3D12::*,
+    Dxgi::Common::{DXGI_FORMAT_UNKNOWN, DXGI_SAMPLE_DESC},
+};
+
+use crate::{
+    render_pass::bindless_texture_pass::MAX_VIEWS,
+    renderer::{Camera, Resources},
+};
+
+pub const MAX_LIGHTS: usize = 1024;
+
+const CLUSTER_TILES_X: u32 = 16;
+const CLUSTER_TILES_Y: u32 = 9;
+const CLUSTER_SLICES: u32 = 24;
+const CLUSTER_COUNT: usize = (CLUSTER_TILES_X * CLUSTER_TILES_Y * CLUSTER_SLICES) as usize;
+const THREAD_GROUP_SIZE: u32 = 64;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct LightCullingConstants {
+    pub inverse_projection: glam::Mat4,
+    pub view: glam::Mat4,
+    pub cluster_counts: [u32; 3],
+    pub light_count: u32,
+    pub light_buffer_index: u32,
+    pub cluster_light_counts_index: u32,
+    pub cluster_light_indices_index: u32,
+    pub near_depth: f32,
+    pub slice_near: f32,
+    pub slice_far: f32,
+    pub padding: [u32; 2],
+}
+
+/// Where the shading pass finds the lights of the cluster a pixel falls into
+#[derive(Debug, Clone, Copy)]
+pub struct ClusteredLights {
+    pub light_buffer_index: u32,
+    pub cluster_light_counts_index: u32,
+    pub cluster_light_indices_index: u32,
+    pub cluster_counts: [u32; 3],
+    pub slice_scale: f32,
+    pub slice_bias: f32,
+}
+
+// The light lists of one view. Buffers decay to common after every submission, so they start
+// each frame in the common state.
+#[derive(Debug)]
+struct ClusterSlot {
+    counts: Resource,
+    indices: Resource,
+    counts_srv: DescriptorHandle,
+    counts_uav: DescriptorHandle,
+    indices_srv: DescriptorHandle,
+    indices_uav: DescriptorHandle,
+}
+
+/// Splits the frustum of each view into clusters and lists the point lights reaching each of
+/// them, so shading only loops over the lights near a pixel
+#[derive(Debug)]
+pub struct LightCullingPass<const FRAME_COUNT: usize> {
+    root_signature: ID3D12RootSignature,
+    pso: ID3D12PipelineState,
+    lights: VersionedBuffer<FRAME_COUNT>,
+    light_srvs: [DescriptorHandle; FRAME_COUNT],
+    light_count: usize,
+    slots: Vec<ClusterSlot>,
+    // Slots already used this frame
+    next_slot: usize,
+}
+
+impl<const FRAME_COUNT: usize> LightCullingPass<FRAME_COUNT> {
+    pub fn new(resources: &mut Resources) -> Result<Self> {
+        let root_signature = create_pass_root_signature(
+            &resources.device,
+            (std::mem::size_of::<LightCullingConstants>() / 4) as u32,
+        )?;
+        let compute_shader = load_hlsl!(
+            "renderer/src/shaders/light_culling.hlsl",
+            "CSMain",
+            "cs_6_6"
+        )?;
+        let pso =
+            create_compute_pipeline_state(&resources.device, &root_signature, &compute_shader)?;
+
+        let light_stride = std::mem::size_of::<PackedPointLight>();
+        let lights =
+            VersionedBuffer::<FRAME_COUNT>::new(&resources.device, light_stride * MAX_LIGHTS)?;
+        ensure!(
+            lights.version_size() % light_stride == 0,
+            "Light buffer versions have to start on a whole light"
+        );
+        let light_srvs = array_init::try_array_init(|i| {
+            create_structured_srv(
+                resources,
+                lights.resource(),
+                lights.version_size() * i / light_stride,
+                MAX_LIGHTS,
+                light_stride,
+            )
+        })?;
+
+        let slots = (0..MAX_VIEWS)
+            .map(|_| {
+                let counts = create_buffer(resources, CLUSTER_COUNT * 4)?;
+                let indices = create_buffer(resources, CLUSTER_COUNT * MAX_LIGHTS_PER_CLUSTER * 4)?;
+
+                Ok(ClusterSlot {
+                    counts_srv: create_structured_srv(resources, &counts, 0, CLUSTER_COUNT, 4)?,
+                    counts_uav: create_structured_uav(resources, &counts, CLUSTER_COUNT)?,
+                    indices_srv: create_structured_srv(
+                        resources,
+                        &indices,
+                        0,
+                        CLUSTER_COUNT * MAX_LIGHTS_PER_CLUSTER,
+                        4,
+                    )?,
+                    indices_uav: create_structured_uav(
+                        resources,
+                        &indices,
+                        CLUSTER_COUNT * MAX_LIGHTS_PER_CLUSTER,
+                    )?,
+                    counts,
+                    indices,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(LightCullingPass {
+            root_signature,
+            pso,
+            lights,
+            light_srvs,
+            light_count: 0,
+            slots,
+            next_slot: 0,
+        })
+    }
+
+    /// Call once the fence of `frame.frame_index` has been waited on, uploads the lights of the frame
+    pub fn begin_frame(&mut self, frame: FrameFence, lights: &[PointLight]) -> Result<()> {
+        ensure!(
+            lights.len() <= MAX_LIGHTS,
+            "Too many lights, at most {} are supported",
+            MAX_LIGHTS
+        );
+
+        self.lights.begin_frame(frame)?;
+        let packed: Vec<PackedPointLight> = lights.iter().map(PointLight::pack).collect();
+        self.lights.write_for_frame(frame.frame_index, &packed)?;
+        self.light_count = lights.len();
+        self.next_slot = 0;
+
+        Ok(())
+    }
+
+    /// Builds the light lists for `camera`, outside of any render target. Each call takes up one
+    /// slot, the lists stay readable by pixel shaders until the end of the frame.
+    pub fn cull(
+        &mut self,
+        command_list: &ID3D12GraphicsCommandList,
+        resources: &Resources,
+        camera: &Camera,
+    ) -> Result<ClusteredLights> {
+        let slot_index = self.next_slot;
+        ensure!(
+            slot_index < self.slots.len(),
+            "Too many views, lights can be culled for at most {} per frame",
+            self.slots.len()
+        );
+        self.next_slot += 1;
+        let slot = &self.slots[slot_index];
+
+        let (near, far) = camera.projection().clip_planes();
+        let grid = ClusterGrid {
+            tiles_x: CLUSTER_TILES_X,
+            tiles_y: CLUSTER_TILES_Y,
+            slices: CLUSTER_SLICES,
+            near,
+            far,
+        };
+        let cluster_counts = [grid.tiles_x, grid.tiles_y, grid.slices];
+        let light_buffer_index = self.light_srvs[resources.frame_index as usize].index as u32;
+
+        let constants = LightCullingConstants {
+            inverse_projection: camera.projection_matrix().inverse(),
+            view: camera.view(),
+            cluster_counts,
+            light_count: self.light_count as u32,
+            light_buffer_index,
+            cluster_light_counts_index: slot.counts_uav.index as u32,
+            cluster_light_indices_index: slot.indices_uav.index as u32,
+            near_depth: camera.depth_range().near_depth(),
+            slice_near: near,
+            slice_far: far,
+            padding: [0; 2],
+        };
+
+        self.transition(
+            command_list,
+            slot,
+            D3D12_RESOURCE_STATE_COMMON,
+            D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+        );
+
+        unsafe {
+            command_list.SetPipelineState(&self.pso);
+            command_list.SetDescriptorHeaps(&[Some(
+                resources
+                    .descriptor_manager
+                    .get_heap(DescriptorType::Resource)?,
+            )]);
+            command_list.SetComputeRootSignature(&self.root_signature);
+            command_list.SetComputeRootConstantBufferView(
+                GLOBAL_CONSTANTS_PARAMETER,
+                resources.global_constants_address(),
+            );
+            command_list.SetComputeRoot32BitConstants(
+                0,
+                (std::mem::size_of::<LightCullingConstants>() / 4) as u32,
+                std::ptr::addr_of!(constants) as _,
+                0,
+            );
+            command_list.Dispatch((CLUSTER_COUNT as u32).div_ceil(THREAD_GROUP_SIZE), 1, 1);
+        }
+
+        self.transition(
+            command_list,
+            slot,
+            D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+            D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE,
+        );
+
+        let (slice_scale, slice_bias) = grid.slice_scale_bias();
+        Ok(ClusteredLights {
+            light_buffer_index,
+            cluster_light_counts_index: slot.counts_srv.index as u32,
+            cluster_light_indices_index: slot.indices_srv.index as u32,
+            cluster_counts,
+            slice_scale,
+            slice_bias,
+        })
+    }
+
+    fn transition(
+        &self,
+        command_list: &ID3D12GraphicsCommandList,
+        slot: &ClusterSlot,
+        state_before: D3D12_RESOURCE_STATES,
+        state_after: D3D12_RESOURCE_STATES,
+    ) {
+        let barriers = [&slot.counts, &slot.indices]
+            .map(|buffer| transition_barrier(&buffer.device_resource, state_before, state_after));
+
+        unsafe {
+            command_list.ResourceBarrier(&barriers);
+        }
+
+        for barrier in barriers {
+            unsafe {
+                let _: D3D12_RESOURCE_TRANSITION_BARRIER =
+                    ManuallyDrop::into_inner(barrier.Anonymous.Transition);
+            }
+        }
+    }
+}
+
+pub(crate) fn create_buffer(resources: &Resources, size: usize) -> Result<Resource> {
+    Resource::create_committed(
+        &resources.device,
+        &D3D12_HEAP_PROPERTIES {
+            Type: D3D12_HEAP_TYPE_DEFAULT,
+            ..Default::default()
+        },
+        &D3D12_RESOURCE_DESC {
+            Dimension: D3D12_RESOURCE_DIMENSION_BUFFER,
+            Width: size as u64,
+            Height: 1,
+            DepthOrArraySize: 1,
+            MipLevels: 1,
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            Layout: D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
+            Flags: D3D12_RESOURCE_FLAG_ALLOW_UNORDERED_ACCESS,
+            ..Default::default()
+        },
+        D3D12_RESOURCE_STATE_COMMON,
+        None,
+        false,
+    )
+}
+
+pub(crate) fn create_structured_srv(
+    resources: &mut Resources,
+    buffer: &Resource,
+    first_element: usize,
+    num_elements: usize,
+    stride: usize,
+) -> Result<DescriptorHandle> {
+    let srv = resources
+        .descriptor_manager
+        .allocate(DescriptorType::Resource)?;
+
+    unsafe {
+        resources.device.CreateShaderResourceView(
+            &buffer.device_resource,
+            &D3D12_SHADER_RESOURCE_VIEW_DESC {
+                Format: DXGI_FORMAT_UNKNOWN,
+                ViewDimension: D3D12_SRV_DIMENSION_BUFFER,
+                Shader4ComponentMapping: D3D12_DEFAULT_SHADER_4_COMPONENT_MAPPING,
+                Anonymous: D3D12_SHADER_RESOURCE_VIEW_DESC_0 {
+                    Buffer: D3D12_BUFFER_SRV {
+                        FirstElement: first_element as u64,
+                        NumElements: num_elements as u32,
+                        StructureByteStride: stride as u32,
+                        Flags: D3D12_BUFFER_SRV_FLAG_NONE,
+                    },
+                },
+            },
+            resources.descriptor_manager.get_cpu_handle(&srv)?,
+        );
+    }
+
+    Ok(srv)
+}
+
+// A view of `num_elements` uints or floats
+pub(crate) fn create_structured_uav(
+    resources: &mut Resources,
+    buffer: &Resource,
+    num_elements: usize,
+) -> Result<DescriptorHandle> {
+    let uav = resources
+        .descriptor_manager
+        .allocate(DescriptorType::Resource)?;
+
+    unsafe {
+        resources.device.CreateUnorderedAccessView(
+            &buffer.device_resource,
+            None,
+            &D3D12_UNORDERED_ACCESS_VIEW_DESC {
+                Format: DXGI_FORMAT_UNKNOWN,
+                ViewDimension: D3D12_UAV_DIMENSION_BUFFER,
+                Anonymous: D3D12_UNORDERED_ACCESS_VIEW_DESC_0 {
+                    Buffer: D3D12_BUFFER_UAV {
+                        FirstElement: 0,
+                        NumElements: num_elements as u32,
+                        StructureByteStride: 4,
+                        CounterOffsetInBytes: 0,
+                        Flags: D3D12_BUFFER_UAV_FLAG_NONE,
+                    },
+                },
+            },
+            resources.descriptor_manager.get_cpu_handle(&uav)?,
+        );
+    }
+
+    Ok(uav)
+}
@@ -0,0 +1,111 @@
+use anyhow::Result;
+use d3d12_utils::{
+    create_fullscreen_pipeline_state, create_pass_root_signature, load_hlsl, RenderTarget, Stats,
+    GLOBAL_CONSTANTS_PARAMETER,
+};
+use windows::Win32::Graphics::{
+    Direct3D::D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST, Direct3D12::*, Dxgi::Common::DXGI_FORMAT,
+};
+
+use crate::renderer::Resources;
+
+// Has to match MAX_BARS in memory_hud.hlsl
+const MAX_BARS: usize = Stats::NUM_POOLS;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct MemoryHudConstants {
+    pub target_size: glam::Vec2,
+    pub num_bars: u32,
+    pub padding: u32,
+    /// x is the usage, y the peak usage
+    pub bars: [glam::Vec4; MAX_BARS],
+}
+
+/// One bar per memory pool in the top left corner, filled up to the current usage with a marker
+/// at the high-water mark. The numbers behind it come from `Resources::stats`.
+#[derive(Debug)]
+pub struct MemoryHudPass {
+    root_signature: ID3D12RootSignature,
+    pso: ID3D12PipelineState,
+    pub enabled: bool,
+}
+
+impl MemoryHudPass {
+    pub fn new(resources: &Resources, render_target_format: DXGI_FORMAT) -> Result<Self> {
+        let root_signature = create_pass_root_signature(
+            &resources.device,
+            (std::mem::size_of::<MemoryHudConstants>() / 4) as u32,
+        )?;
+
+        let vertex_shader = load_hlsl!("renderer/src/shaders/memory_hud.hlsl", "VSMain", "vs_6_0")?;
+        let pixel_shader = load_hlsl!("renderer/src/shaders/memory_hud.hlsl", "PSMain", "ps_6_0")?;
+
+        let pso = create_fullscreen_pipeline_state(
+            &resources.device,
+            &root_signature,
+            &vertex_shader,
+            &pixel_shader,
+            render_target_format,
+        )?;
+
+        Ok(MemoryHudPass {
+            root_signature,
+            pso,
+            enabled: false,
+        })
+    }
+
+    /// Draws over a target that is between `begin` and `end`
+    pub fn render(
+        &self,
+        command_list: &ID3D12GraphicsCommandList,
+        resources: &Resources,
+        render_target: &RenderTarget,
+    ) -> Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+
+        let mut bars = [glam::Vec4::ZERO; MAX_BARS];
+        for (bar, (_, _, pool)) in bars.iter_mut().zip(resources.stats().pools()) {
+            *bar = glam::Vec4::new(pool.usage(), pool.peak_usage(), 0.0, 0.0);
+        }
+
+        let constants = MemoryHudConstants {
+            target_size: glam::Vec2::new(
+                render_target.viewport.Width,
+                render_target.viewport.Height,
+            ),
+            num_bars: MAX_BARS as u32,
+            padding: 0,
+            bars,
+        };
+
+        let rtv_handle = resources.texture_manager.get_rtv(&render_target.color)?;
+        let rtv = resources.descriptor_manager.get_cpu_handle(&rtv_handle)?;
+
+        unsafe {
+            command_list.SetPipelineState(&self.pso);
+            command_list.SetGraphicsRootSignature(&self.root_signature);
+            command_list.SetGraphicsRootConstantBufferView(
+                GLOBAL_CONSTANTS_PARAMETER,
+                resources.global_constants_address(),
+            );
+            command_list.SetGraphicsRoot32BitConstants(
+                0,
+                (std::mem::size_of::<MemoryHudConstants>() / 4) as u32,
+                std::ptr::addr_of!(constants) as _,
+                0,
+            );
+
+            command_list.RSSetViewports(&[render_target.viewport]);
+            command_list.RSSetScissorRects(&[render_target.scissor_rect]);
+            command_list.OMSetRenderTargets(1, &rtv, false, std::ptr::null());
+            command_list.IASetPrimitiveTopology(D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST);
+            command_list.DrawInstanced(6, MAX_BARS as u32, 0, 0);
+        }
+
+        Ok(())
+    }
+}
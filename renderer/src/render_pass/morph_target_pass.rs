@@ -0,0 +1,193 @@
+use std::collections::HashSet;
+
+use anyhow::{ensure, Result};
+use d3d12_utils::{
+    create_compute_pipeline_state, create_pass_root_signature, load_hlsl, BarrierBatcher,
+    DescriptorHandle, DescriptorType, FrameFence, MorphOutput, MorphTargetSet, VersionedBuffer,
+    GLOBAL_CONSTANTS_PARAMETER,
+};
+use windows::Win32::Graphics::Direct3D12::*;
+
+use crate::{
+    object::Object, render_pass::light_culling_pass::create_structured_srv, renderer::Resources,
+};
+
+// Weights of all morphed meshes of a frame together
+const MAX_WEIGHTS: usize = 4096;
+const THREAD_GROUP_SIZE: u32 = 64;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct MorphTargetConstants {
+    pub vertices_index: u32,
+    pub base_vertex: u32,
+    pub deltas_index: u32,
+    pub weights_index: u32,
+    pub first_weight: u32,
+    pub num_targets: u32,
+    pub num_vertices: u32,
+    pub output_index: u32,
+}
+
+/// Blends the morph targets of the meshes objects draw at their weights into the mesh's output
+/// vertex buffer of the frame, which `MeshManager::morphed` draws from. Runs before anything
+/// draws the objects.
+#[derive(Debug)]
+pub struct MorphTargetPass<const FRAME_COUNT: usize> {
+    root_signature: ID3D12RootSignature,
+    pso: ID3D12PipelineState,
+    weights: VersionedBuffer<FRAME_COUNT>,
+    weight_srvs: [DescriptorHandle; FRAME_COUNT],
+}
+
+impl<const FRAME_COUNT: usize> MorphTargetPass<FRAME_COUNT> {
+    pub fn new(resources: &mut Resources) -> Result<Self> {
+        let root_signature = create_pass_root_signature(
+            &resources.device,
+            (std::mem::size_of::<MorphTargetConstants>() / 4) as u32,
+        )?;
+        let compute_shader =
+            load_hlsl!("renderer/src/shaders/morph_targets.hlsl", "Blend", "cs_6_6")?;
+        let pso =
+            create_compute_pipeline_state(&resources.device, &root_signature, &compute_shader)?;
+
+        let weight_stride = std::mem::size_of::<f32>();
+        let weights =
+            VersionedBuffer::<FRAME_COUNT>::new(&resources.device, weight_stride * MAX_WEIGHTS)?;
+        let weight_srvs = array_init::try_array_init(|i| {
+            create_structured_srv(
+                resources,
+                weights.resource(),
+                weights.version_size() * i / weight_stride,
+                MAX_WEIGHTS,
+                weight_stride,
+            )
+        })?;
+
+        Ok(MorphTargetPass {
+            root_signature,
+            pso,
+            weights,
+            weight_srvs,
+        })
+    }
+
+    /// Call once the fence of `frame.frame_index` has been waited on
+    pub fn begin_frame(&mut self, frame: FrameFence) -> Result<()> {
+        self.weights.begin_frame(frame)
+    }
+
+    /// Blends the meshes of the morphed `objects` at the level of detail they draw. Objects
+    /// sharing a mesh share its output, so the first one's weights are used for all of them.
+    /// Weights missing for some targets count as 0.
+    pub fn render<'a, I>(
+        &mut self,
+        command_list: &ID3D12GraphicsCommandList,
+        barriers: &mut BarrierBatcher,
+        resources: &Resources,
+        objects: I,
+    ) -> Result<()>
+    where
+        I: IntoIterator<Item = &'a Object>,
+    {
+        let frame_index = resources.frame_index as usize;
+        let mesh_manager = &resources.mesh_manager;
+
+        let mut blended = HashSet::new();
+        let mut meshes: Vec<(&MorphTargetSet, &MorphOutput, usize)> = Vec::new();
+        let mut num_weights = 0;
+        for object in objects.into_iter().filter(|object| object.is_morphed()) {
+            let mesh = mesh_manager.get_lod(&object.mesh, object.lod);
+            let Some(set) = mesh_manager.get_morph_targets(&mesh) else {
+                continue;
+            };
+            let Some(vbv) = mesh.vbv else {
+                continue;
+            };
+            if !blended.insert(vbv.BufferLocation) {
+                continue;
+            }
+
+            ensure!(
+                num_weights + set.num_targets <= MAX_WEIGHTS,
+                "Too many morph target weights, at most {} can be blended per frame",
+                MAX_WEIGHTS
+            );
+            let weights: Vec<f32> = (0..set.num_targets)
+                .map(|target| object.morph_weights.get(target).copied().unwrap_or(0.0))
+                .collect();
+            self.weights.write_for_frame_at_offset(
+                frame_index,
+                num_weights * std::mem::size_of::<f32>(),
+                &weights,
+            )?;
+            meshes.push((
+                set,
+                &set.outputs[frame_index % set.outputs.len()],
+                num_weights,
+            ));
+            num_weights += set.num_targets;
+        }
+        if meshes.is_empty() {
+            return Ok(());
+        }
+
+        // Outputs decay to common after every submission, like all buffers
+        for (_, output, _) in &meshes {
+            barriers.transition(
+                &output.buffer,
+                D3D12_RESOURCE_STATE_COMMON,
+                D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+            );
+        }
+        barriers.flush(command_list);
+
+        unsafe {
+            command_list.SetPipelineState(&self.pso);
+            command_list.SetDescriptorHeaps(&[Some(
+                resources
+                    .descriptor_manager
+                    .get_heap(DescriptorType::Resource)?,
+            )]);
+            command_list.SetComputeRootSignature(&self.root_signature);
+            command_list.SetComputeRootConstantBufferView(
+                GLOBAL_CONSTANTS_PARAMETER,
+                resources.global_constants_address(),
+            );
+        }
+
+        for (set, output, first_weight) in &meshes {
+            let constants = MorphTargetConstants {
+                vertices_index: set.vertices.index as u32,
+                base_vertex: set.base_vertex,
+                deltas_index: set.deltas.index as u32,
+                weights_index: self.weight_srvs[frame_index].index as u32,
+                first_weight: *first_weight as u32,
+                num_targets: set.num_targets as u32,
+                num_vertices: set.num_vertices as u32,
+                output_index: output.uav.index as u32,
+            };
+
+            unsafe {
+                command_list.SetComputeRoot32BitConstants(
+                    0,
+                    (std::mem::size_of::<MorphTargetConstants>() / 4) as u32,
+                    std::ptr::addr_of!(constants) as _,
+                    0,
+                );
+                command_list.Dispatch(constants.num_vertices.div_ceil(THREAD_GROUP_SIZE), 1, 1);
+            }
+        }
+
+        for (_, output, _) in &meshes {
+            barriers.transition(
+                &output.buffer,
+                D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+                D3D12_RESOURCE_STATE_VERTEX_AND_CONSTANT_BUFFER,
+            );
+        }
+        barriers.flush(command_list);
+
+        Ok(())
+    }
+}
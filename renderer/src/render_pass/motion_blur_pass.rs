@@ -0,0 +1,365 @@
+use anyhow::Result;
+use d3d12_utils::{
+    create_compute_pipeline_state, create_pass_root_signature, load_hlsl, BarrierBatcher,
+    DescriptorType, RenderTarget, TextureDimension, TextureHandle, TextureInfo,
+    GLOBAL_CONSTANTS_PARAMETER,
+};
+use windows::Win32::Graphics::{
+    Direct3D12::*,
+    Dxgi::Common::{DXGI_FORMAT, DXGI_FORMAT_R16G16_FLOAT},
+};
+
+use crate::{renderer::Resources, settings::MotionBlur, view::View};
+
+// Matches TILE_SIZE in motion_blur.hlsl
+const TILE_SIZE: u32 = 16;
+const THREAD_GROUP_SIZE: u32 = 8;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct MotionBlurConstants {
+    pub reprojection: glam::Mat4,
+    pub region: [i32; 4],
+    pub depth_to_view: glam::Vec4,
+    pub color_index: u32,
+    pub depth_index: u32,
+    pub velocity_srv_index: u32,
+    pub velocity_uav_index: u32,
+    pub tile_max_srv_index: u32,
+    pub tile_max_uav_index: u32,
+    pub neighbor_max_srv_index: u32,
+    pub neighbor_max_uav_index: u32,
+    pub output_index: u32,
+    pub velocity_scale: f32,
+    pub tile_counts: [u32; 2],
+}
+
+// All rest in the unordered access state between frames
+#[derive(Debug)]
+struct MotionBlurTextures {
+    velocity: TextureHandle,
+    tile_max: TextureHandle,
+    neighbor_max: TextureHandle,
+    blurred: TextureHandle,
+}
+
+/// Blurs the main view along the motion of the camera since the previous frame, with the tile
+/// max and neighbour max velocities bounding McGuire et al.'s reconstruction filter. Velocities
+/// come from reprojecting the depth, there is no velocity buffer rendered with the scene, so
+/// objects moving on their own aren't blurred.
+#[derive(Debug)]
+pub struct MotionBlurPass {
+    root_signature: ID3D12RootSignature,
+    velocity_pso: ID3D12PipelineState,
+    tile_max_pso: ID3D12PipelineState,
+    neighbor_max_pso: ID3D12PipelineState,
+    reconstruct_pso: ID3D12PipelineState,
+    textures: MotionBlurTextures,
+    // Of the main view when it was last blurred
+    previous_view_projection: Option<glam::Mat4>,
+    pub settings: MotionBlur,
+}
+
+impl MotionBlurPass {
+    /// The blurred copy has the `extent` and `format` of the scene target
+    pub fn new(resources: &mut Resources, extent: (u32, u32), format: DXGI_FORMAT) -> Result<Self> {
+        let root_signature = create_pass_root_signature(
+            &resources.device,
+            (std::mem::size_of::<MotionBlurConstants>() / 4) as u32,
+        )?;
+        let velocity_shader = load_hlsl!(
+            "renderer/src/shaders/motion_blur.hlsl",
+            "Velocity",
+            "cs_6_6"
+        )?;
+        let tile_max_shader =
+            load_hlsl!("renderer/src/shaders/motion_blur.hlsl", "TileMax", "cs_6_6")?;
+        let neighbor_max_shader = load_hlsl!(
+            "renderer/src/shaders/motion_blur.hlsl",
+            "NeighborMax",
+            "cs_6_6"
+        )?;
+        let reconstruct_shader = load_hlsl!(
+            "renderer/src/shaders/motion_blur.hlsl",
+            "Reconstruct",
+            "cs_6_6"
+        )?;
+        let create_pso =
+            |shader| create_compute_pipeline_state(&resources.device, &root_signature, shader);
+
+        Ok(MotionBlurPass {
+            velocity_pso: create_pso(&velocity_shader)?,
+            tile_max_pso: create_pso(&tile_max_shader)?,
+            neighbor_max_pso: create_pso(&neighbor_max_shader)?,
+            reconstruct_pso: create_pso(&reconstruct_shader)?,
+            root_signature,
+            textures: create_textures(resources, extent, format)?,
+            previous_view_projection: None,
+            settings: MotionBlur::default(),
+        })
+    }
+
+    /// Recreates the textures, the GPU must be done with all frames in flight
+    pub fn resize(&mut self, resources: &mut Resources, extent: (u32, u32)) -> Result<()> {
+        let format = resources
+            .texture_manager
+            .get_texture(&self.textures.blurred)?
+            .info
+            .format;
+        let textures = create_textures(resources, extent, format)?;
+        let old = std::mem::replace(&mut self.textures, textures);
+        for texture in [old.velocity, old.tile_max, old.neighbor_max, old.blurred] {
+            resources
+                .texture_manager
+                .delete(&mut resources.descriptor_manager, texture);
+        }
+
+        Ok(())
+    }
+
+    /// Blurs `view` in the colour of `scene`, which has to be ready to be sampled once `barriers`
+    /// is flushed, and is again once they are flushed after. Its depth has to be in the depth
+    /// write state, and is left in it.
+    pub fn render(
+        &mut self,
+        command_list: &ID3D12GraphicsCommandList,
+        barriers: &mut BarrierBatcher,
+        resources: &Resources,
+        scene: &RenderTarget,
+        view: &View,
+    ) -> Result<()> {
+        if !self.settings.enabled {
+            // Re-enabled, it starts over instead of blurring towards where the camera once was
+            self.previous_view_projection = None;
+            return Ok(());
+        }
+        let view_projection = view.camera.view_projection();
+        let Some(previous_view_projection) = self.previous_view_projection.replace(view_projection)
+        else {
+            // Nothing to blur towards yet
+            return Ok(());
+        };
+
+        let texture_manager = &resources.texture_manager;
+        let (render_width, render_height) = scene.render_extent();
+        let tile_counts = [
+            render_width.div_ceil(TILE_SIZE),
+            render_height.div_ceil(TILE_SIZE),
+        ];
+        let region = view.region.pixel_rect((render_width, render_height));
+        let inverse_projection = view.camera.projection_matrix().inverse();
+
+        let constants = MotionBlurConstants {
+            reprojection: previous_view_projection * view_projection.inverse(),
+            region: [region.left, region.top, region.right, region.bottom],
+            depth_to_view: glam::Vec4::new(
+                inverse_projection.z_axis.z,
+                inverse_projection.w_axis.z,
+                inverse_projection.z_axis.w,
+                inverse_projection.w_axis.w,
+            ),
+            color_index: texture_manager.get_srv(&scene.color)?.index as u32,
+            depth_index: texture_manager.get_srv(&scene.depth)?.index as u32,
+            velocity_srv_index: texture_manager.get_srv(&self.textures.velocity)?.index as u32,
+            velocity_uav_index: texture_manager.get_uav(&self.textures.velocity)?.index as u32,
+            tile_max_srv_index: texture_manager.get_srv(&self.textures.tile_max)?.index as u32,
+            tile_max_uav_index: texture_manager.get_uav(&self.textures.tile_max)?.index as u32,
+            neighbor_max_srv_index: texture_manager.get_srv(&self.textures.neighbor_max)?.index
+                as u32,
+            neighbor_max_uav_index: texture_manager.get_uav(&self.textures.neighbor_max)?.index
+                as u32,
+            output_index: texture_manager.get_uav(&self.textures.blurred)?.index as u32,
+            velocity_scale: self.settings.shutter_angle.clamp(0.0, 360.0) / 360.0,
+            tile_counts,
+        };
+
+        let resource = |texture: &TextureHandle| -> Result<ID3D12Resource> {
+            Ok(texture_manager
+                .get_texture(texture)?
+                .get_resource()?
+                .device_resource
+                .clone())
+        };
+        let color = resource(&scene.color)?;
+        let depth = resource(&scene.depth)?;
+        let velocity = resource(&self.textures.velocity)?;
+        let tile_max = resource(&self.textures.tile_max)?;
+        let neighbor_max = resource(&self.textures.neighbor_max)?;
+        let blurred = resource(&self.textures.blurred)?;
+
+        barriers.transition(
+            &depth,
+            D3D12_RESOURCE_STATE_DEPTH_WRITE,
+            D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE,
+        );
+        barriers.flush(command_list);
+
+        unsafe {
+            command_list.SetDescriptorHeaps(&[Some(
+                resources
+                    .descriptor_manager
+                    .get_heap(DescriptorType::Resource)?,
+            )]);
+            command_list.SetComputeRootSignature(&self.root_signature);
+            command_list.SetComputeRootConstantBufferView(
+                GLOBAL_CONSTANTS_PARAMETER,
+                resources.global_constants_address(),
+            );
+            command_list.SetComputeRoot32BitConstants(
+                0,
+                (std::mem::size_of::<MotionBlurConstants>() / 4) as u32,
+                std::ptr::addr_of!(constants) as _,
+                0,
+            );
+
+            command_list.SetPipelineState(&self.velocity_pso);
+            command_list.Dispatch(
+                render_width.div_ceil(THREAD_GROUP_SIZE),
+                render_height.div_ceil(THREAD_GROUP_SIZE),
+                1,
+            );
+        }
+
+        // Each step reads what the one before wrote. The tile max has a thread group per tile.
+        for (written, pso, [groups_x, groups_y]) in [
+            (&velocity, &self.tile_max_pso, tile_counts),
+            (
+                &tile_max,
+                &self.neighbor_max_pso,
+                tile_counts.map(|count| count.div_ceil(THREAD_GROUP_SIZE)),
+            ),
+            (
+                &neighbor_max,
+                &self.reconstruct_pso,
+                [render_width, render_height].map(|size| size.div_ceil(THREAD_GROUP_SIZE)),
+            ),
+        ] {
+            barriers.transition(
+                written,
+                D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+                D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE,
+            );
+            barriers.flush(command_list);
+
+            unsafe {
+                command_list.SetPipelineState(pso);
+                command_list.Dispatch(groups_x, groups_y, 1);
+            }
+        }
+
+        for texture in [&velocity, &tile_max, &neighbor_max] {
+            barriers.transition(
+                texture,
+                D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE,
+                D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+            );
+        }
+        barriers.transition(
+            &depth,
+            D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE,
+            D3D12_RESOURCE_STATE_DEPTH_WRITE,
+        );
+        copy_over_scene(
+            command_list,
+            barriers,
+            &blurred,
+            &color,
+            (render_width, render_height),
+        );
+
+        Ok(())
+    }
+}
+
+/// Copies the rendered part of `filtered`, a texture in the unordered access state, back over
+/// `scene_color`, so the passes after a filter don't need to know about it. Leaves the
+/// transitions back to where both started in `barriers`.
+pub(crate) fn copy_over_scene(
+    command_list: &ID3D12GraphicsCommandList,
+    barriers: &mut BarrierBatcher,
+    filtered: &ID3D12Resource,
+    scene_color: &ID3D12Resource,
+    render_extent: (u32, u32),
+) {
+    let (render_width, render_height) = render_extent;
+
+    barriers.transition(
+        filtered,
+        D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+        D3D12_RESOURCE_STATE_COPY_SOURCE,
+    );
+    barriers.transition(
+        scene_color,
+        D3D12_RESOURCE_STATE_ALL_SHADER_RESOURCE,
+        D3D12_RESOURCE_STATE_COPY_DEST,
+    );
+    barriers.flush(command_list);
+
+    let from = D3D12_TEXTURE_COPY_LOCATION {
+        pResource: Some(filtered.clone()),
+        Type: D3D12_TEXTURE_COPY_TYPE_SUBRESOURCE_INDEX,
+        Anonymous: D3D12_TEXTURE_COPY_LOCATION_0 {
+            SubresourceIndex: 0,
+        },
+    };
+    let to = D3D12_TEXTURE_COPY_LOCATION {
+        pResource: Some(scene_color.clone()),
+        Type: D3D12_TEXTURE_COPY_TYPE_SUBRESOURCE_INDEX,
+        Anonymous: D3D12_TEXTURE_COPY_LOCATION_0 {
+            SubresourceIndex: 0,
+        },
+    };
+    let rendered = D3D12_BOX {
+        left: 0,
+        top: 0,
+        front: 0,
+        right: render_width,
+        bottom: render_height,
+        back: 1,
+    };
+    unsafe {
+        command_list.CopyTextureRegion(&to, 0, 0, 0, &from, &rendered);
+    }
+
+    barriers.transition(
+        filtered,
+        D3D12_RESOURCE_STATE_COPY_SOURCE,
+        D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+    );
+    barriers.transition(
+        scene_color,
+        D3D12_RESOURCE_STATE_COPY_DEST,
+        D3D12_RESOURCE_STATE_ALL_SHADER_RESOURCE,
+    );
+}
+
+fn create_textures(
+    resources: &mut Resources,
+    extent: (u32, u32),
+    format: DXGI_FORMAT,
+) -> Result<MotionBlurTextures> {
+    let (width, height) = extent;
+    let tile_extent = (width.div_ceil(TILE_SIZE), height.div_ceil(TILE_SIZE));
+    let mut create = |(width, height): (u32, u32), format: DXGI_FORMAT| {
+        resources.texture_manager.create_empty_texture(
+            &resources.device,
+            TextureInfo {
+                dimension: TextureDimension::Two(width as usize, height),
+                format,
+                is_unordered_access: true,
+                ..Default::default()
+            },
+            None,
+            D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+            &mut resources.descriptor_manager,
+            true,
+        )
+    };
+
+    Ok(MotionBlurTextures {
+        velocity: create(extent, DXGI_FORMAT_R16G16_FLOAT)?,
+        tile_max: create(tile_extent, DXGI_FORMAT_R16G16_FLOAT)?,
+        neighbor_max: create(tile_extent, DXGI_FORMAT_R16G16_FLOAT)?,
+        blurred: create(extent, format)?,
+    })
+}
@@ -0,0 +1,318 @@
+use anyhow::{ensure, Result};
+use d3d12_utils::{
+    create_compute_pipeline_state, create_pass_root_signature, load_hlsl, BarrierBatcher,
+    DepthRange, DescriptorHandle, DescriptorType, FrameFence, Resource, VersionedBuffer,
+    GLOBAL_CONSTANTS_PARAMETER,
+};
+use windows::Win32::Graphics::Direct3D12::*;
+
+use crate::{
+    render_pass::{
+        bindless_texture_pass::{
+            DrawGeometry, PreparedView, DRAW_ARGUMENTS_STRIDE, MAX_OBJECTS, MAX_VIEWS,
+            MESHLETS_PER_GROUP,
+        },
+        hi_z_pass::HiZ,
+        light_culling_pass::{create_buffer, create_structured_srv, create_structured_uav},
+    },
+    renderer::Resources,
+    view::View,
+};
+
+const THREAD_GROUP_SIZE: u32 = 64;
+const ARGUMENT_DWORDS: usize = DRAW_ARGUMENTS_STRIDE / 4;
+
+// Matches the flags in occlusion_culling.hlsl
+const MESHLETS: u32 = 1;
+const UNBOUNDED: u32 = 2;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct CullObject {
+    pub bounds_min: glam::Vec3,
+    // Indices, or thread groups for meshlets
+    pub count: u32,
+    pub bounds_max: glam::Vec3,
+    pub flags: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct OcclusionCullingConstants {
+    pub view_projection: glam::Mat4,
+    pub region: [f32; 4],
+    pub hi_z_size: [u32; 2],
+    pub hi_z_min_index: u32,
+    pub hi_z_max_index: u32,
+    pub hi_z_mips: u32,
+    pub objects_index: u32,
+    pub arguments_index: u32,
+    pub first_phase_index: u32,
+    pub first_slot: u32,
+    pub draw_count: u32,
+    pub reversed_depth: u32,
+    pub second_phase: u32,
+}
+
+// The indirect arguments of every object slot for one phase. Buffers decay to common after
+// every submission, so they start each frame in the common state.
+#[derive(Debug)]
+struct DrawArguments {
+    buffer: Resource,
+    srv: DescriptorHandle,
+    uav: DescriptorHandle,
+}
+
+/// Leaves the scene's objects hidden behind others out of their draws, on the GPU. The first
+/// phase tests the bounds of every object against the Hi-Z pyramid of the previous frame, and the
+/// second tests the ones it left out again against a pyramid of what the first drew, so objects
+/// coming into view are at most a phase late rather than a frame. Each phase writes the indirect
+/// arguments of all draws of the frame, with the counts of hidden ones at 0.
+#[derive(Debug)]
+pub struct OcclusionCullingPass<const FRAME_COUNT: usize> {
+    root_signature: ID3D12RootSignature,
+    pso: ID3D12PipelineState,
+    objects: VersionedBuffer<FRAME_COUNT>,
+    object_srvs: [DescriptorHandle; FRAME_COUNT],
+    phases: [DrawArguments; 2],
+    // What the previous frame's pyramid was seen from, for each view
+    previous_view_projections: [Option<glam::Mat4>; MAX_VIEWS],
+}
+
+impl<const FRAME_COUNT: usize> OcclusionCullingPass<FRAME_COUNT> {
+    pub fn new(resources: &mut Resources) -> Result<Self> {
+        let root_signature = create_pass_root_signature(
+            &resources.device,
+            (std::mem::size_of::<OcclusionCullingConstants>() / 4) as u32,
+        )?;
+        let compute_shader = load_hlsl!(
+            "renderer/src/shaders/occlusion_culling.hlsl",
+            "Cull",
+            "cs_6_6"
+        )?;
+        let pso =
+            create_compute_pipeline_state(&resources.device, &root_signature, &compute_shader)?;
+
+        let object_stride = std::mem::size_of::<CullObject>();
+        let objects =
+            VersionedBuffer::<FRAME_COUNT>::new(&resources.device, object_stride * MAX_OBJECTS)?;
+        ensure!(
+            objects.version_size() % object_stride == 0,
+            "Object buffer versions have to start on a whole object"
+        );
+        let object_srvs = array_init::try_array_init(|i| {
+            create_structured_srv(
+                resources,
+                objects.resource(),
+                objects.version_size() * i / object_stride,
+                MAX_OBJECTS,
+                object_stride,
+            )
+        })?;
+
+        let num_dwords = MAX_OBJECTS * ARGUMENT_DWORDS;
+        let mut create_arguments = || -> Result<DrawArguments> {
+            let buffer = create_buffer(resources, num_dwords * 4)?;
+
+            Ok(DrawArguments {
+                srv: create_structured_srv(resources, &buffer, 0, num_dwords, 4)?,
+                uav: create_structured_uav(resources, &buffer, num_dwords)?,
+                buffer,
+            })
+        };
+        let phases = [create_arguments()?, create_arguments()?];
+
+        Ok(OcclusionCullingPass {
+            root_signature,
+            pso,
+            objects,
+            object_srvs,
+            phases,
+            previous_view_projections: [None; MAX_VIEWS],
+        })
+    }
+
+    /// Call once the fence of `frame.frame_index` has been waited on
+    pub fn begin_frame(&mut self, frame: FrameFence) -> Result<()> {
+        self.objects.begin_frame(frame)
+    }
+
+    /// Writes the arguments of the draws of `prepared`, prepared for `views` in the same order,
+    /// leaving out what `hi_z` of the previous frame hides. Without it nothing is left out. The
+    /// arguments are ready for the draws once this returns.
+    pub fn cull_first_phase(
+        &mut self,
+        command_list: &ID3D12GraphicsCommandList,
+        barriers: &mut BarrierBatcher,
+        resources: &Resources,
+        hi_z: Option<HiZ>,
+        views: &[View],
+        prepared: &[PreparedView],
+    ) -> Result<ID3D12Resource> {
+        let frame_index = resources.frame_index as usize;
+        for draw in prepared.iter().flat_map(PreparedView::draws) {
+            let (count, meshlets) = match draw.geometry {
+                DrawGeometry::Meshlets(num_meshlets) => {
+                    (num_meshlets.div_ceil(MESHLETS_PER_GROUP), MESHLETS)
+                }
+                DrawGeometry::Indexed { num_indices, .. } => (num_indices, 0),
+            };
+            let bounds = draw.bounds.unwrap_or_default();
+            self.objects.write_for_frame_at_offset(
+                frame_index,
+                draw.slot * std::mem::size_of::<CullObject>(),
+                &[CullObject {
+                    bounds_min: bounds.min,
+                    count,
+                    bounds_max: bounds.max,
+                    flags: meshlets | if draw.bounds.is_none() { UNBOUNDED } else { 0 },
+                }],
+            )?;
+        }
+
+        let view_projections = self.previous_view_projections[..views.len()].to_vec();
+        self.cull(
+            command_list,
+            barriers,
+            resources,
+            hi_z,
+            views,
+            &view_projections,
+            prepared,
+            false,
+        )
+    }
+
+    /// Writes the arguments of the draws the first phase left out and `hi_z`, built from what it
+    /// drew, no longer hides. Every draw of the first phase is left out, so both can be drawn.
+    pub fn cull_second_phase(
+        &mut self,
+        command_list: &ID3D12GraphicsCommandList,
+        barriers: &mut BarrierBatcher,
+        resources: &Resources,
+        hi_z: HiZ,
+        views: &[View],
+        prepared: &[PreparedView],
+    ) -> Result<ID3D12Resource> {
+        // The next frame's first phase tests against this frame's pyramid
+        self.previous_view_projections = [None; MAX_VIEWS];
+        for (previous, view) in self.previous_view_projections.iter_mut().zip(views) {
+            *previous = Some(view.camera.view_projection());
+        }
+
+        let view_projections: Vec<Option<glam::Mat4>> = views
+            .iter()
+            .map(|view| Some(view.camera.view_projection()))
+            .collect();
+        self.cull(
+            command_list,
+            barriers,
+            resources,
+            Some(hi_z),
+            views,
+            &view_projections,
+            prepared,
+            true,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn cull(
+        &self,
+        command_list: &ID3D12GraphicsCommandList,
+        barriers: &mut BarrierBatcher,
+        resources: &Resources,
+        hi_z: Option<HiZ>,
+        views: &[View],
+        view_projections: &[Option<glam::Mat4>],
+        prepared: &[PreparedView],
+        second_phase: bool,
+    ) -> Result<ID3D12Resource> {
+        let [first_phase, second] = &self.phases;
+        let arguments = if second_phase { second } else { first_phase };
+        if second_phase {
+            barriers.transition(
+                &first_phase.buffer.device_resource,
+                D3D12_RESOURCE_STATE_INDIRECT_ARGUMENT,
+                D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE,
+            );
+        }
+        barriers.transition(
+            &arguments.buffer.device_resource,
+            D3D12_RESOURCE_STATE_COMMON,
+            D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+        );
+        barriers.flush(command_list);
+
+        unsafe {
+            command_list.SetPipelineState(&self.pso);
+            command_list.SetDescriptorHeaps(&[Some(
+                resources
+                    .descriptor_manager
+                    .get_heap(DescriptorType::Resource)?,
+            )]);
+            command_list.SetComputeRootSignature(&self.root_signature);
+            command_list.SetComputeRootConstantBufferView(
+                GLOBAL_CONSTANTS_PARAMETER,
+                resources.global_constants_address(),
+            );
+        }
+
+        for ((view, view_projection), prepared) in views.iter().zip(view_projections).zip(prepared)
+        {
+            let Some(first_draw) = prepared.draws().first() else {
+                continue;
+            };
+
+            // Without a pyramid to test against, or a camera it was seen from, everything is drawn
+            let (hi_z, view_projection) = match (hi_z, view_projection) {
+                (Some(hi_z), Some(view_projection)) => (Some(hi_z), *view_projection),
+                _ => (None, glam::Mat4::IDENTITY),
+            };
+            let region = hi_z.map_or([0.0; 4], |hi_z| {
+                let rect = view.region.pixel_rect(hi_z.render_extent);
+                [
+                    rect.left as f32,
+                    rect.top as f32,
+                    (rect.right - rect.left) as f32,
+                    (rect.bottom - rect.top) as f32,
+                ]
+            });
+
+            let constants = OcclusionCullingConstants {
+                view_projection,
+                region,
+                hi_z_size: hi_z.map_or([0; 2], |hi_z| hi_z.extent.into()),
+                hi_z_min_index: hi_z.map_or(0, |hi_z| hi_z.min_index),
+                hi_z_max_index: hi_z.map_or(0, |hi_z| hi_z.max_index),
+                hi_z_mips: hi_z.map_or(0, |hi_z| hi_z.num_mips),
+                objects_index: self.object_srvs[resources.frame_index as usize].index as u32,
+                arguments_index: arguments.uav.index as u32,
+                first_phase_index: first_phase.srv.index as u32,
+                first_slot: first_draw.slot as u32,
+                draw_count: prepared.draws().len() as u32,
+                reversed_depth: (view.camera.depth_range() == DepthRange::Reversed) as u32,
+                second_phase: second_phase as u32,
+            };
+
+            unsafe {
+                command_list.SetComputeRoot32BitConstants(
+                    0,
+                    (std::mem::size_of::<OcclusionCullingConstants>() / 4) as u32,
+                    std::ptr::addr_of!(constants) as _,
+                    0,
+                );
+                command_list.Dispatch(constants.draw_count.div_ceil(THREAD_GROUP_SIZE), 1, 1);
+            }
+        }
+
+        barriers.transition(
+            &arguments.buffer.device_resource,
+            D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+            D3D12_RESOURCE_STATE_INDIRECT_ARGUMENT,
+        );
+        barriers.flush(command_list);
+
+        Ok(arguments.buffer.device_resource.clone())
+    }
+}
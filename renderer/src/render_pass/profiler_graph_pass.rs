@@ -0,0 +1,191 @@
+use anyhow::Result;
+use d3d12_utils::{
+    create_fullscreen_pipeline_state, create_pass_root_signature, load_hlsl, plot_scale,
+    DescriptorHandle, DescriptorType, FrameFence, FrameProfile, ProfilerHistory, RenderTarget,
+    VersionedBuffer, GLOBAL_CONSTANTS_PARAMETER,
+};
+use windows::Win32::Graphics::{
+    Direct3D::D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST, Direct3D12::*, Dxgi::Common::DXGI_FORMAT,
+};
+
+use crate::{render_pass::light_culling_pass::create_structured_srv, renderer::Resources};
+
+// Has to match MAX_GRAPHS in profiler_graphs.hlsl, the frame and the first passes to run
+const MAX_GRAPHS: usize = 16;
+// Frames each graph goes back
+const HISTORY_LENGTH: usize = 240;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct ProfilerGraphConstants {
+    pub target_size: glam::Vec2,
+    pub samples_index: u32,
+    pub history_length: u32,
+    pub head: u32,
+    pub num_graphs: u32,
+    pub budget_ms: f32,
+    pub padding: u32,
+    pub scales: [glam::Vec4; MAX_GRAPHS / 4],
+}
+
+/// Graphs of the GPU time of recent frames in the top right corner, with a line at the frame
+/// budget, and a sparkline below it for each pass, in the order they first ran. Each shows its
+/// latest time in milliseconds. The samples are recorded on the CPU and copied over as they are
+/// every frame the graphs are drawn.
+#[derive(Debug)]
+pub struct ProfilerGraphPass<const FRAME_COUNT: usize> {
+    root_signature: ID3D12RootSignature,
+    pso: ID3D12PipelineState,
+    samples: VersionedBuffer<FRAME_COUNT>,
+    sample_srvs: [DescriptorHandle; FRAME_COUNT],
+    history: ProfilerHistory,
+    pub enabled: bool,
+    /// Milliseconds marked on the frame graph
+    pub budget_ms: f32,
+}
+
+impl<const FRAME_COUNT: usize> ProfilerGraphPass<FRAME_COUNT> {
+    pub fn new(
+        resources: &mut Resources,
+        render_target_format: DXGI_FORMAT,
+        budget_ms: f32,
+    ) -> Result<Self> {
+        let root_signature = create_pass_root_signature(
+            &resources.device,
+            (std::mem::size_of::<ProfilerGraphConstants>() / 4) as u32,
+        )?;
+
+        let vertex_shader = load_hlsl!(
+            "renderer/src/shaders/profiler_graphs.hlsl",
+            "VSMain",
+            "vs_6_6"
+        )?;
+        let pixel_shader = load_hlsl!(
+            "renderer/src/shaders/profiler_graphs.hlsl",
+            "PSMain",
+            "ps_6_6"
+        )?;
+
+        let pso = create_fullscreen_pipeline_state(
+            &resources.device,
+            &root_signature,
+            &vertex_shader,
+            &pixel_shader,
+            render_target_format,
+        )?;
+
+        let sample_stride = std::mem::size_of::<f32>();
+        let num_samples = MAX_GRAPHS * HISTORY_LENGTH;
+        let samples =
+            VersionedBuffer::<FRAME_COUNT>::new(&resources.device, sample_stride * num_samples)?;
+        let sample_srvs = array_init::try_array_init(|i| {
+            create_structured_srv(
+                resources,
+                samples.resource(),
+                samples.version_size() * i / sample_stride,
+                num_samples,
+                sample_stride,
+            )
+        })?;
+
+        Ok(ProfilerGraphPass {
+            root_signature,
+            pso,
+            samples,
+            sample_srvs,
+            history: ProfilerHistory::new(HISTORY_LENGTH, MAX_GRAPHS - 1),
+            enabled: false,
+            budget_ms,
+        })
+    }
+
+    /// Call once the fence of `frame.frame_index` has been waited on
+    pub fn begin_frame(&mut self, frame: FrameFence) -> Result<()> {
+        self.samples.begin_frame(frame)
+    }
+
+    /// Adds a completed frame to the graphs, also while they are hidden so they have a history
+    /// when shown
+    pub fn record(&mut self, profile: &FrameProfile) {
+        self.history.record(profile);
+    }
+
+    /// Draws over a target that is between `begin` and `end`
+    pub fn render(
+        &self,
+        command_list: &ID3D12GraphicsCommandList,
+        resources: &Resources,
+        render_target: &RenderTarget,
+    ) -> Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+
+        let frame_index = resources.frame_index as usize;
+        let frame = self.history.frame();
+        let graphs =
+            std::iter::once(frame).chain(self.history.passes().iter().map(|(_, ring)| ring));
+        let mut scales = [glam::Vec4::ZERO; MAX_GRAPHS / 4];
+        let mut num_graphs = 0;
+        for (graph, ring) in graphs.enumerate() {
+            self.samples.write_for_frame_at_offset(
+                frame_index,
+                graph * HISTORY_LENGTH * std::mem::size_of::<f32>(),
+                ring.as_slice(),
+            )?;
+            // The frame graph always has room for its budget line
+            let max = if graph == 0 {
+                ring.max().max(self.budget_ms)
+            } else {
+                ring.max()
+            };
+            scales[graph / 4][graph % 4] = plot_scale(max);
+            num_graphs += 1;
+        }
+
+        let constants = ProfilerGraphConstants {
+            target_size: glam::Vec2::new(
+                render_target.viewport.Width,
+                render_target.viewport.Height,
+            ),
+            samples_index: self.sample_srvs[frame_index].index as u32,
+            history_length: HISTORY_LENGTH as u32,
+            head: self.history.head() as u32,
+            num_graphs,
+            budget_ms: self.budget_ms,
+            padding: 0,
+            scales,
+        };
+
+        let rtv_handle = resources.texture_manager.get_rtv(&render_target.color)?;
+        let rtv = resources.descriptor_manager.get_cpu_handle(&rtv_handle)?;
+
+        unsafe {
+            command_list.SetPipelineState(&self.pso);
+            command_list.SetDescriptorHeaps(&[Some(
+                resources
+                    .descriptor_manager
+                    .get_heap(DescriptorType::Resource)?,
+            )]);
+            command_list.SetGraphicsRootSignature(&self.root_signature);
+            command_list.SetGraphicsRootConstantBufferView(
+                GLOBAL_CONSTANTS_PARAMETER,
+                resources.global_constants_address(),
+            );
+            command_list.SetGraphicsRoot32BitConstants(
+                0,
+                (std::mem::size_of::<ProfilerGraphConstants>() / 4) as u32,
+                std::ptr::addr_of!(constants) as _,
+                0,
+            );
+
+            command_list.RSSetViewports(&[render_target.viewport]);
+            command_list.RSSetScissorRects(&[render_target.scissor_rect]);
+            command_list.OMSetRenderTargets(1, &rtv, false, std::ptr::null());
+            command_list.IASetPrimitiveTopology(D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST);
+            command_list.DrawInstanced(6, num_graphs, 0, 0);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,186 @@
+use anyhow::Result;
+use d3d12_utils::{
+    create_compute_pipeline_state, create_pass_root_signature, load_hlsl, transition_barrier,
+    DescriptorManager, DescriptorType, RenderTarget, TextureDimension, TextureHandle, TextureInfo,
+    TextureManager, VariableRateShadingSupport, GLOBAL_CONSTANTS_PARAMETER,
+};
+use windows::Win32::Graphics::{Direct3D12::*, Dxgi::Common::DXGI_FORMAT_R8_UINT};
+
+use crate::renderer::Resources;
+
+const THREAD_GROUP_SIZE: u32 = 8;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct ShadingRateConstants {
+    pub scene_index: u32,
+    pub shading_rate_image_index: u32,
+    pub tile_size: u32,
+    pub coarsest_rate: u32,
+    pub render_extent: [u32; 2],
+    pub contrast_threshold: f32,
+}
+
+/// Fills a screen space shading rate image from the luminance contrast of the last rendered frame,
+/// so flat parts of the screen get shaded at a coarser rate
+#[derive(Debug)]
+pub struct ShadingRatePass {
+    root_signature: ID3D12RootSignature,
+    pso: ID3D12PipelineState,
+    support: VariableRateShadingSupport,
+    pub shading_rate_image: TextureHandle,
+    /// Tiles with less relative contrast than this are shaded at 2x2, well below it at the coarsest rate
+    pub contrast_threshold: f32,
+}
+
+impl ShadingRatePass {
+    pub fn new(resources: &mut Resources, extent: (u32, u32)) -> Result<Self> {
+        let root_signature = create_pass_root_signature(
+            &resources.device,
+            (std::mem::size_of::<ShadingRateConstants>() / 4) as u32,
+        )?;
+        let compute_shader =
+            load_hlsl!("renderer/src/shaders/shading_rate.hlsl", "CSMain", "cs_6_6")?;
+        let pso =
+            create_compute_pipeline_state(&resources.device, &root_signature, &compute_shader)?;
+
+        let support = resources.capabilities.variable_rate_shading;
+        let shading_rate_image = create_shading_rate_image(resources, &support, extent)?;
+
+        Ok(ShadingRatePass {
+            root_signature,
+            pso,
+            support,
+            shading_rate_image,
+            contrast_threshold: 0.1,
+        })
+    }
+
+    /// Recreates the shading rate image, the old one must not be in use anymore
+    pub fn resize(&mut self, resources: &mut Resources, extent: (u32, u32)) -> Result<()> {
+        let shading_rate_image = create_shading_rate_image(resources, &self.support, extent)?;
+        let old_image = std::mem::replace(&mut self.shading_rate_image, shading_rate_image);
+        resources
+            .texture_manager
+            .delete(&mut resources.descriptor_manager, old_image);
+
+        Ok(())
+    }
+
+    /// `scene` has to be readable from compute shaders, it still holds the previous frame
+    pub fn render(
+        &self,
+        command_list: &ID3D12GraphicsCommandList,
+        resources: &Resources,
+        scene: &RenderTarget,
+    ) -> Result<()> {
+        let (render_width, render_height) = scene.render_extent();
+        let (width, height) = self
+            .support
+            .shading_rate_image_extent((render_width, render_height));
+
+        let constants = ShadingRateConstants {
+            scene_index: resources.texture_manager.get_srv(&scene.color)?.index as u32,
+            shading_rate_image_index: resources
+                .texture_manager
+                .get_uav(&self.shading_rate_image)?
+                .index as u32,
+            tile_size: self.support.tile_size,
+            coarsest_rate: self.support.clamp_rate(D3D12_SHADING_RATE_4X4).0 as u32,
+            render_extent: [render_width, render_height],
+            contrast_threshold: self.contrast_threshold,
+        };
+
+        self.transition(
+            command_list,
+            &resources.texture_manager,
+            D3D12_RESOURCE_STATE_SHADING_RATE_SOURCE,
+            D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+        )?;
+
+        unsafe {
+            command_list.SetPipelineState(&self.pso);
+            command_list.SetDescriptorHeaps(&[Some(
+                resources
+                    .descriptor_manager
+                    .get_heap(DescriptorType::Resource)?,
+            )]);
+            command_list.SetComputeRootSignature(&self.root_signature);
+            command_list.SetComputeRootConstantBufferView(
+                GLOBAL_CONSTANTS_PARAMETER,
+                resources.global_constants_address(),
+            );
+            command_list.SetComputeRoot32BitConstants(
+                0,
+                (std::mem::size_of::<ShadingRateConstants>() / 4) as u32,
+                std::ptr::addr_of!(constants) as _,
+                0,
+            );
+            command_list.Dispatch(
+                width.div_ceil(THREAD_GROUP_SIZE),
+                height.div_ceil(THREAD_GROUP_SIZE),
+                1,
+            );
+        }
+
+        self.transition(
+            command_list,
+            &resources.texture_manager,
+            D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+            D3D12_RESOURCE_STATE_SHADING_RATE_SOURCE,
+        )
+    }
+
+    #[allow(dead_code)]
+    pub fn delete(
+        self,
+        texture_manager: &mut TextureManager,
+        descriptor_manager: &mut DescriptorManager,
+    ) {
+        texture_manager.delete(descriptor_manager, self.shading_rate_image);
+    }
+
+    fn transition(
+        &self,
+        command_list: &ID3D12GraphicsCommandList,
+        texture_manager: &TextureManager,
+        state_before: D3D12_RESOURCE_STATES,
+        state_after: D3D12_RESOURCE_STATES,
+    ) -> Result<()> {
+        let image = texture_manager.get_texture(&self.shading_rate_image)?;
+        let barrier = transition_barrier(
+            &image.get_resource()?.device_resource,
+            state_before,
+            state_after,
+        );
+        unsafe {
+            command_list.ResourceBarrier(std::slice::from_ref(&barrier));
+            let _: D3D12_RESOURCE_TRANSITION_BARRIER =
+                std::mem::ManuallyDrop::into_inner(barrier.Anonymous.Transition);
+        }
+
+        Ok(())
+    }
+}
+
+fn create_shading_rate_image(
+    resources: &mut Resources,
+    support: &VariableRateShadingSupport,
+    extent: (u32, u32),
+) -> Result<TextureHandle> {
+    let (width, height) = support.shading_rate_image_extent(extent);
+
+    resources.texture_manager.create_empty_texture(
+        &resources.device,
+        TextureInfo {
+            dimension: TextureDimension::Two(width as usize, height),
+            format: DXGI_FORMAT_R8_UINT,
+            is_unordered_access: true,
+            ..Default::default()
+        },
+        None,
+        D3D12_RESOURCE_STATE_SHADING_RATE_SOURCE,
+        &mut resources.descriptor_manager,
+        true,
+    )
+}
@@ -0,0 +1,349 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Context, Result};
+use d3d12_utils::{
+    create_compute_pipeline_state, create_pass_root_signature, load_hlsl, BarrierBatcher,
+    DepthRange, DescriptorType, FrameReadback, HdrImageFormat, ImageExport, RenderTarget,
+    TextureDimension, TextureHandle, TextureInfo, GLOBAL_CONSTANTS_PARAMETER,
+};
+use glam::Vec4;
+use windows::Win32::Graphics::{Direct3D12::*, Dxgi::Common::DXGI_FORMAT_R32G32B32A32_FLOAT};
+
+use crate::renderer::Resources;
+
+const THREAD_GROUP_SIZE: u32 = 8;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct TextureDumpConstants {
+    pub source_index: u32,
+    pub destination_index: u32,
+}
+
+#[derive(Debug)]
+struct DumpSource {
+    name: String,
+    texture: TextureHandle,
+    // The state the texture is in where the dump is recorded, at the end of the frame
+    state: D3D12_RESOURCE_STATES,
+    export: ImageExport,
+}
+
+// A source widened to float RGBA, on its way back to the CPU
+#[derive(Debug)]
+struct PendingDump {
+    path: PathBuf,
+    export: ImageExport,
+    scratch: TextureHandle,
+    readback: FrameReadback,
+}
+
+/// Writes registered textures to disk on request, for looking at intermediate targets outside of
+/// a graphics debugger. Every texture is first copied into a float RGBA texture, so one readback
+/// path handles all formats, and then saved as PNG, grayscale PNG for depth or EXR or Radiance HDR
+/// for float formats, see `ImageExport`.
+#[derive(Debug)]
+pub struct TextureDumpPass {
+    root_signature: ID3D12RootSignature,
+    pso: ID3D12PipelineState,
+    sources: Vec<DumpSource>,
+    directory: PathBuf,
+    hdr_format: HdrImageFormat,
+    requested: bool,
+    pending: Vec<PendingDump>,
+}
+
+impl TextureDumpPass {
+    /// Float textures are saved as `hdr_format`
+    pub fn new(
+        resources: &Resources,
+        directory: &Path,
+        hdr_format: HdrImageFormat,
+    ) -> Result<Self> {
+        let root_signature = create_pass_root_signature(
+            &resources.device,
+            (std::mem::size_of::<TextureDumpConstants>() / 4) as u32,
+        )?;
+        let compute_shader =
+            load_hlsl!("renderer/src/shaders/texture_dump.hlsl", "CSMain", "cs_6_6")?;
+        let pso =
+            create_compute_pipeline_state(&resources.device, &root_signature, &compute_shader)?;
+
+        Ok(Self {
+            root_signature,
+            pso,
+            sources: vec![],
+            directory: directory.to_path_buf(),
+            hdr_format,
+            requested: false,
+            pending: vec![],
+        })
+    }
+
+    /// Adds a 2D texture to the dumps, replacing an earlier one with the same name. `state` is the
+    /// one it is in at the end of the frame. Depth textures are taken to use the standard range.
+    pub fn register(
+        &mut self,
+        resources: &Resources,
+        name: &str,
+        texture: &TextureHandle,
+        state: D3D12_RESOURCE_STATES,
+    ) -> Result<()> {
+        self.insert(resources, name, texture, state, DepthRange::default())
+    }
+
+    /// Registers the colour and depth of `target` as `<name>_color` and `<name>_depth`
+    pub fn register_target(
+        &mut self,
+        resources: &Resources,
+        name: &str,
+        target: &RenderTarget,
+    ) -> Result<()> {
+        self.register(
+            resources,
+            &format!("{}_color", name),
+            &target.color,
+            D3D12_RESOURCE_STATE_ALL_SHADER_RESOURCE,
+        )?;
+        // The depth range decides which end of the depth is near in the image
+        self.insert(
+            resources,
+            &format!("{}_depth", name),
+            &target.depth,
+            D3D12_RESOURCE_STATE_DEPTH_WRITE,
+            target.depth_range,
+        )
+    }
+
+    fn insert(
+        &mut self,
+        resources: &Resources,
+        name: &str,
+        texture: &TextureHandle,
+        state: D3D12_RESOURCE_STATES,
+        depth_range: DepthRange,
+    ) -> Result<()> {
+        let info = &resources.texture_manager.get_texture(texture)?.info;
+        ensure!(
+            matches!(info.dimension, TextureDimension::Two(..)),
+            "Only 2D textures can be dumped, {} is {:?}",
+            name,
+            info.dimension
+        );
+
+        let source = DumpSource {
+            name: name.to_string(),
+            texture: texture.clone(),
+            state,
+            export: ImageExport::for_format(info.format, depth_range, self.hdr_format),
+        };
+        match self.sources.iter_mut().find(|source| source.name == name) {
+            Some(existing) => *existing = source,
+            None => self.sources.push(source),
+        }
+
+        Ok(())
+    }
+
+    /// Dumps every registered texture at the end of the next frame
+    pub fn request(&mut self) {
+        self.requested = true;
+    }
+
+    /// Whether a dump was recorded that `write_files` has to pick up
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Records the copies if a dump was requested. Flushes `barriers`, all registered textures
+    /// have to be in their registered state.
+    pub fn render(
+        &mut self,
+        command_list: &ID3D12GraphicsCommandList,
+        barriers: &mut BarrierBatcher,
+        resources: &mut Resources,
+        frame_number: u64,
+    ) -> Result<()> {
+        if !std::mem::take(&mut self.requested) {
+            return Ok(());
+        }
+        ensure!(self.pending.is_empty(), "The last dump was not written");
+
+        for source in &self.sources {
+            let info = resources.texture_manager.get_texture(&source.texture)?.info;
+            let scratch_info = TextureInfo {
+                dimension: info.dimension,
+                format: DXGI_FORMAT_R32G32B32A32_FLOAT,
+                is_unordered_access: true,
+                ..Default::default()
+            };
+            let readback = FrameReadback::new(&resources.device, &scratch_info.resource_desc(), 1)?;
+            let scratch = resources.texture_manager.create_empty_texture(
+                &resources.device,
+                scratch_info,
+                None,
+                D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+                &mut resources.descriptor_manager,
+                true,
+            )?;
+
+            self.pending.push(PendingDump {
+                path: self.directory.join(format!(
+                    "dump_{}_{}.{}",
+                    frame_number,
+                    source.name,
+                    source.export.extension()
+                )),
+                export: source.export,
+                scratch,
+                readback,
+            });
+        }
+
+        let read_state = D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE;
+        let needs_transition = |source: &DumpSource| (source.state & read_state) != read_state;
+        for source in self
+            .sources
+            .iter()
+            .filter(|source| needs_transition(source))
+        {
+            barriers.transition(
+                &resources
+                    .texture_manager
+                    .get_texture(&source.texture)?
+                    .get_resource()?
+                    .device_resource,
+                source.state,
+                read_state,
+            );
+        }
+        barriers.flush(command_list);
+
+        unsafe {
+            command_list.SetPipelineState(&self.pso);
+            command_list.SetDescriptorHeaps(&[Some(
+                resources
+                    .descriptor_manager
+                    .get_heap(DescriptorType::Resource)?,
+            )]);
+            command_list.SetComputeRootSignature(&self.root_signature);
+            command_list.SetComputeRootConstantBufferView(
+                GLOBAL_CONSTANTS_PARAMETER,
+                resources.global_constants_address(),
+            );
+        }
+
+        for (source, pending) in self.sources.iter().zip(&mut self.pending) {
+            let constants = TextureDumpConstants {
+                source_index: resources.texture_manager.get_srv(&source.texture)?.index as u32,
+                destination_index: resources.texture_manager.get_uav(&pending.scratch)?.index
+                    as u32,
+            };
+            let (width, height) = texture_extent(resources, &source.texture)?;
+
+            unsafe {
+                command_list.SetComputeRoot32BitConstants(
+                    0,
+                    (std::mem::size_of::<TextureDumpConstants>() / 4) as u32,
+                    std::ptr::addr_of!(constants) as _,
+                    0,
+                );
+                command_list.Dispatch(
+                    width.div_ceil(THREAD_GROUP_SIZE),
+                    height.div_ceil(THREAD_GROUP_SIZE),
+                    1,
+                );
+            }
+        }
+
+        for pending in &self.pending {
+            barriers.uav(Some(
+                &resources
+                    .texture_manager
+                    .get_texture(&pending.scratch)?
+                    .get_resource()?
+                    .device_resource,
+            ));
+        }
+        for source in self
+            .sources
+            .iter()
+            .filter(|source| needs_transition(source))
+        {
+            barriers.transition(
+                &resources
+                    .texture_manager
+                    .get_texture(&source.texture)?
+                    .get_resource()?
+                    .device_resource,
+                read_state,
+                source.state,
+            );
+        }
+        barriers.flush(command_list);
+
+        for pending in &mut self.pending {
+            pending.readback.copy(
+                command_list,
+                &resources
+                    .texture_manager
+                    .get_texture(&pending.scratch)?
+                    .get_resource()?
+                    .device_resource,
+                D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+                0,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Saves the dump recorded by `render`, the GPU has to be idle
+    pub fn write_files(&mut self, resources: &mut Resources) -> Result<()> {
+        let pending = std::mem::take(&mut self.pending);
+        for dump in &pending {
+            resources
+                .texture_manager
+                .delete(&mut resources.descriptor_manager, dump.scratch.clone());
+        }
+
+        for mut pending in pending {
+            let frame = pending
+                .readback
+                .take(0)
+                .context("The dump was not copied back")?;
+            // Rows are padded out to the copy alignment
+            let row_size = frame.width as usize * std::mem::size_of::<Vec4>();
+            let texels: Vec<Vec4> = frame
+                .data
+                .chunks(frame.row_pitch)
+                .take(frame.height as usize)
+                .flat_map(|row| row[..row_size].chunks_exact(std::mem::size_of::<Vec4>()))
+                .map(|texel| {
+                    Vec4::from_array(array_init::array_init(|i| {
+                        f32::from_le_bytes(texel[i * 4..i * 4 + 4].try_into().unwrap())
+                    }))
+                })
+                .collect();
+
+            let bytes = pending.export.encode(frame.width, frame.height, &texels)?;
+            std::fs::write(&pending.path, bytes)
+                .with_context(|| format!("Writing {}", pending.path.display()))?;
+            println!("Dumped {}", pending.path.display());
+        }
+
+        Ok(())
+    }
+}
+
+fn texture_extent(resources: &Resources, texture: &TextureHandle) -> Result<(u32, u32)> {
+    match resources
+        .texture_manager
+        .get_texture(texture)?
+        .info
+        .dimension
+    {
+        TextureDimension::Two(width, height) => Ok((width as u32, height)),
+        dimension => anyhow::bail!("Expected a 2D texture, got {:?}", dimension),
+    }
+}
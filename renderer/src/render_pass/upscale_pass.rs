@@ -0,0 +1,152 @@
+use anyhow::{bail, Result};
+use d3d12_utils::{
+    create_fullscreen_pipeline_state, create_pass_root_signature, create_pass_table_root_signature,
+    load_hlsl, DescriptorType, DisplayRotation, RenderTarget, TextureDimension,
+    GLOBAL_CONSTANTS_PARAMETER, PASS_TEXTURE_PARAMETER,
+};
+use windows::Win32::Graphics::{
+    Direct3D::D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST, Direct3D12::*, Dxgi::Common::DXGI_FORMAT,
+};
+
+use crate::renderer::Resources;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct UpscaleConstants {
+    pub texture_index: u32,
+    pub sharpness: f32,
+    pub uv_scale: glam::Vec2,
+    pub texel_size: glam::Vec2,
+    pub quarter_turns: u32,
+}
+
+/// Stretches the rendered part of a (possibly downscaled) target over another target
+#[derive(Debug)]
+pub struct UpscalePass {
+    root_signature: ID3D12RootSignature,
+    pso: ID3D12PipelineState,
+    /// Without them the source is bound through `PASS_TEXTURE_PARAMETER`
+    dynamic_resources: bool,
+    pub sharpness: f32,
+}
+
+impl UpscalePass {
+    pub fn new(resources: &Resources, render_target_format: DXGI_FORMAT) -> Result<Self> {
+        let dynamic_resources = resources.capabilities.dynamic_resources();
+        let num_constants = (std::mem::size_of::<UpscaleConstants>() / 4) as u32;
+        // Below shader model 6.6 the shaders compile to read the source through a table, the root
+        // signature has to come from the same choice as the model they are compiled for
+        let (root_signature, vertex_shader, pixel_shader) = if dynamic_resources {
+            (
+                create_pass_root_signature(&resources.device, num_constants)?,
+                load_hlsl!("renderer/src/shaders/upscale.hlsl", "VSMain", "vs_6_6")?,
+                load_hlsl!("renderer/src/shaders/upscale.hlsl", "PSMain", "ps_6_6")?,
+            )
+        } else {
+            (
+                create_pass_table_root_signature(&resources.device, num_constants)?,
+                load_hlsl!("renderer/src/shaders/upscale.hlsl", "VSMain", "vs_6_0")?,
+                load_hlsl!("renderer/src/shaders/upscale.hlsl", "PSMain", "ps_6_0")?,
+            )
+        };
+
+        let pso = create_fullscreen_pipeline_state(
+            &resources.device,
+            &root_signature,
+            &vertex_shader,
+            &pixel_shader,
+            render_target_format,
+        )?;
+
+        Ok(UpscalePass {
+            root_signature,
+            pso,
+            dynamic_resources,
+            sharpness: 0.0,
+        })
+    }
+
+    /// `rotation` turns the image for a destination that is in the native orientation of a
+    /// rotated display
+    pub fn render(
+        &self,
+        command_list: &ID3D12GraphicsCommandList,
+        resources: &Resources,
+        source: &RenderTarget,
+        destination: &RenderTarget,
+        rotation: DisplayRotation,
+    ) -> Result<()> {
+        let (uv_scale, texel_size) = source_mapping(resources, source)?;
+        let source_srv = resources.texture_manager.get_srv(&source.color)?;
+        let constants = UpscaleConstants {
+            texture_index: source_srv.index as u32,
+            sharpness: self.sharpness,
+            uv_scale,
+            texel_size,
+            quarter_turns: rotation.quarter_turns(),
+        };
+
+        let rtv_handle = resources.texture_manager.get_rtv(&destination.color)?;
+        let rtv = resources.descriptor_manager.get_cpu_handle(&rtv_handle)?;
+
+        unsafe {
+            command_list.SetPipelineState(&self.pso);
+            command_list.SetDescriptorHeaps(&[Some(
+                resources
+                    .descriptor_manager
+                    .get_heap(DescriptorType::Resource)?,
+            )]);
+            command_list.SetGraphicsRootSignature(&self.root_signature);
+            command_list.SetGraphicsRootConstantBufferView(
+                GLOBAL_CONSTANTS_PARAMETER,
+                resources.global_constants_address(),
+            );
+            command_list.SetGraphicsRoot32BitConstants(
+                0,
+                (std::mem::size_of::<UpscaleConstants>() / 4) as u32,
+                std::ptr::addr_of!(constants) as _,
+                0,
+            );
+            if !self.dynamic_resources {
+                command_list.SetGraphicsRootDescriptorTable(
+                    PASS_TEXTURE_PARAMETER,
+                    resources.descriptor_manager.get_gpu_handle(&source_srv)?,
+                );
+            }
+
+            command_list.RSSetViewports(&[destination.viewport]);
+            command_list.RSSetScissorRects(&[destination.scissor_rect]);
+            command_list.OMSetRenderTargets(1, &rtv, false, std::ptr::null());
+            command_list.IASetPrimitiveTopology(D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST);
+            command_list.DrawInstanced(3, 1, 0, 0);
+        }
+
+        Ok(())
+    }
+}
+
+/// The part of `source` that was rendered to as a uv scale, and the size of one of its texels in
+/// uv, for `SampleUpscaled` in upscale_filter.hlsli
+pub(crate) fn source_mapping(
+    resources: &Resources,
+    source: &RenderTarget,
+) -> Result<(glam::Vec2, glam::Vec2)> {
+    let (source_width, source_height) = match resources
+        .texture_manager
+        .get_texture(&source.color)?
+        .info
+        .dimension
+    {
+        TextureDimension::Two(width, height) => (width as f32, height as f32),
+        _ => bail!("Upscale source has to be a 2D texture"),
+    };
+    let (render_width, render_height) = source.render_extent();
+
+    Ok((
+        glam::Vec2::new(
+            render_width as f32 / source_width,
+            render_height as f32 / source_height,
+        ),
+        glam::Vec2::new(1.0 / source_width, 1.0 / source_height),
+    ))
+}
@@ -0,0 +1,335 @@
+use std::f32::consts::PI;
+
+use anyhow::Result;
+use d3d12_utils::{
+    create_overlay_pipeline_state, create_pass_root_signature, load_hlsl, BarrierBatcher,
+    CommandQueue, DescriptorType, FrameFence, Plane, RenderTarget, TargetFormats, TextureDimension,
+    TextureHandle, TextureInfo, UploadPriority, ViewportRect, GLOBAL_CONSTANTS_PARAMETER,
+};
+use windows::Win32::Graphics::{
+    Direct3D::D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST,
+    Direct3D12::*,
+    Dxgi::Common::{DXGI_FORMAT, DXGI_FORMAT_R8G8B8A8_UNORM},
+};
+
+use crate::{
+    object::Object,
+    render_pass::bindless_texture_pass::BindlessTexturePass,
+    renderer::{Camera, Resources},
+};
+
+const REFLECTION_FORMAT: DXGI_FORMAT = DXGI_FORMAT_R8G8B8A8_UNORM;
+const NORMAL_MAP_SIZE: u32 = 256;
+// Frequencies in waves per normal map tile, whole numbers so the tile repeats without seams,
+// amplitude and phase
+const NORMAL_MAP_WAVES: [(f32, f32, f32, f32); 4] = [
+    (1.0, 2.0, 0.3, 0.0),
+    (3.0, -1.0, 0.15, 1.3),
+    (-2.0, 5.0, 0.08, 2.1),
+    (7.0, 3.0, 0.04, 4.4),
+];
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct WaterConstants {
+    pub view_projection: glam::Mat4,
+    pub reflection_view_projection: glam::Mat4,
+    pub camera_position: glam::Vec3,
+    pub height: f32,
+    pub center: glam::Vec2,
+    pub half_extent: glam::Vec2,
+    // Part of the reflection target the view's reflection was drawn into
+    pub region_offset: glam::Vec2,
+    pub region_size: glam::Vec2,
+    pub reflection_index: u32,
+    pub normal_map_index: u32,
+    pub time: f32,
+    pub distortion: f32,
+}
+
+/// A rectangle of water lying flat at `height`
+#[derive(Debug, Clone, Copy)]
+pub struct Water {
+    pub height: f32,
+    /// On the xz plane
+    pub center: glam::Vec2,
+    pub half_extent: glam::Vec2,
+}
+
+impl Water {
+    pub fn plane(&self) -> Plane {
+        Plane::from_point_normal(glam::Vec3::new(0.0, self.height, 0.0), glam::Vec3::Y)
+    }
+}
+
+/// Planar reflections on a water surface. Every view's scene is drawn again through a camera
+/// mirrored about the water into the same region of an offscreen target, which the water then
+/// samples, distorted by a scrolling normal map and blended in by the fresnel term.
+#[derive(Debug)]
+pub struct WaterPass<const FRAME_COUNT: usize> {
+    reflection_pass: BindlessTexturePass<FRAME_COUNT>,
+    // Half the resolution of the scene target
+    reflection_target: RenderTarget,
+    normal_map: TextureHandle,
+    root_signature: ID3D12RootSignature,
+    formats: TargetFormats,
+    pso: ID3D12PipelineState,
+    // Seconds the waves have moved for
+    time: f32,
+    pub water: Water,
+    pub enabled: bool,
+    /// How far the waves shift the reflection, in reflection texture coordinates
+    pub distortion: f32,
+}
+
+impl<const FRAME_COUNT: usize> WaterPass<FRAME_COUNT> {
+    /// Draws the water into render targets of `formats`, reflections are cleared to `clear_color`
+    pub fn new(
+        resources: &mut Resources,
+        dependent_queue: &CommandQueue,
+        formats: TargetFormats,
+        scene_extent: (u32, u32),
+        clear_color: [f32; 4],
+    ) -> Result<Self> {
+        let reflection_pass =
+            BindlessTexturePass::new(resources, TargetFormats::single(REFLECTION_FORMAT))?;
+        let reflection_target = create_reflection_target(resources, scene_extent, clear_color)?;
+
+        let normal_map = resources.texture_manager.create_texture(
+            &resources.device,
+            resources.upload_rings.get_mut(UploadPriority::High),
+            Some(dependent_queue),
+            &mut resources.descriptor_manager,
+            TextureInfo {
+                dimension: TextureDimension::Two(NORMAL_MAP_SIZE as usize, NORMAL_MAP_SIZE),
+                format: DXGI_FORMAT_R8G8B8A8_UNORM,
+                ..Default::default()
+            },
+            &wave_normal_map(NORMAL_MAP_SIZE),
+        )?;
+
+        let root_signature = create_pass_root_signature(
+            &resources.device,
+            (std::mem::size_of::<WaterConstants>() / 4) as u32,
+        )?;
+        let vertex_shader = load_hlsl!("renderer/src/shaders/water.hlsl", "VSMain", "vs_6_6")?;
+        let pixel_shader = load_hlsl!("renderer/src/shaders/water.hlsl", "PSMain", "ps_6_6")?;
+        let pso = create_overlay_pipeline_state(
+            &resources.device,
+            &root_signature,
+            &vertex_shader,
+            &pixel_shader,
+            &formats,
+            D3D12_PRIMITIVE_TOPOLOGY_TYPE_TRIANGLE,
+            resources.depth_range,
+        )?;
+
+        Ok(WaterPass {
+            reflection_pass,
+            reflection_target,
+            normal_map,
+            root_signature,
+            formats,
+            pso,
+            time: 0.0,
+            water: Water {
+                height: 0.0,
+                center: glam::Vec2::ZERO,
+                half_extent: glam::Vec2::splat(5.0),
+            },
+            enabled: true,
+            distortion: 0.02,
+        })
+    }
+
+    pub fn resize(&mut self, resources: &mut Resources, scene_extent: (u32, u32)) -> Result<()> {
+        let clear_color = self.reflection_target.clear_color;
+        let reflection_target = create_reflection_target(resources, scene_extent, clear_color)?;
+        std::mem::replace(&mut self.reflection_target, reflection_target).delete(
+            &mut resources.texture_manager,
+            &mut resources.descriptor_manager,
+        );
+
+        Ok(())
+    }
+
+    /// Moves the waves on by `delta_time` seconds
+    pub fn advance(&mut self, delta_time: f32) {
+        self.time += delta_time;
+    }
+
+    /// `camera` mirrored about the water. Seen from below, the water mirrors what is below it.
+    pub fn reflection_camera(&self, camera: &Camera) -> Camera {
+        let plane = self.water.plane();
+        let plane = if plane.signed_distance(camera.position()) < 0.0 {
+            plane.flipped()
+        } else {
+            plane
+        };
+
+        camera.mirrored(&plane)
+    }
+
+    /// Call once per frame before any reflection is drawn, once the frame's fence has been waited
+    /// on
+    pub fn begin_frame(&mut self, frame: FrameFence) -> Result<()> {
+        self.reflection_pass.begin_frame(frame)
+    }
+
+    /// Draws the reflections of several views, each with the objects its reflection camera sees,
+    /// into the regions the views take up on screen. They are ready to be sampled once `barriers`
+    /// is flushed.
+    pub fn render_reflections<'a, V, I>(
+        &mut self,
+        command_list: &ID3D12GraphicsCommandList,
+        barriers: &mut BarrierBatcher,
+        resources: &Resources,
+        views: V,
+    ) -> Result<()>
+    where
+        V: IntoIterator<Item = (&'a Camera, &'a ViewportRect, I)>,
+        I: IntoIterator<Item = (&'a Object, &'a glam::Mat4)>,
+    {
+        if !self.enabled {
+            return Ok(());
+        }
+
+        self.reflection_target.begin(
+            command_list,
+            barriers,
+            &resources.texture_manager,
+            &resources.descriptor_manager,
+        )?;
+        for (camera, region, objects) in views {
+            self.reflection_pass.render(
+                command_list,
+                resources,
+                camera,
+                &self.reflection_target,
+                region,
+                None,
+                objects,
+            )?;
+        }
+        self.reflection_target
+            .end(barriers, &resources.texture_manager)
+    }
+
+    /// Draws the water into `region` of a target that is between `begin` and `end`, after the
+    /// opaque objects. `reflection_camera` has to be the one the view's reflection was drawn with.
+    pub fn render(
+        &self,
+        command_list: &ID3D12GraphicsCommandList,
+        resources: &Resources,
+        camera: &Camera,
+        reflection_camera: &Camera,
+        render_target: &RenderTarget,
+        region: &ViewportRect,
+    ) -> Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+
+        render_target.validate_formats(&resources.texture_manager, &self.formats)?;
+
+        let constants = WaterConstants {
+            view_projection: camera.view_projection(),
+            reflection_view_projection: reflection_camera.view_projection(),
+            camera_position: camera.position(),
+            height: self.water.height,
+            center: self.water.center,
+            half_extent: self.water.half_extent,
+            region_offset: glam::Vec2::new(region.x, region.y),
+            region_size: glam::Vec2::new(region.width, region.height),
+            reflection_index: resources
+                .texture_manager
+                .get_srv(&self.reflection_target.color)?
+                .index as u32,
+            normal_map_index: resources.texture_manager.get_srv(&self.normal_map)?.index as u32,
+            time: self.time,
+            distortion: self.distortion,
+        };
+
+        let (viewport, scissor_rect) = render_target.region_viewport(region);
+        let rtv_handle = resources.texture_manager.get_rtv(&render_target.color)?;
+        let rtv = resources.descriptor_manager.get_cpu_handle(&rtv_handle)?;
+        let dsv_handle = resources.texture_manager.get_dsv(&render_target.depth)?;
+        let dsv = resources.descriptor_manager.get_cpu_handle(&dsv_handle)?;
+
+        unsafe {
+            command_list.SetPipelineState(&self.pso);
+            command_list.SetDescriptorHeaps(&[Some(
+                resources
+                    .descriptor_manager
+                    .get_heap(DescriptorType::Resource)?,
+            )]);
+            command_list.SetGraphicsRootSignature(&self.root_signature);
+            command_list.SetGraphicsRootConstantBufferView(
+                GLOBAL_CONSTANTS_PARAMETER,
+                resources.global_constants_address(),
+            );
+            command_list.SetGraphicsRoot32BitConstants(
+                0,
+                (std::mem::size_of::<WaterConstants>() / 4) as u32,
+                std::ptr::addr_of!(constants) as _,
+                0,
+            );
+
+            command_list.RSSetViewports(&[viewport]);
+            command_list.RSSetScissorRects(&[scissor_rect]);
+            command_list.OMSetRenderTargets(1, &rtv, false, &dsv);
+            command_list.IASetPrimitiveTopology(D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST);
+            command_list.DrawInstanced(6, 1, 0, 0);
+        }
+
+        Ok(())
+    }
+}
+
+fn create_reflection_target(
+    resources: &mut Resources,
+    scene_extent: (u32, u32),
+    clear_color: [f32; 4],
+) -> Result<RenderTarget> {
+    let (width, height) = scene_extent;
+
+    RenderTarget::new(
+        &resources.device,
+        &mut resources.texture_manager,
+        &mut resources.descriptor_manager,
+        ((width / 2).max(1), (height / 2).max(1)),
+        REFLECTION_FORMAT,
+        clear_color,
+        resources.depth_range,
+    )
+}
+
+// Tangent space normals of a few overlapping sine waves, as RGBA8 with the normal's z in blue
+fn wave_normal_map(size: u32) -> Vec<u8> {
+    let mut data = Vec::with_capacity((size * size * 4) as usize);
+
+    for y in 0..size {
+        for x in 0..size {
+            let uv = glam::Vec2::new(x as f32, y as f32) / size as f32;
+            let slope = NORMAL_MAP_WAVES.iter().fold(
+                glam::Vec2::ZERO,
+                |slope, &(u, v, amplitude, phase)| {
+                    let frequency = glam::Vec2::new(u, v) * 2.0 * PI;
+                    slope + frequency * amplitude * (frequency.dot(uv) + phase).cos()
+                },
+            );
+            // Scaled down so the steepest waves don't lie flat
+            let normal = glam::Vec3::new(-slope.x, -slope.y, 8.0).normalize();
+
+            let encoded = (normal * 0.5 + 0.5) * 255.0;
+            data.extend_from_slice(&[
+                encoded.x.round() as u8,
+                encoded.y.round() as u8,
+                encoded.z.round() as u8,
+                255,
+            ]);
+        }
+    }
+
+    data
+}
@@ -1,24 +1,114 @@
 use std::f32::consts::PI;
-use std::ffi::c_void;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufRead, BufReader, Read, Seek};
+use std::path::{Path, PathBuf};
 
-use anyhow::{Context, Ok, Result};
-use glam::Vec3;
+use anyhow::{ensure, Context, Ok, Result};
+use glam::{Quat, Vec2, Vec3, Vec4};
 
-use windows::core::PCWSTR;
-use windows::Win32::Foundation::{HWND, RECT};
+use windows::core::{Interface, PCWSTR};
+use windows::Win32::Foundation::{BOOL, DXGI_STATUS_OCCLUDED, HWND};
 use windows::Win32::Graphics::Direct3D::*;
 use windows::Win32::Graphics::Direct3D12::*;
 use windows::Win32::Graphics::Dxgi::Common::*;
 use windows::Win32::Graphics::Dxgi::*;
-
-const FRAME_COUNT: usize = 2;
+use winit::event::MouseButton;
+
+pub const FRAME_COUNT: usize = 2;
+const MESH_POOL_VERTEX_BUFFER_SIZE: usize = 16 * 1024 * 1024;
+const MESH_POOL_INDEX_BUFFER_SIZE: usize = 2 * 1024 * 1024;
+const OBJ_CHUNK_SIZE: usize = 3 * 16 * 1024;
+const HIGH_PRIORITY_UPLOAD_RING_SIZE: usize = 16 * 1024 * 1024;
+const BACKGROUND_UPLOAD_RING_SIZE: usize = 500_000_000;
+const MINIMAP_EXTENT: (u32, u32) = (512, 512);
+const TARGET_GPU_FRAME_TIME_MS: f32 = 1000.0 / 60.0;
+const SCREEN_SPACE_VRS: bool = true;
+const SCENE_CLEAR_COLOR: [f32; 4] = [0.0, 0.2, 0.4, 1.0];
+/// Overlays store sRGB colours with premultiplied alpha, the composite pass decodes them
+const SCENE_FORMAT: DXGI_FORMAT = DXGI_FORMAT_R8G8B8A8_UNORM;
+const MINIMAP_FORMAT: DXGI_FORMAT = DXGI_FORMAT_R8G8B8A8_UNORM;
+const OVERLAY_FORMAT: DXGI_FORMAT = DXGI_FORMAT_R8G8B8A8_UNORM;
+/// Frames the swap chain queues before `render` blocks, raise it to trade latency for throughput
+pub const MAXIMUM_FRAME_LATENCY: u32 = 1;
+const FRAME_LATENCY_TIMEOUT_MS: u32 = 1000;
+const FRAME_COMMAND_LIST_NAME: &str = "Frame Command List";
+// Standard or reversed-Z for every camera, pipeline and depth buffer. Reversed keeps far away
+// depth precise.
+const DEPTH_RANGE: DepthRange = DepthRange::Reversed;
+const ZOOM_STEP: f32 = PI / 36.0;
+const MIN_FOV_Y: f32 = PI / 12.0;
+const MAX_FOV_Y: f32 = PI * 2.0 / 3.0;
+// Each level of detail after the first merges vertices on a grid this fine
+const LOD_GRID_RESOLUTIONS: [u32; 2] = [48, 16];
+// Side of the square of demo point lights hovering over the ground
+const DEMO_LIGHT_GRID: u32 = 16;
+const UV_CHECKER_PATH: &str = "assets/uv_checker.dds";
+// Generated in place of the UV checker asset when it is missing
+const CHECKER_TEXTURE_SIZE: u32 = 1024;
+const CHECKER_SQUARES: u32 = 16;
+// Small solid colour images sharing an atlas page, one of them on the third demo object
+const SWATCH_COLOURS: [[u8; 4]; 4] = [
+    [230, 80, 60, 255],
+    [240, 200, 70, 255],
+    [80, 190, 110, 255],
+    [70, 120, 220, 255],
+];
+const SWATCH_SIZE: u32 = 16;
+const SWATCH_ATLAS_EXTENT: (u32, u32) = (64, 64);
+const SIMULATION_RATE_HZ: f32 = 60.0;
+// Views that only live for a frame, like the Hi-Z pyramid's mip UAVs
+const TRANSIENT_DESCRIPTORS_PER_FRAME: usize = 1024;
+// Every frame moves the scene by the same amount while profiling, so each run renders the same
+// frames however long they take
+const PROFILING_DELTA_TIME: f32 = 1.0 / SIMULATION_RATE_HZ;
+// Past this the scene slows down instead of the frame rate collapsing
+const MAX_SIMULATION_STEPS: u32 = 8;
+// Length of the gizmo handles as a fraction of the main view's height
+const GIZMO_SCREEN_SIZE: f32 = 0.15;
+// Half the length of the lines of the cross on a picked surface, in world units
+const PICK_MARKER_SIZE: f32 = 0.05;
 
 use d3d12_utils::*;
 
+use crate::camera_controller::CameraController;
+use crate::config::Config;
+use crate::dynamic_resolution::{DynamicResolution, RenderScaleMode};
+use crate::frame_clock::FrameClock;
+use crate::input::InputSnapshot;
+use crate::level_of_detail::update_lods;
+use crate::material::Material;
 use crate::object::Object;
+#[cfg(feature = "physics")]
+use crate::physics::{ColliderShape, Physics};
+use crate::render_pass::auto_exposure_pass::AutoExposurePass;
 use crate::render_pass::bindless_texture_pass::BindlessTexturePass;
+use crate::render_pass::color_grading_pass::{ColorGradingPass, ColorLut};
+use crate::render_pass::composite_pass::CompositePass;
+use crate::render_pass::compute_composite_pass::ComputeCompositePass;
+use crate::render_pass::debug_line_pass::DebugLinePass;
+use crate::render_pass::depth_of_field_pass::DepthOfFieldPass;
+use crate::render_pass::depth_readback_pass::DepthReadbackPass;
+use crate::render_pass::environment_probe_pass::EnvironmentProbePass;
+use crate::render_pass::grid_pass::GridPass;
+use crate::render_pass::hi_z_pass::HiZPass;
+use crate::render_pass::light_culling_pass::{LightCullingPass, MAX_LIGHTS};
+use crate::render_pass::memory_hud_pass::MemoryHudPass;
+use crate::render_pass::morph_target_pass::MorphTargetPass;
+use crate::render_pass::motion_blur_pass::MotionBlurPass;
+use crate::render_pass::occlusion_culling_pass::OcclusionCullingPass;
+use crate::render_pass::profiler_graph_pass::ProfilerGraphPass;
+use crate::render_pass::shading_rate_pass::ShadingRatePass;
+use crate::render_pass::texture_dump_pass::TextureDumpPass;
+use crate::render_pass::upscale_pass::UpscalePass;
+use crate::render_pass::water_pass::WaterPass;
+use crate::scene_file::{SceneAssets, SceneFile};
+use crate::settings::{SettingChange, Settings};
+use crate::shader_types::write_shader_types_header;
+use crate::texture_streaming::TextureStreaming;
+use crate::transform_cache::TransformCache;
+use crate::video_recorder::{FrameCapture, Recording};
+use crate::view::View;
+use crate::visibility::Visibility;
 
 #[allow(dead_code)]
 fn load_cube() -> Result<(Vec<ObjVertex>, Vec<u32>)> {
@@ -27,29 +117,138 @@ fn load_cube() -> Result<(Vec<ObjVertex>, Vec<u32>)> {
     parse_obj(cube_obj.lines())
 }
 
-fn load_bunny() -> Result<(Vec<ObjVertex>, Vec<u32>)> {
-    let obj = std::fs::read_to_string(r"assets/bunny.obj")?;
-
-    parse_obj(obj.lines())
-}
-
-#[repr(C)]
+/// A view matrix and the projection it is seen through. The projection matrix is rebuilt whenever
+/// the projection, the aspect ratio or the depth range changes.
 #[derive(Debug, Clone, Copy)]
 pub struct Camera {
     V: glam::Mat4,
     P: glam::Mat4,
+    projection: CameraProjection,
+    aspect_ratio: f32,
+    depth_range: DepthRange,
+    /// Plane a planar reflection camera mirrors about, nothing behind it is drawn
+    mirror: Option<Plane>,
 }
 
-#[repr(C)]
-#[derive(Debug, Clone, Copy)]
-struct MaterialConstantBuffer {
-    pub texture_index: u32,
-}
+impl Camera {
+    pub fn new(view: glam::Mat4, projection: CameraProjection, aspect_ratio: f32) -> Self {
+        Self {
+            V: view,
+            P: projection.matrix(aspect_ratio, DEPTH_RANGE),
+            projection,
+            aspect_ratio,
+            depth_range: DEPTH_RANGE,
+            mirror: None,
+        }
+    }
 
-#[repr(C)]
-#[derive(Debug, Clone, Copy)]
-struct ModelConstantBuffer {
-    pub M: glam::Mat4,
+    pub fn perspective(view: glam::Mat4, aspect_ratio: f32) -> Self {
+        Self::new(
+            view,
+            CameraProjection::Perspective {
+                fov_y: PI / 2.0,
+                near: 0.1,
+                far: 100.0,
+            },
+            aspect_ratio,
+        )
+    }
+
+    pub fn orthographic(view: glam::Mat4, height: f32, aspect_ratio: f32) -> Self {
+        Self::new(
+            view,
+            CameraProjection::Orthographic {
+                height,
+                near: 0.1,
+                far: 100.0,
+            },
+            aspect_ratio,
+        )
+    }
+
+    pub fn view(&self) -> glam::Mat4 {
+        self.V
+    }
+
+    pub fn set_view(&mut self, view: glam::Mat4) {
+        self.V = view;
+    }
+
+    pub fn projection_matrix(&self) -> glam::Mat4 {
+        self.P
+    }
+
+    pub fn projection(&self) -> &CameraProjection {
+        &self.projection
+    }
+
+    #[allow(dead_code)]
+    pub fn set_projection(&mut self, projection: CameraProjection) {
+        self.projection = projection;
+        self.rebuild_projection();
+    }
+
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: f32) {
+        self.aspect_ratio = aspect_ratio;
+        self.rebuild_projection();
+    }
+
+    pub fn set_fov_y(&mut self, fov_y: f32) -> Result<()> {
+        self.projection.set_fov_y(fov_y)?;
+        self.rebuild_projection();
+
+        Ok(())
+    }
+
+    #[allow(dead_code)]
+    pub fn set_clip_planes(&mut self, near: f32, far: f32) {
+        self.projection.set_clip_planes(near, far);
+        self.rebuild_projection();
+    }
+
+    pub fn depth_range(&self) -> DepthRange {
+        self.depth_range
+    }
+
+    pub fn view_projection(&self) -> glam::Mat4 {
+        self.P * self.V
+    }
+
+    pub fn position(&self) -> Vec3 {
+        self.V.inverse().w_axis.truncate()
+    }
+
+    pub fn view_depth(&self, point: Vec3) -> f32 {
+        self.V.transform_point3(point).z
+    }
+
+    pub fn projection_scale_y(&self) -> f32 {
+        self.P.y_axis.y
+    }
+
+    /// Sees what this camera would see in a mirror lying in `plane`, for planar reflections.
+    /// Mirroring the image horizontally on top keeps the winding of triangles, so back faces are
+    /// culled as usual. Objects behind the plane are clipped away.
+    pub fn mirrored(&self, plane: &Plane) -> Camera {
+        let mut camera = *self;
+        camera.V = self.V * plane.reflection();
+        camera.mirror = Some(*plane);
+        camera.rebuild_projection();
+
+        camera
+    }
+
+    /// Only the side of it the normal points to is drawn
+    pub fn clip_plane(&self) -> Option<Plane> {
+        self.mirror
+    }
+
+    fn rebuild_projection(&mut self) {
+        self.P = self.projection.matrix(self.aspect_ratio, self.depth_range);
+        if self.mirror.is_some() {
+            self.P = glam::Mat4::from_scale(Vec3::new(-1.0, 1.0, 1.0)) * self.P;
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -59,31 +258,167 @@ pub struct Resources {
     pub descriptor_manager: DescriptorManager,
     pub texture_manager: TextureManager,
     pub mesh_manager: MeshManager,
-    pub upload_ring_buffer: UploadRingBuffer,
-    pub viewport: D3D12_VIEWPORT,
-    pub scissor_rect: RECT,
-    pub camera: Camera,
+    pub upload_rings: UploadRings,
+    pub capabilities: DeviceCapabilities,
+    pub depth_range: DepthRange,
+    /// Noise and lookup tables any pass can sample
+    #[allow(dead_code)]
+    pub builtin_textures: BuiltinTextures,
+    pub global_constants: GlobalConstantBuffer<FRAME_COUNT>,
+}
+
+impl Resources {
+    /// For the root parameter `GLOBAL_CONSTANTS_PARAMETER` of every pass, valid for the frame
+    /// being recorded
+    pub fn global_constants_address(&self) -> u64 {
+        self.global_constants.gpu_address(self.frame_index as usize)
+    }
+
+    /// Everything allocated from the descriptor heap and upload rings so far
+    pub fn allocation_counts(&self) -> AllocationCounts {
+        self.descriptor_manager.allocation_counts() + self.upload_rings.allocation_counts()
+    }
+
+    /// Objects hold a reference to their mesh and texture, so neither goes away while they're drawn
+    pub fn acquire_object_assets(&mut self, object: &Object) -> Result<()> {
+        self.mesh_manager.add_ref(&object.mesh)?;
+        self.texture_manager.add_ref(&object.material.texture)?;
+
+        Ok(())
+    }
+
+    /// `fence_value` is that of the last submission that drew the object
+    pub fn release_object_assets(&mut self, object: Object, fence_value: u64) -> Result<()> {
+        self.mesh_manager.unload(object.mesh, fence_value)?;
+        self.texture_manager
+            .unload(object.material.texture, fence_value)
+    }
+
+    /// How full every manager's pools are right now
+    pub fn stats(&self) -> Stats {
+        let (mesh_pool_vertices, mesh_pool_indices) = self.mesh_manager.pool_stats();
+
+        Stats {
+            texture_heap: self.texture_manager.stats(),
+            mesh_heap: self.mesh_manager.stats(),
+            mesh_pool_vertices,
+            mesh_pool_indices,
+            resource_descriptors: self.descriptor_manager.stats(DescriptorType::Resource),
+            render_target_views: self
+                .descriptor_manager
+                .stats(DescriptorType::RenderTargetView),
+            depth_stencil_views: self
+                .descriptor_manager
+                .stats(DescriptorType::DepthStencilView),
+            transient_descriptors: self.descriptor_manager.transient_stats(),
+            high_priority_uploads: self.upload_rings.stats(UploadPriority::High),
+            background_uploads: self.upload_rings.stats(UploadPriority::Background),
+        }
+    }
 }
+
 #[derive(Debug)]
 pub(crate) struct Renderer {
     #[allow(dead_code)]
     hwnd: HWND,
+    adapter: IDXGIAdapter1,
     #[allow(dead_code)]
     dxgi_factory: IDXGIFactory5,
 
     command_allocators: [ID3D12CommandAllocator; FRAME_COUNT as usize],
     graphics_queue: CommandQueue,
     swap_chain: IDXGISwapChain3,
-    back_buffer_handles: [TextureHandle; FRAME_COUNT],
-    depth_buffer_handles: [TextureHandle; FRAME_COUNT],
+    /// How the display reads the back buffers, only HDR10 swap chains get HDR metadata
+    color_space: DXGI_COLOR_SPACE_TYPE,
+    frame_latency: FrameLatencyWaiter,
+    render_targets: Vec<RenderTarget>,
     command_list: ID3D12GraphicsCommandList,
+    barriers: BarrierBatcher,
     fence_values: [u64; FRAME_COUNT as usize],
 
     pub(crate) resources: Resources,
 
     basic_render_pass: BindlessTexturePass<FRAME_COUNT>,
 
+    scene_target: RenderTarget,
+    // From here on, passes that read the descriptor heap directly are None below shader model
+    // 6.6, see `Renderer::new`
+    depth_of_field_pass: Option<DepthOfFieldPass>,
+    motion_blur_pass: Option<MotionBlurPass>,
+    auto_exposure_pass: Option<AutoExposurePass>,
+    color_grading_pass: Option<ColorGradingPass>,
+    upscale_pass: UpscalePass,
+    overlay_target: RenderTarget,
+    composite_pass: CompositePass,
+    /// Replaces the upscale and composite draws when the back buffers have UAVs
+    compute_composite_pass: Option<ComputeCompositePass>,
+    gpu_timer: GpuTimer,
+    breadcrumbs: Breadcrumbs,
+    pipeline_statistics: PipelineStatisticsQueries,
+    /// Of the last frame the GPU finished
+    frame_profile: FrameProfile,
+    dynamic_resolution: DynamicResolution,
+    shading_rate_pass: Option<ShadingRatePass>,
+    texture_streaming: Option<TextureStreaming>,
+    fence_watcher: FenceWatcher,
+    depth_readback_pass: Option<DepthReadbackPass>,
+    hi_z_pass: Option<HiZPass>,
+    /// Draws the scene's objects indirectly, leaving out the ones the Hi-Z pyramid hides
+    occlusion_culling_pass: Option<OcclusionCullingPass<FRAME_COUNT>>,
+    grid_pass: GridPass,
+    memory_hud_pass: MemoryHudPass,
+    profiler_graph_pass: Option<ProfilerGraphPass<FRAME_COUNT>>,
+    environment_probe_pass: Option<EnvironmentProbePass<FRAME_COUNT>>,
+    light_culling_pass: Option<LightCullingPass<FRAME_COUNT>>,
+    morph_target_pass: Option<MorphTargetPass<FRAME_COUNT>>,
+    water_pass: Option<WaterPass<FRAME_COUNT>>,
+    texture_dump_pass: Option<TextureDumpPass>,
+    debug_line_pass: Option<DebugLinePass<FRAME_COUNT>>,
+
+    minimap_pass: BindlessTexturePass<FRAME_COUNT>,
+    minimap_target: RenderTarget,
+    minimap_camera: Camera,
+
+    /// Drawn into the scene target in order, the first one is the main view
+    pub(crate) views: Vec<View>,
+    camera_controller: CameraController,
+    frame_clock: FrameClock,
+    /// Time and mouse state for the global constants, the rest is filled in when rendering
+    shader_globals: GlobalConstants,
+    simulation_timestep: FixedTimestep,
     objects: Vec<Object>,
+    lights: Vec<PointLight>,
+    scene_assets: SceneAssets,
+    transform_cache: TransformCache,
+    visibility: Visibility,
+    #[cfg(feature = "physics")]
+    physics: Option<Physics>,
+    /// Object the gizmo is attached to, picked with the left mouse button
+    selected_object: Option<usize>,
+    // Surface that isn't an object picked from the depth readback, marked with a cross
+    picked_surface: Option<Vec3>,
+    gizmo: Gizmo,
+    // Local position, rotation and scale of the selected object when a gizmo drag started
+    gizmo_drag_start: Option<(Vec3, Quat, Vec3)>,
+
+    recording: Option<Recording>,
+    frame_capture: Option<FrameCapture>,
+    /// Frames rendered since start up
+    frame_number: u64,
+    settings: Settings,
+    /// Set when the last present found nothing of the window visible
+    occluded: bool,
+    // Whether anything changed since the last frame, for drawing on demand
+    redraw_requested: bool,
+    /// Presents can tear with vsync off
+    allow_tearing: bool,
+    /// How the back buffers are turned against the window, only ever not identity in fullscreen
+    /// with `--pre-rotate`
+    display_rotation: DisplayRotation,
+    /// Between `suspend` and `resume` there are no back buffers to render to
+    suspended: bool,
+    /// Kept to create the renderer again if the device is lost
+    config: Config,
 }
 
 #[derive(Debug)]
@@ -98,14 +433,77 @@ impl Application {
         Application { renderer: None }
     }
 
-    pub fn new(hwnd: HWND, window_size: (u32, u32)) -> Result<Application> {
+    pub fn new(
+        hwnd: HWND,
+        window_size: (u32, u32),
+        config: &Config,
+        settings: Settings,
+    ) -> Result<Application> {
         Ok(Self {
-            renderer: Some(Renderer::new(hwnd, window_size)?),
+            renderer: Some(Renderer::new(hwnd, window_size, config, settings)?),
         })
     }
 
+    /// Fails with a `DeviceRemovedError` if the device was removed, which holds what DRED and
+    /// the renderer's own breadcrumbs recorded
     pub fn render(&mut self) -> Result<()> {
-        self.renderer.as_mut().context("No renderer")?.render()
+        let renderer = self.renderer.as_mut().context("No renderer")?;
+        renderer.render().map_err(|err| {
+            match DeviceRemovedError::check(&renderer.resources.device, || {
+                renderer.breadcrumbs.report()
+            }) {
+                Some(removed) => removed.into(),
+                None => err,
+            }
+        })
+    }
+
+    /// The GPU time and the work of each pass of the last frame the GPU finished
+    pub fn frame_profile(&self) -> Result<FrameProfile> {
+        Ok(self
+            .renderer
+            .as_ref()
+            .context("No renderer")?
+            .frame_profile
+            .clone())
+    }
+
+    /// How the last presents reached the screen, None until the statistics settle
+    pub fn present_statistics(&self) -> Result<Option<PresentStatistics>> {
+        PresentStatistics::query(&self.renderer.as_ref().context("No renderer")?.swap_chain)
+    }
+
+    /// Live textures from the least to the most recently used by the GPU, the ones it is done
+    /// with first
+    pub fn texture_usage(&self) -> Result<TextureLru> {
+        let renderer = self.renderer.as_ref().context("No renderer")?;
+        let completed_fence_value = unsafe { renderer.graphics_queue.fence().GetCompletedValue() };
+
+        Ok(renderer
+            .resources
+            .texture_manager
+            .least_recently_used(completed_fence_value))
+    }
+
+    /// Asks for a frame to be drawn when rendering on demand
+    pub fn request_redraw(&mut self) {
+        if let Some(renderer) = &mut self.renderer {
+            renderer.request_redraw();
+        }
+    }
+
+    /// Whether a frame was asked for since the last call
+    pub fn take_redraw_request(&mut self) -> bool {
+        self.renderer
+            .as_mut()
+            .is_some_and(|renderer| std::mem::take(&mut renderer.redraw_requested))
+    }
+
+    /// Whether rendering is being skipped because nothing of the window can be seen
+    pub fn is_occluded(&self) -> bool {
+        self.renderer
+            .as_ref()
+            .is_some_and(|renderer| renderer.occluded)
     }
 
     pub fn resize(&mut self, extent: (u32, u32)) -> Result<()> {
@@ -115,331 +513,975 @@ impl Application {
             .resize(extent)
     }
 
+    /// Lets go of the back buffers, e.g. while the window is hidden or handed to another parent.
+    /// Nothing is rendered until `resume`.
+    #[allow(dead_code)]
+    pub fn suspend(&mut self) -> Result<()> {
+        self.renderer.as_mut().context("No renderer")?.suspend()
+    }
+
+    /// Creates the back buffers again at `window_size`. A device that hung or was reset while
+    /// suspended can't be brought back, so the whole renderer is created again instead.
+    #[allow(dead_code)]
+    pub fn resume(&mut self, window_size: (u32, u32)) -> Result<()> {
+        let renderer = self.renderer.as_mut().context("No renderer")?;
+        if let Err(err) = unsafe { renderer.resources.device.GetDeviceRemovedReason() } {
+            if err.code() != DXGI_ERROR_DEVICE_HUNG && err.code() != DXGI_ERROR_DEVICE_RESET {
+                return Err(err).context("Device removed while suspended");
+            }
+            eprintln!(
+                "Device lost while suspended, creating the renderer again: {:?}",
+                err
+            );
+
+            let renderer = self.renderer.take().context("No renderer")?;
+            let hwnd = renderer.hwnd;
+            let config = renderer.config.clone();
+            let settings = renderer.settings.clone();
+            // A window only takes one swap chain, the old one has to be gone first
+            drop(renderer);
+
+            self.renderer = Some(Renderer::new(hwnd, window_size, &config, settings)?);
+            return Ok(());
+        }
+
+        renderer.resume(window_size)
+    }
+
     pub fn wait_for_idle(&mut self) -> Result<()> {
         self.renderer
             .as_mut()
             .context("No renderer")?
             .wait_for_idle()
     }
-}
-impl Renderer {
-    pub fn new(hwnd: HWND, window_size: (u32, u32)) -> Result<Renderer> {
-        if cfg!(debug_assertions) {
-            unsafe {
-                let mut debug: Option<ID3D12Debug> = None;
-                if let Some(debug) = D3D12GetDebugInterface(&mut debug).ok().and(debug) {
-                    debug.EnableDebugLayer();
-                }
-            }
-        }
-
-        let dxgi_factory = create_dxgi_factory()?;
 
-        let feature_level = D3D_FEATURE_LEVEL_12_2;
+    /// Records every presented frame into an MP4 at `path` until `stop_recording`
+    pub fn start_recording(&mut self, path: &Path, frames_per_second: u32) -> Result<()> {
+        self.renderer
+            .as_mut()
+            .context("No renderer")?
+            .start_recording(path, frames_per_second)
+    }
 
-        let adapter = get_hardware_adapter(&dxgi_factory, feature_level)?;
+    pub fn stop_recording(&mut self) -> Result<()> {
+        self.renderer
+            .as_mut()
+            .context("No renderer")?
+            .stop_recording()
+    }
 
-        let device = create_device(&adapter, feature_level)?;
+    pub fn is_recording(&self) -> bool {
+        self.renderer
+            .as_ref()
+            .is_some_and(|renderer| renderer.recording.is_some())
+    }
 
-        let data_options = D3D12_FEATURE_DATA_D3D12_OPTIONS {
-            ResourceHeapTier: D3D12_RESOURCE_HEAP_TIER_2,
-            ..Default::default()
-        };
-        unsafe {
-            device
-                .CheckFeatureSupport(
-                    D3D12_FEATURE_D3D12_OPTIONS,
-                    std::ptr::addr_of!(data_options) as *mut c_void,
-                    std::mem::size_of_val(&data_options) as u32,
-                )
-                .expect("Feature not supported");
-        }
+    /// Freezes the scene while rendering carries on, or lets it move again
+    pub fn toggle_pause(&mut self) -> Result<()> {
+        self.renderer
+            .as_mut()
+            .context("No renderer")?
+            .frame_clock
+            .toggle_pause();
 
-        let (width, height) = window_size;
+        Ok(())
+    }
 
-        let mut graphics_queue = CommandQueue::new(
-            &device,
-            D3D12_COMMAND_LIST_TYPE_DIRECT,
-            "Main Graphics Queue",
-        )?;
+    /// Pauses, and moves the scene on by exactly one frame at the next update
+    pub fn step_frame(&mut self) -> Result<()> {
+        self.renderer
+            .as_mut()
+            .context("No renderer")?
+            .frame_clock
+            .step();
 
-        let upload_ring_buffer = UploadRingBuffer::new(&device, None, Some(5e8 as usize))?;
-        let mut texture_manager = TextureManager::new(&device, None)?;
-        let mut descriptor_manager = DescriptorManager::new(&device)?;
-        let mesh_manager = MeshManager::new(&device)?;
+        Ok(())
+    }
 
-        let swap_chain_format = DXGI_FORMAT_R8G8B8A8_UNORM;
-        let swap_chain = create_swapchain(
-            hwnd,
-            &dxgi_factory,
-            &graphics_queue,
-            FRAME_COUNT as u32,
-            swap_chain_format,
-            (width, height),
-        )?;
-        let frame_index = unsafe { swap_chain.GetCurrentBackBufferIndex() };
-        unsafe {
-            dxgi_factory.MakeWindowAssociation(hwnd, DXGI_MWA_NO_ALT_ENTER)?;
+    /// What the window title shows while the scene is paused or not running in real time
+    pub fn clock_status(&self) -> Option<String> {
+        let frame_clock = &self.renderer.as_ref()?.frame_clock;
+        if frame_clock.is_paused() {
+            Some(format!("Paused at frame {}", frame_clock.frame()))
+        } else if frame_clock.time_scale() != 1.0 {
+            Some(format!("{}x speed", frame_clock.time_scale()))
+        } else {
+            None
         }
+    }
 
-        let mut back_buffer_handles: [TextureHandle; FRAME_COUNT] = Default::default();
-        let mut depth_buffer_handles: [TextureHandle; FRAME_COUNT] = Default::default();
-        for i in 0..FRAME_COUNT {
-            let back_buffer: ID3D12Resource = unsafe { swap_chain.GetBuffer(i as u32) }?;
-            unsafe {
-                back_buffer.SetName(PCWSTR::from(&format!("Backbuffer {}", COUNTER).into()))?;
-                COUNTER += 1;
-            }
-            let back_buffer = Resource {
-                device_resource: back_buffer,
-                size: (width * height * 4) as usize,
-                mapped_data: std::ptr::null_mut(),
-            };
-            let back_buffer = Texture {
-                info: TextureInfo {
-                    dimension: TextureDimension::Two(width as usize, height),
-                    format: swap_chain_format,
-                    array_size: 1,
-                    num_mips: 1,
-                    is_render_target: true,
-                    is_depth_buffer: false,
-                    is_unordered_access: false,
-                },
-                resource: Some(back_buffer),
-            };
+    /// Runs the scene `factor` times faster than it does now, between 1/16 and 16 times real time
+    pub fn scale_time(&mut self, factor: f32) -> Result<()> {
+        self.renderer
+            .as_mut()
+            .context("No renderer")?
+            .frame_clock
+            .scale_time(factor);
 
-            back_buffer_handles[i] =
-                texture_manager.add_texture(&device, &mut descriptor_manager, back_buffer)?;
-
-            depth_buffer_handles[i] = texture_manager.create_empty_texture(
-                &device,
-                TextureInfo {
-                    dimension: TextureDimension::Two(width as usize, height),
-                    format: DXGI_FORMAT_D32_FLOAT,
-                    array_size: 1,
-                    num_mips: 1,
-                    is_render_target: false,
-                    is_depth_buffer: true,
-                    is_unordered_access: false,
-                },
-                Some(D3D12_CLEAR_VALUE {
-                    Format: DXGI_FORMAT_D32_FLOAT,
-                    Anonymous: D3D12_CLEAR_VALUE_0 {
-                        DepthStencil: D3D12_DEPTH_STENCIL_VALUE {
-                            Depth: 1.0,
-                            Stencil: 0,
-                        },
-                    },
-                }),
-                D3D12_RESOURCE_STATE_DEPTH_WRITE,
-                &mut descriptor_manager,
-                true,
-            )?;
-        }
+        Ok(())
+    }
 
-        let viewport = D3D12_VIEWPORT {
-            TopLeftX: 0.0,
-            TopLeftY: 0.0,
-            Width: width as f32,
-            Height: height as f32,
-            MinDepth: D3D12_MIN_DEPTH,
-            MaxDepth: D3D12_MAX_DEPTH,
-        };
+    /// Splits the screen between the main view and a second camera, or goes back to the main view
+    pub fn toggle_split_screen(&mut self) -> Result<()> {
+        self.renderer
+            .as_mut()
+            .context("No renderer")?
+            .toggle_split_screen();
 
-        let scissor_rect = RECT {
-            left: 0,
-            top: 0,
-            right: width as i32,
-            bottom: height as i32,
-        };
+        Ok(())
+    }
 
-        let aspect_ratio = (width as f32) / (height as f32);
-        let camera = Camera {
-            V: glam::Mat4::from_translation(Vec3::new(0.0, -0.8, 1.5)).inverse(),
-            P: glam::Mat4::perspective_lh(PI / 2.0, aspect_ratio, 0.1, 100.0),
-        };
-        let mut resources = Resources {
-            device,
-            frame_index,
-            descriptor_manager,
-            texture_manager,
-            mesh_manager,
-            upload_ring_buffer,
-            viewport,
-            scissor_rect,
-            camera,
-        };
+    /// Shows or hides the ground grid and world axes
+    pub fn toggle_grid(&mut self) -> Result<()> {
+        self.update_settings(|settings| settings.show_grid = !settings.show_grid)
+    }
 
-        let command_allocators: [ID3D12CommandAllocator; FRAME_COUNT as usize] =
-            array_init::try_array_init(|_| -> Result<ID3D12CommandAllocator> {
-                let allocator = unsafe {
-                    resources
-                        .device
-                        .CreateCommandAllocator(D3D12_COMMAND_LIST_TYPE_DIRECT)
-                }?;
-                Ok(allocator)
-            })?;
+    /// Shows or hides the memory bars, and prints the full numbers when showing them
+    pub fn toggle_memory_hud(&mut self) -> Result<()> {
+        self.update_settings(|settings| settings.show_memory_hud = !settings.show_memory_hud)?;
 
-        let command_list: ID3D12GraphicsCommandList = unsafe {
-            resources.device.CreateCommandList1(
-                0,
-                D3D12_COMMAND_LIST_TYPE_DIRECT,
-                D3D12_COMMAND_LIST_FLAG_NONE,
-            )
-        }?;
+        let renderer = self.renderer.as_ref().context("No renderer")?;
+        if renderer.settings.show_memory_hud {
+            print!("{}", renderer.resources.stats());
+        }
 
-        let (vertices, indices) = load_bunny()?;
+        Ok(())
+    }
 
-        let vb_desc = D3D12_RESOURCE_DESC {
-            Dimension: D3D12_RESOURCE_DIMENSION_BUFFER,
-            Width: std::mem::size_of_val(vertices.as_slice()) as u64,
-            Height: 1,
-            DepthOrArraySize: 1,
-            MipLevels: 1,
-            SampleDesc: DXGI_SAMPLE_DESC {
-                Count: 1,
-                Quality: 0,
-            },
-            Layout: D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
-            ..Default::default()
-        };
+    /// Shows or hides the GPU time graphs of the frame and its passes, and prints the passes
+    /// in the order of their graphs when showing them
+    pub fn toggle_profiler_graphs(&mut self) -> Result<()> {
+        self.update_settings(|settings| {
+            settings.show_profiler_graphs = !settings.show_profiler_graphs
+        })?;
 
-        let vertex_buffer = resources.mesh_manager.heap.create_resource(
-            &resources.device,
-            &vb_desc,
-            D3D12_RESOURCE_STATE_COMMON,
-            None,
-            false,
-        )?;
+        let renderer = self.renderer.as_ref().context("No renderer")?;
+        if renderer.settings.show_profiler_graphs {
+            println!("{}", renderer.frame_profile);
+        }
 
-        let upload = resources
-            .upload_ring_buffer
-            .allocate(std::mem::size_of_val(vertices.as_slice()))?;
-        upload.sub_resource.copy_from(&vertices)?;
-        upload
-            .sub_resource
-            .copy_to_resource(&upload.command_list, &vertex_buffer)?;
-        upload.submit(Some(&graphics_queue))?;
+        Ok(())
+    }
 
-        let index_buffer_desc = D3D12_RESOURCE_DESC {
-            Dimension: D3D12_RESOURCE_DIMENSION_BUFFER,
-            Width: std::mem::size_of_val(indices.as_slice()) as u64,
-            Height: 1,
-            DepthOrArraySize: 1,
-            MipLevels: 1,
-            SampleDesc: DXGI_SAMPLE_DESC {
-                Count: 1,
-                Quality: 0,
-            },
-            Layout: D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
-            ..Default::default()
-        };
+    /// Switches between adapting the exposure to the scene and the graded exposure alone
+    pub fn toggle_auto_exposure(&mut self) -> Result<()> {
+        self.update_settings(|settings| {
+            settings.auto_exposure.enabled = !settings.auto_exposure.enabled
+        })
+    }
 
-        let index_buffer = resources.mesh_manager.heap.create_resource(
-            &resources.device,
-            &index_buffer_desc,
-            D3D12_RESOURCE_STATE_COMMON,
-            None,
-            false,
-        )?;
+    pub fn toggle_depth_of_field(&mut self) -> Result<()> {
+        self.update_settings(|settings| {
+            settings.depth_of_field.enabled = !settings.depth_of_field.enabled
+        })
+    }
 
-        let upload = resources
-            .upload_ring_buffer
-            .allocate(index_buffer_desc.Width as usize)?;
-        upload.sub_resource.copy_from(&indices)?;
-        upload
-            .sub_resource
-            .copy_to_resource(&upload.command_list, &index_buffer)?;
-        upload.submit(Some(&graphics_queue))?;
+    /// Focuses the depth of field on the surface under a screen position, see `pick`. Returns
+    /// the new focus distance, or None when nothing was there and the focus stays.
+    pub fn focus_at(&mut self, uv: Vec2) -> Result<Option<f32>> {
+        let Some(position) = self.pick(uv) else {
+            return Ok(None);
+        };
+        let distance = self.renderer.as_ref().context("No renderer")?.views[0]
+            .camera
+            .view_depth(position);
+        self.update_settings(|settings| settings.depth_of_field.focus_distance = distance)?;
 
-        // TEXTURE UPLOAD
+        Ok(Some(distance))
+    }
 
-        let f = File::open(r"assets/uv_checker.dds")?;
-        let reader = BufReader::new(f);
+    pub fn settings(&self) -> Result<&Settings> {
+        Ok(&self.renderer.as_ref().context("No renderer")?.settings)
+    }
 
-        let dds_file = ddsfile::Dds::read(reader)?;
+    /// Changes the settings, and lets every pass depending on a changed one know
+    pub fn update_settings(&mut self, update: impl FnOnce(&mut Settings)) -> Result<()> {
+        self.renderer
+            .as_mut()
+            .context("No renderer")?
+            .update_settings(update);
 
-        let dimension = if dds_file.get_depth() > 1 {
-            TextureDimension::Three(
-                dds_file.get_width() as usize,
-                dds_file.get_height(),
-                dds_file.get_depth() as u16,
-            )
-        } else if dds_file.get_height() > 1 {
-            TextureDimension::Two(dds_file.get_width() as usize, dds_file.get_height())
+        Ok(())
+    }
+
+    #[allow(dead_code)]
+    pub fn memory_stats(&self) -> Result<Stats> {
+        Ok(self
+            .renderer
+            .as_ref()
+            .context("No renderer")?
+            .resources
+            .stats())
+    }
+
+    /// Captures the scene around the main camera into an environment probe over the next frames
+    pub fn bake_environment_probe(&mut self) -> Result<()> {
+        self.renderer
+            .as_mut()
+            .context("No renderer")?
+            .bake_environment_probe();
+
+        Ok(())
+    }
+
+    /// The material `object` is drawn with
+    #[allow(dead_code)]
+    pub fn material(&self, object: usize) -> Result<&Material> {
+        let renderer = self.renderer.as_ref().context("No renderer")?;
+        let object = renderer
+            .objects
+            .get(object)
+            .with_context(|| format!("No object {}", object))?;
+
+        Ok(&object.material)
+    }
+
+    /// Edits the material of `object`, the next frame draws with it
+    #[allow(dead_code)]
+    pub fn update_material(
+        &mut self,
+        object: usize,
+        update: impl FnOnce(&mut Material),
+    ) -> Result<()> {
+        self.renderer
+            .as_mut()
+            .context("No renderer")?
+            .update_material(object, update)
+    }
+
+    /// Saves the objects, lights and main camera to a JSON scene file
+    pub fn save_scene(&self, path: &Path) -> Result<()> {
+        self.renderer
+            .as_ref()
+            .context("No renderer")?
+            .save_scene(path)
+    }
+
+    /// Replaces the objects, lights and main camera with those saved in a scene file
+    pub fn load_scene(&mut self, path: &Path) -> Result<()> {
+        self.renderer
+            .as_mut()
+            .context("No renderer")?
+            .load_scene(path)
+    }
+
+    /// Saves the scene, minimap and overlay targets as images in the working directory
+    pub fn dump_textures(&mut self) -> Result<()> {
+        self.renderer
+            .as_mut()
+            .context("No renderer")?
+            .dump_textures();
+
+        Ok(())
+    }
+
+    /// Goes fullscreen on the monitor the window is on, or back to a window
+    pub fn toggle_fullscreen(&mut self) -> Result<()> {
+        let renderer = self.renderer.as_mut().context("No renderer")?;
+        if renderer.is_fullscreen()? {
+            renderer.set_fullscreen(None)
+        } else {
+            let output = renderer.current_output()?;
+            renderer.set_fullscreen(Some(&output))
+        }
+    }
+
+    /// Moves the window, or fullscreen, on to the next monitor
+    pub fn move_to_next_output(&mut self) -> Result<()> {
+        let renderer = self.renderer.as_mut().context("No renderer")?;
+        let outputs = renderer.outputs()?;
+        let current = renderer.current_output()?;
+        let index = outputs
+            .iter()
+            .position(|output| output.name == current.name)
+            .unwrap_or(0);
+
+        match outputs.get((index + 1) % outputs.len().max(1)) {
+            Some(next) => renderer.move_to_output(next),
+            None => Ok(()),
+        }
+    }
+
+    /// Flies the main camera with a frame of input, `delta_time` is in seconds. Does nothing while
+    /// paused, apart from single steps, but objects can still be selected and edited.
+    pub fn update(&mut self, input: &InputSnapshot, delta_time: f32) -> Result<()> {
+        let renderer = self.renderer.as_mut().context("No renderer")?;
+        renderer.update_gizmo(input);
+        renderer.update_shader_globals(input);
+        let delta_time = if renderer.config.profiling {
+            PROFILING_DELTA_TIME
         } else {
-            TextureDimension::One(dds_file.get_width() as usize)
+            delta_time
         };
+        let delta_time = match renderer.frame_clock.advance(delta_time) {
+            Some(delta_time) => delta_time,
+            None => return Ok(()),
+        };
+        renderer.shader_globals.time += delta_time;
+        renderer.shader_globals.delta_time = delta_time;
+
+        let view_projection = renderer.views[0].camera.view_projection();
+        renderer
+            .camera_controller
+            .update(&mut renderer.views[0].camera, input, delta_time);
+        if let Some(water_pass) = &mut renderer.water_pass {
+            water_pass.advance(delta_time);
+        }
+        // The camera follows every frame to stay responsive, the scene moves in fixed steps
+        for _ in 0..renderer.simulation_timestep.advance(delta_time) {
+            renderer.transform_cache.begin_step(&renderer.objects);
+            for object in &mut renderer.objects {
+                object.simulate(renderer.simulation_timestep.step());
+            }
+            #[cfg(feature = "physics")]
+            if let Some(physics) = &mut renderer.physics {
+                physics.step(&mut renderer.objects);
+            }
+        }
+        if input.scroll != 0.0 {
+            renderer.zoom(input.scroll)?;
+        }
+        if renderer.views[0].camera.view_projection() != view_projection {
+            renderer.request_redraw();
+        }
+
+        Ok(())
+    }
+
+    /// Switches the gizmo between moving, rotating and scaling the selected object
+    pub fn set_gizmo_mode(&mut self, mode: GizmoMode) -> Result<()> {
+        let renderer = self.renderer.as_mut().context("No renderer")?;
+        renderer.gizmo.end_drag();
+        renderer.gizmo.mode = mode;
+        renderer.request_redraw();
+
+        Ok(())
+    }
 
-        let texture_info = TextureInfo {
-            dimension,
-            format: DXGI_FORMAT(dds_file.get_dxgi_format().context("No DXGI format")? as u32),
-            array_size: dds_file.get_num_array_layers() as u16,
-            num_mips: dds_file.get_num_mipmap_levels() as u16,
-            is_render_target: false,
-            is_depth_buffer: false,
-            is_unordered_access: false,
+    /// Blends the first morph target of the selected object in a quarter further, back to none
+    /// after all of it. Returns the new weight, None without a selected object.
+    pub fn step_morph_weight(&mut self) -> Result<Option<f32>> {
+        let renderer = self.renderer.as_mut().context("No renderer")?;
+        let Some(index) = renderer.selected_object else {
+            return Ok(None);
         };
+        let object = &mut renderer.objects[index];
+        ensure!(
+            renderer
+                .resources
+                .mesh_manager
+                .get_morph_targets(&object.mesh)
+                .is_some(),
+            "The selected object's mesh has no morph targets"
+        );
 
-        let texture = resources.texture_manager.create_texture(
+        if object.morph_weights.is_empty() {
+            object.morph_weights.push(0.0);
+        }
+        let weight = &mut object.morph_weights[0];
+        *weight = if *weight >= 1.0 { 0.0 } else { *weight + 0.25 };
+        let weight = *weight;
+        renderer.request_redraw();
+
+        Ok(Some(weight))
+    }
+
+    /// The closest object under a screen position, hit against CPU copies of the meshes. `uv` goes
+    /// from (0, 0) at the top left to (1, 1) at the bottom right.
+    pub fn pick_object(&self, uv: Vec2) -> Option<RaycastHit> {
+        let renderer = self.renderer.as_ref()?;
+        renderer.raycast(&renderer.cursor_ray(uv)?)
+    }
+
+    /// The closest object along a world space ray, for gameplay style queries
+    #[allow(dead_code)]
+    pub fn raycast(&self, ray: &Ray) -> Option<RaycastHit> {
+        self.renderer.as_ref()?.raycast(ray)
+    }
+
+    /// Marks the closest surface under a screen position unless it is an object, which the
+    /// renderer selects itself. Clears the mark when there is nothing.
+    pub fn mark_picked_surface(&mut self, uv: Vec2) {
+        let position = match self.pick_object(uv) {
+            Some(_) => None,
+            None => self.pick(uv),
+        };
+        if let Some(renderer) = &mut self.renderer {
+            renderer.picked_surface = position;
+            renderer.request_redraw();
+        }
+    }
+
+    /// World position of the closest surface under a screen position, from a depth readback a
+    /// few frames old. `uv` goes from (0, 0) at the top left to (1, 1) at the bottom right.
+    pub fn pick(&self, uv: Vec2) -> Option<Vec3> {
+        self.renderer
+            .as_ref()?
+            .depth_readback_pass
+            .as_ref()?
+            .latest()?
+            .world_position(uv)
+    }
+}
+impl Renderer {
+    pub fn new(
+        hwnd: HWND,
+        window_size: (u32, u32),
+        config: &Config,
+        settings: Settings,
+    ) -> Result<Renderer> {
+        if cfg!(debug_assertions) {
+            unsafe {
+                let mut debug: Option<ID3D12Debug> = None;
+                if let Some(debug) = D3D12GetDebugInterface(&mut debug).ok().and(debug) {
+                    debug.EnableDebugLayer();
+                }
+            }
+            if let Err(err) = enable_dred() {
+                eprintln!("DRED is not available: {:?}", err);
+            }
+        }
+
+        let dxgi_factory = create_dxgi_factory()?;
+
+        // Shader model 6.6 and the passes that need it are optional, see `dynamic_resources`
+        let feature_level = D3D_FEATURE_LEVEL_12_0;
+
+        let adapter = get_adapter(
+            &dxgi_factory,
+            feature_level,
+            config.adapter.unwrap_or_default(),
+        )?;
+
+        let device = create_device(&adapter, feature_level)?;
+        if config.profiling {
+            // anyhow's Ok shadows the variant
+            match enable_stable_power_state(&device) {
+                Result::Ok(true) => println!("Profiling with stable GPU clocks"),
+                Result::Ok(false) => {
+                    eprintln!("Developer mode is off, profiling with boosting GPU clocks")
+                }
+                Err(err) => eprintln!("Stable power state failed: {:?}", err),
+            }
+        }
+
+        let capabilities = DeviceCapabilities::query(&device)?;
+        ensure!(
+            capabilities.resource_heap_tier().0 >= D3D12_RESOURCE_HEAP_TIER_2.0,
+            "The device doesn't support resource heap tier 2"
+        );
+        // Without it the scene, minimap and overlays bind their textures through tables and
+        // everything else reading the heap directly is left out
+        let dynamic_resources = capabilities.dynamic_resources();
+        // Shaders need at least the model they are loaded for, but compile for what the device
+        // runs from here on. The ones that can bind either way pick by the model, so they stay
+        // below 6.6 without dynamic resources to match their root signatures.
+        set_shader_model(if dynamic_resources {
+            capabilities.shader_model
+        } else {
+            capabilities.shader_model.min(ShaderModel::SM_6_5)
+        });
+
+        let (width, height) = window_size;
+
+        let mut graphics_queue = CommandQueue::new(
+            &device,
+            D3D12_COMMAND_LIST_TYPE_DIRECT,
+            "Main Graphics Queue",
+        )?;
+
+        let mut upload_rings = UploadRings::new(
+            &device,
+            HIGH_PRIORITY_UPLOAD_RING_SIZE,
+            BACKGROUND_UPLOAD_RING_SIZE,
+        )?;
+        let mut texture_manager = TextureManager::new(&device, None)?;
+        let mut descriptor_manager = DescriptorManager::new(&device)?;
+        descriptor_manager.reserve_transient(FRAME_COUNT, TRANSIENT_DESCRIPTORS_PER_FRAME)?;
+        let mesh_manager = MeshManager::new(&device)?;
+
+        let swap_chain_format = DXGI_FORMAT_R8G8B8A8_UNORM;
+        let allow_tearing = tearing_supported(&dxgi_factory);
+        let swap_chain = create_swapchain(
+            hwnd,
+            &dxgi_factory,
+            &graphics_queue,
+            config.back_buffers,
+            swap_chain_format,
+            (width, height),
+            allow_tearing,
+            config.compute_composite,
+            config.pre_rotate,
+        )?;
+        let frame_latency = FrameLatencyWaiter::new(&swap_chain, config.max_frame_latency)?;
+        // Frames in flight take turns in order, whichever back buffer comes next
+        let frame_index = 0;
+        unsafe {
+            dxgi_factory.MakeWindowAssociation(hwnd, DXGI_MWA_NO_ALT_ENTER)?;
+        }
+
+        let render_targets = create_back_buffer_targets(
+            &device,
+            &swap_chain,
+            &mut texture_manager,
+            &mut descriptor_manager,
+            (width, height),
+        )?;
+
+        let aspect_ratio = (width as f32) / (height as f32);
+        let camera = Camera::perspective(
+            glam::Mat4::from_translation(Vec3::new(0.0, -0.8, 1.5)).inverse(),
+            aspect_ratio,
+        );
+        // Included by shaders that read packed buffers, so it has to exist before they compile
+        write_packing_header("renderer/src/shaders/generated/packing.hlsli")?;
+        write_globals_header("renderer/src/shaders/generated/globals.hlsli")?;
+        write_shader_types_header("renderer/src/shaders/generated/shader_types.hlsli")?;
+        let global_constants = GlobalConstantBuffer::new(&device)?;
+
+        let builtin_textures = BuiltinTextures::new(
+            &device,
+            &mut texture_manager,
+            &mut descriptor_manager,
+            upload_rings.get_mut(UploadPriority::High),
+            Some(&graphics_queue),
+        )?;
+
+        let mut resources = Resources {
+            device,
+            frame_index,
+            descriptor_manager,
+            texture_manager,
+            mesh_manager,
+            upload_rings,
+            capabilities,
+            depth_range: DEPTH_RANGE,
+            builtin_textures,
+            global_constants,
+        };
+
+        let command_allocators: [ID3D12CommandAllocator; FRAME_COUNT as usize] =
+            array_init::try_array_init(|_| -> Result<ID3D12CommandAllocator> {
+                let allocator = unsafe {
+                    resources
+                        .device
+                        .CreateCommandAllocator(D3D12_COMMAND_LIST_TYPE_DIRECT)
+                }?;
+                Ok(allocator)
+            })?;
+
+        let command_list: ID3D12GraphicsCommandList = unsafe {
+            resources.device.CreateCommandList1(
+                0,
+                D3D12_COMMAND_LIST_TYPE_DIRECT,
+                D3D12_COMMAND_LIST_FLAG_NONE,
+            )
+        }?;
+        // Shows up in DRED's reports
+        unsafe {
+            command_list.SetName(PCWSTR::from(&FRAME_COMMAND_LIST_NAME.into()))?;
+        }
+
+        resources.mesh_manager.create_pool(
             &resources.device,
-            &mut resources.upload_ring_buffer,
+            MESH_POOL_VERTEX_BUFFER_SIZE,
+            MESH_POOL_INDEX_BUFFER_SIZE,
+        )?;
+
+        // Picking raycasts against the scene's meshes
+        resources.mesh_manager.retain_cpu_copies = true;
+
+        // The finest level goes to the GPU chunk by chunk as the file is read, the coarser ones
+        // are simplified from the vertices gathered along the way
+        let bunny = BufReader::new(
+            File::open(&config.scene)
+                .with_context(|| format!("Opening {}", config.scene.display()))?,
+        );
+        let mut bunny_lod0 = ObjChunk::default();
+        let finest_handle = resources.mesh_manager.add_streamed(
+            resources.upload_rings.get_mut(UploadPriority::Background),
             Some(&graphics_queue),
-            &mut resources.descriptor_manager,
-            texture_info,
-            &dds_file.data,
+            parse_obj_chunked(bunny.lines().map_while(std::io::Result::ok), OBJ_CHUNK_SIZE)
+                .inspect(|chunk| {
+                    if let std::result::Result::Ok(chunk) = chunk {
+                        bunny_lod0.vertices.extend_from_slice(&chunk.vertices);
+                        bunny_lod0.indices.extend_from_slice(&chunk.indices);
+                    }
+                }),
+        )?;
+        let bunny_coarser_lods = generate_lods(
+            &bunny_lod0.vertices,
+            &bunny_lod0.indices,
+            &LOD_GRID_RESOLUTIONS,
+        );
+        let bunny_morph_targets = create_demo_morph_targets(&bunny_lod0.vertices);
+        let bunny_meshlets = if resources.capabilities.mesh_shaders() {
+            std::iter::once(&bunny_lod0)
+                .chain(&bunny_coarser_lods)
+                .map(|lod| build_meshlets(&lod.vertices, &lod.indices))
+                .collect::<Result<Vec<MeshletData>>>()?
+        } else {
+            Vec::new()
+        };
+        let mesh_handle = resources.mesh_manager.add_lods(
+            resources.upload_rings.get_mut(UploadPriority::Background),
+            Some(&graphics_queue),
+            &finest_handle,
+            bunny_coarser_lods,
         )?;
+        for (lod, meshlets) in bunny_meshlets.iter().enumerate() {
+            let lod_handle = resources.mesh_manager.get_lod(&mesh_handle, lod);
+            resources.mesh_manager.add_meshlets(
+                &resources.device,
+                &mut resources.descriptor_manager,
+                resources.upload_rings.get_mut(UploadPriority::Background),
+                Some(&graphics_queue),
+                &lod_handle,
+                meshlets,
+            )?;
+        }
+        // Blended by a compute pass that reads the descriptor heap directly
+        let mesh_handle = if dynamic_resources {
+            resources.mesh_manager.add_morph_targets(
+                &resources.device,
+                &mut resources.descriptor_manager,
+                resources.upload_rings.get_mut(UploadPriority::Background),
+                Some(&graphics_queue),
+                &mesh_handle,
+                &bunny_morph_targets,
+                FRAME_COUNT,
+            )?
+        } else {
+            mesh_handle
+        };
+
+        // TEXTURE UPLOAD
 
-        let mesh_handle = resources.mesh_manager.add(
-            vertex_buffer,
-            index_buffer,
-            std::mem::size_of::<ObjVertex>() as u32,
-            vertices.len(),
+        let mut asset_loader = AssetLoader::new(&resources.device)?;
+        let texture_path = Path::new(UV_CHECKER_PATH);
+        let (texture, texture_info) = if texture_path.exists() {
+            load_dds_texture(
+                &mut resources,
+                &mut asset_loader,
+                &graphics_queue,
+                texture_path,
+                UploadPriority::Background,
+            )?
+        } else {
+            // Nothing has to be downloaded to run the demo
+            create_checker_texture(&mut resources, &mut graphics_queue, dynamic_resources)?
+        };
+
+        let minimap_target = RenderTarget::new(
+            &resources.device,
+            &mut resources.texture_manager,
+            &mut resources.descriptor_manager,
+            MINIMAP_EXTENT,
+            MINIMAP_FORMAT,
+            [0.0, 0.0, 0.0, 1.0],
+            resources.depth_range,
         )?;
+        let minimap_camera = Camera::orthographic(
+            glam::Mat4::look_at_lh(Vec3::new(0.0, 4.0, 1.0), Vec3::new(0.0, 0.0, 1.0), Vec3::Z),
+            4.0,
+            1.0,
+        );
+
+        let mut material = Material::from_texture(texture.clone());
+        // Feedback is written through the descriptor heap
+        let texture_streaming = if dynamic_resources
+            && resources.capabilities.sampler_feedback_tier().0 >= D3D12_SAMPLER_FEEDBACK_TIER_0_9.0
+        {
+            let mut texture_streaming = TextureStreaming::new();
+            material.feedback_index = Some(texture_streaming.add(&mut resources, &texture)?);
+            material.min_lod = texture_info.num_mips.saturating_sub(1) as f32;
+            Some(texture_streaming)
+        } else {
+            None
+        };
+
+        let swatches = create_swatch_atlas(&mut resources, &graphics_queue)?;
+
+        let mut scene_assets = SceneAssets::default();
+        scene_assets.add_mesh(&config.scene.to_string_lossy(), &mesh_handle);
+        scene_assets.add_texture("uv_checker", &texture);
+        scene_assets.add_texture("minimap", &minimap_target.color);
+        for (page_index, page) in swatches.pages.iter().enumerate() {
+            scene_assets.add_texture(&format!("swatches_{}", page_index), page);
+        }
 
         let objects = vec![
             Object {
                 position: Vec3::new(0.0, 0.0, 1.0),
-                texture: texture.clone(),
+                rotation: Quat::from_rotation_y(PI * -0.9),
+                scale: Vec3::ONE,
+                parent: None,
+                lod: 0,
+                material,
                 mesh: mesh_handle,
+                spin: 0.0,
+                morph_weights: vec![],
+            },
+            Object {
+                position: Vec3::new(-2.0, 0.0, 2.0),
+                rotation: Quat::from_rotation_y(PI * -0.9),
+                scale: Vec3::ONE,
+                parent: None,
+                lod: 0,
+                material: Material::from_texture(minimap_target.color.clone()),
+                mesh: mesh_handle,
+                spin: 0.25,
+                morph_weights: vec![],
+            },
+            Object {
+                position: Vec3::new(2.0, 0.0, 2.0),
+                rotation: Quat::from_rotation_y(PI * -0.9),
+                scale: Vec3::splat(0.5),
+                parent: None,
+                lod: 0,
+                material: Material::from_atlas_region(&swatches, 3)?,
+                mesh: mesh_handle,
+                spin: -0.25,
+                morph_weights: vec![],
             },
-            //Object {
-            //    position: Vec3::new(0.0, 1.0, 0.0),
-            //    texture,
-            //    mesh: mesh_handle,
-            //},
         ];
+        // The scene assets keep the references they were created with
+        for object in &objects {
+            resources.acquire_object_assets(object)?;
+        }
 
         graphics_queue.wait_for_idle()?;
 
-        let basic_render_pass = BindlessTexturePass::new(&mut resources)?;
+        let mut basic_render_pass =
+            BindlessTexturePass::new(&mut resources, TargetFormats::single(SCENE_FORMAT))?;
+        basic_render_pass.record_bundles = config.bundles;
+        let mut minimap_pass =
+            BindlessTexturePass::new(&mut resources, TargetFormats::single(MINIMAP_FORMAT))?;
+        // Nobody looks closely at the minimap
+        minimap_pass.shading_rate = D3D12_SHADING_RATE_2X2;
+
+        let shading_rate_pass = if SCREEN_SPACE_VRS
+            && dynamic_resources
+            && resources.capabilities.variable_rate_shading.screen_space()
+        {
+            let pass = ShadingRatePass::new(&mut resources, (width, height))?;
+            basic_render_pass.shading_rate_image = Some(pass.shading_rate_image.clone());
+            Some(pass)
+        } else {
+            None
+        };
+
+        let scene_target = create_scene_target(&mut resources, (width, height))?;
+        let depth_readback_pass = dynamic_resources
+            .then(|| DepthReadbackPass::new(&mut resources, (width, height), FRAME_COUNT))
+            .transpose()?;
+        let hi_z_pass = dynamic_resources
+            .then(|| HiZPass::new(&mut resources, (width, height)))
+            .transpose()?;
+        let occlusion_culling_pass = if config.occlusion_culling && dynamic_resources {
+            Some(OcclusionCullingPass::new(&mut resources)?)
+        } else {
+            None
+        };
+        let grid_pass = GridPass::new(&resources, TargetFormats::single(SCENE_FORMAT))?;
+        let environment_probe_pass = dynamic_resources
+            .then(|| EnvironmentProbePass::new(&mut resources))
+            .transpose()?;
+        let light_culling_pass = dynamic_resources
+            .then(|| LightCullingPass::new(&mut resources))
+            .transpose()?;
+        let morph_target_pass = dynamic_resources
+            .then(|| MorphTargetPass::new(&mut resources))
+            .transpose()?;
+        let water_pass = dynamic_resources
+            .then(|| {
+                WaterPass::new(
+                    &mut resources,
+                    &graphics_queue,
+                    TargetFormats::single(SCENE_FORMAT),
+                    (width, height),
+                    SCENE_CLEAR_COLOR,
+                )
+            })
+            .transpose()?;
+        let color_lut = match &config.color_lut {
+            Some(path) => load_color_lut(&mut resources, &mut asset_loader, &graphics_queue, path)
+                .with_context(|| format!("Loading colour LUT {}", path.display()))?,
+            None => ColorLut::new(resources.builtin_textures.identity_color_lut.clone()),
+        };
+        let depth_of_field_pass = dynamic_resources
+            .then(|| DepthOfFieldPass::new(&mut resources, (width, height), SCENE_FORMAT))
+            .transpose()?;
+        let motion_blur_pass = dynamic_resources
+            .then(|| MotionBlurPass::new(&mut resources, (width, height), SCENE_FORMAT))
+            .transpose()?;
+        let auto_exposure_pass = dynamic_resources
+            .then(|| AutoExposurePass::new(&mut resources))
+            .transpose()?;
+        let color_grading_pass = dynamic_resources
+            .then(|| {
+                ColorGradingPass::new(&mut resources, (width, height), SCENE_FORMAT, color_lut)
+            })
+            .transpose()?;
+        let upscale_pass = UpscalePass::new(&resources, swap_chain_format)?;
+        let memory_hud_pass = MemoryHudPass::new(&resources, OVERLAY_FORMAT)?;
+        let profiler_graph_pass = dynamic_resources
+            .then(|| {
+                ProfilerGraphPass::new(&mut resources, OVERLAY_FORMAT, TARGET_GPU_FRAME_TIME_MS)
+            })
+            .transpose()?;
+        let debug_line_pass = dynamic_resources
+            .then(|| DebugLinePass::new(&mut resources, OVERLAY_FORMAT))
+            .transpose()?;
+        let overlay_target = create_overlay_target(&mut resources, (width, height))?;
+        let composite_pass = CompositePass::new(&resources, swap_chain_format)?;
+        let compute_composite_pass = if config.compute_composite && dynamic_resources {
+            Some(ComputeCompositePass::new(&resources)?)
+        } else {
+            None
+        };
+        let gpu_timer = GpuTimer::new(&resources.device, &graphics_queue, FRAME_COUNT)?;
+        let breadcrumbs = Breadcrumbs::new(&resources.device, FRAME_COUNT)?;
+        let pipeline_statistics =
+            PipelineStatisticsQueries::new(&resources.device, &graphics_queue, FRAME_COUNT)?;
+        let texture_dump_pass = dynamic_resources
+            .then(|| TextureDumpPass::new(&resources, Path::new("."), config.hdr_format))
+            .transpose()?;
+        let dynamic_resolution = DynamicResolution::new(RenderScaleMode::Automatic {
+            target_frame_time_ms: TARGET_GPU_FRAME_TIME_MS,
+        });
 
         let fence_values = [0; 2];
+        let fence_watcher = FenceWatcher::new(&resources.device)?;
 
-        let renderer = Renderer {
+        let mut renderer = Renderer {
             hwnd,
+            adapter,
             dxgi_factory,
 
             resources,
 
             graphics_queue,
             swap_chain,
-            back_buffer_handles,
-            depth_buffer_handles,
+            // What 8 bit swap chains are shown in unless told otherwise
+            color_space: DXGI_COLOR_SPACE_RGB_FULL_G22_NONE_P709,
+            frame_latency,
+            render_targets,
             command_allocators,
             command_list,
+            barriers: BarrierBatcher::new(),
             fence_values,
 
             basic_render_pass,
+
+            scene_target,
+            depth_of_field_pass,
+            motion_blur_pass,
+            auto_exposure_pass,
+            color_grading_pass,
+            upscale_pass,
+            overlay_target,
+            composite_pass,
+            compute_composite_pass,
+            gpu_timer,
+            breadcrumbs,
+            pipeline_statistics,
+            frame_profile: FrameProfile::default(),
+            dynamic_resolution,
+            shading_rate_pass,
+            texture_streaming,
+            fence_watcher,
+            depth_readback_pass,
+            hi_z_pass,
+            occlusion_culling_pass,
+            grid_pass,
+            memory_hud_pass,
+            profiler_graph_pass,
+            environment_probe_pass,
+            light_culling_pass,
+            morph_target_pass,
+            water_pass,
+            texture_dump_pass,
+            debug_line_pass,
+
+            minimap_pass,
+            minimap_target,
+            minimap_camera,
+
+            views: vec![View::full_screen(camera)],
+            camera_controller: CameraController::new(&camera),
+            frame_clock: FrameClock::default(),
+            shader_globals: GlobalConstants::default(),
+            simulation_timestep: FixedTimestep::new(SIMULATION_RATE_HZ, MAX_SIMULATION_STEPS),
             objects,
+            lights: create_demo_lights(),
+            scene_assets,
+            transform_cache: TransformCache::new(),
+            visibility: Visibility::new(),
+            #[cfg(feature = "physics")]
+            physics: None,
+            selected_object: None,
+            picked_surface: None,
+            gizmo: Gizmo::default(),
+            gizmo_drag_start: None,
+            recording: None,
+            frame_capture: None,
+            frame_number: 0,
+            settings,
+            occluded: false,
+            redraw_requested: true,
+            allow_tearing,
+            display_rotation: DisplayRotation::Identity,
+            suspended: false,
+            config: config.clone(),
         };
+        renderer.apply_settings(&SettingChange::ALL);
+        renderer.register_dump_sources()?;
+        match renderer.diagnostics_report() {
+            Result::Ok(report) => {
+                if let Err(err) = std::fs::write(&config.diagnostics, report.to_string()) {
+                    eprintln!(
+                        "Failed to write {}: {:?}",
+                        config.diagnostics.display(),
+                        err
+                    );
+                }
+            }
+            Err(err) => eprintln!("Failed to gather diagnostics: {:?}", err),
+        }
+        #[cfg(feature = "physics")]
+        if config.physics {
+            renderer.physics = Some(renderer.create_physics()?);
+        }
+
+        if let Some(frame) = config.capture_frame {
+            let back_buffer = renderer
+                .resources
+                .texture_manager
+                .get_texture(&renderer.render_targets[0].color)?;
+            renderer.frame_capture = Some(FrameCapture::new(
+                &renderer.resources.device,
+                &back_buffer.get_resource()?.device_resource,
+                FRAME_COUNT,
+                frame,
+                PathBuf::from(format!("capture_{}.dds", frame)),
+            )?);
+        }
 
         Ok(renderer)
     }
 
     pub fn resize(&mut self, _extent: (u32, u32)) -> Result<()> {
+        ensure!(
+            !self.suspended,
+            "Resizing while suspended, resume at the new size instead"
+        );
+        self.request_redraw();
         self.wait_for_idle().expect("All GPU work done");
 
+        // The video can't change size
+        self.stop_recording()?;
+
         // Resetting the command allocator while the frame is being rendered is not okay
         for i in 0..FRAME_COUNT {
             let command_allocator = &self.command_allocators[i];
@@ -458,6 +1500,10 @@ impl Renderer {
                     D3D12_COMMAND_LIST_FLAG_NONE,
                 )
             }?;
+            unsafe {
+                self.command_list
+                    .SetName(PCWSTR::from(&FRAME_COMMAND_LIST_NAME.into()))?;
+            }
         }
 
         let (width, height) = _extent;
@@ -477,18 +1523,11 @@ impl Renderer {
         //    }
         //}
 
-        for i in 0..FRAME_COUNT {
-            self.resources.texture_manager.delete(
-                &mut self.resources.descriptor_manager,
-                self.back_buffer_handles[i].clone(),
-            );
-            self.back_buffer_handles[i] = Default::default();
-
-            self.resources.texture_manager.delete(
+        for render_target in self.render_targets.drain(..) {
+            render_target.delete(
+                &mut self.resources.texture_manager,
                 &mut self.resources.descriptor_manager,
-                self.depth_buffer_handles[i].clone(),
             );
-            self.depth_buffer_handles[i] = Default::default();
         }
 
         if cfg!(debug_assertions) {
@@ -506,102 +1545,617 @@ impl Renderer {
             }
         }
 
+        // Windows get turned by the compositor, only fullscreen back buffers are scanned out as is
+        self.display_rotation = if self.config.pre_rotate && self.is_fullscreen()? {
+            self.current_output()?.rotation
+        } else {
+            DisplayRotation::Identity
+        };
+        let back_buffer_extent = self.display_rotation.native_extent((width, height));
+
         unsafe {
+            // Zero keeps the number of buffers, the flags have to stay the ones it was created with
+            let flags = self.swap_chain.GetDesc1()?.Flags;
             self.swap_chain.ResizeBuffers(
-                FRAME_COUNT as u32,
-                width,
-                height,
-                DXGI_FORMAT_UNKNOWN,
                 0,
+                back_buffer_extent.0,
+                back_buffer_extent.1,
+                DXGI_FORMAT_UNKNOWN,
+                flags,
             )?;
         }
 
-        for i in 0..FRAME_COUNT {
-            let back_buffer: ID3D12Resource = unsafe { self.swap_chain.GetBuffer(i as u32) }?;
-            unsafe {
-                back_buffer.SetName(PCWSTR::from(&format!("Backbuffer {}", COUNTER).into()))?;
-                COUNTER += 1;
+        self.render_targets = create_back_buffer_targets(
+            &self.resources.device,
+            &self.swap_chain,
+            &mut self.resources.texture_manager,
+            &mut self.resources.descriptor_manager,
+            back_buffer_extent,
+        )?;
+        // Its readback buffers have the size of the old back buffers
+        if let Some(frame_capture) = &mut self.frame_capture {
+            let back_buffer = self
+                .resources
+                .texture_manager
+                .get_texture(&self.render_targets[0].color)?;
+            if frame_capture.recreate(
+                &self.resources.device,
+                &back_buffer.get_resource()?.device_resource,
+            )? {
+                println!("Captured a frame to {}", frame_capture.path().display());
+                self.frame_capture = None;
             }
-            let back_buffer = Resource {
-                device_resource: back_buffer,
-                size: (width * height * 4) as usize,
-                mapped_data: std::ptr::null_mut(),
-            };
-            let back_buffer = Texture {
-                info: TextureInfo {
-                    dimension: TextureDimension::Two(width as usize, height),
-                    format: DXGI_FORMAT_R8G8B8A8_UNORM,
-                    array_size: 1,
-                    num_mips: 1,
-                    is_render_target: true,
-                    is_depth_buffer: false,
-                    is_unordered_access: false,
-                },
-                resource: Some(back_buffer),
-            };
+        }
 
-            self.back_buffer_handles[i] = self.resources.texture_manager.add_texture(
-                &self.resources.device,
-                &mut self.resources.descriptor_manager,
-                back_buffer,
-            )?;
+        let scene_target = create_scene_target(&mut self.resources, (width, height))?;
+        std::mem::replace(&mut self.scene_target, scene_target).delete(
+            &mut self.resources.texture_manager,
+            &mut self.resources.descriptor_manager,
+        );
+        let overlay_target = create_overlay_target(&mut self.resources, (width, height))?;
+        std::mem::replace(&mut self.overlay_target, overlay_target).delete(
+            &mut self.resources.texture_manager,
+            &mut self.resources.descriptor_manager,
+        );
+        if let Some(water_pass) = &mut self.water_pass {
+            water_pass.resize(&mut self.resources, (width, height))?;
+        }
+        if let Some(depth_of_field_pass) = &mut self.depth_of_field_pass {
+            depth_of_field_pass.resize(&mut self.resources, (width, height))?;
+        }
+        if let Some(motion_blur_pass) = &mut self.motion_blur_pass {
+            motion_blur_pass.resize(&mut self.resources, (width, height))?;
+        }
+        if let Some(color_grading_pass) = &mut self.color_grading_pass {
+            color_grading_pass.resize(&mut self.resources, (width, height))?;
+        }
 
-            self.depth_buffer_handles[i] = self.resources.texture_manager.create_empty_texture(
-                &self.resources.device,
-                TextureInfo {
-                    dimension: TextureDimension::Two(width as usize, height),
-                    format: DXGI_FORMAT_D32_FLOAT,
-                    array_size: 1,
-                    num_mips: 1,
-                    is_render_target: false,
-                    is_depth_buffer: true,
-                    is_unordered_access: false,
-                },
-                Some(D3D12_CLEAR_VALUE {
-                    Format: DXGI_FORMAT_D32_FLOAT,
-                    Anonymous: D3D12_CLEAR_VALUE_0 {
-                        DepthStencil: D3D12_DEPTH_STENCIL_VALUE {
-                            Depth: 1.0,
-                            Stencil: 0,
-                        },
-                    },
-                }),
-                D3D12_RESOURCE_STATE_DEPTH_WRITE,
-                &mut self.resources.descriptor_manager,
-                true,
+        if let Some(shading_rate_pass) = &mut self.shading_rate_pass {
+            shading_rate_pass.resize(&mut self.resources, (width, height))?;
+            self.basic_render_pass.shading_rate_image =
+                Some(shading_rate_pass.shading_rate_image.clone());
+        }
+        if let Some(depth_readback_pass) = &mut self.depth_readback_pass {
+            depth_readback_pass.resize(&mut self.resources, (width, height))?;
+        }
+        if let Some(hi_z_pass) = &mut self.hi_z_pass {
+            hi_z_pass.resize(&mut self.resources, (width, height))?;
+        }
+        self.register_dump_sources()?;
+
+        for view in &mut self.views {
+            view.camera
+                .set_aspect_ratio(view.region.aspect_ratio((width, height)));
+        }
+
+        Ok(())
+    }
+
+    fn diagnostics_report(&self) -> Result<DiagnosticsReport> {
+        Ok(DiagnosticsReport {
+            adapter: AdapterInfo::query(&self.adapter)?,
+            capabilities: self.resources.capabilities,
+            stats: self.resources.stats(),
+        })
+    }
+
+    // Called again whenever the targets are recreated
+    fn register_dump_sources(&mut self) -> Result<()> {
+        let Some(dumps) = &mut self.texture_dump_pass else {
+            return Ok(());
+        };
+        dumps.register_target(&self.resources, "scene", &self.scene_target)?;
+        dumps.register_target(&self.resources, "minimap", &self.minimap_target)?;
+        dumps.register(
+            &self.resources,
+            "overlay",
+            &self.overlay_target.color,
+            D3D12_RESOURCE_STATE_ALL_SHADER_RESOURCE,
+        )
+    }
+
+    /// Every monitor on the adapter the renderer runs on
+    pub fn outputs(&self) -> Result<Vec<Output>> {
+        let format = unsafe { self.swap_chain.GetDesc1()? }.Format;
+
+        enumerate_outputs(&self.adapter, format)
+    }
+
+    /// The monitor most of the window is on
+    pub fn current_output(&self) -> Result<Output> {
+        let format = unsafe { self.swap_chain.GetDesc1()? }.Format;
+        let output = unsafe { self.swap_chain.GetContainingOutput()? };
+
+        Output::new(output.cast()?, format)
+    }
+
+    pub fn is_fullscreen(&self) -> Result<bool> {
+        let mut fullscreen = BOOL::default();
+        unsafe {
+            self.swap_chain
+                .GetFullscreenState(&mut fullscreen, std::ptr::null_mut())?;
+        }
+
+        Ok(fullscreen.as_bool())
+    }
+
+    /// Exclusive fullscreen on `output` at its desktop resolution and fastest refresh rate, or
+    /// back to a window for None. The window gets resized, which resizes the swap chain.
+    pub fn set_fullscreen(&mut self, output: Option<&Output>) -> Result<()> {
+        self.wait_for_idle()?;
+
+        match output {
+            Some(output) => {
+                let format = unsafe { self.swap_chain.GetDesc1()? }.Format;
+                let mode = output
+                    .fullscreen_mode()
+                    .with_context(|| format!("{} has no display modes", output.name))?;
+                let target: &IDXGIOutput = (&output.output).into();
+                unsafe {
+                    // Setting the mode first switches straight to it
+                    self.swap_chain.ResizeTarget(&mode.desc(format))?;
+                    self.swap_chain.SetFullscreenState(true, target)?;
+                }
+            }
+            None => unsafe {
+                self.swap_chain
+                    .SetFullscreenState(false, None::<&IDXGIOutput>)?;
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Puts the window in the middle of `output`, or makes `output` the fullscreen one
+    pub fn move_to_output(&mut self, output: &Output) -> Result<()> {
+        if self.is_fullscreen()? {
+            self.set_fullscreen(Some(output))
+        } else {
+            output.center_window(self.hwnd)
+        }
+    }
+
+    pub fn bake_environment_probe(&mut self) {
+        let position = self.views[0].camera.position();
+        if let Some(environment_probe_pass) = &mut self.environment_probe_pass {
+            environment_probe_pass.bake(position);
+        }
+    }
+
+    /// Materials are written to the GPU for every draw, so edits show up from the next frame on.
+    /// Swapping the texture drops the streaming state of the old one.
+    pub fn update_material(
+        &mut self,
+        object: usize,
+        update: impl FnOnce(&mut Material),
+    ) -> Result<()> {
+        let mut material = self
+            .objects
+            .get(object)
+            .with_context(|| format!("No object {}", object))?
+            .material
+            .clone();
+        update(&mut material);
+
+        let old_texture = &self.objects[object].material.texture;
+        if material.texture.index != old_texture.index {
+            let texture = self
+                .resources
+                .texture_manager
+                .get_texture(&material.texture)?;
+            ensure!(
+                matches!(texture.info.dimension, TextureDimension::Two(..)),
+                "Materials sample 2D textures, got {:?}",
+                texture.info.dimension
+            );
+            self.resources.texture_manager.get_srv(&material.texture)?;
+
+            material.feedback_index = self.streaming_feedback(&material.texture);
+            material.min_lod = 0.0;
+
+            let last_fence_value = self.last_fence_value();
+            let texture_manager = &mut self.resources.texture_manager;
+            texture_manager.add_ref(&material.texture)?;
+            texture_manager.unload(
+                self.objects[object].material.texture.clone(),
+                last_fence_value,
             )?;
         }
 
-        self.resources.frame_index = unsafe { self.swap_chain.GetCurrentBackBufferIndex() };
+        self.objects[object].material = material;
+        self.request_redraw();
+
+        Ok(())
+    }
+
+    // Another object may already stream the texture, its feedback map covers it
+    fn streaming_feedback(&self, texture: &TextureHandle) -> Option<u32> {
+        self.objects
+            .iter()
+            .find(|object| object.material.texture.index == texture.index)
+            .and_then(|object| object.material.feedback_index)
+    }
+
+    pub fn save_scene(&self, path: &Path) -> Result<()> {
+        SceneFile::capture(
+            self.views[0].camera.position(),
+            self.camera_controller.forward(),
+            &self.objects,
+            &self.lights,
+            &self.scene_assets,
+        )?
+        .save(path)
+    }
+
+    pub fn load_scene(&mut self, path: &Path) -> Result<()> {
+        let scene = SceneFile::load(path)?;
+        let mut objects = scene.objects(&self.scene_assets)?;
+        let lights = scene.lights();
+        ensure!(
+            lights.len() <= MAX_LIGHTS,
+            "{} has {} lights, at most {} are supported",
+            path.display(),
+            lights.len(),
+            MAX_LIGHTS
+        );
+
+        for object in &mut objects {
+            object.material.feedback_index = self.streaming_feedback(&object.material.texture);
+            self.resources.acquire_object_assets(object)?;
+        }
+        let last_fence_value = self.last_fence_value();
+        for object in std::mem::replace(&mut self.objects, objects) {
+            self.resources
+                .release_object_assets(object, last_fence_value)?;
+        }
+        self.lights = lights;
+        self.selected_object = None;
+        self.picked_surface = None;
+        self.gizmo.end_drag();
+        #[cfg(feature = "physics")]
+        if self.physics.is_some() {
+            self.physics = Some(self.create_physics()?);
+        }
+        // Nothing to blend from
+        self.transform_cache.begin_step(&self.objects);
+        // Raycasts before the next frame already see the new objects
+        self.transform_cache.build(&self.objects, 1.0)?;
+        self.visibility.build(&self.objects, &self.transform_cache);
+
+        let position = Vec3::from_array(scene.camera.position);
+        let forward = Vec3::from_array(scene.camera.forward).normalize();
+        let camera = &mut self.views[0].camera;
+        camera.set_view(glam::Mat4::look_at_lh(
+            position,
+            position + forward,
+            Vec3::Y,
+        ));
+        self.camera_controller = CameraController::new(camera);
+        self.request_redraw();
+
+        Ok(())
+    }
+
+    /// Selects objects with the left mouse button, and moves, rotates or scales the selected one
+    /// by dragging the handles of the gizmo
+    /// Mouse state for the global constants, the scene time stops until the frame clock advances
+    fn update_shader_globals(&mut self, input: &InputSnapshot) {
+        let globals = &mut self.shader_globals;
+        globals.delta_time = 0.0;
+        globals.mouse_position = input.cursor_position;
+        globals.mouse_buttons = [
+            (MouseButton::Left, MOUSE_BUTTON_LEFT),
+            (MouseButton::Right, MOUSE_BUTTON_RIGHT),
+            (MouseButton::Middle, MOUSE_BUTTON_MIDDLE),
+        ]
+        .into_iter()
+        .filter(|(button, _)| input.mouse_buttons.is_held(*button))
+        .fold(0, |buttons, (_, bit)| buttons | bit);
+    }
+
+    fn update_gizmo(&mut self, input: &InputSnapshot) {
+        let buttons = &input.mouse_buttons;
+        if !buttons.is_held(MouseButton::Left) {
+            self.gizmo.end_drag();
+        }
 
-        self.resources.viewport = D3D12_VIEWPORT {
-            TopLeftX: 0.0,
-            TopLeftY: 0.0,
-            Width: width as f32,
-            Height: height as f32,
-            MinDepth: D3D12_MIN_DEPTH,
-            MaxDepth: D3D12_MAX_DEPTH,
+        let Some(ray) = self.cursor_ray(input.cursor_uv) else {
+            return;
         };
+        let frame = self.gizmo_frame();
+
+        if buttons.was_pressed(MouseButton::Left) {
+            if frame.is_some_and(|frame| self.gizmo.begin_drag(&frame, &ray)) {
+                self.gizmo_drag_start = self.selected_object.map(|index| {
+                    let object = &self.objects[index];
+                    (object.position, object.rotation, object.scale)
+                });
+            } else {
+                self.selected_object = self.raycast(&ray).map(|hit| hit.object);
+            }
+            self.request_redraw();
+        } else if self.gizmo.is_dragging() {
+            if let Some(edit) = self.gizmo.drag(&ray) {
+                self.apply_gizmo_edit(edit);
+            }
+        } else if let Some(frame) = frame {
+            self.gizmo.hover(&frame, &ray);
+        }
+    }
+
+    /// Dynamic bodies for every root object with bounds, on a ground at the height of the grid.
+    /// Hulls where the mesh manager kept the geometry, boxes otherwise.
+    #[cfg(feature = "physics")]
+    fn create_physics(&self) -> Result<Physics> {
+        let mesh_manager = &self.resources.mesh_manager;
+        let mut physics = Physics::new(self.simulation_timestep.step());
+        physics.add_ground(0.0);
+
+        for (index, object) in self.objects.iter().enumerate() {
+            if object.parent.is_some() || object.mesh.bounds.is_none() {
+                continue;
+            }
+            let shape = match mesh_manager.get_cpu_mesh(&object.mesh) {
+                Some(_) => ColliderShape::ConvexHull,
+                None => ColliderShape::Aabb,
+            };
+            physics.add_object(index, object, mesh_manager, shape, true)?;
+        }
+
+        Ok(physics)
+    }
 
-        self.resources.scissor_rect = RECT {
-            left: 0,
-            top: 0,
-            right: width as i32,
-            bottom: height as i32,
+    /// The ray through a screen position of the main view, None outside of it. `uv` goes from
+    /// (0, 0) at the top left to (1, 1) at the bottom right of the window.
+    fn cursor_ray(&self, uv: Vec2) -> Option<Ray> {
+        let view = &self.views[0];
+        view.region.contains(uv).then(|| {
+            Ray::from_screen(
+                &view.camera.view_projection(),
+                view.region.to_local(uv),
+                view.camera.depth_range(),
+            )
+        })
+    }
+
+    /// The closest object along a world space ray, as of the last frame
+    fn raycast(&self, ray: &Ray) -> Option<RaycastHit> {
+        self.visibility.raycast(
+            ray,
+            &self.objects,
+            &self.transform_cache,
+            &self.resources.mesh_manager,
+        )
+    }
+
+    // The gizmo sits on the selected object, sized to stay the same on screen
+    fn gizmo_frame(&self) -> Option<GizmoFrame> {
+        let transform = self.transform_cache.get(self.selected_object?)?;
+        let (_, orientation, center) = transform.to_scale_rotation_translation();
+        let camera = &self.views[0].camera;
+        let distance = camera.view_depth(center).max(f32::EPSILON);
+
+        Some(GizmoFrame {
+            center,
+            orientation,
+            size: distance * 2.0 / camera.projection_scale_y() * GIZMO_SCREEN_SIZE,
+        })
+    }
+
+    fn apply_gizmo_edit(&mut self, edit: GizmoEdit) {
+        let (Some(index), Some((position, rotation, scale))) =
+            (self.selected_object, self.gizmo_drag_start)
+        else {
+            return;
         };
+        // Edits are in world space, objects are placed relative to their parents
+        let parent = self.objects[index]
+            .parent
+            .and_then(|parent| self.transform_cache.get(parent))
+            .copied()
+            .unwrap_or(glam::Mat4::IDENTITY);
+
+        let object = &mut self.objects[index];
+        match edit {
+            GizmoEdit::Translate(offset) => {
+                object.position = position + parent.inverse().transform_vector3(offset);
+            }
+            GizmoEdit::Rotate(world_rotation) => {
+                let (_, parent_rotation, _) = parent.to_scale_rotation_translation();
+                object.rotation =
+                    (parent_rotation.inverse() * world_rotation * parent_rotation * rotation)
+                        .normalize();
+            }
+            GizmoEdit::Scale(axis, factor) => {
+                object.scale = scale;
+                object.scale[axis.index()] *= factor;
+            }
+        }
+        #[cfg(feature = "physics")]
+        if let Some(physics) = &mut self.physics {
+            physics.teleport(index, object);
+        }
+        // Shown where it was dragged to right away instead of being blended in over a step
+        self.transform_cache.skip_interpolation(index, object);
+        self.request_redraw();
+    }
 
-        let aspect_ratio = (width as f32) / (height as f32);
+    /// Writes the registered intermediate targets to disk at the end of the next frame
+    pub fn dump_textures(&mut self) {
+        if let Some(texture_dump_pass) = &mut self.texture_dump_pass {
+            texture_dump_pass.request();
+        }
+        self.request_redraw();
+    }
+
+    /// Narrows the field of view of the main camera for positive `steps`, widens it for negative
+    /// Makes sure another frame gets drawn when rendering on demand, for anything that changes
+    /// what is on screen
+    pub fn request_redraw(&mut self) {
+        self.redraw_requested = true;
+    }
+
+    pub fn zoom(&mut self, steps: f32) -> Result<()> {
+        let camera = &mut self.views[0].camera;
+        if let CameraProjection::Perspective { fov_y, .. } = *camera.projection() {
+            camera.set_fov_y((fov_y - steps * ZOOM_STEP).clamp(MIN_FOV_Y, MAX_FOV_Y))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn update_settings(&mut self, update: impl FnOnce(&mut Settings)) {
+        let previous = self.settings.clone();
+        update(&mut self.settings);
+        self.request_redraw();
+
+        let changes = self.settings.changes(&previous);
+        self.apply_settings(&changes);
+    }
 
-        let camera = Camera {
-            V: glam::Mat4::from_translation(Vec3::new(0.0, -0.8, 1.5)),
-            P: glam::Mat4::perspective_lh(PI / 2.0, aspect_ratio, 0.1, 100.0),
+    fn apply_settings(&mut self, changes: &[SettingChange]) {
+        for change in changes {
+            match change {
+                SettingChange::RenderScale => {
+                    self.dynamic_resolution.mode = match self.settings.render_scale {
+                        // Timings are only comparable at the same resolution
+                        _ if self.config.profiling => RenderScaleMode::Fixed(1.0),
+                        Some(scale) => RenderScaleMode::Fixed(scale),
+                        None => RenderScaleMode::Automatic {
+                            target_frame_time_ms: TARGET_GPU_FRAME_TIME_MS,
+                        },
+                    }
+                }
+                // Read at every present
+                SettingChange::Vsync => (),
+                SettingChange::Grid => self.grid_pass.enabled = self.settings.show_grid,
+                SettingChange::MemoryHud => {
+                    self.memory_hud_pass.enabled = self.settings.show_memory_hud
+                }
+                SettingChange::ProfilerGraphs => {
+                    if let Some(profiler_graph_pass) = &mut self.profiler_graph_pass {
+                        profiler_graph_pass.enabled = self.settings.show_profiler_graphs
+                    }
+                }
+                SettingChange::ColorGrading => {
+                    if let Some(color_grading_pass) = &mut self.color_grading_pass {
+                        color_grading_pass.grading = self.settings.color_grading
+                    }
+                }
+                SettingChange::AutoExposure => {
+                    if let Some(auto_exposure_pass) = &mut self.auto_exposure_pass {
+                        auto_exposure_pass.settings = self.settings.auto_exposure
+                    }
+                }
+                SettingChange::DepthOfField => {
+                    if let Some(depth_of_field_pass) = &mut self.depth_of_field_pass {
+                        depth_of_field_pass.settings = self.settings.depth_of_field
+                    }
+                }
+                SettingChange::MotionBlur => {
+                    if let Some(motion_blur_pass) = &mut self.motion_blur_pass {
+                        motion_blur_pass.settings = self.settings.motion_blur
+                    }
+                }
+                SettingChange::HdrMetadata => {
+                    if let Err(err) = self.apply_hdr_metadata() {
+                        eprintln!("Failed to set the HDR metadata: {:?}", err);
+                    }
+                }
+            }
+        }
+    }
+
+    // A swap chain created again, e.g. after the device was lost, starts without metadata and
+    // gets it from here when the settings are applied to the new renderer. Displays only read it
+    // along with PQ encoded Rec. 2020 frames, anything else presented with it would claim to be
+    // mastered for a range it isn't in.
+    fn apply_hdr_metadata(&self) -> Result<()> {
+        if self.color_space != DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020 {
+            if self.settings.hdr_metadata.is_some() {
+                eprintln!("HDR metadata is only sent with an HDR10 swap chain, this one is SDR");
+            }
+            return Ok(());
+        }
+
+        let metadata = self.settings.hdr_metadata.map(|hdr| hdr.metadata());
+        set_hdr_metadata(&self.swap_chain, metadata.as_ref())
+    }
+
+    pub fn toggle_split_screen(&mut self) {
+        let extent = self.scene_target.extent;
+        let main_camera = self.views[0].camera;
+
+        self.views = if self.views.len() > 1 {
+            vec![View::full_screen(main_camera)]
+        } else {
+            let left = ViewportRect {
+                width: 0.5,
+                ..ViewportRect::FULL
+            };
+            let right = ViewportRect { x: 0.5, ..left };
+            let side_camera = Camera::perspective(
+                glam::Mat4::look_at_lh(
+                    Vec3::new(2.0, 0.5, -1.0),
+                    Vec3::new(0.0, 0.0, 1.0),
+                    Vec3::Y,
+                ),
+                right.aspect_ratio(extent),
+            );
+
+            vec![
+                View {
+                    camera: main_camera,
+                    region: left,
+                },
+                View {
+                    camera: side_camera,
+                    region: right,
+                },
+            ]
         };
 
-        self.resources.camera = camera;
+        for view in &mut self.views {
+            view.camera
+                .set_aspect_ratio(view.region.aspect_ratio(extent));
+        }
+    }
+
+    pub fn suspend(&mut self) -> Result<()> {
+        ensure!(!self.suspended, "Already suspended");
+
+        // The swap chain can't give up its buffers in exclusive fullscreen
+        if self.is_fullscreen()? {
+            self.set_fullscreen(None)?;
+        }
+        self.wait_for_idle()?;
+        // Recording needs the back buffers
+        self.stop_recording()?;
+
+        for render_target in self.render_targets.drain(..) {
+            render_target.delete(
+                &mut self.resources.texture_manager,
+                &mut self.resources.descriptor_manager,
+            );
+        }
+        self.suspended = true;
 
         Ok(())
     }
 
+    pub fn resume(&mut self, window_size: (u32, u32)) -> Result<()> {
+        ensure!(self.suspended, "Not suspended");
+        self.suspended = false;
+
+        // Brings back the back buffers, and everything sized to match them
+        self.resize(window_size)
+    }
+
+    // Of the last frame submitted
+    fn last_fence_value(&self) -> u64 {
+        self.fence_values.into_iter().max().unwrap_or_default()
+    }
+
     pub fn wait_for_idle(&mut self) -> Result<()> {
         for fence in self.fence_values {
             self.graphics_queue.wait_for_fence_blocking(fence)?;
@@ -609,11 +2163,142 @@ impl Renderer {
         self.graphics_queue.wait_for_idle()
     }
 
+    pub fn start_recording(&mut self, path: &Path, frames_per_second: u32) -> Result<()> {
+        ensure!(self.recording.is_none(), "Already recording");
+
+        let back_buffer = self
+            .resources
+            .texture_manager
+            .get_texture(&self.render_targets[0].color)?;
+        self.recording = Some(Recording::new(
+            &self.resources.device,
+            &back_buffer.get_resource()?.device_resource,
+            FRAME_COUNT,
+            path,
+            frames_per_second,
+        )?);
+
+        Ok(())
+    }
+
+    pub fn stop_recording(&mut self) -> Result<()> {
+        if let Some(recording) = self.recording.take() {
+            self.wait_for_idle()?;
+            recording.finish()?;
+        }
+
+        Ok(())
+    }
+
     pub fn render(&mut self) -> Result<()> {
-        let last_fence_value = self.fence_values[self.resources.frame_index as usize];
+        if self.suspended {
+            return Ok(());
+        }
+        if self.occluded {
+            // A test present only checks whether the window can be seen again
+            if unsafe { self.swap_chain.Present(0, DXGI_PRESENT_TEST) } == DXGI_STATUS_OCCLUDED {
+                // The frame is still owed once the window shows again
+                self.request_redraw();
+                return Ok(());
+            }
+            self.occluded = false;
+        }
+
+        // Waiting for the swap chain before anything else keeps the frame as close to its
+        // presentation as possible. A timeout is not an error, the frame fence still throttles us.
+        self.frame_latency.wait(FRAME_LATENCY_TIMEOUT_MS)?;
+
+        let frame_index = self.resources.frame_index as usize;
+        let last_fence_value = self.fence_values[frame_index];
         self.graphics_queue
             .wait_for_fence_blocking(last_fence_value)?;
 
+        let completed_fence_value = unsafe { self.graphics_queue.fence().GetCompletedValue() };
+        let frame = FrameFence {
+            frame_index,
+            fence_value: self.graphics_queue.next_fence_value(),
+            completed_fence_value,
+        };
+        self.resources.texture_manager.collect_garbage(
+            &mut self.resources.descriptor_manager,
+            completed_fence_value,
+        );
+        self.resources.mesh_manager.collect_garbage(
+            &mut self.resources.descriptor_manager,
+            completed_fence_value,
+        );
+        self.resources.descriptor_manager.begin_frame(frame_index)?;
+
+        if let Some(recording) = &mut self.recording {
+            recording.write_completed_frame(frame_index)?;
+        }
+        if let Some(frame_capture) = &mut self.frame_capture {
+            if frame_capture.write_completed_frame(frame_index)? {
+                println!("Captured a frame to {}", frame_capture.path().display());
+                self.frame_capture = None;
+            }
+        }
+        if let Some(depth_readback_pass) = &mut self.depth_readback_pass {
+            depth_readback_pass.collect(frame_index)?;
+        }
+
+        // Frozen frames still go through the GPU timer so they show up in the profiler
+        let gpu_frame_time_ms = self.gpu_timer.read_milliseconds(frame_index);
+        self.frame_profile = FrameProfile {
+            gpu_time_ms: gpu_frame_time_ms,
+            passes: self.pipeline_statistics.read(frame_index),
+        };
+        if let Some(profiler_graph_pass) = &mut self.profiler_graph_pass {
+            profiler_graph_pass.record(&self.frame_profile);
+        }
+        let scene_frozen = self.frame_clock.is_frozen();
+        if !scene_frozen {
+            self.dynamic_resolution.update(gpu_frame_time_ms);
+        }
+        let render_extent = self
+            .dynamic_resolution
+            .render_extent(self.scene_target.extent);
+        self.scene_target.set_render_extent(render_extent);
+
+        if let Some(texture_streaming) = self.texture_streaming.as_mut().filter(|_| !scene_frozen) {
+            if texture_streaming.update(&mut self.objects) {
+                self.redraw_requested = true;
+            }
+        }
+
+        self.transform_cache
+            .build(&self.objects, self.simulation_timestep.alpha())?;
+        self.visibility.build(&self.objects, &self.transform_cache);
+        update_lods(
+            &mut self.objects,
+            &self.transform_cache,
+            &self.views,
+            &self.resources.mesh_manager,
+        );
+        let minimap_visible = self.visibility.visible_objects(&self.minimap_camera);
+        let visible_per_view: Vec<Vec<usize>> = self
+            .views
+            .iter()
+            .map(|view| self.visibility.visible_objects(&view.camera))
+            .collect();
+        let reflections: Vec<(Camera, Vec<usize>)> = match &self.water_pass {
+            Some(water_pass) => self
+                .views
+                .iter()
+                .map(|view| {
+                    let camera = water_pass.reflection_camera(&view.camera);
+                    (camera, self.visibility.visible_objects(&camera))
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        let probe_capture = match &mut self.environment_probe_pass {
+            Some(environment_probe_pass) => environment_probe_pass
+                .begin_frame(&mut self.resources, frame)?
+                .map(|camera| (self.visibility.visible_objects(&camera), camera)),
+            None => None,
+        };
+
         //self.populate_command_list()?;
         // Resetting the command allocator while the frame is being rendered is not okay
         let command_allocator = &self.command_allocators[self.resources.frame_index as usize];
@@ -627,53 +2312,579 @@ impl Renderer {
             command_list.Reset(command_allocator, None)?;
         }
 
-        let render_target_handle = &self.back_buffer_handles[self.resources.frame_index as usize];
-        let depth_buffer_handle = &self.depth_buffer_handles[self.resources.frame_index as usize];
+        self.gpu_timer.begin(command_list, frame_index);
+        self.breadcrumbs
+            .begin_frame(command_list, frame_index, self.frame_number)?;
+        self.pipeline_statistics.begin_frame(frame_index)?;
+        let (width, height) = self.scene_target.extent;
+        self.resources.global_constants.begin_frame(
+            frame,
+            &GlobalConstants {
+                frame_number: self.frame_number as u32,
+                resolution: Vec2::new(width as f32, height as f32),
+                render_resolution: Vec2::new(render_extent.0 as f32, render_extent.1 as f32),
+                ..self.shader_globals
+            },
+        )?;
+        self.minimap_pass.begin_frame(frame)?;
+        self.basic_render_pass.begin_frame(frame)?;
+        if let Some(water_pass) = &mut self.water_pass {
+            water_pass.begin_frame(frame)?;
+        }
+        if let Some(light_culling_pass) = &mut self.light_culling_pass {
+            light_culling_pass.begin_frame(frame, &self.lights)?;
+        }
+        if let Some(occlusion_culling_pass) = &mut self.occlusion_culling_pass {
+            occlusion_culling_pass.begin_frame(frame)?;
+        }
+        if let Some(morph_target_pass) = &mut self.morph_target_pass {
+            morph_target_pass.begin_frame(frame)?;
+        }
+        if let Some(profiler_graph_pass) = &mut self.profiler_graph_pass {
+            profiler_graph_pass.begin_frame(frame)?;
+        }
+        let mut debug_lines = match self.gizmo_frame() {
+            Some(gizmo_frame) => self.gizmo.lines(&gizmo_frame),
+            None => vec![],
+        };
+        if let Some(position) = self.picked_surface {
+            debug_lines.extend([Vec3::X, Vec3::Y, Vec3::Z].map(|axis| DebugLine {
+                start: position - axis * PICK_MARKER_SIZE,
+                end: position + axis * PICK_MARKER_SIZE,
+                color: Vec4::ONE,
+            }));
+        }
+        if let Some(debug_line_pass) = &mut self.debug_line_pass {
+            debug_line_pass.begin_frame(frame_index, &debug_lines)?;
+        }
 
-        let rtv_handle = self
-            .resources
+        // Textures uploaded since the last frame
+        self.resources
             .texture_manager
-            .get_rtv(render_target_handle)?;
-        let rtv = self
-            .resources
-            .descriptor_manager
-            .get_cpu_handle(&rtv_handle)?;
+            .transition_uploads(&mut self.barriers);
+        self.barriers.flush(command_list);
 
-        let dsv_handle = self
-            .resources
-            .texture_manager
-            .get_dsv(depth_buffer_handle)?;
-        let dsv = self
-            .resources
-            .descriptor_manager
-            .get_cpu_handle(&dsv_handle)?;
-        unsafe {
-            command_list.ClearDepthStencilView(dsv, D3D12_CLEAR_FLAG_DEPTH, 1.0, 0, &[]);
-            command_list.ClearRenderTargetView(rtv, &*[0.0, 0.2, 0.4, 1.0].as_ptr(), &[]);
+        if let Some(texture_streaming) = &mut self.texture_streaming {
+            texture_streaming.prepare(command_list, &mut self.barriers, &self.resources)?;
         }
 
-        let render_target = self
-            .resources
-            .texture_manager
-            .get_texture(render_target_handle)?;
+        // Every pass drawing the objects reads the blended vertices
+        if let Some(morph_target_pass) = &mut self.morph_target_pass {
+            self.breadcrumbs.begin(command_list, "Morph targets")?;
+            self.pipeline_statistics.begin(
+                command_list,
+                "Morph targets",
+                self.resources.allocation_counts(),
+            )?;
+            morph_target_pass.render(
+                command_list,
+                &mut self.barriers,
+                &self.resources,
+                &self.objects,
+            )?;
+            self.pipeline_statistics
+                .end(command_list, self.resources.allocation_counts())?;
+            self.breadcrumbs.end(command_list)?;
+        }
 
-        let barrier = transition_barrier(
-            &render_target.get_resource()?.device_resource,
-            D3D12_RESOURCE_STATE_PRESENT,
-            D3D12_RESOURCE_STATE_RENDER_TARGET,
-        );
-        unsafe { command_list.ResourceBarrier(&[barrier.clone()]) };
+        self.breadcrumbs.begin(command_list, "Minimap")?;
+        self.pipeline_statistics.begin(
+            command_list,
+            "Minimap",
+            self.resources.allocation_counts(),
+        )?;
+        self.minimap_pass.render_to_target(
+            command_list,
+            &mut self.barriers,
+            &self.resources,
+            &self.minimap_camera,
+            &self.minimap_target,
+            self.transform_cache
+                .select(&self.objects, &minimap_visible)
+                .filter(|(object, _)| {
+                    object.material.texture.index != self.minimap_target.color.index
+                }),
+        )?;
+
+        self.pipeline_statistics
+            .end(command_list, self.resources.allocation_counts())?;
+        self.breadcrumbs.end(command_list)?;
+
+        if let (Some((visible, camera)), Some(environment_probe_pass)) =
+            (&probe_capture, &mut self.environment_probe_pass)
+        {
+            self.breadcrumbs.begin(command_list, "Environment probe")?;
+            self.pipeline_statistics.begin(
+                command_list,
+                "Environment probe",
+                self.resources.allocation_counts(),
+            )?;
+            environment_probe_pass.render(
+                command_list,
+                &mut self.barriers,
+                &self.resources,
+                camera,
+                self.transform_cache.select(&self.objects, visible),
+            )?;
+            self.pipeline_statistics
+                .end(command_list, self.resources.allocation_counts())?;
+            self.breadcrumbs.end(command_list)?;
+        }
+        if let Some(environment_probe_pass) = &self.environment_probe_pass {
+            self.basic_render_pass.environment_lights =
+                environment_probe_pass.lights(&self.resources)?;
+        }
+
+        if let Some(water_pass) = &mut self.water_pass {
+            self.breadcrumbs.begin(command_list, "Water reflections")?;
+            self.pipeline_statistics.begin(
+                command_list,
+                "Water reflections",
+                self.resources.allocation_counts(),
+            )?;
+            water_pass.render_reflections(
+                command_list,
+                &mut self.barriers,
+                &self.resources,
+                self.views
+                    .iter()
+                    .zip(&reflections)
+                    .map(|(view, (camera, visible))| {
+                        (
+                            camera,
+                            &view.region,
+                            self.transform_cache.select(&self.objects, visible),
+                        )
+                    }),
+            )?;
+            self.pipeline_statistics
+                .end(command_list, self.resources.allocation_counts())?;
+            self.breadcrumbs.end(command_list)?;
+        }
+
+        // The passes up to the scene target record their own barriers
+        self.barriers.flush(command_list);
 
-        let _: D3D12_RESOURCE_TRANSITION_BARRIER =
-            unsafe { std::mem::ManuallyDrop::into_inner(barrier.Anonymous.Transition) };
-        self.basic_render_pass.render(
+        if let Some(shading_rate_pass) = &self.shading_rate_pass {
+            self.breadcrumbs.begin(command_list, "Shading rate")?;
+            self.pipeline_statistics.begin(
+                command_list,
+                "Shading rate",
+                self.resources.allocation_counts(),
+            )?;
+            shading_rate_pass.render(command_list, &self.resources, &self.scene_target)?;
+            self.pipeline_statistics
+                .end(command_list, self.resources.allocation_counts())?;
+            self.breadcrumbs.end(command_list)?;
+        }
+
+        let lights_per_view = match &mut self.light_culling_pass {
+            Some(light_culling_pass) => {
+                self.breadcrumbs.begin(command_list, "Light culling")?;
+                self.pipeline_statistics.begin(
+                    command_list,
+                    "Light culling",
+                    self.resources.allocation_counts(),
+                )?;
+                let lights_per_view = self
+                    .views
+                    .iter()
+                    .map(|view| {
+                        light_culling_pass
+                            .cull(command_list, &self.resources, &view.camera)
+                            .map(Some)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                self.pipeline_statistics
+                    .end(command_list, self.resources.allocation_counts())?;
+                self.breadcrumbs.end(command_list)?;
+                lights_per_view
+            }
+            None => self.views.iter().map(|_| None).collect(),
+        };
+
+        self.breadcrumbs.begin(command_list, "Scene")?;
+        self.pipeline_statistics.begin(
             command_list,
-            &mut self.resources,
-            render_target_handle,
-            depth_buffer_handle,
-            &self.objects,
+            "Scene",
+            self.resources.allocation_counts(),
+        )?;
+        self.scene_target.begin(
+            command_list,
+            &mut self.barriers,
+            &self.resources.texture_manager,
+            &self.resources.descriptor_manager,
+        )?;
+        match (&mut self.occlusion_culling_pass, &mut self.hi_z_pass) {
+            (Some(occlusion_culling_pass), Some(hi_z_pass)) => {
+                let prepared = self
+                    .views
+                    .iter()
+                    .zip(&visible_per_view)
+                    .zip(&lights_per_view)
+                    .map(|((view, visible), lights)| {
+                        self.basic_render_pass.prepare(
+                            &self.resources,
+                            &view.camera,
+                            &self.scene_target,
+                            &view.region,
+                            lights.as_ref(),
+                            self.transform_cache.select(&self.objects, visible),
+                        )
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+
+                // What was in front last frame likely still is, drawing it first gives a pyramid
+                // to test the rest against
+                let arguments = occlusion_culling_pass.cull_first_phase(
+                    command_list,
+                    &mut self.barriers,
+                    &self.resources,
+                    hi_z_pass.latest(),
+                    &self.views,
+                    &prepared,
+                )?;
+                for view in &prepared {
+                    self.basic_render_pass.draw(
+                        command_list,
+                        &self.resources,
+                        &self.scene_target,
+                        view,
+                        Some(&arguments),
+                    )?;
+                }
+
+                let hi_z = hi_z_pass.render(
+                    command_list,
+                    &mut self.barriers,
+                    &mut self.resources,
+                    &self.scene_target,
+                )?;
+                let arguments = occlusion_culling_pass.cull_second_phase(
+                    command_list,
+                    &mut self.barriers,
+                    &self.resources,
+                    hi_z,
+                    &self.views,
+                    &prepared,
+                )?;
+                for view in &prepared {
+                    self.basic_render_pass.draw(
+                        command_list,
+                        &self.resources,
+                        &self.scene_target,
+                        view,
+                        Some(&arguments),
+                    )?;
+                }
+            }
+            _ => {
+                for ((view, visible), lights) in self
+                    .views
+                    .iter()
+                    .zip(&visible_per_view)
+                    .zip(&lights_per_view)
+                {
+                    self.basic_render_pass.render(
+                        command_list,
+                        &self.resources,
+                        &view.camera,
+                        &self.scene_target,
+                        &view.region,
+                        lights.as_ref(),
+                        self.transform_cache.select(&self.objects, visible),
+                    )?;
+                }
+            }
+        }
+        for (index, view) in self.views.iter().enumerate() {
+            // Under the grid, which marks the ground the water lies on
+            if let (Some(water_pass), Some((reflection_camera, _))) =
+                (&self.water_pass, reflections.get(index))
+            {
+                water_pass.render(
+                    command_list,
+                    &self.resources,
+                    &view.camera,
+                    reflection_camera,
+                    &self.scene_target,
+                    &view.region,
+                )?;
+            }
+            self.grid_pass.render(
+                command_list,
+                &self.resources,
+                &view.camera,
+                &self.scene_target,
+                &view.region,
+            )?;
+        }
+        // Only colour grading samples the scene, the depth readback and streaming resolve overlap
+        // with the transition
+        self.scene_target
+            .start_end(&mut self.barriers, &self.resources.texture_manager)?;
+        self.pipeline_statistics
+            .end(command_list, self.resources.allocation_counts())?;
+        self.breadcrumbs.end(command_list)?;
+
+        if let (Some(main_view), Some(depth_readback_pass)) =
+            (self.views.first(), &mut self.depth_readback_pass)
+        {
+            self.breadcrumbs.begin(command_list, "Depth readback")?;
+            self.pipeline_statistics.begin(
+                command_list,
+                "Depth readback",
+                self.resources.allocation_counts(),
+            )?;
+            depth_readback_pass.render(
+                command_list,
+                &mut self.barriers,
+                &self.resources,
+                &self.scene_target,
+                main_view,
+                frame_index,
+            )?;
+            self.pipeline_statistics
+                .end(command_list, self.resources.allocation_counts())?;
+            self.breadcrumbs.end(command_list)?;
+        }
+
+        if let Some(hi_z_pass) = &mut self.hi_z_pass {
+            self.breadcrumbs.begin(command_list, "Hi-Z")?;
+            self.pipeline_statistics.begin(
+                command_list,
+                "Hi-Z",
+                self.resources.allocation_counts(),
+            )?;
+            hi_z_pass.render(
+                command_list,
+                &mut self.barriers,
+                &mut self.resources,
+                &self.scene_target,
+            )?;
+            self.pipeline_statistics
+                .end(command_list, self.resources.allocation_counts())?;
+            self.breadcrumbs.end(command_list)?;
+        }
+
+        if let Some(texture_streaming) = &mut self.texture_streaming {
+            texture_streaming.resolve(command_list, &mut self.barriers, &self.resources)?;
+        }
+
+        self.scene_target
+            .finish_end(&mut self.barriers, &self.resources.texture_manager)?;
+        if let (Some(main_view), Some(depth_of_field_pass)) =
+            (self.views.first(), &mut self.depth_of_field_pass)
+        {
+            self.breadcrumbs.begin(command_list, "Depth of field")?;
+            self.pipeline_statistics.begin(
+                command_list,
+                "Depth of field",
+                self.resources.allocation_counts(),
+            )?;
+            depth_of_field_pass.render(
+                command_list,
+                &mut self.barriers,
+                &self.resources,
+                &self.scene_target,
+                main_view,
+            )?;
+            self.pipeline_statistics
+                .end(command_list, self.resources.allocation_counts())?;
+            self.breadcrumbs.end(command_list)?;
+        }
+        if let (Some(main_view), Some(motion_blur_pass)) =
+            (self.views.first(), &mut self.motion_blur_pass)
+        {
+            self.breadcrumbs.begin(command_list, "Motion blur")?;
+            self.pipeline_statistics.begin(
+                command_list,
+                "Motion blur",
+                self.resources.allocation_counts(),
+            )?;
+            motion_blur_pass.render(
+                command_list,
+                &mut self.barriers,
+                &self.resources,
+                &self.scene_target,
+                main_view,
+            )?;
+            self.pipeline_statistics
+                .end(command_list, self.resources.allocation_counts())?;
+            self.breadcrumbs.end(command_list)?;
+        }
+
+        let exposure = match &self.auto_exposure_pass {
+            Some(auto_exposure_pass) => {
+                self.breadcrumbs.begin(command_list, "Auto exposure")?;
+                self.pipeline_statistics.begin(
+                    command_list,
+                    "Auto exposure",
+                    self.resources.allocation_counts(),
+                )?;
+                let exposure = auto_exposure_pass.render(
+                    command_list,
+                    &mut self.barriers,
+                    &self.resources,
+                    &self.scene_target,
+                )?;
+                self.pipeline_statistics
+                    .end(command_list, self.resources.allocation_counts())?;
+                self.breadcrumbs.end(command_list)?;
+                exposure
+            }
+            None => None,
+        };
+
+        // Shown ungraded without the pass
+        let graded_target = match &mut self.color_grading_pass {
+            Some(color_grading_pass) => {
+                self.breadcrumbs.begin(command_list, "Colour grading")?;
+                self.pipeline_statistics.begin(
+                    command_list,
+                    "Colour grading",
+                    self.resources.allocation_counts(),
+                )?;
+                let graded_target = color_grading_pass.render(
+                    command_list,
+                    &mut self.barriers,
+                    &self.resources,
+                    &self.scene_target,
+                    exposure,
+                )?;
+                self.pipeline_statistics
+                    .end(command_list, self.resources.allocation_counts())?;
+                self.breadcrumbs.end(command_list)?;
+                graded_target
+            }
+            None => &self.scene_target,
+        };
+
+        self.breadcrumbs.begin(command_list, "Overlays")?;
+        self.pipeline_statistics.begin(
+            command_list,
+            "Overlays",
+            self.resources.allocation_counts(),
         )?;
+        self.overlay_target.begin(
+            command_list,
+            &mut self.barriers,
+            &self.resources.texture_manager,
+            &self.resources.descriptor_manager,
+        )?;
+        if let Some(debug_line_pass) = &self.debug_line_pass {
+            debug_line_pass.render(
+                command_list,
+                &self.resources,
+                &self.views[0].camera,
+                &self.overlay_target,
+                &self.views[0].region,
+            )?;
+        }
+        self.memory_hud_pass
+            .render(command_list, &self.resources, &self.overlay_target)?;
+        if let Some(profiler_graph_pass) = &self.profiler_graph_pass {
+            profiler_graph_pass.render(command_list, &self.resources, &self.overlay_target)?;
+        }
+        self.overlay_target
+            .end(&mut self.barriers, &self.resources.texture_manager)?;
+        self.pipeline_statistics
+            .end(command_list, self.resources.allocation_counts())?;
+        self.breadcrumbs.end(command_list)?;
+
+        let back_buffer_index = unsafe { self.swap_chain.GetCurrentBackBufferIndex() };
+        let render_target = &self.render_targets[back_buffer_index as usize];
+        self.breadcrumbs
+            .begin(command_list, "Upscale and composite")?;
+        self.pipeline_statistics.begin(
+            command_list,
+            "Upscale and composite",
+            self.resources.allocation_counts(),
+        )?;
+        if let Some(compute_composite_pass) = &self.compute_composite_pass {
+            compute_composite_pass.render(
+                command_list,
+                &mut self.barriers,
+                &self.resources,
+                graded_target,
+                self.upscale_pass.sharpness,
+                &self.overlay_target,
+                render_target,
+                self.display_rotation,
+            )?;
+        } else {
+            render_target.begin(
+                command_list,
+                &mut self.barriers,
+                &self.resources.texture_manager,
+                &self.resources.descriptor_manager,
+            )?;
 
+            // The scene is still written in display encoding, so it goes through the UNORM view
+            self.upscale_pass.render(
+                command_list,
+                &self.resources,
+                graded_target,
+                render_target,
+                self.display_rotation,
+            )?;
+            self.composite_pass.render(
+                command_list,
+                &self.resources,
+                &self.overlay_target,
+                render_target,
+                self.display_rotation,
+            )?;
+
+            render_target.end(&mut self.barriers, &self.resources.texture_manager)?;
+        }
+        self.barriers.flush(command_list);
+        self.pipeline_statistics
+            .end(command_list, self.resources.allocation_counts())?;
+        self.breadcrumbs.end(command_list)?;
+
+        if let Some(recording) = &mut self.recording {
+            let back_buffer = self
+                .resources
+                .texture_manager
+                .get_texture(&render_target.color)?;
+            recording.capture(
+                command_list,
+                &back_buffer.get_resource()?.device_resource,
+                D3D12_RESOURCE_STATE_PRESENT,
+                frame_index,
+            )?;
+        }
+        if let Some(frame_capture) = &mut self.frame_capture {
+            let back_buffer = self
+                .resources
+                .texture_manager
+                .get_texture(&render_target.color)?;
+            frame_capture.capture(
+                command_list,
+                &back_buffer.get_resource()?.device_resource,
+                D3D12_RESOURCE_STATE_PRESENT,
+                self.frame_number,
+                frame_index,
+            )?;
+        }
+
+        if let Some(texture_dump_pass) = &mut self.texture_dump_pass {
+            texture_dump_pass.render(
+                command_list,
+                &mut self.barriers,
+                &mut self.resources,
+                self.frame_number,
+            )?;
+        }
+
+        self.pipeline_statistics.resolve(command_list)?;
+        self.gpu_timer.end(command_list, frame_index)?;
+
+        ensure!(
+            self.barriers.is_empty() && self.barriers.num_splits_in_flight() == 0,
+            "Resource transitions left unrecorded at the end of the frame"
+        );
         unsafe {
             command_list.Close()?;
         }
@@ -683,31 +2894,411 @@ impl Renderer {
         let fence_value = self
             .graphics_queue
             .execute_command_list(&generic_command_list)?;
+        ensure!(
+            fence_value == frame.fence_value,
+            "Frame {} was submitted with fence value {} instead of {}",
+            self.frame_number,
+            fence_value,
+            frame.fence_value
+        );
 
-        self.fence_values[self.resources.frame_index as usize] = fence_value;
+        self.fence_values[frame_index] = fence_value;
+        self.resources.texture_manager.submitted(fence_value);
 
-        let render_target = self
-            .resources
-            .texture_manager
-            .get_texture(render_target_handle)?;
+        if let Some(texture_streaming) = &mut self.texture_streaming {
+            texture_streaming.submitted(&self.fence_watcher, &self.graphics_queue, fence_value)?;
+        }
 
-        unsafe {
-            let barrier = transition_barrier(
-                &render_target.get_resource()?.device_resource,
-                D3D12_RESOURCE_STATE_RENDER_TARGET,
-                D3D12_RESOURCE_STATE_PRESENT,
-            );
-            command_list.ResourceBarrier(&[barrier.clone()]);
-            let _: D3D12_RESOURCE_TRANSITION_BARRIER =
-                std::mem::ManuallyDrop::into_inner(barrier.Anonymous.Transition);
+        // Dumps are rare enough to simply wait for
+        if self
+            .texture_dump_pass
+            .as_ref()
+            .is_some_and(TextureDumpPass::is_pending)
+        {
+            self.wait_for_idle()?;
+            if let Some(texture_dump_pass) = &mut self.texture_dump_pass {
+                texture_dump_pass.write_files(&mut self.resources)?;
+            }
         }
 
-        unsafe { self.swap_chain.Present(1, 0) }.ok()?;
+        let sync_interval = if self.settings.vsync { 1 } else { 0 };
+        // Tearing isn't allowed in exclusive fullscreen, which doesn't wait for the compositor
+        let present_flags =
+            if !self.settings.vsync && self.allow_tearing && !self.is_fullscreen()? {
+                DXGI_PRESENT_ALLOW_TEARING
+            } else {
+                0
+            };
+        let status = unsafe { self.swap_chain.Present(sync_interval, present_flags) };
+        status.ok()?;
+        self.occluded = status == DXGI_STATUS_OCCLUDED;
+        self.frame_number += 1;
 
-        self.resources.frame_index = unsafe { self.swap_chain.GetCurrentBackBufferIndex() };
+        self.resources.frame_index = (self.frame_number % FRAME_COUNT as u64) as u32;
 
-        self.resources.upload_ring_buffer.clean_up_submissions()?;
+        self.resources.upload_rings.clean_up_submissions()?;
 
         Ok(())
     }
 }
+
+fn create_back_buffer_targets(
+    device: &ID3D12Device4,
+    swap_chain: &IDXGISwapChain3,
+    texture_manager: &mut TextureManager,
+    descriptor_manager: &mut DescriptorManager,
+    extent: (u32, u32),
+) -> Result<Vec<RenderTarget>> {
+    let buffer_count = unsafe { swap_chain.GetDesc1()? }.BufferCount;
+    (0..buffer_count)
+        .map(|i| -> Result<RenderTarget> {
+            let back_buffer: ID3D12Resource = unsafe { swap_chain.GetBuffer(i) }?;
+            unsafe {
+                back_buffer.SetName(PCWSTR::from(&format!("Backbuffer {}", COUNTER).into()))?;
+                COUNTER += 1;
+            }
+            let color = texture_manager.add_external_texture(
+                device,
+                descriptor_manager,
+                ExternalTexture {
+                    resource: back_buffer,
+                    state: D3D12_RESOURCE_STATE_PRESENT,
+                },
+            )?;
+            let resting_state = texture_manager
+                .get_texture(&color)?
+                .external_state
+                .context("Back buffer is not external")?;
+            let depth = create_depth_buffer(
+                device,
+                texture_manager,
+                descriptor_manager,
+                extent,
+                DEPTH_RANGE,
+            )?;
+
+            let mut render_target =
+                RenderTarget::from_textures(color, depth, extent, resting_state, DEPTH_RANGE);
+            // The upscale pass covers every pixel with a full screen triangle and has no depth
+            render_target.color_load = LoadOp::DontCare;
+            render_target.depth_load = LoadOp::DontCare;
+            render_target.add_srgb_view(device, texture_manager, descriptor_manager)?;
+
+            Ok(render_target)
+        })
+        .collect()
+}
+
+// Only the headers are read here, the payload goes through the asset loader into an upload heap
+// buffer and from there into the texture
+fn load_dds_texture(
+    resources: &mut Resources,
+    asset_loader: &mut AssetLoader,
+    dependent_queue: &CommandQueue,
+    path: &Path,
+    priority: UploadPriority,
+) -> Result<(TextureHandle, TextureInfo)> {
+    let file = File::open(path)?;
+    let file_size = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    ensure!(&magic == b"DDS ", "{} is not a DDS file", path.display());
+    let header = ddsfile::Header::read(&mut reader)?;
+    let header10 = if header.spf.fourcc == Some(ddsfile::FourCC(ddsfile::FourCC::DX10)) {
+        Some(ddsfile::Header10::read(&mut reader)?)
+    } else {
+        None
+    };
+    let payload_offset = reader.stream_position()?;
+    let payload_size = (file_size - payload_offset) as usize;
+    let dds_file = ddsfile::Dds {
+        header,
+        header10,
+        data: Vec::new(),
+    };
+
+    let dimension = if dds_file.get_depth() > 1 {
+        TextureDimension::Three(
+            dds_file.get_width() as usize,
+            dds_file.get_height(),
+            dds_file.get_depth() as u16,
+        )
+    } else if dds_file.get_height() > 1 {
+        TextureDimension::Two(dds_file.get_width() as usize, dds_file.get_height())
+    } else {
+        TextureDimension::One(dds_file.get_width() as usize)
+    };
+
+    let texture_info = TextureInfo {
+        dimension,
+        format: DXGI_FORMAT(dds_file.get_dxgi_format().context("No DXGI format")? as u32),
+        array_size: dds_file.get_num_array_layers() as u16,
+        num_mips: dds_file.get_num_mipmap_levels() as u16,
+        is_render_target: false,
+        is_depth_buffer: false,
+        is_unordered_access: false,
+        is_cube: false,
+    };
+
+    let payload = Resource::create_committed(
+        &resources.device,
+        &D3D12_HEAP_PROPERTIES {
+            Type: D3D12_HEAP_TYPE_UPLOAD,
+            ..Default::default()
+        },
+        &D3D12_RESOURCE_DESC {
+            Dimension: D3D12_RESOURCE_DIMENSION_BUFFER,
+            Width: payload_size as u64,
+            Height: 1,
+            DepthOrArraySize: 1,
+            MipLevels: 1,
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            Layout: D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
+            ..Default::default()
+        },
+        D3D12_RESOURCE_STATE_GENERIC_READ,
+        None,
+        true,
+    )?;
+    let request = asset_loader.load(
+        &[AssetBlob {
+            path: path.to_path_buf(),
+            file_offset: payload_offset,
+            size: payload_size,
+            compression: BlobCompression::None,
+            destination: payload.create_sub_resource(payload_size, 0)?,
+        }],
+        resources.upload_rings.get_mut(priority),
+        None,
+    )?;
+    // The rows are laid out for the texture on the CPU
+    asset_loader.wait(request)?;
+
+    // The loader is done writing the payload, which outlives the upload
+    let texture = unsafe {
+        resources.texture_manager.create_texture_from_ptr(
+            &resources.device,
+            resources.upload_rings.get_mut(priority),
+            Some(dependent_queue),
+            &mut resources.descriptor_manager,
+            texture_info,
+            payload.mapped_data as *const u8,
+            payload_size,
+            &[],
+        )
+    }?;
+
+    Ok((texture, texture_info))
+}
+
+// A .cube file, or a DDS file holding a 3D texture that covers colours from 0 to 1
+fn load_color_lut(
+    resources: &mut Resources,
+    asset_loader: &mut AssetLoader,
+    dependent_queue: &CommandQueue,
+    path: &Path,
+) -> Result<ColorLut> {
+    if path
+        .extension()
+        .is_some_and(|extension| extension == "cube")
+    {
+        let text = std::fs::read_to_string(path)?;
+        let lut = parse_cube(text.lines())?;
+        // Every frame grades through it, so it skips the queue of streamed meshes
+        let texture = resources.texture_manager.create_texture(
+            &resources.device,
+            resources.upload_rings.get_mut(UploadPriority::High),
+            Some(dependent_queue),
+            &mut resources.descriptor_manager,
+            lut.texture_info(),
+            &lut.texture_data(),
+        )?;
+
+        Ok(ColorLut {
+            texture,
+            domain_min: lut.domain_min,
+            domain_max: lut.domain_max,
+        })
+    } else {
+        let (texture, texture_info) = load_dds_texture(
+            resources,
+            asset_loader,
+            dependent_queue,
+            path,
+            UploadPriority::High,
+        )?;
+        ensure!(
+            matches!(texture_info.dimension, TextureDimension::Three(..)),
+            "Colour LUT textures have to be 3D"
+        );
+
+        Ok(ColorLut::new(texture))
+    }
+}
+
+// Stands in for the UV checker asset. Generated on the CPU when the compute shader can't write
+// through the descriptor heap.
+fn create_checker_texture(
+    resources: &mut Resources,
+    queue: &mut CommandQueue,
+    dynamic_resources: bool,
+) -> Result<(TextureHandle, TextureInfo)> {
+    let texture_info = TextureInfo {
+        dimension: TextureDimension::Two(CHECKER_TEXTURE_SIZE as usize, CHECKER_TEXTURE_SIZE),
+        format: DXGI_FORMAT_R8G8B8A8_UNORM,
+        ..Default::default()
+    };
+    if !dynamic_resources {
+        let texture = resources.texture_manager.create_texture(
+            &resources.device,
+            resources.upload_rings.get_mut(UploadPriority::High),
+            Some(queue),
+            &mut resources.descriptor_manager,
+            texture_info,
+            &checker_texels(CHECKER_TEXTURE_SIZE, CHECKER_SQUARES),
+        )?;
+        return Ok((texture, texture_info));
+    }
+    let compute_shader = load_hlsl!("renderer/src/shaders/checker.hlsl", "CSMain", "cs_6_6")?;
+
+    let texture = ProceduralTextureGenerator::new(&resources.device)?.create_texture(
+        &resources.device,
+        queue,
+        &mut resources.texture_manager,
+        &mut resources.descriptor_manager,
+        texture_info,
+        &compute_shader,
+        [8, 8, 1],
+        [CHECKER_SQUARES as f32, 0.0, 0.0, 0.0],
+    )?;
+
+    Ok((texture, texture_info))
+}
+
+// Packs the swatches into as few pages as they fit on, the scene assets keep the pages alive
+fn create_swatch_atlas(resources: &mut Resources, queue: &CommandQueue) -> Result<TextureAtlas> {
+    let mut builder = TextureAtlasBuilder::new(SWATCH_ATLAS_EXTENT, DXGI_FORMAT_R8G8B8A8_UNORM)?;
+    for colour in SWATCH_COLOURS {
+        builder.add_image(
+            SWATCH_SIZE,
+            SWATCH_SIZE,
+            &colour.repeat((SWATCH_SIZE * SWATCH_SIZE) as usize),
+        )?;
+    }
+
+    builder.build(
+        &resources.device,
+        &mut resources.texture_manager,
+        resources.upload_rings.get_mut(UploadPriority::High),
+        Some(queue),
+        &mut resources.descriptor_manager,
+    )
+}
+
+// What checker.hlsl writes, as RGBA8 rows of `size` texels
+fn checker_texels(size: u32, squares: u32) -> Vec<u8> {
+    (0..size * size)
+        .flat_map(|index| {
+            let uv = (Vec2::new((index % size) as f32, (index / size) as f32) + 0.5) / size as f32;
+            let square = (uv * squares as f32).as_uvec2();
+            let brightness = if (square.x + square.y) % 2 == 1 {
+                0.35
+            } else {
+                1.0
+            };
+            let colour = Vec3::new(uv.x, uv.y, 1.0 - uv.x) * brightness;
+
+            colour
+                .extend(1.0)
+                .to_array()
+                .map(|channel| (channel * 255.0).round() as u8)
+        })
+        .collect()
+}
+
+// Puffing the mesh up along its normals and stretching it upwards from its feet
+fn create_demo_morph_targets(vertices: &[ObjVertex]) -> Vec<MorphTarget> {
+    let bounds = vertices
+        .iter()
+        .fold(Aabb::EMPTY, |bounds, vertex| bounds.extend(vertex.position));
+    let inflate = (bounds.max - bounds.min).length() * 0.05;
+
+    vec![
+        MorphTarget {
+            position_deltas: vertices
+                .iter()
+                .map(|vertex| vertex.normal * inflate)
+                .collect(),
+            normal_deltas: vec![Vec3::ZERO; vertices.len()],
+        },
+        MorphTarget {
+            position_deltas: vertices
+                .iter()
+                .map(|vertex| Vec3::new(0.0, (vertex.position.y - bounds.min.y) * 0.5, 0.0))
+                .collect(),
+            normal_deltas: vec![Vec3::ZERO; vertices.len()],
+        },
+    ]
+}
+
+// A square of small coloured lights just above the ground, spread over the scene
+fn create_demo_lights() -> Vec<PointLight> {
+    let spacing = 1.5;
+    let offset = (DEMO_LIGHT_GRID - 1) as f32 * spacing * 0.5;
+
+    (0..DEMO_LIGHT_GRID * DEMO_LIGHT_GRID)
+        .map(|i| {
+            let (x, z) = (i % DEMO_LIGHT_GRID, i / DEMO_LIGHT_GRID);
+            let hue = i as f32 * 0.618_034 * 2.0 * PI;
+
+            PointLight {
+                position: Vec3::new(
+                    x as f32 * spacing - offset,
+                    0.5,
+                    z as f32 * spacing - offset,
+                ),
+                radius: 2.0,
+                color: Vec3::new(
+                    hue.cos(),
+                    (hue + PI * 2.0 / 3.0).cos(),
+                    (hue + PI * 4.0 / 3.0).cos(),
+                ) * 0.5
+                    + 0.5,
+                intensity: 2.0,
+            }
+        })
+        .collect()
+}
+
+// Cleared to transparent every frame, overlays draw without depth
+fn create_overlay_target(resources: &mut Resources, extent: (u32, u32)) -> Result<RenderTarget> {
+    let mut overlay_target = RenderTarget::new(
+        &resources.device,
+        &mut resources.texture_manager,
+        &mut resources.descriptor_manager,
+        extent,
+        OVERLAY_FORMAT,
+        [0.0; 4],
+        resources.depth_range,
+    )?;
+    overlay_target.depth_load = LoadOp::DontCare;
+
+    Ok(overlay_target)
+}
+
+fn create_scene_target(resources: &mut Resources, extent: (u32, u32)) -> Result<RenderTarget> {
+    RenderTarget::new(
+        &resources.device,
+        &mut resources.texture_manager,
+        &mut resources.descriptor_manager,
+        extent,
+        SCENE_FORMAT,
+        SCENE_CLEAR_COLOR,
+        resources.depth_range,
+    )
+}
@@ -0,0 +1,251 @@
+use std::path::Path;
+
+use anyhow::{ensure, Context, Result};
+use d3d12_utils::{MeshHandle, PointLight, TextureHandle};
+use glam::{Quat, Vec2, Vec3, Vec4};
+use serde::{Deserialize, Serialize};
+
+use crate::{material::Material, object::Object};
+
+/// Objects, lights and the camera of a scene, saved as JSON. Meshes and textures are referred to
+/// by the names they were registered under in `SceneAssets`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneFile {
+    pub camera: CameraPose,
+    pub objects: Vec<SceneObject>,
+    pub lights: Vec<SceneLight>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CameraPose {
+    pub position: [f32; 3],
+    pub forward: [f32; 3],
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneObject {
+    /// Relative to the parent, if there is one
+    pub position: [f32; 3],
+    /// Quaternion, x y z w
+    pub rotation: [f32; 4],
+    #[serde(default = "unit_scale")]
+    pub scale: [f32; 3],
+    /// Index of the parent in `objects`
+    #[serde(default)]
+    pub parent: Option<usize>,
+    #[serde(default)]
+    pub spin: f32,
+    /// One for each morph target of the mesh
+    #[serde(default)]
+    pub morph_weights: Vec<f32>,
+    pub mesh: String,
+    pub material: SceneMaterial,
+}
+
+// Scene files from before objects could be scaled
+fn unit_scale() -> [f32; 3] {
+    [1.0; 3]
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneMaterial {
+    pub texture: String,
+    pub uv_offset: [f32; 2],
+    pub uv_scale: [f32; 2],
+    pub base_color_factor: [f32; 4],
+    pub emissive_factor: [f32; 3],
+    #[serde(default = "full_roughness")]
+    pub roughness: f32,
+}
+
+// Scene files from before materials reflected their environment
+fn full_roughness() -> f32 {
+    1.0
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SceneLight {
+    pub position: [f32; 3],
+    pub radius: f32,
+    pub color: [f32; 3],
+    pub intensity: f32,
+}
+
+/// The meshes and textures scene files can refer to, by name
+#[derive(Debug, Default)]
+pub struct SceneAssets {
+    meshes: Vec<(String, MeshHandle)>,
+    textures: Vec<(String, TextureHandle)>,
+}
+
+impl SceneAssets {
+    pub fn add_mesh(&mut self, name: &str, mesh: &MeshHandle) {
+        self.meshes.push((name.to_string(), *mesh));
+    }
+
+    pub fn add_texture(&mut self, name: &str, texture: &TextureHandle) {
+        self.textures.push((name.to_string(), texture.clone()));
+    }
+
+    fn mesh(&self, name: &str) -> Result<MeshHandle> {
+        self.meshes
+            .iter()
+            .find(|(mesh_name, _)| mesh_name == name)
+            .map(|(_, mesh)| *mesh)
+            .with_context(|| format!("Unknown mesh \"{}\"", name))
+    }
+
+    fn mesh_name(&self, mesh: &MeshHandle) -> Result<&str> {
+        self.meshes
+            .iter()
+            .find(|(_, other)| other.is_same(mesh))
+            .map(|(name, _)| name.as_str())
+            .context("The mesh was not registered as a scene asset")
+    }
+
+    fn texture(&self, name: &str) -> Result<TextureHandle> {
+        self.textures
+            .iter()
+            .find(|(texture_name, _)| texture_name == name)
+            .map(|(_, texture)| texture.clone())
+            .with_context(|| format!("Unknown texture \"{}\"", name))
+    }
+
+    fn texture_name(&self, texture: &TextureHandle) -> Result<&str> {
+        self.textures
+            .iter()
+            .find(|(_, other)| other.index == texture.index)
+            .map(|(name, _)| name.as_str())
+            .with_context(|| {
+                format!(
+                    "Texture {} was not registered as a scene asset",
+                    texture.index
+                )
+            })
+    }
+}
+
+impl SceneFile {
+    /// Fails for meshes or textures that are not in `assets`
+    pub fn capture(
+        camera_position: Vec3,
+        camera_forward: Vec3,
+        objects: &[Object],
+        lights: &[PointLight],
+        assets: &SceneAssets,
+    ) -> Result<Self> {
+        let objects = objects
+            .iter()
+            .enumerate()
+            .map(|(index, object)| {
+                let material = &object.material;
+                Ok(SceneObject {
+                    position: object.position.to_array(),
+                    rotation: object.rotation.to_array(),
+                    scale: object.scale.to_array(),
+                    parent: object.parent,
+                    spin: object.spin,
+                    morph_weights: object.morph_weights.clone(),
+                    mesh: assets
+                        .mesh_name(&object.mesh)
+                        .with_context(|| format!("Object {}", index))?
+                        .to_string(),
+                    material: SceneMaterial {
+                        texture: assets
+                            .texture_name(&material.texture)
+                            .with_context(|| format!("Object {}", index))?
+                            .to_string(),
+                        uv_offset: material.uv_offset.to_array(),
+                        uv_scale: material.uv_scale.to_array(),
+                        base_color_factor: material.base_color_factor.to_array(),
+                        emissive_factor: material.emissive_factor.to_array(),
+                        roughness: material.roughness,
+                    },
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let lights = lights
+            .iter()
+            .map(|light| SceneLight {
+                position: light.position.to_array(),
+                radius: light.radius,
+                color: light.color.to_array(),
+                intensity: light.intensity,
+            })
+            .collect();
+
+        Ok(Self {
+            camera: CameraPose {
+                position: camera_position.to_array(),
+                forward: camera_forward.to_array(),
+            },
+            objects,
+            lights,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text =
+            std::fs::read_to_string(path).with_context(|| format!("Reading {}", path.display()))?;
+        serde_json::from_str(&text).with_context(|| format!("Parsing {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let text = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, text).with_context(|| format!("Writing {}", path.display()))
+    }
+
+    /// The objects with their meshes and textures looked up in `assets`. Materials start out
+    /// without sampler feedback and at the finest mip.
+    pub fn objects(&self, assets: &SceneAssets) -> Result<Vec<Object>> {
+        self.objects
+            .iter()
+            .enumerate()
+            .map(|(index, object)| {
+                ensure!(
+                    object
+                        .parent
+                        .is_none_or(|parent| parent < self.objects.len()),
+                    "Parent {:?} of object {} does not exist",
+                    object.parent,
+                    index
+                );
+
+                let scene_material = &object.material;
+                let material = Material {
+                    uv_offset: Vec2::from_array(scene_material.uv_offset),
+                    uv_scale: Vec2::from_array(scene_material.uv_scale),
+                    base_color_factor: Vec4::from_array(scene_material.base_color_factor),
+                    emissive_factor: Vec3::from_array(scene_material.emissive_factor),
+                    roughness: scene_material.roughness,
+                    ..Material::from_texture(assets.texture(&scene_material.texture)?)
+                };
+
+                Ok(Object {
+                    position: Vec3::from_array(object.position),
+                    rotation: Quat::from_array(object.rotation).normalize(),
+                    scale: Vec3::from_array(object.scale),
+                    parent: object.parent,
+                    material,
+                    mesh: assets.mesh(&object.mesh)?,
+                    lod: 0,
+                    spin: object.spin,
+                    morph_weights: object.morph_weights.clone(),
+                })
+            })
+            .collect()
+    }
+
+    pub fn lights(&self) -> Vec<PointLight> {
+        self.lights
+            .iter()
+            .map(|light| PointLight {
+                position: Vec3::from_array(light.position),
+                radius: light.radius,
+                color: Vec3::from_array(light.color),
+                intensity: light.intensity,
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,308 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use d3d12_utils::HdrMetadata;
+use serde::{Deserialize, Serialize};
+
+/// Renderer options that can be changed while running, kept between runs in a TOML file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Fraction of the window resolution the scene is rendered at. Left out, the scale follows
+    /// the GPU frame time.
+    pub render_scale: Option<f32>,
+    pub vsync: bool,
+    pub show_grid: bool,
+    pub show_memory_hud: bool,
+    pub show_profiler_graphs: bool,
+    pub color_grading: ColorGrading,
+    pub auto_exposure: AutoExposure,
+    pub depth_of_field: DepthOfField,
+    pub motion_blur: MotionBlur,
+    /// Sent with every frame when the swap chain is HDR10, left out none is
+    pub hdr_metadata: Option<HdrMetadataSettings>,
+}
+
+/// Adjustments to the final image, applied before the colour LUT
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColorGrading {
+    /// In stops, each one doubles the brightness
+    pub exposure: f32,
+    /// Scales the distance from middle grey, 1 leaves colours alone
+    pub contrast: f32,
+    /// Scales the distance from grey of the same luminance, 0 gives black and white
+    pub saturation: f32,
+}
+
+impl Default for ColorGrading {
+    fn default() -> Self {
+        Self {
+            exposure: 0.0,
+            contrast: 1.0,
+            saturation: 1.0,
+        }
+    }
+}
+
+/// Adapts the exposure to how bright the scene is, a bit every frame like eyes do. Added to the
+/// exposure of the colour grading.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoExposure {
+    pub enabled: bool,
+    /// Range the exposure adapts in, in stops
+    pub min_exposure: f32,
+    pub max_exposure: f32,
+    /// How quickly the exposure rises in the dark and falls in bright light, per second
+    pub speed_up: f32,
+    pub speed_down: f32,
+    /// Used instead of the measured exposure while enabled, in stops
+    pub manual_exposure: Option<f32>,
+}
+
+impl Default for AutoExposure {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_exposure: -4.0,
+            max_exposure: 4.0,
+            speed_up: 1.0,
+            speed_down: 3.0,
+            manual_exposure: None,
+        }
+    }
+}
+
+/// Blurs what is nearer or further than the focus distance, more the wider the aperture
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DepthOfField {
+    pub enabled: bool,
+    /// Distance along the view direction that is sharp, in world units taken as metres
+    pub focus_distance: f32,
+    /// Focal length over aperture diameter, lower values blur more
+    pub f_number: f32,
+}
+
+impl Default for DepthOfField {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            focus_distance: 5.0,
+            f_number: 2.0,
+        }
+    }
+}
+
+/// Blurs the scene along the motion of the camera
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MotionBlur {
+    pub enabled: bool,
+    /// In degrees of the frame the shutter is open for, 360 blurs over the whole frame
+    pub shutter_angle: f32,
+}
+
+impl Default for MotionBlur {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            shutter_angle: 180.0,
+        }
+    }
+}
+
+/// The HDR10 metadata of the swap chain, in nits. Content light levels of 0 are unknown.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HdrMetadataSettings {
+    pub max_mastering_luminance: f32,
+    pub min_mastering_luminance: f32,
+    pub max_content_light_level: f32,
+    pub max_frame_average_light_level: f32,
+}
+
+impl Default for HdrMetadataSettings {
+    fn default() -> Self {
+        Self {
+            max_mastering_luminance: 1000.0,
+            min_mastering_luminance: 0.001,
+            max_content_light_level: 0.0,
+            max_frame_average_light_level: 0.0,
+        }
+    }
+}
+
+impl HdrMetadataSettings {
+    pub fn metadata(&self) -> HdrMetadata {
+        HdrMetadata {
+            max_content_light_level: self.max_content_light_level,
+            max_frame_average_light_level: self.max_frame_average_light_level,
+            ..HdrMetadata::rec2020(self.max_mastering_luminance, self.min_mastering_luminance)
+        }
+    }
+}
+
+/// Which part of the settings changed, so only the passes depending on it have to react
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingChange {
+    RenderScale,
+    Vsync,
+    Grid,
+    MemoryHud,
+    ProfilerGraphs,
+    ColorGrading,
+    AutoExposure,
+    DepthOfField,
+    MotionBlur,
+    HdrMetadata,
+}
+
+impl SettingChange {
+    pub const ALL: [SettingChange; 10] = [
+        SettingChange::RenderScale,
+        SettingChange::Vsync,
+        SettingChange::Grid,
+        SettingChange::MemoryHud,
+        SettingChange::ProfilerGraphs,
+        SettingChange::ColorGrading,
+        SettingChange::AutoExposure,
+        SettingChange::DepthOfField,
+        SettingChange::MotionBlur,
+        SettingChange::HdrMetadata,
+    ];
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            render_scale: None,
+            vsync: true,
+            show_grid: true,
+            show_memory_hud: false,
+            show_profiler_graphs: false,
+            color_grading: ColorGrading::default(),
+            auto_exposure: AutoExposure::default(),
+            depth_of_field: DepthOfField::default(),
+            motion_blur: MotionBlur::default(),
+            hdr_metadata: None,
+        }
+    }
+}
+
+impl Settings {
+    /// A missing file gives the defaults, so the first run does not need one
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => {
+                toml::from_str(&text).with_context(|| format!("Parsing {}", path.display()))
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err).with_context(|| format!("Reading {}", path.display())),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let text = toml::to_string_pretty(self)?;
+        std::fs::write(path, text).with_context(|| format!("Writing {}", path.display()))
+    }
+
+    /// Everything that differs from `previous`
+    pub fn changes(&self, previous: &Settings) -> Vec<SettingChange> {
+        let mut changes = vec![];
+        if self.render_scale != previous.render_scale {
+            changes.push(SettingChange::RenderScale);
+        }
+        if self.vsync != previous.vsync {
+            changes.push(SettingChange::Vsync);
+        }
+        if self.show_grid != previous.show_grid {
+            changes.push(SettingChange::Grid);
+        }
+        if self.show_memory_hud != previous.show_memory_hud {
+            changes.push(SettingChange::MemoryHud);
+        }
+        if self.show_profiler_graphs != previous.show_profiler_graphs {
+            changes.push(SettingChange::ProfilerGraphs);
+        }
+        if self.color_grading != previous.color_grading {
+            changes.push(SettingChange::ColorGrading);
+        }
+        if self.auto_exposure != previous.auto_exposure {
+            changes.push(SettingChange::AutoExposure);
+        }
+        if self.depth_of_field != previous.depth_of_field {
+            changes.push(SettingChange::DepthOfField);
+        }
+        if self.motion_blur != previous.motion_blur {
+            changes.push(SettingChange::MotionBlur);
+        }
+        if self.hdr_metadata != previous.hdr_metadata {
+            changes.push(SettingChange::HdrMetadata);
+        }
+
+        changes
+    }
+
+    /// `self` with whatever differs between `before` and `after` taken from `after`. Saves what
+    /// was changed while running, but not what the command line only overrode for the run.
+    pub fn with_changes(&self, before: &Settings, after: &Settings) -> Settings {
+        let mut settings = self.clone();
+        for change in after.changes(before) {
+            match change {
+                SettingChange::RenderScale => settings.render_scale = after.render_scale,
+                SettingChange::Vsync => settings.vsync = after.vsync,
+                SettingChange::Grid => settings.show_grid = after.show_grid,
+                SettingChange::MemoryHud => settings.show_memory_hud = after.show_memory_hud,
+                SettingChange::ProfilerGraphs => {
+                    settings.show_profiler_graphs = after.show_profiler_graphs
+                }
+                SettingChange::ColorGrading => settings.color_grading = after.color_grading,
+                SettingChange::AutoExposure => settings.auto_exposure = after.auto_exposure,
+                SettingChange::DepthOfField => settings.depth_of_field = after.depth_of_field,
+                SettingChange::MotionBlur => settings.motion_blur = after.motion_blur,
+                SettingChange::HdrMetadata => settings.hdr_metadata = after.hdr_metadata,
+            }
+        }
+
+        settings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_are_not_saved() {
+        let saved = Settings::default();
+        let mut overridden = saved.clone();
+        overridden.vsync = !saved.vsync;
+
+        let mut changed = overridden.clone();
+        changed.show_grid = !saved.show_grid;
+        changed.render_scale = Some(0.5);
+
+        let to_save = saved.with_changes(&overridden, &changed);
+        assert_eq!(to_save.vsync, saved.vsync);
+        assert_eq!(to_save.show_grid, changed.show_grid);
+        assert_eq!(to_save.render_scale, Some(0.5));
+        assert_eq!(
+            to_save.changes(&saved),
+            [SettingChange::RenderScale, SettingChange::Grid]
+        );
+    }
+
+    #[test]
+    fn settings_changed_back_keep_the_saved_value() {
+        let saved = Settings::default();
+        let mut overridden = saved.clone();
+        overridden.vsync = !saved.vsync;
+
+        let mut changed = overridden.clone();
+        changed.vsync = saved.vsync;
+
+        assert_eq!(saved.with_changes(&overridden, &changed), saved);
+    }
+}
@@ -0,0 +1,85 @@
+use std::path::Path;
+
+use anyhow::Result;
+use d3d12_utils::{hlsl_cbuffer, hlsl_struct, write_generated_header};
+
+// Also compiled into the build script, which writes the header for embedded shaders, so only
+// d3d12_utils, glam and anyhow can be used here
+
+hlsl_struct! {
+    #[derive(Debug, Clone, Copy)]
+    pub struct CameraConstantBuffer {
+        pub view: glam::Mat4,
+        pub projection: glam::Mat4,
+        // Lights culled for this view by light_culling.hlsl, NO_LIGHTS when there are none
+        pub light_buffer_index: u32,
+        pub cluster_light_counts_index: u32,
+        pub cluster_light_indices_index: u32,
+        pub slice_scale: f32,
+        pub cluster_counts: [u32; 3],
+        pub slice_bias: f32,
+        // Pixel rectangle of the view the clusters are spread over
+        pub region_offset: glam::Vec2,
+        pub region_size: glam::Vec2,
+        // World space, geometry on the negative side is clipped
+        pub clip_plane: glam::Vec4,
+    }
+}
+
+pub const NO_LIGHTS: u32 = u32::MAX;
+
+hlsl_struct! {
+    #[derive(Debug, Clone, Copy)]
+    pub struct MaterialConstantBuffer {
+        pub uv_offset: glam::Vec2,
+        pub uv_scale: glam::Vec2,
+        pub texture_index: u32,
+        pub feedback_index: u32,
+        pub min_lod: f32,
+        pub base_color_factor: glam::Vec4,
+        pub emissive_factor: glam::Vec3,
+        pub roughness: f32,
+        // Prefiltered cubemap of the nearest environment probe, NO_ENVIRONMENT when there is none
+        pub environment_index: u32,
+        pub environment_max_mip: f32,
+    }
+}
+
+pub const NO_FEEDBACK: u32 = u32::MAX;
+pub const NO_ENVIRONMENT: u32 = u32::MAX;
+
+hlsl_struct! {
+    #[derive(Debug, Clone, Copy)]
+    pub struct ModelConstantBuffer {
+        pub model: glam::Mat4,
+        // Object space culling inputs and meshlet buffer indices for the mesh shader path
+        pub frustum_planes: [glam::Vec4; 6],
+        pub camera_position: glam::Vec3,
+        pub meshlet_count: u32,
+        pub vertex_buffer_index: u32,
+        pub meshlet_buffer_index: u32,
+        pub meshlet_bounds_index: u32,
+        pub vertex_index_buffer_index: u32,
+        pub primitive_index_buffer_index: u32,
+    }
+}
+
+/// Declares the constant buffers of bindless_texture.hlsl, which includes the header
+pub fn write_shader_types_header(path: impl AsRef<Path>) -> Result<()> {
+    let header = format!(
+        "// Generated by write_shader_types_header in shader_types.rs, do not edit.\n\
+        #pragma once\n\n\
+        {}\nstatic const uint NO_LIGHTS = {:#X};\n\n\
+        {}\nstatic const uint NO_FEEDBACK = {:#X};\n\
+        static const uint NO_ENVIRONMENT = {:#X};\n\n\
+        {}",
+        hlsl_cbuffer::<CameraConstantBuffer>("Camera", 0),
+        NO_LIGHTS,
+        hlsl_cbuffer::<MaterialConstantBuffer>("Material", 1),
+        NO_FEEDBACK,
+        NO_ENVIRONMENT,
+        hlsl_cbuffer::<ModelConstantBuffer>("Model", 2),
+    );
+
+    write_generated_header(path.as_ref(), &header)
+}
@@ -0,0 +1,112 @@
+#include "renderer/src/shaders/generated/globals.hlsli"
+
+cbuffer Constants : register(b0) {
+    uint scene_index;
+    uint histogram_index;
+    uint exposure_index;
+    uint use_manual_exposure;
+    uint2 render_extent;
+    // Log2 luminances the histogram covers
+    float min_log_luminance;
+    float log_luminance_range;
+    // In stops
+    float min_exposure;
+    float max_exposure;
+    float manual_exposure;
+    // Per second
+    float speed_up;
+    float speed_down;
+    uint3 padding;
+}
+
+// Matches HISTOGRAM_BINS in auto_exposure_pass.rs, one thread per bin
+static const uint HISTOGRAM_BINS = 64;
+// What the average luminance is exposed to
+static const float MIDDLE_GREY = 0.18;
+// Rec. 709 luma weights
+static const float3 LUMA = float3(0.2126, 0.7152, 0.0722);
+
+groupshared uint local_bins[HISTOGRAM_BINS];
+groupshared float weighted_bins[HISTOGRAM_BINS];
+
+// Black pixels get bin 0 to themselves and are left out of the average
+uint Bin(float luminance)
+{
+    if (luminance < 1e-5)
+    {
+        return 0;
+    }
+
+    float position = saturate((log2(luminance) - min_log_luminance) / log_luminance_range);
+    return uint(position * (HISTOGRAM_BINS - 2) + 1.0);
+}
+
+[numthreads(8, 8, 1)]
+void Histogram(uint3 id : SV_DispatchThreadID, uint index : SV_GroupIndex)
+{
+    local_bins[index] = 0;
+    GroupMemoryBarrierWithGroupSync();
+
+    if (all(id.xy < render_extent))
+    {
+        Texture2D<float4> scene = ResourceDescriptorHeap[scene_index];
+        float luminance = dot(scene.Load(int3(id.xy, 0)).rgb, LUMA);
+        InterlockedAdd(local_bins[Bin(luminance)], 1);
+    }
+    GroupMemoryBarrierWithGroupSync();
+
+    RWStructuredBuffer<uint> histogram = ResourceDescriptorHeap[histogram_index];
+    InterlockedAdd(histogram[index], local_bins[index]);
+}
+
+[numthreads(HISTOGRAM_BINS, 1, 1)]
+void Adapt(uint index : SV_GroupIndex)
+{
+    RWStructuredBuffer<uint> histogram = ResourceDescriptorHeap[histogram_index];
+    RWStructuredBuffer<float> exposure = ResourceDescriptorHeap[exposure_index];
+
+    uint count = histogram[index];
+    weighted_bins[index] = float(count) * float(index);
+    // Empty for the next frame
+    histogram[index] = 0;
+    GroupMemoryBarrierWithGroupSync();
+
+    [unroll]
+    for (uint stride = HISTOGRAM_BINS / 2; stride > 0; stride >>= 1)
+    {
+        if (index < stride)
+        {
+            weighted_bins[index] += weighted_bins[index + stride];
+        }
+        GroupMemoryBarrierWithGroupSync();
+    }
+
+    if (index != 0)
+    {
+        return;
+    }
+
+    if (use_manual_exposure != 0)
+    {
+        exposure[0] = manual_exposure;
+        return;
+    }
+
+    float current = exposure[0];
+    // The thread of bin 0 holds the number of black pixels
+    float lit_pixels = float(render_extent.x * render_extent.y) - float(count);
+    if (lit_pixels < 1.0)
+    {
+        // Nothing to measure, so the exposure stays where it is
+        return;
+    }
+
+    float average_bin = weighted_bins[0] / lit_pixels;
+    float average_log_luminance =
+        (average_bin - 1.0) / (HISTOGRAM_BINS - 2) * log_luminance_range + min_log_luminance;
+    float target = clamp(log2(MIDDLE_GREY) - average_log_luminance, min_exposure, max_exposure);
+
+    float speed = target > current ? speed_up : speed_down;
+    // Frame rate independent, and frozen while paused
+    exposure[0] = current + (target - current) * (1.0 - exp(-globals.delta_time * speed));
+}
@@ -1,47 +1,244 @@
-cbuffer Camera : register(b0) {
-    float4x4 V;
-    float4x4 P;
-}
-
-cbuffer Material : register(b1) {
-    uint texture_index;
-}
-
-cbuffer Model : register(b2) {
-    float4x4 M;
-}
-
+#include "renderer/src/shaders/generated/packing.hlsli"
+#include "renderer/src/shaders/generated/shader_types.hlsli"
 
 SamplerState s1 : register(s0);
 
+// Below shader model 6.6 there is no ResourceDescriptorHeap. The material's texture is bound
+// through a descriptor table then, and the clustered lights and sampler feedback are left out.
+#if __SHADER_TARGET_MAJOR == 6 && __SHADER_TARGET_MINOR < 6
+#define DESCRIPTOR_TABLES
+Texture2D<float4> material_texture : register(t0);
+#endif
+
 struct PSInput
 {
     float4 position : SV_POSITION;
     float4 position_world : POSITION;
     float3 normal : NORMAL;
     float2 uv : TEXCOORD;
+    float clip_distance : SV_ClipDistance0;
 };
 
-PSInput VSMain(uint instance : SV_InstanceID, float3 position : POSITION, float3 normal : NORMAL, float2 uv : TEXCOORD)
+PSInput TransformVertex(float3 position, float3 normal, float2 uv)
 {
     PSInput result;
 
-    position -= float3(instance*2, 0, instance);
-
-    float4 pos_world = mul(M, float4(position, 1.0));
-    float4 pos_view = mul(V, pos_world);
+    float4 pos_world = mul(model, float4(position, 1.0));
+    float4 pos_view = mul(view, pos_world);
 
-    float3 normal_world = mul(M, float4(normal, 0.0)).xyz;
+    float3 normal_world = mul(model, float4(normal, 0.0)).xyz;
 
-    float4 pos_clip = mul(P, pos_view);
+    float4 pos_clip = mul(projection, pos_view);
     result.position = pos_clip;
     result.position_world = pos_world;
-    result.normal = normalize(mul(V, float4(normal_world, 0.0)).xyz); // Use 0.0 because normal is a bivector
-    result.uv = uv;
+    result.normal = normalize(mul(view, float4(normal_world, 0.0)).xyz); // Use 0.0 because normal is a bivector
+    result.uv = uv * uv_scale + uv_offset;
+    result.clip_distance = dot(clip_plane, pos_world);
 
     return result;
 }
 
+PSInput VSMain(uint instance : SV_InstanceID, float3 position : POSITION, float3 normal : NORMAL, float2 uv : TEXCOORD)
+{
+    position -= float3(instance*2, 0, instance);
+
+    return TransformVertex(position, normal, uv);
+}
+
+#ifndef DESCRIPTOR_TABLES
+struct Vertex
+{
+    float3 position;
+    float3 normal;
+    float2 uv;
+};
+
+struct Meshlet
+{
+    uint vertex_offset;
+    uint vertex_count;
+    uint triangle_offset;
+    uint triangle_count;
+};
+
+struct MeshletBounds
+{
+    float3 center;
+    float radius;
+    float3 cone_axis;
+    float cone_cutoff;
+};
+
+// Matches MAX_MESHLET_VERTICES and MAX_MESHLET_TRIANGLES in meshlet.rs
+static const uint MAX_MESHLET_VERTICES = 64;
+static const uint MAX_MESHLET_TRIANGLES = 126;
+static const uint MESHLETS_PER_GROUP = 32;
+
+struct Payload
+{
+    uint meshlet_indices[MESHLETS_PER_GROUP];
+};
+
+groupshared Payload visible_meshlets;
+groupshared uint visible_meshlet_count;
+
+bool IsMeshletVisible(MeshletBounds bounds)
+{
+    for (uint i = 0; i < 6; ++i)
+    {
+        if (dot(frustum_planes[i].xyz, bounds.center) + frustum_planes[i].w < -bounds.radius)
+        {
+            return false;
+        }
+    }
+
+    // Every triangle faces away from the camera
+    float3 to_center = bounds.center - camera_position;
+    return dot(to_center, bounds.cone_axis) < bounds.cone_cutoff * length(to_center) + bounds.radius;
+}
+
+// Counts through group shared memory, a group can span several waves when they are narrower than
+// MESHLETS_PER_GROUP
+[numthreads(MESHLETS_PER_GROUP, 1, 1)]
+void ASMain(uint meshlet_index : SV_DispatchThreadID, uint thread_index : SV_GroupThreadID)
+{
+    if (thread_index == 0)
+    {
+        visible_meshlet_count = 0;
+    }
+    GroupMemoryBarrierWithGroupSync();
+
+    bool visible = false;
+    if (meshlet_index < meshlet_count)
+    {
+        StructuredBuffer<MeshletBounds> bounds = ResourceDescriptorHeap[meshlet_bounds_index];
+        visible = IsMeshletVisible(bounds[meshlet_index]);
+    }
+
+    if (visible)
+    {
+        uint slot;
+        InterlockedAdd(visible_meshlet_count, 1, slot);
+        visible_meshlets.meshlet_indices[slot] = meshlet_index;
+    }
+    GroupMemoryBarrierWithGroupSync();
+
+    DispatchMesh(visible_meshlet_count, 1, 1, visible_meshlets);
+}
+
+[outputtopology("triangle")]
+[numthreads(128, 1, 1)]
+void MSMain(
+    uint thread_index : SV_GroupThreadID,
+    uint group_index : SV_GroupID,
+    in payload Payload payload,
+    out vertices PSInput out_vertices[MAX_MESHLET_VERTICES],
+    out indices uint3 out_triangles[MAX_MESHLET_TRIANGLES])
+{
+    StructuredBuffer<Meshlet> meshlets = ResourceDescriptorHeap[meshlet_buffer_index];
+    Meshlet meshlet = meshlets[payload.meshlet_indices[group_index]];
+
+    SetMeshOutputCounts(meshlet.vertex_count, meshlet.triangle_count);
+
+    if (thread_index < meshlet.vertex_count)
+    {
+        StructuredBuffer<uint> vertex_indices = ResourceDescriptorHeap[vertex_index_buffer_index];
+        StructuredBuffer<Vertex> vertex_buffer = ResourceDescriptorHeap[vertex_buffer_index];
+        Vertex vertex = vertex_buffer[vertex_indices[meshlet.vertex_offset + thread_index]];
+
+        out_vertices[thread_index] = TransformVertex(vertex.position, vertex.normal, vertex.uv);
+    }
+
+    if (thread_index < meshlet.triangle_count)
+    {
+        StructuredBuffer<uint> primitive_indices = ResourceDescriptorHeap[primitive_index_buffer_index];
+        uint primitive = primitive_indices[meshlet.triangle_offset + thread_index];
+
+        out_triangles[thread_index] = uint3(primitive & 0xFF, (primitive >> 8) & 0xFF, (primitive >> 16) & 0xFF);
+    }
+}
+#endif
+
+// Matches PackedPointLight in light_clustering.rs
+struct PackedPointLight
+{
+    float3 position;
+    uint radius_intensity;
+    uint color;
+};
+
+// Matches MAX_LIGHTS_PER_CLUSTER in light_clustering.rs
+static const uint MAX_LIGHTS_PER_CLUSTER = 64;
+
+// Diffuse light from the point lights of the cluster the pixel is in, found the same way as
+// ClusterGrid::cluster_at
+float3 ClusteredLighting(PSInput input)
+{
+#ifdef DESCRIPTOR_TABLES
+    return 0.0;
+#else
+    if (light_buffer_index == NO_LIGHTS)
+    {
+        return 0.0;
+    }
+
+    float view_depth = mul(view, input.position_world).z;
+    uint2 tile = min(uint2(max((input.position.xy - region_offset) / region_size, 0.0) * cluster_counts.xy), cluster_counts.xy - 1);
+    uint slice = min(uint(max(log(max(view_depth, 1e-6)) * slice_scale + slice_bias, 0.0)), cluster_counts.z - 1);
+    uint cluster = (slice * cluster_counts.y + tile.y) * cluster_counts.x + tile.x;
+
+    StructuredBuffer<PackedPointLight> lights = ResourceDescriptorHeap[light_buffer_index];
+    StructuredBuffer<uint> cluster_light_counts = ResourceDescriptorHeap[cluster_light_counts_index];
+    StructuredBuffer<uint> cluster_light_indices = ResourceDescriptorHeap[cluster_light_indices_index];
+
+    float3 total = 0.0;
+    uint count = cluster_light_counts[cluster];
+    for (uint i = 0; i < count; ++i)
+    {
+        PackedPointLight light = lights[cluster_light_indices[cluster * MAX_LIGHTS_PER_CLUSTER + i]];
+        float2 radius_intensity = UnpackHalf2(light.radius_intensity);
+
+        // The normal is in view space
+        float3 to_light = mul(view, float4(light.position - input.position_world.xyz, 0.0)).xyz;
+        float distance_to_light = length(to_light);
+        float falloff = saturate(1.0 - distance_to_light / radius_intensity.x);
+        float n_dot_l = saturate(dot(to_light / max(distance_to_light, 1e-4), input.normal));
+
+        total += UnpackRgba8(light.color).rgb * radius_intensity.y * falloff * falloff * n_dot_l;
+    }
+
+    return total;
+#endif
+}
+
+// Image based lighting from the nearest environment probe, its mips are prefiltered for
+// roughnesses from 0 at the top to 1 at the last one
+float3 EnvironmentLighting(PSInput input, float3 albedo)
+{
+#ifdef DESCRIPTOR_TABLES
+    return 0.0;
+#else
+    if (environment_index == NO_ENVIRONMENT)
+    {
+        return 0.0;
+    }
+
+    TextureCube<float4> environment = ResourceDescriptorHeap[environment_index];
+
+    // The normal is in view space, where the camera sits at the origin. The view only rotates and
+    // translates, so multiplying by its transpose takes directions back to world space.
+    float3 to_pixel = normalize(mul(view, input.position_world).xyz);
+    float3 reflected = mul(float4(reflect(to_pixel, input.normal), 0.0), view).xyz;
+    float3 normal_world = mul(float4(input.normal, 0.0), view).xyz;
+
+    float3 diffuse = environment.SampleLevel(s1, normal_world, environment_max_mip).rgb;
+    float3 specular = environment.SampleLevel(s1, reflected, roughness * environment_max_mip).rgb;
+    float fresnel = 0.04 + 0.96 * pow(1.0 - saturate(dot(-to_pixel, input.normal)), 5.0);
+
+    return diffuse * albedo + specular * fresnel * (1.0 - roughness);
+#endif
+}
+
 float4 PSMain(PSInput input) : SV_TARGET
 {
 
@@ -56,9 +253,22 @@ float4 PSMain(PSInput input) : SV_TARGET
     light_col *= (1 / (l_dist * l_dist));
 
 
+#ifdef DESCRIPTOR_TABLES
+    Texture2D<float4> tex = material_texture;
+#else
     Texture2D<float4> tex = ResourceDescriptorHeap[texture_index];
 
-    float4 colour = tex.Sample(s1, input.uv) * (float4(0.2,0.2,0.2,1.0) + (ldotn * light_col) / 3.14159); 
+    if (feedback_index != NO_FEEDBACK)
+    {
+        FeedbackTexture2D<SAMPLER_FEEDBACK_MIN_MIP> feedback = ResourceDescriptorHeap[feedback_index];
+        feedback.WriteSamplerFeedback(tex, s1, input.uv);
+    }
+#endif
+
+    float4 albedo = tex.Sample(s1, input.uv, int2(0, 0), min_lod) * base_color_factor;
+    float4 colour = albedo * (float4(0.2,0.2,0.2,1.0) + (ldotn * light_col + float4(ClusteredLighting(input), 0.0)) / 3.14159); 
+    colour.rgb += EnvironmentLighting(input, albedo.rgb);
+    colour.rgb += emissive_factor;
     //colour = clamp(colour, 0.0, 1.0);
 
     return colour;
@@ -0,0 +1,28 @@
+// Matches ProceduralTextureConstants in procedural_texture.rs
+cbuffer Constants : register(b0) {
+    uint destination_index;
+    uint3 size;
+    // x: squares along each side
+    float4 parameters;
+}
+
+static const uint THREAD_GROUP_SIZE = 8;
+
+// A UV test pattern: a checkerboard tinted by the texture coordinate, so mirrored or rotated
+// mappings stand out
+[numthreads(THREAD_GROUP_SIZE, THREAD_GROUP_SIZE, 1)]
+void CSMain(uint3 texel : SV_DispatchThreadID)
+{
+    if (any(texel.xy >= size.xy))
+    {
+        return;
+    }
+
+    RWTexture2D<float4> destination = ResourceDescriptorHeap[destination_index];
+
+    float2 uv = (texel.xy + 0.5) / size.xy;
+    uint2 square = uint2(uv * parameters.x);
+    float brightness = (square.x + square.y) % 2 == 0 ? 1.0 : 0.35;
+
+    destination[texel.xy] = float4(float3(uv, 1.0 - uv.x) * brightness, 1.0);
+}
@@ -0,0 +1,63 @@
+cbuffer Constants : register(b0) {
+    float3 domain_min;
+    uint source_index;
+    float3 domain_max;
+    uint lut_index;
+    float2 uv_scale;
+    float exposure_scale;
+    float lut_size;
+    float contrast;
+    float saturation;
+    // Of a buffer with stops to add, e.g. from auto exposure
+    uint exposure_index;
+    uint padding;
+}
+
+SamplerState linear_clamp : register(s0);
+
+static const uint NO_EXPOSURE = 0xffffffff;
+static const float MIDDLE_GREY = 0.5;
+// Rec. 709 luma weights
+static const float3 LUMA = float3(0.2126, 0.7152, 0.0722);
+
+struct PSInput
+{
+    float4 position : SV_POSITION;
+    float2 uv : TEXCOORD;
+};
+
+PSInput VSMain(uint vertex_id : SV_VertexID)
+{
+    PSInput result;
+
+    float2 uv = float2((vertex_id << 1) & 2, vertex_id & 2);
+    result.position = float4(uv * float2(2.0, -2.0) + float2(-1.0, 1.0), 0.0, 1.0);
+    result.uv = uv;
+
+    return result;
+}
+
+float4 PSMain(PSInput input) : SV_TARGET
+{
+    Texture2D<float4> scene = ResourceDescriptorHeap[source_index];
+    Texture3D<float4> lut = ResourceDescriptorHeap[lut_index];
+
+    // The viewport only covers the rendered part, which is the top left uv_scale of the scene
+    float4 colour = scene.Sample(linear_clamp, input.uv * uv_scale);
+
+    float3 graded = colour.rgb * exposure_scale;
+    if (exposure_index != NO_EXPOSURE)
+    {
+        StructuredBuffer<float> exposure = ResourceDescriptorHeap[exposure_index];
+        graded *= exp2(exposure[0]);
+    }
+    graded = (graded - MIDDLE_GREY) * contrast + MIDDLE_GREY;
+    graded = lerp(dot(graded, LUMA), graded, saturation);
+
+    // Texel centres, so the first and last texels map to the ends of the domain
+    float3 coordinates = saturate((graded - domain_min) / (domain_max - domain_min));
+    coordinates = coordinates * (lut_size - 1.0) / lut_size + 0.5 / lut_size;
+    graded = lut.SampleLevel(linear_clamp, coordinates, 0.0).rgb;
+
+    return float4(saturate(graded), colour.a);
+}
@@ -0,0 +1,55 @@
+#include "renderer/src/shaders/rotation.hlsli"
+
+cbuffer Constants : register(b0) {
+    uint overlay_index;
+    uint quarter_turns;
+}
+
+// Below shader model 6.6 there is no ResourceDescriptorHeap, the overlay is bound through a
+// descriptor table then
+#if __SHADER_TARGET_MAJOR == 6 && __SHADER_TARGET_MINOR < 6
+#define DESCRIPTOR_TABLES
+Texture2D<float4> overlay_texture : register(t0);
+#endif
+
+struct PSInput
+{
+    float4 position : SV_POSITION;
+};
+
+PSInput VSMain(uint vertex_id : SV_VertexID)
+{
+    PSInput result;
+
+    float2 uv = float2((vertex_id << 1) & 2, vertex_id & 2);
+    result.position = float4(uv * float2(2.0, -2.0) + float2(-1.0, 1.0), 0.0, 1.0);
+
+    return result;
+}
+
+float3 srgb_to_linear(float3 colour)
+{
+    return lerp(colour / 12.92, pow((colour + 0.055) / 1.055, 2.4), step(0.04045, colour));
+}
+
+// Written through an sRGB view, so it comes out linear and the hardware blends in linear space
+float4 PSMain(PSInput input) : SV_TARGET
+{
+#ifdef DESCRIPTOR_TABLES
+    Texture2D<float4> overlay = overlay_texture;
+#else
+    Texture2D<float4> overlay = ResourceDescriptorHeap[overlay_index];
+#endif
+
+    // Overlays are drawn with colours picked in sRGB and premultiplied alpha
+    uint2 overlay_size;
+    overlay.GetDimensions(overlay_size.x, overlay_size.y);
+    float4 colour = overlay.Load(int3(DesktopPixel(input.position.xy, overlay_size, quarter_turns), 0));
+    if (colour.a <= 0.0)
+    {
+        discard;
+    }
+
+    float3 straight = saturate(colour.rgb / colour.a);
+    return float4(srgb_to_linear(straight) * colour.a, colour.a);
+}
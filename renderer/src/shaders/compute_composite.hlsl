@@ -0,0 +1,59 @@
+#include "renderer/src/shaders/rotation.hlsli"
+#include "renderer/src/shaders/upscale_filter.hlsli"
+
+cbuffer Constants : register(b0) {
+    uint scene_index;
+    float sharpness;
+    float2 uv_scale;
+    float2 texel_size;
+    uint overlay_index;
+    uint destination_index;
+    uint2 destination_size;
+    uint quarter_turns;
+    uint padding;
+}
+
+SamplerState linear_clamp : register(s0);
+
+float3 srgb_to_linear(float3 colour)
+{
+    return lerp(colour / 12.92, pow((colour + 0.055) / 1.055, 2.4), step(0.04045, colour));
+}
+
+float3 linear_to_srgb(float3 colour)
+{
+    return lerp(colour * 12.92, 1.055 * pow(colour, 1.0 / 2.4) - 0.055, step(0.0031308, colour));
+}
+
+// The upscale and composite passes in one go, written straight to a back buffer UAV. UAVs can't
+// have sRGB formats, so the overlay is blended in linear space by hand.
+[numthreads(8, 8, 1)]
+void CSMain(uint3 id : SV_DispatchThreadID)
+{
+    if (any(id.xy >= destination_size))
+    {
+        return;
+    }
+
+    Texture2D<float4> scene = ResourceDescriptorHeap[scene_index];
+    Texture2D<float4> overlay = ResourceDescriptorHeap[overlay_index];
+    RWTexture2D<unorm float4> destination = ResourceDescriptorHeap[destination_index];
+
+    float2 uv = DesktopUv((id.xy + 0.5) / float2(destination_size), quarter_turns);
+    // Still in display encoding, like what the upscale pass writes through the UNORM view
+    float3 colour = SampleUpscaled(scene, linear_clamp, uv, uv_scale, texel_size, sharpness).rgb;
+
+    // Overlays are drawn with colours picked in sRGB and premultiplied alpha
+    uint2 overlay_size;
+    overlay.GetDimensions(overlay_size.x, overlay_size.y);
+    float4 overlay_colour = overlay.Load(int3(DesktopPixel(id.xy + 0.5, overlay_size, quarter_turns), 0));
+    if (overlay_colour.a > 0.0)
+    {
+        float3 straight = saturate(overlay_colour.rgb / overlay_colour.a);
+        float3 blended = srgb_to_linear(straight) * overlay_colour.a
+                       + srgb_to_linear(saturate(colour)) * (1.0 - overlay_colour.a);
+        colour = linear_to_srgb(blended);
+    }
+
+    destination[id.xy] = float4(colour, 1.0);
+}
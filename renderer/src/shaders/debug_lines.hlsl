@@ -0,0 +1,39 @@
+#include "renderer/src/shaders/generated/packing.hlsli"
+
+cbuffer Constants : register(b0) {
+    float4x4 view_projection;
+    uint vertex_buffer_index;
+    uint3 padding;
+}
+
+struct DebugLineVertex
+{
+    float3 position;
+    uint color;
+};
+
+struct PSInput
+{
+    float4 position : SV_POSITION;
+    float4 color : COLOR;
+};
+
+// Two vertices per line, pulled from a structured buffer over the whole vertex ring. The draw
+// starts at the frame's first vertex, which SV_VertexID includes.
+PSInput VSMain(uint vertex_id : SV_VertexID)
+{
+    StructuredBuffer<DebugLineVertex> vertices = ResourceDescriptorHeap[vertex_buffer_index];
+    DebugLineVertex vertex = vertices[vertex_id];
+
+    PSInput result;
+    result.position = mul(view_projection, float4(vertex.position, 1.0));
+    result.color = UnpackRgba8(vertex.color);
+
+    return result;
+}
+
+// The overlay is composited with premultiplied alpha
+float4 PSMain(PSInput input) : SV_TARGET
+{
+    return float4(input.color.rgb * input.color.a, input.color.a);
+}
@@ -0,0 +1,37 @@
+cbuffer Constants : register(b0) {
+    uint depth_index;
+    uint downsampled_index;
+    uint2 render_extent;
+    uint reversed_depth;
+}
+
+// One thread per downsampled texel, keeps the closest depth of the pixels it covers
+[numthreads(8, 8, 1)]
+void CSMain(uint3 texel : SV_DispatchThreadID)
+{
+    RWTexture2D<float> downsampled = ResourceDescriptorHeap[downsampled_index];
+    Texture2D<float> depth = ResourceDescriptorHeap[depth_index];
+
+    uint width, height;
+    downsampled.GetDimensions(width, height);
+    if (texel.x >= width || texel.y >= height)
+    {
+        return;
+    }
+
+    // The whole render extent maps to the downsampled texture, whatever the render scale
+    uint2 block_start = texel.xy * render_extent / uint2(width, height);
+    uint2 block_end = max((texel.xy + 1) * render_extent / uint2(width, height), block_start + 1);
+
+    float closest = reversed_depth ? 0.0 : 1.0;
+    for (uint y = block_start.y; y < block_end.y; ++y)
+    {
+        for (uint x = block_start.x; x < block_end.x; ++x)
+        {
+            float texel_depth = depth.Load(int3(x, y, 0));
+            closest = reversed_depth ? max(closest, texel_depth) : min(closest, texel_depth);
+        }
+    }
+
+    downsampled[texel.xy] = closest;
+}
@@ -0,0 +1,83 @@
+cbuffer Constants : register(b0) {
+    // Pixels of the view that is blurred, left, top, right and bottom
+    int4 region;
+    // Turns depth into view distance, (z, w) = (x * depth + y, z * depth + w)
+    float4 depth_to_view;
+    uint color_index;
+    uint depth_index;
+    uint output_index;
+    float focus_distance;
+    // Circle of confusion in pixels of a surface infinitely far away
+    float coc_scale;
+    uint3 padding;
+}
+
+// Largest circle of confusion radius in pixels, also how far samples are gathered from
+static const float MAX_COC = 12.0;
+static const float GOLDEN_ANGLE = 2.39996323;
+// Samples get sparser further out, one per this many pixels of circumference
+static const float RADIUS_STEP = 1.0;
+
+bool InRegion(int2 pixel)
+{
+    return all(pixel >= region.xy) && all(pixel < region.zw);
+}
+
+float ViewDistance(float depth)
+{
+    float2 zw = depth_to_view.xz * depth + depth_to_view.yw;
+
+    // Points at infinity with reversed depth have w = 0
+    return abs(zw.x) / max(abs(zw.y), 1e-7);
+}
+
+// Radius in pixels of the circle a point at `distance` blurs into
+float CircleOfConfusion(float distance)
+{
+    return min(abs(coc_scale * (1.0 - focus_distance / distance)), MAX_COC);
+}
+
+// Scatter as gather: every sample on a spiral around the pixel counts as far as its own circle
+// of confusion reaches the pixel. Samples behind the pixel can't blur over it much more than it is
+// blurred itself, which keeps backgrounds from bleeding over sharp foregrounds.
+[numthreads(8, 8, 1)]
+void Gather(uint3 id : SV_DispatchThreadID)
+{
+    Texture2D<float4> color = ResourceDescriptorHeap[color_index];
+    RWTexture2D<float4> output = ResourceDescriptorHeap[output_index];
+    int2 pixel = id.xy;
+    float4 center = color[pixel];
+    if (!InRegion(pixel))
+    {
+        output[pixel] = center;
+        return;
+    }
+
+    Texture2D<float> depth = ResourceDescriptorHeap[depth_index];
+    float center_distance = ViewDistance(depth[pixel]);
+    float center_size = CircleOfConfusion(center_distance);
+
+    float3 sum = center.rgb;
+    float count = 1.0;
+    float radius = RADIUS_STEP;
+    for (float angle = 0.0; radius < MAX_COC; angle += GOLDEN_ANGLE)
+    {
+        float2 offset = float2(cos(angle), sin(angle)) * radius;
+        int2 sample_pixel = clamp(int2(round(pixel + offset)), region.xy, region.zw - 1);
+
+        float sample_distance = ViewDistance(depth[sample_pixel]);
+        float sample_size = CircleOfConfusion(sample_distance);
+        if (sample_distance > center_distance)
+        {
+            sample_size = min(sample_size, center_size * 2.0);
+        }
+
+        // Samples not reaching the pixel stand in for the average so far
+        float coverage = smoothstep(radius - 0.5, radius + 0.5, sample_size);
+        sum += lerp(sum / count, color[sample_pixel].rgb, coverage);
+        count += 1.0;
+        radius += RADIUS_STEP / radius;
+    }
+
+    output[pixel] = float4(sum / count, center.a);
+}
@@ -0,0 +1,89 @@
+cbuffer Constants : register(b0) {
+    uint source_index;
+    uint destination_index;
+    uint size;
+    float roughness;
+}
+
+SamplerState linear_clamp : register(s0);
+
+static const uint SAMPLE_COUNT = 128;
+static const float PI = 3.14159265;
+
+// Same layout as cube_face_direction in cubemap.rs
+float3 CubeFaceDirection(uint face, float2 uv)
+{
+    float2 st = uv * 2.0 - 1.0;
+    switch (face)
+    {
+    case 0:
+        return float3(1.0, -st.y, -st.x);
+    case 1:
+        return float3(-1.0, -st.y, st.x);
+    case 2:
+        return float3(st.x, 1.0, st.y);
+    case 3:
+        return float3(st.x, -1.0, -st.y);
+    case 4:
+        return float3(st.x, -st.y, 1.0);
+    default:
+        return float3(-st.x, -st.y, -1.0);
+    }
+}
+
+float2 Hammersley(uint i)
+{
+    return float2(float(i) / float(SAMPLE_COUNT), float(reversebits(i)) * 2.3283064365386963e-10);
+}
+
+// Half vector around `normal` distributed like GGX with the given alpha
+float3 ImportanceSampleGGX(float2 xi, float3 normal, float alpha)
+{
+    float phi = 2.0 * PI * xi.x;
+    float cos_theta = sqrt((1.0 - xi.y) / (1.0 + (alpha * alpha - 1.0) * xi.y));
+    float sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+
+    float3 up = abs(normal.z) < 0.999 ? float3(0.0, 0.0, 1.0) : float3(1.0, 0.0, 0.0);
+    float3 tangent = normalize(cross(up, normal));
+    float3 bitangent = cross(normal, tangent);
+
+    return tangent * sin_theta * cos(phi) + bitangent * sin_theta * sin(phi) + normal * cos_theta;
+}
+
+// One thread per texel of one mip of every face. Each mip is the captured cube convolved with
+// GGX for its roughness, assuming the view and reflection directions match the normal.
+[numthreads(8, 8, 1)]
+void CSMain(uint3 texel : SV_DispatchThreadID)
+{
+    if (texel.x >= size || texel.y >= size)
+    {
+        return;
+    }
+
+    TextureCube<float4> source = ResourceDescriptorHeap[source_index];
+    RWTexture2DArray<float4> destination = ResourceDescriptorHeap[destination_index];
+
+    float3 normal = normalize(CubeFaceDirection(texel.z, (texel.xy + 0.5) / size));
+    if (roughness == 0.0)
+    {
+        destination[texel] = source.SampleLevel(linear_clamp, normal, 0.0);
+        return;
+    }
+
+    float alpha = roughness * roughness;
+    float4 total = 0.0;
+    float total_weight = 0.0;
+    for (uint i = 0; i < SAMPLE_COUNT; ++i)
+    {
+        float3 half_vector = ImportanceSampleGGX(Hammersley(i), normal, alpha);
+        float3 light = 2.0 * dot(normal, half_vector) * half_vector - normal;
+        float n_dot_l = dot(normal, light);
+        if (n_dot_l > 0.0)
+        {
+            total += source.SampleLevel(linear_clamp, light, 0.0) * n_dot_l;
+            total_weight += n_dot_l;
+        }
+    }
+
+    destination[texel] = total / max(total_weight, 1e-4);
+}
@@ -0,0 +1,120 @@
+cbuffer Constants : register(b0) {
+    float4x4 view_projection;
+    float4x4 inverse_view_projection;
+    float3 camera_position;
+    float cell_size;
+    float fade_distance;
+    float axis_length;
+    // Depth of the near plane, 1 with reversed depth
+    float near_depth;
+    uint padding;
+}
+
+// Every 10th line is drawn stronger
+static const float MAJOR_LINE_SPACING = 10.0;
+
+struct GridInput
+{
+    float4 position : SV_POSITION;
+    float2 clip : TEXCOORD;
+};
+
+struct GridOutput
+{
+    float4 color : SV_TARGET;
+    float depth : SV_DEPTH;
+};
+
+GridInput GridVSMain(uint vertex_id : SV_VertexID)
+{
+    GridInput result;
+
+    float2 uv = float2((vertex_id << 1) & 2, vertex_id & 2);
+    result.clip = uv * float2(2.0, -2.0) + float2(-1.0, 1.0);
+    result.position = float4(result.clip, 0.0, 1.0);
+
+    return result;
+}
+
+float3 Unproject(float2 clip, float depth)
+{
+    float4 world = mul(inverse_view_projection, float4(clip, depth, 1.0));
+
+    return world.xyz / world.w;
+}
+
+// 1 on a line, fading to 0 a pixel away from it
+float2 LineCoverage(float2 coordinate)
+{
+    float2 distance_to_line = abs(frac(coordinate - 0.5) - 0.5);
+
+    return 1.0 - saturate(distance_to_line / fwidth(coordinate));
+}
+
+// The ground plane at y = 0, intersected per pixel so it has no edges
+GridOutput GridPSMain(GridInput input)
+{
+    float3 near_point = Unproject(input.clip, near_depth);
+    float3 far_point = Unproject(input.clip, 1.0 - near_depth);
+    float t = near_point.y / (near_point.y - far_point.y);
+    if (!(t >= 0.0 && t <= 1.0))
+    {
+        discard;
+    }
+    float3 position = lerp(near_point, far_point, t);
+
+    float2 coordinate = position.xz / cell_size;
+    float2 minor = LineCoverage(coordinate);
+    float2 major = LineCoverage(coordinate / MAJOR_LINE_SPACING);
+    float4 color = float4(0.5, 0.5, 0.5, max(max(minor.x, minor.y) * 0.3, max(major.x, major.y) * 0.7));
+
+    // The world X axis runs along z = 0, the Z axis along x = 0
+    float2 axis_width = fwidth(coordinate);
+    if (abs(coordinate.x) < axis_width.x)
+    {
+        color = float4(0.2, 0.2, 1.0, 1.0);
+    }
+    if (abs(coordinate.y) < axis_width.y)
+    {
+        color = float4(1.0, 0.2, 0.2, 1.0);
+    }
+
+    color.a *= saturate(1.0 - distance(position, camera_position) / fade_distance);
+    if (color.a <= 0.0)
+    {
+        discard;
+    }
+
+    float4 clip = mul(view_projection, float4(position, 1.0));
+
+    GridOutput output;
+    output.color = color;
+    output.depth = clip.z / clip.w;
+
+    return output;
+}
+
+struct AxisInput
+{
+    float4 position : SV_POSITION;
+    float4 color : COLOR;
+};
+
+// Three lines from the origin along the positive X, Y and Z axes
+AxisInput AxisVSMain(uint vertex_id : SV_VertexID)
+{
+    uint axis = vertex_id / 2;
+    float3 direction = float3(axis == 0, axis == 1, axis == 2);
+    float3 position = direction * axis_length * (vertex_id & 1);
+
+    AxisInput result;
+    result.position = mul(view_projection, float4(position, 1.0));
+    result.color = float4(lerp(0.2, 1.0, direction), 1.0);
+
+    return result;
+}
+
+float4 AxisPSMain(AxisInput input) : SV_TARGET
+{
+    return input.color;
+}
@@ -0,0 +1,66 @@
+cbuffer Constants : register(b0) {
+    // The depth buffer for the top mip, otherwise the views of the mip above
+    uint source_min_index;
+    uint source_max_index;
+    uint destination_min_index;
+    uint destination_max_index;
+    uint2 source_size;
+    uint2 destination_size;
+}
+
+// The top mip has a texel per pixel. Texels outside the rendered part hold the whole depth range,
+// so they never hide anything whichever way round depth goes.
+[numthreads(8, 8, 1)]
+void CopyDepth(uint3 id : SV_DispatchThreadID)
+{
+    if (any(id.xy >= destination_size))
+    {
+        return;
+    }
+
+    RWTexture2D<float> min_depth = ResourceDescriptorHeap[destination_min_index];
+    RWTexture2D<float> max_depth = ResourceDescriptorHeap[destination_max_index];
+    if (any(id.xy >= source_size))
+    {
+        min_depth[id.xy] = 0.0;
+        max_depth[id.xy] = 1.0;
+        return;
+    }
+
+    Texture2D<float> depth = ResourceDescriptorHeap[source_min_index];
+    float value = depth[id.xy];
+    min_depth[id.xy] = value;
+    max_depth[id.xy] = value;
+}
+
+// Each texel covers the 2x2 texels above it, and the extra row and column next to them when the
+// mip above has an odd size, so no texel of it is left out
+[numthreads(8, 8, 1)]
+void Reduce(uint3 id : SV_DispatchThreadID)
+{
+    if (any(id.xy >= destination_size))
+    {
+        return;
+    }
+
+    RWTexture2D<float> source_min = ResourceDescriptorHeap[source_min_index];
+    RWTexture2D<float> source_max = ResourceDescriptorHeap[source_max_index];
+    uint2 first = id.xy * 2;
+    uint2 last = min(first + 1 + (source_size & 1) * (id.xy == destination_size - 1), source_size - 1);
+
+    float result_min = 1.0;
+    float result_max = 0.0;
+    for (uint y = first.y; y <= last.y; ++y)
+    {
+        for (uint x = first.x; x <= last.x; ++x)
+        {
+            result_min = min(result_min, source_min[uint2(x, y)]);
+            result_max = max(result_max, source_max[uint2(x, y)]);
+        }
+    }
+
+    RWTexture2D<float> min_depth = ResourceDescriptorHeap[destination_min_index];
+    RWTexture2D<float> max_depth = ResourceDescriptorHeap[destination_max_index];
+    min_depth[id.xy] = result_min;
+    max_depth[id.xy] = result_max;
+}
@@ -0,0 +1,102 @@
+#include "renderer/src/shaders/generated/packing.hlsli"
+
+cbuffer Constants : register(b0) {
+    float4x4 inverse_projection;
+    float4x4 view;
+    uint3 cluster_counts;
+    uint light_count;
+    uint light_buffer_index;
+    uint cluster_light_counts_index;
+    uint cluster_light_indices_index;
+    // Depth of the near plane, 1 with reversed depth
+    float near_depth;
+    // View space depths the slices are spread over
+    float slice_near;
+    float slice_far;
+    uint2 padding;
+}
+
+// Matches PackedPointLight in light_clustering.rs
+struct PackedPointLight
+{
+    float3 position;
+    uint radius_intensity;
+    uint color;
+};
+
+// Matches MAX_LIGHTS_PER_CLUSTER in light_clustering.rs
+static const uint MAX_LIGHTS_PER_CLUSTER = 64;
+
+float3 ViewPosition(float2 ndc, float depth)
+{
+    float4 position = mul(inverse_projection, float4(ndc, depth, 1.0));
+
+    return position.xyz / position.w;
+}
+
+// Point at view space depth `z` on the ray through `ndc`, for perspective and orthographic
+// projections alike
+float3 PointAtDepth(float2 ndc, float z)
+{
+    float3 near_point = ViewPosition(ndc, near_depth);
+    float3 far_point = ViewPosition(ndc, 1.0 - near_depth);
+
+    return lerp(near_point, far_point, (z - near_point.z) / (far_point.z - near_point.z));
+}
+
+// One thread per cluster, laid out like ClusterGrid::cluster_index. Every light is tested against
+// the view space box around the cluster.
+[numthreads(64, 1, 1)]
+void CSMain(uint cluster : SV_DispatchThreadID)
+{
+    if (cluster >= cluster_counts.x * cluster_counts.y * cluster_counts.z)
+    {
+        return;
+    }
+
+    uint tile_x = cluster % cluster_counts.x;
+    uint tile_y = (cluster / cluster_counts.x) % cluster_counts.y;
+    uint slice = cluster / (cluster_counts.x * cluster_counts.y);
+
+    // Same spacing as ClusterGrid::slice_bounds
+    float depth_ratio = slice_far / slice_near;
+    float front = slice_near * pow(depth_ratio, float(slice) / cluster_counts.z);
+    float back = slice_near * pow(depth_ratio, float(slice + 1) / cluster_counts.z);
+
+    // Tile rows go down the screen while NDC y goes up
+    float2 tile_min = float2(tile_x, tile_y) / float2(cluster_counts.xy);
+    float2 tile_max = float2(tile_x + 1, tile_y + 1) / float2(cluster_counts.xy);
+    float2 ndc_min = float2(tile_min.x * 2.0 - 1.0, 1.0 - tile_max.y * 2.0);
+    float2 ndc_max = float2(tile_max.x * 2.0 - 1.0, 1.0 - tile_min.y * 2.0);
+
+    float3 aabb_min = 1e30;
+    float3 aabb_max = -1e30;
+    for (uint corner = 0; corner < 4; ++corner)
+    {
+        float2 ndc = float2(corner & 1 ? ndc_max.x : ndc_min.x, corner & 2 ? ndc_max.y : ndc_min.y);
+        float3 front_point = PointAtDepth(ndc, front);
+        float3 back_point = PointAtDepth(ndc, back);
+        aabb_min = min(aabb_min, min(front_point, back_point));
+        aabb_max = max(aabb_max, max(front_point, back_point));
+    }
+
+    StructuredBuffer<PackedPointLight> lights = ResourceDescriptorHeap[light_buffer_index];
+    RWStructuredBuffer<uint> cluster_light_counts = ResourceDescriptorHeap[cluster_light_counts_index];
+    RWStructuredBuffer<uint> cluster_light_indices = ResourceDescriptorHeap[cluster_light_indices_index];
+
+    uint count = 0;
+    for (uint i = 0; i < light_count && count < MAX_LIGHTS_PER_CLUSTER; ++i)
+    {
+        PackedPointLight light = lights[i];
+        float radius = UnpackHalf2(light.radius_intensity).x;
+        float3 center = mul(view, float4(light.position, 1.0)).xyz;
+        float3 offset = clamp(center, aabb_min, aabb_max) - center;
+        if (dot(offset, offset) <= radius * radius)
+        {
+            cluster_light_indices[cluster * MAX_LIGHTS_PER_CLUSTER + count] = i;
+            ++count;
+        }
+    }
+
+    cluster_light_counts[cluster] = count;
+}
@@ -0,0 +1,60 @@
+#define MAX_BARS 10
+
+cbuffer Constants : register(b0) {
+    float2 target_size;
+    uint num_bars;
+    uint padding;
+    // x is how full a pool is, y the most it has ever been
+    float4 bars[MAX_BARS];
+}
+
+// In pixels from the top left of the target
+static const float2 ORIGIN = float2(16.0, 16.0);
+static const float2 BAR_SIZE = float2(200.0, 10.0);
+static const float BAR_GAP = 4.0;
+
+struct PSInput
+{
+    float4 position : SV_POSITION;
+    float2 uv : TEXCOORD;
+    nointerpolation uint bar : BAR;
+};
+
+// Two triangles per bar, one instance per bar
+PSInput VSMain(uint vertex_id : SV_VertexID, uint instance_id : SV_InstanceID)
+{
+    const float2 corners[6] = {
+        float2(0.0, 0.0), float2(1.0, 0.0), float2(0.0, 1.0),
+        float2(0.0, 1.0), float2(1.0, 0.0), float2(1.0, 1.0),
+    };
+    float2 corner = corners[vertex_id];
+    float2 pixel = ORIGIN + float2(0.0, instance_id * (BAR_SIZE.y + BAR_GAP)) + corner * BAR_SIZE;
+
+    PSInput result;
+    result.position = float4(pixel / target_size * float2(2.0, -2.0) + float2(-1.0, 1.0), 0.0, 1.0);
+    result.uv = corner;
+    result.bar = instance_id;
+
+    return result;
+}
+
+float4 PSMain(PSInput input) : SV_TARGET
+{
+    float usage = bars[input.bar].x;
+    float peak = bars[input.bar].y;
+
+    // A pixel wide marker at the high-water mark
+    if (abs(input.uv.x - peak) * BAR_SIZE.x < 1.0)
+    {
+        return float4(1.0, 1.0, 1.0, 1.0);
+    }
+
+    if (input.uv.x < usage)
+    {
+        // Green with room to spare, red when about to run out
+        float3 colour = lerp(float3(0.2, 0.8, 0.2), float3(0.9, 0.2, 0.1), smoothstep(0.5, 0.9, usage));
+        return float4(colour, 1.0);
+    }
+
+    return float4(0.1, 0.1, 0.1, 1.0);
+}
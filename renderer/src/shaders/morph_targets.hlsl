@@ -0,0 +1,61 @@
+cbuffer Constants : register(b0) {
+    // The whole vertex pool, the mesh's vertices start at base_vertex
+    uint vertices_index;
+    uint base_vertex;
+    // Every target's deltas one after the other
+    uint deltas_index;
+    uint weights_index;
+    // Of the mesh's weights in the frame's weights
+    uint first_weight;
+    uint num_targets;
+    uint num_vertices;
+    uint output_index;
+}
+
+// Matches ObjVertex
+struct Vertex
+{
+    float3 position;
+    float3 normal;
+    float2 uv;
+};
+
+// Matches MorphDelta
+struct MorphDelta
+{
+    float3 position;
+    float3 normal;
+};
+
+[numthreads(64, 1, 1)]
+void Blend(uint3 id : SV_DispatchThreadID)
+{
+    if (id.x >= num_vertices)
+    {
+        return;
+    }
+
+    StructuredBuffer<Vertex> vertices = ResourceDescriptorHeap[vertices_index];
+    StructuredBuffer<MorphDelta> deltas = ResourceDescriptorHeap[deltas_index];
+    StructuredBuffer<float> weights = ResourceDescriptorHeap[weights_index];
+    Vertex vertex = vertices[base_vertex + id.x];
+
+    for (uint target = 0; target < num_targets; ++target)
+    {
+        float weight = weights[first_weight + target];
+        // Most targets of most meshes are off at any one time
+        if (weight != 0.0)
+        {
+            MorphDelta delta = deltas[target * num_vertices + id.x];
+            vertex.position += delta.position * weight;
+            vertex.normal += delta.normal * weight;
+        }
+    }
+
+    // Normals added up from several targets are no longer unit length
+    float length_squared = dot(vertex.normal, vertex.normal);
+    vertex.normal = length_squared > 0.0 ? vertex.normal * rsqrt(length_squared) : 0.0;
+
+    RWStructuredBuffer<Vertex> output = ResourceDescriptorHeap[output_index];
+    output[id.x] = vertex;
+}
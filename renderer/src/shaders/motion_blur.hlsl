@@ -0,0 +1,208 @@
+#include "renderer/src/shaders/generated/globals.hlsli"
+
+cbuffer Constants : register(b0) {
+    // From the NDC of this frame to the clip space of the previous one
+    float4x4 reprojection;
+    // Pixels of the view that is blurred, left, top, right and bottom
+    int4 region;
+    // Turns depth into view distance, (z, w) = (x * depth + y, z * depth + w)
+    float4 depth_to_view;
+    uint color_index;
+    uint depth_index;
+    uint velocity_srv_index;
+    uint velocity_uav_index;
+    uint tile_max_srv_index;
+    uint tile_max_uav_index;
+    uint neighbor_max_srv_index;
+    uint neighbor_max_uav_index;
+    uint output_index;
+    // Fraction of the frame the shutter is open for
+    float velocity_scale;
+    uint2 tile_counts;
+}
+
+// Matches TILE_SIZE in motion_blur_pass.rs, also the longest blur in pixels
+static const uint TILE_SIZE = 16;
+static const uint SAMPLE_COUNT = 15;
+// Fraction of its distance a pixel can be behind another and still count as the same surface
+static const float SOFT_DEPTH_EXTENT = 0.02;
+
+bool InRegion(int2 pixel)
+{
+    return all(pixel >= region.xy) && all(pixel < region.zw);
+}
+
+float ViewDistance(float depth)
+{
+    float2 zw = depth_to_view.xz * depth + depth_to_view.yw;
+
+    return abs(zw.x / zw.y);
+}
+
+// Camera motion of each pixel over the open shutter, in pixels
+[numthreads(8, 8, 1)]
+void Velocity(uint3 id : SV_DispatchThreadID)
+{
+    RWTexture2D<float2> velocity = ResourceDescriptorHeap[velocity_uav_index];
+    int2 pixel = id.xy;
+    if (!InRegion(pixel))
+    {
+        velocity[pixel] = 0.0;
+        return;
+    }
+
+    Texture2D<float> depth = ResourceDescriptorHeap[depth_index];
+    float2 size = region.zw - region.xy;
+    float2 position = pixel + 0.5;
+    float2 uv = (position - region.xy) / size;
+    float2 ndc = uv * float2(2.0, -2.0) + float2(-1.0, 1.0);
+
+    float4 previous = mul(reprojection, float4(ndc, depth[pixel], 1.0));
+    if (previous.w <= 0.0)
+    {
+        // Was behind the camera
+        velocity[pixel] = 0.0;
+        return;
+    }
+    float2 previous_uv = previous.xy / previous.w * float2(0.5, -0.5) + 0.5;
+    float2 motion = (position - (previous_uv * size + region.xy)) * velocity_scale;
+
+    // Longer blurs than a tile would miss the neighbourhood the reconstruction searches
+    float length_pixels = length(motion);
+    velocity[pixel] = length_pixels > TILE_SIZE ? motion * (TILE_SIZE / length_pixels) : motion;
+}
+
+groupshared float2 longest[TILE_SIZE * TILE_SIZE];
+
+// The longest velocity in each tile
+[numthreads(TILE_SIZE, TILE_SIZE, 1)]
+void TileMax(uint3 id : SV_DispatchThreadID, uint3 tile : SV_GroupID, uint index : SV_GroupIndex)
+{
+    Texture2D<float2> velocity = ResourceDescriptorHeap[velocity_srv_index];
+    longest[index] = InRegion(id.xy) ? velocity[id.xy] : 0.0;
+    GroupMemoryBarrierWithGroupSync();
+
+    [unroll]
+    for (uint stride = TILE_SIZE * TILE_SIZE / 2; stride > 0; stride >>= 1)
+    {
+        if (index < stride && dot(longest[index + stride], longest[index + stride]) >
+            dot(longest[index], longest[index]))
+        {
+            longest[index] = longest[index + stride];
+        }
+        GroupMemoryBarrierWithGroupSync();
+    }
+
+    if (index == 0)
+    {
+        RWTexture2D<float2> tile_max = ResourceDescriptorHeap[tile_max_uav_index];
+        tile_max[tile.xy] = longest[0];
+    }
+}
+
+// The longest velocity around each tile, blurs reach at most one tile into their neighbours
+[numthreads(8, 8, 1)]
+void NeighborMax(uint3 id : SV_DispatchThreadID)
+{
+    if (any(id.xy >= tile_counts))
+    {
+        return;
+    }
+
+    Texture2D<float2> tile_max = ResourceDescriptorHeap[tile_max_srv_index];
+    float2 result = 0.0;
+    for (int y = -1; y <= 1; ++y)
+    {
+        for (int x = -1; x <= 1; ++x)
+        {
+            int2 tile = clamp(int2(id.xy) + int2(x, y), 0, int2(tile_counts) - 1);
+            float2 velocity = tile_max[tile];
+            if (dot(velocity, velocity) > dot(result, result))
+            {
+                result = velocity;
+            }
+        }
+    }
+
+    RWTexture2D<float2> neighbor_max = ResourceDescriptorHeap[neighbor_max_uav_index];
+    neighbor_max[id.xy] = result;
+}
+
+// 1 when `a` is in front of `b`, fading out over the soft depth extent
+float SoftDepthCompare(float a, float b)
+{
+    return saturate(1.0 - (a - b) / (SOFT_DEPTH_EXTENT * b));
+}
+
+// How much a pixel moving with `speed` pixels covers one `distance` away
+float Cone(float distance, float speed)
+{
+    return saturate(1.0 - distance / speed);
+}
+
+float Cylinder(float distance, float speed)
+{
+    return 1.0 - smoothstep(0.95 * speed, 1.05 * speed, distance);
+}
+
+float InterleavedGradientNoise(float2 position)
+{
+    return frac(52.9829189 * frac(dot(position, float2(0.06711056, 0.00583715))));
+}
+
+// McGuire et al.'s reconstruction filter, gathers along the dominant velocity of the
+// neighbourhood and weighs each sample by whether it moves over this pixel or this pixel over it
+[numthreads(8, 8, 1)]
+void Reconstruct(uint3 id : SV_DispatchThreadID)
+{
+    Texture2D<float4> color = ResourceDescriptorHeap[color_index];
+    RWTexture2D<float4> output = ResourceDescriptorHeap[output_index];
+    int2 pixel = id.xy;
+    float4 center = color[pixel];
+
+    Texture2D<float2> neighbor_max = ResourceDescriptorHeap[neighbor_max_srv_index];
+    float2 dominant = InRegion(pixel) ? neighbor_max[pixel / TILE_SIZE] : 0.0;
+    if (dot(dominant, dominant) < 0.25)
+    {
+        output[pixel] = center;
+        return;
+    }
+
+    Texture2D<float> depth = ResourceDescriptorHeap[depth_index];
+    Texture2D<float2> velocity = ResourceDescriptorHeap[velocity_srv_index];
+    float center_distance = ViewDistance(depth[pixel]);
+    float center_speed = max(length(velocity[pixel]), 0.5);
+
+    float total_weight = 1.0 / center_speed;
+    float4 sum = center * total_weight;
+    float jitter = InterleavedGradientNoise(float2(pixel) + globals.frame_number % 64) - 0.5;
+
+    for (uint i = 0; i < SAMPLE_COUNT; ++i)
+    {
+        if (i == SAMPLE_COUNT / 2)
+        {
+            // The centre is already in
+            continue;
+        }
+
+        float t = lerp(-1.0, 1.0, (i + jitter + 1.0) / (SAMPLE_COUNT + 1.0));
+        int2 sample_pixel = clamp(int2(round(pixel + dominant * t)), region.xy, region.zw - 1);
+        float distance = length(float2(sample_pixel - pixel));
+
+        float sample_distance = ViewDistance(depth[sample_pixel]);
+        float sample_speed = max(length(velocity[sample_pixel]), 0.5);
+        float foreground = SoftDepthCompare(sample_distance, center_distance);
+        float background = SoftDepthCompare(center_distance, sample_distance);
+
+        // The sample blurred over this pixel, this pixel blurred over the sample, or both moving
+        // together
+        float weight = foreground * Cone(distance, sample_speed) +
+            background * Cone(distance, center_speed) +
+            Cylinder(distance, sample_speed) * Cylinder(distance, center_speed) * 2.0;
+
+        total_weight += weight;
+        sum += color[sample_pixel] * weight;
+    }
+
+    output[pixel] = sum / total_weight;
+}
@@ -0,0 +1,141 @@
+cbuffer Constants : register(b0) {
+    // What the Hi-Z pyramid was seen from
+    float4x4 view_projection;
+    // Pixels of the top mip the view covers, offset and size
+    float4 region;
+    uint2 hi_z_size;
+    uint hi_z_min_index;
+    uint hi_z_max_index;
+    // 0 when there is no pyramid to test against
+    uint hi_z_mips;
+    uint objects_index;
+    uint arguments_index;
+    // Arguments of the first phase, only read by the second
+    uint first_phase_index;
+    uint first_slot;
+    uint draw_count;
+    uint reversed_depth;
+    uint second_phase;
+}
+
+struct CullObject
+{
+    float3 bounds_min;
+    // Indices, or thread groups for meshlets
+    uint count;
+    float3 bounds_max;
+    uint flags;
+};
+
+// Matches the flags in occlusion_culling_pass.rs
+static const uint MESHLETS = 1;
+static const uint UNBOUNDED = 2;
+// D3D12_DRAW_INDEXED_ARGUMENTS, meshlet draws use the first 3 for D3D12_DISPATCH_MESH_ARGUMENTS
+static const uint ARGUMENT_DWORDS = 5;
+
+// Boxes reaching behind the camera or wholly out of the view can't be tested, they are kept.
+// Otherwise the box is hidden when its nearest point is further than the furthest depth under it,
+// read from the mip where it covers at most 2x2 texels.
+bool IsOccluded(float3 bounds_min, float3 bounds_max)
+{
+    if (hi_z_mips == 0)
+    {
+        return false;
+    }
+
+    float2 ndc_min = 1e30;
+    float2 ndc_max = -1e30;
+    float depth_min = 1e30;
+    float depth_max = -1e30;
+    for (uint i = 0; i < 8; ++i)
+    {
+        float3 corner = lerp(bounds_min, bounds_max, float3(i & 1, (i >> 1) & 1, (i >> 2) & 1));
+        float4 clip = mul(view_projection, float4(corner, 1.0));
+        if (clip.w <= 1e-5)
+        {
+            return false;
+        }
+
+        float3 ndc = clip.xyz / clip.w;
+        ndc_min = min(ndc_min, ndc.xy);
+        ndc_max = max(ndc_max, ndc.xy);
+        depth_min = min(depth_min, ndc.z);
+        depth_max = max(depth_max, ndc.z);
+    }
+    if (any(ndc_max < -1.0) || any(ndc_min > 1.0))
+    {
+        return false;
+    }
+    // What lies outside can't hide anything or be seen
+    ndc_min = max(ndc_min, -1.0);
+    ndc_max = min(ndc_max, 1.0);
+
+    // Pixels have y going down
+    float2 pixel_min = region.xy + (float2(ndc_min.x, -ndc_max.y) * 0.5 + 0.5) * region.zw;
+    float2 pixel_max = region.xy + (float2(ndc_max.x, -ndc_min.y) * 0.5 + 0.5) * region.zw;
+    float2 size = max(pixel_max - pixel_min, 0.0);
+    uint mip = min((uint)ceil(log2(max(max(size.x, size.y), 1.0))), hi_z_mips - 1);
+
+    uint2 mip_size = max(hi_z_size >> mip, 1);
+    uint2 first = min((uint2)pixel_min >> mip, mip_size - 1);
+    uint2 last = min((uint2)pixel_max >> mip, mip_size - 1);
+
+    // Furthest is the largest depth, or the smallest with reversed depth
+    Texture2D<float> furthest_depth = ResourceDescriptorHeap[reversed_depth ? hi_z_min_index : hi_z_max_index];
+    float furthest = reversed_depth ? 1.0 : 0.0;
+    for (uint y = first.y; y <= last.y; ++y)
+    {
+        for (uint x = first.x; x <= last.x; ++x)
+        {
+            float depth = furthest_depth.Load(int3(x, y, mip));
+            furthest = reversed_depth ? min(furthest, depth) : max(furthest, depth);
+        }
+    }
+
+    return reversed_depth ? depth_max < furthest : depth_min > furthest;
+}
+
+bool WasDrawn(uint slot, uint flags)
+{
+    StructuredBuffer<uint> first_phase = ResourceDescriptorHeap[first_phase_index];
+    uint base = slot * ARGUMENT_DWORDS;
+
+    // Thread groups for meshlets, instances otherwise
+    return first_phase[base + ((flags & MESHLETS) ? 0 : 1)] != 0;
+}
+
+[numthreads(64, 1, 1)]
+void Cull(uint3 id : SV_DispatchThreadID)
+{
+    if (id.x >= draw_count)
+    {
+        return;
+    }
+
+    uint slot = first_slot + id.x;
+    StructuredBuffer<CullObject> objects = ResourceDescriptorHeap[objects_index];
+    CullObject object = objects[slot];
+    bool visible = (object.flags & UNBOUNDED) != 0 || !IsOccluded(object.bounds_min, object.bounds_max);
+    if (second_phase)
+    {
+        // The first phase drew the rest already
+        visible = visible && !WasDrawn(slot, object.flags);
+    }
+
+    RWStructuredBuffer<uint> arguments = ResourceDescriptorHeap[arguments_index];
+    uint base = slot * ARGUMENT_DWORDS;
+    if (object.flags & MESHLETS)
+    {
+        arguments[base] = visible ? object.count : 0;
+        arguments[base + 1] = 1;
+        arguments[base + 2] = 1;
+    }
+    else
+    {
+        arguments[base] = object.count;
+        arguments[base + 1] = visible ? 1 : 0;
+        arguments[base + 2] = 0;
+    }
+    arguments[base + 3] = 0;
+    arguments[base + 4] = 0;
+}
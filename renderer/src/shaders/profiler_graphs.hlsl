@@ -0,0 +1,162 @@
+#define MAX_GRAPHS 16
+
+cbuffer Constants : register(b0) {
+    float2 target_size;
+    // MAX_GRAPHS rings of history_length samples, all with their oldest at head
+    uint samples_index;
+    uint history_length;
+    uint head;
+    uint num_graphs;
+    // Milliseconds marked on the frame graph
+    float budget_ms;
+    uint padding;
+    // Milliseconds at the top of each graph, four to an element
+    float4 scales[MAX_GRAPHS / 4];
+}
+
+// In pixels, the graphs sit in the top right corner. The first is the frame time, the rest are
+// smaller sparklines of each pass.
+static const float MARGIN = 16.0;
+static const float2 FRAME_GRAPH_SIZE = float2(240.0, 64.0);
+static const float2 SPARKLINE_SIZE = float2(240.0, 20.0);
+static const float GRAPH_GAP = 4.0;
+
+// Seven segment digits for the latest sample, as ddd.dd
+static const float2 LABEL_ORIGIN = float2(3.0, 3.0);
+static const float2 DIGIT_SIZE = float2(5.0, 9.0);
+static const float DIGIT_ADVANCE = 7.0;
+// Bits a to g, clockwise from the top and the middle last
+static const uint DIGIT_SEGMENTS[10] = { 0x3F, 0x06, 0x5B, 0x4F, 0x66, 0x6D, 0x7D, 0x07, 0x7F, 0x6F };
+
+struct PSInput
+{
+    float4 position : SV_POSITION;
+    // Pixels from the top left of the graph
+    float2 pixel : PIXEL;
+    nointerpolation uint graph : GRAPH;
+};
+
+float2 GraphSize(uint graph)
+{
+    return graph == 0 ? FRAME_GRAPH_SIZE : SPARKLINE_SIZE;
+}
+
+float GraphScale(uint graph)
+{
+    return scales[graph / 4][graph % 4];
+}
+
+float Sample(uint graph, uint age)
+{
+    StructuredBuffer<float> samples = ResourceDescriptorHeap[samples_index];
+    return samples[graph * history_length + (head + age) % history_length];
+}
+
+// Two triangles per graph, one instance per graph
+PSInput VSMain(uint vertex_id : SV_VertexID, uint instance_id : SV_InstanceID)
+{
+    const float2 corners[6] = {
+        float2(0.0, 0.0), float2(1.0, 0.0), float2(0.0, 1.0),
+        float2(0.0, 1.0), float2(1.0, 0.0), float2(1.0, 1.0),
+    };
+    float2 size = GraphSize(instance_id);
+    float top = instance_id == 0
+        ? MARGIN
+        : MARGIN + FRAME_GRAPH_SIZE.y + GRAPH_GAP + (instance_id - 1) * (SPARKLINE_SIZE.y + GRAPH_GAP);
+    float2 origin = float2(target_size.x - MARGIN - size.x, top);
+    float2 pixel = corners[vertex_id] * size;
+
+    PSInput result;
+    result.position = float4((origin + pixel) / target_size * float2(2.0, -2.0) + float2(-1.0, 1.0), 0.0, 1.0);
+    result.pixel = pixel;
+    result.graph = instance_id;
+
+    return result;
+}
+
+bool DigitSegment(uint digit, float2 p)
+{
+    uint segments = DIGIT_SEGMENTS[digit];
+    bool left = p.x < 1.0;
+    bool right = p.x >= DIGIT_SIZE.x - 1.0;
+    bool upper = p.y < DIGIT_SIZE.y * 0.5;
+    bool lower = p.y >= DIGIT_SIZE.y * 0.5 - 1.0;
+
+    return ((segments & 0x01) && p.y < 1.0)
+        || ((segments & 0x02) && right && upper)
+        || ((segments & 0x04) && right && lower)
+        || ((segments & 0x08) && p.y >= DIGIT_SIZE.y - 1.0)
+        || ((segments & 0x10) && left && lower)
+        || ((segments & 0x20) && left && upper)
+        || ((segments & 0x40) && abs(p.y - (DIGIT_SIZE.y - 1.0) * 0.5) < 0.5);
+}
+
+bool Label(float2 pixel, float value)
+{
+    float2 p = pixel - LABEL_ORIGIN;
+    if (any(p < 0.0))
+    {
+        return false;
+    }
+    uint cell = (uint)(p.x / DIGIT_ADVANCE);
+    p.x -= cell * DIGIT_ADVANCE;
+    if (cell >= 6 || any(p >= DIGIT_SIZE))
+    {
+        return false;
+    }
+
+    // The decimal point
+    if (cell == 3)
+    {
+        return p.x < 1.0 && p.y >= DIGIT_SIZE.y - 1.0;
+    }
+
+    // Hundreds, tens and ones, then tenths and hundredths, as powers of ten of hundredths
+    uint hundredths = (uint)round(clamp(value, 0.0, 999.99) * 100.0);
+    uint place = cell < 3 ? 4 - cell : 5 - cell;
+    uint power = 1;
+    for (uint i = 0; i < place; ++i)
+    {
+        power *= 10;
+    }
+    // No leading zeros before the ones
+    if (cell < 2 && hundredths < power)
+    {
+        return false;
+    }
+
+    return DigitSegment((hundredths / power) % 10, p);
+}
+
+float4 PSMain(PSInput input) : SV_TARGET
+{
+    float2 size = GraphSize(input.graph);
+    float scale = GraphScale(input.graph);
+
+    if (Label(input.pixel, Sample(input.graph, history_length - 1)))
+    {
+        return float4(1.0, 1.0, 1.0, 1.0);
+    }
+
+    // Oldest on the left, newest on the right
+    uint age = min((uint)(input.pixel.x / size.x * history_length), history_length - 1);
+    float value = Sample(input.graph, age);
+    float height = (size.y - input.pixel.y) / size.y * scale;
+
+    // A pixel high line at the frame budget
+    if (input.graph == 0 && abs(height - budget_ms) * size.y / scale < 0.5)
+    {
+        return float4(0.9, 0.9, 0.3, 1.0);
+    }
+
+    // Premultiplied, the overlays are composited over the scene
+    if (height < value)
+    {
+        // Green within the budget, red past it. Sparklines go by how full they are.
+        float over = input.graph == 0 ? step(budget_ms, value) : smoothstep(0.5, 1.0, value / scale);
+        float3 colour = lerp(float3(0.2, 0.8, 0.2), float3(0.9, 0.2, 0.1), over);
+        return float4(colour, 1.0);
+    }
+
+    return float4(float3(0.05, 0.05, 0.05) * 0.75, 0.75);
+}
@@ -0,0 +1,26 @@
+#pragma once
+
+// The uv of the desktop image that ends up at native_uv of a back buffer that is turned
+// quarter_turns clockwise against the desktop, see DisplayRotation::desktop_uv
+float2 DesktopUv(float2 native_uv, uint quarter_turns)
+{
+    switch (quarter_turns & 3)
+    {
+    case 1:
+        return float2(native_uv.y, 1.0 - native_uv.x);
+    case 2:
+        return 1.0 - native_uv;
+    case 3:
+        return float2(1.0 - native_uv.y, native_uv.x);
+    default:
+        return native_uv;
+    }
+}
+
+// Pixel of a desktop oriented texture of desktop_size under a pixel of the turned back buffer
+uint2 DesktopPixel(float2 native_position, uint2 desktop_size, uint quarter_turns)
+{
+    float2 native_size = (quarter_turns & 1) ? desktop_size.yx : desktop_size;
+    float2 uv = DesktopUv(native_position / native_size, quarter_turns);
+    return min(uint2(uv * desktop_size), desktop_size - 1);
+}
@@ -0,0 +1,68 @@
+cbuffer Constants : register(b0) {
+    uint scene_index;
+    uint shading_rate_image_index;
+    uint tile_size;
+    uint coarsest_rate;
+    uint2 render_extent;
+    float contrast_threshold;
+}
+
+// D3D12_SHADING_RATE values
+static const uint SHADING_RATE_1X1 = 0x0;
+static const uint SHADING_RATE_2X2 = 0x5;
+
+float luminance(float3 colour)
+{
+    return dot(colour, float3(0.2126, 0.7152, 0.0722));
+}
+
+// One thread per shading rate tile, flat tiles of the last frame get shaded coarser
+[numthreads(8, 8, 1)]
+void CSMain(uint3 tile : SV_DispatchThreadID)
+{
+    RWTexture2D<uint> shading_rates = ResourceDescriptorHeap[shading_rate_image_index];
+    Texture2D<float4> scene = ResourceDescriptorHeap[scene_index];
+
+    uint width, height;
+    shading_rates.GetDimensions(width, height);
+    if (tile.x >= width || tile.y >= height)
+    {
+        return;
+    }
+
+    uint2 tile_origin = tile.xy * tile_size;
+    if (any(tile_origin >= render_extent))
+    {
+        shading_rates[tile.xy] = coarsest_rate;
+        return;
+    }
+
+    uint2 tile_end = min(tile_origin + tile_size, render_extent);
+
+    float min_luminance = 1e10;
+    float max_luminance = 0.0;
+    // Every other pixel is plenty to find edges
+    for (uint y = tile_origin.y; y < tile_end.y; y += 2)
+    {
+        for (uint x = tile_origin.x; x < tile_end.x; x += 2)
+        {
+            float l = luminance(scene.Load(int3(x, y, 0)).rgb);
+            min_luminance = min(min_luminance, l);
+            max_luminance = max(max_luminance, l);
+        }
+    }
+
+    float contrast = (max_luminance - min_luminance) / max(max_luminance, 1e-3);
+
+    uint rate = SHADING_RATE_1X1;
+    if (contrast < 0.25 * contrast_threshold)
+    {
+        rate = coarsest_rate;
+    }
+    else if (contrast < contrast_threshold)
+    {
+        rate = SHADING_RATE_2X2;
+    }
+
+    shading_rates[tile.xy] = rate;
+}
@@ -0,0 +1,21 @@
+cbuffer Constants : register(b0) {
+    uint source_index;
+    uint destination_index;
+}
+
+// Widens any texture to 32 bit float RGBA, so the CPU reads every format the same way
+[numthreads(8, 8, 1)]
+void CSMain(uint3 texel : SV_DispatchThreadID)
+{
+    Texture2D<float4> source = ResourceDescriptorHeap[source_index];
+    RWTexture2D<float4> destination = ResourceDescriptorHeap[destination_index];
+
+    uint width, height;
+    destination.GetDimensions(width, height);
+    if (texel.x >= width || texel.y >= height)
+    {
+        return;
+    }
+
+    destination[texel.xy] = source.Load(int3(texel.xy, 0));
+}
@@ -0,0 +1,47 @@
+#include "renderer/src/shaders/rotation.hlsli"
+#include "renderer/src/shaders/upscale_filter.hlsli"
+
+cbuffer Constants : register(b0) {
+    uint texture_index;
+    float sharpness;
+    float2 uv_scale;
+    float2 texel_size;
+    uint quarter_turns;
+}
+
+SamplerState linear_clamp : register(s0);
+
+// Below shader model 6.6 there is no ResourceDescriptorHeap, the scene is bound through a
+// descriptor table then
+#if __SHADER_TARGET_MAJOR == 6 && __SHADER_TARGET_MINOR < 6
+#define DESCRIPTOR_TABLES
+Texture2D<float4> scene_texture : register(t0);
+#endif
+
+struct PSInput
+{
+    float4 position : SV_POSITION;
+    float2 uv : TEXCOORD;
+};
+
+PSInput VSMain(uint vertex_id : SV_VertexID)
+{
+    PSInput result;
+
+    float2 uv = float2((vertex_id << 1) & 2, vertex_id & 2);
+    result.position = float4(uv * float2(2.0, -2.0) + float2(-1.0, 1.0), 0.0, 1.0);
+    result.uv = uv;
+
+    return result;
+}
+
+float4 PSMain(PSInput input) : SV_TARGET
+{
+#ifdef DESCRIPTOR_TABLES
+    Texture2D<float4> scene = scene_texture;
+#else
+    Texture2D<float4> scene = ResourceDescriptorHeap[texture_index];
+#endif
+
+    return SampleUpscaled(scene, linear_clamp, DesktopUv(input.uv, quarter_turns), uv_scale, texel_size, sharpness);
+}
@@ -0,0 +1,31 @@
+#pragma once
+
+// Samples the rendered top left uv_scale of a (possibly downscaled) scene at a uv of the whole
+// destination, sharpening when sharpness is above 0
+float4 SampleUpscaled(Texture2D<float4> scene, SamplerState linear_clamp, float2 destination_uv,
+                      float2 uv_scale, float2 texel_size, float sharpness)
+{
+    float2 max_uv = uv_scale - 0.5 * texel_size;
+    float2 uv = min(destination_uv * uv_scale, max_uv);
+
+    float4 colour = scene.SampleLevel(linear_clamp, uv, 0.0);
+
+    if (sharpness > 0.0)
+    {
+        // Contrast adaptive sharpening, a simplified take on FSR1's RCAS
+        float3 north = scene.SampleLevel(linear_clamp, min(uv - float2(0.0, texel_size.y), max_uv), 0.0).rgb;
+        float3 south = scene.SampleLevel(linear_clamp, min(uv + float2(0.0, texel_size.y), max_uv), 0.0).rgb;
+        float3 east = scene.SampleLevel(linear_clamp, min(uv + float2(texel_size.x, 0.0), max_uv), 0.0).rgb;
+        float3 west = scene.SampleLevel(linear_clamp, min(uv - float2(texel_size.x, 0.0), max_uv), 0.0).rgb;
+
+        float3 min_colour = min(colour.rgb, min(min(north, south), min(east, west)));
+        float3 max_colour = max(colour.rgb, max(max(north, south), max(east, west)));
+
+        float3 amplitude = sqrt(saturate(min(min_colour, 1.0 - max_colour) / max(max_colour, 1e-5)));
+        float3 weight = -amplitude * lerp(1.0 / 8.0, 1.0 / 5.0, saturate(sharpness));
+
+        colour.rgb = saturate((colour.rgb + (north + south + east + west) * weight) / (1.0 + 4.0 * weight));
+    }
+
+    return colour;
+}
@@ -0,0 +1,80 @@
+cbuffer Constants : register(b0) {
+    float4x4 view_projection;
+    float4x4 reflection_view_projection;
+    float3 camera_position;
+    float height;
+    float2 center;
+    float2 half_extent;
+    // Part of the reflection target the view's reflection was drawn into
+    float2 region_offset;
+    float2 region_size;
+    uint reflection_index;
+    uint normal_map_index;
+    float time;
+    float distortion;
+}
+
+SamplerState linear_clamp : register(s0);
+
+// World units one tile of the normal map covers, for the two layers of waves
+static const float2 WAVE_SCALES = float2(3.0, 1.3);
+static const float3 WATER_COLOUR = float3(0.02, 0.12, 0.16);
+// Reflectance of water looked at straight on
+static const float FRESNEL_F0 = 0.02;
+
+static const float2 CORNERS[6] = {
+    float2(-1.0, -1.0), float2(-1.0, 1.0), float2(1.0, 1.0),
+    float2(-1.0, -1.0), float2(1.0, 1.0), float2(1.0, -1.0),
+};
+
+struct PSInput
+{
+    float4 position : SV_POSITION;
+    float3 position_world : POSITION;
+};
+
+PSInput VSMain(uint vertex_id : SV_VertexID)
+{
+    float2 corner = center + CORNERS[vertex_id] * half_extent;
+
+    PSInput result;
+    result.position_world = float3(corner.x, height, corner.y);
+    result.position = mul(view_projection, float4(result.position_world, 1.0));
+
+    return result;
+}
+
+float3 SampleWaveNormal(Texture2D<float4> normal_map, float2 position, float scale, float2 direction)
+{
+    // The clamping sampler is made to repeat by hand
+    float2 uv = frac(position / scale + direction * time);
+    float3 normal = normal_map.Sample(linear_clamp, uv).xyz * 2.0 - 1.0;
+
+    // The normal map's z points up, out of the water
+    return float3(normal.x, normal.z, normal.y);
+}
+
+float4 PSMain(PSInput input) : SV_TARGET
+{
+    Texture2D<float4> normal_map = ResourceDescriptorHeap[normal_map_index];
+    Texture2D<float4> reflection = ResourceDescriptorHeap[reflection_index];
+
+    float2 position = input.position_world.xz;
+    float3 normal = normalize(
+        SampleWaveNormal(normal_map, position, WAVE_SCALES.x, float2(0.02, 0.01)) +
+        SampleWaveNormal(normal_map, position, WAVE_SCALES.y, float2(-0.015, 0.025)));
+
+    // The water lies in the mirror plane, so it projects to the same spot in the reflection
+    float4 reflection_clip = mul(reflection_view_projection, float4(input.position_world, 1.0));
+    float2 reflection_uv = reflection_clip.xy / reflection_clip.w * float2(0.5, -0.5) + 0.5;
+    reflection_uv = region_offset + (reflection_uv + normal.xz * distortion) * region_size;
+    reflection_uv = clamp(reflection_uv, region_offset, region_offset + region_size);
+    float3 reflected = reflection.Sample(linear_clamp, reflection_uv).rgb;
+
+    // Schlick's approximation, grazing angles reflect nearly everything
+    float3 to_camera = normalize(camera_position - input.position_world);
+    float cos_theta = saturate(abs(dot(normal, to_camera)));
+    float fresnel = FRESNEL_F0 + (1.0 - FRESNEL_F0) * pow(1.0 - cos_theta, 5.0);
+
+    return float4(lerp(WATER_COLOUR, reflected, fresnel), lerp(0.7, 1.0, fresnel));
+}
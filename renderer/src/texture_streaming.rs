@@ -0,0 +1,175 @@
+use std::sync::mpsc::Receiver;
+
+use anyhow::Result;
+use d3d12_utils::{
+    finest_requested_mip, BarrierBatcher, CommandQueue, DescriptorType, FenceWatcher, MipResidency,
+    SamplerFeedbackMap, TextureHandle,
+};
+use windows::Win32::Graphics::Direct3D12::ID3D12GraphicsCommandList;
+
+use crate::{object::Object, renderer::Resources};
+
+const FEEDBACK_MIP_REGION: u32 = 64;
+// Frames between two feedback resolves
+const RESOLVE_INTERVAL: u64 = 8;
+// Resolves a mip has to go unrequested before it gets evicted
+const EVICTION_DELAY: u32 = 4;
+
+#[derive(Debug)]
+struct StreamedTexture {
+    texture: TextureHandle,
+    feedback: SamplerFeedbackMap,
+    residency: MipResidency,
+    cleared: bool,
+}
+
+/// Decides which mips of a texture need to be resident from sampler feedback.
+/// There is no actual streaming of texture data yet, the decisions clamp the mips materials sample.
+#[derive(Debug)]
+pub struct TextureStreaming {
+    textures: Vec<StreamedTexture>,
+    frame: u64,
+    resolve_recorded: bool,
+    pending_resolve: Option<Receiver<u64>>,
+}
+
+impl TextureStreaming {
+    pub fn new() -> Self {
+        Self {
+            textures: Vec::new(),
+            frame: 0,
+            resolve_recorded: false,
+            pending_resolve: None,
+        }
+    }
+
+    /// Returns the index of the feedback map for `Material::feedback_index`
+    pub fn add(&mut self, resources: &mut Resources, texture: &TextureHandle) -> Result<u32> {
+        let texture_resource = resources.texture_manager.get_texture(texture)?;
+        let num_mips = texture_resource.info.num_mips as u8;
+        let feedback = SamplerFeedbackMap::new(
+            &resources.device,
+            &mut resources.descriptor_manager,
+            &texture_resource.get_resource()?.device_resource,
+            FEEDBACK_MIP_REGION,
+        )?;
+        let feedback_index = feedback.uav.index as u32;
+
+        self.textures.push(StreamedTexture {
+            texture: texture.clone(),
+            feedback,
+            residency: MipResidency::new(num_mips, EVICTION_DELAY),
+            cleared: false,
+        });
+
+        Ok(feedback_index)
+    }
+
+    /// Reads back the last resolve once the GPU is done with it and applies new residency
+    /// decisions to the materials sampling each texture. True if any material changed.
+    pub fn update(&mut self, objects: &mut [Object]) -> bool {
+        match &self.pending_resolve {
+            Some(resolve_done) if resolve_done.try_recv().is_ok() => {
+                self.pending_resolve = None;
+            }
+            _ => return false,
+        }
+
+        let mut changed = false;
+
+        for streamed in &mut self.textures {
+            let requested_mip = finest_requested_mip(&streamed.feedback.read());
+            if let Some(finest_mip) = streamed.residency.update(requested_mip) {
+                for object in objects
+                    .iter_mut()
+                    .filter(|object| object.material.texture.index == streamed.texture.index)
+                {
+                    object.material.min_lod = finest_mip as f32;
+                    changed = true;
+                }
+            }
+        }
+
+        changed
+    }
+
+    /// Clears feedback maps that were added since the last frame, before anything samples them
+    pub fn prepare(
+        &mut self,
+        command_list: &ID3D12GraphicsCommandList,
+        barriers: &mut BarrierBatcher,
+        resources: &Resources,
+    ) -> Result<()> {
+        if self.textures.iter().all(|streamed| streamed.cleared) {
+            return Ok(());
+        }
+
+        unsafe {
+            command_list.SetDescriptorHeaps(&[Some(
+                resources
+                    .descriptor_manager
+                    .get_heap(DescriptorType::Resource)?,
+            )]);
+        }
+        for streamed in self
+            .textures
+            .iter_mut()
+            .filter(|streamed| !streamed.cleared)
+        {
+            streamed
+                .feedback
+                .clear(command_list, barriers, &resources.descriptor_manager)?;
+            streamed.cleared = true;
+        }
+
+        Ok(())
+    }
+
+    /// Resolves the feedback written this frame every `RESOLVE_INTERVAL` frames, call once per
+    /// frame after all passes sampling the textures
+    pub fn resolve(
+        &mut self,
+        command_list: &ID3D12GraphicsCommandList,
+        barriers: &mut BarrierBatcher,
+        resources: &Resources,
+    ) -> Result<()> {
+        self.frame += 1;
+        if self.resolve_recorded
+            || self.pending_resolve.is_some()
+            || !self.frame.is_multiple_of(RESOLVE_INTERVAL)
+        {
+            return Ok(());
+        }
+
+        unsafe {
+            command_list.SetDescriptorHeaps(&[Some(
+                resources
+                    .descriptor_manager
+                    .get_heap(DescriptorType::Resource)?,
+            )]);
+        }
+        for streamed in &self.textures {
+            streamed
+                .feedback
+                .resolve(command_list, barriers, &resources.descriptor_manager)?;
+        }
+        self.resolve_recorded = true;
+
+        Ok(())
+    }
+
+    /// Call with the fence value of the command list `resolve` was recorded into
+    pub fn submitted(
+        &mut self,
+        fence_watcher: &FenceWatcher,
+        queue: &CommandQueue,
+        fence_value: u64,
+    ) -> Result<()> {
+        if self.resolve_recorded {
+            self.pending_resolve = Some(fence_watcher.completion_channel(queue, fence_value)?);
+            self.resolve_recorded = false;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,198 @@
+use anyhow::{ensure, Result};
+use glam::{Mat4, Quat, Vec3};
+
+use crate::object::Object;
+
+/// World transforms of all objects in the scene, built once per frame and shared by every pass
+/// drawing the scene. Frames fall between simulation steps, so the transforms are blended from
+/// where the objects were before the last step.
+#[derive(Debug, Default)]
+pub struct TransformCache {
+    world_transforms: Vec<Mat4>,
+    // Local position and rotation of every object before the last simulation step
+    previous_poses: Vec<(Vec3, Quat)>,
+}
+
+impl TransformCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call before every simulation step
+    pub fn begin_step(&mut self, objects: &[Object]) {
+        self.previous_poses = objects
+            .iter()
+            .map(|object| (object.position, object.rotation))
+            .collect();
+    }
+
+    /// `alpha` goes from the poses before the last simulation step at 0 to the current ones at 1.
+    /// Parents are transformed before their children, so every world transform is computed once.
+    pub fn build(&mut self, objects: &[Object], alpha: f32) -> Result<()> {
+        // Objects were added or removed since the last step
+        if self.previous_poses.len() != objects.len() {
+            self.previous_poses.clear();
+        }
+
+        let mut world_transforms: Vec<Option<Mat4>> = vec![None; objects.len()];
+        // Objects between the one being built and the first ancestor that is done, child first
+        let mut chain = Vec::new();
+        let mut on_chain = vec![false; objects.len()];
+
+        for index in 0..objects.len() {
+            let mut next = Some(index);
+            let mut parent_transform = Mat4::IDENTITY;
+            while let Some(current) = next {
+                if let Some(transform) = world_transforms[current] {
+                    parent_transform = transform;
+                    break;
+                }
+                ensure!(
+                    !on_chain[current],
+                    "Object {} has a cycle in its parents",
+                    index
+                );
+                on_chain[current] = true;
+                chain.push(current);
+
+                next = objects[current].parent;
+                if let Some(parent) = next {
+                    ensure!(
+                        parent < objects.len(),
+                        "Parent {} of object {} does not exist",
+                        parent,
+                        current
+                    );
+                }
+            }
+
+            let mut transform = parent_transform;
+            for current in chain.drain(..).rev() {
+                transform *= self.local_transform(objects, current, alpha);
+                world_transforms[current] = Some(transform);
+                on_chain[current] = false;
+            }
+        }
+
+        self.world_transforms = world_transforms.into_iter().flatten().collect();
+
+        Ok(())
+    }
+
+    /// Moves an object straight to its current pose, e.g. after it was dragged somewhere
+    pub fn skip_interpolation(&mut self, index: usize, object: &Object) {
+        if let Some(pose) = self.previous_poses.get_mut(index) {
+            *pose = (object.position, object.rotation);
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Mat4> {
+        self.world_transforms.get(index)
+    }
+
+    /// Pairs the objects the cache was built from with their world transforms
+    pub fn iter<'a>(
+        &'a self,
+        objects: &'a [Object],
+    ) -> impl Iterator<Item = (&'a Object, &'a Mat4)> {
+        objects.iter().zip(self.world_transforms.iter())
+    }
+
+    /// Like `iter`, but only for the objects at `indices`
+    pub fn select<'a>(
+        &'a self,
+        objects: &'a [Object],
+        indices: &'a [usize],
+    ) -> impl Iterator<Item = (&'a Object, &'a Mat4)> {
+        indices
+            .iter()
+            .map(|index| (&objects[*index], &self.world_transforms[*index]))
+    }
+
+    fn local_transform(&self, objects: &[Object], index: usize, alpha: f32) -> Mat4 {
+        let object = &objects[index];
+        match self.previous_poses.get(index) {
+            // Nothing in a simulation step scales objects
+            Some(&(position, rotation)) => Mat4::from_scale_rotation_translation(
+                object.scale,
+                rotation.slerp(object.rotation, alpha),
+                position.lerp(object.position, alpha),
+            ),
+            None => object.local_transform(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use d3d12_utils::{MeshHandle, TextureHandle};
+
+    use super::*;
+    use crate::material::Material;
+
+    fn object(position: Vec3, parent: Option<usize>) -> Object {
+        Object {
+            position,
+            rotation: Quat::IDENTITY,
+            scale: Vec3::ONE,
+            parent,
+            material: Material::from_texture(TextureHandle::default()),
+            mesh: MeshHandle::default(),
+            lod: 0,
+            spin: 0.0,
+            morph_weights: vec![],
+        }
+    }
+
+    fn translation(cache: &TransformCache, index: usize) -> Vec3 {
+        cache.get(index).unwrap().w_axis.truncate()
+    }
+
+    #[test]
+    fn children_are_moved_by_their_parents() {
+        // Listed before their parents, which doesn't matter
+        let objects = [
+            object(Vec3::Z, Some(1)),
+            object(Vec3::Y, Some(2)),
+            object(Vec3::X, None),
+        ];
+        let mut cache = TransformCache::new();
+        cache.build(&objects, 1.0).unwrap();
+
+        assert_eq!(translation(&cache, 0), Vec3::ONE);
+        assert_eq!(translation(&cache, 1), Vec3::new(1.0, 1.0, 0.0));
+        assert_eq!(translation(&cache, 2), Vec3::X);
+    }
+
+    #[test]
+    fn parents_are_blended_too() {
+        let mut objects = [object(Vec3::ZERO, None), object(Vec3::X, Some(0))];
+        let mut cache = TransformCache::new();
+        cache.begin_step(&objects);
+        objects[0].position = Vec3::new(0.0, 2.0, 0.0);
+        cache.build(&objects, 0.5).unwrap();
+
+        assert_eq!(translation(&cache, 1), Vec3::new(1.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn cycles_are_errors() {
+        let objects = [
+            object(Vec3::ZERO, None),
+            object(Vec3::ZERO, Some(2)),
+            object(Vec3::ZERO, Some(1)),
+        ];
+        let error = TransformCache::new().build(&objects, 1.0).unwrap_err();
+        assert_eq!(error.to_string(), "Object 1 has a cycle in its parents");
+        assert!(TransformCache::new()
+            .build(&[object(Vec3::ZERO, Some(0))], 1.0)
+            .is_err());
+    }
+
+    #[test]
+    fn missing_parents_are_errors() {
+        let objects = [object(Vec3::ZERO, None), object(Vec3::ZERO, Some(5))];
+        let error = TransformCache::new().build(&objects, 1.0).unwrap_err();
+        assert_eq!(error.to_string(), "Parent 5 of object 1 does not exist");
+    }
+}
@@ -0,0 +1,356 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{ensure, Context, Result};
+use d3d12_utils::{CapturedFrame, FrameReadback};
+use ddsfile::{AlphaMode, D3D10ResourceDimension, Dds, DxgiFormat, NewDxgiParams};
+use windows::{
+    core::HSTRING,
+    Win32::{
+        Graphics::{
+            Direct3D12::{
+                ID3D12Device4, ID3D12GraphicsCommandList, ID3D12Resource, D3D12_RESOURCE_STATES,
+            },
+            Dxgi::Common::DXGI_FORMAT_R8G8B8A8_UNORM,
+        },
+        Media::MediaFoundation::*,
+    },
+};
+
+const MF_VERSION: u32 = (MF_SDK_VERSION << 16) | MF_API_VERSION;
+const BITRATE: u32 = 20_000_000;
+// Media Foundation timestamps are in 100ns units
+const TIMESTAMPS_PER_SECOND: i64 = 10_000_000;
+
+/// Writes frames to an H.264 MP4 through the Media Foundation sink writer.
+/// Frames are timestamped by their index, not by when they were rendered, so a recording always
+/// plays back at `frames_per_second`.
+#[derive(Debug)]
+pub struct VideoRecorder {
+    sink_writer: IMFSinkWriter,
+    stream_index: u32,
+    extent: (u32, u32),
+    frame_duration: i64,
+    frames_written: i64,
+    // Declared last so it shuts Media Foundation down after the sink writer is released
+    _media_foundation: MediaFoundation,
+}
+
+/// Balances a successful `MFStartup` with `MFShutdown` when dropped
+#[derive(Debug)]
+struct MediaFoundation;
+
+impl MediaFoundation {
+    fn startup() -> Result<Self> {
+        unsafe { MFStartup(MF_VERSION, MFSTARTUP_FULL) }?;
+
+        Ok(Self)
+    }
+}
+
+impl Drop for MediaFoundation {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = MFShutdown();
+        }
+    }
+}
+
+impl VideoRecorder {
+    pub fn new(path: &Path, extent: (u32, u32), frames_per_second: u32) -> Result<Self> {
+        let (width, height) = extent;
+        ensure!(
+            width % 2 == 0 && height % 2 == 0,
+            "H.264 needs an even width and height, got {}x{}",
+            width,
+            height
+        );
+
+        let path = HSTRING::from(
+            path.to_str()
+                .context("Recording path is not valid unicode")?,
+        );
+
+        // Shuts down again if anything below fails
+        let media_foundation = MediaFoundation::startup()?;
+
+        let sink_writer = unsafe {
+            let mut attributes: Option<IMFAttributes> = None;
+            MFCreateAttributes(&mut attributes, 1)?;
+            let attributes = attributes.unwrap();
+            attributes.SetUINT32(&MF_READWRITE_ENABLE_HARDWARE_TRANSFORMS, 1)?;
+
+            MFCreateSinkWriterFromURL(&path, None, &attributes)?
+        };
+
+        let frame_size = ((width as u64) << 32) | height as u64;
+        let frame_rate = ((frames_per_second as u64) << 32) | 1;
+        let pixel_aspect_ratio = (1 << 32) | 1;
+
+        let stream_index = unsafe {
+            let output_type = MFCreateMediaType()?;
+            output_type.SetGUID(&MF_MT_MAJOR_TYPE, &MFMediaType_Video)?;
+            output_type.SetGUID(&MF_MT_SUBTYPE, &MFVideoFormat_H264)?;
+            output_type.SetUINT32(&MF_MT_AVG_BITRATE, BITRATE)?;
+            output_type.SetUINT32(&MF_MT_INTERLACE_MODE, MFVideoInterlace_Progressive.0 as u32)?;
+            output_type.SetUINT64(&MF_MT_FRAME_SIZE, frame_size)?;
+            output_type.SetUINT64(&MF_MT_FRAME_RATE, frame_rate)?;
+            output_type.SetUINT64(&MF_MT_PIXEL_ASPECT_RATIO, pixel_aspect_ratio)?;
+            let stream_index = sink_writer.AddStream(&output_type)?;
+
+            let input_type = MFCreateMediaType()?;
+            input_type.SetGUID(&MF_MT_MAJOR_TYPE, &MFMediaType_Video)?;
+            input_type.SetGUID(&MF_MT_SUBTYPE, &MFVideoFormat_RGB32)?;
+            input_type.SetUINT32(&MF_MT_INTERLACE_MODE, MFVideoInterlace_Progressive.0 as u32)?;
+            // A positive stride means top-down rows
+            input_type.SetUINT32(&MF_MT_DEFAULT_STRIDE, width * 4)?;
+            input_type.SetUINT64(&MF_MT_FRAME_SIZE, frame_size)?;
+            input_type.SetUINT64(&MF_MT_FRAME_RATE, frame_rate)?;
+            input_type.SetUINT64(&MF_MT_PIXEL_ASPECT_RATIO, pixel_aspect_ratio)?;
+            sink_writer.SetInputMediaType(stream_index, &input_type, None)?;
+
+            sink_writer.BeginWriting()?;
+
+            stream_index
+        };
+
+        Ok(Self {
+            sink_writer,
+            stream_index,
+            extent,
+            frame_duration: TIMESTAMPS_PER_SECOND / frames_per_second as i64,
+            frames_written: 0,
+            _media_foundation: media_foundation,
+        })
+    }
+
+    /// `frame` has to be RGBA8 in the recording's extent
+    pub fn write_frame(&mut self, frame: &CapturedFrame) -> Result<()> {
+        let (width, height) = self.extent;
+        ensure!(
+            (frame.width, frame.height) == self.extent,
+            "Frame is {}x{}, the recording is {}x{}",
+            frame.width,
+            frame.height,
+            width,
+            height
+        );
+
+        let row_bytes = width as usize * 4;
+        let size = row_bytes * height as usize;
+
+        unsafe {
+            let buffer = MFCreateMemoryBuffer(size as u32)?;
+            let mut data = std::ptr::null_mut();
+            buffer.Lock(&mut data, std::ptr::null_mut(), std::ptr::null_mut())?;
+            let pixels = std::slice::from_raw_parts_mut(data, size);
+            for (row, source) in pixels
+                .chunks_exact_mut(row_bytes)
+                .zip(frame.data.chunks(frame.row_pitch))
+            {
+                // RGB32 is BGRX
+                for (pixel, source) in row.chunks_exact_mut(4).zip(source.chunks_exact(4)) {
+                    pixel.copy_from_slice(&[source[2], source[1], source[0], 0xFF]);
+                }
+            }
+            buffer.Unlock()?;
+            buffer.SetCurrentLength(size as u32)?;
+
+            let sample = MFCreateSample()?;
+            sample.AddBuffer(&buffer)?;
+            sample.SetSampleTime(self.frames_written * self.frame_duration)?;
+            sample.SetSampleDuration(self.frame_duration)?;
+            self.sink_writer.WriteSample(self.stream_index, &sample)?;
+        }
+        self.frames_written += 1;
+
+        Ok(())
+    }
+
+    /// Writes out the MP4, it is not playable before this
+    pub fn finish(self) -> Result<()> {
+        unsafe { self.sink_writer.Finalize() }?;
+
+        Ok(())
+    }
+}
+
+/// Copies the presented back buffer of every frame to the CPU and encodes it once the GPU is done
+/// with the frame
+#[derive(Debug)]
+pub struct Recording {
+    recorder: VideoRecorder,
+    readback: FrameReadback,
+}
+
+impl Recording {
+    pub fn new(
+        device: &ID3D12Device4,
+        back_buffer: &ID3D12Resource,
+        num_frames: usize,
+        path: &Path,
+        frames_per_second: u32,
+    ) -> Result<Self> {
+        let desc = unsafe { back_buffer.GetDesc() };
+        // The encoder takes the bytes of each frame in this order
+        ensure!(
+            desc.Format == DXGI_FORMAT_R8G8B8A8_UNORM,
+            "Only R8G8B8A8 back buffers can be recorded, got {:?}",
+            desc.Format
+        );
+
+        Ok(Self {
+            recorder: VideoRecorder::new(
+                path,
+                (desc.Width as u32, desc.Height),
+                frames_per_second,
+            )?,
+            readback: FrameReadback::new(device, &desc, num_frames)?,
+        })
+    }
+
+    /// Call once the fence of `frame_index` has been waited on
+    pub fn write_completed_frame(&mut self, frame_index: usize) -> Result<()> {
+        if let Some(frame) = self.readback.take(frame_index) {
+            self.recorder.write_frame(&frame)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn capture(
+        &mut self,
+        command_list: &ID3D12GraphicsCommandList,
+        back_buffer: &ID3D12Resource,
+        back_buffer_state: D3D12_RESOURCE_STATES,
+        frame_index: usize,
+    ) -> Result<()> {
+        self.readback
+            .copy(command_list, back_buffer, back_buffer_state, frame_index)
+    }
+
+    /// The GPU has to be idle, so the frames still in flight can be written
+    pub fn finish(mut self) -> Result<()> {
+        for frame in self.readback.take_all() {
+            self.recorder.write_frame(&frame)?;
+        }
+
+        self.recorder.finish()
+    }
+}
+
+/// Saves the back buffer of a single frame, counted from the first rendered one, to a DDS file
+#[derive(Debug)]
+pub struct FrameCapture {
+    frame: u64,
+    path: PathBuf,
+    readback: FrameReadback,
+}
+
+impl FrameCapture {
+    pub fn new(
+        device: &ID3D12Device4,
+        back_buffer: &ID3D12Resource,
+        num_frames: usize,
+        frame: u64,
+        path: PathBuf,
+    ) -> Result<Self> {
+        let desc = unsafe { back_buffer.GetDesc() };
+        ensure!(
+            desc.Format == DXGI_FORMAT_R8G8B8A8_UNORM,
+            "Only R8G8B8A8 back buffers can be captured, got {:?}",
+            desc.Format
+        );
+
+        Ok(Self {
+            frame,
+            path,
+            readback: FrameReadback::new(device, &desc, num_frames)?,
+        })
+    }
+
+    pub fn capture(
+        &mut self,
+        command_list: &ID3D12GraphicsCommandList,
+        back_buffer: &ID3D12Resource,
+        back_buffer_state: D3D12_RESOURCE_STATES,
+        frame_number: u64,
+        frame_index: usize,
+    ) -> Result<()> {
+        if frame_number != self.frame {
+            return Ok(());
+        }
+
+        self.readback
+            .copy(command_list, back_buffer, back_buffer_state, frame_index)
+    }
+
+    /// Starts over with readback buffers for `back_buffer` once the back buffers were recreated,
+    /// possibly at another size. The GPU has to be idle, a frame that was already captured is
+    /// written first. Returns whether the capture is done.
+    pub fn recreate(
+        &mut self,
+        device: &ID3D12Device4,
+        back_buffer: &ID3D12Resource,
+    ) -> Result<bool> {
+        let num_frames = self.readback.num_frames();
+        for frame_index in 0..num_frames {
+            if self.write_completed_frame(frame_index)? {
+                return Ok(true);
+            }
+        }
+
+        *self = Self::new(
+            device,
+            back_buffer,
+            num_frames,
+            self.frame,
+            self.path.clone(),
+        )?;
+        Ok(false)
+    }
+
+    /// Call once the fence of `frame_index` has been waited on, returns whether the frame was
+    /// written and the capture is done
+    pub fn write_completed_frame(&mut self, frame_index: usize) -> Result<bool> {
+        let frame = match self.readback.take(frame_index) {
+            Some(frame) => frame,
+            None => return Ok(false),
+        };
+
+        let mut dds = Dds::new_dxgi(NewDxgiParams {
+            height: frame.height,
+            width: frame.width,
+            depth: None,
+            format: DxgiFormat::R8G8B8A8_UNorm,
+            mipmap_levels: None,
+            array_layers: None,
+            caps2: None,
+            is_cubemap: false,
+            resource_dimension: D3D10ResourceDimension::Texture2D,
+            alpha_mode: AlphaMode::Unknown,
+        })?;
+        // The readback rows are padded out to the copy alignment, DDS rows are tightly packed
+        let row_size = frame.width as usize * 4;
+        dds.data = frame
+            .data
+            .chunks(frame.row_pitch)
+            .take(frame.height as usize)
+            .flat_map(|row| &row[..row_size])
+            .copied()
+            .collect();
+
+        let mut file = File::create(&self.path)
+            .with_context(|| format!("Creating {}", self.path.display()))?;
+        dds.write(&mut file)?;
+
+        Ok(true)
+    }
+
+    /// Where the frame is written to
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
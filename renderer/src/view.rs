@@ -0,0 +1,19 @@
+use d3d12_utils::ViewportRect;
+
+use crate::renderer::Camera;
+
+/// A camera rendered into part of the screen, each view is culled separately
+#[derive(Debug, Clone, Copy)]
+pub struct View {
+    pub camera: Camera,
+    pub region: ViewportRect,
+}
+
+impl View {
+    pub fn full_screen(camera: Camera) -> Self {
+        Self {
+            camera,
+            region: ViewportRect::FULL,
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use d3d12_utils::{Bvh, Frustum, MeshManager, Ray, RaycastHit};
+
+use crate::{object::Object, renderer::Camera, transform_cache::TransformCache};
+
+/// CPU frustum culling, rebuilt every frame after the transform cache so every pass can ask for
+/// the objects its camera sees. The same bounds serve as the broad phase of raycasts.
+#[derive(Debug, Default)]
+pub struct Visibility {
+    bvh: Bvh,
+    // Object index of each item in the BVH
+    culled_objects: Vec<usize>,
+    // Objects whose mesh has no bounds
+    always_visible: Vec<usize>,
+}
+
+impl Visibility {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn build(&mut self, objects: &[Object], transform_cache: &TransformCache) {
+        self.culled_objects.clear();
+        self.always_visible.clear();
+
+        let mut world_bounds = Vec::with_capacity(objects.len());
+        for (index, (object, transform)) in transform_cache.iter(objects).enumerate() {
+            match object.mesh.bounds {
+                Some(bounds) => {
+                    world_bounds.push(bounds.transform(transform));
+                    self.culled_objects.push(index);
+                }
+                None => self.always_visible.push(index),
+            }
+        }
+
+        self.bvh = Bvh::build(&world_bounds);
+    }
+
+    /// Indices of the objects inside the camera's frustum, in scene order
+    pub fn visible_objects(&self, camera: &Camera) -> Vec<usize> {
+        let frustum = Frustum::from_view_projection(&camera.view_projection());
+
+        let mut visible_items = Vec::new();
+        self.bvh.query(&frustum, &mut visible_items);
+
+        let mut visible: Vec<usize> = visible_items
+            .into_iter()
+            .map(|item| self.culled_objects[item])
+            .chain(self.always_visible.iter().copied())
+            .collect();
+        visible.sort_unstable();
+
+        visible
+    }
+
+    /// The closest object along `ray`. Objects whose bounds the ray passes through are hit against
+    /// the CPU copy of their mesh, or against their bounds when the mesh manager has none. Objects
+    /// without bounds are never hit.
+    pub fn raycast(
+        &self,
+        ray: &Ray,
+        objects: &[Object],
+        transform_cache: &TransformCache,
+        mesh_manager: &MeshManager,
+    ) -> Option<RaycastHit> {
+        let mut items = Vec::new();
+        self.bvh.query_ray(ray, &mut items);
+
+        items
+            .into_iter()
+            .filter_map(|item| {
+                // Objects may have changed since the last build
+                let index = self.culled_objects[item];
+                let object = objects.get(index)?;
+                let transform = transform_cache.get(index)?;
+
+                match mesh_manager.get_cpu_mesh(&object.mesh) {
+                    Some(mesh) => RaycastHit::on_mesh(index, ray, transform, mesh),
+                    None => {
+                        RaycastHit::on_bounds(index, ray, &object.mesh.bounds?.transform(transform))
+                    }
+                }
+            })
+            .min_by(|a, b| a.distance.total_cmp(&b.distance))
+    }
+}